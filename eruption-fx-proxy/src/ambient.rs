@@ -0,0 +1,767 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use eruption_sdk::{canvas::Canvas, color::Color};
+use image::{
+    imageops::{self, FilterType},
+    RgbaImage,
+};
+
+use crate::{
+    backends::{Backend, BackendData},
+    constants,
+    hwdevices::KeyboardDevice,
+};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// Resampling filter used to scale captured regions down to the keyboard's layout
+const FILTER_TYPE: FilterType = FilterType::Gaussian;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AmbientError {
+    #[error("Invalid capture region: {description}")]
+    InvalidRegion { description: String },
+
+    #[error("Capture region is not supported by the screenshot backend: {description}")]
+    UnsupportedRegion { description: String },
+
+    #[error("Value out of range for {name}: {value}")]
+    ValueOutOfRange { name: &'static str, value: f64 },
+}
+
+/// A rectangular area of the screen, in pixels
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the part of `self` that lies within an image of the given size
+    pub fn clamp_to(&self, width: u32, height: u32) -> Option<Rect> {
+        let x0 = (self.x as i64).clamp(0, width as i64);
+        let y0 = (self.y as i64).clamp(0, height as i64);
+        let x1 = (self.x as i64 + self.width as i64).clamp(0, width as i64);
+        let y1 = (self.y as i64 + self.height as i64).clamp(0, height as i64);
+
+        if x1 > x0 && y1 > y0 {
+            Some(Rect::new(
+                x0 as i32,
+                y0 as i32,
+                (x1 - x0) as u32,
+                (y1 - y0) as u32,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// The part of the screen that gets mapped onto the keyboard
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum CaptureRegion {
+    /// The whole screen
+    #[default]
+    Screen,
+
+    /// Strips along the edges of the screen, `thickness` is given in percent
+    Edges { thickness: u32 },
+
+    /// A single monitor, identified by its index
+    Monitor { index: usize },
+
+    /// The topmost window whose title contains `title`
+    Window { title: String },
+}
+
+impl FromStr for CaptureRegion {
+    type Err = AmbientError;
+
+    /// Parses a region description like `screen`, `edges:10`, `monitor:1` or `window:Firefox`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || AmbientError::InvalidRegion {
+            description: s.to_string(),
+        };
+
+        let (kind, arg) = match s.trim().split_once(':') {
+            Some((kind, arg)) => (kind.trim(), Some(arg.trim())),
+            None => (s.trim(), None),
+        };
+
+        match (kind.to_lowercase().as_str(), arg) {
+            ("screen", None) => Ok(CaptureRegion::Screen),
+
+            ("edges", None) => Ok(CaptureRegion::Edges {
+                thickness: constants::DEFAULT_EDGE_THICKNESS_PERCENT,
+            }),
+
+            ("edges", Some(arg)) => match arg.parse::<u32>() {
+                Ok(thickness) if (1..=50).contains(&thickness) => {
+                    Ok(CaptureRegion::Edges { thickness })
+                }
+
+                _ => Err(invalid()),
+            },
+
+            ("monitor", Some(arg)) => Ok(CaptureRegion::Monitor {
+                index: arg.parse::<usize>().map_err(|_| invalid())?,
+            }),
+
+            ("window", Some(arg)) if !arg.is_empty() => Ok(CaptureRegion::Window {
+                title: arg.to_string(),
+            }),
+
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl CaptureRegion {
+    /// Returns `true` if the backend has to locate a monitor or window to capture this region
+    pub fn requires_backend_support(&self) -> bool {
+        matches!(
+            self,
+            CaptureRegion::Monitor { .. } | CaptureRegion::Window { .. }
+        )
+    }
+}
+
+impl fmt::Display for CaptureRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureRegion::Screen => write!(f, "screen"),
+            CaptureRegion::Edges { thickness } => write!(f, "edges:{}", thickness),
+            CaptureRegion::Monitor { index } => write!(f, "monitor:{}", index),
+            CaptureRegion::Window { title } => write!(f, "window:{}", title),
+        }
+    }
+}
+
+/// Settings of the ambient effect, may be changed at runtime via D-Bus
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmbientSettings {
+    /// The part of the screen that gets mapped onto the keyboard
+    pub region: CaptureRegion,
+
+    /// Saturation factor, `1.0` leaves the colors unchanged and `0.0` yields shades of gray
+    pub saturation: f64,
+
+    /// Gamma exponent, values above `1.0` darken the mid-tones
+    pub gamma: f64,
+
+    /// Weight of the previous frame in `0.0..1.0`, `0.0` disables temporal smoothing
+    pub smoothing: f64,
+}
+
+impl Default for AmbientSettings {
+    fn default() -> Self {
+        Self {
+            region: CaptureRegion::Screen,
+            saturation: 1.0,
+            gamma: 1.0,
+            smoothing: 0.0,
+        }
+    }
+}
+
+impl AmbientSettings {
+    /// Loads the `[ambient]` section of the configuration file, invalid values are
+    /// logged and replaced by their defaults
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut result = Self::default();
+
+        if let Ok(region) = config.get::<String>("ambient.region") {
+            result
+                .set_region(&region)
+                .unwrap_or_else(|e| log::error!("{}", e));
+        }
+
+        if let Ok(saturation) = config.get::<f64>("ambient.saturation") {
+            result
+                .set_saturation(saturation)
+                .unwrap_or_else(|e| log::error!("{}", e));
+        }
+
+        if let Ok(gamma) = config.get::<f64>("ambient.gamma") {
+            result
+                .set_gamma(gamma)
+                .unwrap_or_else(|e| log::error!("{}", e));
+        }
+
+        if let Ok(smoothing) = config.get::<f64>("ambient.smoothing") {
+            result
+                .set_smoothing(smoothing)
+                .unwrap_or_else(|e| log::error!("{}", e));
+        }
+
+        result
+    }
+
+    pub fn set_region(&mut self, region: &str) -> Result<()> {
+        self.region = region.parse::<CaptureRegion>()?;
+
+        Ok(())
+    }
+
+    pub fn set_saturation(&mut self, saturation: f64) -> Result<()> {
+        self.saturation = check_range("saturation", saturation, 0.0, 4.0)?;
+
+        Ok(())
+    }
+
+    pub fn set_gamma(&mut self, gamma: f64) -> Result<()> {
+        self.gamma = check_range("gamma", gamma, 0.1, 4.0)?;
+
+        Ok(())
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: f64) -> Result<()> {
+        self.smoothing = check_range("smoothing", smoothing, 0.0, 0.99)?;
+
+        Ok(())
+    }
+}
+
+fn check_range(name: &'static str, value: f64, min: f64, max: f64) -> Result<f64> {
+    if value.is_finite() && (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(AmbientError::ValueOutOfRange { name, value }.into())
+    }
+}
+
+/// Resolves the area of the screen that has to be captured for `region`, `None` means
+/// the whole screen. Monitors or windows that can't be found fall back to the whole screen,
+/// regions that the backend is not able to locate at all are rejected
+pub fn get_capture_area(backend: &mut dyn Backend, region: &CaptureRegion) -> Result<Option<Rect>> {
+    if region.requires_backend_support() && !backend.supports_regions() {
+        return Err(AmbientError::UnsupportedRegion {
+            description: region.to_string(),
+        }
+        .into());
+    }
+
+    match region {
+        CaptureRegion::Screen | CaptureRegion::Edges { .. } => Ok(None),
+
+        CaptureRegion::Monitor { index } => {
+            let result = backend.get_monitor_rects()?.get(*index).copied();
+
+            if result.is_none() {
+                log::debug!(
+                    "Monitor {} not available, capturing the whole screen",
+                    index
+                );
+            }
+
+            Ok(result)
+        }
+
+        CaptureRegion::Window { title } => {
+            let result = backend.find_window_rect(title)?;
+
+            if result.is_none() {
+                log::debug!("No window matches '{}', capturing the whole screen", title);
+            }
+
+            Ok(result)
+        }
+    }
+}
+
+/// Caches the result of `get_capture_area()`, locating a window is too expensive to be
+/// done for every frame
+#[derive(Debug, Default)]
+pub struct CaptureArea {
+    region: Option<CaptureRegion>,
+    area: Option<Rect>,
+    last_update: Option<Instant>,
+}
+
+impl CaptureArea {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the area of the screen that has to be captured for `region`, the area is
+    /// resolved again when `region` changes or the cached value is outdated
+    pub fn get(
+        &mut self,
+        backend: &mut dyn Backend,
+        region: &CaptureRegion,
+    ) -> Result<Option<Rect>> {
+        let max_age = Duration::from_millis(constants::CAPTURE_AREA_REFRESH_MILLIS);
+
+        let outdated = match self.last_update {
+            Some(last_update) => last_update.elapsed() >= max_age,
+            None => true,
+        };
+
+        if outdated || self.region.as_ref() != Some(region) {
+            self.area = get_capture_area(backend, region)?;
+            self.region = Some(region.clone());
+            self.last_update = Some(Instant::now());
+        }
+
+        Ok(self.area)
+    }
+}
+
+/// Converts screenshots to canvases, keeps the state required for temporal smoothing
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    previous: Option<Vec<[f64; 4]>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the previous frame, e.g. after the effect has been disabled
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// Converts `image` to fit the topology of `device`, only the part of the image
+    /// covered by `area` is taken into account
+    pub fn process(
+        &mut self,
+        image: &BackendData,
+        area: Option<Rect>,
+        settings: &AmbientSettings,
+        device: &KeyboardDevice,
+    ) -> Result<Canvas> {
+        let num_keys = device.get_num_keys();
+        let num_cols = device.get_num_cols();
+        let num_rows = device.get_num_rows();
+
+        let mut result = Canvas::new();
+
+        let area = area
+            .unwrap_or_else(|| Rect::new(0, 0, image.width(), image.height()))
+            .clamp_to(image.width(), image.height());
+
+        let area = match area {
+            Some(area) => area,
+
+            // nothing has been captured (yet)
+            None => return Ok(result),
+        };
+
+        let grid = sample_region(
+            image,
+            area,
+            &settings.region,
+            num_cols as u32,
+            num_rows as u32,
+        );
+
+        let previous = self
+            .previous
+            .take()
+            .filter(|previous| previous.len() == num_keys + 1);
+        let mut current = vec![[0.0; 4]; num_keys + 1];
+
+        for x in 0..num_cols {
+            for y in 0..num_rows {
+                let key_index: usize =
+                    (device.get_rows_topology()[x + (y * (num_cols + 1))]) as usize + 1;
+
+                if !(1..=num_keys).contains(&key_index) {
+                    continue;
+                }
+
+                let pixel = grid.get_pixel(x as u32, y as u32);
+                let mut color = adjust_color(
+                    [
+                        pixel[0] as f64,
+                        pixel[1] as f64,
+                        pixel[2] as f64,
+                        pixel[3] as f64,
+                    ],
+                    settings.saturation,
+                    settings.gamma,
+                );
+
+                if let Some(previous) = &previous {
+                    color = smooth_color(previous[key_index], color, settings.smoothing);
+                }
+
+                current[key_index] = color;
+
+                result[key_index] = Color::new(
+                    color[0].round() as u8,
+                    color[1].round() as u8,
+                    color[2].round() as u8,
+                    color[3].round() as u8,
+                );
+            }
+        }
+
+        self.previous = Some(current);
+
+        Ok(result)
+    }
+}
+
+/// Scales `area` of `image` down to a grid of `cols` x `rows` pixels
+pub fn sample_region(
+    image: &BackendData,
+    area: Rect,
+    region: &CaptureRegion,
+    cols: u32,
+    rows: u32,
+) -> RgbaImage {
+    match region {
+        CaptureRegion::Edges { thickness } => {
+            let strip_height = (area.height * thickness / 100).max(1);
+            let strip_width = (area.width * thickness / 100).max(1);
+
+            let bottom = area.y + (area.height - strip_height) as i32;
+            let right = area.x + (area.width - strip_width) as i32;
+
+            let top_strip = resize_area(
+                image,
+                Rect::new(area.x, area.y, area.width, strip_height),
+                cols,
+                1,
+            );
+            let bottom_strip = resize_area(
+                image,
+                Rect::new(area.x, bottom, area.width, strip_height),
+                cols,
+                1,
+            );
+            let left_strip = resize_area(
+                image,
+                Rect::new(area.x, area.y, strip_width, area.height),
+                1,
+                rows,
+            );
+            let right_strip = resize_area(
+                image,
+                Rect::new(right, area.y, strip_width, area.height),
+                1,
+                rows,
+            );
+
+            // every key takes the color of the screen edge it is closest to
+            RgbaImage::from_fn(cols, rows, |x, y| {
+                let fx = (x as f64 + 0.5) / cols as f64;
+                let fy = (y as f64 + 0.5) / rows as f64;
+
+                let distances = [fx, 1.0 - fx, fy, 1.0 - fy];
+                let nearest = (0..distances.len())
+                    .min_by(|a, b| distances[*a].total_cmp(&distances[*b]))
+                    .unwrap_or(0);
+
+                match nearest {
+                    0 => *left_strip.get_pixel(0, y),
+                    1 => *right_strip.get_pixel(0, y),
+                    2 => *top_strip.get_pixel(x, 0),
+                    _ => *bottom_strip.get_pixel(x, 0),
+                }
+            })
+        }
+
+        _ => resize_area(image, area, cols, rows),
+    }
+}
+
+fn resize_area(image: &BackendData, area: Rect, width: u32, height: u32) -> RgbaImage {
+    let view = imageops::crop_imm(image, area.x as u32, area.y as u32, area.width, area.height);
+
+    imageops::resize(&*view, width, height, FILTER_TYPE)
+}
+
+/// Applies saturation and gamma correction to an RGBA color, the alpha channel is preserved
+pub fn adjust_color(color: [f64; 4], saturation: f64, gamma: f64) -> [f64; 4] {
+    let luma = 0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2];
+
+    let adjust = |c: f64| {
+        let c = (luma + (c - luma) * saturation).clamp(0.0, 255.0);
+
+        255.0 * (c / 255.0).powf(gamma)
+    };
+
+    [
+        adjust(color[0]),
+        adjust(color[1]),
+        adjust(color[2]),
+        color[3],
+    ]
+}
+
+/// Blends the previous frame's color into the current one
+pub fn smooth_color(previous: [f64; 4], current: [f64; 4], smoothing: f64) -> [f64; 4] {
+    let mut result = current;
+
+    for (c, p) in result.iter_mut().zip(previous.iter()) {
+        *c = p * smoothing + *c * (1.0 - smoothing);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::hwdevices;
+
+    /// Loads an image file, used in place of a live screenshot
+    fn load_asset(name: &str) -> BackendData {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../support/tests/assets/ambient")
+            .join(name);
+
+        image::open(path).unwrap().into_rgba8()
+    }
+
+    fn rgba(color: &Color) -> [u8; 4] {
+        [color.r(), color.g(), color.b(), color.a()]
+    }
+
+    #[test]
+    fn parse_regions() {
+        for region in ["screen", "edges:15", "monitor:1", "window:Firefox"] {
+            assert_eq!(region.parse::<CaptureRegion>().unwrap().to_string(), region);
+        }
+
+        assert_eq!(
+            "edges".parse::<CaptureRegion>().unwrap(),
+            CaptureRegion::Edges {
+                thickness: constants::DEFAULT_EDGE_THICKNESS_PERCENT
+            }
+        );
+
+        for region in [
+            "",
+            "edges:0",
+            "edges:51",
+            "monitor",
+            "monitor:x",
+            "window:",
+            "tv",
+        ] {
+            assert!(region.parse::<CaptureRegion>().is_err(), "{}", region);
+        }
+
+        assert_eq!(
+            Rect::new(-10, 20, 50, 50).clamp_to(64, 36),
+            Some(Rect::new(0, 20, 40, 16))
+        );
+        assert_eq!(Rect::new(64, 0, 10, 10).clamp_to(64, 36), None);
+    }
+
+    /// Reports a single window, counts how often the screen layout has been queried
+    #[derive(Clone)]
+    struct TestBackend {
+        supports_regions: bool,
+        lookups: usize,
+    }
+
+    impl Backend for TestBackend {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_id(&self) -> String {
+            "test".to_string()
+        }
+
+        fn get_name(&self) -> String {
+            "Test".to_string()
+        }
+
+        fn get_description(&self) -> String {
+            "Test backend".to_string()
+        }
+
+        fn is_failed(&self) -> bool {
+            false
+        }
+
+        fn set_failed(&mut self, _failed: bool) {}
+
+        fn poll(&mut self) -> Result<BackendData> {
+            Ok(BackendData::new(64, 36))
+        }
+
+        fn supports_regions(&self) -> bool {
+            self.supports_regions
+        }
+
+        fn get_monitor_rects(&mut self) -> Result<Vec<Rect>> {
+            self.lookups += 1;
+
+            Ok(vec![Rect::new(0, 0, 32, 36), Rect::new(32, 0, 32, 36)])
+        }
+
+        fn find_window_rect(&mut self, title: &str) -> Result<Option<Rect>> {
+            self.lookups += 1;
+
+            Ok(Some(Rect::new(8, 8, 16, 16)).filter(|_| title == "Firefox"))
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+            self
+        }
+    }
+
+    #[test]
+    fn capture_areas() {
+        let monitor = CaptureRegion::Monitor { index: 1 };
+        let window = CaptureRegion::Window {
+            title: "Firefox".to_string(),
+        };
+
+        let mut backend = TestBackend {
+            supports_regions: false,
+            lookups: 0,
+        };
+
+        assert_eq!(
+            get_capture_area(&mut backend, &CaptureRegion::Screen).unwrap(),
+            None
+        );
+        assert!(get_capture_area(&mut backend, &monitor).is_err());
+        assert!(get_capture_area(&mut backend, &window).is_err());
+        assert_eq!(backend.lookups, 0);
+
+        backend.supports_regions = true;
+
+        let missing = CaptureRegion::Monitor { index: 2 };
+        assert_eq!(get_capture_area(&mut backend, &missing).unwrap(), None);
+
+        // the area is only looked up again after the region has been changed
+        let mut area = CaptureArea::new();
+
+        for _ in 0..10 {
+            let result = area.get(&mut backend, &window).unwrap();
+            assert_eq!(result, Some(Rect::new(8, 8, 16, 16)));
+        }
+
+        assert_eq!(backend.lookups, 2);
+
+        let result = area.get(&mut backend, &monitor).unwrap();
+        assert_eq!(result, Some(Rect::new(32, 0, 32, 36)));
+        assert_eq!(backend.lookups, 3);
+    }
+
+    #[test]
+    fn sample_edges() {
+        // red left, blue right, green top and white bottom edge, with a gray center
+        let image = load_asset("edges.png");
+        let area = Rect::new(0, 0, image.width(), image.height());
+
+        let grid = sample_region(&image, area, &CaptureRegion::Edges { thickness: 10 }, 21, 6);
+
+        let is_close = |x: u32, y: u32, expected: [u8; 3]| {
+            let pixel = grid.get_pixel(x, y);
+
+            (0..3).all(|i| (pixel[i] as i32 - expected[i] as i32).abs() < 32)
+        };
+
+        assert!(is_close(0, 2, [255, 0, 0]));
+        assert!(is_close(20, 3, [0, 0, 255]));
+        assert!(is_close(10, 0, [0, 255, 0]));
+        assert!(is_close(10, 5, [255, 255, 255]));
+
+        // only the center of the screen
+        let grid = sample_region(
+            &image,
+            Rect::new(16, 8, 32, 20),
+            &CaptureRegion::Screen,
+            21,
+            6,
+        );
+
+        assert!(grid.pixels().all(|p| p.0 == [128, 128, 128, 255]));
+    }
+
+    #[test]
+    fn color_processing() {
+        let gray = adjust_color([200.0, 100.0, 50.0, 255.0], 0.0, 1.0);
+        assert!((gray[0] - gray[1]).abs() < 0.001 && (gray[1] - gray[2]).abs() < 0.001);
+        assert_eq!(gray[3], 255.0);
+
+        let unchanged = adjust_color([200.0, 100.0, 50.0, 255.0], 1.0, 1.0);
+        assert!((unchanged[0] - 200.0).abs() < 0.001 && (unchanged[2] - 50.0).abs() < 0.001);
+
+        let darkened = adjust_color([0.0, 127.5, 255.0, 255.0], 1.0, 2.0);
+        assert!(darkened[0].abs() < 0.001);
+        assert!((darkened[1] - 63.75).abs() < 0.001);
+        assert!((darkened[2] - 255.0).abs() < 0.001);
+
+        let smoothed = smooth_color([0.0; 4], [255.0; 4], 0.75);
+        assert!((smoothed[0] - 63.75).abs() < 0.001);
+    }
+
+    #[test]
+    fn process_image_file() {
+        let device = hwdevices::get_keyboard_device(0, 0).unwrap();
+        let image = load_asset("edges.png");
+        let black = BackendData::new(64, 36);
+
+        let mut settings = AmbientSettings::default();
+        settings.set_region("monitor:0").unwrap();
+        settings.set_smoothing(0.5).unwrap();
+
+        let area = Some(Rect::new(16, 8, 32, 20));
+
+        let mut pipeline = Pipeline::new();
+
+        let canvas = pipeline.process(&image, area, &settings, &device).unwrap();
+        assert_eq!(rgba(&canvas[1]), [128, 128, 128, 255]);
+
+        // the previous frame is blended into the current one
+        let canvas = pipeline.process(&black, area, &settings, &device).unwrap();
+        assert_eq!(rgba(&canvas[1]), [64, 64, 64, 128]);
+
+        pipeline.reset();
+
+        let canvas = pipeline.process(&black, area, &settings, &device).unwrap();
+        assert_eq!(rgba(&canvas[1]), [0, 0, 0, 0]);
+    }
+}
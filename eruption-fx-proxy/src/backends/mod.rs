@@ -25,6 +25,8 @@ use dyn_clonable::{clonable, dyn_clone};
 
 use image::{ImageBuffer, Rgba};
 
+use crate::ambient::Rect;
+
 #[cfg(feature = "backend-gnome")]
 pub mod gnome;
 #[cfg(feature = "backend-wayland")]
@@ -79,6 +81,22 @@ pub trait Backend: Clone {
 
     fn poll(&mut self) -> Result<BackendData>;
 
+    /// Returns `true` if the backend is able to locate monitors and windows, see
+    /// `get_monitor_rects()` and `find_window_rect()`
+    fn supports_regions(&self) -> bool {
+        false
+    }
+
+    /// Returns the areas of the screen covered by the individual monitors
+    fn get_monitor_rects(&mut self) -> Result<Vec<Rect>> {
+        Ok(vec![])
+    }
+
+    /// Returns the area of the screen covered by the topmost window whose title contains `title`
+    fn find_window_rect(&mut self, _title: &str) -> Result<Option<Rect>> {
+        Ok(None)
+    }
+
     fn as_any(&self) -> &dyn std::any::Any;
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
 }
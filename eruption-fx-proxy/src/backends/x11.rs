@@ -20,6 +20,7 @@
 */
 
 use super::{Backend, BackendData, BackendError};
+use crate::ambient::Rect;
 
 type Result<T> = std::result::Result<T, eyre::Error>;
 
@@ -96,6 +97,41 @@ impl Backend for X11Backend {
         Ok(result)
     }
 
+    fn supports_regions(&self) -> bool {
+        true
+    }
+
+    fn get_monitor_rects(&mut self) -> Result<Vec<Rect>> {
+        let display = self.display.as_ref().ok_or(BackendError::NoDisplay)?;
+
+        let window = display.get_default_root();
+
+        let result = match display.get_screen_rects(window) {
+            Some(rects) => rects
+                .filter(|r| r.w > 0 && r.h > 0)
+                .map(|r| Rect::new(r.x, r.y, r.w as u32, r.h as u32))
+                .collect(),
+
+            None => vec![],
+        };
+
+        Ok(result)
+    }
+
+    fn find_window_rect(&mut self, title: &str) -> Result<Option<Rect>> {
+        let display = self.display.as_ref().ok_or(BackendError::NoDisplay)?;
+
+        let root = display.get_default_root();
+
+        let result = display.find_window_by_title(root, title).map(|window| {
+            let r = display.get_window_rect(window);
+
+            Rect::new(r.x, r.y, r.w.max(0) as u32, r.h.max(0) as u32)
+        });
+
+        Ok(result)
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -245,22 +281,84 @@ pub mod xwrap {
             }
         }
 
-        // pub fn get_screen_rects(&self, root: xlib::Window) -> Option<ScreenRectIter<'_>> {
-        //     unsafe {
-        //         let xrr_res = xrandr::XRRGetScreenResourcesCurrent(self.handle, root);
-
-        //         if xrr_res.is_null() {
-        //             return None;
-        //         }
-
-        //         Some(ScreenRectIter {
-        //             dpy: &self,
-        //             res: xrr_res,
-        //             crtcs: slice::from_raw_parts((*xrr_res).crtcs, (*xrr_res).ncrtc as usize),
-        //             i: 0,
-        //         })
-        //     }
-        // }
+        pub fn get_screen_rects(&self, root: xlib::Window) -> Option<ScreenRectIter<'_>> {
+            unsafe {
+                let xrr_res = xrandr::XRRGetScreenResourcesCurrent(self.handle, root);
+
+                if xrr_res.is_null() {
+                    return None;
+                }
+
+                Some(ScreenRectIter {
+                    dpy: self,
+                    res: xrr_res,
+                    crtcs: slice::from_raw_parts((*xrr_res).crtcs, (*xrr_res).ncrtc as usize),
+                    i: 0,
+                })
+            }
+        }
+
+        /// Searches the window tree below `window` for a viewable window whose title
+        /// contains `title`, windows higher up in the stacking order are preferred
+        pub fn find_window_by_title(
+            &self,
+            window: xlib::Window,
+            title: &str,
+        ) -> Option<xlib::Window> {
+            unsafe {
+                let mut name: *mut raw::c_char = ptr::null_mut();
+
+                if xlib::XFetchName(self.handle, window, &mut name) != 0 && !name.is_null() {
+                    let matches = ffi::CStr::from_ptr(name).to_string_lossy().contains(title);
+
+                    xlib::XFree(name as *mut raw::c_void);
+
+                    if matches && self.is_viewable(window) {
+                        return Some(window);
+                    }
+                }
+
+                let mut root = 0;
+                let mut parent = 0;
+                let mut children: *mut xlib::Window = ptr::null_mut();
+                let mut nchildren = 0;
+
+                if xlib::XQueryTree(
+                    self.handle,
+                    window,
+                    &mut root,
+                    &mut parent,
+                    &mut children,
+                    &mut nchildren,
+                ) == 0
+                    || children.is_null()
+                {
+                    return None;
+                }
+
+                // children are returned in bottom-to-top stacking order
+                let result = slice::from_raw_parts(children, nchildren as usize)
+                    .iter()
+                    .rev()
+                    .find_map(|child| self.find_window_by_title(*child, title));
+
+                xlib::XFree(children as *mut raw::c_void);
+
+                result
+            }
+        }
+
+        fn is_viewable(&self, window: xlib::Window) -> bool {
+            unsafe {
+                let mut attrs = mem::MaybeUninit::uninit();
+
+                if xlib::XGetWindowAttributes(self.handle, window, attrs.as_mut_ptr()) == 0 {
+                    return false;
+                }
+
+                attrs.assume_init().map_state == xlib::IsViewable
+            }
+        }
     }
 
     // impl Drop for Display {
@@ -21,12 +21,21 @@
 
 #![allow(dead_code)]
 
+/// Default configuration file
+pub const FX_PROXY_CONFIG_FILE: &str = "/etc/eruption/fx-proxy.conf";
+
 /// Default delay between images, used for animation mode
 pub const DEFAULT_ANIMATION_DELAY_MILLIS: u64 = 83;
 
 /// Default delay between screenshots, used for ambient mode
 pub const DEFAULT_FRAME_DELAY_MILLIS: u64 = 37;
 
+/// Interval in which the location of the captured monitor or window is updated
+pub const CAPTURE_AREA_REFRESH_MILLIS: u64 = 1000;
+
+/// Default thickness of the screen edge strips, in percent, used for ambient mode
+pub const DEFAULT_EDGE_THICKNESS_PERCENT: u32 = 10;
+
 /// Timeout value to use for D-Bus connections
 pub const DBUS_TIMEOUT_MILLIS: u64 = 5000;
 
@@ -20,11 +20,13 @@
 */
 
 use dbus::{ffidisp::Connection, ffidisp::NameFlag};
-use dbus_tree::{Access, EmitsChangedSignal, Factory};
+use dbus_tree::{Access, EmitsChangedSignal, Factory, MethodErr};
 use flume::Sender;
 use log::*;
 use std::sync::{atomic::Ordering, Arc};
 
+use crate::ambient::{AmbientError, CaptureRegion};
+
 /// D-Bus messages and signals that are processed by the main thread
 #[derive(Debug, Clone)]
 pub enum Message {
@@ -68,6 +70,10 @@ impl DbusApi {
         let status_changed_signal_0 = status_changed_signal.clone();
         let status_changed_signal_1 = status_changed_signal.clone();
         let status_changed_signal_2 = status_changed_signal.clone();
+        let status_changed_signal_3 = status_changed_signal.clone();
+        let status_changed_signal_4 = status_changed_signal.clone();
+        let status_changed_signal_5 = status_changed_signal.clone();
+        let status_changed_signal_6 = status_changed_signal.clone();
         let status_changed_signal_clone = status_changed_signal;
 
         let tree = f.tree(()).add(
@@ -117,7 +123,115 @@ impl DbusApi {
                             );
 
                             Ok(vec![m.msg.method_return()])
-                        })),
+                        }))
+                        .add_p(
+                            f.property::<&str, _>("AmbientRegion", ())
+                                .emits_changed(EmitsChangedSignal::True)
+                                .access(Access::ReadWrite)
+                                .on_get(|i, _m| {
+                                    i.append(crate::AMBIENT_SETTINGS.read().region.to_string());
+                                    Ok(())
+                                })
+                                .on_set(move |i, _m| {
+                                    let region: String = i.read()?;
+
+                                    let parsed = region
+                                        .parse::<CaptureRegion>()
+                                        .map_err(|e| MethodErr::invalid_arg(&e.to_string()))?;
+
+                                    if parsed.requires_backend_support()
+                                        && !crate::REGIONS_SUPPORTED.load(Ordering::SeqCst)
+                                    {
+                                        let e = AmbientError::UnsupportedRegion {
+                                            description: region,
+                                        };
+
+                                        return Err(MethodErr::invalid_arg(&e.to_string()));
+                                    }
+
+                                    crate::AMBIENT_SETTINGS
+                                        .write()
+                                        .set_region(&region)
+                                        .map_err(|e| MethodErr::invalid_arg(&e.to_string()))?;
+
+                                    status_changed_signal_3.emit(
+                                        &"/org/eruption/fx_proxy/effects".into(),
+                                        &"org.eruption.fx_proxy.Effects".into(),
+                                        &["AmbientRegion"],
+                                    );
+
+                                    Ok(())
+                                }),
+                        )
+                        .add_p(
+                            f.property::<f64, _>("AmbientSaturation", ())
+                                .emits_changed(EmitsChangedSignal::True)
+                                .access(Access::ReadWrite)
+                                .on_get(|i, _m| {
+                                    i.append(crate::AMBIENT_SETTINGS.read().saturation);
+                                    Ok(())
+                                })
+                                .on_set(move |i, _m| {
+                                    crate::AMBIENT_SETTINGS
+                                        .write()
+                                        .set_saturation(i.read()?)
+                                        .map_err(|e| MethodErr::invalid_arg(&e.to_string()))?;
+
+                                    status_changed_signal_4.emit(
+                                        &"/org/eruption/fx_proxy/effects".into(),
+                                        &"org.eruption.fx_proxy.Effects".into(),
+                                        &["AmbientSaturation"],
+                                    );
+
+                                    Ok(())
+                                }),
+                        )
+                        .add_p(
+                            f.property::<f64, _>("AmbientGamma", ())
+                                .emits_changed(EmitsChangedSignal::True)
+                                .access(Access::ReadWrite)
+                                .on_get(|i, _m| {
+                                    i.append(crate::AMBIENT_SETTINGS.read().gamma);
+                                    Ok(())
+                                })
+                                .on_set(move |i, _m| {
+                                    crate::AMBIENT_SETTINGS
+                                        .write()
+                                        .set_gamma(i.read()?)
+                                        .map_err(|e| MethodErr::invalid_arg(&e.to_string()))?;
+
+                                    status_changed_signal_5.emit(
+                                        &"/org/eruption/fx_proxy/effects".into(),
+                                        &"org.eruption.fx_proxy.Effects".into(),
+                                        &["AmbientGamma"],
+                                    );
+
+                                    Ok(())
+                                }),
+                        )
+                        .add_p(
+                            f.property::<f64, _>("AmbientSmoothing", ())
+                                .emits_changed(EmitsChangedSignal::True)
+                                .access(Access::ReadWrite)
+                                .on_get(|i, _m| {
+                                    i.append(crate::AMBIENT_SETTINGS.read().smoothing);
+                                    Ok(())
+                                })
+                                .on_set(move |i, _m| {
+                                    crate::AMBIENT_SETTINGS
+                                        .write()
+                                        .set_smoothing(i.read()?)
+                                        .map_err(|e| MethodErr::invalid_arg(&e.to_string()))?;
+
+                                    status_changed_signal_6.emit(
+                                        &"/org/eruption/fx_proxy/effects".into(),
+                                        &"org.eruption.fx_proxy.Effects".into(),
+                                        &["AmbientSmoothing"],
+                                    );
+
+                                    Ok(())
+                                }),
+                        ),
                 ),
         );
 
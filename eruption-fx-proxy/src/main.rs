@@ -22,6 +22,7 @@
 use clap::CommandFactory;
 use clap::Parser;
 use clap_complete::Shell;
+use config::Config;
 
 use flume::unbounded;
 use flume::Receiver;
@@ -49,6 +50,7 @@ use eruption_sdk::canvas::Canvas;
 use eruption_sdk::color::Color;
 use eruption_sdk::connection::{Connection, ConnectionType};
 
+mod ambient;
 mod backends;
 mod constants;
 mod dbus_client;
@@ -90,6 +92,13 @@ lazy_static! {
     /// Enable Ambient effect flag
     pub static ref ENABLE_AMBIENT_EFFECT: AtomicBool = AtomicBool::new(false);
 
+    /// Settings of the Ambient effect
+    pub static ref AMBIENT_SETTINGS: Arc<RwLock<ambient::AmbientSettings>> =
+        Arc::new(RwLock::new(ambient::AmbientSettings::default()));
+
+    /// Set if the active backend is able to capture single monitors or windows
+    pub static ref REGIONS_SUPPORTED: AtomicBool = AtomicBool::new(false);
+
     /// Global "quit" status flag
     pub static ref QUIT: AtomicBool = AtomicBool::new(false);
 }
@@ -212,6 +221,22 @@ pub async fn run_main_loop(_ctrl_c_rx: &Receiver<bool>) -> Result<()> {
         // instantiate the best fitting backend for the current system configuration
         let mut backend = backends::get_best_fitting_backend()?;
 
+        REGIONS_SUPPORTED.store(backend.supports_regions(), Ordering::SeqCst);
+
+        {
+            let mut settings = AMBIENT_SETTINGS.write();
+
+            if settings.region.requires_backend_support() && !backend.supports_regions() {
+                log::error!(
+                    "Capture region '{}' is not supported by {}, using the whole screen",
+                    settings.region,
+                    backend.get_name()
+                );
+
+                settings.region = ambient::CaptureRegion::Screen;
+            }
+        }
+
         log::debug!("Connecting to Eruption...");
 
         let connection = Connection::new(ConnectionType::Local)?;
@@ -226,6 +251,12 @@ pub async fn run_main_loop(_ctrl_c_rx: &Receiver<bool>) -> Result<()> {
 
         let mut canvas_cleared = false;
 
+        // converts screenshots to canvases, keeps state across frames
+        let mut pipeline = ambient::Pipeline::new();
+
+        // the captured part of the screen, refreshed periodically
+        let mut capture_area = ambient::CaptureArea::new();
+
         // create a new canvas
         let mut canvas = Canvas::new();
         canvas.fill(Color::new(0, 0, 0, 0));
@@ -240,15 +271,20 @@ pub async fn run_main_loop(_ctrl_c_rx: &Receiver<bool>) -> Result<()> {
             }
 
             if ENABLE_AMBIENT_EFFECT.load(Ordering::SeqCst) {
+                let settings = AMBIENT_SETTINGS.read().clone();
+
                 // request a screenshot from the backend and convert the image to the device's topology
+                let area = capture_area.get(backend.as_mut(), &settings.region)?;
                 let image_buffer = backend.poll()?;
-                let result = util::process_image_buffer(image_buffer, &device)?;
+                let result = pipeline.process(&image_buffer, area, &settings, &device)?;
 
                 // TODO: Implement blend code
                 // utils::blend(&mut canvas, &result);
                 canvas = result;
 
                 any_updates = true;
+            } else {
+                pipeline.reset();
             }
 
             if any_updates {
@@ -367,6 +403,24 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             })
             .unwrap_or_else(|e| error!("Could not set CTRL-C handler: {}", e));
 
+            // process configuration file
+            let config_file = opts
+                .config
+                .unwrap_or_else(|| constants::FX_PROXY_CONFIG_FILE.to_string());
+
+            match Config::builder()
+                .add_source(config::File::new(&config_file, config::FileFormat::Toml))
+                .build()
+            {
+                Ok(config) => {
+                    *AMBIENT_SETTINGS.write() = ambient::AmbientSettings::from_config(&config);
+                }
+
+                Err(e) => {
+                    log::error!("Could not parse configuration file: {}", e);
+                }
+            }
+
             // initialize the D-Bus API
             let (dbus_tx, _dbus_rx) = unbounded();
             let _dbus_api_tx = spawn_dbus_api_thread(dbus_tx)?;
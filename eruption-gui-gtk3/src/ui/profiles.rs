@@ -752,6 +752,25 @@ fn populate_visual_config_editor<P: AsRef<Path>>(builder: &Builder, profile: P)
 
         container.pack_start(&expander, false, false, 8);
 
+        // show the permissions that have been requested by the script
+        if !manifest.permissions.is_empty() {
+            let permissions = manifest
+                .permissions
+                .iter()
+                .map(|p| p.get_description())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let label = LabelBuilder::new()
+                .label(&format!("Requested permissions: {}", permissions))
+                .justify(Justification::Fill)
+                .halign(Align::Start)
+                .wrap(true)
+                .build();
+
+            expander_container.pack_start(&label, false, false, 8);
+        }
+
         let profile_script_parameters = profile.config.get_parameters(&manifest.name);
         for param in manifest.config.iter() {
             let value = profile_script_parameters.and_then(|p| p.get_parameter(&param.name));
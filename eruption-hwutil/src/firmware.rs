@@ -0,0 +1,422 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! Firmware image container format and flash pipeline
+//!
+//! A firmware image consists of a fixed size header, followed by the payload.
+//! All multi-byte values are stored in little endian byte order:
+//!
+//! | Offset | Size | Description                                   |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | Magic: "ERFW"                                 |
+//! | 4      | 2    | Format version, currently 1                   |
+//! | 6      | 2    | Size of the header in bytes (32)              |
+//! | 8      | 2    | USB vendor ID of the target device            |
+//! | 10     | 2    | USB product ID of the target device           |
+//! | 12     | 2    | Firmware version                              |
+//! | 14     | 2    | Reserved, must be 0                           |
+//! | 16     | 4    | Block size used when flashing the payload     |
+//! | 20     | 4    | Size of the payload in bytes                  |
+//! | 24     | 4    | CRC-32 of the payload                         |
+//! | 28     | 4    | CRC-32 of the header bytes 0..28              |
+
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::hwdevices::DeviceTrait;
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// Magic bytes at the start of each firmware image
+pub const FIRMWARE_MAGIC: &[u8; 4] = b"ERFW";
+
+/// The supported version of the container format
+pub const FIRMWARE_FORMAT_VERSION: u16 = 1;
+
+/// Size of the container header in bytes
+pub const FIRMWARE_HEADER_SIZE: usize = 32;
+
+/// The maximum supported block size
+pub const MAX_BLOCK_SIZE: u32 = 4096;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FirmwareError {
+    #[error("The file is too short to contain a firmware image")]
+    Truncated,
+
+    #[error("Invalid magic bytes, not a firmware image")]
+    InvalidMagic,
+
+    #[error("Unsupported container format version: {version}")]
+    UnsupportedVersion { version: u16 },
+
+    #[error("Invalid header: {description}")]
+    InvalidHeader { description: String },
+
+    #[error("Header checksum mismatch: expected {expected:#010x}, found {found:#010x}")]
+    HeaderChecksumMismatch { expected: u32, found: u32 },
+
+    #[error("Payload checksum mismatch: expected {expected:#010x}, found {found:#010x}")]
+    PayloadChecksumMismatch { expected: u32, found: u32 },
+
+    #[error("The firmware image is meant for device {image_vid:04x}:{image_pid:04x}, not for {device_vid:04x}:{device_pid:04x}")]
+    DeviceMismatch {
+        image_vid: u16,
+        image_pid: u16,
+        device_vid: u16,
+        device_pid: u16,
+    },
+
+    #[error("Verification of the block at address {address:#x} failed")]
+    VerificationFailed { address: u32 },
+}
+
+/// A parsed and validated firmware image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareImage {
+    pub usb_vid: u16,
+    pub usb_pid: u16,
+    pub firmware_version: u16,
+    pub block_size: u32,
+    pub payload: Vec<u8>,
+}
+
+impl FirmwareImage {
+    #[cfg(test)]
+    pub fn new(
+        usb_vid: u16,
+        usb_pid: u16,
+        firmware_version: u16,
+        block_size: u32,
+        payload: Vec<u8>,
+    ) -> Self {
+        Self {
+            usb_vid,
+            usb_pid,
+            firmware_version,
+            block_size,
+            payload,
+        }
+    }
+
+    /// Load and validate a firmware image from the file `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = std::fs::read(path)?;
+
+        Self::parse(&data)
+    }
+
+    /// Parse and validate a firmware image
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < FIRMWARE_HEADER_SIZE {
+            return Err(FirmwareError::Truncated.into());
+        }
+
+        if &data[0..4] != FIRMWARE_MAGIC {
+            return Err(FirmwareError::InvalidMagic.into());
+        }
+
+        let version = LittleEndian::read_u16(&data[4..6]);
+        if version != FIRMWARE_FORMAT_VERSION {
+            return Err(FirmwareError::UnsupportedVersion { version }.into());
+        }
+
+        let header_crc = LittleEndian::read_u32(&data[28..32]);
+        let found = crc32(&data[0..28]);
+        if header_crc != found {
+            return Err(FirmwareError::HeaderChecksumMismatch {
+                expected: header_crc,
+                found,
+            }
+            .into());
+        }
+
+        let header_size = LittleEndian::read_u16(&data[6..8]) as usize;
+        if header_size != FIRMWARE_HEADER_SIZE {
+            return Err(FirmwareError::InvalidHeader {
+                description: format!("Unsupported header size: {}", header_size),
+            }
+            .into());
+        }
+
+        let usb_vid = LittleEndian::read_u16(&data[8..10]);
+        let usb_pid = LittleEndian::read_u16(&data[10..12]);
+        let firmware_version = LittleEndian::read_u16(&data[12..14]);
+        let block_size = LittleEndian::read_u32(&data[16..20]);
+        let payload_size = LittleEndian::read_u32(&data[20..24]) as usize;
+        let payload_crc = LittleEndian::read_u32(&data[24..28]);
+
+        if block_size == 0 || block_size > MAX_BLOCK_SIZE {
+            return Err(FirmwareError::InvalidHeader {
+                description: format!("Invalid block size: {}", block_size),
+            }
+            .into());
+        }
+
+        if data.len() - FIRMWARE_HEADER_SIZE != payload_size {
+            return Err(FirmwareError::InvalidHeader {
+                description: format!(
+                    "Payload size mismatch: expected {} bytes, found {} bytes",
+                    payload_size,
+                    data.len() - FIRMWARE_HEADER_SIZE
+                ),
+            }
+            .into());
+        }
+
+        let payload = &data[FIRMWARE_HEADER_SIZE..];
+
+        let found = crc32(payload);
+        if payload_crc != found {
+            return Err(FirmwareError::PayloadChecksumMismatch {
+                expected: payload_crc,
+                found,
+            }
+            .into());
+        }
+
+        Ok(Self {
+            usb_vid,
+            usb_pid,
+            firmware_version,
+            block_size,
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Serialize the firmware image, including the container header
+    #[cfg(test)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![0; FIRMWARE_HEADER_SIZE];
+
+        result[0..4].copy_from_slice(FIRMWARE_MAGIC);
+        LittleEndian::write_u16(&mut result[4..6], FIRMWARE_FORMAT_VERSION);
+        LittleEndian::write_u16(&mut result[6..8], FIRMWARE_HEADER_SIZE as u16);
+        LittleEndian::write_u16(&mut result[8..10], self.usb_vid);
+        LittleEndian::write_u16(&mut result[10..12], self.usb_pid);
+        LittleEndian::write_u16(&mut result[12..14], self.firmware_version);
+        LittleEndian::write_u32(&mut result[16..20], self.block_size);
+        LittleEndian::write_u32(&mut result[20..24], self.payload.len() as u32);
+        LittleEndian::write_u32(&mut result[24..28], crc32(&self.payload));
+
+        let header_crc = crc32(&result[0..28]);
+        LittleEndian::write_u32(&mut result[28..32], header_crc);
+
+        result.extend_from_slice(&self.payload);
+
+        result
+    }
+
+    /// Make sure that the firmware image is meant for the device `usb_vid`:`usb_pid`
+    pub fn validate_target(&self, usb_vid: u16, usb_pid: u16) -> Result<()> {
+        if self.usb_vid != usb_vid || self.usb_pid != usb_pid {
+            return Err(FirmwareError::DeviceMismatch {
+                image_vid: self.usb_vid,
+                image_pid: self.usb_pid,
+                device_vid: usb_vid,
+                device_pid: usb_pid,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns an iterator over the blocks of the payload, along with their addresses
+    pub fn blocks(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        let block_size = self.block_size as usize;
+
+        self.payload
+            .chunks(block_size)
+            .enumerate()
+            .map(move |(i, chunk)| ((i * block_size) as u32, chunk))
+    }
+
+    /// Returns the number of blocks of the payload
+    pub fn block_count(&self) -> usize {
+        (self.payload.len() + self.block_size as usize - 1) / self.block_size as usize
+    }
+
+    /// Returns the firmware version in human readable form
+    pub fn version_string(&self) -> String {
+        format!(
+            "{}.{:02}",
+            self.firmware_version / 100,
+            self.firmware_version % 100
+        )
+    }
+}
+
+/// Flash the firmware image `image` to the device `device`. Each block is read back
+/// and verified after it has been written. In dry-run mode, nothing will be written
+/// to the device. The `progress` callback is invoked after each block with the
+/// index of the block and the total number of blocks
+pub fn flash<F>(
+    device: &dyn DeviceTrait,
+    image: &FirmwareImage,
+    dry_run: bool,
+    mut progress: F,
+) -> Result<()>
+where
+    F: FnMut(usize, usize),
+{
+    let block_count = image.block_count();
+
+    if dry_run {
+        for (index, _block) in image.blocks().enumerate() {
+            progress(index + 1, block_count);
+        }
+
+        return Ok(());
+    }
+
+    device.enter_bootloader()?;
+
+    let result = image
+        .blocks()
+        .enumerate()
+        .try_for_each(|(index, (address, block))| {
+            device.write_firmware_block(address, block)?;
+
+            let readback = device.read_firmware_block(address, block.len())?;
+            if readback != block {
+                return Err(FirmwareError::VerificationFailed { address }.into());
+            }
+
+            progress(index + 1, block_count);
+
+            Ok::<(), eyre::Error>(())
+        });
+
+    // always try to leave the bootloader, even if flashing failed
+    let leave_result = device.leave_bootloader();
+
+    result?;
+    leave_result?;
+
+    Ok(())
+}
+
+/// Compute the CRC-32 (IEEE 802.3) checksum of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffff_u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hwdevices::{SimulatedDevice, SIMULATED_USB_PID, SIMULATED_USB_VID};
+
+    fn make_image(size: usize) -> FirmwareImage {
+        let payload = (0..size).map(|i| (i % 251) as u8).collect();
+
+        FirmwareImage::new(SIMULATED_USB_VID, SIMULATED_USB_PID, 102, 256, payload)
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn parse_roundtrip() {
+        let image = make_image(1000);
+        let parsed = FirmwareImage::parse(&image.to_bytes()).unwrap();
+
+        assert_eq!(parsed, image);
+        assert_eq!(parsed.block_count(), 4);
+        assert_eq!(parsed.version_string(), "1.02");
+    }
+
+    #[test]
+    fn parse_corrupted_image() {
+        let mut data = make_image(1000).to_bytes();
+
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+
+        let err = FirmwareImage::parse(&data).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FirmwareError>(),
+            Some(FirmwareError::PayloadChecksumMismatch { .. })
+        ));
+
+        let mut data = make_image(1000).to_bytes();
+        data[8] ^= 0xff;
+
+        let err = FirmwareImage::parse(&data).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FirmwareError>(),
+            Some(FirmwareError::HeaderChecksumMismatch { .. })
+        ));
+
+        assert!(FirmwareImage::parse(b"ERFW").is_err());
+    }
+
+    #[test]
+    fn validate_target() {
+        let image = make_image(16);
+
+        assert!(image
+            .validate_target(SIMULATED_USB_VID, SIMULATED_USB_PID)
+            .is_ok());
+        assert!(image.validate_target(0x1e7d, 0x3098).is_err());
+    }
+
+    #[test]
+    fn flash_simulated_device() {
+        let image = make_image(1000);
+        let device = SimulatedDevice::new();
+
+        let mut blocks_done = 0;
+        flash(&device, &image, false, |index, _total| blocks_done = index).unwrap();
+
+        assert_eq!(blocks_done, 4);
+        assert_eq!(
+            device.get_written_blocks(),
+            vec![(0, 256), (256, 256), (512, 256), (768, 232)]
+        );
+        assert_eq!(&device.get_flash_contents()[..1000], &image.payload[..]);
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let image = make_image(1000);
+        let device = SimulatedDevice::new();
+
+        flash(&device, &image, true, |_, _| {}).unwrap();
+
+        assert!(device.get_written_blocks().is_empty());
+        assert!(device.get_flash_contents().iter().all(|b| *b == 0xff));
+    }
+}
@@ -41,8 +41,10 @@ mod roccat_vulcan_1xx;
 mod roccat_vulcan_pro;
 mod roccat_vulcan_pro_tkl;
 mod roccat_vulcan_tkl;
+mod simulated_device;
 
 pub use custom_serial_leds::*;
+pub use simulated_device::*;
 
 use std::collections::HashMap;
 
@@ -76,6 +78,12 @@ enum HwDeviceError {
 
     #[error("The operation is not supported")]
     OpNotSupported,
+
+    #[error("The device is not in bootloader mode")]
+    BootloaderNotActive,
+
+    #[error("Invalid firmware address: {address:#x}")]
+    InvalidAddress { address: u32 },
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -97,6 +105,13 @@ impl std::ops::Deref for DeviceStatus {
     }
 }
 
+/// Information about the firmware that is installed on a device
+#[derive(Debug, Clone)]
+pub struct FirmwareInfo {
+    pub firmware_version: String,
+    pub bootloader_version: Option<String>,
+}
+
 pub trait DeviceTrait {
     fn send_init_sequence(&self) -> Result<()>;
 
@@ -110,6 +125,45 @@ pub trait DeviceTrait {
     fn send_test_pattern(&self) -> Result<()>;
 
     fn device_status(&self) -> Result<DeviceStatus>;
+
+    // firmware related functions, not supported by most devices
+
+    fn get_firmware_info(&self) -> Result<FirmwareInfo> {
+        Err(HwDeviceError::OpNotSupported.into())
+    }
+
+    fn enter_bootloader(&self) -> Result<()> {
+        Err(HwDeviceError::OpNotSupported.into())
+    }
+
+    fn leave_bootloader(&self) -> Result<()> {
+        Err(HwDeviceError::OpNotSupported.into())
+    }
+
+    fn write_firmware_block(&self, _address: u32, _data: &[u8]) -> Result<()> {
+        Err(HwDeviceError::OpNotSupported.into())
+    }
+
+    fn read_firmware_block(&self, _address: u32, _size: usize) -> Result<Vec<u8>> {
+        Err(HwDeviceError::OpNotSupported.into())
+    }
+}
+
+/// Query the firmware version of a device, that supports the
+/// ROCCAT style device info HID report with ID `report_id`
+fn query_firmware_info(device: &dyn DeviceTrait, report_id: u8) -> Result<FirmwareInfo> {
+    let buf = device.read_feature_report(report_id, 6)?;
+
+    if buf[0] != report_id {
+        return Err(HwDeviceError::InvalidResult {}.into());
+    }
+
+    let version = buf[2];
+
+    Ok(FirmwareInfo {
+        firmware_version: format!("{}.{:02}", version / 100, version % 100),
+        bootloader_version: None,
+    })
 }
 
 pub fn bind_device(
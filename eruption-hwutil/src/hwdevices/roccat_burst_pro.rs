@@ -154,6 +154,10 @@ impl RoccatBurstPro {
 }
 
 impl DeviceTrait for RoccatBurstPro {
+    fn get_firmware_info(&self) -> Result<super::FirmwareInfo> {
+        super::query_firmware_info(self, 0x09)
+    }
+
     fn send_init_sequence(&self) -> Result<()> {
         println_v!(1, "Sending device init sequence...");
 
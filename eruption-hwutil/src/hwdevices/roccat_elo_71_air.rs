@@ -207,6 +207,10 @@ impl RoccatElo71Air {
 }
 
 impl DeviceTrait for RoccatElo71Air {
+    fn get_firmware_info(&self) -> Result<super::FirmwareInfo> {
+        super::query_firmware_info(self, 0x09)
+    }
+
     fn send_init_sequence(&self) -> Result<()> {
         println_v!(1, "Sending device init sequence...");
 
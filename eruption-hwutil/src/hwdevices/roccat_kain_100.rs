@@ -161,6 +161,10 @@ impl RoccatKain100 {
 }
 
 impl DeviceTrait for RoccatKain100 {
+    fn get_firmware_info(&self) -> Result<super::FirmwareInfo> {
+        super::query_firmware_info(self, 0x09)
+    }
+
     fn send_init_sequence(&self) -> Result<()> {
         println_v!(1, "Sending device init sequence...");
 
@@ -179,6 +179,10 @@ impl RoccatKoneXtd {
 }
 
 impl DeviceTrait for RoccatKoneXtd {
+    fn get_firmware_info(&self) -> Result<super::FirmwareInfo> {
+        super::query_firmware_info(self, 0x09)
+    }
+
     fn send_init_sequence(&self) -> Result<()> {
         println_v!(1, "Sending device init sequence...");
 
@@ -314,6 +314,10 @@ impl RoccatMagma {
 }
 
 impl DeviceTrait for RoccatMagma {
+    fn get_firmware_info(&self) -> Result<super::FirmwareInfo> {
+        super::query_firmware_info(self, 0x0f)
+    }
+
     fn send_init_sequence(&self) -> Result<()> {
         println_v!(1, "Sending device init sequence...");
 
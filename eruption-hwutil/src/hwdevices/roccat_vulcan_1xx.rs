@@ -385,6 +385,10 @@ impl RoccatVulcan1xx {
 }
 
 impl DeviceTrait for RoccatVulcan1xx {
+    fn get_firmware_info(&self) -> Result<super::FirmwareInfo> {
+        super::query_firmware_info(self, 0x0f)
+    }
+
     fn send_init_sequence(&self) -> Result<()> {
         println_v!(1, "Sending device init sequence...");
 
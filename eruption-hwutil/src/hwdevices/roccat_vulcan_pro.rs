@@ -372,6 +372,10 @@ impl RoccatVulcanPro {
 }
 
 impl DeviceTrait for RoccatVulcanPro {
+    fn get_firmware_info(&self) -> Result<super::FirmwareInfo> {
+        super::query_firmware_info(self, 0x0f)
+    }
+
     fn send_init_sequence(&self) -> Result<()> {
         println_v!(1, "Sending device init sequence...");
 
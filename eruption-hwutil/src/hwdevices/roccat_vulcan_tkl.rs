@@ -337,6 +337,10 @@ impl RoccatVulcanTKL {
 }
 
 impl DeviceTrait for RoccatVulcanTKL {
+    fn get_firmware_info(&self) -> Result<super::FirmwareInfo> {
+        super::query_firmware_info(self, 0x0f)
+    }
+
     fn send_init_sequence(&self) -> Result<()> {
         println_v!(1, "Sending device init sequence...");
 
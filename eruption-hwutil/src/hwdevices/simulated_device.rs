@@ -0,0 +1,194 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+use super::{DeviceStatus, DeviceTrait, FirmwareInfo, HwDeviceError, RGBA};
+
+#[allow(unused)]
+use crate::{constants, eprintln_v, println_v};
+
+pub type Result<T> = super::Result<T>;
+
+/// USB vendor ID reported by the simulated device
+pub const SIMULATED_USB_VID: u16 = 0xffff;
+
+/// USB product ID reported by the simulated device
+pub const SIMULATED_USB_PID: u16 = 0x0300;
+
+/// Size of the flash memory of the simulated device
+pub const SIMULATED_FLASH_SIZE: usize = 64 * 1024;
+
+/// A simulated device with an in-memory flash, used to test the firmware
+/// flash pipeline without risking to brick real hardware
+pub struct SimulatedDevice {
+    flash: Mutex<Vec<u8>>,
+    bootloader_active: Mutex<bool>,
+
+    /// Addresses of all blocks that have been written, in order
+    written_blocks: Mutex<Vec<(u32, usize)>>,
+}
+
+impl SimulatedDevice {
+    pub fn new() -> Self {
+        Self {
+            flash: Mutex::new(vec![0xff; SIMULATED_FLASH_SIZE]),
+            bootloader_active: Mutex::new(false),
+            written_blocks: Mutex::new(vec![]),
+        }
+    }
+
+    /// Returns the addresses and sizes of all blocks that have been written
+    #[cfg(test)]
+    pub fn get_written_blocks(&self) -> Vec<(u32, usize)> {
+        self.written_blocks.lock().clone()
+    }
+
+    /// Returns a copy of the contents of the flash memory
+    #[cfg(test)]
+    pub fn get_flash_contents(&self) -> Vec<u8> {
+        self.flash.lock().clone()
+    }
+}
+
+impl Default for SimulatedDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeviceTrait for SimulatedDevice {
+    fn send_init_sequence(&self) -> Result<()> {
+        println_v!(1, "Sending device init sequence...");
+
+        Ok(())
+    }
+
+    fn write_data_raw(&self, _buf: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_data_raw(&self, size: usize) -> Result<Vec<u8>> {
+        Ok(vec![0; size])
+    }
+
+    fn write_feature_report(&self, _buffer: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_feature_report(&self, id: u8, size: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0; size];
+        buf[0] = id;
+
+        Ok(buf)
+    }
+
+    fn send_led_map(&self, _led_map: &[RGBA]) -> Result<()> {
+        println_v!(1, "Setting LEDs from supplied map...");
+
+        Ok(())
+    }
+
+    fn send_test_pattern(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn device_status(&self) -> Result<DeviceStatus> {
+        let mut table = HashMap::new();
+
+        table.insert("connected".to_owned(), format!("{}", true));
+        table.insert(
+            "bootloader-active".to_owned(),
+            format!("{}", *self.bootloader_active.lock()),
+        );
+
+        Ok(DeviceStatus(table))
+    }
+
+    fn get_firmware_info(&self) -> Result<FirmwareInfo> {
+        Ok(FirmwareInfo {
+            firmware_version: "1.00".to_string(),
+            bootloader_version: Some("1.00".to_string()),
+        })
+    }
+
+    fn enter_bootloader(&self) -> Result<()> {
+        println_v!(1, "Entering bootloader mode...");
+
+        *self.bootloader_active.lock() = true;
+        self.written_blocks.lock().clear();
+
+        Ok(())
+    }
+
+    fn leave_bootloader(&self) -> Result<()> {
+        println_v!(1, "Leaving bootloader mode...");
+
+        if !*self.bootloader_active.lock() {
+            return Err(HwDeviceError::BootloaderNotActive.into());
+        }
+
+        *self.bootloader_active.lock() = false;
+
+        Ok(())
+    }
+
+    fn write_firmware_block(&self, address: u32, data: &[u8]) -> Result<()> {
+        if !*self.bootloader_active.lock() {
+            return Err(HwDeviceError::BootloaderNotActive.into());
+        }
+
+        let start = address as usize;
+        let end = start + data.len();
+
+        if end > SIMULATED_FLASH_SIZE {
+            return Err(HwDeviceError::InvalidAddress { address }.into());
+        }
+
+        // blocks have to be written in ascending order, without any overlap
+        if let Some((last_address, last_size)) = self.written_blocks.lock().last() {
+            if start < *last_address as usize + last_size {
+                return Err(HwDeviceError::InvalidAddress { address }.into());
+            }
+        }
+
+        self.flash.lock()[start..end].copy_from_slice(data);
+        self.written_blocks.lock().push((address, data.len()));
+
+        Ok(())
+    }
+
+    fn read_firmware_block(&self, address: u32, size: usize) -> Result<Vec<u8>> {
+        if !*self.bootloader_active.lock() {
+            return Err(HwDeviceError::BootloaderNotActive.into());
+        }
+
+        let start = address as usize;
+        let end = start + size;
+
+        if end > SIMULATED_FLASH_SIZE {
+            return Err(HwDeviceError::InvalidAddress { address }.into());
+        }
+
+        Ok(self.flash.lock()[start..end].to_vec())
+    }
+}
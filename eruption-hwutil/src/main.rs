@@ -35,6 +35,7 @@ use parking_lot::Mutex;
 use rust_embed::RustEmbed;
 use std::{
     env,
+    path::PathBuf,
     process::{self},
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -46,6 +47,7 @@ use std::{
 
 mod constants;
 mod device;
+mod firmware;
 mod hwdevices;
 mod util;
 
@@ -130,6 +132,18 @@ lazy_static! {
 pub enum MainError {
     #[error("Unknown error: {description}")]
     UnknownError { description: String },
+
+    #[error("Invalid device index: {index}")]
+    InvalidDeviceIndex { index: usize },
+
+    #[error("Could not open the device, is the device in use?")]
+    OpenDeviceError {},
+
+    #[error("Could not open HIDAPI")]
+    HidApiError {},
+
+    #[error("Aborted by user")]
+    Aborted {},
 }
 
 /// Supported command line arguments
@@ -192,11 +206,34 @@ pub enum Subcommands {
 pub enum FirmwareSubcommands {
     /// Get some information about the currently installed firmware
     #[clap(display_order = 0)]
-    Info { device: u64 },
+    Info {
+        device: usize,
+
+        /// Use a simulated device instead of real hardware
+        #[clap(long)]
+        simulate: bool,
+    },
 
     /// Flash firmware to device (DANGEROUS, may brick the device)
     #[clap(display_order = 1)]
-    Flash { device: u64 },
+    Flash {
+        device: usize,
+
+        /// The firmware image file to flash
+        firmware_file: PathBuf,
+
+        /// Only validate the firmware image, do not write anything to the device
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Use a simulated device instead of real hardware
+        #[clap(long)]
+        simulate: bool,
+
+        /// Do not ask for confirmation before flashing
+        #[clap(short, long)]
+        yes: bool,
+    },
 }
 
 /// Print license information
@@ -523,7 +560,79 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             }
         }
 
-        Subcommands::Firmware { command: _ } => {}
+        Subcommands::Firmware { command } => match command {
+            FirmwareSubcommands::Info {
+                device: device_index,
+                simulate,
+            } => {
+                with_firmware_device(device_index, simulate, |hwdev, _usb_vid, _usb_pid| {
+                    let info = hwdev.get_firmware_info()?;
+
+                    println!("Firmware version:   {}", info.firmware_version.bold());
+                    println!(
+                        "Bootloader version: {}",
+                        info.bootloader_version
+                            .unwrap_or_else(|| "---".to_string())
+                            .bold()
+                    );
+
+                    Ok(())
+                })?;
+            }
+
+            FirmwareSubcommands::Flash {
+                device: device_index,
+                firmware_file,
+                dry_run,
+                simulate,
+                yes,
+            } => {
+                let image = firmware::FirmwareImage::load(&firmware_file)?;
+
+                println!(
+                    "Firmware image: {:04x}:{:04x}, version {}, {} bytes in {} blocks",
+                    image.usb_vid,
+                    image.usb_pid,
+                    image.version_string().bold(),
+                    image.payload.len(),
+                    image.block_count()
+                );
+
+                with_firmware_device(device_index, simulate, |hwdev, usb_vid, usb_pid| {
+                    image.validate_target(usb_vid, usb_pid)?;
+
+                    if dry_run {
+                        println!("Dry run, nothing will be written to the device");
+                    } else {
+                        if !yes && !confirm_flash()? {
+                            return Err(MainError::Aborted {}.into());
+                        }
+
+                        println!(
+                            "{}",
+                            "Flashing firmware, do not disconnect the device!".bold()
+                        );
+                    }
+
+                    let term = console::Term::stdout();
+
+                    println!();
+
+                    firmware::flash(hwdev, &image, dry_run, |index, total| {
+                        let _ = term.clear_last_lines(1);
+                        println!("Block {}/{}", index, total);
+                    })?;
+
+                    if dry_run {
+                        println!("The firmware image is valid for this device");
+                    } else {
+                        println!("{}", "Firmware flashed successfully".bold());
+                    }
+
+                    Ok(())
+                })?;
+            }
+        },
 
         Subcommands::Completions { shell } => {
             const BIN_NAME: &str = env!("CARGO_PKG_NAME");
@@ -538,6 +647,70 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
     Ok(())
 }
 
+/// Bind the device with index `device_index` and run `f` on it. If `simulate` is set,
+/// a simulated device will be used instead of real hardware
+fn with_firmware_device<F>(
+    device_index: usize,
+    simulate: bool,
+    f: F,
+) -> std::result::Result<(), eyre::Error>
+where
+    F: FnOnce(&dyn hwdevices::DeviceTrait, u16, u16) -> std::result::Result<(), eyre::Error>,
+{
+    if simulate {
+        println_v!(1, "Using a simulated device");
+
+        let hwdev = hwdevices::SimulatedDevice::new();
+        hwdev.send_init_sequence()?;
+
+        return f(
+            &hwdev,
+            hwdevices::SIMULATED_USB_VID,
+            hwdevices::SIMULATED_USB_PID,
+        );
+    }
+
+    // create the one and only hidapi instance
+    let hidapi = hidapi::HidApi::new().map_err(|_| MainError::HidApiError {})?;
+
+    let (index, device) = hidapi.device_list().enumerate().nth(device_index).ok_or(
+        MainError::InvalidDeviceIndex {
+            index: device_index,
+        },
+    )?;
+
+    println!(
+        "{}: {}/{}",
+        format!("{:02}", index).bold(),
+        device.manufacturer_string().unwrap_or("<unknown>").bold(),
+        device.product_string().unwrap_or("<unknown>").bold()
+    );
+
+    let dev = device
+        .open_device(&hidapi)
+        .map_err(|_| MainError::OpenDeviceError {})?;
+
+    let hwdev = hwdevices::bind_device(dev, &hidapi, device.vendor_id(), device.product_id())?;
+
+    hwdev.send_init_sequence()?;
+
+    f(hwdev.as_ref(), device.vendor_id(), device.product_id())
+}
+
+/// Ask the user whether the firmware should really be flashed
+fn confirm_flash() -> std::result::Result<bool, eyre::Error> {
+    let term = console::Term::stdout();
+
+    term.write_str(&format!(
+        "{} [y/N] ",
+        "Flashing a firmware may brick the device. Continue?".bold()
+    ))?;
+
+    let answer = term.read_line()?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 /// Main program entrypoint
 pub fn main() -> std::result::Result<(), eyre::Error> {
     let language_loader: FluentLanguageLoader = fluent_language_loader!();
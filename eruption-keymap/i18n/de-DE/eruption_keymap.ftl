@@ -31,6 +31,8 @@ show-about = Zeigt verfügbare Informationen über eine Keymap
 macros-about = Zeigt eine Liste der verfügbaren Makros in einer Lua Datei
 events-about = Zeigt eine Liste der verfügbaren Linux EVDEV Ereignisse
 compile-about = Übersetzt eine Keymap zu Lua Code und macht sie für Eruption verfügbar
+import-about = Importiert eine Keymap aus einer QMK keymap.json Datei oder einer VIA Sicherung
+export-about = Exportiert eine Keymap in eine QMK keymap.json Datei oder eine VIA Sicherung
 mapping-add-about = Ein Tasten Mapping von `source` nach `action` hinzufügen
 mapping-remove-about = Das Tasten Mapping von `source` entfernen
 mapping-enable-about = Aktiviert eine Mapping Regel
@@ -50,9 +52,13 @@ symbol = Symbol
 code = Code
 
 compiling = Übersetze Keymap: 
+importing = Importiere Keymap: 
+exporting = Exportiere Keymap: 
 
 success = Erfolgreich
 
+conversion-report = Die folgenden Einträge konnten nicht konvertiert werden:
+
 functions-in-file = Funktionen in Lua Datei: 
 
 action-not-implemented = Diese Aktion ist nicht implementiert
@@ -31,6 +31,8 @@ show-about = Show some information about a keymap
 macros-about = Show a list of available macros in a Lua file
 events-about = Show a list of available Linux EVDEV events
 compile-about = Compile a keymap to Lua code and make it available to Eruption
+import-about = Import a keymap from a QMK keymap.json file or a VIA keymap backup
+export-about = Export a keymap to a QMK keymap.json file or a VIA keymap backup
 mapping-add-about = Add a mapping rule for `source` that executes `action`
 mapping-remove-about = Remove the mapping rule for `source`
 mapping-enable-about = Enable a mapping rule
@@ -50,9 +52,13 @@ symbol = Symbol
 code = Code
 
 compiling = Compiling keymap: 
+importing = Importing keymap: 
+exporting = Exporting keymap: 
 
 success = Success
 
+conversion-report = The following items could not be converted:
+
 functions-in-file = Functions in Lua file: 
 
 action-not-implemented = This action is not implemented
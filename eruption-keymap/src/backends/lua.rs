@@ -25,8 +25,8 @@ use std::{fs, path::Path};
 use chrono::Utc;
 use colored::Colorize;
 
-use crate::mapping::{Action, Event, KeyMappingTable};
-use crate::{messages, tr};
+use crate::mapping::{Action, Event, Key, KeyMappingTable};
+use crate::{hwdevices, messages, tr};
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
 
@@ -516,6 +516,80 @@ impl super::Backend for LuaBackend {
                         }
                     }
                 }
+
+                // macro files are played back by the daemon
+                Action::PlayMacro(_) => match &source.event {
+                    Event::SimpleKeyDown(key) | Event::SimpleKeyUp(key) => {
+                        if !rule.enabled {
+                            write!(&mut text, "-- ACTION IS DISABLED: ")?;
+                        }
+
+                        writeln!(
+                            &mut text,
+                            "MACRO_TABLE[{}] = {}",
+                            key.key_index,
+                            action_to_lua_value(&rule.action).unwrap()
+                        )?;
+                    }
+
+                    _ => {
+                        messages::warning!(
+                            "Rule: {}: {}",
+                            &format!("#{:0>2}", index + 1),
+                            tr!("action-not-implemented").yellow()
+                        );
+
+                        writeln!(&mut text, "-- ACTION IS NOT IMPLEMENTED")?;
+                    }
+                },
+
+                // tap-hold, tap dance and chords are supported for simple key events
+                Action::TapHold(_) | Action::TapDance(_) | Action::Chord(_) => {
+                    match &source.event {
+                        Event::SimpleKeyDown(key) | Event::SimpleKeyUp(key) => {
+                            if let Some(statement) = complex_action_to_lua(key, &rule.action) {
+                                if !rule.enabled {
+                                    write!(&mut text, "-- ACTION IS DISABLED: ")?;
+                                }
+
+                                writeln!(&mut text, "{}", statement)?;
+                            } else {
+                                messages::warning!(
+                                    "Rule: {}: {}",
+                                    &format!("#{:0>2}", index + 1),
+                                    tr!("action-not-implemented").yellow()
+                                );
+
+                                writeln!(&mut text, "-- ACTION IS NOT IMPLEMENTED")?;
+                            }
+                        }
+
+                        _ => {
+                            messages::warning!(
+                                "Rule: {}: {}",
+                                &format!("#{:0>2}", index + 1),
+                                tr!("action-not-implemented").yellow()
+                            );
+
+                            writeln!(&mut text, "-- ACTION IS NOT IMPLEMENTED")?;
+                        }
+                    }
+                }
+
+                // layers and one-shot modifiers are only supported by the
+                // keymap engine of the daemon, see the `native` backend
+                Action::SwitchLayer(_)
+                | Action::ToggleLayer(_)
+                | Action::MomentaryLayer(_)
+                | Action::OneShot(_) => {
+                    messages::warning!(
+                        "Rule: {}: {}",
+                        &format!("#{:0>2}", index + 1),
+                        tr!("action-not-implemented").yellow()
+                    );
+
+                    writeln!(&mut text, "-- ACTION IS NOT IMPLEMENTED")?;
+                }
             }
 
             // insert a newline
@@ -533,3 +607,177 @@ impl super::Backend for LuaBackend {
         Ok(())
     }
 }
+
+/// Generate a Lua value for an action that is executed by a tap-hold key, a tap dance,
+/// a chord or a key of the `MACRO_TABLE`. Only key events and macros are supported
+fn action_to_lua_value(action: &Action) -> Option<String> {
+    match action {
+        Action::InjectKey(event) => Some(format!("{}", event.event)),
+        Action::Call(call) => Some(call.function_name.clone()),
+        Action::PlayMacro(name) => Some(format!(
+            "function(down) if down then play_macro({:?}) end end",
+            name
+        )),
+
+        _ => None,
+    }
+}
+
+/// Generate a Lua statement that registers a tap-hold key, a tap dance or a chord,
+/// returns `None` if the action can not be expressed in Lua
+fn complex_action_to_lua(key: &Key, action: &Action) -> Option<String> {
+    match action {
+        Action::TapHold(tap_hold) => Some(format!(
+            "TAP_HOLD_TABLE[{}] = {{ tap = {}, hold = {}, timeout = {} }}",
+            key.key_index,
+            action_to_lua_value(&tap_hold.tap)?,
+            action_to_lua_value(&tap_hold.hold)?,
+            tap_hold.timeout_millis
+        )),
+
+        Action::TapDance(tap_dance) => {
+            let actions = tap_dance
+                .actions
+                .iter()
+                .map(action_to_lua_value)
+                .collect::<Option<Vec<_>>>()?;
+
+            Some(format!(
+                "TAP_DANCE_TABLE[{}] = {{ actions = {{ {} }}, timeout = {} }}",
+                key.key_index,
+                actions.join(", "),
+                tap_dance.timeout_millis
+            ))
+        }
+
+        Action::Chord(chord) => {
+            // the evdev codes of the keys are required to replay the keys,
+            // if the chord has not been completed
+            let keys = std::iter::once(key)
+                .chain(chord.keys.iter())
+                .map(|key| {
+                    hwdevices::index_to_ev_key(key.key_index + 1, (key.usb_vid, key.usb_pid))
+                        .map(|ev_key| format!("[{}] = {}", key.key_index, ev_key as u32))
+                })
+                .collect::<Option<Vec<_>>>()?;
+
+            Some(format!(
+                "CHORD_TABLE[#CHORD_TABLE + 1] = {{ keys = {{ {} }}, action = {}, timeout = {} }}",
+                keys.join(", "),
+                action_to_lua_value(&chord.action)?,
+                chord.timeout_millis
+            ))
+        }
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::{Chord, EvdevEvent, Macro, TapDance, TapHold};
+
+    const DEVICE: (u16, u16) = (0x1e7d, 0x3098);
+
+    const KEY_ESC: u32 = 1;
+    const KEY_LEFTCTRL: u32 = 29;
+    const KEY_A: u32 = 30;
+    const KEY_S: u32 = 31;
+
+    // key indices of the Vulcan 1xx
+    const INDEX_A: usize = 9;
+    const INDEX_S: usize = 15;
+
+    fn inject_key(event: u32) -> Action {
+        Action::InjectKey(EvdevEvent { event })
+    }
+
+    fn call(function_name: &str) -> Action {
+        Action::Call(Macro {
+            function_name: function_name.to_string(),
+        })
+    }
+
+    #[test]
+    fn tap_hold_to_lua() {
+        let action = Action::TapHold(TapHold {
+            tap: Box::new(inject_key(KEY_ESC)),
+            hold: Box::new(inject_key(KEY_LEFTCTRL)),
+            timeout_millis: 200,
+        });
+
+        assert_eq!(
+            complex_action_to_lua(&Key::new(INDEX_A, DEVICE), &action).unwrap(),
+            "TAP_HOLD_TABLE[9] = { tap = 1, hold = 29, timeout = 200 }"
+        );
+    }
+
+    #[test]
+    fn tap_dance_to_lua() {
+        let action = Action::TapDance(TapDance {
+            actions: vec![
+                inject_key(KEY_A),
+                call("easy_shift"),
+                Action::PlayMacro("my-macro".to_string()),
+            ],
+            timeout_millis: 250,
+        });
+
+        assert_eq!(
+            complex_action_to_lua(&Key::new(INDEX_S, DEVICE), &action).unwrap(),
+            "TAP_DANCE_TABLE[15] = { actions = { 30, easy_shift, \
+             function(down) if down then play_macro(\"my-macro\") end end }, timeout = 250 }"
+        );
+    }
+
+    #[test]
+    fn chord_to_lua() {
+        let action = Action::Chord(Chord {
+            keys: vec![Key::new(INDEX_S, DEVICE)],
+            action: Box::new(inject_key(KEY_ESC)),
+            timeout_millis: 50,
+        });
+
+        // the chord contains the evdev codes of all of its keys, including the source key
+        assert_eq!(
+            complex_action_to_lua(&Key::new(INDEX_A, DEVICE), &action).unwrap(),
+            format!(
+                "CHORD_TABLE[#CHORD_TABLE + 1] = {{ keys = {{ [9] = {KEY_A}, [15] = {KEY_S} }}, \
+                 action = 1, timeout = 50 }}"
+            )
+        );
+    }
+
+    #[test]
+    fn unsupported_complex_actions() {
+        let key = Key::new(INDEX_A, DEVICE);
+
+        // nested complex actions and layer switches can not be expressed in Lua
+        let nested = Action::TapHold(TapHold {
+            tap: Box::new(inject_key(KEY_A)),
+            hold: Box::new(Action::TapDance(TapDance {
+                actions: vec![inject_key(KEY_S)],
+                timeout_millis: 200,
+            })),
+            timeout_millis: 200,
+        });
+
+        let layer = Action::TapDance(TapDance {
+            actions: vec![inject_key(KEY_A), Action::MomentaryLayer(2)],
+            timeout_millis: 200,
+        });
+
+        // a key that does not exist on the device
+        let unknown_key = Action::Chord(Chord {
+            keys: vec![Key::new(400, DEVICE)],
+            action: Box::new(inject_key(KEY_ESC)),
+            timeout_millis: 50,
+        });
+
+        assert_eq!(complex_action_to_lua(&key, &nested), None);
+        assert_eq!(complex_action_to_lua(&key, &layer), None);
+        assert_eq!(complex_action_to_lua(&key, &unknown_key), None);
+        assert_eq!(complex_action_to_lua(&key, &inject_key(KEY_A)), None);
+    }
+}
@@ -25,6 +25,7 @@ use crate::mapping::KeyMappingTable;
 
 pub mod lua;
 pub mod native;
+pub mod qmk;
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
 
@@ -0,0 +1,244 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! Conversion between QMK keycodes and Linux evdev event codes
+
+/// A QMK basic keycode. `names` lists the current QMK name first, followed by its aliases
+/// and the names used by older QMK and VIA versions
+#[derive(Debug, Clone, Copy)]
+pub struct KeycodeInfo {
+    pub names: &'static [&'static str],
+
+    /// The numeric QMK keycode, as used by older VIA keymap files
+    pub code: u16,
+
+    /// The corresponding Linux evdev event code
+    pub evdev: u32,
+}
+
+macro_rules! keycode {
+    ([$($name:literal),+], $code:literal, $evdev:literal) => {
+        KeycodeInfo {
+            names: &[$($name),+],
+            code: $code,
+            evdev: $evdev,
+        }
+    };
+}
+
+#[rustfmt::skip]
+pub const KEYCODES: &[KeycodeInfo] = &[
+    keycode!(["KC_A"], 0x04, 30),
+    keycode!(["KC_B"], 0x05, 48),
+    keycode!(["KC_C"], 0x06, 46),
+    keycode!(["KC_D"], 0x07, 32),
+    keycode!(["KC_E"], 0x08, 18),
+    keycode!(["KC_F"], 0x09, 33),
+    keycode!(["KC_G"], 0x0a, 34),
+    keycode!(["KC_H"], 0x0b, 35),
+    keycode!(["KC_I"], 0x0c, 23),
+    keycode!(["KC_J"], 0x0d, 36),
+    keycode!(["KC_K"], 0x0e, 37),
+    keycode!(["KC_L"], 0x0f, 38),
+    keycode!(["KC_M"], 0x10, 50),
+    keycode!(["KC_N"], 0x11, 49),
+    keycode!(["KC_O"], 0x12, 24),
+    keycode!(["KC_P"], 0x13, 25),
+    keycode!(["KC_Q"], 0x14, 16),
+    keycode!(["KC_R"], 0x15, 19),
+    keycode!(["KC_S"], 0x16, 31),
+    keycode!(["KC_T"], 0x17, 20),
+    keycode!(["KC_U"], 0x18, 22),
+    keycode!(["KC_V"], 0x19, 47),
+    keycode!(["KC_W"], 0x1a, 17),
+    keycode!(["KC_X"], 0x1b, 45),
+    keycode!(["KC_Y"], 0x1c, 21),
+    keycode!(["KC_Z"], 0x1d, 44),
+
+    keycode!(["KC_1"], 0x1e, 2),
+    keycode!(["KC_2"], 0x1f, 3),
+    keycode!(["KC_3"], 0x20, 4),
+    keycode!(["KC_4"], 0x21, 5),
+    keycode!(["KC_5"], 0x22, 6),
+    keycode!(["KC_6"], 0x23, 7),
+    keycode!(["KC_7"], 0x24, 8),
+    keycode!(["KC_8"], 0x25, 9),
+    keycode!(["KC_9"], 0x26, 10),
+    keycode!(["KC_0"], 0x27, 11),
+
+    keycode!(["KC_ENTER", "KC_ENT"], 0x28, 28),
+    keycode!(["KC_ESCAPE", "KC_ESC"], 0x29, 1),
+    keycode!(["KC_BACKSPACE", "KC_BSPC", "KC_BSPACE"], 0x2a, 14),
+    keycode!(["KC_TAB"], 0x2b, 15),
+    keycode!(["KC_SPACE", "KC_SPC"], 0x2c, 57),
+    keycode!(["KC_MINUS", "KC_MINS"], 0x2d, 12),
+    keycode!(["KC_EQUAL", "KC_EQL"], 0x2e, 13),
+    keycode!(["KC_LEFT_BRACKET", "KC_LBRC", "KC_LBRACKET"], 0x2f, 26),
+    keycode!(["KC_RIGHT_BRACKET", "KC_RBRC", "KC_RBRACKET"], 0x30, 27),
+    keycode!(["KC_BACKSLASH", "KC_BSLS", "KC_BSLASH"], 0x31, 43),
+    keycode!(["KC_NONUS_HASH", "KC_NUHS"], 0x32, 43),
+    keycode!(["KC_SEMICOLON", "KC_SCLN", "KC_SCOLON"], 0x33, 39),
+    keycode!(["KC_QUOTE", "KC_QUOT"], 0x34, 40),
+    keycode!(["KC_GRAVE", "KC_GRV"], 0x35, 41),
+    keycode!(["KC_COMMA", "KC_COMM"], 0x36, 51),
+    keycode!(["KC_DOT"], 0x37, 52),
+    keycode!(["KC_SLASH", "KC_SLSH"], 0x38, 53),
+    keycode!(["KC_CAPS_LOCK", "KC_CAPS", "KC_CLCK", "KC_CAPSLOCK"], 0x39, 58),
+
+    keycode!(["KC_F1"], 0x3a, 59),
+    keycode!(["KC_F2"], 0x3b, 60),
+    keycode!(["KC_F3"], 0x3c, 61),
+    keycode!(["KC_F4"], 0x3d, 62),
+    keycode!(["KC_F5"], 0x3e, 63),
+    keycode!(["KC_F6"], 0x3f, 64),
+    keycode!(["KC_F7"], 0x40, 65),
+    keycode!(["KC_F8"], 0x41, 66),
+    keycode!(["KC_F9"], 0x42, 67),
+    keycode!(["KC_F10"], 0x43, 68),
+    keycode!(["KC_F11"], 0x44, 87),
+    keycode!(["KC_F12"], 0x45, 88),
+
+    keycode!(["KC_PRINT_SCREEN", "KC_PSCR", "KC_PSCREEN"], 0x46, 99),
+    keycode!(["KC_SCROLL_LOCK", "KC_SCRL", "KC_SLCK", "KC_SCROLLLOCK"], 0x47, 70),
+    keycode!(["KC_PAUSE", "KC_PAUS", "KC_BRK"], 0x48, 119),
+    keycode!(["KC_INSERT", "KC_INS"], 0x49, 110),
+    keycode!(["KC_HOME"], 0x4a, 102),
+    keycode!(["KC_PAGE_UP", "KC_PGUP"], 0x4b, 104),
+    keycode!(["KC_DELETE", "KC_DEL"], 0x4c, 111),
+    keycode!(["KC_END"], 0x4d, 107),
+    keycode!(["KC_PAGE_DOWN", "KC_PGDN", "KC_PGDOWN"], 0x4e, 109),
+    keycode!(["KC_RIGHT", "KC_RGHT"], 0x4f, 106),
+    keycode!(["KC_LEFT"], 0x50, 105),
+    keycode!(["KC_DOWN"], 0x51, 108),
+    keycode!(["KC_UP"], 0x52, 103),
+
+    keycode!(["KC_NUM_LOCK", "KC_NUM", "KC_NLCK", "KC_NUMLOCK"], 0x53, 69),
+    keycode!(["KC_KP_SLASH", "KC_PSLS"], 0x54, 98),
+    keycode!(["KC_KP_ASTERISK", "KC_PAST"], 0x55, 55),
+    keycode!(["KC_KP_MINUS", "KC_PMNS"], 0x56, 74),
+    keycode!(["KC_KP_PLUS", "KC_PPLS"], 0x57, 78),
+    keycode!(["KC_KP_ENTER", "KC_PENT"], 0x58, 96),
+    keycode!(["KC_KP_1", "KC_P1"], 0x59, 79),
+    keycode!(["KC_KP_2", "KC_P2"], 0x5a, 80),
+    keycode!(["KC_KP_3", "KC_P3"], 0x5b, 81),
+    keycode!(["KC_KP_4", "KC_P4"], 0x5c, 75),
+    keycode!(["KC_KP_5", "KC_P5"], 0x5d, 76),
+    keycode!(["KC_KP_6", "KC_P6"], 0x5e, 77),
+    keycode!(["KC_KP_7", "KC_P7"], 0x5f, 71),
+    keycode!(["KC_KP_8", "KC_P8"], 0x60, 72),
+    keycode!(["KC_KP_9", "KC_P9"], 0x61, 73),
+    keycode!(["KC_KP_0", "KC_P0"], 0x62, 82),
+    keycode!(["KC_KP_DOT", "KC_PDOT"], 0x63, 83),
+    keycode!(["KC_NONUS_BACKSLASH", "KC_NUBS"], 0x64, 86),
+    keycode!(["KC_APPLICATION", "KC_APP"], 0x65, 127),
+    keycode!(["KC_KP_EQUAL", "KC_PEQL"], 0x67, 117),
+
+    keycode!(["KC_F13"], 0x68, 183),
+    keycode!(["KC_F14"], 0x69, 184),
+    keycode!(["KC_F15"], 0x6a, 185),
+    keycode!(["KC_F16"], 0x6b, 186),
+    keycode!(["KC_F17"], 0x6c, 187),
+    keycode!(["KC_F18"], 0x6d, 188),
+    keycode!(["KC_F19"], 0x6e, 189),
+    keycode!(["KC_F20"], 0x6f, 190),
+    keycode!(["KC_F21"], 0x70, 191),
+    keycode!(["KC_F22"], 0x71, 192),
+    keycode!(["KC_F23"], 0x72, 193),
+    keycode!(["KC_F24"], 0x73, 194),
+
+    keycode!(["KC_AUDIO_MUTE", "KC_MUTE"], 0xa8, 113),
+    keycode!(["KC_AUDIO_VOL_UP", "KC_VOLU"], 0xa9, 115),
+    keycode!(["KC_AUDIO_VOL_DOWN", "KC_VOLD"], 0xaa, 114),
+    keycode!(["KC_MEDIA_NEXT_TRACK", "KC_MNXT"], 0xab, 163),
+    keycode!(["KC_MEDIA_PREV_TRACK", "KC_MPRV"], 0xac, 165),
+    keycode!(["KC_MEDIA_STOP", "KC_MSTP"], 0xad, 166),
+    keycode!(["KC_MEDIA_PLAY_PAUSE", "KC_MPLY"], 0xae, 164),
+
+    keycode!(["KC_LEFT_CTRL", "KC_LCTL", "KC_LCTRL"], 0xe0, 29),
+    keycode!(["KC_LEFT_SHIFT", "KC_LSFT", "KC_LSHIFT"], 0xe1, 42),
+    keycode!(["KC_LEFT_ALT", "KC_LALT", "KC_LOPT"], 0xe2, 56),
+    keycode!(["KC_LEFT_GUI", "KC_LGUI", "KC_LCMD", "KC_LWIN"], 0xe3, 125),
+    keycode!(["KC_RIGHT_CTRL", "KC_RCTL", "KC_RCTRL"], 0xe4, 97),
+    keycode!(["KC_RIGHT_SHIFT", "KC_RSFT", "KC_RSHIFT"], 0xe5, 54),
+    keycode!(["KC_RIGHT_ALT", "KC_RALT", "KC_ROPT", "KC_ALGR"], 0xe6, 100),
+    keycode!(["KC_RIGHT_GUI", "KC_RGUI", "KC_RCMD", "KC_RWIN"], 0xe7, 126),
+];
+
+/// The QMK modifier bits, as used by mod-tap and one-shot modifier keycodes
+#[rustfmt::skip]
+pub const MODIFIERS: &[(&str, u32)] = &[
+    ("MOD_LCTL", 29),
+    ("MOD_LSFT", 42),
+    ("MOD_LALT", 56),
+    ("MOD_LGUI", 125),
+    ("MOD_RCTL", 97),
+    ("MOD_RSFT", 54),
+    ("MOD_RALT", 100),
+    ("MOD_RGUI", 126),
+];
+
+/// The shorthands of the mod-tap keycodes, e.g. `LCTL_T(KC_A)`
+#[rustfmt::skip]
+pub const MOD_TAP_SHORTHANDS: &[(&str, &str)] = &[
+    ("LCTL_T", "MOD_LCTL"),
+    ("LSFT_T", "MOD_LSFT"),
+    ("LALT_T", "MOD_LALT"),
+    ("LGUI_T", "MOD_LGUI"),
+    ("RCTL_T", "MOD_RCTL"),
+    ("RSFT_T", "MOD_RSFT"),
+    ("RALT_T", "MOD_RALT"),
+    ("RGUI_T", "MOD_RGUI"),
+    ("CTL_T", "MOD_LCTL"),
+    ("SFT_T", "MOD_LSFT"),
+    ("ALT_T", "MOD_LALT"),
+    ("GUI_T", "MOD_LGUI"),
+];
+
+/// Find a basic keycode by one of its names
+pub fn from_name(name: &str) -> Option<&'static KeycodeInfo> {
+    KEYCODES.iter().find(|info| info.names.contains(&name))
+}
+
+/// Find a basic keycode by its numeric value
+pub fn from_code(code: u16) -> Option<&'static KeycodeInfo> {
+    KEYCODES.iter().find(|info| info.code == code)
+}
+
+/// Find the basic keycode that corresponds to the evdev event code `evdev`
+pub fn from_evdev(evdev: u32) -> Option<&'static KeycodeInfo> {
+    KEYCODES.iter().find(|info| info.evdev == evdev)
+}
+
+/// Map a QMK modifier, e.g. `MOD_LSFT`, to an evdev event code
+pub fn modifier_to_evdev(modifier: &str) -> Option<u32> {
+    MODIFIERS
+        .iter()
+        .find(|(name, _)| *name == modifier)
+        .map(|(_, evdev)| *evdev)
+}
+
+/// Map an evdev event code of a modifier key to a QMK modifier, e.g. `MOD_LSFT`
+pub fn evdev_to_modifier(evdev: u32) -> Option<&'static str> {
+    MODIFIERS
+        .iter()
+        .find(|(_, code)| *code == evdev)
+        .map(|(name, _)| *name)
+}
@@ -0,0 +1,939 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! Import and export of QMK `keymap.json` files and VIA keymap backups
+//!
+//! QMK keymaps do not carry any information about the physical keys of a layout. The
+//! physical key of each position is therefore identified by its keycode on the base layer
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::{fs, path::Path};
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::hwdevices;
+use crate::mapping::{Action, EvdevEvent, Event, Key, KeyMappingTable, Rule, Source, TapHold};
+use crate::util;
+
+pub mod keycodes;
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// The tapping term that QMK uses by default
+const DEFAULT_TAPPING_TERM: u64 = 200;
+
+/// The maximum number of layers that QMK supports
+const MAX_LAYERS: usize = 32;
+
+/// The file format that is generated by the export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QmkFormat {
+    /// A QMK `keymap.json` file, as used by the QMK configurator and `qmk compile`
+    #[default]
+    Qmk,
+
+    /// A VIA keymap backup
+    Via,
+}
+
+/// A QMK `keymap.json` file or a VIA keymap backup. Both formats store the layers as
+/// lists of keycodes, in the order of the positions of the layout
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QmkKeymap {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub keyboard: String,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub keymap: String,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub layout: String,
+
+    /// The name of the keyboard, used by VIA
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+
+    /// The USB vendor and product ID of the keyboard, used by VIA
+    #[serde(
+        default,
+        rename = "vendorProductId",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub vendor_product_id: Option<u32>,
+
+    pub layers: Vec<Vec<Keycode>>,
+}
+
+/// A keycode, either by name, or as a numeric value as used by older versions of VIA
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Keycode {
+    Name(String),
+    Code(u16),
+}
+
+impl Display for Keycode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Keycode::Name(name) => f.write_str(name),
+            Keycode::Code(code) => f.write_str(&format!("0x{:04x}", code)),
+        }
+    }
+}
+
+/// Everything that could not be converted
+#[derive(Debug, Clone, Default)]
+pub struct ConversionReport {
+    pub entries: Vec<ReportEntry>,
+}
+
+impl ConversionReport {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn add(&mut self, layer: Option<usize>, position: Option<usize>, item: String, reason: String) {
+        self.entries.push(ReportEntry {
+            layer,
+            position,
+            item,
+            reason,
+        });
+    }
+}
+
+impl Display for ConversionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in self.entries.iter() {
+            writeln!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A keycode or mapping rule that could not be converted
+#[derive(Debug, Clone)]
+pub struct ReportEntry {
+    /// The QMK layer
+    pub layer: Option<usize>,
+
+    /// The position on the QMK layer
+    pub position: Option<usize>,
+
+    pub item: String,
+    pub reason: String,
+}
+
+impl Display for ReportEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(layer) = self.layer {
+            f.write_str(&format!("Layer {}, ", layer))?;
+        }
+
+        if let Some(position) = self.position {
+            f.write_str(&format!("position {}, ", position))?;
+        }
+
+        f.write_str(&format!("{}: {}", self.item, self.reason))
+    }
+}
+
+/// A parsed QMK keycode. Layers are numbered as in QMK, starting at 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QmkKey {
+    Transparent,
+    NoKey,
+
+    /// A basic keycode, as an evdev event code
+    Basic(u32),
+
+    Momentary(usize),
+    Toggle(usize),
+    To(usize),
+
+    OneShotMod(u32),
+
+    LayerTap(usize, u32),
+    ModTap(u32, u32),
+}
+
+impl QmkKey {
+    /// The evdev event code of the physical key, if the keycode is found on the base layer
+    fn identity(&self) -> Option<u32> {
+        match *self {
+            QmkKey::Basic(evdev) | QmkKey::LayerTap(_, evdev) | QmkKey::ModTap(_, evdev) => {
+                Some(evdev)
+            }
+
+            _ => None,
+        }
+    }
+}
+
+fn parse_keycode(keycode: &Keycode) -> std::result::Result<QmkKey, String> {
+    match keycode {
+        Keycode::Code(0x0000) => Ok(QmkKey::NoKey),
+        Keycode::Code(0x0001) => Ok(QmkKey::Transparent),
+
+        Keycode::Code(code) => keycodes::from_code(*code)
+            .map(|info| QmkKey::Basic(info.evdev))
+            .ok_or_else(|| "Unsupported numeric keycode".to_string()),
+
+        Keycode::Name(name) => parse_keycode_name(name.trim()),
+    }
+}
+
+fn parse_keycode_name(name: &str) -> std::result::Result<QmkKey, String> {
+    match name {
+        "KC_TRNS" | "KC_TRANSPARENT" | "_______" => return Ok(QmkKey::Transparent),
+        "KC_NO" | "XXXXXXX" => return Ok(QmkKey::NoKey),
+        _ => {}
+    }
+
+    let (function, args) = match name.split_once('(') {
+        Some((function, args)) if args.ends_with(')') => (
+            function.trim(),
+            args[..args.len() - 1]
+                .split(',')
+                .map(|arg| arg.trim())
+                .collect::<Vec<_>>(),
+        ),
+
+        Some(_) => return Err("Invalid keycode".to_string()),
+
+        None => {
+            return keycodes::from_name(name)
+                .map(|info| QmkKey::Basic(info.evdev))
+                .ok_or_else(|| "Unsupported keycode".to_string())
+        }
+    };
+
+    let layer = |arg: &str| match arg.parse::<usize>() {
+        Ok(layer) if layer < MAX_LAYERS => Ok(layer),
+        _ => Err("Invalid layer number".to_string()),
+    };
+
+    let basic = |arg: &str| match parse_keycode_name(arg)? {
+        QmkKey::Basic(evdev) => Ok(evdev),
+        _ => Err("Only basic keycodes may be used as a tap action".to_string()),
+    };
+
+    let modifier = |arg: &str| {
+        keycodes::modifier_to_evdev(arg)
+            .ok_or_else(|| "Only a single modifier is supported".to_string())
+    };
+
+    match (function, args.as_slice()) {
+        ("MO", [arg]) => Ok(QmkKey::Momentary(layer(arg)?)),
+        ("TG", [arg]) => Ok(QmkKey::Toggle(layer(arg)?)),
+        ("TO", [arg]) | ("DF", [arg]) => Ok(QmkKey::To(layer(arg)?)),
+
+        ("OSM", [arg]) => Ok(QmkKey::OneShotMod(modifier(arg)?)),
+
+        ("LT", [layer_arg, key]) => Ok(QmkKey::LayerTap(layer(layer_arg)?, basic(key)?)),
+        ("MT", [modifier_arg, key]) => Ok(QmkKey::ModTap(modifier(modifier_arg)?, basic(key)?)),
+
+        (function, [key]) => match keycodes::MOD_TAP_SHORTHANDS
+            .iter()
+            .find(|(shorthand, _)| *shorthand == function)
+        {
+            Some((_, modifier_name)) => Ok(QmkKey::ModTap(modifier(modifier_name)?, basic(key)?)),
+            None => Err("Unsupported keycode".to_string()),
+        },
+
+        _ => Err("Unsupported keycode".to_string()),
+    }
+}
+
+/// Convert a QMK keymap to a [KeyMappingTable] for the device `(usb_vid, usb_pid)`. The
+/// function `key_index` maps an evdev event code to the index of the key on the device
+pub fn import<F>(
+    keymap: &QmkKeymap,
+    (usb_vid, usb_pid): (u16, u16),
+    key_index: F,
+) -> (KeyMappingTable, ConversionReport)
+where
+    F: Fn(u32) -> Option<usize>,
+{
+    let mut table = KeyMappingTable::new();
+    let mut report = ConversionReport::default();
+
+    let description = if !keymap.name.is_empty() {
+        format!("Imported from VIA keymap: {}", keymap.name)
+    } else {
+        format!(
+            "Imported from QMK keymap: {} {}",
+            keymap.keyboard, keymap.keymap
+        )
+    };
+
+    table.set_description(description.trim());
+
+    // identify the physical keys by their keycode on the base layer
+    let mut seen = BTreeSet::new();
+    let mut identities = Vec::new();
+
+    for (position, keycode) in keymap.layers.first().into_iter().flatten().enumerate() {
+        let identity = match parse_keycode(keycode) {
+            Ok(key) => match key.identity() {
+                Some(evdev) if !seen.insert(evdev) => {
+                    report.add(
+                        Some(0),
+                        Some(position),
+                        keycode.to_string(),
+                        "Duplicate key on the base layer, position skipped".to_string(),
+                    );
+
+                    None
+                }
+
+                Some(evdev) => match key_index(evdev) {
+                    Some(index) => Some((evdev, index)),
+
+                    None => {
+                        report.add(
+                            Some(0),
+                            Some(position),
+                            keycode.to_string(),
+                            "Key is not available on the device, position skipped".to_string(),
+                        );
+
+                        None
+                    }
+                },
+
+                None => {
+                    report.add(
+                        Some(0),
+                        Some(position),
+                        keycode.to_string(),
+                        "Could not identify the physical key, position skipped".to_string(),
+                    );
+
+                    None
+                }
+            },
+
+            Err(reason) => {
+                report.add(
+                    Some(0),
+                    Some(position),
+                    keycode.to_string(),
+                    format!("{}, position skipped", reason),
+                );
+
+                None
+            }
+        };
+
+        identities.push(identity);
+    }
+
+    for (layer, keycodes) in keymap.layers.iter().enumerate() {
+        for (position, keycode) in keycodes.iter().enumerate() {
+            let (evdev, index) = match identities.get(position) {
+                Some(Some(identity)) => *identity,
+                _ => continue,
+            };
+
+            let key = match parse_keycode(keycode) {
+                Ok(key) => key,
+
+                Err(reason) => {
+                    // the base layer has already been reported
+                    if layer > 0 {
+                        report.add(Some(layer), Some(position), keycode.to_string(), reason);
+                    }
+
+                    continue;
+                }
+            };
+
+            let action = match key {
+                QmkKey::Transparent => continue,
+
+                // the key is mapped to itself
+                QmkKey::Basic(code) if layer == 0 && code == evdev => continue,
+
+                QmkKey::Basic(code) => Action::InjectKey(EvdevEvent { event: code }),
+                QmkKey::NoKey => Action::Null,
+
+                QmkKey::Momentary(target) => Action::MomentaryLayer(target + 1),
+                QmkKey::Toggle(target) => Action::ToggleLayer(target + 1),
+                QmkKey::To(target) => Action::SwitchLayer(target + 1),
+
+                QmkKey::OneShotMod(modifier) => Action::OneShot(EvdevEvent { event: modifier }),
+
+                QmkKey::LayerTap(target, code) => Action::TapHold(TapHold {
+                    tap: Box::new(Action::InjectKey(EvdevEvent { event: code })),
+                    hold: Box::new(Action::MomentaryLayer(target + 1)),
+                    timeout_millis: DEFAULT_TAPPING_TERM,
+                }),
+
+                QmkKey::ModTap(modifier, code) => Action::TapHold(TapHold {
+                    tap: Box::new(Action::InjectKey(EvdevEvent { event: code })),
+                    hold: Box::new(Action::InjectKey(EvdevEvent { event: modifier })),
+                    timeout_millis: DEFAULT_TAPPING_TERM,
+                }),
+            };
+
+            let source = Source::new_with_layers(
+                Event::SimpleKeyDown(Key::new(index, (usb_vid, usb_pid))),
+                &[layer + 1],
+            );
+
+            table.insert(
+                source,
+                Rule::new(action, &format!("QMK: {}", keycode), true),
+            );
+        }
+    }
+
+    (table, report)
+}
+
+/// The name of the basic keycode that corresponds to the evdev event code `evdev`
+fn basic_keycode_name(evdev: u32) -> std::result::Result<&'static str, String> {
+    keycodes::from_evdev(evdev)
+        .map(|info| info.names[0])
+        .ok_or_else(|| format!("The event code {} has no QMK equivalent", evdev))
+}
+
+fn action_to_keycode(action: &Action) -> std::result::Result<String, String> {
+    let qmk_layer = |layer: usize| {
+        layer
+            .checked_sub(1)
+            .ok_or_else(|| "Invalid layer number".to_string())
+    };
+
+    match action {
+        Action::Null => Ok("KC_NO".to_string()),
+
+        Action::InjectKey(key) => basic_keycode_name(key.event).map(|name| name.to_string()),
+
+        Action::MomentaryLayer(layer) => Ok(format!("MO({})", qmk_layer(*layer)?)),
+        Action::ToggleLayer(layer) => Ok(format!("TG({})", qmk_layer(*layer)?)),
+        Action::SwitchLayer(layer) => Ok(format!("TO({})", qmk_layer(*layer)?)),
+
+        Action::OneShot(key) => keycodes::evdev_to_modifier(key.event)
+            .map(|modifier| format!("OSM({})", modifier))
+            .ok_or_else(|| "Only modifiers may be one-shot keys in QMK".to_string()),
+
+        Action::TapHold(tap_hold) => {
+            let tap = match tap_hold.tap.as_ref() {
+                Action::InjectKey(key) => basic_keycode_name(key.event)?,
+                _ => return Err("Only basic keycodes may be used as a tap action".to_string()),
+            };
+
+            match tap_hold.hold.as_ref() {
+                Action::MomentaryLayer(layer) => Ok(format!("LT({}, {})", qmk_layer(*layer)?, tap)),
+
+                Action::InjectKey(key) => {
+                    let modifier = keycodes::evdev_to_modifier(key.event)
+                        .ok_or_else(|| "Only modifiers may be used as a hold action".to_string())?;
+
+                    let shorthand = keycodes::MOD_TAP_SHORTHANDS
+                        .iter()
+                        .find(|(_, name)| *name == modifier)
+                        .map(|(shorthand, _)| *shorthand)
+                        .unwrap();
+
+                    Ok(format!("{}({})", shorthand, tap))
+                }
+
+                _ => Err("Unsupported hold action".to_string()),
+            }
+        }
+
+        Action::Call(_) | Action::Chord(_) | Action::TapDance(_) | Action::PlayMacro(_) => {
+            Err("Action is not supported by QMK keymaps".to_string())
+        }
+    }
+}
+
+/// The highest layer that is referenced by `action`
+fn referenced_layer(action: &Action) -> usize {
+    match action {
+        Action::MomentaryLayer(layer) | Action::ToggleLayer(layer) | Action::SwitchLayer(layer) => {
+            *layer
+        }
+
+        Action::TapHold(tap_hold) => {
+            referenced_layer(&tap_hold.tap).max(referenced_layer(&tap_hold.hold))
+        }
+
+        _ => 0,
+    }
+}
+
+/// Describe what is lost when `action` is exported as `keycode`
+fn export_notes(action: &Action, keycode: &str, layer: usize, evdev: u32) -> Vec<String> {
+    let mut result = Vec::new();
+
+    if let Action::TapHold(tap_hold) = action {
+        if tap_hold.timeout_millis != DEFAULT_TAPPING_TERM {
+            result.push(format!(
+                "QMK uses a global tapping term, the timeout of {} ms is lost",
+                tap_hold.timeout_millis
+            ));
+        }
+    }
+
+    if layer == 0 {
+        let identity = parse_keycode_name(keycode)
+            .ok()
+            .and_then(|key| key.identity());
+
+        if identity != Some(evdev) {
+            result.push(
+                "Remapped on the base layer, the key will be identified by this keycode on import"
+                    .to_string(),
+            );
+        }
+    }
+
+    result
+}
+
+/// Convert a [KeyMappingTable] to a QMK keymap. The function `evdev_code` maps a key to its
+/// evdev event code. Only `key-down` rules are exported
+pub fn export<F>(
+    table: &KeyMappingTable,
+    format: QmkFormat,
+    evdev_code: F,
+) -> (QmkKeymap, ConversionReport)
+where
+    F: Fn(&Key) -> Option<u32>,
+{
+    let mut report = ConversionReport::default();
+
+    let mut keys = BTreeMap::new();
+    let mut rules = BTreeMap::new();
+    let mut device = None;
+    let mut layer_count = 1;
+
+    for (source, rule) in table.mappings() {
+        let key = match &source.event {
+            Event::SimpleKeyDown(key) => key,
+
+            // key-up rules complement the key-down rules, and are not needed by QMK
+            Event::SimpleKeyUp(_) => continue,
+
+            event => {
+                report.add(
+                    None,
+                    None,
+                    event.to_string(),
+                    "Only key-down rules can be exported".to_string(),
+                );
+
+                continue;
+            }
+        };
+
+        if !rule.enabled {
+            report.add(
+                None,
+                None,
+                source.event.to_string(),
+                "Disabled rules are not exported".to_string(),
+            );
+
+            continue;
+        }
+
+        let evdev = match evdev_code(key) {
+            Some(evdev) => evdev,
+
+            None => {
+                report.add(
+                    None,
+                    None,
+                    source.event.to_string(),
+                    "Could not determine the event code of the key".to_string(),
+                );
+
+                continue;
+            }
+        };
+
+        keys.insert(key.key_index, evdev);
+        device.get_or_insert((key.usb_vid, key.usb_pid));
+
+        for layer in source.layers.0.iter() {
+            if *layer == 0 {
+                continue;
+            }
+
+            layer_count = layer_count.max(*layer).max(referenced_layer(&rule.action));
+
+            rules.insert((key.key_index, *layer), &rule.action);
+        }
+    }
+
+    let mut layers = Vec::new();
+
+    for layer in 0..layer_count {
+        let mut keycodes = Vec::new();
+
+        for (position, (key_index, evdev)) in keys.iter().enumerate() {
+            let default = if layer == 0 {
+                basic_keycode_name(*evdev).unwrap_or_else(|reason| {
+                    report.add(Some(layer), Some(position), evdev.to_string(), reason);
+
+                    "KC_NO"
+                })
+            } else {
+                "KC_TRNS"
+            };
+
+            let keycode = match rules.get(&(*key_index, layer + 1)) {
+                Some(action) => match action_to_keycode(action) {
+                    Ok(keycode) => {
+                        for reason in export_notes(action, &keycode, layer, *evdev) {
+                            report.add(Some(layer), Some(position), keycode.clone(), reason);
+                        }
+
+                        keycode
+                    }
+
+                    Err(reason) => {
+                        report.add(Some(layer), Some(position), action.to_string(), reason);
+
+                        default.to_string()
+                    }
+                },
+
+                None => default.to_string(),
+            };
+
+            keycodes.push(Keycode::Name(keycode));
+        }
+
+        layers.push(keycodes);
+    }
+
+    let keymap = match format {
+        QmkFormat::Qmk => QmkKeymap {
+            version: Some(1),
+            keymap: "default".to_string(),
+            layout: "LAYOUT".to_string(),
+            layers,
+            ..Default::default()
+        },
+
+        QmkFormat::Via => QmkKeymap {
+            name: table.description().to_string(),
+            vendor_product_id: device
+                .map(|(usb_vid, usb_pid)| (usb_vid as u32) << 16 | usb_pid as u32),
+            layers,
+            ..Default::default()
+        },
+    };
+
+    (keymap, report)
+}
+
+#[derive(Debug, Default)]
+pub struct QmkBackend {
+    pub format: QmkFormat,
+
+    /// The name of the QMK keyboard, e.g. `planck/rev6`
+    pub keyboard: String,
+
+    /// The name of the QMK layout macro, e.g. `LAYOUT_ortho_4x12`
+    pub layout: String,
+}
+
+impl QmkBackend {
+    pub fn new(format: QmkFormat, keyboard: &str, layout: &str) -> Self {
+        Self {
+            format,
+            keyboard: keyboard.to_string(),
+            layout: layout.to_string(),
+        }
+    }
+
+    /// Import a QMK `keymap.json` file or a VIA keymap backup for the device `(usb_vid, usb_pid)`
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        (usb_vid, usb_pid): (u16, u16),
+    ) -> Result<(KeyMappingTable, ConversionReport)> {
+        let data = fs::read_to_string(path.as_ref())?;
+        let keymap: QmkKeymap = serde_json::from_str(&data)?;
+
+        Ok(import(&keymap, (usb_vid, usb_pid), |evdev| {
+            util::int_to_evdev_key_event(evdev)
+                .and_then(|ev_key| hwdevices::ev_key_to_index(&ev_key, (usb_vid, usb_pid)))
+                .map(|index| index + 1)
+        }))
+    }
+
+    pub fn export(&self, table: &KeyMappingTable) -> (QmkKeymap, ConversionReport) {
+        let (mut keymap, report) = export(table, self.format, |key| {
+            hwdevices::index_to_ev_key(key.key_index + 1, (key.usb_vid, key.usb_pid))
+                .map(|ev_key| ev_key as u32)
+        });
+
+        if self.format == QmkFormat::Qmk {
+            keymap.keyboard = self.keyboard.clone();
+
+            if !self.layout.is_empty() {
+                keymap.layout = self.layout.clone();
+            }
+        }
+
+        (keymap, report)
+    }
+}
+
+impl super::Backend for QmkBackend {
+    fn generate(&self, table: &KeyMappingTable) -> Result<String> {
+        let (keymap, report) = self.export(table);
+
+        for entry in report.entries.iter() {
+            warn!("{}", entry);
+        }
+
+        let result = serde_json::to_string_pretty(&keymap)?;
+
+        Ok(result)
+    }
+
+    fn write_to_file<P: AsRef<Path>>(&self, path: P, table: &KeyMappingTable) -> Result<()> {
+        let path = path.as_ref();
+
+        let data = self.generate(table)?;
+        fs::write(path, data)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: (u16, u16) = (0x1e7d, 0x3098);
+
+    const KEY_ESC: u32 = 1;
+    const KEY_1: u32 = 2;
+    const KEY_Q: u32 = 16;
+    const KEY_W: u32 = 17;
+    const KEY_LEFTCTRL: u32 = 29;
+    const KEY_A: u32 = 30;
+    const KEY_GRAVE: u32 = 41;
+    const KEY_LEFTSHIFT: u32 = 42;
+    const KEY_SPACE: u32 = 57;
+    const KEY_CAPSLOCK: u32 = 58;
+
+    fn load_keymap() -> QmkKeymap {
+        serde_json::from_str(include_str!(
+            "../../../../support/tests/assets/qmk_keymap.json"
+        ))
+        .unwrap()
+    }
+
+    /// Use the evdev event codes as key indices
+    fn import_keymap(keymap: &QmkKeymap) -> (KeyMappingTable, ConversionReport) {
+        import(keymap, DEVICE, |evdev| Some(evdev as usize))
+    }
+
+    fn action(table: &KeyMappingTable, evdev: u32, layer: usize) -> Option<Action> {
+        let source = Source::new_with_layers(
+            Event::SimpleKeyDown(Key::new(evdev as usize, DEVICE)),
+            &[layer],
+        );
+
+        table
+            .mappings()
+            .get(&source)
+            .map(|rule| rule.action.clone())
+    }
+
+    fn inject_key(event: u32) -> Action {
+        Action::InjectKey(EvdevEvent { event })
+    }
+
+    #[test]
+    fn import_qmk_keymap() {
+        let (table, report) = import_keymap(&load_keymap());
+
+        // the base layer maps most keys to themselves
+        assert_eq!(action(&table, KEY_ESC, 1), None);
+        assert_eq!(action(&table, KEY_GRAVE, 1), None);
+
+        assert_eq!(
+            action(&table, KEY_SPACE, 1),
+            Some(Action::TapHold(TapHold {
+                tap: Box::new(inject_key(KEY_SPACE)),
+                hold: Box::new(Action::MomentaryLayer(2)),
+                timeout_millis: DEFAULT_TAPPING_TERM,
+            }))
+        );
+
+        assert_eq!(
+            action(&table, KEY_A, 1),
+            Some(Action::TapHold(TapHold {
+                tap: Box::new(inject_key(KEY_A)),
+                hold: Box::new(inject_key(KEY_LEFTCTRL)),
+                timeout_millis: DEFAULT_TAPPING_TERM,
+            }))
+        );
+
+        assert_eq!(action(&table, KEY_ESC, 2), Some(inject_key(KEY_GRAVE)));
+        assert_eq!(action(&table, KEY_Q, 2), Some(inject_key(KEY_1)));
+        assert_eq!(action(&table, KEY_CAPSLOCK, 2), Some(inject_key(KEY_ESC)));
+        assert_eq!(action(&table, KEY_SPACE, 2), None);
+
+        assert_eq!(
+            action(&table, KEY_Q, 3),
+            Some(Action::OneShot(EvdevEvent {
+                event: KEY_LEFTSHIFT
+            }))
+        );
+        assert_eq!(action(&table, KEY_W, 3), Some(Action::ToggleLayer(2)));
+        assert_eq!(action(&table, KEY_SPACE, 3), Some(Action::Null));
+        assert_eq!(
+            action(&table, KEY_CAPSLOCK, 3),
+            Some(Action::SwitchLayer(1))
+        );
+
+        // the position of MO(2) can not be identified, QK_BOOT is not supported
+        assert_eq!(report.entries.len(), 2);
+        assert_eq!(report.entries[0].item, "MO(2)");
+        assert_eq!(report.entries[1].item, "QK_BOOT");
+    }
+
+    #[test]
+    fn numeric_keycodes() {
+        let keymap: QmkKeymap = serde_json::from_str(
+            r#"{ "name": "VIA", "vendorProductId": 1, "layers": [[4, 41], [1, 20]] }"#,
+        )
+        .unwrap();
+
+        let (table, report) = import_keymap(&keymap);
+
+        assert!(report.is_empty());
+        assert_eq!(table.mappings().len(), 1);
+        assert_eq!(action(&table, KEY_ESC, 2), Some(inject_key(KEY_Q)));
+    }
+
+    #[test]
+    fn invalid_layer_numbers() {
+        let keymap: QmkKeymap = serde_json::from_str(
+            r#"{
+                "keyboard": "test",
+                "keymap": "test",
+                "layers": [
+                    ["KC_ESC", "KC_Q", "KC_W"],
+                    ["MO(31)", "MO(32)", "LT(18446744073709551615, KC_A)"]
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let (table, report) = import_keymap(&keymap);
+
+        assert_eq!(table.mappings().len(), 1);
+        assert_eq!(report.entries.len(), 2);
+        assert_eq!(report.entries[0].item, "MO(32)");
+        assert_eq!(report.entries[1].item, "LT(18446744073709551615, KC_A)");
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let (table, _report) = import_keymap(&load_keymap());
+
+        let (keymap, report) = export(&table, QmkFormat::Qmk, |key| Some(key.key_index as u32));
+
+        assert!(report.is_empty());
+        assert_eq!(keymap.layers.len(), 3);
+        assert_eq!(keymap.layers[0][0], Keycode::Name("KC_ESCAPE".to_string()));
+
+        let (result, report) = import_keymap(&keymap);
+
+        assert!(report.is_empty());
+
+        let actions = |table: &KeyMappingTable| {
+            table
+                .mappings()
+                .iter()
+                .map(|(source, rule)| (source.clone(), rule.action.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(actions(&result), actions(&table));
+    }
+
+    #[test]
+    fn export_report() {
+        let mut table = KeyMappingTable::new();
+
+        table.insert(
+            Source::new(Event::SimpleKeyDown(Key::new(KEY_A as usize, DEVICE))),
+            Rule::new(Action::PlayMacro("example".to_string()), "", true),
+        );
+
+        table.insert(
+            Source::new_with_layers(Event::SimpleKeyDown(Key::new(KEY_Q as usize, DEVICE)), &[2]),
+            Rule::new(
+                Action::TapHold(TapHold {
+                    tap: Box::new(inject_key(KEY_ESC)),
+                    hold: Box::new(Action::MomentaryLayer(3)),
+                    timeout_millis: 150,
+                }),
+                "",
+                true,
+            ),
+        );
+
+        let (keymap, report) = export(&table, QmkFormat::Via, |key| Some(key.key_index as u32));
+
+        assert_eq!(keymap.vendor_product_id, Some(0x1e7d3098));
+        assert_eq!(
+            keymap.layers,
+            vec![
+                vec![
+                    Keycode::Name("KC_Q".to_string()),
+                    Keycode::Name("KC_A".to_string())
+                ],
+                vec![
+                    Keycode::Name("LT(2, KC_ESCAPE)".to_string()),
+                    Keycode::Name("KC_TRNS".to_string())
+                ],
+                vec![
+                    Keycode::Name("KC_TRNS".to_string()),
+                    Keycode::Name("KC_TRNS".to_string())
+                ],
+            ]
+        );
+
+        // the macro and the timeout of the tap-hold key are reported
+        assert_eq!(report.entries.len(), 2);
+    }
+}
@@ -50,7 +50,11 @@ use walkdir::WalkDir;
 
 use crate::{
     backends::Backend,
-    backends::{lua::LuaBackend, native::NativeBackend},
+    backends::{
+        lua::LuaBackend,
+        native::NativeBackend,
+        qmk::{ConversionReport, QmkBackend, QmkFormat},
+    },
     lua_introspection::LuaSyntaxIntrospection,
     mapping::{KeyMappingTable, Rule},
 };
@@ -65,6 +69,7 @@ mod lua_introspection;
 mod mapping;
 mod messages;
 mod parsers;
+mod table;
 mod util;
 
 #[allow(unused)]
@@ -104,6 +109,9 @@ lazy_static! {
 
 #[derive(Debug, thiserror::Error)]
 pub enum MainError {
+    #[error("The keymap {path} already exists, use --force to overwrite it")]
+    KeymapExists { path: String },
+
     #[error("Unknown error: {description}")]
     UnknownError { description: String },
 }
@@ -120,6 +128,8 @@ lazy_static! {
     static ref MACROS_ABOUT: String = tr!("macros-about");
     static ref EVENTS_ABOUT: String = tr!("events-about");
     static ref COMPILE_ABOUT: String = tr!("compile-about");
+    static ref IMPORT_ABOUT: String = tr!("import-about");
+    static ref EXPORT_ABOUT: String = tr!("export-about");
     static ref MAPPING_ADD_ABOUT: String = tr!("mapping-add-about");
     static ref MAPPING_REMOVE_ABOUT: String = tr!("mapping-remove-about");
     static ref MAPPING_ENABLE_ABOUT: String = tr!("mapping-enable-about");
@@ -193,6 +203,46 @@ pub enum Subcommands {
         keymap: PathBuf,
     },
 
+    /// Import a keymap from a QMK `keymap.json` file or a VIA keymap backup
+    #[clap(about(IMPORT_ABOUT.as_str()))]
+    Import {
+        /// Specify the device to import the keymap for
+        #[clap(required = false, short, long, default_value = "0")]
+        device: String,
+
+        /// The filename of the keymap that will be created
+        #[clap(required = true, short, long)]
+        keymap: PathBuf,
+
+        /// Overwrite the keymap, if it already exists
+        #[clap(long)]
+        force: bool,
+
+        qmk_file: PathBuf,
+    },
+
+    /// Export a keymap to a QMK `keymap.json` file or a VIA keymap backup
+    #[clap(about(EXPORT_ABOUT.as_str()))]
+    Export {
+        /// The filename of the keymap
+        #[clap(required = false, short, long, default_value = "default.keymap")]
+        keymap: PathBuf,
+
+        /// Generate a VIA keymap backup instead of a QMK `keymap.json` file
+        #[clap(long)]
+        via: bool,
+
+        /// The name of the QMK keyboard, e.g. `planck/rev6`
+        #[clap(required = false, long, default_value = "")]
+        keyboard: String,
+
+        /// The name of the QMK layout macro
+        #[clap(required = false, long, default_value = "LAYOUT")]
+        layout: String,
+
+        qmk_file: PathBuf,
+    },
+
     /// Generate shell completions
     #[clap(hide = true, about(COMPLETIONS_ABOUT.as_str()))]
     Completions {
@@ -297,6 +347,16 @@ pub enum CompletionsSubcommands {
     Zsh,
 }
 
+/// Print everything that could not be converted during an import or export
+fn print_conversion_report(report: &ConversionReport) {
+    if report.is_empty() {
+        println!("{}", tr!("success"));
+    } else {
+        println!("{}", tr!("conversion-report").bold());
+        print!("{}", report);
+    }
+}
+
 /// Print license information
 #[allow(dead_code)]
 fn print_header() {
@@ -457,7 +517,7 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
                     source.get_layers_mut().extend(&layers);
                 }
 
-                let action = parsers::action::parse(&action)?;
+                let action = parsers::action::parse(&action, device)?;
 
                 let rule = Rule::new(action, &description, enabled);
 
@@ -636,6 +696,76 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             println!("{}", tr!("success"));
         }
 
+        Subcommands::Import {
+            device,
+            keymap,
+            force,
+            qmk_file,
+        } => {
+            let device = device.parse::<usize>()?;
+
+            let device_info = util::get_device_info_from_index(device as u64)
+                .ok_or(parsers::source::SourceError::InvalidDevice {})?;
+
+            println!(
+                "Selected device: {} {}",
+                device_info.make.bold(),
+                device_info.model.bold()
+            );
+
+            let path = if keymap.components().count() > 1 {
+                keymap
+            } else {
+                PathBuf::from(constants::DEFAULT_KEYMAP_DIR).join(keymap)
+            };
+
+            if path.exists() && !force {
+                return Err(MainError::KeymapExists {
+                    path: path.display().to_string(),
+                }
+                .into());
+            }
+
+            println!("{} {}", tr!("importing"), &qmk_file.display().bold());
+
+            let (mut table, report) =
+                QmkBackend::from_file(&qmk_file, (device_info.usb_vid, device_info.usb_pid))?;
+
+            if let Some(file_name) = path.file_name() {
+                table.set_file_name(file_name);
+            }
+
+            NativeBackend::new().write_to_file(&path, &table)?;
+
+            print_conversion_report(&report);
+        }
+
+        Subcommands::Export {
+            keymap,
+            via,
+            keyboard,
+            layout,
+            qmk_file,
+        } => {
+            let path = if keymap.components().count() > 1 {
+                keymap
+            } else {
+                PathBuf::from(constants::DEFAULT_KEYMAP_DIR).join(keymap)
+            };
+
+            println!("{} {}", tr!("exporting"), &path.display().bold());
+
+            let table = NativeBackend::from_file(&path)?;
+
+            let format = if via { QmkFormat::Via } else { QmkFormat::Qmk };
+            let backend = QmkBackend::new(format, &keyboard, &layout);
+
+            let (keymap, report) = backend.export(&table);
+            std::fs::write(&qmk_file, serde_json::to_string_pretty(&keymap)?)?;
+
+            print_conversion_report(&report);
+        }
+
         Subcommands::Macros { lua_path } => {
             let path = if lua_path.components().count() > 1 {
                 lua_path
@@ -19,108 +19,13 @@
     Copyright (c) 2019-2022, The Eruption Development Team
 */
 
-use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
-use std::path::Path;
-use std::path::PathBuf;
 
-use chrono::{DateTime, Utc};
 use color_eyre::owo_colors::OwoColorize;
-use serde::Deserialize;
-use serde::Serialize;
-use serde_json_any_key::any_key_map;
 
 use crate::util;
 
-//pub type Result<T> = std::result::Result<T, eyre::Error>;
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub struct KeyMappingTable {
-    pub metadata: TableMetadata,
-
-    #[serde(with = "any_key_map")]
-    pub mappings: BTreeMap<Source, Rule>,
-}
-
-#[allow(unused)]
-impl KeyMappingTable {
-    pub fn new() -> Self {
-        Self {
-            metadata: TableMetadata::default(),
-            mappings: BTreeMap::new(),
-        }
-    }
-
-    pub fn file_name(&self) -> &Path {
-        &self.metadata.file_name
-    }
-
-    pub fn description(&self) -> &str {
-        &self.metadata.description
-    }
-
-    pub fn set_file_name<P: AsRef<Path>>(&mut self, file_name: P) {
-        self.metadata.file_name = file_name.as_ref().to_path_buf();
-    }
-
-    pub fn set_description(&mut self, description: &str) {
-        self.metadata.description = description.to_string();
-    }
-
-    pub fn insert(&mut self, source: Source, rule: Rule) -> Option<Rule> {
-        self.mappings.insert(source, rule)
-    }
-
-    pub fn remove(&mut self, source: &Source) -> Option<Rule> {
-        self.mappings.remove(source)
-    }
-
-    pub fn metadata(&self) -> &TableMetadata {
-        &self.metadata
-    }
-
-    pub fn mappings(&self) -> &BTreeMap<Source, Rule> {
-        &self.mappings
-    }
-
-    pub fn metadata_mut(&mut self) -> &mut TableMetadata {
-        &mut self.metadata
-    }
-
-    pub fn mappings_mut(&mut self) -> &mut BTreeMap<Source, Rule> {
-        &mut self.mappings
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub struct TableMetadata {
-    pub file_name: PathBuf,
-    pub description: String,
-    pub creation_date: DateTime<Utc>,
-}
-
-#[allow(unused)]
-impl TableMetadata {
-    pub fn new(file_name: PathBuf, description: String, creation_date: DateTime<Utc>) -> Self {
-        TableMetadata {
-            file_name,
-            description,
-            creation_date,
-        }
-    }
-}
-
-impl Default for TableMetadata {
-    fn default() -> TableMetadata {
-        Self {
-            file_name: PathBuf::from("default.keymap"),
-            description: "<no description specified>".to_string(),
-            creation_date: Utc::now(),
-        }
-    }
-}
+pub use crate::table::*;
 
 impl Display for TableMetadata {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -133,42 +38,6 @@ impl Display for TableMetadata {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
-#[serde(rename_all = "lowercase")]
-pub struct Source {
-    pub event: Event,
-    pub layers: LayerSet<usize>,
-}
-
-impl Source {
-    pub fn new(event: Event) -> Self {
-        let mut layers = BTreeSet::new();
-
-        layers.insert(1);
-
-        Self {
-            event,
-            layers: LayerSet(layers),
-        }
-    }
-
-    #[allow(unused)]
-    pub fn new_with_layers(event: Event, active_layers: &[usize]) -> Self {
-        let mut layers = BTreeSet::new();
-
-        layers.extend(active_layers.iter());
-
-        Self {
-            event,
-            layers: LayerSet(layers),
-        }
-    }
-
-    pub fn get_layers_mut(&mut self) -> &mut BTreeSet<usize> {
-        &mut self.layers.0
-    }
-}
-
 impl Display for Source {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let has_layers = match self.event {
@@ -202,11 +71,6 @@ impl Display for Source {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
-pub struct LayerSet<T>(pub BTreeSet<T>)
-where
-    T: Ord + PartialOrd;
-
 impl<T> Display for LayerSet<T>
 where
     T: Display + Ord + PartialOrd,
@@ -220,38 +84,12 @@ where
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
-#[serde(rename_all = "lowercase")]
-pub struct Rule {
-    pub description: String,
-    pub enabled: bool,
-    pub action: Action,
-}
-
-impl Rule {
-    pub fn new(action: Action, description: &str, enabled: bool) -> Self {
-        Self {
-            description: description.to_owned(),
-            enabled,
-            action,
-        }
-    }
-}
-
 impl Display for Rule {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&format!("Action: {}", self.action))
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
-#[serde(rename_all = "lowercase")]
-pub enum Action {
-    Null,
-    InjectKey(EvdevEvent),
-    Call(Macro),
-}
-
 impl Display for Action {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -259,29 +97,58 @@ impl Display for Action {
 
             Action::InjectKey(key) => f.write_str(&format!("Event: {}", key)),
             Action::Call(call) => f.write_str(&format!("Call: {}", call)),
+
+            Action::SwitchLayer(layer) => f.write_str(&format!("Switch to layer: {}", layer)),
+            Action::ToggleLayer(layer) => f.write_str(&format!("Toggle layer: {}", layer)),
+            Action::MomentaryLayer(layer) => f.write_str(&format!("Momentary layer: {}", layer)),
+            Action::TapHold(tap_hold) => f.write_str(&format!("{}", tap_hold)),
+            Action::OneShot(key) => f.write_str(&format!("One-shot: {}", key)),
+            Action::Chord(chord) => f.write_str(&format!("{}", chord)),
+            Action::TapDance(tap_dance) => f.write_str(&format!("{}", tap_dance)),
+            Action::PlayMacro(name) => f.write_str(&format!("Play macro: {}", name)),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
-#[serde(rename_all = "lowercase")]
-pub struct Key {
-    pub key_index: usize,
+impl Display for TapHold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format!(
+            "Tap: [{}] Hold ({} ms): [{}]",
+            self.tap, self.timeout_millis, self.hold
+        ))
+    }
+}
 
-    /// The USB vendor ID of the device
-    pub usb_vid: u16,
+impl Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| format!("{}", key))
+            .collect::<Vec<_>>()
+            .join(" + ");
 
-    /// The USB product ID of the device
-    pub usb_pid: u16,
+        f.write_str(&format!(
+            "Chord with: {} ({} ms): [{}]",
+            keys, self.timeout_millis, self.action
+        ))
+    }
 }
 
-impl Key {
-    pub fn new(key_index: usize, (usb_vid, usb_pid): (u16, u16)) -> Self {
-        Self {
-            key_index,
-            usb_vid,
-            usb_pid,
-        }
+impl Display for TapDance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let actions = self
+            .actions
+            .iter()
+            .enumerate()
+            .map(|(index, action)| format!("{}x: [{}]", index + 1, action))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        f.write_str(&format!(
+            "Tap dance ({} ms): {}",
+            self.timeout_millis, actions
+        ))
     }
 }
 
@@ -296,12 +163,6 @@ impl Display for Key {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
-#[serde(rename_all = "lowercase")]
-pub struct EvdevEvent {
-    pub event: u32,
-}
-
 impl Display for EvdevEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         //f.write_str(&format!("Event:{}", self.key_index))
@@ -309,43 +170,12 @@ impl Display for EvdevEvent {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
-#[serde(rename_all = "lowercase")]
-pub struct Macro {
-    pub function_name: String,
-}
-
 impl Display for Macro {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&format!("function {}", self.function_name))
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
-#[serde(rename_all = "lowercase")]
-pub enum Event {
-    Null,
-
-    HidKeyDown(Key),
-    HidKeyUp(Key),
-    HidMouseDown(Key),
-    HidMouseUp(Key),
-
-    EasyShiftKeyDown(Key),
-    EasyShiftKeyUp(Key),
-    EasyShiftMouseDown(Key),
-    EasyShiftMouseUp(Key),
-    EasyShiftMouseWheel(Direction),
-    EasyShiftMouseDpi(Direction),
-
-    SimpleKeyDown(Key),
-    SimpleKeyUp(Key),
-    SimpleMouseDown(Key),
-    SimpleMouseUp(Key),
-    SimpleMouseWheel(Direction),
-    SimpleMouseDpi(Direction),
-}
-
 impl Display for Event {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -379,26 +209,6 @@ impl Display for Event {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Hash, Eq, PartialEq, Ord, PartialOrd)]
-#[serde(rename_all = "lowercase")]
-pub enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
-
-impl Direction {
-    pub fn as_int(&self) -> i32 {
-        match self {
-            Direction::Up => 1,
-            Direction::Down => 2,
-            Direction::Left => 3,
-            Direction::Right => 4,
-        }
-    }
-}
-
 impl Display for Direction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
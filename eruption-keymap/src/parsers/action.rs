@@ -20,49 +20,306 @@
 */
 
 use eyre::eyre;
+use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
 
-use crate::{mapping::*, util};
+use crate::{constants, mapping::*, util};
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
 
+#[derive(Debug, thiserror::Error)]
+pub enum ActionError {
+    #[error("Invalid key: {symbol}")]
+    InvalidKey { symbol: String },
+
+    #[error("Invalid device")]
+    InvalidDevice {},
+}
+
 #[derive(Parser)]
 #[grammar = "parsers/grammar/action.pest"]
 pub struct ActionParser;
 
-pub fn parse(action: &str) -> Result<Action> {
-    let mut result = None;
+pub fn parse(action: &str, device: usize) -> Result<Action> {
+    // the device is only queried if the action refers to keys, like a chord does
+    parse_with_usb_id(action, &|| {
+        let device_info =
+            util::get_device_info_from_index(device as u64).ok_or(ActionError::InvalidDevice {})?;
+
+        Ok((device_info.usb_vid, device_info.usb_pid))
+    })
+}
+
+fn parse_with_usb_id(action: &str, usb_id: &dyn Fn() -> Result<(u16, u16)>) -> Result<Action> {
+    let pair = ActionParser::parse(Rule::Action, action)?.next().unwrap();
+
+    // the first inner pair is the action, followed by EOI
+    let pair = pair
+        .into_inner()
+        .next()
+        .ok_or_else(|| eyre!("Parse error in action expression"))?;
+
+    parse_action(pair, usb_id)
+}
+
+fn parse_action(pair: Pair<Rule>, usb_id: &dyn Fn() -> Result<(u16, u16)>) -> Result<Action> {
+    match pair.as_rule() {
+        Rule::Event => {
+            let text = pair.into_inner().as_str();
+
+            Ok(Action::InjectKey(parse_evdev_event(text)?))
+        }
+
+        Rule::Call => {
+            let function_name = pair.into_inner().as_str().to_string();
+
+            Ok(Action::Call(Macro { function_name }))
+        }
+
+        Rule::Null => Ok(Action::Null),
+
+        Rule::PlayMacro => {
+            let name = pair.into_inner().as_str().to_string();
+
+            Ok(Action::PlayMacro(name))
+        }
+
+        Rule::SwitchLayer => {
+            let layer = pair.into_inner().as_str().parse::<usize>()?;
+
+            Ok(Action::SwitchLayer(layer))
+        }
+
+        Rule::ToggleLayer => {
+            let layer = pair.into_inner().as_str().parse::<usize>()?;
+
+            Ok(Action::ToggleLayer(layer))
+        }
+
+        Rule::MomentaryLayer => {
+            let layer = pair.into_inner().as_str().parse::<usize>()?;
+
+            Ok(Action::MomentaryLayer(layer))
+        }
+
+        Rule::OneShot => {
+            let text = pair.into_inner().as_str();
+
+            Ok(Action::OneShot(parse_evdev_event(text)?))
+        }
+
+        Rule::TapHold => {
+            let mut inner = pair.into_inner();
 
-    let pairs = ActionParser::parse(Rule::Action, action)?.next().unwrap();
+            let tap = parse_action(inner.next().unwrap(), usb_id)?;
+            let hold = parse_action(inner.next().unwrap(), usb_id)?;
 
-    for pair in pairs.into_inner() {
-        match pair.as_rule() {
-            Rule::Event => {
-                let text = pair.into_inner().as_str();
+            let timeout_millis = match inner.next() {
+                Some(timeout) => timeout.as_str().parse::<u64>()?,
+                None => constants::DEFAULT_TAP_HOLD_TIMEOUT_MILLIS,
+            };
 
-                if let Some(code) = util::evdev_event_code_from_string(text) {
-                    let event = code as u32;
-                    result = Some(Action::InjectKey(EvdevEvent { event }));
+            Ok(Action::TapHold(TapHold {
+                tap: Box::new(tap),
+                hold: Box::new(hold),
+                timeout_millis,
+            }))
+        }
+
+        Rule::TapDance => {
+            let mut actions = Vec::new();
+            let mut timeout_millis = constants::DEFAULT_TAP_DANCE_TIMEOUT_MILLIS;
+
+            for pair in pair.into_inner() {
+                if pair.as_rule() == Rule::timeout {
+                    timeout_millis = pair.as_str().parse::<u64>()?;
                 } else {
-                    let event = text.parse::<u32>()?;
-                    result = Some(Action::InjectKey(EvdevEvent { event }));
+                    actions.push(parse_action(pair, usb_id)?);
                 }
             }
 
-            Rule::Call => {
-                let function_name = pair.into_inner().as_str().to_string();
-                result = Some(Action::Call(Macro { function_name }));
-            }
+            Ok(Action::TapDance(TapDance {
+                actions,
+                timeout_millis,
+            }))
+        }
+
+        Rule::Chord => {
+            let mut inner = pair.into_inner();
+
+            let (usb_vid, usb_pid) = usb_id()?;
 
-            Rule::Null => result = Some(Action::Null),
+            let mut keys = Vec::new();
 
-            _ => {
-                /* do nothing */
-                continue;
+            for key in inner.next().unwrap().into_inner() {
+                let symbol = key.as_str();
+                let key_index = util::symbol_to_key_index(symbol, (usb_vid, usb_pid)).ok_or(
+                    ActionError::InvalidKey {
+                        symbol: symbol.to_string(),
+                    },
+                )?;
+
+                keys.push(Key::new(key_index, (usb_vid, usb_pid)));
             }
+
+            let action = parse_action(inner.next().unwrap(), usb_id)?;
+
+            let timeout_millis = match inner.next() {
+                Some(timeout) => timeout.as_str().parse::<u64>()?,
+                None => constants::DEFAULT_CHORD_TIMEOUT_MILLIS,
+            };
+
+            Ok(Action::Chord(Chord {
+                keys,
+                action: Box::new(action),
+                timeout_millis,
+            }))
         }
+
+        _ => Err(eyre!("Parse error in action expression")),
+    }
+}
+
+/// Parse an evdev event, either given by its symbolic name or as a number
+fn parse_evdev_event(text: &str) -> Result<EvdevEvent> {
+    if let Some(code) = util::evdev_event_code_from_string(text) {
+        Ok(EvdevEvent { event: code as u32 })
+    } else {
+        Ok(EvdevEvent {
+            event: text.parse::<u32>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: (u16, u16) = (0x1e7d, 0x3098);
+
+    const KEY_ESC: u32 = 1;
+    const KEY_LEFTCTRL: u32 = 29;
+    const KEY_A: u32 = 30;
+    const KEY_B: u32 = 48;
+
+    // key indices of the Vulcan 1xx
+    const INDEX_A: usize = 9;
+    const INDEX_S: usize = 15;
+
+    fn parse_for_device(action: &str) -> Result<Action> {
+        parse_with_usb_id(action, &|| Ok(DEVICE))
     }
 
-    result.ok_or_else(|| eyre!("Parse error in action expression"))
+    /// Parse an action that must not need to query the device
+    fn parse_without_device(action: &str) -> Result<Action> {
+        parse_with_usb_id(action, &|| Err(ActionError::InvalidDevice {}.into()))
+    }
+
+    fn inject_key(event: u32) -> Action {
+        Action::InjectKey(EvdevEvent { event })
+    }
+
+    #[test]
+    fn tap_hold() {
+        assert_eq!(
+            parse_without_device("tap-hold(event:KEY_A, event:KEY_LEFTCTRL)").unwrap(),
+            Action::TapHold(TapHold {
+                tap: Box::new(inject_key(KEY_A)),
+                hold: Box::new(inject_key(KEY_LEFTCTRL)),
+                timeout_millis: constants::DEFAULT_TAP_HOLD_TIMEOUT_MILLIS,
+            })
+        );
+
+        assert_eq!(
+            parse_without_device("tap-hold(event:KEY_ESC,momentary-layer:2,150)").unwrap(),
+            Action::TapHold(TapHold {
+                tap: Box::new(inject_key(KEY_ESC)),
+                hold: Box::new(Action::MomentaryLayer(2)),
+                timeout_millis: 150,
+            })
+        );
+    }
+
+    #[test]
+    fn tap_dance() {
+        assert_eq!(
+            parse_without_device("tap-dance(event:KEY_A, event:KEY_B, play-macro:my-macro)")
+                .unwrap(),
+            Action::TapDance(TapDance {
+                actions: vec![
+                    inject_key(KEY_A),
+                    inject_key(KEY_B),
+                    Action::PlayMacro("my-macro".to_string())
+                ],
+                timeout_millis: constants::DEFAULT_TAP_DANCE_TIMEOUT_MILLIS,
+            })
+        );
+
+        assert_eq!(
+            parse_without_device("tap-dance(event:KEY_A, 300)").unwrap(),
+            Action::TapDance(TapDance {
+                actions: vec![inject_key(KEY_A)],
+                timeout_millis: 300,
+            })
+        );
+    }
+
+    #[test]
+    fn chord() {
+        assert_eq!(
+            parse_for_device("chord(KEY_A + key_s, event:KEY_ESC)").unwrap(),
+            Action::Chord(Chord {
+                keys: vec![Key::new(INDEX_A, DEVICE), Key::new(INDEX_S, DEVICE)],
+                action: Box::new(inject_key(KEY_ESC)),
+                timeout_millis: constants::DEFAULT_CHORD_TIMEOUT_MILLIS,
+            })
+        );
+
+        // keys may also be given by their index
+        assert_eq!(
+            parse_for_device("chord(15, call:easy_shift, 80)").unwrap(),
+            Action::Chord(Chord {
+                keys: vec![Key::new(INDEX_S, DEVICE)],
+                action: Box::new(Action::Call(Macro {
+                    function_name: "easy_shift".to_string()
+                })),
+                timeout_millis: 80,
+            })
+        );
+    }
+
+    #[test]
+    fn chord_requires_a_device() {
+        assert!(parse_without_device("chord(KEY_A, event:KEY_B)").is_err());
+    }
+
+    #[test]
+    fn malformed_actions() {
+        let actions = [
+            "",
+            "tap-hold(event:KEY_A)",
+            "tap-hold(event:KEY_A, event:KEY_B, )",
+            "tap-hold(event:KEY_A, event:KEY_B, 200, 300)",
+            "tap-hold(event:KEY_A, event:KEY_B",
+            "tap-hold(event:KEY_A, event:KEY_B) null",
+            "tap-hold(event:KEY_A, event:KEY_B, 99999999999999999999999)",
+            "tap-hold(event:KEY_UNKNOWN, event:KEY_B)",
+            "tap-dance()",
+            "tap-dance(200)",
+            "tap-dance(event:KEY_A,, event:KEY_B)",
+            "chord(, event:KEY_A)",
+            "chord(KEY_A +, event:KEY_B)",
+            "chord(KEY_A + KEY_UNKNOWN, event:KEY_B)",
+            "chord(KEY_A)",
+            "chord(KEY_A, event:KEY_B, -5)",
+        ];
+
+        for action in actions {
+            assert!(
+                parse_for_device(action).is_err(),
+                "'{action}' should have been rejected"
+            );
+        }
+    }
 }
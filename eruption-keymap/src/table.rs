@@ -0,0 +1 @@
+../../eruption/src/keymap/table.rs
\ No newline at end of file
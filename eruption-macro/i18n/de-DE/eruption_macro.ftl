@@ -24,6 +24,7 @@ about = Ein Makro Werkzeug für Eruption
 verbose-about = Verboser Modus (-v, -vv, -vvv, etc.)
 list-about = Zeigt eine Liste der verfügbaren Makros in einer Lua Datei
 record-about = Zeichnet eine Tastensequenz auf und speichert sie als Makro
+show-about = Zeigt die Schritte einer Makro-Datei an
 compile-about = Übersetzt Makros zu Lua Code und macht sie für Eruption verfügbar
 description-about = Zeigt oder setzt die Beschreibung eines Makros
 completions-about = Shell Definitionen für Autovervollständigung erzeugen
@@ -36,6 +37,9 @@ compiling = Übersetze Makros:
 
 success = Erfolgreich
 
+recording-started = Aufnahme läuft, { $key } drücken um sie zu beenden...
+macro-saved = { $steps } Schritte gespeichert in:
+
 functions-in-file = Funktionen in Lua Datei: 
 
 action-not-implemented = Diese Aktion ist nicht implementiert
@@ -24,6 +24,7 @@ about = A CLI macro utility for Eruption
 verbose-about = Verbose mode (-v, -vv, -vvv, etc.)
 list-about = Show a list of available macros in a Lua file
 record-about = Record a key sequence and save it as a macro
+show-about = Show the steps of a macro file
 compile-about = Compile macros to Lua code and make them available to Eruption
 description-about = Show or set the description of a specified macro
 completions-about = Generate shell completions
@@ -36,6 +37,9 @@ compiling = Compiling macros:
 
 success = Success
 
+recording-started = Recording, press { $key } to stop...
+macro-saved = Saved { $steps } steps to:
+
 functions-in-file = Functions in Lua file: 
 
 action-not-implemented = This action is not implemented
@@ -0,0 +1 @@
+../../eruption/src/macro_player/file.rs
\ No newline at end of file
@@ -42,6 +42,7 @@ use std::{
 };
 
 use crate::lua_introspection::LuaSyntaxIntrospection;
+use crate::macro_file::{MacroFile, Step};
 
 // mod assistants;
 mod constants;
@@ -49,7 +50,9 @@ mod dbus_client;
 mod device;
 mod hwdevices;
 mod lua_introspection;
+mod macro_file;
 mod messages;
+mod recorder;
 mod util;
 
 #[allow(unused)]
@@ -102,7 +105,7 @@ lazy_static! {
     // static ref ASSISTANT_ABOUT: String = tr!("assistant-about");
     static ref LIST_ABOUT: String = tr!("list-about");
     // static ref MAPPING_ABOUT: String = tr!("mapping-about");
-    // static ref SHOW_ABOUT: String = tr!("show-about");
+    static ref SHOW_ABOUT: String = tr!("show-about");
     // static ref EVENTS_ABOUT: String = tr!("events-about");
     static ref COMPILE_ABOUT: String = tr!("compile-about");
     static ref MACRO_CREATE_ABOUT: String = tr!("macro-create-about");
@@ -137,17 +140,38 @@ pub enum Subcommands {
         lua_path: PathBuf,
     },
 
-    /// Record a key sequence and save it as a Lua function
+    /// Record a key sequence and save it as a macro file
     #[clap(about(RECORD_ABOUT.as_str()), display_order = 1)]
     Record {
-        #[clap(required = false, short, long, default_value = "user-macros.lua")]
-        lua_file: PathBuf,
+        /// Write the macro to the specified file, instead of the macro directory of Eruption
+        #[clap(required = false, short = 'f', long)]
+        macro_file: Option<PathBuf>,
+
+        /// The key that stops the recording
+        #[clap(required = false, short, long, default_value = "KEY_ESC")]
+        stop_key: String,
+
+        /// Record relative mouse movement
+        #[clap(short, long)]
+        mouse_movement: bool,
+
+        /// The number of times the macro is played back, 0 repeats it until it is cancelled
+        #[clap(required = false, short, long, default_value = "1")]
+        repeat: usize,
+
+        /// Do not cancel the playback of the macro when a key is pressed
+        #[clap(long)]
+        no_cancel_on_keypress: bool,
 
         macro_name: String,
 
         description: Option<String>,
     },
 
+    /// Show the steps of a macro file
+    #[clap(about(SHOW_ABOUT.as_str()), display_order = 2)]
+    Show { macro_file: PathBuf },
+
     /// Run an assistant that guides you through creating one or more key mappings
     //#[clap(about(ASSISTANT_ABOUT.as_str()))]
     //Assistant { keymap: PathBuf },
@@ -221,6 +245,13 @@ pub enum CompletionsSubcommands {
     Zsh,
 }
 
+/// Returns the path of the macro file `name` in the macro directory of Eruption
+fn macro_file_path(name: &str) -> PathBuf {
+    PathBuf::from(constants::DEFAULT_MACRO_DIR)
+        .join(name)
+        .with_extension(macro_file::MACRO_FILE_EXTENSION)
+}
+
 /// Print license information
 #[allow(dead_code)]
 fn print_header() {
@@ -331,11 +362,84 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             println!();
         } */
         Subcommands::Record {
-            lua_file: _,
-            macro_name: _,
-            description: _,
+            macro_file,
+            stop_key,
+            mouse_movement,
+            repeat,
+            no_cancel_on_keypress,
+            macro_name,
+            description,
         } => {
-            todo!()
+            let path = macro_file.unwrap_or_else(|| macro_file_path(&macro_name));
+
+            let stop_key = util::evdev_event_code_from_string(&stop_key)
+                .ok_or_else(|| eyre::eyre!("Invalid stop key: {}", stop_key))?
+                as u32;
+
+            println!(
+                "{}",
+                tr!(
+                    "recording-started",
+                    key = util::evdev_event_code_to_string(stop_key)
+                )
+                .bold()
+            );
+
+            let steps = recorder::record(stop_key, mouse_movement)?;
+
+            let mut macro_file =
+                MacroFile::new(&macro_name, &description.unwrap_or_default(), steps);
+
+            macro_file.repeat = repeat;
+            macro_file.cancel_on_keypress = !no_cancel_on_keypress;
+
+            macro_file.write_to_file(&path)?;
+
+            println!(
+                "{} {}",
+                tr!("macro-saved", steps = macro_file.steps.len()),
+                &path.display().bold()
+            );
+        }
+
+        Subcommands::Show { macro_file } => {
+            let path = if macro_file.components().count() > 1 {
+                macro_file
+            } else {
+                macro_file_path(&macro_file.to_string_lossy())
+            };
+
+            let macro_file = MacroFile::from_file(&path)?;
+
+            println!(
+                "{} - {}",
+                macro_file.metadata.name.bold(),
+                macro_file.metadata.description
+            );
+            println!(
+                "repeat: {}, cancel on keypress: {}, duration: {} ms\n",
+                macro_file.repeat,
+                macro_file.cancel_on_keypress,
+                macro_file.duration().as_millis()
+            );
+
+            for (index, step) in macro_file.steps.iter().enumerate() {
+                let text = match step {
+                    Step::KeyDown(code) => {
+                        format!("key down: {}", util::evdev_event_code_to_string(*code))
+                    }
+                    Step::KeyUp(code) => {
+                        format!("key up: {}", util::evdev_event_code_to_string(*code))
+                    }
+                    Step::ButtonDown(code) => format!("button down: {}", code),
+                    Step::ButtonUp(code) => format!("button up: {}", code),
+                    Step::MouseMove { x, y } => format!("mouse move: {}, {}", x, y),
+                    Step::MouseWheel(delta) => format!("mouse wheel: {}", delta),
+                    Step::Delay(millis) => format!("delay: {} ms", millis),
+                };
+
+                println!("{:>4}: {}", index, text);
+            }
         }
 
         Subcommands::Create {
@@ -0,0 +1,363 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use evdev_rs::enums::{EventCode, EV_REL, EV_SYN};
+use evdev_rs::{Device, DeviceWrapper, ReadFlag};
+use flume::{unbounded, RecvTimeoutError};
+use log::*;
+
+use crate::macro_file::Step;
+
+type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// The USB vendor ID of the virtual input devices of the Eruption daemon
+const VIRTUAL_DEVICE_VID: u16 = 0xffff;
+
+/// The USB product ID of the virtual keyboard of the Eruption daemon
+const VIRTUAL_KEYBOARD_PID: u16 = 0x0123;
+
+/// The USB product ID of the virtual mouse of the Eruption daemon
+const VIRTUAL_MOUSE_PID: u16 = 0x0124;
+
+/// The range of evdev event codes of mouse buttons (BTN_MISC up to KEY_OK)
+const BUTTON_CODES: std::ops::Range<u32> = 0x100..0x160;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecorderError {
+    #[error("Could not find the virtual keyboard of the Eruption daemon, is the daemon running?")]
+    VirtualKeyboardNotFound {},
+}
+
+/// An input event of the virtual keyboard or mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// A key or button event, `value` is 0 for a release, 1 for a press and 2 for an auto-repeat
+    Key {
+        code: u32,
+        value: i32,
+    },
+
+    RelX(i32),
+    RelY(i32),
+    Wheel(i32),
+
+    Sync,
+}
+
+impl InputEvent {
+    fn from_evdev(event: &evdev_rs::InputEvent) -> Option<Self> {
+        match event.event_code {
+            EventCode::EV_KEY(code) => Some(Self::Key {
+                code: code as u32,
+                value: event.value,
+            }),
+
+            EventCode::EV_REL(EV_REL::REL_X) => Some(Self::RelX(event.value)),
+            EventCode::EV_REL(EV_REL::REL_Y) => Some(Self::RelY(event.value)),
+            EventCode::EV_REL(EV_REL::REL_WHEEL) => Some(Self::Wheel(event.value)),
+
+            EventCode::EV_SYN(EV_SYN::SYN_REPORT) => Some(Self::Sync),
+
+            _ => None,
+        }
+    }
+}
+
+/// Converts a stream of input events to the steps of a macro, preserving the timing
+/// between the events
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    record_mouse_movement: bool,
+
+    steps: Vec<Step>,
+    last_time: Option<Duration>,
+
+    pressed_keys: BTreeSet<u32>,
+    pressed_buttons: BTreeSet<u32>,
+    pending_movement: (i32, i32),
+}
+
+impl Recorder {
+    pub fn new(record_mouse_movement: bool) -> Self {
+        Self {
+            record_mouse_movement,
+            ..Default::default()
+        }
+    }
+
+    /// Record the input event `event`, that occurred at `time`
+    pub fn push(&mut self, time: Duration, event: InputEvent) {
+        match event {
+            InputEvent::Key { code, value: 1 } => {
+                if BUTTON_CODES.contains(&code) {
+                    if self.pressed_buttons.insert(code) {
+                        self.add_step(time, Step::ButtonDown(code));
+                    }
+                } else if self.pressed_keys.insert(code) {
+                    self.add_step(time, Step::KeyDown(code));
+                }
+            }
+
+            // releases of keys that have been pressed before the recording started are ignored
+            InputEvent::Key { code, value: 0 } => {
+                if self.pressed_buttons.remove(&code) {
+                    self.add_step(time, Step::ButtonUp(code));
+                } else if self.pressed_keys.remove(&code) {
+                    self.add_step(time, Step::KeyUp(code));
+                }
+            }
+
+            // auto-repeat events are generated by the system during playback
+            InputEvent::Key { .. } => { /* do nothing */ }
+
+            InputEvent::RelX(value) if self.record_mouse_movement => {
+                self.pending_movement.0 += value;
+            }
+
+            InputEvent::RelY(value) if self.record_mouse_movement => {
+                self.pending_movement.1 += value;
+            }
+
+            InputEvent::RelX(_) | InputEvent::RelY(_) => { /* do nothing */ }
+
+            InputEvent::Wheel(delta) => self.add_step(time, Step::MouseWheel(delta)),
+
+            // movement on both axes is reported in a single frame
+            InputEvent::Sync => {
+                let (x, y) = std::mem::take(&mut self.pending_movement);
+
+                if x != 0 || y != 0 {
+                    self.add_step(time, Step::MouseMove { x, y });
+                }
+            }
+        }
+    }
+
+    /// Returns the recorded steps. Keys and buttons that are still pressed will be released
+    pub fn finish(mut self) -> Vec<Step> {
+        let keys = std::mem::take(&mut self.pressed_keys);
+        let buttons = std::mem::take(&mut self.pressed_buttons);
+
+        self.steps.extend(keys.into_iter().map(Step::KeyUp));
+        self.steps.extend(buttons.into_iter().map(Step::ButtonUp));
+
+        self.steps
+    }
+
+    fn add_step(&mut self, time: Duration, step: Step) {
+        if let Some(last_time) = self.last_time {
+            let millis = time.saturating_sub(last_time).as_millis() as u64;
+
+            if millis > 0 {
+                self.steps.push(Step::Delay(millis));
+            }
+        }
+
+        self.last_time = Some(time);
+        self.steps.push(step);
+    }
+}
+
+/// Find the input device with the USB ID `usb_vid:usb_pid`
+fn find_input_device(usb_vid: u16, usb_pid: u16) -> Option<PathBuf> {
+    let mut paths = fs::read_dir("/dev/input")
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().starts_with("event"))
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+
+    paths.sort();
+
+    paths.into_iter().find(|path| {
+        File::open(path)
+            .ok()
+            .and_then(|devfile| Device::new_from_file(devfile).ok())
+            .map(|device| device.vendor_id() == usb_vid && device.product_id() == usb_pid)
+            .unwrap_or(false)
+    })
+}
+
+/// Record the input events of the virtual keyboard and mouse of the Eruption daemon,
+/// until the key `stop_key` is pressed, or the program is interrupted
+pub fn record(stop_key: u32, record_mouse_movement: bool) -> Result<Vec<Step>> {
+    let keyboard = find_input_device(VIRTUAL_DEVICE_VID, VIRTUAL_KEYBOARD_PID)
+        .ok_or(RecorderError::VirtualKeyboardNotFound {})?;
+
+    let mut paths = vec![keyboard];
+
+    match find_input_device(VIRTUAL_DEVICE_VID, VIRTUAL_MOUSE_PID) {
+        Some(mouse) => paths.push(mouse),
+        None => warn!("Could not find the virtual mouse, mouse events will not be recorded"),
+    }
+
+    let (events_tx, events_rx) = unbounded();
+
+    for path in paths {
+        debug!("Recording input device: {}", path.display());
+
+        let events_tx = events_tx.clone();
+
+        thread::Builder::new()
+            .name("recorder".to_owned())
+            .spawn(move || -> Result<()> {
+                let device = Device::new_from_file(File::open(&path)?)?;
+
+                loop {
+                    match device.next_event(ReadFlag::NORMAL | ReadFlag::BLOCKING) {
+                        Ok((_status, event)) => {
+                            if events_tx.send(event).is_err() {
+                                break Ok(());
+                            }
+                        }
+
+                        Err(e) => {
+                            error!("Could not read an input event: {}", e);
+
+                            break Err(e.into());
+                        }
+                    }
+                }
+            })?;
+    }
+
+    drop(events_tx);
+
+    let mut recorder = Recorder::new(record_mouse_movement);
+
+    while !crate::QUIT.load(Ordering::SeqCst) {
+        match events_rx.recv_timeout(Duration::from_millis(250)) {
+            Ok(event) => {
+                if let Some(input_event) = InputEvent::from_evdev(&event) {
+                    if matches!(input_event, InputEvent::Key { code, value: 1 } if code == stop_key)
+                    {
+                        break;
+                    }
+
+                    let time =
+                        Duration::new(event.time.tv_sec as u64, event.time.tv_usec as u32 * 1000);
+
+                    recorder.push(time, input_event);
+                }
+            }
+
+            Err(RecvTimeoutError::Timeout) => continue,
+
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Ok(recorder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InputEvent, Recorder};
+    use crate::macro_file::Step;
+    use std::time::Duration;
+
+    const KEY_ENTER: u32 = 28;
+    const KEY_A: u32 = 30;
+    const KEY_LEFTSHIFT: u32 = 42;
+    const BTN_LEFT: u32 = 272;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(1_000_000 + millis)
+    }
+
+    fn key(code: u32, value: i32) -> InputEvent {
+        InputEvent::Key { code, value }
+    }
+
+    #[test]
+    fn keys_and_timing() {
+        let mut recorder = Recorder::new(false);
+
+        // the release of the key that started the recording is ignored
+        recorder.push(millis(0), key(KEY_ENTER, 0));
+
+        recorder.push(millis(100), key(KEY_LEFTSHIFT, 1));
+        recorder.push(millis(100), key(KEY_A, 1));
+        recorder.push(millis(300), key(KEY_A, 2));
+        recorder.push(millis(350), key(KEY_A, 0));
+        recorder.push(millis(400), InputEvent::Sync);
+
+        // shift is still pressed when the recording is stopped
+        assert_eq!(
+            recorder.finish(),
+            vec![
+                Step::KeyDown(KEY_LEFTSHIFT),
+                Step::KeyDown(KEY_A),
+                Step::Delay(250),
+                Step::KeyUp(KEY_A),
+                Step::KeyUp(KEY_LEFTSHIFT),
+            ]
+        );
+    }
+
+    #[test]
+    fn mouse() {
+        let mut recorder = Recorder::new(true);
+
+        recorder.push(millis(0), key(BTN_LEFT, 1));
+        recorder.push(millis(0), InputEvent::Sync);
+        recorder.push(millis(10), InputEvent::RelX(3));
+        recorder.push(millis(10), InputEvent::RelY(-2));
+        recorder.push(millis(10), InputEvent::Sync);
+        recorder.push(millis(20), InputEvent::RelX(1));
+        recorder.push(millis(20), InputEvent::Sync);
+        recorder.push(millis(25), key(BTN_LEFT, 0));
+        recorder.push(millis(40), InputEvent::Wheel(-1));
+
+        assert_eq!(
+            recorder.finish(),
+            vec![
+                Step::ButtonDown(BTN_LEFT),
+                Step::Delay(10),
+                Step::MouseMove { x: 3, y: -2 },
+                Step::Delay(10),
+                Step::MouseMove { x: 1, y: 0 },
+                Step::Delay(5),
+                Step::ButtonUp(BTN_LEFT),
+                Step::Delay(15),
+                Step::MouseWheel(-1),
+            ]
+        );
+
+        // mouse movement is only recorded on request
+        let mut recorder = Recorder::new(false);
+
+        recorder.push(millis(0), InputEvent::RelX(3));
+        recorder.push(millis(0), InputEvent::Sync);
+
+        assert!(recorder.finish().is_empty());
+    }
+}
@@ -25,6 +25,7 @@ verbose-about = Verboser Modus (-v, -vv, -vvv, etc.)
 completions-about = Shell Definitionen für Autovervollständigung erzeugen
 
 connecting-to = Verbinde mit: {$host}
+protocol-version = Verwende Network FX Protokoll Version {$version}
 sending-status-inquiry = Sende STATUS Anfrage...
 sending-data = Sende Daten...
 processing-image-files = Verarbeite Bilddateien...
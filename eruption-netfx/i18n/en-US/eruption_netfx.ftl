@@ -25,6 +25,7 @@ verbose-about = Verbose mode (-v, -vv, -vvv, etc.)
 completions-about = Generate shell completions
 
 connecting-to = Connecting to: {$host}
+protocol-version = Using Network FX protocol version {$version}
 sending-status-inquiry = Sending STATUS inquiry...
 sending-data = Sending data...
 processing-image-files = Pre-processing image files...
@@ -103,7 +103,7 @@ impl Backend for GnomeBackend {
             super::utils::process_image_file("/tmp/eruption-netfx/screenshot.png", &device)?;
         */
 
-        let result = String::new();
+        let result = Vec::new();
 
         Ok(result)
     }
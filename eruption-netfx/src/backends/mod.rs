@@ -23,6 +23,8 @@ use std::cell::RefCell;
 
 use dyn_clonable::{clonable, dyn_clone};
 
+use crate::protocol::LedColor;
+
 #[cfg(feature = "backend-gnome")]
 pub mod gnome;
 #[cfg(feature = "backend-wayland")]
@@ -45,7 +47,7 @@ thread_local! {
     pub(crate) static BACKENDS: RefCell<Vec<Box<dyn Backend + 'static>>> = RefCell::new(vec![]);
 }
 
-pub type BackendData = String;
+pub type BackendData = Vec<LedColor>;
 
 #[clonable]
 pub trait Backend: Clone {
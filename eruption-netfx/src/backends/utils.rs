@@ -23,6 +23,7 @@
 use crate::backends::x11::xwrap::Image;
 
 use crate::hwdevices::KeyboardDevice;
+use crate::protocol::LedColor;
 use image::{imageops::FilterType, GenericImageView};
 
 #[allow(unused_imports)]
@@ -33,10 +34,10 @@ use std::path::Path;
 #[allow(dead_code)]
 type Result<T> = std::result::Result<T, eyre::Error>;
 
-/// Converts an image buffer to a Network FX command stream
+/// Converts an image buffer to the colors of the LEDs of the device
 #[allow(dead_code)]
-pub fn process_image_buffer(buffer: &[u8], device: &KeyboardDevice) -> Result<String> {
-    let mut result = String::new();
+pub fn process_image_buffer(buffer: &[u8], device: &KeyboardDevice) -> Result<Vec<LedColor>> {
+    let mut result = Vec::new();
 
     let img = image::load_from_memory(buffer)?;
     let img = img.resize_exact(
@@ -56,23 +57,24 @@ pub fn process_image_buffer(buffer: &[u8], device: &KeyboardDevice) -> Result<St
 
             let pixel = img.get_pixel(x as u32, y as u32);
 
-            let r = pixel[0];
-            let g = pixel[1];
-            let b = pixel[2];
-            let a = pixel[3];
-
-            result += &format!("{}:{}:{}:{}:{}\n", key_index, r, g, b, a);
+            result.push(LedColor {
+                index: key_index - 1,
+                color: [pixel[0], pixel[1], pixel[2], pixel[3]],
+            });
         }
     }
 
     Ok(result)
 }
 
-/// Loads and converts an image file to a Network FX command stream
+/// Loads and converts an image file to the colors of the LEDs of the device
 #[allow(dead_code)]
 #[allow(dead_code)]
-pub fn process_image_file<P: AsRef<Path>>(filename: P, device: &KeyboardDevice) -> Result<String> {
-    let mut result = String::new();
+pub fn process_image_file<P: AsRef<Path>>(
+    filename: P,
+    device: &KeyboardDevice,
+) -> Result<Vec<LedColor>> {
+    let mut result = Vec::new();
 
     let filename = filename.as_ref();
 
@@ -94,22 +96,20 @@ pub fn process_image_file<P: AsRef<Path>>(filename: P, device: &KeyboardDevice)
 
             let pixel = img.get_pixel(x as u32, y as u32);
 
-            let r = pixel[0];
-            let g = pixel[1];
-            let b = pixel[2];
-            let a = pixel[3];
-
-            result += &format!("{}:{}:{}:{}:{}\n", key_index, r, g, b, a);
+            result.push(LedColor {
+                index: key_index - 1,
+                color: [pixel[0], pixel[1], pixel[2], pixel[3]],
+            });
         }
     }
 
     Ok(result)
 }
 
-/// Converts an image buffer to a Network FX command stream
+/// Converts an image buffer to the colors of the LEDs of the device
 #[cfg(feature = "backend-x11")]
-pub fn process_screenshot(image: &Image, device: &KeyboardDevice) -> Result<String> {
-    let mut result = String::new();
+pub fn process_screenshot(image: &Image, device: &KeyboardDevice) -> Result<Vec<LedColor>> {
+    let mut result = Vec::new();
 
     let buffer = image.into_image_buffer().unwrap();
     let img = DynamicImage::ImageRgba8(buffer);
@@ -130,12 +130,10 @@ pub fn process_screenshot(image: &Image, device: &KeyboardDevice) -> Result<Stri
 
             let pixel = img.get_pixel(x as u32, y as u32);
 
-            let r = pixel[0];
-            let g = pixel[1];
-            let b = pixel[2];
-            let a = pixel[3];
-
-            result += &format!("{}:{}:{}:{}:{}\n", key_index, r, g, b, a);
+            result.push(LedColor {
+                index: key_index - 1,
+                color: [pixel[0], pixel[1], pixel[2], pixel[3]],
+            });
         }
     }
 
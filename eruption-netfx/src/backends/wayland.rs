@@ -65,7 +65,7 @@ impl Backend for WaylandBackend {
     fn poll(&mut self) -> Result<BackendData> {
         wayshot::screenshot()?;
 
-        Ok("".to_string())
+        Ok(Vec::new())
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
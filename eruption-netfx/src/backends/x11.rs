@@ -96,9 +96,9 @@ impl Backend for X11Backend {
             .get_image(window, sel, xwrap::ALL_PLANES, x11::xlib::ZPixmap)
             .unwrap();
 
-        let commands = super::utils::process_screenshot(&image, device)?;
+        let leds = super::utils::process_screenshot(&image, device)?;
 
-        Ok(commands)
+        Ok(leds)
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
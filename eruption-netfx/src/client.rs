@@ -0,0 +1,156 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use crate::protocol::{self, LedColor, Reply};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("The server rejected the request: {reply}")]
+    ServerError { reply: String },
+
+    #[error("Unsupported protocol version: {version}")]
+    UnsupportedVersion { version: u8 },
+}
+
+/// A connection to a Network FX server
+pub struct Connection {
+    stream: BufReader<TcpStream>,
+    version: u8,
+    seq: u16,
+}
+
+impl Connection {
+    /// Connect to the server at `address`. If `version` is `None`, the highest protocol
+    /// version supported by both sides is negotiated, falling back to version 1 for
+    /// servers that do not understand the `HELLO` command
+    pub async fn connect(address: &str, version: Option<u8>) -> Result<Self> {
+        let requested = version.unwrap_or(protocol::MAX_PROTOCOL_VERSION);
+
+        if !(1..=protocol::MAX_PROTOCOL_VERSION).contains(&requested) {
+            return Err(ClientError::UnsupportedVersion { version: requested }.into());
+        }
+
+        let mut stream = BufReader::new(TcpStream::connect(address).await?);
+
+        if requested >= 2 {
+            stream
+                .write_all(format!("HELLO {}\n", requested).as_bytes())
+                .await?;
+
+            let mut reply = String::new();
+            stream.read_line(&mut reply).await?;
+
+            match protocol::parse_hello_reply(&reply) {
+                Some((version, _canvas_size)) => {
+                    return Ok(Self {
+                        stream,
+                        version,
+                        seq: 0,
+                    })
+                }
+
+                None if version.is_some() => {
+                    return Err(ClientError::ServerError {
+                        reply: reply.trim().to_string(),
+                    }
+                    .into())
+                }
+
+                None => {
+                    // servers that only implement version 1 close the connection, so reconnect
+                    stream = BufReader::new(TcpStream::connect(address).await?);
+                }
+            }
+        }
+
+        Ok(Self {
+            stream,
+            version: 1,
+            seq: 0,
+        })
+    }
+
+    /// The negotiated protocol version
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Send the colors of LEDs and wait for the server to acknowledge them. Uses a single
+    /// binary frame if the server supports protocol version 2, or else text commands
+    pub async fn send_leds(&mut self, leds: &[LedColor]) -> Result<()> {
+        if self.version >= 2 {
+            self.seq = self.seq.wrapping_add(1);
+
+            let spans = protocol::spans_from_leds(leds);
+            let frame = protocol::encode_frame(self.seq, true, &spans);
+
+            self.stream.write_all(&frame).await?;
+
+            let mut buf = [0u8; protocol::HEADER_SIZE];
+            self.stream.read_exact(&mut buf).await?;
+
+            match protocol::decode_reply(&buf)? {
+                Reply::Ack { .. } => Ok(()),
+
+                Reply::Error { code, .. } => Err(ClientError::ServerError {
+                    reply: code.to_string(),
+                }
+                .into()),
+            }
+        } else {
+            // send all commands at once, then collect one reply per command
+            self.stream
+                .write_all(protocol::format_commands(leds).as_bytes())
+                .await?;
+
+            for _ in 0..leds.len() {
+                let mut reply = String::new();
+                self.stream.read_line(&mut reply).await?;
+
+                if !reply.starts_with("OK") {
+                    return Err(ClientError::ServerError {
+                        reply: reply.trim().to_string(),
+                    }
+                    .into());
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Terminate the connection to the server
+    pub async fn close(mut self) -> Result<()> {
+        if self.version >= 2 {
+            self.stream.write_all(&protocol::encode_bye()).await?;
+        } else {
+            self.stream.write_all(b"QUIT\n").await?;
+        }
+
+        self.stream.shutdown().await?;
+
+        Ok(())
+    }
+}
@@ -22,6 +22,7 @@
 use clap::CommandFactory;
 use clap::Parser;
 use clap_complete::Shell;
+use client::Connection;
 use colored::Colorize;
 use i18n_embed::{
     fluent::{fluent_language_loader, FluentLanguageLoader},
@@ -39,8 +40,10 @@ use tokio::net::TcpStream;
 use tokio::time::Duration;
 
 mod backends;
+mod client;
 mod constants;
 mod hwdevices;
+mod protocol;
 mod util;
 
 #[derive(RustEmbed)]
@@ -97,6 +100,10 @@ pub struct Options {
     hostname: Option<String>,
     port: Option<u16>,
 
+    /// The Network FX protocol version to use, negotiated with the server if omitted
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=2))]
+    protocol: Option<u8>,
+
     #[clap(subcommand)]
     command: Subcommands,
 }
@@ -282,8 +289,14 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             if opts.verbose > 1 {
                 println!("{}", tr!("connecting-to", host = address.to_string()));
             }
-            let socket = TcpStream::connect(address).await?;
-            let mut buf_reader = BufReader::new(socket);
+            let mut connection = Connection::connect(&address, opts.protocol).await?;
+
+            if opts.verbose > 1 {
+                println!(
+                    "{}",
+                    tr!("protocol-version", version = connection.version())
+                );
+            }
 
             if filename.to_string_lossy() == "-" {
                 let stdin = io::stdin();
@@ -291,47 +304,36 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
 
                 loop {
                     let mut buffer = Vec::new();
-                    let _len = reader.read_to_end(&mut buffer).await?;
+                    let len = reader.read_to_end(&mut buffer).await?;
+                    if len == 0 {
+                        break;
+                    }
 
-                    let commands = util::process_image_buffer(&buffer, &device)?;
+                    let leds = util::process_image_buffer(&buffer, &device)?;
 
-                    // print and send the specified command
+                    // print and send the LED colors
                     if opts.verbose > 0 {
                         println!("{}", tr!("sending-data"));
                     }
                     if opts.verbose > 1 {
-                        println!("{}", &commands);
-                    }
-                    buf_reader.write_all(&Vec::from(commands)).await?;
-
-                    // receive and print the response
-                    let mut buffer = String::new();
-                    buf_reader.read_line(&mut buffer).await?;
-
-                    println!("{}", buffer.bold());
-
-                    if buffer.starts_with("BYE") || buffer.starts_with("ERROR:") {
-                        break;
+                        println!("{}", protocol::format_commands(&leds));
                     }
+                    connection.send_leds(&leds).await?;
                 }
             } else {
-                let commands = util::process_image_file(&filename, &device)?;
+                let leds = util::process_image_file(&filename, &device)?;
 
-                // print and send the specified command
+                // print and send the LED colors
                 if opts.verbose > 0 {
                     println!("{}", tr!("sending-data"));
                 }
                 if opts.verbose > 1 {
-                    println!("{}", &commands);
+                    println!("{}", protocol::format_commands(&leds));
                 }
-                buf_reader.write_all(&Vec::from(commands)).await?;
-
-                // receive and print the response
-                let mut buffer = String::new();
-                buf_reader.read_line(&mut buffer).await?;
-
-                println!("{}", &buffer.bold());
+                connection.send_leds(&leds).await?;
             }
+
+            connection.close().await?;
         }
 
         Subcommands::Animation {
@@ -347,10 +349,16 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             if opts.verbose > 1 {
                 println!("{}", tr!("connecting-to", host = address.to_string()));
             }
-            let socket = TcpStream::connect(address).await?;
-            let mut buf_reader = BufReader::new(socket);
+            let mut connection = Connection::connect(&address, opts.protocol).await?;
 
-            // holds pre-processed command-sequences for each image
+            if opts.verbose > 1 {
+                println!(
+                    "{}",
+                    tr!("protocol-version", version = connection.version())
+                );
+            }
+
+            // holds the pre-processed LED colors of each image
             let processed_images = Arc::new(Mutex::new(vec![]));
 
             if opts.verbose > 0 {
@@ -390,8 +398,8 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
                             .map_err(|e| {
                                 eprintln!("{}", tr!("image-error", message = e.to_string()))
                             })
-                            .map(|commands| {
-                                processed_images.lock().push(commands);
+                            .map(|leds| {
+                                processed_images.lock().push(leds);
                             });
                     });
                 }
@@ -402,27 +410,15 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             }
 
             loop {
-                for commands in processed_images.lock().iter() {
-                    // print and send the specified command
+                for leds in processed_images.lock().iter() {
+                    // print and send the LED colors
                     if opts.verbose > 1 {
                         println!("{}", tr!("sending-data"));
                     }
                     if opts.verbose > 2 {
-                        println!("{}", &commands);
-                    }
-                    buf_reader.write_all(&Vec::from(commands.clone())).await?;
-
-                    // receive and print the response
-                    let mut buffer = String::new();
-                    buf_reader.read_line(&mut buffer).await?;
-
-                    if opts.verbose > 1 {
-                        println!("{}", buffer.bold());
-                    }
-
-                    if buffer.starts_with("BYE") || buffer.starts_with("ERROR:") {
-                        break;
+                        println!("{}", protocol::format_commands(leds));
                     }
+                    connection.send_leds(leds).await?;
 
                     thread::sleep(Duration::from_millis(
                         frame_delay.unwrap_or(constants::DEFAULT_ANIMATION_DELAY_MILLIS),
@@ -441,8 +437,14 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             if opts.verbose > 1 {
                 println!("{}", tr!("connecting-to", host = address.to_string()));
             }
-            let socket = TcpStream::connect(address).await?;
-            let mut buf_reader = BufReader::new(socket);
+            let mut connection = Connection::connect(&address, opts.protocol).await?;
+
+            if opts.verbose > 1 {
+                println!(
+                    "{}",
+                    tr!("protocol-version", version = connection.version())
+                );
+            }
 
             // register all available screenshot backends
             backends::register_backends()?;
@@ -451,25 +453,17 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
             let mut backend = backends::get_best_fitting_backend()?;
 
             loop {
-                // request a screenshot encoded as Network FX protocol commands from the backend
-                let commands = backend.poll()?;
+                // request a screenshot, scaled down to the LEDs of the device, from the backend
+                let leds = backend.poll()?;
 
-                // print and send the commands
+                // print and send the LED colors
                 if opts.verbose > 0 {
                     println!("{}", tr!("sending-data"));
                 }
                 if opts.verbose > 1 {
-                    println!("{}", &commands);
-                }
-                buf_reader.write_all(&Vec::from(commands)).await?;
-
-                // receive and print the response
-                let mut buffer = String::new();
-                buf_reader.read_line(&mut buffer).await?;
-
-                if buffer.starts_with("BYE") || buffer.starts_with("ERROR:") {
-                    break;
+                    println!("{}", protocol::format_commands(&leds));
                 }
+                connection.send_leds(&leds).await?;
 
                 thread::sleep(Duration::from_millis(
                     frame_delay.unwrap_or(constants::DEFAULT_FRAME_DELAY_MILLIS),
@@ -0,0 +1 @@
+../../eruption/src/plugins/netfx/protocol.rs
\ No newline at end of file
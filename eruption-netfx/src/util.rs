@@ -20,14 +20,15 @@
 */
 
 use crate::hwdevices::KeyboardDevice;
+use crate::protocol::LedColor;
 use image::{imageops::FilterType, GenericImageView};
 use std::path::Path;
 
 type Result<T> = std::result::Result<T, eyre::Error>;
 
-/// Converts an image buffer to a Network FX command stream
-pub fn process_image_buffer(buffer: &[u8], device: &KeyboardDevice) -> Result<String> {
-    let mut result = String::new();
+/// Converts an image buffer to the colors of the LEDs of the device
+pub fn process_image_buffer(buffer: &[u8], device: &KeyboardDevice) -> Result<Vec<LedColor>> {
+    let mut result = Vec::new();
 
     let img = image::load_from_memory(buffer)?;
     let img = img.resize_exact(
@@ -47,21 +48,22 @@ pub fn process_image_buffer(buffer: &[u8], device: &KeyboardDevice) -> Result<St
 
             let pixel = img.get_pixel(x as u32, y as u32);
 
-            let r = pixel[0];
-            let g = pixel[1];
-            let b = pixel[2];
-            let a = pixel[3];
-
-            result += &format!("{}:{}:{}:{}:{}\n", key_index, r, g, b, a);
+            result.push(LedColor {
+                index: key_index - 1,
+                color: [pixel[0], pixel[1], pixel[2], pixel[3]],
+            });
         }
     }
 
     Ok(result)
 }
 
-/// Loads and converts an image file to a Network FX command stream
-pub fn process_image_file<P: AsRef<Path>>(filename: P, device: &KeyboardDevice) -> Result<String> {
-    let mut result = String::new();
+/// Loads and converts an image file to the colors of the LEDs of the device
+pub fn process_image_file<P: AsRef<Path>>(
+    filename: P,
+    device: &KeyboardDevice,
+) -> Result<Vec<LedColor>> {
+    let mut result = Vec::new();
 
     let filename = filename.as_ref();
 
@@ -83,12 +85,10 @@ pub fn process_image_file<P: AsRef<Path>>(filename: P, device: &KeyboardDevice)
 
             let pixel = img.get_pixel(x as u32, y as u32);
 
-            let r = pixel[0];
-            let g = pixel[1];
-            let b = pixel[2];
-            let a = pixel[3];
-
-            result += &format!("{}:{}:{}:{}:{}\n", key_index, r, g, b, a);
+            result.push(LedColor {
+                index: key_index - 1,
+                color: [pixel[0], pixel[1], pixel[2], pixel[3]],
+            });
         }
     }
 
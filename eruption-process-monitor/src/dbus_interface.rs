@@ -24,9 +24,11 @@ use dbus_tree::{Factory, Signal};
 use flume::Sender;
 use indexmap::IndexMap;
 use log::*;
+use std::str::FromStr;
 use std::sync::Arc;
 
-use crate::{Action, RuleMetadata, Selector, WindowFocusedSelectorMode};
+use crate::schedule::Schedule;
+use crate::{Action, DaylightSelectorMode, RuleMetadata, Selector, WindowFocusedSelectorMode};
 
 /// D-Bus messages and signals that are processed by the main thread
 #[derive(Debug, Clone)]
@@ -86,20 +88,30 @@ impl DbusApi {
                                     .map(|(selector, (metadata, action))| {
                                         let (sensor_val, selector_val) = match selector {
                                             Selector::ProcessExec { comm } => {
-                                                ("exec".to_string(), comm)
+                                                ("exec".to_string(), comm.to_owned())
                                             }
 
                                             Selector::WindowFocused { mode, regex } => match mode {
                                                 WindowFocusedSelectorMode::WindowName => {
-                                                    ("window-name".to_string(), regex)
-                                                }
-                                                WindowFocusedSelectorMode::WindowInstance => {
-                                                    ("window-instance".to_string(), regex)
+                                                    ("window-name".to_string(), regex.to_owned())
                                                 }
+                                                WindowFocusedSelectorMode::WindowInstance => (
+                                                    "window-instance".to_string(),
+                                                    regex.to_owned(),
+                                                ),
                                                 WindowFocusedSelectorMode::WindowClass => {
-                                                    ("window-class".to_string(), regex)
+                                                    ("window-class".to_string(), regex.to_owned())
                                                 }
                                             },
+
+                                            Selector::Schedule { schedule } => {
+                                                ("time".to_string(), schedule.to_owned())
+                                            }
+
+                                            Selector::Daylight { mode } => (
+                                                "daylight".to_string(),
+                                                mode.to_string().to_lowercase(),
+                                            ),
                                         };
 
                                         let action_val = match action {
@@ -182,6 +194,23 @@ impl DbusApi {
                                                 }
                                             }
 
+                                            "time" => {
+                                                // reject invalid schedules early
+                                                Schedule::from_str(selector_val)?;
+
+                                                sensor = Selector::Schedule {
+                                                    schedule: selector_val.into(),
+                                                }
+                                            }
+
+                                            "daylight" => {
+                                                sensor = Selector::Daylight {
+                                                    mode: DaylightSelectorMode::from_str(
+                                                        selector_val,
+                                                    )?,
+                                                }
+                                            }
+
                                             _ => {
                                                 return Err(DbusApiError::InvalidArgument {}.into())
                                             }
@@ -245,17 +274,25 @@ impl DbusApi {
             .iter()
             .map(|(selector, (metadata, action))| {
                 let (sensor_val, selector_val) = match selector {
-                    Selector::ProcessExec { comm } => ("exec".to_string(), comm),
+                    Selector::ProcessExec { comm } => ("exec".to_string(), comm.to_owned()),
 
                     Selector::WindowFocused { mode, regex } => match mode {
-                        WindowFocusedSelectorMode::WindowName => ("window-name".to_string(), regex),
+                        WindowFocusedSelectorMode::WindowName => {
+                            ("window-name".to_string(), regex.to_owned())
+                        }
                         WindowFocusedSelectorMode::WindowInstance => {
-                            ("window-instance".to_string(), regex)
+                            ("window-instance".to_string(), regex.to_owned())
                         }
                         WindowFocusedSelectorMode::WindowClass => {
-                            ("window-class".to_string(), regex)
+                            ("window-class".to_string(), regex.to_owned())
                         }
                     },
+
+                    Selector::Schedule { schedule } => ("time".to_string(), schedule.to_owned()),
+
+                    Selector::Daylight { mode } => {
+                        ("daylight".to_string(), mode.to_string().to_lowercase())
+                    }
                 };
 
                 let action_val = match action {
@@ -36,6 +36,7 @@ use crate::sensors::WaylandSensorData;
 use crate::sensors::WAYLAND_CONNECTION_SUCCESSFULL;
 
 use crate::sensors::SensorConfiguration;
+use crate::sensors::TimeSensorData;
 
 #[allow(unused)]
 use crate::sensors::SENSORS_CONFIGURATION;
@@ -67,6 +68,7 @@ use regex::Regex;
 use rust_embed::RustEmbed;
 use sensors::WindowSensorData;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use std::{env, fmt, fs, path::PathBuf, process, sync::atomic::AtomicBool, sync::Arc};
 use std::{sync::atomic::Ordering, thread, time::Duration};
 use syslog::Facility;
@@ -78,6 +80,7 @@ mod dbus_interface;
 mod logger;
 #[cfg(feature = "sensor-procmon")]
 mod procmon;
+mod schedule;
 mod sensors;
 mod util;
 
@@ -117,6 +120,9 @@ lazy_static! {
     /// Saved previous states
     pub static ref PREVIOUS_STATES_MAP: Arc<RwLock<IndexMap<i32, Action>>> = Arc::new(RwLock::new(IndexMap::new()));
 
+    /// Currently active time based rules, and the states to return to when they become inactive
+    pub static ref ACTIVE_TIME_RULES: Arc<RwLock<IndexMap<Selector, Action>>> = Arc::new(RwLock::new(IndexMap::new()));
+
     /// Currently selected slot and profile
     pub static ref CURRENT_STATE: Arc<RwLock<(Option<u64>, Option<String>)>> = Arc::new(RwLock::new((None, None)));
 
@@ -182,6 +188,44 @@ impl fmt::Display for WindowFocusedSelectorMode {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum DaylightSelectorMode {
+    Day,
+    Night,
+}
+
+impl fmt::Display for DaylightSelectorMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DaylightSelectorMode::Day => {
+                write!(f, "Day")?;
+            }
+
+            DaylightSelectorMode::Night => {
+                write!(f, "Night")?;
+            }
+        };
+
+        Ok(())
+    }
+}
+
+impl FromStr for DaylightSelectorMode {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "day" => Ok(DaylightSelectorMode::Day),
+            "night" => Ok(DaylightSelectorMode::Night),
+
+            _ => Err(MainError::UnknownError {
+                description: format!("Invalid daylight mode: {}", s),
+            }
+            .into()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum Selector {
     ProcessExec {
@@ -191,6 +235,14 @@ pub enum Selector {
         mode: WindowFocusedSelectorMode,
         regex: String,
     },
+    /// Active while the local time matches a cron-like schedule
+    Schedule {
+        schedule: String,
+    },
+    /// Active during the day or during the night, at the configured location
+    Daylight {
+        mode: DaylightSelectorMode,
+    },
 }
 
 impl fmt::Display for Selector {
@@ -203,6 +255,14 @@ impl fmt::Display for Selector {
             Selector::WindowFocused { mode, regex } => {
                 write!(f, "On window focused: {}: '{}'", mode, regex)?;
             }
+
+            Selector::Schedule { schedule } => {
+                write!(f, "On schedule: '{}'", schedule)?;
+            }
+
+            Selector::Daylight { mode } => {
+                write!(f, "On daylight: {}", mode)?;
+            }
         };
 
         Ok(())
@@ -506,6 +566,11 @@ fn process_fs_event(event: &FileSystemEvent, dbus_api_tx: &Sender<DbusApiEvent>)
                 debug!("{} => {} ({})", selector, action, metadata);
             }
 
+            // forget about time based rules that have been removed
+            ACTIVE_TIME_RULES
+                .write()
+                .retain(|selector, _| RULES_MAP.read().contains_key(selector));
+
             dbus_api_tx.send(DbusApiEvent::RulesChanged {})?;
         }
     }
@@ -520,23 +585,7 @@ fn process_dbus_event(event: &dbus_client::Message) -> Result<()> {
             // update the default rule to use the newly selected profile,
             // but only if we did not initiate the profile change
             if !PROFILE_CHANGING.load(Ordering::SeqCst) {
-                let selector = Selector::WindowFocused {
-                    mode: WindowFocusedSelectorMode::WindowInstance,
-                    regex: ".*".to_string(),
-                };
-
-                if let Some((_metadata, action)) = RULES_MAP.write().get_mut(&selector) {
-                    info!(
-                        "Updating the default rule to use the profile: {}",
-                        profile_name
-                    );
-
-                    *action = Action::SwitchToProfile {
-                        profile_name: profile_name.clone(),
-                    };
-                } else {
-                    error!("Could not get the default rule");
-                }
+                update_default_rule(profile_name);
 
                 // update global state
                 CURRENT_STATE.write().1 = Some(profile_name.clone());
@@ -552,6 +601,99 @@ fn process_dbus_event(event: &dbus_client::Message) -> Result<()> {
     Ok(())
 }
 
+/// Update the auto-generated default rule to use the profile `profile_name`
+fn update_default_rule(profile_name: &str) {
+    let selector = Selector::WindowFocused {
+        mode: WindowFocusedSelectorMode::WindowInstance,
+        regex: ".*".to_string(),
+    };
+
+    if let Some((_metadata, action)) = RULES_MAP.write().get_mut(&selector) {
+        info!(
+            "Updating the default rule to use the profile: {}",
+            profile_name
+        );
+
+        *action = Action::SwitchToProfile {
+            profile_name: profile_name.to_owned(),
+        };
+    } else {
+        error!("Could not get the default rule");
+    }
+}
+
+/// Process time related events. Time based rules are active for as long as they match,
+/// afterwards the previous profile or slot is restored
+fn process_time_event(event: &TimeSensorData) -> Result<()> {
+    let mut activated = vec![];
+    let mut deactivated = vec![];
+
+    for (selector, (metadata, action)) in RULES_MAP.read().iter() {
+        let matches = match selector {
+            Selector::Schedule { schedule } => schedule::Schedule::from_str(schedule)
+                .map(|schedule| schedule.matches(&event.now))
+                .unwrap_or(false),
+
+            Selector::Daylight { mode } => match (mode, event.daylight) {
+                (DaylightSelectorMode::Day, Some(daylight)) => daylight,
+                (DaylightSelectorMode::Night, Some(daylight)) => !daylight,
+
+                // no location has been configured
+                (_, None) => false,
+            },
+
+            _ => continue,
+        };
+
+        let is_active = metadata.enabled && matches;
+
+        let was_active = ACTIVE_TIME_RULES.read().contains_key(selector);
+
+        if is_active && !was_active {
+            activated.push((selector.clone(), action.clone()));
+        } else if !is_active && was_active {
+            deactivated.push(selector.clone());
+        }
+    }
+
+    for selector in deactivated {
+        if let Some(return_action) = ACTIVE_TIME_RULES.write().shift_remove(&selector) {
+            debug!("Time based rule became inactive: {}", selector);
+
+            if let Action::SwitchToProfile { profile_name } = &return_action {
+                update_default_rule(profile_name);
+            }
+
+            process_action(&return_action)?;
+        }
+    }
+
+    for (selector, action) in activated {
+        debug!("Time based rule became active: {}", selector);
+
+        let return_action = match action {
+            Action::SwitchToProfile { .. } => Action::SwitchToProfile {
+                profile_name: dbus_client::get_active_profile()?,
+            },
+
+            Action::SwitchToSlot { .. } => Action::SwitchToSlot {
+                slot_index: dbus_client::get_active_slot()?,
+            },
+        };
+
+        ACTIVE_TIME_RULES.write().insert(selector, return_action);
+
+        // the default rule would switch back to the previous profile on the next window focus change
+        if let Action::SwitchToProfile { profile_name } = &action {
+            update_default_rule(profile_name);
+        }
+
+        process_action(&action)?;
+    }
+
+    Ok(())
+}
+
 #[allow(dead_code)]
 fn process_window_event(event: &dyn WindowSensorData) -> Result<()> {
     trace!("Sensor data: {:#?}", event);
@@ -934,6 +1076,14 @@ pub fn run_main_loop(
                             handled = true;
                         }
 
+                        if let Some(data) = data.as_any().downcast_ref::<TimeSensorData>() {
+                            log::trace!("Processing time sensor data");
+
+                            process_time_event(data)?;
+
+                            handled = true;
+                        }
+
                         if !handled {
                             log::trace!("Sensor data: {:?}", data);
 
@@ -1308,6 +1458,20 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
                             mode: WindowFocusedSelectorMode::WindowName,
                             regex: selector.clone(),
                         });
+                    } else if sensor.contains("time") {
+                        match schedule::Schedule::from_str(selector) {
+                            Ok(_) => {
+                                parsed_selector = Some(Selector::Schedule {
+                                    schedule: selector.clone(),
+                                })
+                            }
+
+                            Err(e) => error!("Invalid schedule: {}", e),
+                        }
+                    } else if sensor.contains("daylight") {
+                        if let Ok(mode) = DaylightSelectorMode::from_str(selector) {
+                            parsed_selector = Some(Selector::Daylight { mode });
+                        }
                     }
 
                     if parsed_selector.is_none() {
@@ -0,0 +1,266 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+use std::str::FromStr;
+
+type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("Invalid schedule, expected 5 fields: minute hour day-of-month month day-of-week")]
+    InvalidFieldCount {},
+
+    #[error("Invalid {field} field: '{value}'")]
+    InvalidField { field: &'static str, value: String },
+}
+
+/// A cron-like schedule, consisting of the five fields
+/// `minute hour day-of-month month day-of-week`.
+///
+/// Each field may be `*`, a single value, a range `a-b`, a step `*/n` or `a-b/n`,
+/// or a comma separated list of these. Day-of-week 0 and 7 are both Sunday.
+/// A schedule is active during every minute that it matches, so e.g.
+/// `* 9-17 * * 1-5` is active during office hours on weekdays
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+
+    // required to implement the day-of-month/day-of-week semantics of cron
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl Schedule {
+    /// Returns `true` if the local time `time` matches the schedule
+    pub fn matches<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> bool {
+        let is_set = |set: u64, value: u32| set & (1 << value) != 0;
+
+        let day_of_month = is_set(self.days_of_month, time.day());
+        let day_of_week = is_set(self.days_of_week, time.weekday().num_days_from_sunday());
+
+        // like cron: if both day fields are restricted, either one has to match
+        let day = if self.days_of_month_restricted && self.days_of_week_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        };
+
+        day && is_set(self.minutes, time.minute())
+            && is_set(self.hours, time.hour())
+            && is_set(self.months, time.month())
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+
+        if fields.len() != 5 {
+            return Err(ScheduleError::InvalidFieldCount {}.into());
+        }
+
+        let mut days_of_week = parse_field("day-of-week", fields[4], 0, 7)?;
+
+        // 7 is an alias for sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field("minute", fields[0], 0, 59)?,
+            hours: parse_field("hour", fields[1], 0, 23)?,
+            days_of_month: parse_field("day-of-month", fields[2], 1, 31)?,
+            months: parse_field("month", fields[3], 1, 12)?,
+            days_of_week,
+
+            days_of_month_restricted: fields[2] != "*",
+            days_of_week_restricted: fields[4] != "*",
+        })
+    }
+}
+
+/// Parse a single field of a schedule into a bit set
+fn parse_field(field: &'static str, value: &str, min: u32, max: u32) -> Result<u64> {
+    let invalid = || ScheduleError::InvalidField {
+        field,
+        value: value.to_string(),
+    };
+
+    let mut result = 0;
+
+    for part in value.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                start.parse::<u32>().map_err(|_| invalid())?,
+                end.parse::<u32>().map_err(|_| invalid())?,
+            )
+        } else {
+            let start = range.parse::<u32>().map_err(|_| invalid())?;
+
+            // `a/n` means "from a to the maximum, every n"
+            if part.contains('/') {
+                (start, max)
+            } else {
+                (start, start)
+            }
+        };
+
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid().into());
+        }
+
+        for v in (start..=end).step_by(step as usize) {
+            result |= 1 << v;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Returns `true` if the sun is above the horizon at the location `latitude`, `longitude`
+/// (in degrees, north and east are positive) at the time `time`.
+///
+/// Uses the sunrise equation, which is accurate to about a minute and does not
+/// require any network access
+pub fn is_daylight(time: &DateTime<Utc>, latitude: f64, longitude: f64) -> bool {
+    let julian_date = time.timestamp() as f64 / 86400.0 + 2440587.5;
+
+    // the day whose solar noon is closest to `time`
+    let n = (julian_date - 2451545.0 - 0.0008 + longitude / 360.0).round();
+
+    let mean_solar_time = n + 0.0008 - longitude / 360.0;
+    let mean_anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.0);
+
+    let m = mean_anomaly.to_radians();
+    let center = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+
+    let ecliptic_longitude = (mean_anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let lambda = ecliptic_longitude.to_radians();
+
+    let solar_transit =
+        2451545.0 + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2.0 * lambda).sin();
+
+    let declination = (lambda.sin() * 23.4397_f64.to_radians().sin()).asin();
+    let phi = latitude.to_radians();
+
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - phi.sin() * declination.sin())
+        / (phi.cos() * declination.cos());
+
+    if cos_hour_angle < -1.0 {
+        // polar day
+        true
+    } else if cos_hour_angle > 1.0 {
+        // polar night
+        false
+    } else {
+        let hour_angle = cos_hour_angle.acos().to_degrees();
+
+        (julian_date - solar_transit).abs() <= hour_angle / 360.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_daylight, Schedule};
+    use chrono::{TimeZone, Utc};
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_schedules() {
+        assert!(Schedule::from_str("* * * * *").is_ok());
+        assert!(Schedule::from_str("*/15 9-17 1,15 * 1-5").is_ok());
+        assert!(Schedule::from_str("0 22 * * 7").is_ok());
+
+        assert!(Schedule::from_str("* * * *").is_err());
+        assert!(Schedule::from_str("60 * * * *").is_err());
+        assert!(Schedule::from_str("* 17-9 * * *").is_err());
+        assert!(Schedule::from_str("*/0 * * * *").is_err());
+        assert!(Schedule::from_str("* * 0 * *").is_err());
+    }
+
+    #[test]
+    fn match_schedules() {
+        let office_hours = Schedule::from_str("* 9-16 * * 1-5").unwrap();
+
+        // 2022-11-14 is a monday
+        assert!(office_hours.matches(&Utc.with_ymd_and_hms(2022, 11, 14, 9, 0, 0).unwrap()));
+        assert!(office_hours.matches(&Utc.with_ymd_and_hms(2022, 11, 14, 16, 59, 0).unwrap()));
+        assert!(!office_hours.matches(&Utc.with_ymd_and_hms(2022, 11, 14, 17, 0, 0).unwrap()));
+        assert!(!office_hours.matches(&Utc.with_ymd_and_hms(2022, 11, 13, 10, 0, 0).unwrap()));
+
+        let sundays = Schedule::from_str("*/30 * * * 7").unwrap();
+
+        assert!(sundays.matches(&Utc.with_ymd_and_hms(2022, 11, 13, 10, 30, 0).unwrap()));
+        assert!(!sundays.matches(&Utc.with_ymd_and_hms(2022, 11, 13, 10, 31, 0).unwrap()));
+
+        // either the day-of-month or the day-of-week has to match
+        let first_or_friday = Schedule::from_str("* * 1 * 5").unwrap();
+
+        assert!(first_or_friday.matches(&Utc.with_ymd_and_hms(2022, 11, 1, 12, 0, 0).unwrap()));
+        assert!(first_or_friday.matches(&Utc.with_ymd_and_hms(2022, 11, 18, 12, 0, 0).unwrap()));
+        assert!(!first_or_friday.matches(&Utc.with_ymd_and_hms(2022, 11, 17, 12, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn daylight() {
+        // Berlin, sunrise at 02:44 UTC and sunset at 19:34 UTC on the summer solstice
+        let (latitude, longitude) = (52.52, 13.405);
+
+        let at = |h, m| Utc.with_ymd_and_hms(2022, 6, 21, h, m, 0).unwrap();
+
+        assert!(!is_daylight(&at(2, 30), latitude, longitude));
+        assert!(is_daylight(&at(3, 0), latitude, longitude));
+        assert!(is_daylight(&at(12, 0), latitude, longitude));
+        assert!(is_daylight(&at(19, 20), latitude, longitude));
+        assert!(!is_daylight(&at(19, 50), latitude, longitude));
+        assert!(!is_daylight(&at(23, 0), latitude, longitude));
+
+        // San Francisco, sunset is at 03:35 UTC of the next day
+        let (latitude, longitude) = (37.77, -122.42);
+
+        assert!(is_daylight(&at(3, 20), latitude, longitude));
+        assert!(!is_daylight(&at(3, 50), latitude, longitude));
+
+        // Tromsø, polar day and polar night
+        let (latitude, longitude) = (69.65, 18.96);
+
+        assert!(is_daylight(&at(0, 0), latitude, longitude));
+        assert!(!is_daylight(
+            &Utc.with_ymd_and_hms(2022, 12, 21, 11, 0, 0).unwrap(),
+            latitude,
+            longitude
+        ));
+    }
+}
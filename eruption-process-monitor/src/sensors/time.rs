@@ -0,0 +1,145 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local, Utc};
+use log::*;
+
+use super::Sensor;
+use crate::schedule;
+
+type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, Clone)]
+pub struct TimeSensorData {
+    /// The current local time
+    pub now: DateTime<Local>,
+
+    /// Whether the sun is currently above the horizon, `None` if no location has been configured
+    pub daylight: Option<bool>,
+}
+
+impl super::SensorData for TimeSensorData {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeSensor {
+    pub location: Option<(f64, f64)>,
+    pub is_failed: bool,
+}
+
+impl TimeSensor {
+    pub fn new() -> Self {
+        let config = crate::CONFIG.lock();
+        let config = config.as_ref().unwrap();
+
+        let location = match (
+            config.get_float("global.latitude"),
+            config.get_float("global.longitude"),
+        ) {
+            (Ok(latitude), Ok(longitude)) => Some((latitude, longitude)),
+
+            _ => None,
+        };
+
+        TimeSensor {
+            location,
+            is_failed: false,
+        }
+    }
+}
+
+#[async_trait]
+impl Sensor for TimeSensor {
+    fn initialize(&mut self) -> Result<()> {
+        if self.location.is_none() {
+            info!("No location has been configured, rules based on daylight will be inactive");
+        }
+
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        // time based rules work independently of the desktop environment
+        true
+    }
+
+    fn get_id(&self) -> String {
+        "time".to_string()
+    }
+
+    fn get_name(&self) -> String {
+        "Time".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "Watches the time of day and the position of the sun".to_string()
+    }
+
+    fn get_usage_example(&self) -> String {
+        r#"
+Time:
+rules add time '<minute> <hour> <day of month> <month> <day of week>' [<profile-name.profile>|<slot number>]
+rules add daylight [day|night] [<profile-name.profile>|<slot number>]
+
+rules add time '* 9-16 * * 1-5' /var/lib/eruption/profiles/profile1.profile
+rules add daylight night 2
+
+Time based rules are active as long as they match, afterwards the previous profile or slot is restored.
+Rules based on daylight require the `latitude` and `longitude` to be set in the `[global]` section
+of the configuration file
+"#
+        .to_string()
+    }
+
+    fn is_pollable(&self) -> bool {
+        true
+    }
+
+    fn is_failed(&self) -> bool {
+        self.is_failed
+    }
+
+    fn set_failed(&mut self, failed: bool) {
+        self.is_failed = failed;
+    }
+
+    fn poll(&mut self) -> Result<Box<dyn super::SensorData>> {
+        let now = Local::now();
+
+        let daylight = self.location.map(|(latitude, longitude)| {
+            schedule::is_daylight(&now.with_timezone(&Utc), latitude, longitude)
+        });
+
+        Ok(Box::from(TimeSensorData { now, daylight }))
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
@@ -0,0 +1,363 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use config::{Config, ConfigError};
+use lazy_static::lazy_static;
+use log::*;
+use parking_lot::RwLock;
+use serde::Deserialize;
+
+use crate::{constants, hwdevices::RGBA};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CanvasError {
+    #[error("Invalid canvas geometry: {description}")]
+    InvalidGeometry { description: String },
+
+    #[error("Invalid region for device {device}: {description}")]
+    InvalidRegion { device: usize, description: String },
+}
+
+lazy_static! {
+    /// The layout of the global canvas
+    pub static ref CANVAS_LAYOUT: Arc<RwLock<CanvasLayout>> =
+        Arc::new(RwLock::new(CanvasLayout::default()));
+}
+
+/// A rectangular region of the canvas, that is shown on a single device
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DeviceRegion {
+    /// The index of the device, as shown by `eruptionctl devices list`
+    pub device: usize,
+
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+
+    /// Clockwise rotation of the device in degrees, one of 0, 90, 180 or 270
+    #[serde(default)]
+    pub rotation: u16,
+}
+
+/// The geometry of the canvas, and the placement of the devices on it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CanvasLayout {
+    #[serde(default = "default_width")]
+    pub width: usize,
+
+    #[serde(default = "default_height")]
+    pub height: usize,
+
+    #[serde(default)]
+    pub regions: Vec<DeviceRegion>,
+}
+
+fn default_width() -> usize {
+    constants::CANVAS_WIDTH
+}
+
+fn default_height() -> usize {
+    constants::CANVAS_HEIGHT
+}
+
+impl Default for CanvasLayout {
+    fn default() -> Self {
+        Self {
+            width: default_width(),
+            height: default_height(),
+            regions: vec![],
+        }
+    }
+}
+
+impl CanvasLayout {
+    /// Returns the number of "pixels" on the canvas
+    pub fn size(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(CanvasError::InvalidGeometry {
+                description: format!("{}x{}", self.width, self.height),
+            }
+            .into());
+        }
+
+        for region in self.regions.iter() {
+            if region.width == 0 || region.height == 0 {
+                return Err(CanvasError::InvalidRegion {
+                    device: region.device,
+                    description: "Empty region".to_string(),
+                }
+                .into());
+            }
+
+            if region.x + region.width > self.width || region.y + region.height > self.height {
+                return Err(CanvasError::InvalidRegion {
+                    device: region.device,
+                    description: "Region exceeds the bounds of the canvas".to_string(),
+                }
+                .into());
+            }
+
+            if !matches!(region.rotation, 0 | 90 | 180 | 270) {
+                return Err(CanvasError::InvalidRegion {
+                    device: region.device,
+                    description: format!("Unsupported rotation: {}", region.rotation),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the region of the device with index `device`. Devices without
+    /// a configured region will show the whole canvas
+    pub fn get_region(&self, device: usize) -> DeviceRegion {
+        self.regions
+            .iter()
+            .find(|r| r.device == device)
+            .cloned()
+            .unwrap_or(DeviceRegion {
+                device,
+                x: 0,
+                y: 0,
+                width: self.width,
+                height: self.height,
+                rotation: 0,
+            })
+    }
+
+    /// Map the region of the device with index `device` onto the native LED layout of the
+    /// device. Native LED maps are CANVAS_WIDTH x CANVAS_HEIGHT in size, in column-major order
+    pub fn map_to_device<'a>(&self, canvas: &'a [RGBA], device: usize) -> Cow<'a, [RGBA]> {
+        let region = self.get_region(device);
+
+        if region.rotation == 0
+            && region.width == constants::CANVAS_WIDTH
+            && region.height == constants::CANVAS_HEIGHT
+            && self.width == constants::CANVAS_WIDTH
+            && self.height == constants::CANVAS_HEIGHT
+        {
+            // the device covers the whole canvas, nothing to do
+            return Cow::Borrowed(canvas);
+        }
+
+        let mut result = vec![
+            RGBA {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            };
+            constants::CANVAS_SIZE
+        ];
+
+        for (index, color) in result.iter_mut().enumerate() {
+            if let Some(source) = self
+                .region_to_canvas_index(&region, index)
+                .and_then(|idx| canvas.get(idx))
+            {
+                *color = *source;
+            }
+        }
+
+        Cow::Owned(result)
+    }
+
+    /// Returns the index of the canvas "pixel" that is shown by the LED with the native
+    /// index `index` of the device with index `device`
+    pub fn device_to_canvas_index(&self, device: usize, index: usize) -> Option<usize> {
+        self.region_to_canvas_index(&self.get_region(device), index)
+    }
+
+    fn region_to_canvas_index(&self, region: &DeviceRegion, index: usize) -> Option<usize> {
+        if index >= constants::CANVAS_SIZE {
+            return None;
+        }
+
+        let dx = index / constants::CANVAS_HEIGHT;
+        let dy = index % constants::CANVAS_HEIGHT;
+
+        let u = (dx as f32 + 0.5) / constants::CANVAS_WIDTH as f32;
+        let v = (dy as f32 + 0.5) / constants::CANVAS_HEIGHT as f32;
+
+        let (u, v) = match region.rotation {
+            90 => (1.0 - v, u),
+            180 => (1.0 - u, 1.0 - v),
+            270 => (v, 1.0 - u),
+
+            _ => (u, v),
+        };
+
+        let cx = region.x + ((u * region.width as f32) as usize).min(region.width - 1);
+        let cy = region.y + ((v * region.height as f32) as usize).min(region.height - 1);
+
+        Some(cx * self.height + cy).filter(|idx| *idx < self.size())
+    }
+}
+
+/// Load the canvas layout from the `[canvas]` section of the configuration file
+pub fn load_layout(config: &Config) {
+    match config.get::<CanvasLayout>("canvas") {
+        Ok(layout) => match layout.validate() {
+            Ok(()) => {
+                info!(
+                    "Canvas layout: {}x{}, with {} device region(s)",
+                    layout.width,
+                    layout.height,
+                    layout.regions.len()
+                );
+
+                *CANVAS_LAYOUT.write() = layout;
+            }
+
+            Err(e) => error!("Invalid canvas layout, using the default layout: {}", e),
+        },
+
+        Err(ConfigError::NotFound(_)) => {
+            debug!("No canvas layout specified, using the default layout");
+        }
+
+        Err(e) => error!(
+            "Could not parse canvas layout, using the default layout: {}",
+            e
+        ),
+    }
+}
+
+/// Returns the number of "pixels" on the canvas
+pub fn get_canvas_size() -> usize {
+    CANVAS_LAYOUT.read().size()
+}
+
+/// Returns the width of the canvas
+pub fn get_canvas_width() -> usize {
+    CANVAS_LAYOUT.read().width
+}
+
+/// Returns the height of the canvas
+pub fn get_canvas_height() -> usize {
+    CANVAS_LAYOUT.read().height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CanvasLayout, DeviceRegion};
+    use crate::{constants, hwdevices::RGBA};
+
+    fn make_canvas(layout: &CanvasLayout) -> Vec<RGBA> {
+        (0..layout.size())
+            .map(|i| RGBA {
+                r: (i / layout.height) as u8,
+                g: (i % layout.height) as u8,
+                b: 0,
+                a: 255,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn default_layout_is_passthrough() {
+        let layout = CanvasLayout::default();
+        let canvas = make_canvas(&layout);
+
+        let mapped = layout.map_to_device(&canvas, 0);
+
+        assert_eq!(layout.size(), constants::CANVAS_SIZE);
+        assert!(matches!(mapped, std::borrow::Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn map_regions() {
+        let layout = CanvasLayout {
+            width: constants::CANVAS_WIDTH * 2,
+            height: constants::CANVAS_HEIGHT,
+            regions: vec![
+                DeviceRegion {
+                    device: 0,
+                    x: 0,
+                    y: 0,
+                    width: constants::CANVAS_WIDTH,
+                    height: constants::CANVAS_HEIGHT,
+                    rotation: 0,
+                },
+                DeviceRegion {
+                    device: 1,
+                    x: constants::CANVAS_WIDTH,
+                    y: 0,
+                    width: constants::CANVAS_WIDTH,
+                    height: constants::CANVAS_HEIGHT,
+                    rotation: 180,
+                },
+            ],
+        };
+
+        assert!(layout.validate().is_ok());
+
+        let canvas = make_canvas(&layout);
+
+        let first = layout.map_to_device(&canvas, 0);
+        assert_eq!(first[0].r, 0);
+        assert_eq!(first[constants::CANVAS_SIZE - 1].r, 29);
+
+        // the second device is rotated by 180 degrees
+        let second = layout.map_to_device(&canvas, 1);
+        assert_eq!(second[0].r, 59);
+        assert_eq!(second[0].g, 5);
+        assert_eq!(second[constants::CANVAS_SIZE - 1].r, 30);
+        assert_eq!(second[constants::CANVAS_SIZE - 1].g, 0);
+
+        // and back from the native layout of a device onto the canvas
+        assert_eq!(layout.device_to_canvas_index(0, 7), Some(7));
+        assert_eq!(layout.device_to_canvas_index(1, 0), Some(layout.size() - 1));
+        assert_eq!(
+            layout.device_to_canvas_index(1, constants::CANVAS_SIZE),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_regions() {
+        let layout = CanvasLayout {
+            width: 10,
+            height: 10,
+            regions: vec![DeviceRegion {
+                device: 0,
+                x: 5,
+                y: 0,
+                width: 10,
+                height: 10,
+                rotation: 45,
+            }],
+        };
+
+        assert!(layout.validate().is_err());
+    }
+}
@@ -30,12 +30,24 @@ pub const DEFAULT_PROFILE_DIR: &str = "/var/lib/eruption/profiles/";
 /// Default script directory
 pub const DEFAULT_SCRIPT_DIR: &str = "/usr/share/eruption/scripts/";
 
+/// Default device descriptions directory
+pub const DEFAULT_DEVICE_DESCRIPTION_DIR: &str = "/usr/share/eruption/devices.d/";
+
 /// Default script directory
 pub const DEFAULT_MACRO_DIR: &str = "/usr/share/eruption/scripts/lib/macros";
 
 /// Default script directory
 pub const DEFAULT_KEYMAP_DIR: &str = "/usr/share/eruption/scripts/lib/keymaps";
 
+/// Default time after which a tap-hold key is considered as being held
+pub const DEFAULT_TAP_HOLD_TIMEOUT_MILLIS: u64 = 200;
+
+/// Default time after the last tap, after which a tap dance is finished
+pub const DEFAULT_TAP_DANCE_TIMEOUT_MILLIS: u64 = 200;
+
+/// Default time window in which all keys of a chord have to be pressed
+pub const DEFAULT_CHORD_TIMEOUT_MILLIS: u64 = 50;
+
 /// The `/run/eruption/` directory
 pub const RUN_ERUPTION_DIR: &str = "/run/eruption/";
 
@@ -63,6 +75,51 @@ pub const CONTROL_SOCKET_NAME: &str = "/run/eruption/control.sock";
 /// Eruption daemon audio data UNIX domain socket
 pub const AUDIO_SOCKET_NAME: &str = "/run/eruption/audio.sock";
 
+/// Default listen address of the remote control API
+pub const REMOTE_API_DEFAULT_ADDRESS: &str = "127.0.0.1:8359";
+
+/// Max number of concurrently connected clients of the remote control API
+pub const REMOTE_API_MAX_CLIENTS: usize = 16;
+
+/// Max size of a single message of the remote control API, in bytes
+pub const REMOTE_API_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Time in which a client of the remote control API has to authenticate, or is disconnected
+pub const REMOTE_API_AUTH_TIMEOUT_MILLIS: u64 = 5000;
+
+/// Timeout of the Network FX server, used to check for a pending shutdown
+pub const NETFX_ACCEPT_TIMEOUT_MILLIS: u64 = 100;
+
+/// Max length of a single text command of the Network FX protocol, in bytes
+pub const NETFX_MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Max number of concurrently connected Network FX clients
+pub const NETFX_MAX_CLIENTS: usize = 16;
+
+/// Network FX clients that have not sent anything for this long are disconnected
+pub const NETFX_CLIENT_TIMEOUT_MILLIS: u64 = 60000;
+
+/// Default address of the OpenRGB SDK server, OpenRGB uses port 6742
+pub const OPENRGB_DEFAULT_ADDRESS: &str = "127.0.0.1:6742";
+
+/// Max number of concurrently connected OpenRGB clients
+pub const OPENRGB_MAX_CLIENTS: usize = 16;
+
+/// Max size of a single packet of the OpenRGB SDK protocol, in bytes
+pub const OPENRGB_MAX_PACKET_SIZE: usize = 1024 * 1024;
+
+/// Default mapping of E1.31 and DDP channels onto the canvas
+pub const DEFAULT_LIGHTING_MAPPING_FILE: &str = "/etc/eruption/lighting.toml";
+
+/// Default address that the E1.31 and DDP receivers bind to
+pub const LIGHTING_DEFAULT_BIND_ADDRESS: &str = "0.0.0.0";
+
+/// Give control back to the Lua scripts after no lighting data has been received for this long
+pub const LIGHTING_TIMEOUT_MILLIS: u64 = 2500;
+
+/// Timeout of the E1.31 and DDP receive calls
+pub const LIGHTING_RECV_TIMEOUT_MILLIS: u64 = 250;
+
 /// Number of slots
 pub const NUM_SLOTS: usize = 4;
 
@@ -122,6 +179,9 @@ pub const DBUS_TIMEOUT_MILLIS: u32 = 250;
 /// that may involve interactivity like e.g.: PolicyKit authentication
 pub const DBUS_TIMEOUT_MILLIS_INTERACTIVE: u32 = 30000;
 
+/// Default amount of time that LED colors set via D-Bus take precedence over the Lua scripts
+pub const DBUS_LED_COLORS_TIMEOUT_MILLIS: u64 = 5000;
+
 /// Wait n seconds before sending the LED "off pattern" on shutdown
 pub const SHUTDOWN_TIMEOUT_MILLIS: u32 = DEVICE_SETTLE_MILLIS as u32;
 
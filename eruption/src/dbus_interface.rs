@@ -25,18 +25,24 @@ use dbus_tree::{
     Access, MethodErr, Signal, {EmitsChangedSignal, Factory},
 };
 use flume::Sender;
+use lazy_static::lazy_static;
 use log::*;
+use parking_lot::{Mutex, RwLock};
 use std::path::PathBuf;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::{
+    canvas,
     color_scheme::ColorScheme,
-    constants, hwdevices,
+    constants,
+    hwdevices::{self, RGBA},
     plugins::{self, audio},
     profiles, script,
     scripting::parameters,
     scripting::parameters_util,
+    scripting::profiler,
 };
 
 /// D-Bus messages and signals that are processed by the main thread
@@ -62,6 +68,19 @@ pub enum DbusApiError {
     // OpNotSupported {},
 }
 
+lazy_static! {
+    /// LED map that has been set via the `SetLedColors` D-Bus method
+    pub static ref LED_MAP: Arc<RwLock<Vec<RGBA>>> = Arc::new(RwLock::new(vec![RGBA {
+        r: 0x00,
+        g: 0x00,
+        b: 0x00,
+        a: 0x00,
+    }; canvas::get_canvas_size()]));
+
+    /// Point in time when control over the LEDs will be returned to the Lua scripts
+    pub static ref LED_MAP_DEADLINE: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DeviceStatus {
     pub index: u64,
@@ -70,6 +89,20 @@ pub struct DeviceStatus {
     pub status: hwdevices::DeviceStatus,
 }
 
+/// A profile with its parent profile and included fragments merged in
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ResolvedProfile {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub profile_file: PathBuf,
+    pub extends: Option<String>,
+    pub includes: Vec<PathBuf>,
+    pub resolved_from: Vec<PathBuf>,
+    pub active_scripts: Vec<PathBuf>,
+    pub config: parameters::ProfileConfiguration,
+}
+
 /// D-Bus API support
 pub struct DbusApi {
     connection: Option<Arc<Connection>>,
@@ -322,13 +355,79 @@ impl DbusApi {
                                 })
                                 .outarg::<Vec<(u8, u8, u8, u8)>, _>("values"),
                             )
-                            // .add_m(
-                            //     f.method("SetLedColors", (), move |m| {
-                            //         *crate::LAST_DBUS_EVENT_TIME.lock() = Instant::now();
-                            //         let s = *script::LED_MAP.read();
-                            //         Ok(vec![m.msg.method_return().append_all(s)])
-                            //     }), // .outarg::<Vec<RGBA>, _>("values"),
-                            // )
+                            .add_m(
+                                f.method("GetScriptStats", (), move |m| {
+                                    if perms::has_monitor_permission_cached(
+                                        &m.msg.sender().unwrap(),
+                                    )
+                                    .unwrap_or(false)
+                                    {
+                                        // the summary statistics are computed here, so that clients
+                                        // don't have to interpret the buckets themselves
+                                        let profiles = profiler::SCRIPT_PROFILES
+                                            .read()
+                                            .iter()
+                                            .flatten()
+                                            .map(|profile| {
+                                                let mut value = serde_json::json!(profile);
+
+                                                value["tick_mean_micros"] =
+                                                    profile.tick.mean_micros().into();
+                                                value["tick_p95_micros"] =
+                                                    profile.tick.percentile_micros(0.95).into();
+                                                value["render_mean_micros"] =
+                                                    profile.render.mean_micros().into();
+
+                                                value
+                                            })
+                                            .collect::<Vec<_>>();
+
+                                        let result = serde_json::json!({
+                                            "bucket_bounds_micros": profiler::BUCKET_BOUNDS_MICROS,
+                                            "scripts": profiles,
+                                        })
+                                        .to_string();
+
+                                        Ok(vec![m.msg.method_return().append1(result)])
+                                    } else {
+                                        Err(MethodErr::failed("Authentication failed"))
+                                    }
+                                })
+                                .outarg::<String, _>("stats"),
+                            )
+                            .add_m(
+                                f.method("SetLedColors", (), move |m| {
+                                    if perms::has_settings_permission_cached(
+                                        &m.msg.sender().unwrap(),
+                                    )
+                                    .unwrap_or(false)
+                                    {
+                                        let (start, values, alpha, timeout_millis): (
+                                            u64,
+                                            Vec<(u8, u8, u8, u8)>,
+                                            u8,
+                                            u32,
+                                        ) = m.msg.read4()?;
+
+                                        match set_led_colors(start, &values, alpha, timeout_millis)
+                                        {
+                                            Ok(()) => Ok(vec![m.msg.method_return().append1(true)]),
+
+                                            Err(e) => {
+                                                debug!("Could not set LED colors: {}", e);
+                                                Err(MethodErr::invalid_arg(&start))
+                                            }
+                                        }
+                                    } else {
+                                        Err(MethodErr::failed("Authentication failed"))
+                                    }
+                                })
+                                .inarg::<u64, _>("start")
+                                .inarg::<Vec<(u8, u8, u8, u8)>, _>("values")
+                                .inarg::<u8, _>("alpha")
+                                .inarg::<u32, _>("timeout_millis")
+                                .outarg::<bool, _>("status"),
+                            )
                             .add_m(
                                 f.method("GetManagedDevices", (), move |m| {
                                     if perms::has_monitor_permission_cached(
@@ -721,6 +820,114 @@ impl DbusApi {
                             ),
                     ),
             )
+            .add(
+                f.object_path("/org/eruption/persistence", ())
+                    .introspectable()
+                    .add(
+                        f.interface("org.eruption.Persistence", ())
+                            .add_m(
+                                f.method("ListValues", (), move |m| {
+                                    if perms::has_monitor_permission_cached(
+                                        &m.msg.sender().unwrap(),
+                                    )
+                                    .unwrap_or(false)
+                                    {
+                                        let namespace: String = m.msg.read1()?;
+
+                                        let s = plugins::PersistencePlugin::list_persistent_data(
+                                            &namespace,
+                                        );
+
+                                        Ok(vec![m.msg.method_return().append1(s)])
+                                    } else {
+                                        Err(MethodErr::failed("Authentication failed"))
+                                    }
+                                })
+                                .inarg::<String, _>("namespace")
+                                .outarg::<Vec<(String, String, String)>, _>("values"),
+                            )
+                            .add_m(
+                                f.method("Export", (), move |m| {
+                                    if perms::has_monitor_permission_cached(
+                                        &m.msg.sender().unwrap(),
+                                    )
+                                    .unwrap_or(false)
+                                    {
+                                        let namespace: String = m.msg.read1()?;
+
+                                        let s = plugins::PersistencePlugin::export_persistent_data(
+                                            &namespace,
+                                        )
+                                        .map_err(|e| {
+                                            MethodErr::failed(&format!(
+                                                "Could not export stored values: {}",
+                                                e
+                                            ))
+                                        })?;
+
+                                        Ok(vec![m.msg.method_return().append1(s)])
+                                    } else {
+                                        Err(MethodErr::failed("Authentication failed"))
+                                    }
+                                })
+                                .inarg::<String, _>("namespace")
+                                .outarg::<String, _>("data"),
+                            )
+                            .add_m(
+                                f.method("Import", (), move |m| {
+                                    if perms::has_settings_permission_cached(
+                                        &m.msg.sender().unwrap(),
+                                    )
+                                    .unwrap_or(false)
+                                    {
+                                        let data: String = m.msg.read1()?;
+
+                                        plugins::PersistencePlugin::import_persistent_data(&data)
+                                            .map_err(|e| {
+                                            MethodErr::failed(&format!(
+                                                "Could not import stored values: {}",
+                                                e
+                                            ))
+                                        })?;
+
+                                        let s = true;
+                                        Ok(vec![m.msg.method_return().append1(s)])
+                                    } else {
+                                        Err(MethodErr::failed("Authentication failed"))
+                                    }
+                                })
+                                .inarg::<String, _>("data")
+                                .outarg::<bool, _>("status"),
+                            )
+                            .add_m(
+                                f.method("Clear", (), move |m| {
+                                    if perms::has_settings_permission_cached(
+                                        &m.msg.sender().unwrap(),
+                                    )
+                                    .unwrap_or(false)
+                                    {
+                                        let namespace: String = m.msg.read1()?;
+
+                                        let s = plugins::PersistencePlugin::clear_persistent_data(
+                                            &namespace,
+                                        )
+                                        .map_err(|e| {
+                                            MethodErr::failed(&format!(
+                                                "Could not clear stored values: {}",
+                                                e
+                                            ))
+                                        })? as u64;
+
+                                        Ok(vec![m.msg.method_return().append1(s)])
+                                    } else {
+                                        Err(MethodErr::failed("Authentication failed"))
+                                    }
+                                })
+                                .inarg::<String, _>("namespace")
+                                .outarg::<u64, _>("count"),
+                            ),
+                    ),
+            )
             .add(
                 f.object_path("/org/eruption/slot", ())
                     .introspectable()
@@ -921,6 +1128,28 @@ impl DbusApi {
                                 })
                                 .outarg::<Vec<(String, String)>, _>("profiles"),
                             )
+                            .add_m(
+                                f.method("GetProfileInfo", (), move |m| {
+                                    if perms::has_monitor_permission_cached(
+                                        &m.msg.sender().unwrap(),
+                                    )
+                                    .unwrap_or(false)
+                                    {
+                                        let profile_file: &str = m.msg.read1()?;
+
+                                        trace!("Querying resolved profile {}", profile_file);
+
+                                        let result = query_resolved_profile(profile_file)
+                                            .map_err(|e| MethodErr::failed(&format!("{}", e)))?;
+
+                                        Ok(vec![m.msg.method_return().append1(result)])
+                                    } else {
+                                        Err(MethodErr::failed("Authentication failed"))
+                                    }
+                                })
+                                .inarg::<&str, _>("filename")
+                                .outarg::<String, _>("profile"),
+                            )
                             .add_m(
                                 f.method("SetParameter", (), move |m| {
                                     if perms::has_settings_permission_cached(
@@ -1166,6 +1395,76 @@ pub fn initialize(dbus_tx: Sender<Message>) -> Result<DbusApi> {
     DbusApi::new(dbus_tx)
 }
 
+/// Set the colors of the LEDs starting at index `start` of the canvas. The colors will be blended
+/// on top of the output of the Lua scripts, until `timeout_millis` have passed
+fn set_led_colors(
+    start: u64,
+    values: &[(u8, u8, u8, u8)],
+    alpha: u8,
+    timeout_millis: u32,
+) -> Result<()> {
+    // `start` is supplied by the caller, so guard against overflows
+    let start = usize::try_from(start).map_err(|_| DbusApiError::InvalidParameter {})?;
+
+    match start.checked_add(values.len()) {
+        Some(end) if end <= canvas::get_canvas_size() => {}
+
+        _ => return Err(DbusApiError::InvalidParameter {}.into()),
+    }
+
+    let mut deadline = LED_MAP_DEADLINE.lock();
+    let mut led_map = LED_MAP.write();
+
+    // start out with a clear map, if the previously set colors have already expired
+    if !matches!(*deadline, Some(instant) if instant > Instant::now()) {
+        led_map.fill(RGBA {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        });
+    }
+
+    for (color, value) in led_map[start..].iter_mut().zip(values.iter()) {
+        *color = RGBA {
+            r: value.0,
+            g: value.1,
+            b: value.2,
+            a: ((value.3 as u32 * alpha as u32) / 255) as u8,
+        };
+    }
+
+    let timeout_millis = if timeout_millis == 0 {
+        constants::DBUS_LED_COLORS_TIMEOUT_MILLIS
+    } else {
+        timeout_millis as u64
+    };
+
+    *deadline = Some(Instant::now() + Duration::from_millis(timeout_millis));
+
+    script::FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
+
+    Ok(())
+}
+
+/// Returns `true` if colors have been set via D-Bus and did not expire yet
+pub fn is_led_map_active() -> bool {
+    let mut deadline = LED_MAP_DEADLINE.lock();
+
+    match *deadline {
+        Some(instant) if instant > Instant::now() => true,
+
+        Some(_) => {
+            debug!("Colors set via D-Bus expired, returning control to the Lua scripts");
+
+            *deadline = None;
+            false
+        }
+
+        None => false,
+    }
+}
+
 fn apply_parameter(
     profile_file: &str,
     script_file: &str,
@@ -1182,6 +1481,28 @@ fn apply_parameter(
     )
 }
 
+/// Load the profile `profile_file` and merge in its parent profile and all included fragments
+fn query_resolved_profile(profile_file: &str) -> Result<String> {
+    let profile = profiles::Profile::load_file_and_state_only(&PathBuf::from(profile_file))?;
+
+    let mut config = profile.inherited_config;
+    config.merge(&profile.config);
+
+    let result = ResolvedProfile {
+        id: profile.id.to_string(),
+        name: profile.name,
+        description: profile.description,
+        profile_file: profile.profile_file,
+        extends: profile.extends,
+        includes: profile.includes,
+        resolved_from: profile.resolved_from,
+        active_scripts: profile.active_scripts,
+        config,
+    };
+
+    Ok(serde_json::to_string_pretty(&result)?)
+}
+
 /// Query the device specific status from the global status store
 fn query_device_specific_status(device: u64) -> Result<String> {
     let device_status = crate::DEVICE_STATUS.as_ref().lock();
@@ -1523,7 +1844,7 @@ fn get_device_specific_ids(device: u64) -> Result<(u16, u16)> {
     }
 }
 
-mod perms {
+pub(crate) mod perms {
     use dbus::{arg::RefArg, arg::Variant, blocking::Connection};
     use lazy_static::lazy_static;
     use parking_lot::RwLock;
@@ -1609,7 +1930,6 @@ mod perms {
 
     pub fn has_monitor_permission(sender: &str) -> Result<(bool, bool)> {
         use bus::OrgFreedesktopDBus;
-        use polkit::OrgFreedesktopPolicyKit1Authority;
 
         let conn = Connection::new_system().unwrap();
 
@@ -1622,6 +1942,16 @@ mod perms {
         let pid: u32 = dbus_proxy.get_connection_unix_process_id(sender)?;
         let uid: u32 = dbus_proxy.get_connection_unix_user(sender)?;
 
+        has_monitor_permission_of_process(pid, uid)
+    }
+
+    /// Check whether the process `pid` of the user `uid` holds the `org.eruption.monitor`
+    /// permission, used for clients that are not connected via D-Bus
+    pub fn has_monitor_permission_of_process(pid: u32, uid: u32) -> Result<(bool, bool)> {
+        use polkit::OrgFreedesktopPolicyKit1Authority;
+
+        let conn = Connection::new_system().unwrap();
+
         let polkit_proxy = conn.with_proxy(
             "org.freedesktop.PolicyKit1",
             "/org/freedesktop/PolicyKit1/Authority",
@@ -2564,3 +2894,22 @@ mod perms {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{set_led_colors, DbusApiError};
+
+    #[test]
+    fn set_led_colors_out_of_range() {
+        let values = vec![(0xff, 0x00, 0x00, 0xff); 4];
+
+        for start in [u64::MAX, u64::MAX - 2, usize::MAX as u64, 1 << 40] {
+            let result = set_led_colors(start, &values, 0xff, 0);
+
+            assert!(matches!(
+                result.unwrap_err().downcast_ref::<DbusApiError>(),
+                Some(DbusApiError::InvalidParameter {})
+            ));
+        }
+    }
+}
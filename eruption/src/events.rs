@@ -20,9 +20,9 @@
 */
 
 use crate::{
-    constants, dbus_interface, events, macros, script, switch_profile, DbusApiEvent,
-    FileSystemEvent, KeyboardDevice, KeyboardHidEvent, MouseDevice, MouseHidEvent, ACTIVE_SLOT,
-    DEVICE_STATUS, FAILED_TXS, KEY_STATES, LUA_TXS, MOUSE_MOTION_BUF,
+    constants, dbus_interface, events, keymap, macro_player, macros, script, switch_profile,
+    DbusApiEvent, FileSystemEvent, KeyboardDevice, KeyboardHidEvent, MouseDevice, MouseHidEvent,
+    ACTIVE_SLOT, DEVICE_STATUS, FAILED_TXS, KEY_STATES, LUA_TXS, MOUSE_MOTION_BUF,
     MOUSE_MOVE_EVENT_LAST_DISPATCHED, REQUEST_FAILSAFE_MODE, REQUEST_PROFILE_RELOAD,
     UPCALL_COMPLETED_ON_KEYBOARD_HID_EVENT, UPCALL_COMPLETED_ON_KEY_DOWN,
     UPCALL_COMPLETED_ON_KEY_UP, UPCALL_COMPLETED_ON_MOUSE_BUTTON_DOWN,
@@ -34,6 +34,7 @@ use lazy_static::lazy_static;
 use log::{error, info, trace, warn};
 use parking_lot::Mutex;
 
+use std::path::Path;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -102,7 +103,7 @@ pub fn process_filesystem_event(
             REQUEST_PROFILE_RELOAD.store(true, Ordering::SeqCst);
         }
 
-        FileSystemEvent::ScriptChanged => {
+        FileSystemEvent::ScriptChanged { path } => {
             events::notify_observers(events::Event::FileSystemEvent(fsevent.clone()))
                 .unwrap_or_else(|e| error!("Error during notification of observers: {}", e));
 
@@ -110,14 +111,71 @@ pub fn process_filesystem_event(
             //     .send(DbusApiEvent::ScriptChanged)
             //     .unwrap_or_else(|e| error!("Could not send a pending dbus API event: {}", e));
 
-            // TODO: maybe make this more fine grained
-            REQUEST_PROFILE_RELOAD.store(true, Ordering::SeqCst);
+            // only restart the affected scripts if possible, changes to manifests or
+            // library files still require the whole profile to be reloaded
+            if !hot_reload_script(path) {
+                REQUEST_PROFILE_RELOAD.store(true, Ordering::SeqCst);
+            }
+        }
+
+        FileSystemEvent::KeymapChanged { path } => {
+            events::notify_observers(events::Event::FileSystemEvent(fsevent.clone()))
+                .unwrap_or_else(|e| error!("Error during notification of observers: {}", e));
+
+            keymap::reload_keymap(path)
+                .unwrap_or_else(|e| error!("Could not reload the keymap table: {}", e));
         }
     }
 
     Ok(())
 }
 
+/// Request a hot reload of all running instances of the script `path`. Returns `false` if the
+/// script is not running, or if at least one of its instances could not be reloaded
+fn hot_reload_script(path: &Path) -> bool {
+    if path.extension().unwrap_or_default() != "lua" || !path.is_file() {
+        return false;
+    }
+
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    let lua_txs = LUA_TXS.read();
+    let instances = lua_txs
+        .iter()
+        .enumerate()
+        .filter(|(_idx, lua_tx)| {
+            lua_tx
+                .script_file
+                .canonicalize()
+                .unwrap_or_else(|_| lua_tx.script_file.clone())
+                == path
+        })
+        .collect::<Vec<_>>();
+
+    // a failed VM is not running anymore, so it can not be reloaded
+    if instances.is_empty()
+        || instances
+            .iter()
+            .any(|(idx, lua_tx)| lua_tx.is_failed || FAILED_TXS.read().contains(idx))
+    {
+        return false;
+    }
+
+    info!("Hot reloading script: {}", path.display());
+
+    let mut result = true;
+
+    for (_idx, lua_tx) in instances {
+        lua_tx.send(script::Message::HotReload).unwrap_or_else(|e| {
+            error!("Could not send a message to a Lua VM: {}", e);
+
+            result = false;
+        });
+    }
+
+    result
+}
+
 /// Process D-Bus events
 pub fn process_dbus_event(
     dbus_event: &dbus_interface::Message,
@@ -859,10 +917,26 @@ pub fn process_keyboard_event(
     // notify all observers of raw events
     events::notify_observers(events::Event::RawKeyboardEvent(raw_event.clone())).ok();
 
+    // set if the native keymap engine remapped the key
+    let mut consumed = false;
+
     if let evdev_rs::enums::EventCode::EV_KEY(ref code) = raw_event.event_code {
         let is_pressed = raw_event.value > 0;
         let index = keyboard_device.read().ev_key_to_key_index(*code);
 
+        let usb_id = (
+            keyboard_device.read().get_usb_vid(),
+            keyboard_device.read().get_usb_pid(),
+        );
+
+        // pressing a key cancels the playback of macros, this has to happen before
+        // the upcalls, since the key may start the playback of a macro
+        if raw_event.value == 1 {
+            macro_player::cancel_on_keypress();
+        }
+
+        consumed = keymap::process_key_event(index as usize, *code as u32, raw_event.value, usb_id);
+
         trace!("Key index: {:#x}", index);
 
         if is_pressed {
@@ -953,13 +1027,16 @@ pub fn process_keyboard_event(
     }
 
     // handler for Message::MirrorKey will drop the key if a Lua VM
-    // called inject_key(..), so that the key won't be reported twice
-    macros::UINPUT_TX
-        .read()
-        .as_ref()
-        .unwrap()
-        .send(macros::Message::MirrorKey(raw_event.clone()))
-        .unwrap_or_else(|e| error!("Could not send a pending keyboard event: {}", e));
+    // called inject_key(..), so that the key won't be reported twice.
+    // Keys that have been remapped by the keymap engine are not mirrored at all
+    if !consumed {
+        macros::UINPUT_TX
+            .read()
+            .as_ref()
+            .unwrap()
+            .send(macros::Message::MirrorKey(raw_event.clone()))
+            .unwrap_or_else(|e| error!("Could not send a pending keyboard event: {}", e));
+    }
 
     Ok(())
 }
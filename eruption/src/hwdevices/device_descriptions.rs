@@ -0,0 +1,636 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use evdev_rs::enums::EV_KEY;
+use hidapi::HidApi;
+use log::*;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::any::Any;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::hwdevices::DeviceStatus;
+
+use super::{
+    corsair_strafe, generic_keyboard, generic_lamp_array, generic_mouse, roccat_aimo_pad,
+    roccat_burst_pro, roccat_elo_71_air, roccat_kain_100, roccat_kain_2xx, roccat_kone_aimo,
+    roccat_kone_aimo_remastered, roccat_kone_pro, roccat_kone_pro_air, roccat_kone_pure_ultra,
+    roccat_kone_xp, roccat_kone_xtd, roccat_kova_2016, roccat_kova_aimo, roccat_magma, roccat_nyth,
+    roccat_vulcan_1xx, roccat_vulcan_pro, roccat_vulcan_pro_tkl, roccat_vulcan_tkl,
+};
+use super::{
+    DeviceCapabilities, DeviceInfoTrait, DeviceTrait, DriverMetadata, HwDeviceError,
+    KeyboardDevice, KeyboardDeviceTrait, KeyboardDriver, KeyboardHidEvent, KeyboardHidEventCode,
+    LedKind, MaturityLevel, MiscDevice, MiscDriver, MouseDevice, MouseDeviceTrait, MouseDriver,
+    RGBA,
+};
+
+pub type Result<T> = super::Result<T>;
+
+type KeyboardBindFn = dyn Fn(&HidApi, u16, u16, &str) -> Result<KeyboardDevice> + Sync + Send;
+type MouseBindFn = dyn Fn(&HidApi, u16, u16, &str) -> Result<MouseDevice> + Sync + Send;
+type MiscBindFn = dyn Fn(&HidApi, u16, u16, &str) -> Result<MiscDevice> + Sync + Send;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeviceDescriptionError {
+    #[error("Unknown protocol family '{protocol}' for device class '{device_class}'")]
+    UnknownProtocol {
+        device_class: String,
+        protocol: String,
+    },
+
+    #[error("Unknown device class: '{device_class}'")]
+    UnknownDeviceClass { device_class: String },
+
+    #[error("Invalid topology: {description}")]
+    InvalidTopology { description: String },
+
+    #[error("Overrides are only supported for keyboard devices")]
+    OverridesNotSupported {},
+}
+
+/// A file in the `devices.d` directory, may hold multiple device descriptions
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeviceDescriptionFile {
+    #[serde(default)]
+    pub devices: Vec<DeviceDescription>,
+}
+
+/// Declarative description of a device, that is driven by one of the
+/// existing protocol families (device drivers)
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceDescription {
+    pub make: String,
+    pub name: String,
+
+    pub usb_vid: u16,
+    pub usb_pid: u16,
+
+    /// One of "keyboard", "mouse" or "misc"
+    pub device_class: String,
+
+    /// The name of the protocol family, e.g. "roccat-vulcan-1xx"
+    pub protocol: String,
+
+    pub maturity_level: MaturityLevel,
+
+    // optional overrides, currently only supported for keyboards
+    pub led_count: Option<usize>,
+    pub num_keys: Option<usize>,
+    pub rows_topology: Option<Vec<Vec<u8>>>,
+    pub cols_topology: Option<Vec<Vec<u8>>>,
+    pub support_script: Option<String>,
+}
+
+impl DeviceDescription {
+    pub fn has_overrides(&self) -> bool {
+        self.led_count.is_some()
+            || self.num_keys.is_some()
+            || self.rows_topology.is_some()
+            || self.cols_topology.is_some()
+            || self.support_script.is_some()
+    }
+
+    /// Validate the description and build a driver that binds the described device
+    pub fn to_driver(&self) -> Result<Box<dyn DriverMetadata + Sync + Send + 'static>> {
+        // driver metadata is expected to live for the lifetime of the process
+        let make: &'static str = Box::leak(self.make.clone().into_boxed_str());
+        let name: &'static str = Box::leak(self.name.clone().into_boxed_str());
+
+        match self.device_class.as_str() {
+            "keyboard" => {
+                let protocol_bind_fn = keyboard_protocol(&self.protocol).ok_or_else(|| {
+                    DeviceDescriptionError::UnknownProtocol {
+                        device_class: self.device_class.clone(),
+                        protocol: self.protocol.clone(),
+                    }
+                })?;
+
+                let bind_fn: &'static KeyboardBindFn = if self.has_overrides() {
+                    let overrides = Overrides::from_description(self)?;
+
+                    Box::leak(Box::new(
+                        move |hidapi: &HidApi, usb_vid: u16, usb_pid: u16, serial: &str| {
+                            let device = protocol_bind_fn(hidapi, usb_vid, usb_pid, serial)?;
+
+                            DescribedKeyboard::wrap(device, overrides.clone())
+                        },
+                    ))
+                } else {
+                    protocol_bind_fn
+                };
+
+                Ok(KeyboardDriver::register(
+                    make,
+                    name,
+                    self.usb_vid,
+                    self.usb_pid,
+                    bind_fn,
+                    self.maturity_level,
+                ))
+            }
+
+            "mouse" => {
+                if self.has_overrides() {
+                    return Err(DeviceDescriptionError::OverridesNotSupported {}.into());
+                }
+
+                let bind_fn = mouse_protocol(&self.protocol).ok_or_else(|| {
+                    DeviceDescriptionError::UnknownProtocol {
+                        device_class: self.device_class.clone(),
+                        protocol: self.protocol.clone(),
+                    }
+                })?;
+
+                Ok(MouseDriver::register(
+                    make,
+                    name,
+                    self.usb_vid,
+                    self.usb_pid,
+                    bind_fn,
+                    self.maturity_level,
+                ))
+            }
+
+            "misc" => {
+                if self.has_overrides() {
+                    return Err(DeviceDescriptionError::OverridesNotSupported {}.into());
+                }
+
+                let bind_fn = misc_protocol(&self.protocol).ok_or_else(|| {
+                    DeviceDescriptionError::UnknownProtocol {
+                        device_class: self.device_class.clone(),
+                        protocol: self.protocol.clone(),
+                    }
+                })?;
+
+                Ok(MiscDriver::register(
+                    make,
+                    name,
+                    self.usb_vid,
+                    self.usb_pid,
+                    bind_fn,
+                    self.maturity_level,
+                ))
+            }
+
+            _ => Err(DeviceDescriptionError::UnknownDeviceClass {
+                device_class: self.device_class.clone(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Returns the bind function of a keyboard protocol family
+fn keyboard_protocol(protocol: &str) -> Option<&'static KeyboardBindFn> {
+    let bind_fn: &'static KeyboardBindFn = match protocol {
+        "roccat-vulcan-1xx" => &roccat_vulcan_1xx::bind_hiddev,
+        "roccat-vulcan-pro" => &roccat_vulcan_pro::bind_hiddev,
+        "roccat-vulcan-tkl" => &roccat_vulcan_tkl::bind_hiddev,
+        "roccat-vulcan-pro-tkl" => &roccat_vulcan_pro_tkl::bind_hiddev,
+        "roccat-magma" => &roccat_magma::bind_hiddev,
+        "corsair-strafe" => &corsair_strafe::bind_hiddev,
+        "generic-keyboard" => &generic_keyboard::bind_hiddev,
+
+        _ => return None,
+    };
+
+    Some(bind_fn)
+}
+
+/// Returns the bind function of a mouse protocol family
+fn mouse_protocol(protocol: &str) -> Option<&'static MouseBindFn> {
+    let bind_fn: &'static MouseBindFn = match protocol {
+        "roccat-kone-aimo" => &roccat_kone_aimo::bind_hiddev,
+        "roccat-kone-aimo-remastered" => &roccat_kone_aimo_remastered::bind_hiddev,
+        "roccat-kone-xtd" => &roccat_kone_xtd::bind_hiddev,
+        "roccat-kone-pure-ultra" => &roccat_kone_pure_ultra::bind_hiddev,
+        "roccat-burst-pro" => &roccat_burst_pro::bind_hiddev,
+        "roccat-kone-xp" => &roccat_kone_xp::bind_hiddev,
+        "roccat-kone-pro" => &roccat_kone_pro::bind_hiddev,
+        "roccat-kone-pro-air" => &roccat_kone_pro_air::bind_hiddev,
+        "roccat-kain-100" => &roccat_kain_100::bind_hiddev,
+        "roccat-kain-2xx" => &roccat_kain_2xx::bind_hiddev,
+        "roccat-kova-aimo" => &roccat_kova_aimo::bind_hiddev,
+        "roccat-kova-2016" => &roccat_kova_2016::bind_hiddev,
+        "roccat-nyth" => &roccat_nyth::bind_hiddev,
+        "generic-mouse" => &generic_mouse::bind_hiddev,
+
+        _ => return None,
+    };
+
+    Some(bind_fn)
+}
+
+/// Returns the bind function of a misc device protocol family
+fn misc_protocol(protocol: &str) -> Option<&'static MiscBindFn> {
+    let bind_fn: &'static MiscBindFn = match protocol {
+        "roccat-elo-71-air" => &roccat_elo_71_air::bind_hiddev,
+        "roccat-aimo-pad" => &roccat_aimo_pad::bind_hiddev,
+        "generic-lamp-array" => &generic_lamp_array::bind_hiddev,
+
+        _ => return None,
+    };
+
+    Some(bind_fn)
+}
+
+/// Parse a single device description file
+pub fn parse_device_description_file(path: &Path) -> Result<DeviceDescriptionFile> {
+    let data = fs::read_to_string(path)?;
+    let result = toml::from_str::<DeviceDescriptionFile>(&data)?;
+
+    Ok(result)
+}
+
+/// Load all device descriptions from the `.toml` files in `dirs`,
+/// and return the resulting drivers. Invalid descriptions will be skipped
+pub fn load_device_descriptions(
+    dirs: &[PathBuf],
+) -> Vec<Box<dyn DriverMetadata + Sync + Send + 'static>> {
+    let mut result = Vec::new();
+
+    for dir in dirs {
+        let mut files = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().unwrap_or_default() == "toml")
+                .collect::<Vec<PathBuf>>(),
+
+            Err(e) => {
+                debug!(
+                    "Could not read device descriptions from {}: {}",
+                    dir.display(),
+                    e
+                );
+
+                continue;
+            }
+        };
+
+        // process files in a well defined order
+        files.sort();
+
+        for file in files {
+            match parse_device_description_file(&file) {
+                Ok(descriptions) => {
+                    for description in descriptions.devices {
+                        match description.to_driver() {
+                            Ok(driver) => {
+                                info!(
+                                    "Loaded device description: {} {} (0x{:x}:0x{:x}) using protocol '{}'",
+                                    description.make,
+                                    description.name,
+                                    description.usb_vid,
+                                    description.usb_pid,
+                                    description.protocol
+                                );
+
+                                result.push(driver);
+                            }
+
+                            Err(e) => error!(
+                                "Invalid device description '{} {}' in {}: {}",
+                                description.make,
+                                description.name,
+                                file.display(),
+                                e
+                            ),
+                        }
+                    }
+                }
+
+                Err(e) => error!(
+                    "Could not parse device description file {}: {}",
+                    file.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    result
+}
+
+/// Keyboard specific overrides of a device description
+#[derive(Debug, Clone)]
+pub struct Overrides {
+    pub led_count: Option<usize>,
+    pub num_keys: Option<usize>,
+    pub rows_topology: Option<&'static [&'static [u8]]>,
+    pub cols_topology: Option<&'static [&'static [u8]]>,
+    pub support_script: Option<String>,
+}
+
+impl Overrides {
+    pub fn from_description(description: &DeviceDescription) -> Result<Self> {
+        fn leak_topology(topology: &[Vec<u8>]) -> &'static [&'static [u8]] {
+            let rows = topology
+                .iter()
+                .map(|v| &*Box::leak(v.clone().into_boxed_slice()))
+                .collect::<Vec<&'static [u8]>>();
+
+            Box::leak(rows.into_boxed_slice())
+        }
+
+        if let Some(num_keys) = description.num_keys {
+            let max_index = description
+                .rows_topology
+                .iter()
+                .chain(description.cols_topology.iter())
+                .flatten()
+                .flatten()
+                .filter(|i| **i != 0xff)
+                .max();
+
+            if let Some(max_index) = max_index {
+                if *max_index as usize >= num_keys {
+                    return Err(DeviceDescriptionError::InvalidTopology {
+                        description: format!(
+                            "Key index {} exceeds the number of keys ({})",
+                            max_index, num_keys
+                        ),
+                    }
+                    .into());
+                }
+            }
+        }
+
+        Ok(Self {
+            led_count: description.led_count,
+            num_keys: description.num_keys,
+            rows_topology: description.rows_topology.as_deref().map(leak_topology),
+            cols_topology: description.cols_topology.as_deref().map(leak_topology),
+            support_script: description.support_script.clone(),
+        })
+    }
+}
+
+/// A keyboard that is driven by an existing protocol family, with
+/// parts of its metadata overridden by a device description
+pub struct DescribedKeyboard {
+    inner: Box<dyn KeyboardDeviceTrait + Sync + Send>,
+    overrides: Overrides,
+}
+
+impl DescribedKeyboard {
+    /// Wrap a freshly bound keyboard device
+    pub fn wrap(device: KeyboardDevice, overrides: Overrides) -> Result<KeyboardDevice> {
+        let inner = Arc::try_unwrap(device)
+            .map_err(|_e| HwDeviceError::DeviceNotBound {})?
+            .into_inner();
+
+        Ok(Arc::new(RwLock::new(Box::new(Self { inner, overrides }))))
+    }
+}
+
+impl DeviceInfoTrait for DescribedKeyboard {
+    fn get_device_capabilities(&self) -> DeviceCapabilities {
+        self.inner.get_device_capabilities()
+    }
+
+    fn get_device_info(&self) -> Result<super::DeviceInfo> {
+        self.inner.get_device_info()
+    }
+
+    fn get_firmware_revision(&self) -> String {
+        self.inner.get_firmware_revision()
+    }
+}
+
+impl DeviceTrait for DescribedKeyboard {
+    fn get_usb_path(&self) -> String {
+        self.inner.get_usb_path()
+    }
+
+    fn get_usb_vid(&self) -> u16 {
+        self.inner.get_usb_vid()
+    }
+
+    fn get_usb_pid(&self) -> u16 {
+        self.inner.get_usb_pid()
+    }
+
+    fn get_serial(&self) -> Option<&str> {
+        self.inner.get_serial()
+    }
+
+    fn get_support_script_file(&self) -> String {
+        match self.overrides.support_script {
+            Some(ref support_script) => support_script.clone(),
+            None => self.inner.get_support_script_file(),
+        }
+    }
+
+    fn open(&mut self, api: &hidapi::HidApi) -> Result<()> {
+        self.inner.open(api)
+    }
+
+    fn close_all(&mut self) -> Result<()> {
+        self.inner.close_all()
+    }
+
+    fn send_init_sequence(&mut self) -> Result<()> {
+        self.inner.send_init_sequence()
+    }
+
+    fn is_initialized(&self) -> Result<bool> {
+        self.inner.is_initialized()
+    }
+
+    fn has_failed(&self) -> Result<bool> {
+        self.inner.has_failed()
+    }
+
+    fn write_data_raw(&self, buf: &[u8]) -> Result<()> {
+        self.inner.write_data_raw(buf)
+    }
+
+    fn read_data_raw(&self, size: usize) -> Result<Vec<u8>> {
+        self.inner.read_data_raw(size)
+    }
+
+    fn device_status(&self) -> Result<DeviceStatus> {
+        self.inner.device_status()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self.inner.as_any_mut()
+    }
+
+    fn as_device(&self) -> &dyn DeviceTrait {
+        self
+    }
+
+    fn as_device_mut(&mut self) -> &mut dyn DeviceTrait {
+        self
+    }
+
+    fn as_mouse_device(&self) -> Option<&dyn MouseDeviceTrait> {
+        None
+    }
+
+    fn as_mouse_device_mut(&mut self) -> Option<&mut dyn MouseDeviceTrait> {
+        None
+    }
+}
+
+impl KeyboardDeviceTrait for DescribedKeyboard {
+    fn set_status_led(&self, led_kind: LedKind, on: bool) -> Result<()> {
+        self.inner.set_status_led(led_kind, on)
+    }
+
+    fn set_local_brightness(&mut self, brightness: i32) -> Result<()> {
+        self.inner.set_local_brightness(brightness)
+    }
+
+    fn get_local_brightness(&self) -> Result<i32> {
+        self.inner.get_local_brightness()
+    }
+
+    fn send_led_map(&mut self, led_map: &[RGBA]) -> Result<()> {
+        match self.overrides.led_count {
+            Some(led_count) if led_count < led_map.len() => {
+                // switch off all LEDs that are not present on the device
+                let mut led_map = led_map.to_vec();
+
+                for color in led_map.iter_mut().skip(led_count) {
+                    *color = RGBA {
+                        r: 0,
+                        g: 0,
+                        b: 0,
+                        a: 0,
+                    };
+                }
+
+                self.inner.send_led_map(&led_map)
+            }
+
+            _ => self.inner.send_led_map(led_map),
+        }
+    }
+
+    fn set_led_init_pattern(&mut self) -> Result<()> {
+        self.inner.set_led_init_pattern()
+    }
+
+    fn set_led_off_pattern(&mut self) -> Result<()> {
+        self.inner.set_led_off_pattern()
+    }
+
+    fn get_next_event(&self) -> Result<KeyboardHidEvent> {
+        self.inner.get_next_event()
+    }
+
+    fn get_next_event_timeout(&self, millis: i32) -> Result<KeyboardHidEvent> {
+        self.inner.get_next_event_timeout(millis)
+    }
+
+    fn ev_key_to_key_index(&self, key: EV_KEY) -> u8 {
+        self.inner.ev_key_to_key_index(key)
+    }
+
+    fn hid_event_code_to_key_index(&self, code: &KeyboardHidEventCode) -> u8 {
+        self.inner.hid_event_code_to_key_index(code)
+    }
+
+    fn hid_event_code_to_report(&self, code: &KeyboardHidEventCode) -> u8 {
+        self.inner.hid_event_code_to_report(code)
+    }
+
+    fn get_num_keys(&self) -> usize {
+        self.overrides
+            .num_keys
+            .unwrap_or_else(|| self.inner.get_num_keys())
+    }
+
+    fn get_num_rows(&self) -> usize {
+        match self.overrides.rows_topology {
+            Some(rows) => rows.len(),
+            None => self.inner.get_num_rows(),
+        }
+    }
+
+    fn get_num_cols(&self) -> usize {
+        match self.overrides.cols_topology {
+            Some(cols) => cols.len(),
+            None => self.inner.get_num_cols(),
+        }
+    }
+
+    fn get_row_topology(&self, row: usize) -> &'static [u8] {
+        match self.overrides.rows_topology {
+            Some(rows) => rows.get(row).copied().unwrap_or(&[]),
+            None => self.inner.get_row_topology(row),
+        }
+    }
+
+    fn get_col_topology(&self, col: usize) -> &'static [u8] {
+        match self.overrides.cols_topology {
+            Some(cols) => cols.get(col).copied().unwrap_or(&[]),
+            None => self.inner.get_col_topology(col),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{load_device_descriptions, parse_device_description_file};
+    use crate::hwdevices::MaturityLevel;
+
+    #[test]
+    fn parse_device_descriptions() -> super::Result<()> {
+        let path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let file = parse_device_description_file(
+            &path.join("../support/tests/assets/devices.d/vulcan-clone.toml"),
+        )?;
+
+        assert_eq!(file.devices.len(), 2);
+
+        let keyboard = &file.devices[0];
+        assert_eq!(keyboard.usb_vid, 0x1e7d);
+        assert_eq!(keyboard.protocol, "roccat-vulcan-1xx");
+        assert_eq!(keyboard.maturity_level, MaturityLevel::Testing);
+        assert_eq!(keyboard.rows_topology.as_ref().unwrap().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_valid_descriptions_only() {
+        let path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let drivers = load_device_descriptions(&[path.join("../support/tests/assets/devices.d/")]);
+
+        // the description using an unknown protocol family will be skipped
+        assert_eq!(drivers.len(), 1);
+        assert_eq!(drivers[0].get_usb_pid(), 0x30ff);
+    }
+}
@@ -0,0 +1,1071 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use hidapi::HidApi;
+use log::*;
+use parking_lot::{Mutex, RwLock};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::constants;
+
+use super::hid_descriptor::{ReportDescriptor, ReportKind};
+use super::{
+    Capability, DeviceCapabilities, DeviceInfoTrait, DeviceStatus, DeviceTrait, HwDeviceError,
+    MiscDevice, MiscDeviceTrait, MouseDeviceTrait, RGBA,
+};
+
+pub type Result<T> = super::Result<T>;
+
+/// The HID "Lighting And Illumination" usage page
+pub const LAMP_ARRAY_USAGE_PAGE: u16 = 0x59;
+
+/// Usage of the LampArray application collection
+pub const LAMP_ARRAY_USAGE: u16 = 0x01;
+
+// usages of the reports and fields of the LampArray usage page
+const LAMP_ARRAY_ATTRIBUTES_REPORT: u16 = 0x02;
+const LAMP_COUNT: u16 = 0x03;
+const BOUNDING_BOX_WIDTH: u16 = 0x04;
+const BOUNDING_BOX_HEIGHT: u16 = 0x05;
+const BOUNDING_BOX_DEPTH: u16 = 0x06;
+const LAMP_ARRAY_KIND: u16 = 0x07;
+const MIN_UPDATE_INTERVAL: u16 = 0x08;
+const LAMP_ATTRIBUTES_REQUEST_REPORT: u16 = 0x20;
+const LAMP_ID: u16 = 0x21;
+const LAMP_ATTRIBUTES_RESPONSE_REPORT: u16 = 0x22;
+const POSITION_X: u16 = 0x23;
+const POSITION_Y: u16 = 0x24;
+const POSITION_Z: u16 = 0x25;
+const LAMP_PURPOSES: u16 = 0x26;
+const UPDATE_LATENCY: u16 = 0x27;
+const RED_LEVEL_COUNT: u16 = 0x28;
+const GREEN_LEVEL_COUNT: u16 = 0x29;
+const BLUE_LEVEL_COUNT: u16 = 0x2a;
+const INTENSITY_LEVEL_COUNT: u16 = 0x2b;
+const IS_PROGRAMMABLE: u16 = 0x2c;
+const LAMP_MULTI_UPDATE_REPORT: u16 = 0x50;
+const RED_UPDATE_CHANNEL: u16 = 0x51;
+const GREEN_UPDATE_CHANNEL: u16 = 0x52;
+const BLUE_UPDATE_CHANNEL: u16 = 0x53;
+const INTENSITY_UPDATE_CHANNEL: u16 = 0x54;
+const LAMP_UPDATE_FLAGS: u16 = 0x55;
+const LAMP_ARRAY_CONTROL_REPORT: u16 = 0x70;
+const AUTONOMOUS_MODE: u16 = 0x71;
+
+/// Set in the last LampMultiUpdateReport of a frame
+const LAMP_UPDATE_COMPLETE: u32 = 0x01;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LampArrayError {
+    #[error("The device does not declare a LampArray report with usage 0x{usage:02x}")]
+    MissingReport { usage: u16 },
+
+    #[error("The LampArray report 0x{report:02x} lacks a field with usage 0x{usage:02x}")]
+    MissingField { report: u16, usage: u16 },
+
+    #[error("Could not find the report descriptor of the LampArray interface")]
+    NoReportDescriptor {},
+}
+
+/// Binds the driver to a device
+pub fn bind_hiddev(
+    hidapi: &HidApi,
+    usb_vid: u16,
+    usb_pid: u16,
+    serial: &str,
+) -> super::Result<MiscDevice> {
+    let lamp_array_dev = hidapi.device_list().find(|&device| {
+        device.vendor_id() == usb_vid
+            && device.product_id() == usb_pid
+            && device.serial_number().unwrap_or("") == serial
+            && device.usage_page() == LAMP_ARRAY_USAGE_PAGE
+            && device.usage() == LAMP_ARRAY_USAGE
+    });
+
+    match lamp_array_dev {
+        Some(lamp_array_dev) => Ok(Arc::new(RwLock::new(Box::new(GenericLampArray::bind(
+            lamp_array_dev,
+        ))))),
+
+        None => Err(HwDeviceError::EnumerationError {}.into()),
+    }
+}
+
+/// Access to the feature reports of a LampArray interface. Abstracted, so that
+/// recorded devices may be replayed in place of the real hardware
+pub trait LampArrayTransport: Send {
+    /// Fills `buf` with the feature report whose ID is stored in `buf[0]`
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize>;
+
+    fn send_feature_report(&self, buf: &[u8]) -> Result<()>;
+}
+
+impl LampArrayTransport for hidapi::HidDevice {
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        match hidapi::HidDevice::get_feature_report(self, buf) {
+            Ok(result) => {
+                hexdump::hexdump_iter(buf).for_each(|s| trace!("  {}", s));
+
+                Ok(result)
+            }
+
+            Err(_) => Err(HwDeviceError::InvalidResult {}.into()),
+        }
+    }
+
+    fn send_feature_report(&self, buf: &[u8]) -> Result<()> {
+        match hidapi::HidDevice::send_feature_report(self, buf) {
+            Ok(()) => {
+                hexdump::hexdump_iter(buf).for_each(|s| trace!("  {}", s));
+
+                Ok(())
+            }
+
+            Err(_) => Err(HwDeviceError::WriteError {}.into()),
+        }
+    }
+}
+
+/// Attributes of the LampArray as a whole, sizes are given in micrometers
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LampArrayAttributes {
+    pub lamp_count: u16,
+
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+
+    pub kind: u32,
+    pub min_update_interval_micros: u32,
+}
+
+/// Attributes of a single lamp, positions are given in micrometers
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LampAttributes {
+    pub lamp_id: u16,
+
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+
+    pub update_latency_micros: u32,
+    pub purposes: u32,
+
+    pub red_level_count: u8,
+    pub green_level_count: u8,
+    pub blue_level_count: u8,
+    pub intensity_level_count: u8,
+
+    pub is_programmable: bool,
+}
+
+/// Implements the LampArray protocol on top of a transport
+#[derive(Debug, Clone)]
+pub struct LampArray {
+    pub descriptor: ReportDescriptor,
+    pub attributes: LampArrayAttributes,
+    pub lamps: Vec<LampAttributes>,
+
+    /// Native LED index of each lamp, derived from the lamp's position
+    pub led_indices: Vec<usize>,
+}
+
+impl LampArray {
+    /// Queries the attributes of the LampArray and of all of its lamps
+    pub fn query(transport: &dyn LampArrayTransport, descriptor: ReportDescriptor) -> Result<Self> {
+        let report = find_report(&descriptor, LAMP_ARRAY_ATTRIBUTES_REPORT)?;
+
+        let mut buf = report.new_buffer();
+        transport.get_feature_report(&mut buf)?;
+
+        let get = |usage| -> Result<u32> {
+            Ok(report.get(
+                &buf,
+                find_field(report, LAMP_ARRAY_ATTRIBUTES_REPORT, usage)?,
+            ))
+        };
+
+        let attributes = LampArrayAttributes {
+            lamp_count: get(LAMP_COUNT)? as u16,
+            width: get(BOUNDING_BOX_WIDTH)?,
+            height: get(BOUNDING_BOX_HEIGHT)?,
+            depth: get(BOUNDING_BOX_DEPTH).unwrap_or(0),
+            kind: get(LAMP_ARRAY_KIND).unwrap_or(0),
+            min_update_interval_micros: get(MIN_UPDATE_INTERVAL).unwrap_or(0),
+        };
+
+        debug!("LampArray attributes: {:?}", attributes);
+
+        let request = find_report(&descriptor, LAMP_ATTRIBUTES_REQUEST_REPORT)?;
+        let response = find_report(&descriptor, LAMP_ATTRIBUTES_RESPONSE_REPORT)?;
+
+        let mut lamps = Vec::with_capacity(attributes.lamp_count as usize);
+
+        for lamp_id in 0..attributes.lamp_count {
+            let mut buf = request.new_buffer();
+            request.set(
+                &mut buf,
+                find_field(request, LAMP_ATTRIBUTES_REQUEST_REPORT, LAMP_ID)?,
+                lamp_id as u32,
+            );
+            transport.send_feature_report(&buf)?;
+
+            let mut buf = response.new_buffer();
+            transport.get_feature_report(&mut buf)?;
+
+            let get = |usage| -> Result<u32> {
+                Ok(response.get(
+                    &buf,
+                    find_field(response, LAMP_ATTRIBUTES_RESPONSE_REPORT, usage)?,
+                ))
+            };
+
+            let lamp = LampAttributes {
+                lamp_id: get(LAMP_ID)? as u16,
+                x: get(POSITION_X)?,
+                y: get(POSITION_Y)?,
+                z: get(POSITION_Z).unwrap_or(0),
+                update_latency_micros: get(UPDATE_LATENCY).unwrap_or(0),
+                purposes: get(LAMP_PURPOSES).unwrap_or(0),
+                red_level_count: get(RED_LEVEL_COUNT)? as u8,
+                green_level_count: get(GREEN_LEVEL_COUNT)? as u8,
+                blue_level_count: get(BLUE_LEVEL_COUNT)? as u8,
+                intensity_level_count: get(INTENSITY_LEVEL_COUNT).unwrap_or(0) as u8,
+                is_programmable: get(IS_PROGRAMMABLE).unwrap_or(1) != 0,
+            };
+
+            trace!("Lamp {}: {:?}", lamp_id, lamp);
+
+            lamps.push(lamp);
+        }
+
+        let led_indices = lamps
+            .iter()
+            .map(|lamp| lamp_to_led_index(&attributes, lamp))
+            .collect();
+
+        Ok(Self {
+            descriptor,
+            attributes,
+            lamps,
+            led_indices,
+        })
+    }
+
+    /// In autonomous mode the device renders its own effects and ignores any updates
+    pub fn set_autonomous_mode(
+        &self,
+        transport: &dyn LampArrayTransport,
+        enabled: bool,
+    ) -> Result<()> {
+        let report = find_report(&self.descriptor, LAMP_ARRAY_CONTROL_REPORT)?;
+
+        let mut buf = report.new_buffer();
+        report.set(
+            &mut buf,
+            find_field(report, LAMP_ARRAY_CONTROL_REPORT, AUTONOMOUS_MODE)?,
+            enabled as u32,
+        );
+
+        transport.send_feature_report(&buf)
+    }
+
+    /// Sends the colors of all programmable lamps, using as few LampMultiUpdateReports as possible
+    pub fn send_led_map(
+        &self,
+        transport: &dyn LampArrayTransport,
+        led_map: &[RGBA],
+        brightness: i32,
+    ) -> Result<()> {
+        let report = find_report(&self.descriptor, LAMP_MULTI_UPDATE_REPORT)?;
+
+        let count_field = find_field(report, LAMP_MULTI_UPDATE_REPORT, LAMP_COUNT)?;
+        let flags_field = find_field(report, LAMP_MULTI_UPDATE_REPORT, LAMP_UPDATE_FLAGS)?;
+
+        let id_fields = report
+            .fields(LAMP_ARRAY_USAGE_PAGE, LAMP_ID)
+            .collect::<Vec<_>>();
+        let channels = [
+            RED_UPDATE_CHANNEL,
+            GREEN_UPDATE_CHANNEL,
+            BLUE_UPDATE_CHANNEL,
+            INTENSITY_UPDATE_CHANNEL,
+        ]
+        .map(|usage| {
+            report
+                .fields(LAMP_ARRAY_USAGE_PAGE, usage)
+                .collect::<Vec<_>>()
+        });
+
+        let slots = channels
+            .iter()
+            .map(|c| c.len())
+            .fold(id_fields.len(), usize::min);
+
+        if slots == 0 {
+            return Err(LampArrayError::MissingField {
+                report: LAMP_MULTI_UPDATE_REPORT,
+                usage: LAMP_ID,
+            }
+            .into());
+        }
+
+        let lamps = self
+            .lamps
+            .iter()
+            .zip(self.led_indices.iter())
+            .filter(|(lamp, _)| lamp.is_programmable)
+            .collect::<Vec<_>>();
+
+        let chunks = lamps.chunks(slots).count();
+
+        for (index, chunk) in lamps.chunks(slots).enumerate() {
+            let mut buf = report.new_buffer();
+
+            report.set(&mut buf, count_field, chunk.len() as u32);
+
+            if index + 1 == chunks {
+                report.set(&mut buf, flags_field, LAMP_UPDATE_COMPLETE);
+            }
+
+            for (slot, (lamp, led_index)) in chunk.iter().enumerate() {
+                let color = led_map.get(**led_index).copied().unwrap_or(RGBA {
+                    r: 0,
+                    g: 0,
+                    b: 0,
+                    a: 0,
+                });
+
+                let dim = |value: u8| (value as f32 * (brightness as f32 / 100.0)).floor();
+
+                // brightness is only applied to the color channels, lamps with few intensity
+                // levels would otherwise go dark long before the colors do
+                let levels = [
+                    (dim(color.r), lamp.red_level_count),
+                    (dim(color.g), lamp.green_level_count),
+                    (dim(color.b), lamp.blue_level_count),
+                    (255.0, lamp.intensity_level_count),
+                ];
+
+                report.set(&mut buf, id_fields[slot], lamp.lamp_id as u32);
+
+                for (channel, (value, level_count)) in channels.iter().zip(levels) {
+                    let value = (value * level_count as f32 / 255.0).round() as u32;
+
+                    report.set(&mut buf, channel[slot], value);
+                }
+            }
+
+            transport.send_feature_report(&buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn find_report(
+    descriptor: &ReportDescriptor,
+    usage: u16,
+) -> Result<&super::hid_descriptor::Report> {
+    Ok(descriptor
+        .find_report(ReportKind::Feature, LAMP_ARRAY_USAGE_PAGE, usage)
+        .ok_or(LampArrayError::MissingReport { usage })?)
+}
+
+fn find_field(
+    report: &super::hid_descriptor::Report,
+    report_usage: u16,
+    usage: u16,
+) -> Result<&super::hid_descriptor::Field> {
+    Ok(report
+        .field(LAMP_ARRAY_USAGE_PAGE, usage)
+        .ok_or(LampArrayError::MissingField {
+            report: report_usage,
+            usage,
+        })?)
+}
+
+/// Maps the position of a lamp within the bounding box of the LampArray onto the
+/// native LED layout, which spans CANVAS_WIDTH x CANVAS_HEIGHT in column-major order
+pub fn lamp_to_led_index(attributes: &LampArrayAttributes, lamp: &LampAttributes) -> usize {
+    let scale = |pos: u32, size: u32, cells: usize| {
+        if size == 0 {
+            0
+        } else {
+            ((pos as u64 * cells as u64 / size as u64) as usize).min(cells - 1)
+        }
+    };
+
+    let x = scale(lamp.x, attributes.width, constants::CANVAS_WIDTH);
+    let y = scale(lamp.y, attributes.height, constants::CANVAS_HEIGHT);
+
+    x * constants::CANVAS_HEIGHT + y
+}
+
+/// Reads the report descriptor of the LampArray interface of a device from sysfs
+pub fn read_report_descriptor(usb_vid: u16, usb_pid: u16) -> Result<ReportDescriptor> {
+    let hid_id = format!("HID_ID=0003:{:08X}:{:08X}", usb_vid, usb_pid);
+
+    for entry in fs::read_dir(Path::new("/sys/class/hidraw"))?.flatten() {
+        let device = entry.path().join("device");
+
+        let uevent = fs::read_to_string(device.join("uevent")).unwrap_or_default();
+
+        if !uevent.lines().any(|l| l.eq_ignore_ascii_case(&hid_id)) {
+            continue;
+        }
+
+        if let Ok(data) = fs::read(device.join("report_descriptor")) {
+            match ReportDescriptor::parse(&data) {
+                Ok(descriptor) => {
+                    if descriptor.has_application(LAMP_ARRAY_USAGE_PAGE, LAMP_ARRAY_USAGE) {
+                        return Ok(descriptor);
+                    }
+                }
+
+                Err(e) => warn!("Could not parse a HID report descriptor: {}", e),
+            }
+        }
+    }
+
+    Err(LampArrayError::NoReportDescriptor {}.into())
+}
+
+#[derive(Clone)]
+/// Device driver for devices that implement the HID LampArray standard
+pub struct GenericLampArray {
+    pub is_initialized: bool,
+
+    pub is_bound: bool,
+    pub hiddev_info: Option<hidapi::DeviceInfo>,
+
+    pub is_opened: bool,
+    pub hiddev: Arc<Mutex<Option<Box<dyn LampArrayTransport>>>>,
+    pub descriptor: Option<ReportDescriptor>,
+    pub lamp_array: Option<LampArray>,
+    pub last_update: Option<Instant>,
+    pub has_failed: bool,
+
+    // device specific configuration options
+    pub brightness: i32,
+
+    // device status
+    pub device_status: DeviceStatus,
+}
+
+impl GenericLampArray {
+    /// Binds the driver to the supplied HID device
+    pub fn bind(hiddev: &hidapi::DeviceInfo) -> Self {
+        info!("Bound driver: Generic LampArray device");
+
+        Self {
+            is_initialized: false,
+
+            is_bound: true,
+            hiddev_info: Some(hiddev.clone()),
+
+            is_opened: false,
+            hiddev: Arc::new(Mutex::new(None)),
+            descriptor: None,
+            lamp_array: None,
+            last_update: None,
+            has_failed: false,
+
+            brightness: 100,
+
+            device_status: DeviceStatus(HashMap::new()),
+        }
+    }
+}
+
+impl DeviceInfoTrait for GenericLampArray {
+    fn get_device_capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities::from([Capability::Misc, Capability::RgbLighting])
+    }
+
+    fn get_device_info(&self) -> Result<super::DeviceInfo> {
+        trace!("Querying the device for information...");
+
+        let result = super::DeviceInfo::new(0);
+        Ok(result)
+    }
+
+    fn get_firmware_revision(&self) -> String {
+        "<not supported>".to_string()
+    }
+}
+
+impl DeviceTrait for GenericLampArray {
+    fn get_usb_path(&self) -> String {
+        self.hiddev_info
+            .clone()
+            .unwrap()
+            .path()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn get_usb_vid(&self) -> u16 {
+        self.hiddev_info.as_ref().unwrap().vendor_id()
+    }
+
+    fn get_usb_pid(&self) -> u16 {
+        self.hiddev_info.as_ref().unwrap().product_id()
+    }
+
+    fn get_serial(&self) -> Option<&str> {
+        self.hiddev_info.as_ref().unwrap().serial_number()
+    }
+
+    fn get_support_script_file(&self) -> String {
+        "misc/generic_lamp_array".to_string()
+    }
+
+    fn open(&mut self, api: &hidapi::HidApi) -> Result<()> {
+        trace!("Opening HID devices now...");
+
+        if !self.is_bound {
+            Err(HwDeviceError::DeviceNotBound {}.into())
+        } else {
+            // the descriptor has to be read before the device gets opened
+            self.descriptor = Some(read_report_descriptor(
+                self.get_usb_vid(),
+                self.get_usb_pid(),
+            )?);
+
+            trace!("Opening LampArray device...");
+
+            match self.hiddev_info.as_ref().unwrap().open_device(api) {
+                Ok(dev) => *self.hiddev.lock() = Some(Box::new(dev)),
+                Err(_) => return Err(HwDeviceError::DeviceOpenError {}.into()),
+            };
+
+            self.is_opened = true;
+
+            Ok(())
+        }
+    }
+
+    fn close_all(&mut self) -> Result<()> {
+        trace!("Closing HID devices now...");
+
+        if !self.is_bound {
+            Err(HwDeviceError::DeviceNotBound {}.into())
+        } else if !self.is_opened {
+            Err(HwDeviceError::DeviceNotOpened {}.into())
+        } else {
+            // hand control back to the device's built-in effects
+            if let (Some(lamp_array), Some(hiddev)) =
+                (self.lamp_array.as_ref(), self.hiddev.lock().as_ref())
+            {
+                lamp_array
+                    .set_autonomous_mode(hiddev.as_ref(), true)
+                    .unwrap_or_else(|e| warn!("Could not enable autonomous mode: {}", e));
+            }
+
+            trace!("Closing LampArray device...");
+            *self.hiddev.lock() = None;
+
+            self.is_opened = false;
+
+            Ok(())
+        }
+    }
+
+    fn send_init_sequence(&mut self) -> Result<()> {
+        trace!("Sending device init sequence...");
+
+        if !self.is_bound {
+            Err(HwDeviceError::DeviceNotBound {}.into())
+        } else if !self.is_opened {
+            Err(HwDeviceError::DeviceNotOpened {}.into())
+        } else {
+            let hiddev = self.hiddev.lock();
+            let hiddev = hiddev.as_ref().unwrap();
+
+            let descriptor = self
+                .descriptor
+                .clone()
+                .ok_or(LampArrayError::NoReportDescriptor {})?;
+
+            let lamp_array = LampArray::query(hiddev.as_ref(), descriptor)?;
+
+            // take control over the lamps
+            lamp_array.set_autonomous_mode(hiddev.as_ref(), false)?;
+
+            info!(
+                "LampArray device with {} lamps initialized",
+                lamp_array.attributes.lamp_count
+            );
+
+            self.device_status.0.insert(
+                "lamp-count".to_string(),
+                format!("{}", lamp_array.attributes.lamp_count),
+            );
+
+            self.lamp_array = Some(lamp_array);
+            self.is_initialized = true;
+
+            Ok(())
+        }
+    }
+
+    fn is_initialized(&self) -> Result<bool> {
+        Ok(self.is_initialized)
+    }
+
+    fn has_failed(&self) -> Result<bool> {
+        Ok(self.has_failed)
+    }
+
+    fn write_data_raw(&self, _buf: &[u8]) -> Result<()> {
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn read_data_raw(&self, _size: usize) -> Result<Vec<u8>> {
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn device_status(&self) -> Result<DeviceStatus> {
+        Ok(self.device_status.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_device(&self) -> &dyn DeviceTrait {
+        self
+    }
+
+    fn as_device_mut(&mut self) -> &mut dyn DeviceTrait {
+        self
+    }
+
+    fn as_mouse_device(&self) -> Option<&dyn MouseDeviceTrait> {
+        None
+    }
+
+    fn as_mouse_device_mut(&mut self) -> Option<&mut dyn MouseDeviceTrait> {
+        None
+    }
+}
+
+impl MiscDeviceTrait for GenericLampArray {
+    fn has_input_device(&self) -> bool {
+        false
+    }
+
+    fn set_local_brightness(&mut self, brightness: i32) -> Result<()> {
+        trace!("Setting device specific brightness");
+
+        self.brightness = brightness;
+
+        Ok(())
+    }
+
+    fn get_local_brightness(&self) -> Result<i32> {
+        trace!("Querying device specific brightness");
+
+        Ok(self.brightness)
+    }
+
+    fn send_led_map(&mut self, led_map: &[RGBA]) -> Result<()> {
+        trace!("Setting LEDs from supplied map...");
+
+        if !self.is_bound {
+            Err(HwDeviceError::DeviceNotBound {}.into())
+        } else if !self.is_opened {
+            Err(HwDeviceError::DeviceNotOpened {}.into())
+        } else if !self.is_initialized {
+            Err(HwDeviceError::DeviceNotInitialized {}.into())
+        } else {
+            let lamp_array = self.lamp_array.as_ref().unwrap();
+
+            // honor the minimum update interval of the device
+            let min_interval =
+                Duration::from_micros(lamp_array.attributes.min_update_interval_micros as u64);
+
+            if self
+                .last_update
+                .map(|t| t.elapsed() < min_interval)
+                .unwrap_or(false)
+            {
+                return Ok(());
+            }
+
+            let result = {
+                let hiddev = self.hiddev.lock();
+                let hiddev = hiddev.as_ref().unwrap();
+
+                lamp_array.send_led_map(hiddev.as_ref(), led_map, self.brightness)
+            };
+
+            if let Err(e) = result {
+                // the device has failed or has been disconnected
+                self.is_initialized = false;
+                self.is_opened = false;
+                self.has_failed = true;
+
+                return Err(e);
+            }
+
+            self.last_update = Some(Instant::now());
+
+            Ok(())
+        }
+    }
+
+    fn set_led_init_pattern(&mut self) -> Result<()> {
+        trace!("Setting LED init pattern...");
+
+        if !self.is_bound {
+            Err(HwDeviceError::DeviceNotBound {}.into())
+        } else if !self.is_opened {
+            Err(HwDeviceError::DeviceNotOpened {}.into())
+        } else if !self.is_initialized {
+            Err(HwDeviceError::DeviceNotInitialized {}.into())
+        } else {
+            let led_map: [RGBA; constants::CANVAS_SIZE] = [RGBA {
+                r: 0x00,
+                g: 0x00,
+                b: 0x00,
+                a: 0x00,
+            }; constants::CANVAS_SIZE];
+
+            self.send_led_map(&led_map)?;
+
+            Ok(())
+        }
+    }
+
+    fn set_led_off_pattern(&mut self) -> Result<()> {
+        trace!("Setting LED off pattern...");
+
+        if !self.is_bound {
+            Err(HwDeviceError::DeviceNotBound {}.into())
+        } else if !self.is_opened {
+            Err(HwDeviceError::DeviceNotOpened {}.into())
+        } else if !self.is_initialized {
+            Err(HwDeviceError::DeviceNotInitialized {}.into())
+        } else {
+            let led_map: [RGBA; constants::CANVAS_SIZE] = [RGBA {
+                r: 0x00,
+                g: 0x00,
+                b: 0x00,
+                a: 0x00,
+            }; constants::CANVAS_SIZE];
+
+            // the minimum update interval must not swallow the final update
+            self.last_update = None;
+            self.send_led_map(&led_map)?;
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+    use serde::Deserialize;
+    use std::collections::VecDeque;
+    use std::path::PathBuf;
+
+    use super::{lamp_to_led_index, LampArray, LampArrayTransport, Result};
+    use crate::constants;
+    use crate::hwdevices::hid_descriptor::ReportDescriptor;
+    use crate::hwdevices::RGBA;
+
+    #[derive(Debug, Deserialize)]
+    struct Recording {
+        report_descriptor: String,
+        replies: Vec<Reply>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Reply {
+        data: String,
+    }
+
+    /// Replays the recorded feature reports of a device, and records all reports sent to it
+    struct MockTransport {
+        replies: Mutex<VecDeque<Vec<u8>>>,
+        sent: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl LampArrayTransport for MockTransport {
+        fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+            let mut replies = self.replies.lock();
+
+            let index = replies
+                .iter()
+                .position(|r| r[0] == buf[0])
+                .expect("No recorded reply for the requested report");
+            let reply = replies.remove(index).unwrap();
+
+            buf[..reply.len()].copy_from_slice(&reply);
+
+            Ok(reply.len())
+        }
+
+        fn send_feature_report(&self, buf: &[u8]) -> Result<()> {
+            self.sent.lock().push(buf.to_vec());
+
+            Ok(())
+        }
+    }
+
+    fn parse_hex(s: &str) -> Vec<u8> {
+        s.split_whitespace()
+            .map(|b| u8::from_str_radix(b, 16).unwrap())
+            .collect()
+    }
+
+    fn load_recording(name: &str) -> (ReportDescriptor, MockTransport) {
+        let path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let data =
+            std::fs::read_to_string(path.join("../support/tests/assets/lamparray/").join(name))
+                .unwrap();
+
+        let recording: Recording = toml::from_str(&data).unwrap();
+
+        let descriptor = ReportDescriptor::parse(&parse_hex(&recording.report_descriptor)).unwrap();
+        let transport = MockTransport {
+            replies: Mutex::new(
+                recording
+                    .replies
+                    .iter()
+                    .map(|r| parse_hex(&r.data))
+                    .collect(),
+            ),
+            sent: Mutex::new(vec![]),
+        };
+
+        (descriptor, transport)
+    }
+
+    #[test]
+    fn query_recorded_device() -> Result<()> {
+        let (descriptor, transport) = load_recording("keyboard.toml");
+
+        let lamp_array = LampArray::query(&transport, descriptor)?;
+
+        assert_eq!(lamp_array.attributes.lamp_count, 4);
+        assert_eq!(lamp_array.attributes.width, 100_000);
+        assert_eq!(lamp_array.attributes.height, 40_000);
+        assert_eq!(lamp_array.attributes.min_update_interval_micros, 33_333);
+
+        assert_eq!(lamp_array.lamps[1].x, 95_000);
+        assert_eq!(lamp_array.lamps[2].y, 35_000);
+        assert_eq!(lamp_array.lamps[3].red_level_count, 1);
+
+        // the four corners of the canvas
+        assert_eq!(lamp_array.led_indices, vec![6, 168, 11, 173]);
+
+        // one LampAttributesRequestReport per lamp
+        assert_eq!(
+            *transport.sent.lock(),
+            vec![
+                vec![0x02, 0x00, 0x00],
+                vec![0x02, 0x01, 0x00],
+                vec![0x02, 0x02, 0x00],
+                vec![0x02, 0x03, 0x00],
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn send_colors() -> Result<()> {
+        let (descriptor, transport) = load_recording("keyboard.toml");
+
+        let lamp_array = LampArray::query(&transport, descriptor)?;
+        transport.sent.lock().clear();
+
+        let mut led_map = [RGBA {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        }; constants::CANVAS_SIZE];
+
+        led_map[6] = RGBA {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        led_map[173] = RGBA {
+            r: 200,
+            g: 100,
+            b: 0,
+            a: 255,
+        };
+
+        lamp_array.send_led_map(&transport, &led_map, 100)?;
+
+        let sent = transport.sent.lock();
+        assert_eq!(sent.len(), 1);
+
+        let report = &sent[0];
+        assert_eq!(report.len(), 51);
+
+        // report ID, lamp count and the "update complete" flag
+        assert_eq!(report[..3], [0x04, 0x04, 0x01]);
+
+        // lamp IDs
+        assert_eq!(
+            report[3..11],
+            [0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00]
+        );
+
+        // RGBI of each lamp, lamp 3 only supports on/off
+        assert_eq!(report[19..23], [255, 0, 0, 1]);
+        assert_eq!(report[23..27], [0, 0, 0, 1]);
+        assert_eq!(report[31..35], [1, 0, 0, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn send_dimmed_colors() -> Result<()> {
+        let (descriptor, transport) = load_recording("keyboard.toml");
+
+        let lamp_array = LampArray::query(&transport, descriptor)?;
+        transport.sent.lock().clear();
+
+        let mut led_map = [RGBA {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        }; constants::CANVAS_SIZE];
+
+        led_map[6] = RGBA {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        led_map[173] = RGBA {
+            r: 255,
+            g: 100,
+            b: 0,
+            a: 255,
+        };
+
+        lamp_array.send_led_map(&transport, &led_map, 40)?;
+
+        let sent = transport.sent.lock();
+        assert_eq!(sent.len(), 1);
+
+        // only the colors are dimmed, the intensity stays at its maximum
+        let report = &sent[0];
+        assert_eq!(report[19..23], [102, 0, 0, 1]);
+        assert_eq!(report[23..27], [0, 0, 0, 1]);
+        assert_eq!(report[31..35], [0, 0, 0, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn send_colors_in_chunks() -> Result<()> {
+        let (descriptor, transport) = load_recording("keyboard.toml");
+
+        let mut lamp_array = LampArray::query(&transport, descriptor)?;
+        transport.sent.lock().clear();
+
+        // the recorded device fits 8 lamps into a single report
+        let lamps = lamp_array.lamps.clone();
+        lamp_array.lamps = lamps.iter().cycle().take(10).copied().collect();
+        lamp_array.led_indices = vec![0; 10];
+
+        let led_map = [RGBA {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        }; constants::CANVAS_SIZE];
+
+        lamp_array.send_led_map(&transport, &led_map, 100)?;
+
+        let sent = transport.sent.lock();
+        assert_eq!(sent.len(), 2);
+
+        // only the last report of a frame completes the update
+        assert_eq!(sent[0][..3], [0x04, 0x08, 0x00]);
+        assert_eq!(sent[1][..3], [0x04, 0x02, 0x01]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_autonomous_mode() -> Result<()> {
+        let (descriptor, transport) = load_recording("keyboard.toml");
+
+        let lamp_array = LampArray::query(&transport, descriptor)?;
+        transport.sent.lock().clear();
+
+        lamp_array.set_autonomous_mode(&transport, false)?;
+        lamp_array.set_autonomous_mode(&transport, true)?;
+
+        assert_eq!(
+            *transport.sent.lock(),
+            vec![vec![0x06, 0x00], vec![0x06, 0x01]]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn lamps_outside_of_the_bounding_box() {
+        let (descriptor, transport) = load_recording("keyboard.toml");
+
+        let lamp_array = LampArray::query(&transport, descriptor).unwrap();
+
+        let mut lamp = lamp_array.lamps[0];
+        lamp.x = 500_000;
+        lamp.y = 500_000;
+
+        assert_eq!(
+            lamp_to_led_index(&lamp_array.attributes, &lamp),
+            constants::CANVAS_SIZE - 1
+        );
+
+        let mut attributes = lamp_array.attributes;
+        attributes.width = 0;
+        attributes.height = 0;
+
+        assert_eq!(lamp_to_led_index(&attributes, &lamp), 0);
+    }
+}
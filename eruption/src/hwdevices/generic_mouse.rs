@@ -280,89 +280,11 @@ impl MouseDeviceTrait for GenericMouse {
     }
 
     fn ev_key_to_button_index(&self, code: EV_KEY) -> Result<u8> {
-        match code {
-            EV_KEY::KEY_RESERVED => Ok(0),
-
-            EV_KEY::BTN_LEFT => Ok(1),
-            EV_KEY::BTN_MIDDLE => Ok(2),
-            EV_KEY::BTN_RIGHT => Ok(3),
-
-            EV_KEY::BTN_0 => Ok(4),
-            EV_KEY::BTN_1 => Ok(5),
-            EV_KEY::BTN_2 => Ok(6),
-            EV_KEY::BTN_3 => Ok(7),
-            EV_KEY::BTN_4 => Ok(8),
-            EV_KEY::BTN_5 => Ok(9),
-            EV_KEY::BTN_6 => Ok(10),
-            EV_KEY::BTN_7 => Ok(11),
-            EV_KEY::BTN_8 => Ok(12),
-            EV_KEY::BTN_9 => Ok(13),
-
-            EV_KEY::BTN_EXTRA => Ok(14),
-            EV_KEY::BTN_SIDE => Ok(15),
-            EV_KEY::BTN_FORWARD => Ok(16),
-            EV_KEY::BTN_BACK => Ok(17),
-            EV_KEY::BTN_TASK => Ok(18),
-
-            EV_KEY::KEY_0 => Ok(19),
-            EV_KEY::KEY_1 => Ok(20),
-            EV_KEY::KEY_2 => Ok(21),
-            EV_KEY::KEY_3 => Ok(22),
-            EV_KEY::KEY_4 => Ok(23),
-            EV_KEY::KEY_5 => Ok(24),
-            EV_KEY::KEY_6 => Ok(25),
-            EV_KEY::KEY_7 => Ok(26),
-            EV_KEY::KEY_8 => Ok(27),
-            EV_KEY::KEY_9 => Ok(28),
-
-            EV_KEY::KEY_MINUS => Ok(29),
-            EV_KEY::KEY_EQUAL => Ok(30),
-
-            _ => Err(HwDeviceError::MappingError {}.into()),
-        }
+        ev_key_to_button_index(code)
     }
 
     fn button_index_to_ev_key(&self, index: u32) -> Result<EV_KEY> {
-        match index {
-            0 => Ok(EV_KEY::KEY_RESERVED),
-
-            1 => Ok(EV_KEY::BTN_LEFT),
-            2 => Ok(EV_KEY::BTN_MIDDLE),
-            3 => Ok(EV_KEY::BTN_RIGHT),
-
-            4 => Ok(EV_KEY::BTN_0),
-            5 => Ok(EV_KEY::BTN_1),
-            6 => Ok(EV_KEY::BTN_2),
-            7 => Ok(EV_KEY::BTN_3),
-            8 => Ok(EV_KEY::BTN_4),
-            9 => Ok(EV_KEY::BTN_5),
-            10 => Ok(EV_KEY::BTN_6),
-            11 => Ok(EV_KEY::BTN_7),
-            12 => Ok(EV_KEY::BTN_8),
-            13 => Ok(EV_KEY::BTN_9),
-
-            14 => Ok(EV_KEY::BTN_EXTRA),
-            15 => Ok(EV_KEY::BTN_SIDE),
-            16 => Ok(EV_KEY::BTN_FORWARD),
-            17 => Ok(EV_KEY::BTN_BACK),
-            18 => Ok(EV_KEY::BTN_TASK),
-
-            19 => Ok(EV_KEY::KEY_0),
-            20 => Ok(EV_KEY::KEY_1),
-            21 => Ok(EV_KEY::KEY_2),
-            22 => Ok(EV_KEY::KEY_3),
-            23 => Ok(EV_KEY::KEY_4),
-            24 => Ok(EV_KEY::KEY_5),
-            25 => Ok(EV_KEY::KEY_6),
-            26 => Ok(EV_KEY::KEY_7),
-            27 => Ok(EV_KEY::KEY_8),
-            28 => Ok(EV_KEY::KEY_9),
-
-            29 => Ok(EV_KEY::KEY_MINUS),
-            30 => Ok(EV_KEY::KEY_EQUAL),
-
-            _ => Err(HwDeviceError::MappingError {}.into()),
-        }
+        button_index_to_ev_key(index)
     }
 
     fn send_led_map(&mut self, _led_map: &[RGBA]) -> Result<()> {
@@ -387,3 +309,91 @@ impl MouseDeviceTrait for GenericMouse {
         false
     }
 }
+
+/// Maps an evdev key code to a generic button index
+pub fn ev_key_to_button_index(code: EV_KEY) -> Result<u8> {
+    match code {
+        EV_KEY::KEY_RESERVED => Ok(0),
+
+        EV_KEY::BTN_LEFT => Ok(1),
+        EV_KEY::BTN_MIDDLE => Ok(2),
+        EV_KEY::BTN_RIGHT => Ok(3),
+
+        EV_KEY::BTN_0 => Ok(4),
+        EV_KEY::BTN_1 => Ok(5),
+        EV_KEY::BTN_2 => Ok(6),
+        EV_KEY::BTN_3 => Ok(7),
+        EV_KEY::BTN_4 => Ok(8),
+        EV_KEY::BTN_5 => Ok(9),
+        EV_KEY::BTN_6 => Ok(10),
+        EV_KEY::BTN_7 => Ok(11),
+        EV_KEY::BTN_8 => Ok(12),
+        EV_KEY::BTN_9 => Ok(13),
+
+        EV_KEY::BTN_EXTRA => Ok(14),
+        EV_KEY::BTN_SIDE => Ok(15),
+        EV_KEY::BTN_FORWARD => Ok(16),
+        EV_KEY::BTN_BACK => Ok(17),
+        EV_KEY::BTN_TASK => Ok(18),
+
+        EV_KEY::KEY_0 => Ok(19),
+        EV_KEY::KEY_1 => Ok(20),
+        EV_KEY::KEY_2 => Ok(21),
+        EV_KEY::KEY_3 => Ok(22),
+        EV_KEY::KEY_4 => Ok(23),
+        EV_KEY::KEY_5 => Ok(24),
+        EV_KEY::KEY_6 => Ok(25),
+        EV_KEY::KEY_7 => Ok(26),
+        EV_KEY::KEY_8 => Ok(27),
+        EV_KEY::KEY_9 => Ok(28),
+
+        EV_KEY::KEY_MINUS => Ok(29),
+        EV_KEY::KEY_EQUAL => Ok(30),
+
+        _ => Err(HwDeviceError::MappingError {}.into()),
+    }
+}
+
+/// Maps a button index back to an evdev key code
+pub fn button_index_to_ev_key(index: u32) -> Result<EV_KEY> {
+    match index {
+        0 => Ok(EV_KEY::KEY_RESERVED),
+
+        1 => Ok(EV_KEY::BTN_LEFT),
+        2 => Ok(EV_KEY::BTN_MIDDLE),
+        3 => Ok(EV_KEY::BTN_RIGHT),
+
+        4 => Ok(EV_KEY::BTN_0),
+        5 => Ok(EV_KEY::BTN_1),
+        6 => Ok(EV_KEY::BTN_2),
+        7 => Ok(EV_KEY::BTN_3),
+        8 => Ok(EV_KEY::BTN_4),
+        9 => Ok(EV_KEY::BTN_5),
+        10 => Ok(EV_KEY::BTN_6),
+        11 => Ok(EV_KEY::BTN_7),
+        12 => Ok(EV_KEY::BTN_8),
+        13 => Ok(EV_KEY::BTN_9),
+
+        14 => Ok(EV_KEY::BTN_EXTRA),
+        15 => Ok(EV_KEY::BTN_SIDE),
+        16 => Ok(EV_KEY::BTN_FORWARD),
+        17 => Ok(EV_KEY::BTN_BACK),
+        18 => Ok(EV_KEY::BTN_TASK),
+
+        19 => Ok(EV_KEY::KEY_0),
+        20 => Ok(EV_KEY::KEY_1),
+        21 => Ok(EV_KEY::KEY_2),
+        22 => Ok(EV_KEY::KEY_3),
+        23 => Ok(EV_KEY::KEY_4),
+        24 => Ok(EV_KEY::KEY_5),
+        25 => Ok(EV_KEY::KEY_6),
+        26 => Ok(EV_KEY::KEY_7),
+        27 => Ok(EV_KEY::KEY_8),
+        28 => Ok(EV_KEY::KEY_9),
+
+        29 => Ok(EV_KEY::KEY_MINUS),
+        30 => Ok(EV_KEY::KEY_EQUAL),
+
+        _ => Err(HwDeviceError::MappingError {}.into()),
+    }
+}
@@ -0,0 +1,467 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! A minimal parser for HID report descriptors, used to discover the layout of the
+//! reports of vendor-neutral devices at runtime
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// Maximum number of usages that a single usage range may expand to
+const MAX_USAGE_RANGE: u32 = 1024;
+
+/// Maximum number of fields that a single main item may declare
+const MAX_REPORT_COUNT: u32 = 4096;
+
+/// Maximum size of a single report in bytes, matches the largest report buffer of the kernel
+const MAX_REPORT_SIZE: usize = 16384;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HidDescriptorError {
+    #[error("The report descriptor is truncated")]
+    Truncated {},
+
+    #[error("Unbalanced collections in the report descriptor")]
+    UnbalancedCollection {},
+
+    #[error("Invalid report size: {size}")]
+    InvalidReportSize { size: u32 },
+
+    #[error("Invalid report count: {count}")]
+    InvalidReportCount { count: u32 },
+
+    #[error("Report {id} exceeds the maximum supported size")]
+    ReportTooLarge { id: u8 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportKind {
+    Input,
+    Output,
+    Feature,
+}
+
+/// A single data field of a report
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub usage_page: u16,
+    pub usage: u16,
+
+    /// Offset in bits, relative to the start of the report's data (after the report ID)
+    pub bit_offset: usize,
+    pub bit_size: usize,
+}
+
+/// The layout of a single report, as declared by the report descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub kind: ReportKind,
+    pub id: u8,
+
+    /// Usage of the innermost collection that contains the report
+    pub usage_page: u16,
+    pub usage: u16,
+
+    pub fields: Vec<Field>,
+    pub bit_size: usize,
+}
+
+impl Report {
+    /// Returns the size of the report in bytes, including the report ID
+    pub fn size(&self) -> usize {
+        1 + self.bit_size.div_ceil(8)
+    }
+
+    /// Returns a zeroed buffer for the report, with the report ID already filled in
+    pub fn new_buffer(&self) -> Vec<u8> {
+        let mut result = vec![0; self.size()];
+        result[0] = self.id;
+
+        result
+    }
+
+    /// Returns all fields with the given usage, in declaration order
+    pub fn fields(&self, usage_page: u16, usage: u16) -> impl Iterator<Item = &Field> {
+        self.fields
+            .iter()
+            .filter(move |f| f.usage_page == usage_page && f.usage == usage)
+    }
+
+    /// Returns the first field with the given usage
+    pub fn field(&self, usage_page: u16, usage: u16) -> Option<&Field> {
+        self.fields(usage_page, usage).next()
+    }
+
+    /// Extracts the value of `field` from `buf`, fields wider than 32 bits are truncated
+    pub fn get(&self, buf: &[u8], field: &Field) -> u32 {
+        let mut result = 0;
+
+        for bit in 0..field.bit_size.min(32) {
+            let pos = 8 + field.bit_offset + bit;
+
+            if let Some(byte) = buf.get(pos / 8) {
+                result |= (((byte >> (pos % 8)) & 1) as u32) << bit;
+            }
+        }
+
+        result
+    }
+
+    /// Stores `value` in `field` of `buf`
+    pub fn set(&self, buf: &mut [u8], field: &Field, value: u32) {
+        for bit in 0..field.bit_size.min(32) {
+            let pos = 8 + field.bit_offset + bit;
+
+            if let Some(byte) = buf.get_mut(pos / 8) {
+                if (value >> bit) & 1 != 0 {
+                    *byte |= 1 << (pos % 8);
+                } else {
+                    *byte &= !(1 << (pos % 8));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct GlobalState {
+    usage_page: u16,
+    report_id: u8,
+    report_size: u32,
+    report_count: u32,
+}
+
+/// The parsed report descriptor of a HID interface
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportDescriptor {
+    /// Usages of all application collections
+    pub applications: Vec<(u16, u16)>,
+
+    pub reports: Vec<Report>,
+}
+
+impl ReportDescriptor {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut result = Self::default();
+
+        let mut global = GlobalState::default();
+        let mut global_stack: Vec<GlobalState> = Vec::new();
+
+        let mut usages: Vec<(u16, u16)> = Vec::new();
+        let mut usage_minimum: Option<u32> = None;
+
+        let mut collections: Vec<(u16, u16)> = Vec::new();
+
+        let mut pos = 0;
+
+        while pos < data.len() {
+            let prefix = data[pos];
+
+            // long items are not used by any known device, skip them
+            if prefix == 0xfe {
+                let size = *data.get(pos + 1).ok_or(HidDescriptorError::Truncated {})?;
+                pos += 3 + size as usize;
+
+                continue;
+            }
+
+            let size = match prefix & 0x03 {
+                3 => 4,
+                n => n as usize,
+            };
+
+            let bytes = data
+                .get(pos + 1..pos + 1 + size)
+                .ok_or(HidDescriptorError::Truncated {})?;
+
+            let value = bytes
+                .iter()
+                .rev()
+                .fold(0_u32, |acc, b| (acc << 8) | *b as u32);
+
+            let item_type = (prefix >> 2) & 0x03;
+            let tag = prefix >> 4;
+
+            match (item_type, tag) {
+                // main items
+                (0, 0x08) | (0, 0x09) | (0, 0x0b) => {
+                    let kind = match tag {
+                        0x08 => ReportKind::Input,
+                        0x09 => ReportKind::Output,
+                        _ => ReportKind::Feature,
+                    };
+
+                    if global.report_size > 32 {
+                        return Err(HidDescriptorError::InvalidReportSize {
+                            size: global.report_size,
+                        }
+                        .into());
+                    }
+
+                    if global.report_count > MAX_REPORT_COUNT {
+                        return Err(HidDescriptorError::InvalidReportCount {
+                            count: global.report_count,
+                        }
+                        .into());
+                    }
+
+                    let (usage_page, usage) = collections.last().copied().unwrap_or_default();
+
+                    let index = match result
+                        .reports
+                        .iter()
+                        .position(|r| r.kind == kind && r.id == global.report_id)
+                    {
+                        Some(index) => index,
+
+                        None => {
+                            result.reports.push(Report {
+                                kind,
+                                id: global.report_id,
+                                usage_page,
+                                usage,
+                                fields: vec![],
+                                bit_size: 0,
+                            });
+
+                            result.reports.len() - 1
+                        }
+                    };
+
+                    let report = &mut result.reports[index];
+
+                    let bit_size = report.bit_size
+                        + global.report_count as usize * global.report_size as usize;
+
+                    if bit_size > MAX_REPORT_SIZE * 8 {
+                        return Err(HidDescriptorError::ReportTooLarge { id: report.id }.into());
+                    }
+
+                    // constant fields without usages are padding
+                    let is_padding = value & 0x01 != 0 && usages.is_empty();
+
+                    for i in 0..global.report_count as usize {
+                        if !is_padding {
+                            let (usage_page, usage) = usages
+                                .get(i)
+                                .or_else(|| usages.last())
+                                .copied()
+                                .unwrap_or_default();
+
+                            report.fields.push(Field {
+                                usage_page,
+                                usage,
+                                bit_offset: report.bit_size,
+                                bit_size: global.report_size as usize,
+                            });
+                        }
+
+                        report.bit_size += global.report_size as usize;
+                    }
+                }
+
+                // collection
+                (0, 0x0a) => {
+                    let usage = usages.first().copied().unwrap_or_default();
+
+                    // application collection
+                    if value == 0x01 {
+                        result.applications.push(usage);
+                    }
+
+                    collections.push(usage);
+                }
+
+                // end collection
+                (0, 0x0c) => {
+                    collections
+                        .pop()
+                        .ok_or(HidDescriptorError::UnbalancedCollection {})?;
+                }
+
+                // global items
+                (1, 0x00) => global.usage_page = value as u16,
+                (1, 0x07) => global.report_size = value,
+                (1, 0x08) => global.report_id = value as u8,
+                (1, 0x09) => global.report_count = value,
+                (1, 0x0a) => global_stack.push(global),
+                (1, 0x0b) => global = global_stack.pop().unwrap_or_default(),
+
+                // local items
+                (2, 0x00) => usages.push(extended_usage(global.usage_page, size, value)),
+                (2, 0x01) => usage_minimum = Some(value),
+                (2, 0x02) => {
+                    if let Some(minimum) = usage_minimum.take() {
+                        let maximum = value.min(minimum.saturating_add(MAX_USAGE_RANGE));
+
+                        for usage in minimum..=maximum {
+                            usages.push(extended_usage(global.usage_page, size, usage));
+                        }
+                    }
+                }
+
+                _ => { /* ignore all other items */ }
+            }
+
+            // local items are only valid up to the next main item
+            if item_type == 0 {
+                usages.clear();
+                usage_minimum = None;
+            }
+
+            pos += 1 + size;
+        }
+
+        if !collections.is_empty() {
+            return Err(HidDescriptorError::UnbalancedCollection {}.into());
+        }
+
+        Ok(result)
+    }
+
+    /// Returns true if the descriptor declares an application collection with the given usage
+    pub fn has_application(&self, usage_page: u16, usage: u16) -> bool {
+        self.applications.contains(&(usage_page, usage))
+    }
+
+    /// Returns the report of kind `kind` that is contained in the collection with the given usage
+    pub fn find_report(&self, kind: ReportKind, usage_page: u16, usage: u16) -> Option<&Report> {
+        self.reports
+            .iter()
+            .find(|r| r.kind == kind && r.usage_page == usage_page && r.usage == usage)
+    }
+}
+
+/// Usages of 4 bytes carry their own usage page in the upper 16 bits
+fn extended_usage(usage_page: u16, size: usize, value: u32) -> (u16, u16) {
+    if size == 4 {
+        ((value >> 16) as u16, value as u16)
+    } else {
+        (usage_page, value as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HidDescriptorError, ReportDescriptor, ReportKind, Result};
+
+    // a vendor defined application with a feature report of two 3 bit fields,
+    // 2 bits of padding and a 16 bit field
+    const DESCRIPTOR: [u8; 32] = [
+        0x06, 0x00, 0xff, // usage page (vendor defined)
+        0x09, 0x01, // usage (1)
+        0xa1, 0x01, // collection (application)
+        0x85, 0x07, //   report id (7)
+        0x09, 0x02, //   usage (2)
+        0x09, 0x03, //   usage (3)
+        0x75, 0x03, //   report size (3)
+        0x95, 0x02, //   report count (2)
+        0xb1, 0x02, //   feature (data, variable, absolute)
+        0x75, 0x02, //   report size (2)
+        0x95, 0x01, //   report count (1)
+        0xb1, 0x03, //   feature (constant)
+        0x09, 0x04, //   usage (4)
+        0x75, 0x10, //   report size (16)
+        0xb1, 0x02, //   feature (data, variable, absolute)
+        0xc0, // end collection
+    ];
+
+    #[test]
+    fn parse_descriptor() -> Result<()> {
+        let descriptor = ReportDescriptor::parse(&DESCRIPTOR)?;
+
+        assert!(descriptor.has_application(0xff00, 0x01));
+        assert_eq!(descriptor.reports.len(), 1);
+
+        let report = descriptor
+            .find_report(ReportKind::Feature, 0xff00, 0x01)
+            .unwrap();
+
+        assert_eq!(report.id, 7);
+        assert_eq!(report.size(), 4);
+        assert_eq!(report.fields.len(), 3);
+
+        let field = report.field(0xff00, 0x04).unwrap();
+        assert_eq!(field.bit_offset, 8);
+        assert_eq!(field.bit_size, 16);
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_and_set_fields() -> Result<()> {
+        let descriptor = ReportDescriptor::parse(&DESCRIPTOR)?;
+        let report = &descriptor.reports[0];
+
+        let mut buf = report.new_buffer();
+
+        report.set(&mut buf, report.field(0xff00, 0x02).unwrap(), 0x05);
+        report.set(&mut buf, report.field(0xff00, 0x03).unwrap(), 0x06);
+        report.set(&mut buf, report.field(0xff00, 0x04).unwrap(), 0xbeef);
+
+        assert_eq!(buf, vec![0x07, 0x35, 0xef, 0xbe]);
+
+        assert_eq!(report.get(&buf, report.field(0xff00, 0x02).unwrap()), 0x05);
+        assert_eq!(report.get(&buf, report.field(0xff00, 0x03).unwrap()), 0x06);
+        assert_eq!(
+            report.get(&buf, report.field(0xff00, 0x04).unwrap()),
+            0xbeef
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        // the last item lacks its data
+        let result = ReportDescriptor::parse(&DESCRIPTOR[..30]);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<HidDescriptorError>(),
+            Some(HidDescriptorError::Truncated {})
+        ));
+
+        // the application collection is never closed
+        let result = ReportDescriptor::parse(&DESCRIPTOR[..31]);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<HidDescriptorError>(),
+            Some(HidDescriptorError::UnbalancedCollection {})
+        ));
+
+        // report count (4294967295)
+        let mut data = DESCRIPTOR.to_vec();
+        data.splice(15..17, [0x97, 0xff, 0xff, 0xff, 0xff]);
+
+        let result = ReportDescriptor::parse(&data);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<HidDescriptorError>(),
+            Some(HidDescriptorError::InvalidReportCount { count: 0xffffffff })
+        ));
+
+        // report size (32), report count (4096), the padding exceeds the maximum report size
+        let mut data = DESCRIPTOR.to_vec();
+        data.splice(13..17, [0x75, 0x20, 0x96, 0x00, 0x10]);
+
+        let result = ReportDescriptor::parse(&data);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<HidDescriptorError>(),
+            Some(HidDescriptorError::ReportTooLarge { id: 7 })
+        ));
+    }
+}
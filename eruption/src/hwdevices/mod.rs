@@ -20,6 +20,7 @@
 */
 
 use evdev_rs::enums::EV_KEY;
+use flume::Receiver;
 use hidapi::HidApi;
 use lazy_static::lazy_static;
 use log::*;
@@ -33,8 +34,11 @@ use udev::Enumerator;
 
 mod corsair_strafe;
 mod custom_serial_leds;
+mod device_descriptions;
 mod generic_keyboard;
+mod generic_lamp_array;
 mod generic_mouse;
+mod hid_descriptor;
 mod roccat_aimo_pad;
 mod roccat_burst_pro;
 mod roccat_elo_71_air;
@@ -55,6 +59,10 @@ mod roccat_vulcan_1xx;
 mod roccat_vulcan_pro;
 mod roccat_vulcan_pro_tkl;
 mod roccat_vulcan_tkl;
+mod virtual_device;
+mod virtual_keyboard;
+mod virtual_misc;
+mod virtual_mouse;
 
 pub type KeyboardDevice = Arc<RwLock<Box<dyn KeyboardDeviceTrait + Sync + Send>>>;
 pub type MouseDevice = Arc<RwLock<Box<dyn MouseDeviceTrait + Sync + Send>>>;
@@ -76,7 +84,7 @@ pub type Result<T> = std::result::Result<T, eyre::Error>;
 #[rustfmt::skip]
 lazy_static! {
     // List of supported devices
-    pub static ref DRIVERS: Arc<Mutex<[Box<(dyn DriverMetadata + Sync + Send + 'static)>; 28]>> = Arc::new(Mutex::new([
+    pub static ref DRIVERS: Arc<Mutex<Vec<Box<(dyn DriverMetadata + Sync + Send + 'static)>>>> = Arc::new(Mutex::new(vec![
         // Supported keyboards
 
         // ROCCAT
@@ -948,6 +956,122 @@ pub fn get_non_pnp_devices() -> Result<Vec<NonPnPDevice>> {
     }
 }
 
+/// Returns the make and model of a supported device, as registered by its driver
+pub fn get_device_make_and_model(usb_vid: u16, usb_pid: u16) -> Option<(String, String)> {
+    let drivers = DRIVERS.lock();
+    let driver = drivers
+        .iter()
+        .find(|d| d.get_usb_vid() == usb_vid && d.get_usb_pid() == usb_pid)?
+        .as_any();
+
+    if let Some(driver) = driver.downcast_ref::<KeyboardDriver>() {
+        Some((
+            driver.device_make.to_string(),
+            driver.device_name.to_string(),
+        ))
+    } else if let Some(driver) = driver.downcast_ref::<MouseDriver>() {
+        Some((
+            driver.device_make.to_string(),
+            driver.device_name.to_string(),
+        ))
+    } else if let Some(driver) = driver.downcast_ref::<MiscDriver>() {
+        Some((
+            driver.device_make.to_string(),
+            driver.device_name.to_string(),
+        ))
+    } else {
+        driver.downcast_ref::<MiscSerialDriver>().map(|driver| {
+            (
+                driver.device_make.to_string(),
+                driver.device_name.to_string(),
+            )
+        })
+    }
+}
+
+/// Load the declarative device descriptions from the `devices.d` directories.
+/// Described devices take precedence over the built-in drivers
+pub fn load_device_descriptions() {
+    let dirs = crate::util::get_device_description_dirs();
+    let drivers = device_descriptions::load_device_descriptions(&dirs);
+
+    if !drivers.is_empty() {
+        info!("Loaded {} device description(s)", drivers.len());
+
+        let mut table = DRIVERS.lock();
+        table.splice(0..0, drivers);
+    }
+}
+
+/// Returns the receiving end of the input event channel of a virtual device,
+/// or `None` if the device is backed by real hardware
+pub fn get_virtual_input_rx(device: &dyn DeviceTrait) -> Option<Receiver<evdev_rs::InputEvent>> {
+    if let Some(device) = device
+        .as_any()
+        .downcast_ref::<virtual_keyboard::VirtualKeyboard>()
+    {
+        Some(device.state.get_input_rx())
+    } else if let Some(device) = device
+        .as_any()
+        .downcast_ref::<virtual_mouse::VirtualMouse>()
+    {
+        Some(device.state.get_input_rx())
+    } else {
+        device
+            .as_any()
+            .downcast_ref::<virtual_misc::VirtualMisc>()
+            .map(|device| device.state.get_input_rx())
+    }
+}
+
+/// Bind the generic LampArray driver to `device_info`, unless it has already been bound
+/// to another interface of the same device
+fn bind_lamp_array<'a>(
+    api: &HidApi,
+    device_info: &'a hidapi::DeviceInfo,
+    misc_devices: &mut Vec<MiscDevice>,
+    bound_lamp_arrays: &mut Vec<(u16, u16, &'a str)>,
+) {
+    let serial = device_info.serial_number().unwrap_or("");
+    let path = device_info.path().to_string_lossy().to_string();
+
+    if !bound_lamp_arrays.contains(&(device_info.vendor_id(), device_info.product_id(), serial)) {
+        info!(
+            "Found LampArray device: 0x{:x}:0x{:x} ({}) - {} {}",
+            device_info.vendor_id(),
+            device_info.product_id(),
+            path,
+            device_info
+                .manufacturer_string()
+                .unwrap_or("<unknown>")
+                .to_string(),
+            device_info
+                .product_string()
+                .unwrap_or("<unknown>")
+                .to_string()
+        );
+
+        let driver_maturity_level = *crate::DRIVER_MATURITY_LEVEL.lock();
+
+        if MaturityLevel::Experimental <= driver_maturity_level {
+            if let Ok(device) = generic_lamp_array::bind_hiddev(
+                api,
+                device_info.vendor_id(),
+                device_info.product_id(),
+                serial,
+            ) {
+                misc_devices.push(device);
+                bound_lamp_arrays.push((device_info.vendor_id(), device_info.product_id(), serial));
+            } else {
+                error!("Failed to bind the device driver");
+            }
+        } else {
+            warn!("Not binding the device driver because it would require a lesser code maturity level");
+            warn!("To enable this device driver, please change the 'driver_maturity_level' setting in eruption.conf respectively");
+        }
+    }
+}
+
 /// Enumerates all HID devices on the system (and static device declarations
 /// from the .conf file as well), and then returns a tuple of all the supported devices
 pub fn probe_devices() -> Result<(Vec<KeyboardDevice>, Vec<MouseDevice>, Vec<MiscDevice>)> {
@@ -968,16 +1092,53 @@ pub fn probe_devices() -> Result<(Vec<KeyboardDevice>, Vec<MouseDevice>, Vec<Mis
 
             let serial_leds = custom_serial_leds::CustomSerialLeds::bind(device.device_file);
 
-            // non pnp devices are currently always 'misc' devices
             misc_devices.push(Arc::new(RwLock::new(
                 Box::new(serial_leds) as Box<dyn MiscDeviceTrait + Sync + Send>
             )));
+        } else if device.class == "virtual-keyboard" {
+            info!(
+                "Binding virtual keyboard device: {} ({})",
+                device.name,
+                device.device_file.display()
+            );
+
+            let keyboard =
+                virtual_keyboard::VirtualKeyboard::bind(&device.name, device.device_file);
+
+            keyboard_devices.push(Arc::new(RwLock::new(
+                Box::new(keyboard) as Box<dyn KeyboardDeviceTrait + Sync + Send>
+            )));
+        } else if device.class == "virtual-mouse" {
+            info!(
+                "Binding virtual mouse device: {} ({})",
+                device.name,
+                device.device_file.display()
+            );
+
+            let mouse = virtual_mouse::VirtualMouse::bind(&device.name, device.device_file);
+
+            mouse_devices.push(Arc::new(RwLock::new(
+                Box::new(mouse) as Box<dyn MouseDeviceTrait + Sync + Send>
+            )));
+        } else if device.class == "virtual-misc" {
+            info!(
+                "Binding virtual misc device: {} ({})",
+                device.name,
+                device.device_file.display()
+            );
+
+            let misc = virtual_misc::VirtualMisc::bind(&device.name, device.device_file);
+
+            misc_devices.push(Arc::new(RwLock::new(
+                Box::new(misc) as Box<dyn MiscDeviceTrait + Sync + Send>
+            )));
         } else {
             error!("Unknown device class specified in the configuration file");
         }
     }
 
     let mut bound_devices = vec![];
+    let mut bound_lamp_arrays = vec![];
 
     let hidapi = crate::HIDAPI.read();
     let api = hidapi.as_ref().unwrap();
@@ -1162,6 +1323,16 @@ pub fn probe_devices() -> Result<(Vec<KeyboardDevice>, Vec<MouseDevice>, Vec<Mis
                 let serial = device_info.serial_number().unwrap_or("");
                 let path = device_info.path().to_string_lossy().to_string();
 
+                // devices that implement the HID LampArray standard are supported by a
+                // generic driver, in addition to the driver of their input interfaces
+                if device_info.usage_page() == generic_lamp_array::LAMP_ARRAY_USAGE_PAGE
+                    && device_info.usage() == generic_lamp_array::LAMP_ARRAY_USAGE
+                {
+                    bind_lamp_array(api, device_info, &mut misc_devices, &mut bound_lamp_arrays);
+
+                    continue;
+                }
+
                 if !bound_devices.contains(&(
                     device_info.vendor_id(),
                     device_info.product_id(),
@@ -1274,6 +1445,7 @@ pub fn probe_devices_hotplug() -> Result<(Vec<KeyboardDevice>, Vec<MouseDevice>,
     let mut misc_devices = vec![];
 
     let mut bound_devices = vec![];
+    let mut bound_lamp_arrays = vec![];
 
     let mut hidapi = crate::HIDAPI.write();
     let api = hidapi.as_mut().unwrap();
@@ -1457,6 +1629,16 @@ pub fn probe_devices_hotplug() -> Result<(Vec<KeyboardDevice>, Vec<MouseDevice>,
                 let serial = device_info.serial_number().unwrap_or("");
                 let path = device_info.path().to_string_lossy().to_string();
 
+                // devices that implement the HID LampArray standard are supported by a
+                // generic driver, in addition to the driver of their input interfaces
+                if device_info.usage_page() == generic_lamp_array::LAMP_ARRAY_USAGE_PAGE
+                    && device_info.usage() == generic_lamp_array::LAMP_ARRAY_USAGE
+                {
+                    bind_lamp_array(api, device_info, &mut misc_devices, &mut bound_lamp_arrays);
+
+                    continue;
+                }
+
                 if !bound_devices.contains(&(
                     device_info.vendor_id(),
                     device_info.product_id(),
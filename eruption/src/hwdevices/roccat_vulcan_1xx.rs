@@ -1114,7 +1114,7 @@ fn keyboard_hid_event_code_from_report(report: u8, code: u8) -> KeyboardHidEvent
 
 /// Map evdev event codes to key indices, for ISO variant
 #[rustfmt::skip]
-const EV_TO_INDEX_ISO: [u8; 0x2ff + 1] = [
+pub(crate) const EV_TO_INDEX_ISO: [u8; 0x2ff + 1] = [
     0xff, 0x00, 0x06, 0x0c, 0x12, 0x18, 0x1d, 0x21, 0x31, 0x36, 0x3c, 0x42, 0x48, 0x4f, 0x57,
     0x02, // 0x000
     0x07, 0x0d, 0x13, 0x19, 0x1e, 0x22, 0x32, 0x37, 0x3d, 0x43, 0x49, 0x50, 0x58, 0x05, 0x08,
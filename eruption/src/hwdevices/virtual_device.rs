@@ -0,0 +1,626 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use evdev_rs::enums::{int_to_ev_key, EventCode, EV_KEY, EV_REL, EV_SYN};
+use evdev_rs::{InputEvent, TimeVal};
+use flume::{unbounded, Receiver, Sender};
+use log::*;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{KeyboardHidEvent, KeyboardHidEventCode, MouseHidEvent, RGBA};
+
+pub type Result<T> = super::Result<T>;
+
+/// USB vendor ID reported by all virtual devices
+pub const VIRTUAL_USB_VID: u16 = 0xffff;
+
+/// USB product IDs reported by the virtual devices
+pub const VIRTUAL_KEYBOARD_USB_PID: u16 = 0x0200;
+pub const VIRTUAL_MOUSE_USB_PID: u16 = 0x0201;
+pub const VIRTUAL_MISC_USB_PID: u16 = 0x0202;
+
+/// Max. number of LED frames that will be retained by a virtual device
+pub const MAX_RECORDED_FRAMES: usize = 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum VirtualDeviceError {
+    #[error("Invalid command: {description}")]
+    InvalidCommand { description: String },
+
+    #[error("Command not supported by this device class")]
+    NotSupported {},
+}
+
+/// The class of a virtual device
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VirtualDeviceClass {
+    Keyboard,
+    Mouse,
+    Misc,
+}
+
+/// A HID event that has been injected via the control socket
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScriptedHidEvent {
+    Keyboard(KeyboardHidEvent),
+    Mouse(MouseHidEvent),
+}
+
+/// A command received on the control socket of a virtual device
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Inject an evdev key or button event
+    Key { key: EV_KEY, value: i32 },
+
+    /// Inject an evdev relative axis event
+    Rel { axis: EV_REL, value: i32 },
+
+    /// Queue a HID event, to be returned by `get_next_event_timeout()`
+    Hid(ScriptedHidEvent),
+
+    /// Query the number of frames that have been sent to the device
+    Frames,
+
+    /// Query a recorded frame, defaults to the most recent one
+    Frame(Option<usize>),
+
+    /// Drop all recorded frames
+    Clear,
+
+    /// Query the device specific brightness
+    Brightness,
+}
+
+/// State that is shared between a virtual device and its control socket
+pub struct VirtualDeviceState {
+    pub class: VirtualDeviceClass,
+    pub name: String,
+    pub socket_path: PathBuf,
+
+    frames: Mutex<VecDeque<Vec<RGBA>>>,
+    frame_count: AtomicUsize,
+    brightness: AtomicI32,
+    listening: AtomicBool,
+
+    hid_tx: Sender<ScriptedHidEvent>,
+    hid_rx: Receiver<ScriptedHidEvent>,
+
+    input_tx: Sender<InputEvent>,
+    input_rx: Receiver<InputEvent>,
+}
+
+impl VirtualDeviceState {
+    pub fn new(class: VirtualDeviceClass, name: &str, socket_path: &Path) -> Self {
+        let (hid_tx, hid_rx) = unbounded();
+        let (input_tx, input_rx) = unbounded();
+
+        Self {
+            class,
+            name: name.to_string(),
+            socket_path: socket_path.to_path_buf(),
+
+            frames: Mutex::new(VecDeque::new()),
+            frame_count: AtomicUsize::new(0),
+            brightness: AtomicI32::new(100),
+            listening: AtomicBool::new(false),
+
+            hid_tx,
+            hid_rx,
+
+            input_tx,
+            input_rx,
+        }
+    }
+
+    /// Record a frame that has been sent to the device
+    pub fn record_frame(&self, led_map: &[RGBA]) {
+        let mut frames = self.frames.lock();
+
+        if frames.len() >= MAX_RECORDED_FRAMES {
+            frames.pop_front();
+        }
+
+        frames.push_back(led_map.to_vec());
+
+        self.frame_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns the total number of frames that have been sent to the device
+    pub fn frame_count(&self) -> usize {
+        self.frame_count.load(Ordering::SeqCst)
+    }
+
+    /// Returns the retained frame with index `index`, or the most recent one
+    pub fn get_frame(&self, index: Option<usize>) -> Option<Vec<RGBA>> {
+        let frames = self.frames.lock();
+
+        match index {
+            Some(index) => frames.get(index).cloned(),
+            None => frames.back().cloned(),
+        }
+    }
+
+    /// Drop all recorded frames
+    pub fn clear_frames(&self) {
+        self.frames.lock().clear();
+        self.frame_count.store(0, Ordering::SeqCst);
+    }
+
+    pub fn get_brightness(&self) -> i32 {
+        self.brightness.load(Ordering::SeqCst)
+    }
+
+    pub fn set_brightness(&self, brightness: i32) {
+        self.brightness.store(brightness, Ordering::SeqCst);
+    }
+
+    /// Returns the next queued HID event, waits for at most `millis` milliseconds.
+    /// A negative value of `millis` blocks until an event is available
+    pub fn next_hid_event(&self, millis: i32) -> Option<ScriptedHidEvent> {
+        match millis {
+            0 => self.hid_rx.try_recv().ok(),
+            m if m < 0 => self.hid_rx.recv().ok(),
+            m => self
+                .hid_rx
+                .recv_timeout(Duration::from_millis(m as u64))
+                .ok(),
+        }
+    }
+
+    /// Returns the receiving end of the evdev input event channel
+    pub fn get_input_rx(&self) -> Receiver<InputEvent> {
+        self.input_rx.clone()
+    }
+
+    /// Execute a command, received on the control socket
+    pub fn execute(&self, command: &Command) -> Result<String> {
+        match command {
+            Command::Key { key, value } => {
+                if self.class == VirtualDeviceClass::Misc {
+                    return Err(VirtualDeviceError::NotSupported {}.into());
+                }
+
+                self.inject_input_event(EventCode::EV_KEY(*key), *value)?;
+
+                Ok("OK".to_string())
+            }
+
+            Command::Rel { axis, value } => {
+                if self.class != VirtualDeviceClass::Mouse {
+                    return Err(VirtualDeviceError::NotSupported {}.into());
+                }
+
+                self.inject_input_event(EventCode::EV_REL(*axis), *value)?;
+
+                Ok("OK".to_string())
+            }
+
+            Command::Hid(event) => {
+                match (self.class, event) {
+                    (VirtualDeviceClass::Keyboard, ScriptedHidEvent::Keyboard(_))
+                    | (VirtualDeviceClass::Mouse, ScriptedHidEvent::Mouse(_)) => {
+                        self.hid_tx.send(*event)?
+                    }
+
+                    _ => return Err(VirtualDeviceError::NotSupported {}.into()),
+                }
+
+                Ok("OK".to_string())
+            }
+
+            Command::Frames => Ok(format!("OK {}", self.frame_count())),
+
+            Command::Frame(index) => match self.get_frame(*index) {
+                Some(frame) => {
+                    let colors = frame
+                        .iter()
+                        .map(|c| format!("{:02x}{:02x}{:02x}{:02x}", c.r, c.g, c.b, c.a))
+                        .collect::<Vec<String>>();
+
+                    Ok(format!("OK {}", colors.join(" ")))
+                }
+
+                None => Err(VirtualDeviceError::InvalidCommand {
+                    description: "No such frame".to_string(),
+                }
+                .into()),
+            },
+
+            Command::Clear => {
+                self.clear_frames();
+
+                Ok("OK".to_string())
+            }
+
+            Command::Brightness => Ok(format!("OK {}", self.get_brightness())),
+        }
+    }
+
+    /// Send an input event, followed by a SYN_REPORT, just like the kernel would do
+    fn inject_input_event(&self, event_code: EventCode, value: i32) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let time = TimeVal {
+            tv_sec: now.as_secs() as _,
+            tv_usec: now.subsec_micros() as _,
+        };
+
+        self.input_tx.send(InputEvent {
+            time,
+            event_code,
+            value,
+        })?;
+
+        self.input_tx.send(InputEvent {
+            time,
+            event_code: EventCode::EV_SYN(EV_SYN::SYN_REPORT),
+            value: 0,
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Parse a line received on the control socket. Supported commands are:
+///
+/// * `key <ev_key> <value>`: Inject an evdev key or button event
+/// * `rel <x|y|z|wheel|hwheel> <value>`: Inject a relative axis event (mice only)
+/// * `hid <event> [arg]`: Queue a HID event, e.g. `hid key-down KEY_FN` or `hid dpi-change 2`
+/// * `frames`: Query the number of frames that have been sent to the device
+/// * `frame [n]`: Query a recorded frame as hex encoded RGBA values
+/// * `clear`: Drop all recorded frames
+/// * `brightness`: Query the device specific brightness
+///
+/// Every command is answered with a single line, starting with either `OK` or `ERROR`
+pub fn parse_command(line: &str) -> Result<Command> {
+    fn invalid(description: &str) -> eyre::Error {
+        VirtualDeviceError::InvalidCommand {
+            description: description.to_string(),
+        }
+        .into()
+    }
+
+    fn arg<T: std::str::FromStr>(args: &[&str], idx: usize) -> Result<T> {
+        args.get(idx)
+            .ok_or_else(|| invalid("Missing argument"))?
+            .parse::<T>()
+            .map_err(|_e| invalid("Invalid argument"))
+    }
+
+    fn hid_event_code(code: &str) -> Result<KeyboardHidEventCode> {
+        match code.to_uppercase().as_str() {
+            "KEY_F1" => Ok(KeyboardHidEventCode::KEY_F1),
+            "KEY_F2" => Ok(KeyboardHidEventCode::KEY_F2),
+            "KEY_F3" => Ok(KeyboardHidEventCode::KEY_F3),
+            "KEY_F4" => Ok(KeyboardHidEventCode::KEY_F4),
+
+            "KEY_F5" => Ok(KeyboardHidEventCode::KEY_F5),
+            "KEY_F6" => Ok(KeyboardHidEventCode::KEY_F6),
+            "KEY_F7" => Ok(KeyboardHidEventCode::KEY_F7),
+            "KEY_F8" => Ok(KeyboardHidEventCode::KEY_F8),
+
+            "KEY_ESC" => Ok(KeyboardHidEventCode::KEY_ESC),
+            "KEY_CAPS_LOCK" => Ok(KeyboardHidEventCode::KEY_CAPS_LOCK),
+            "KEY_FN" => Ok(KeyboardHidEventCode::KEY_FN),
+            "KEY_EASY_SHIFT" => Ok(KeyboardHidEventCode::KEY_EASY_SHIFT),
+
+            code => Ok(KeyboardHidEventCode::Unknown(
+                code.parse::<u8>()
+                    .map_err(|_e| invalid("Invalid HID event code"))?,
+            )),
+        }
+    }
+
+    let tokens = line.split_whitespace().collect::<Vec<&str>>();
+
+    match tokens.as_slice() {
+        ["key", args @ ..] => {
+            let key = int_to_ev_key(arg::<u32>(args, 0)?).ok_or_else(|| invalid("Invalid key"))?;
+            let value = arg::<i32>(args, 1)?;
+
+            Ok(Command::Key { key, value })
+        }
+
+        ["rel", args @ ..] => {
+            let axis = match *args.first().ok_or_else(|| invalid("Missing argument"))? {
+                "x" => EV_REL::REL_X,
+                "y" => EV_REL::REL_Y,
+                "z" => EV_REL::REL_Z,
+                "wheel" => EV_REL::REL_WHEEL,
+                "hwheel" => EV_REL::REL_HWHEEL,
+
+                _ => return Err(invalid("Invalid axis")),
+            };
+
+            let value = arg::<i32>(args, 1)?;
+
+            Ok(Command::Rel { axis, value })
+        }
+
+        ["hid", event, args @ ..] => {
+            let event = match *event {
+                "key-down" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::KeyDown {
+                    code: hid_event_code(args.first().ok_or_else(|| invalid("Missing argument"))?)?,
+                }),
+                "key-up" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::KeyUp {
+                    code: hid_event_code(args.first().ok_or_else(|| invalid("Missing argument"))?)?,
+                }),
+
+                "next-slot" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::NextSlot),
+                "previous-slot" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::PreviousSlot),
+
+                "brightness-up" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::BrightnessUp),
+                "brightness-down" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::BrightnessDown),
+                "set-brightness" => {
+                    ScriptedHidEvent::Keyboard(KeyboardHidEvent::SetBrightness(arg(args, 0)?))
+                }
+
+                "mute-down" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::MuteDown),
+                "mute-up" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::MuteUp),
+                "volume-down" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::VolumeDown),
+                "volume-up" => ScriptedHidEvent::Keyboard(KeyboardHidEvent::VolumeUp),
+
+                "button-down" => ScriptedHidEvent::Mouse(MouseHidEvent::ButtonDown(arg(args, 0)?)),
+                "button-up" => ScriptedHidEvent::Mouse(MouseHidEvent::ButtonUp(arg(args, 0)?)),
+                "dpi-change" => ScriptedHidEvent::Mouse(MouseHidEvent::DpiChange(arg(args, 0)?)),
+
+                _ => return Err(invalid("Unknown HID event")),
+            };
+
+            Ok(Command::Hid(event))
+        }
+
+        ["frames"] => Ok(Command::Frames),
+        ["frame"] => Ok(Command::Frame(None)),
+        ["frame", args @ ..] => Ok(Command::Frame(Some(arg::<usize>(args, 0)?))),
+        ["clear"] => Ok(Command::Clear),
+        ["brightness"] => Ok(Command::Brightness),
+
+        _ => Err(invalid("Unknown command")),
+    }
+}
+
+/// Bind the control socket of a virtual device and spawn a thread that serves it
+pub fn start_control_socket(state: Arc<VirtualDeviceState>) -> Result<()> {
+    if state.listening.swap(true, Ordering::SeqCst) {
+        // the control socket is already being served
+        return Ok(());
+    }
+
+    // unlink any leftover control sockets
+    let _result = fs::remove_file(&state.socket_path)
+        .map_err(|e| debug!("Unlink of virtual device control socket failed: {}", e));
+
+    let listener = match UnixListener::bind(&state.socket_path) {
+        Ok(listener) => listener,
+
+        Err(e) => {
+            state.listening.store(false, Ordering::SeqCst);
+
+            return Err(e.into());
+        }
+    };
+
+    info!(
+        "Virtual device '{}' is listening on: {}",
+        state.name,
+        state.socket_path.display()
+    );
+
+    thread::Builder::new()
+        .name(format!("virtual-device:{}", state.name))
+        .spawn(move || {
+            for stream in listener.incoming() {
+                if crate::QUIT.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                match stream {
+                    Ok(stream) => {
+                        let state = state.clone();
+
+                        thread::Builder::new()
+                            .name(format!("virtual-device-client:{}", state.name))
+                            .spawn(move || {
+                                run_client_loop(&state, stream).unwrap_or_else(|e| {
+                                    debug!("Virtual device client disconnected: {}", e)
+                                })
+                            })
+                            .unwrap_or_else(|e| {
+                                error!("Could not spawn a thread: {}", e);
+                                panic!()
+                            });
+                    }
+
+                    Err(e) => error!("Could not accept a connection: {}", e),
+                }
+            }
+        })
+        .unwrap_or_else(|e| {
+            error!("Could not spawn a thread: {}", e);
+            panic!()
+        });
+
+    Ok(())
+}
+
+fn run_client_loop(state: &VirtualDeviceState, stream: UnixStream) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        trace!("Virtual device '{}': {}", state.name, line.trim());
+
+        let response = match parse_command(&line).and_then(|command| state.execute(&command)) {
+            Ok(response) => response,
+            Err(e) => format!("ERROR {}", e),
+        };
+
+        writeln!(writer, "{}", response)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parse_commands() -> super::Result<()> {
+        assert_eq!(
+            parse_command("key 30 1")?,
+            Command::Key {
+                key: EV_KEY::KEY_A,
+                value: 1
+            }
+        );
+
+        assert_eq!(
+            parse_command("hid key-down KEY_FN")?,
+            Command::Hid(ScriptedHidEvent::Keyboard(KeyboardHidEvent::KeyDown {
+                code: KeyboardHidEventCode::KEY_FN
+            }))
+        );
+
+        assert_eq!(
+            parse_command("hid dpi-change 2")?,
+            Command::Hid(ScriptedHidEvent::Mouse(MouseHidEvent::DpiChange(2)))
+        );
+
+        assert_eq!(parse_command("frame")?, Command::Frame(None));
+        assert_eq!(parse_command("frame 3")?, Command::Frame(Some(3)));
+
+        assert!(parse_command("rel q 1").is_err());
+        assert!(parse_command("hid set-brightness").is_err());
+        assert!(parse_command("bogus").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn record_frames() {
+        let state = VirtualDeviceState::new(
+            VirtualDeviceClass::Misc,
+            "test",
+            &std::env::temp_dir().join("unused.sock"),
+        );
+
+        for i in 0..(MAX_RECORDED_FRAMES + 8) {
+            state.record_frame(&[RGBA {
+                r: i as u8,
+                g: 0,
+                b: 0,
+                a: 255,
+            }]);
+        }
+
+        assert_eq!(state.frame_count(), MAX_RECORDED_FRAMES + 8);
+        assert_eq!(
+            state.get_frame(None).unwrap()[0].r,
+            (MAX_RECORDED_FRAMES + 7) as u8
+        );
+        assert_eq!(state.get_frame(Some(0)).unwrap()[0].r, 8);
+
+        state.clear_frames();
+
+        assert_eq!(state.frame_count(), 0);
+        assert!(state.get_frame(None).is_none());
+    }
+
+    #[test]
+    fn control_socket() -> super::Result<()> {
+        let socket_path =
+            std::env::temp_dir().join(format!("eruption-virtual-{}.sock", std::process::id()));
+
+        let state = Arc::new(VirtualDeviceState::new(
+            VirtualDeviceClass::Keyboard,
+            "test",
+            &socket_path,
+        ));
+
+        start_control_socket(state.clone())?;
+
+        state.record_frame(&[RGBA {
+            r: 0xff,
+            g: 0x80,
+            b: 0x00,
+            a: 0xff,
+        }]);
+
+        let mut stream = UnixStream::connect(&socket_path)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut request = |line: &str| -> super::Result<String> {
+            writeln!(stream, "{}", line)?;
+
+            let mut response = String::new();
+            reader.read_line(&mut response)?;
+
+            Ok(response.trim().to_string())
+        };
+
+        assert_eq!(request("frames")?, "OK 1");
+        assert_eq!(request("frame")?, "OK ff8000ff");
+        assert_eq!(request("key 30 1")?, "OK");
+        assert_eq!(request("hid next-slot")?, "OK");
+        assert!(request("rel x 10")?.starts_with("ERROR"));
+        assert!(request("hid button-down 1")?.starts_with("ERROR"));
+
+        let input_rx = state.get_input_rx();
+
+        let event = input_rx.recv()?;
+        assert_eq!(event.event_code, EventCode::EV_KEY(EV_KEY::KEY_A));
+        assert_eq!(event.value, 1);
+
+        let event = input_rx.recv()?;
+        assert_eq!(event.event_code, EventCode::EV_SYN(EV_SYN::SYN_REPORT));
+
+        assert_eq!(
+            state.next_hid_event(100),
+            Some(ScriptedHidEvent::Keyboard(KeyboardHidEvent::NextSlot))
+        );
+        assert_eq!(state.next_hid_event(0), None);
+
+        let _ = std::fs::remove_file(&socket_path);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use evdev_rs::enums::EV_KEY;
+use log::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{any::Any, collections::HashMap};
+
+use crate::hwdevices::DeviceStatus;
+
+use super::roccat_vulcan_1xx::{
+    COLS_TOPOLOGY, EV_TO_INDEX_ISO, NUM_COLS, NUM_KEYS, NUM_ROWS, ROWS_TOPOLOGY,
+};
+use super::virtual_device::{
+    self, ScriptedHidEvent, VirtualDeviceClass, VirtualDeviceState, VIRTUAL_KEYBOARD_USB_PID,
+    VIRTUAL_USB_VID,
+};
+use super::{
+    Capability, DeviceCapabilities, DeviceInfoTrait, DeviceTrait, KeyboardDeviceTrait,
+    KeyboardHidEvent, KeyboardHidEventCode, LedKind, MouseDeviceTrait, RGBA,
+};
+
+pub type Result<T> = super::Result<T>;
+
+#[derive(Clone)]
+/// A virtual keyboard device that mirrors the layout of a ROCCAT Vulcan 100/12x.
+/// It is controlled by and may be inspected via a UNIX domain socket
+pub struct VirtualKeyboard {
+    pub state: Arc<VirtualDeviceState>,
+}
+
+impl VirtualKeyboard {
+    /// Binds the driver to the supplied control socket
+    pub fn bind(name: &str, socket_path: PathBuf) -> Self {
+        info!("Bound driver: Virtual Keyboard");
+
+        Self {
+            state: Arc::new(VirtualDeviceState::new(
+                VirtualDeviceClass::Keyboard,
+                name,
+                &socket_path,
+            )),
+        }
+    }
+}
+
+impl DeviceInfoTrait for VirtualKeyboard {
+    fn get_device_capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities::from([Capability::Keyboard, Capability::RgbLighting])
+    }
+
+    fn get_device_info(&self) -> Result<super::DeviceInfo> {
+        trace!("Querying the device for information...");
+
+        let result = super::DeviceInfo::new(0);
+        Ok(result)
+    }
+
+    fn get_firmware_revision(&self) -> String {
+        "<virtual>".to_string()
+    }
+}
+
+impl DeviceTrait for VirtualKeyboard {
+    fn get_usb_path(&self) -> String {
+        self.state.socket_path.to_string_lossy().to_string()
+    }
+
+    fn get_usb_vid(&self) -> u16 {
+        VIRTUAL_USB_VID
+    }
+
+    fn get_usb_pid(&self) -> u16 {
+        VIRTUAL_KEYBOARD_USB_PID
+    }
+
+    fn get_serial(&self) -> Option<&str> {
+        Some(&self.state.name)
+    }
+
+    fn get_support_script_file(&self) -> String {
+        "keyboards/roccat_vulcan_1xx".to_string()
+    }
+
+    fn open(&mut self, _api: &hidapi::HidApi) -> Result<()> {
+        trace!("Opening devices now...");
+
+        virtual_device::start_control_socket(self.state.clone())
+    }
+
+    fn close_all(&mut self) -> Result<()> {
+        trace!("Closing devices now...");
+
+        Ok(())
+    }
+
+    fn send_init_sequence(&mut self) -> Result<()> {
+        trace!("Sending device init sequence...");
+
+        Ok(())
+    }
+
+    fn is_initialized(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn has_failed(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn write_data_raw(&self, _buf: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_data_raw(&self, size: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        buf.resize(size, 0);
+
+        Ok(buf)
+    }
+
+    fn device_status(&self) -> Result<DeviceStatus> {
+        let mut table = HashMap::new();
+
+        table.insert("connected".to_owned(), format!("{}", true));
+        table.insert("frames".to_owned(), format!("{}", self.state.frame_count()));
+
+        Ok(DeviceStatus(table))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_device(&self) -> &dyn DeviceTrait {
+        self
+    }
+
+    fn as_device_mut(&mut self) -> &mut dyn DeviceTrait {
+        self
+    }
+
+    fn as_mouse_device(&self) -> Option<&dyn MouseDeviceTrait> {
+        None
+    }
+
+    fn as_mouse_device_mut(&mut self) -> Option<&mut dyn MouseDeviceTrait> {
+        None
+    }
+}
+
+impl KeyboardDeviceTrait for VirtualKeyboard {
+    fn set_status_led(&self, _led_kind: LedKind, _on: bool) -> Result<()> {
+        trace!("Setting status LED state");
+
+        Ok(())
+    }
+
+    #[inline]
+    fn get_next_event(&self) -> Result<KeyboardHidEvent> {
+        self.get_next_event_timeout(-1)
+    }
+
+    fn get_next_event_timeout(&self, millis: i32) -> Result<KeyboardHidEvent> {
+        trace!("Querying control device for next event");
+
+        match self.state.next_hid_event(millis) {
+            Some(ScriptedHidEvent::Keyboard(event)) => Ok(event),
+
+            _ => Ok(KeyboardHidEvent::Unknown),
+        }
+    }
+
+    fn ev_key_to_key_index(&self, key: EV_KEY) -> u8 {
+        EV_TO_INDEX_ISO[((key as u8) as usize)].saturating_add(1)
+    }
+
+    fn hid_event_code_to_key_index(&self, code: &KeyboardHidEventCode) -> u8 {
+        match code {
+            KeyboardHidEventCode::KEY_FN => 77,
+
+            KeyboardHidEventCode::KEY_CAPS_LOCK => 4,
+            KeyboardHidEventCode::KEY_EASY_SHIFT => 4,
+
+            _ => 0,
+        }
+    }
+
+    fn hid_event_code_to_report(&self, code: &KeyboardHidEventCode) -> u8 {
+        match code {
+            KeyboardHidEventCode::Unknown(code) => *code,
+
+            _ => 0,
+        }
+    }
+
+    fn set_local_brightness(&mut self, brightness: i32) -> Result<()> {
+        trace!("Setting device specific brightness");
+
+        self.state.set_brightness(brightness);
+
+        Ok(())
+    }
+
+    fn get_local_brightness(&self) -> Result<i32> {
+        trace!("Querying device specific brightness");
+
+        Ok(self.state.get_brightness())
+    }
+
+    fn send_led_map(&mut self, led_map: &[RGBA]) -> Result<()> {
+        trace!("Setting LEDs from supplied map...");
+
+        self.state
+            .record_frame(&led_map[0..NUM_KEYS.min(led_map.len())]);
+
+        Ok(())
+    }
+
+    fn set_led_init_pattern(&mut self) -> Result<()> {
+        trace!("Setting LED init pattern...");
+
+        Ok(())
+    }
+
+    fn set_led_off_pattern(&mut self) -> Result<()> {
+        trace!("Setting LED off pattern...");
+
+        Ok(())
+    }
+
+    /// Returns the number of keys
+    fn get_num_keys(&self) -> usize {
+        NUM_KEYS
+    }
+
+    /// Returns the number of rows (vertical number of keys)
+    fn get_num_rows(&self) -> usize {
+        NUM_ROWS
+    }
+
+    /// Returns the number of columns (horizontal number of keys)
+    fn get_num_cols(&self) -> usize {
+        NUM_COLS
+    }
+
+    /// Returns the indices of the keys in row `row`
+    fn get_row_topology(&self, row: usize) -> &'static [u8] {
+        let idx = row * NUM_COLS;
+        &ROWS_TOPOLOGY[idx..(idx + NUM_COLS + 1)]
+    }
+
+    /// Returns the indices of the keys in column `col`
+    fn get_col_topology(&self, col: usize) -> &'static [u8] {
+        let idx = col * NUM_ROWS;
+        &COLS_TOPOLOGY[idx..(idx + NUM_ROWS + 1)]
+    }
+}
@@ -0,0 +1,207 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use log::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{any::Any, collections::HashMap};
+
+use crate::hwdevices::DeviceStatus;
+
+use super::virtual_device::{
+    self, VirtualDeviceClass, VirtualDeviceState, VIRTUAL_MISC_USB_PID, VIRTUAL_USB_VID,
+};
+use super::{
+    DeviceCapabilities, DeviceInfoTrait, DeviceTrait, MiscDeviceTrait, MouseDeviceTrait, RGBA,
+};
+
+pub type Result<T> = super::Result<T>;
+
+#[derive(Clone)]
+/// A virtual LED-only device.
+/// It is controlled by and may be inspected via a UNIX domain socket
+pub struct VirtualMisc {
+    pub state: Arc<VirtualDeviceState>,
+}
+
+impl VirtualMisc {
+    /// Binds the driver to the supplied control socket
+    pub fn bind(name: &str, socket_path: PathBuf) -> Self {
+        info!("Bound driver: Virtual Misc Device");
+
+        Self {
+            state: Arc::new(VirtualDeviceState::new(
+                VirtualDeviceClass::Misc,
+                name,
+                &socket_path,
+            )),
+        }
+    }
+}
+
+impl DeviceInfoTrait for VirtualMisc {
+    fn get_device_capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities::from([])
+    }
+
+    fn get_device_info(&self) -> Result<super::DeviceInfo> {
+        trace!("Querying the device for information...");
+
+        let result = super::DeviceInfo::new(0);
+        Ok(result)
+    }
+
+    fn get_firmware_revision(&self) -> String {
+        "<virtual>".to_string()
+    }
+}
+
+impl DeviceTrait for VirtualMisc {
+    fn get_usb_path(&self) -> String {
+        self.state.socket_path.to_string_lossy().to_string()
+    }
+
+    fn get_usb_vid(&self) -> u16 {
+        VIRTUAL_USB_VID
+    }
+
+    fn get_usb_pid(&self) -> u16 {
+        VIRTUAL_MISC_USB_PID
+    }
+
+    fn get_serial(&self) -> Option<&str> {
+        Some(&self.state.name)
+    }
+
+    fn get_support_script_file(&self) -> String {
+        "misc/custom_serial_leds".to_string()
+    }
+
+    fn open(&mut self, _api: &hidapi::HidApi) -> Result<()> {
+        trace!("Opening devices now...");
+
+        virtual_device::start_control_socket(self.state.clone())
+    }
+
+    fn close_all(&mut self) -> Result<()> {
+        trace!("Closing devices now...");
+
+        Ok(())
+    }
+
+    fn has_failed(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn send_init_sequence(&mut self) -> Result<()> {
+        trace!("Sending device init sequence...");
+
+        Ok(())
+    }
+
+    fn is_initialized(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn write_data_raw(&self, _buf: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_data_raw(&self, size: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        buf.resize(size, 0);
+
+        Ok(buf)
+    }
+
+    fn device_status(&self) -> Result<DeviceStatus> {
+        let mut table = HashMap::new();
+
+        table.insert("connected".to_owned(), format!("{}", true));
+        table.insert("frames".to_owned(), format!("{}", self.state.frame_count()));
+
+        Ok(DeviceStatus(table))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_device(&self) -> &dyn DeviceTrait {
+        self
+    }
+
+    fn as_device_mut(&mut self) -> &mut dyn DeviceTrait {
+        self
+    }
+
+    fn as_mouse_device(&self) -> Option<&dyn MouseDeviceTrait> {
+        None
+    }
+
+    fn as_mouse_device_mut(&mut self) -> Option<&mut dyn MouseDeviceTrait> {
+        None
+    }
+}
+
+impl MiscDeviceTrait for VirtualMisc {
+    fn has_input_device(&self) -> bool {
+        false
+    }
+
+    fn set_local_brightness(&mut self, brightness: i32) -> Result<()> {
+        trace!("Setting device specific brightness");
+
+        self.state.set_brightness(brightness);
+
+        Ok(())
+    }
+
+    fn get_local_brightness(&self) -> Result<i32> {
+        trace!("Querying device specific brightness");
+
+        Ok(self.state.get_brightness())
+    }
+
+    fn send_led_map(&mut self, led_map: &[RGBA]) -> Result<()> {
+        trace!("Setting LEDs from supplied map...");
+
+        self.state.record_frame(led_map);
+
+        Ok(())
+    }
+
+    fn set_led_init_pattern(&mut self) -> Result<()> {
+        trace!("Setting LED init pattern...");
+
+        Ok(())
+    }
+
+    fn set_led_off_pattern(&mut self) -> Result<()> {
+        trace!("Setting LED off pattern...");
+
+        Ok(())
+    }
+}
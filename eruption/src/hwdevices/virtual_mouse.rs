@@ -0,0 +1,306 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use evdev_rs::enums::EV_KEY;
+use log::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{any::Any, collections::HashMap};
+
+use crate::hwdevices::DeviceStatus;
+
+use super::generic_mouse;
+use super::virtual_device::{
+    self, ScriptedHidEvent, VirtualDeviceClass, VirtualDeviceState, VIRTUAL_MOUSE_USB_PID,
+    VIRTUAL_USB_VID,
+};
+use super::{
+    Capability, DeviceCapabilities, DeviceInfoTrait, DeviceTrait, HwDeviceError, MouseDeviceTrait,
+    MouseHidEvent, RGBA,
+};
+
+pub type Result<T> = super::Result<T>;
+
+#[derive(Clone)]
+/// A virtual mouse device, using the button mapping of the generic mouse driver.
+/// It is controlled by and may be inspected via a UNIX domain socket
+pub struct VirtualMouse {
+    pub state: Arc<VirtualDeviceState>,
+}
+
+impl VirtualMouse {
+    /// Binds the driver to the supplied control socket
+    pub fn bind(name: &str, socket_path: PathBuf) -> Self {
+        info!("Bound driver: Virtual Mouse");
+
+        Self {
+            state: Arc::new(VirtualDeviceState::new(
+                VirtualDeviceClass::Mouse,
+                name,
+                &socket_path,
+            )),
+        }
+    }
+}
+
+impl DeviceInfoTrait for VirtualMouse {
+    fn get_device_capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities::from([Capability::Mouse, Capability::RgbLighting])
+    }
+
+    fn get_device_info(&self) -> Result<super::DeviceInfo> {
+        trace!("Querying the device for information...");
+
+        let result = super::DeviceInfo::new(0);
+        Ok(result)
+    }
+
+    fn get_firmware_revision(&self) -> String {
+        "<virtual>".to_string()
+    }
+}
+
+impl DeviceTrait for VirtualMouse {
+    fn get_usb_path(&self) -> String {
+        self.state.socket_path.to_string_lossy().to_string()
+    }
+
+    fn get_usb_vid(&self) -> u16 {
+        VIRTUAL_USB_VID
+    }
+
+    fn get_usb_pid(&self) -> u16 {
+        VIRTUAL_MOUSE_USB_PID
+    }
+
+    fn get_serial(&self) -> Option<&str> {
+        Some(&self.state.name)
+    }
+
+    fn get_support_script_file(&self) -> String {
+        "mice/generic_mouse".to_string()
+    }
+
+    fn open(&mut self, _api: &hidapi::HidApi) -> Result<()> {
+        trace!("Opening devices now...");
+
+        virtual_device::start_control_socket(self.state.clone())
+    }
+
+    fn close_all(&mut self) -> Result<()> {
+        trace!("Closing devices now...");
+
+        Ok(())
+    }
+
+    fn send_init_sequence(&mut self) -> Result<()> {
+        trace!("Sending device init sequence...");
+
+        Ok(())
+    }
+
+    fn is_initialized(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn has_failed(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn write_data_raw(&self, _buf: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_data_raw(&self, size: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        buf.resize(size, 0);
+
+        Ok(buf)
+    }
+
+    fn device_status(&self) -> Result<DeviceStatus> {
+        let mut table = HashMap::new();
+
+        table.insert("connected".to_owned(), format!("{}", true));
+        table.insert("frames".to_owned(), format!("{}", self.state.frame_count()));
+
+        Ok(DeviceStatus(table))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_device(&self) -> &dyn DeviceTrait {
+        self
+    }
+
+    fn as_device_mut(&mut self) -> &mut dyn DeviceTrait {
+        self
+    }
+
+    fn as_mouse_device(&self) -> Option<&dyn MouseDeviceTrait> {
+        Some(self as &dyn MouseDeviceTrait)
+    }
+
+    fn as_mouse_device_mut(&mut self) -> Option<&mut dyn MouseDeviceTrait> {
+        Some(self as &mut dyn MouseDeviceTrait)
+    }
+}
+
+impl MouseDeviceTrait for VirtualMouse {
+    fn get_profile(&self) -> Result<i32> {
+        trace!("Querying device profile config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn set_profile(&mut self, _profile: i32) -> Result<()> {
+        trace!("Setting device profile config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn get_dpi(&self) -> Result<i32> {
+        trace!("Querying device DPI config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn set_dpi(&mut self, _dpi: i32) -> Result<()> {
+        trace!("Setting device DPI config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn get_rate(&self) -> Result<i32> {
+        trace!("Querying device poll rate config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn set_rate(&mut self, _rate: i32) -> Result<()> {
+        trace!("Setting device poll rate config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn get_dcu_config(&self) -> Result<i32> {
+        trace!("Querying device DCU config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn set_dcu_config(&mut self, _dcu: i32) -> Result<()> {
+        trace!("Setting device DCU config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn get_angle_snapping(&self) -> Result<bool> {
+        trace!("Querying device angle-snapping config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn set_angle_snapping(&mut self, _angle_snapping: bool) -> Result<()> {
+        trace!("Setting device angle-snapping config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn get_debounce(&self) -> Result<bool> {
+        trace!("Querying device debounce config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn set_debounce(&mut self, _debounce: bool) -> Result<()> {
+        trace!("Setting device debounce config");
+
+        Err(HwDeviceError::OpNotSupported {}.into())
+    }
+
+    fn get_local_brightness(&self) -> Result<i32> {
+        trace!("Querying device specific brightness");
+
+        Ok(self.state.get_brightness())
+    }
+
+    fn set_local_brightness(&mut self, brightness: i32) -> Result<()> {
+        trace!("Setting device specific brightness");
+
+        self.state.set_brightness(brightness);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn get_next_event(&self) -> Result<MouseHidEvent> {
+        self.get_next_event_timeout(-1)
+    }
+
+    fn get_next_event_timeout(&self, millis: i32) -> Result<MouseHidEvent> {
+        trace!("Querying control device for next event");
+
+        match self.state.next_hid_event(millis) {
+            Some(ScriptedHidEvent::Mouse(event)) => Ok(event),
+
+            _ => Ok(MouseHidEvent::Unknown),
+        }
+    }
+
+    fn ev_key_to_button_index(&self, code: EV_KEY) -> Result<u8> {
+        generic_mouse::ev_key_to_button_index(code)
+    }
+
+    fn button_index_to_ev_key(&self, index: u32) -> Result<EV_KEY> {
+        generic_mouse::button_index_to_ev_key(index)
+    }
+
+    fn send_led_map(&mut self, led_map: &[RGBA]) -> Result<()> {
+        trace!("Setting LEDs from supplied map...");
+
+        self.state.record_frame(led_map);
+
+        Ok(())
+    }
+
+    fn set_led_init_pattern(&mut self) -> Result<()> {
+        trace!("Setting LED init pattern...");
+
+        Ok(())
+    }
+
+    fn set_led_off_pattern(&mut self) -> Result<()> {
+        trace!("Setting LED off pattern...");
+
+        Ok(())
+    }
+
+    fn has_secondary_device(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,956 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use super::table::{Action, EvdevEvent, Event, KeyMappingTable};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// The layer that is active after a keymap table has been loaded
+pub const BASE_LAYER: usize = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EngineError {
+    #[error("Unsupported source of rule '{description}': {event}")]
+    UnsupportedSource { description: String, event: String },
+}
+
+/// Make sure that the keymap table `table` can be executed by the engine. The engine
+/// only processes key-down events of keyboards, rules for any other source are rejected
+pub fn validate(table: &KeyMappingTable) -> Result<()> {
+    for (source, rule) in table.mappings.iter() {
+        if !rule.enabled {
+            continue;
+        }
+
+        match source.event {
+            Event::Null | Event::SimpleKeyDown(_) => {}
+
+            _ => {
+                return Err(EngineError::UnsupportedSource {
+                    description: rule.description.clone(),
+                    event: format!("{:?}", source.event),
+                }
+                .into())
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A key event that shall be injected into the virtual keyboard
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub code: u32,
+    pub down: bool,
+}
+
+/// The state of a key that is currently held down
+#[derive(Debug, Clone)]
+enum Held {
+    /// The key is not remapped, it is mirrored to the virtual keyboard as is
+    PassThrough,
+
+    /// The key has been resolved to `action`, that will be released when the key is released
+    Action {
+        action: Action,
+        one_shot_modifiers: Vec<u32>,
+    },
+
+    /// A tap-hold key that has neither been released nor held for long enough yet
+    TapHold {
+        tap: Action,
+        hold: Action,
+        deadline: Instant,
+    },
+
+    /// A one-shot modifier, that turns into a regular modifier if another key is
+    /// pressed while it is held down
+    OneShot { code: u32, interrupted: bool },
+
+    /// The key of a tap dance that has not been finished yet
+    TapDance,
+
+    /// A key of a pending or completed chord
+    Chord,
+}
+
+/// A chord, whose keys have not all been pressed yet
+#[derive(Debug, Clone)]
+struct PendingChord {
+    keys: Vec<usize>,
+    action: Action,
+    deadline: Instant,
+
+    /// The key indices and evdev key codes of the keys pressed so far
+    pressed: Vec<(usize, u32)>,
+}
+
+/// A completed chord, its action is released together with the first released key
+#[derive(Debug, Clone)]
+struct ActiveChord {
+    keys: Vec<usize>,
+    held: Held,
+}
+
+/// A tap dance that has not been finished yet
+#[derive(Debug, Clone)]
+struct PendingTapDance {
+    key_index: usize,
+    actions: Vec<Action>,
+    timeout: Duration,
+    deadline: Instant,
+
+    taps: usize,
+    pressed: bool,
+}
+
+/// Executes a keymap table: Resolves key events to actions, based on the active layers.
+///
+/// Layers are numbered starting at 1. The active layers are the default layer, all
+/// toggled layers and all momentary layers whose keys are held down. Each key is looked
+/// up in the active layers, starting with the highest one; if no rule exists on a layer,
+/// the next lower active layer is consulted. Chords on the active layers take precedence
+/// over the rules of their keys.
+/// Keys are always released with the action they have been pressed with, even if the
+/// active layers changed in the meantime
+#[derive(Debug)]
+pub struct Engine {
+    table: KeyMappingTable,
+
+    default_layer: usize,
+    toggled_layers: BTreeSet<usize>,
+    momentary_layers: BTreeMap<usize, usize>,
+
+    held: HashMap<usize, Held>,
+    armed_one_shots: Vec<u32>,
+
+    pending_chord: Option<PendingChord>,
+    active_chords: Vec<ActiveChord>,
+    pending_tap_dance: Option<PendingTapDance>,
+
+    output: Vec<Output>,
+    macros: Vec<String>,
+}
+
+impl Engine {
+    /// Create a new engine, executing the keymap table `table`
+    pub fn new(table: KeyMappingTable) -> Self {
+        Self {
+            table,
+
+            default_layer: BASE_LAYER,
+            toggled_layers: BTreeSet::new(),
+            momentary_layers: BTreeMap::new(),
+
+            held: HashMap::new(),
+            armed_one_shots: Vec::new(),
+
+            pending_chord: None,
+            active_chords: Vec::new(),
+            pending_tap_dance: None,
+
+            output: Vec::new(),
+            macros: Vec::new(),
+        }
+    }
+
+    /// Returns all currently active layers, the highest layer first
+    pub fn active_layers(&self) -> Vec<usize> {
+        let mut result = BTreeSet::new();
+
+        result.insert(self.default_layer);
+        result.extend(self.toggled_layers.iter());
+        result.extend(self.momentary_layers.keys());
+
+        result.into_iter().rev().collect()
+    }
+
+    /// Returns the key events that shall be injected, in order
+    pub fn take_output(&mut self) -> Vec<Output> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Returns the names of the macro files that shall be played back, in order
+    pub fn take_macros(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.macros)
+    }
+
+    /// Process a key event of the key `key_index` with the evdev key code `code` of the
+    /// device with the USB ID `usb_id`, where `value` is 0 for a release, 1 for a press
+    /// and 2 for an auto-repeat.
+    ///
+    /// Returns `true` if the event has been consumed, i.e. it must not be mirrored to the
+    /// virtual keyboard
+    pub fn process_key_event(
+        &mut self,
+        key_index: usize,
+        code: u32,
+        value: i32,
+        usb_id: (u16, u16),
+        now: Instant,
+    ) -> bool {
+        match value {
+            0 => self.key_up(key_index, now),
+            1 => self.key_down(key_index, code, usb_id, now),
+
+            // auto-repeat events of remapped keys are dropped
+            _ => !matches!(self.held.get(&key_index), None | Some(Held::PassThrough)),
+        }
+    }
+
+    /// Resolve pending tap-hold keys, chords and tap dances whose timeout expired
+    pub fn poll(&mut self, now: Instant) {
+        if matches!(&self.pending_chord, Some(chord) if chord.deadline <= now) {
+            self.abort_chord(now);
+        }
+
+        if matches!(&self.pending_tap_dance, Some(tap_dance) if tap_dance.deadline <= now) {
+            self.finish_tap_dance(now);
+        }
+
+        let expired = self
+            .held
+            .iter()
+            .filter_map(|(key_index, held)| match held {
+                Held::TapHold { deadline, .. } if *deadline <= now => Some(*key_index),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for key_index in expired {
+            self.resolve_hold(key_index, now);
+        }
+    }
+
+    fn key_down(&mut self, key_index: usize, code: u32, usb_id: (u16, u16), now: Instant) -> bool {
+        // a pending chord collects its keys, any other key aborts the chord
+        if let Some(chord) = self.pending_chord.as_mut() {
+            if chord.keys.contains(&key_index) {
+                chord.pressed.push((key_index, code));
+                self.held.insert(key_index, Held::Chord);
+
+                if chord.pressed.len() == chord.keys.len() {
+                    self.complete_chord(now);
+                }
+
+                return true;
+            } else {
+                self.abort_chord(now);
+            }
+        }
+
+        // repeated taps of a tap dance, any other key finishes the tap dance
+        if let Some(tap_dance) = self.pending_tap_dance.as_mut() {
+            if tap_dance.key_index == key_index {
+                tap_dance.taps += 1;
+                tap_dance.pressed = true;
+                tap_dance.deadline = now + tap_dance.timeout;
+
+                self.held.insert(key_index, Held::TapDance);
+
+                return true;
+            } else {
+                self.finish_tap_dance(now);
+            }
+        }
+
+        // pressing another key decides pending tap-hold keys in favor of "hold"
+        let pending = self
+            .held
+            .iter()
+            .filter_map(|(index, held)| match held {
+                Held::TapHold { .. } => Some(*index),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for index in pending {
+            self.resolve_hold(index, now);
+        }
+
+        // held one-shot modifiers act as regular modifiers, if another key is pressed
+        for held in self.held.values_mut() {
+            if let Held::OneShot { code, interrupted } = held {
+                if !*interrupted {
+                    *interrupted = true;
+
+                    self.output.push(Output {
+                        code: *code,
+                        down: true,
+                    });
+                }
+            }
+        }
+
+        if let Some((keys, action, timeout)) = self.find_chord(key_index, usb_id) {
+            self.pending_chord = Some(PendingChord {
+                keys,
+                action,
+                deadline: now + timeout,
+                pressed: vec![(key_index, code)],
+            });
+
+            self.held.insert(key_index, Held::Chord);
+
+            return true;
+        }
+
+        let action = match self.lookup(key_index, usb_id) {
+            Some(Action::TapDance(tap_dance)) => {
+                let timeout = Duration::from_millis(tap_dance.timeout_millis);
+
+                self.pending_tap_dance = Some(PendingTapDance {
+                    key_index,
+                    actions: tap_dance.actions,
+                    timeout,
+                    deadline: now + timeout,
+
+                    taps: 1,
+                    pressed: true,
+                });
+
+                self.held.insert(key_index, Held::TapDance);
+
+                return true;
+            }
+
+            Some(action) => action,
+
+            None if self.armed_one_shots.is_empty() => {
+                self.held.insert(key_index, Held::PassThrough);
+
+                return false;
+            }
+
+            // the key has to be injected, so that it will be modified by the one-shot modifiers
+            None => Action::InjectKey(EvdevEvent { event: code }),
+        };
+
+        let held = self.press(action, now);
+        self.held.insert(key_index, held);
+
+        true
+    }
+
+    fn key_up(&mut self, key_index: usize, now: Instant) -> bool {
+        // releasing a key of a pending chord aborts the chord
+        let is_pending_chord_key = self
+            .pending_chord
+            .as_ref()
+            .map(|chord| chord.pressed.iter().any(|(index, _)| *index == key_index))
+            .unwrap_or(false);
+
+        if is_pending_chord_key {
+            self.abort_chord(now);
+        }
+
+        match self.held.remove(&key_index) {
+            // the key has been pressed before the keymap was loaded
+            None => false,
+
+            Some(Held::PassThrough) => false,
+
+            Some(Held::TapDance) => {
+                if let Some(tap_dance) = self.pending_tap_dance.as_mut() {
+                    tap_dance.pressed = false;
+                    tap_dance.deadline = now + tap_dance.timeout;
+                }
+
+                true
+            }
+
+            Some(Held::Chord) => {
+                if let Some(pos) = self
+                    .active_chords
+                    .iter()
+                    .position(|chord| chord.keys.contains(&key_index))
+                {
+                    let chord = self.active_chords.remove(pos);
+
+                    self.release_held(chord.held, now);
+                }
+
+                true
+            }
+
+            Some(held) => {
+                self.release_held(held, now);
+
+                true
+            }
+        }
+    }
+
+    /// Resolve the action of the key `key_index` on the active layers
+    fn lookup(&self, key_index: usize, usb_id: (u16, u16)) -> Option<Action> {
+        for layer in self.active_layers() {
+            let rule = self.table.mappings.iter().find(|(source, rule)| {
+                rule.enabled
+                    && source.layers.0.contains(&layer)
+                    && match &source.event {
+                        Event::SimpleKeyDown(key) => key.matches(key_index, usb_id),
+
+                        _ => false,
+                    }
+            });
+
+            if let Some((_source, rule)) = rule {
+                return match &rule.action {
+                    // disabled rules and macros are left to the Lua scripts
+                    Action::Null | Action::Call(_) => None,
+
+                    action => Some(action.clone()),
+                };
+            }
+        }
+
+        None
+    }
+
+    /// Find a chord on the active layers that contains the key `key_index`. Returns the
+    /// key indices of all keys of the chord, its action and its timeout
+    fn find_chord(
+        &self,
+        key_index: usize,
+        usb_id: (u16, u16),
+    ) -> Option<(Vec<usize>, Action, Duration)> {
+        for layer in self.active_layers() {
+            for (source, rule) in self.table.mappings.iter() {
+                if !rule.enabled || !source.layers.0.contains(&layer) {
+                    continue;
+                }
+
+                if let (Event::SimpleKeyDown(key), Action::Chord(chord)) =
+                    (&source.event, &rule.action)
+                {
+                    if !key.matches(key.key_index, usb_id) {
+                        continue;
+                    }
+
+                    let mut keys = vec![key.key_index];
+
+                    for other in chord.keys.iter() {
+                        if other.matches(other.key_index, usb_id)
+                            && !keys.contains(&other.key_index)
+                        {
+                            keys.push(other.key_index);
+                        }
+                    }
+
+                    if keys.contains(&key_index) {
+                        return Some((
+                            keys,
+                            *chord.action.clone(),
+                            Duration::from_millis(chord.timeout_millis),
+                        ));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    fn press(&mut self, action: Action, now: Instant) -> Held {
+        let mut one_shot_modifiers = Vec::new();
+
+        match &action {
+            Action::InjectKey(event) => {
+                // apply armed one-shot modifiers to the next key
+                one_shot_modifiers = std::mem::take(&mut self.armed_one_shots);
+
+                for code in one_shot_modifiers.iter() {
+                    self.output.push(Output {
+                        code: *code,
+                        down: true,
+                    });
+                }
+
+                self.output.push(Output {
+                    code: event.event,
+                    down: true,
+                });
+            }
+
+            Action::SwitchLayer(layer) => {
+                self.default_layer = *layer;
+                self.toggled_layers.clear();
+            }
+
+            Action::ToggleLayer(layer) => {
+                if !self.toggled_layers.remove(layer) {
+                    self.toggled_layers.insert(*layer);
+                }
+            }
+
+            Action::MomentaryLayer(layer) => {
+                *self.momentary_layers.entry(*layer).or_insert(0) += 1;
+            }
+
+            Action::TapHold(tap_hold) => {
+                return Held::TapHold {
+                    tap: *tap_hold.tap.clone(),
+                    hold: *tap_hold.hold.clone(),
+                    deadline: now + Duration::from_millis(tap_hold.timeout_millis),
+                };
+            }
+
+            Action::OneShot(event) => {
+                return Held::OneShot {
+                    code: event.event,
+                    interrupted: false,
+                };
+            }
+
+            Action::PlayMacro(name) => {
+                self.macros.push(name.clone());
+            }
+
+            // chords and tap dances are only supported as top-level actions
+            Action::Null | Action::Call(_) | Action::Chord(_) | Action::TapDance(_) => {
+                /* do nothing */
+            }
+        }
+
+        Held::Action {
+            action,
+            one_shot_modifiers,
+        }
+    }
+
+    fn release(&mut self, action: &Action) {
+        match action {
+            Action::InjectKey(event) => {
+                self.output.push(Output {
+                    code: event.event,
+                    down: false,
+                });
+            }
+
+            Action::MomentaryLayer(layer) => {
+                if let Some(count) = self.momentary_layers.get_mut(layer) {
+                    *count -= 1;
+
+                    if *count == 0 {
+                        self.momentary_layers.remove(layer);
+                    }
+                }
+            }
+
+            _ => { /* do nothing */ }
+        }
+    }
+
+    /// Release a key that has been resolved to `held`
+    fn release_held(&mut self, held: Held, now: Instant) {
+        match held {
+            Held::Action {
+                action,
+                one_shot_modifiers,
+            } => {
+                self.release(&action);
+
+                for code in one_shot_modifiers.into_iter().rev() {
+                    self.output.push(Output { code, down: false });
+                }
+            }
+
+            Held::TapHold { tap, .. } => {
+                let tap = Self::simple_action(tap);
+
+                if let Held::Action {
+                    action,
+                    one_shot_modifiers,
+                } = self.press(tap, now)
+                {
+                    self.release(&action);
+
+                    for code in one_shot_modifiers.into_iter().rev() {
+                        self.output.push(Output { code, down: false });
+                    }
+                }
+            }
+
+            Held::OneShot { code, interrupted } => {
+                if interrupted {
+                    self.output.push(Output { code, down: false });
+                } else if !self.armed_one_shots.contains(&code) {
+                    self.armed_one_shots.push(code);
+                }
+            }
+
+            Held::PassThrough | Held::TapDance | Held::Chord => { /* do nothing */ }
+        }
+    }
+
+    fn resolve_hold(&mut self, key_index: usize, now: Instant) {
+        if let Some(Held::TapHold { hold, .. }) = self.held.remove(&key_index) {
+            let held = self.press(Self::simple_action(hold), now);
+            self.held.insert(key_index, held);
+        }
+    }
+
+    /// All keys of the pending chord have been pressed, execute its action
+    fn complete_chord(&mut self, now: Instant) {
+        if let Some(chord) = self.pending_chord.take() {
+            let held = self.press(Self::simple_action(chord.action), now);
+
+            self.active_chords.push(ActiveChord {
+                keys: chord.pressed.iter().map(|(index, _)| *index).collect(),
+                held,
+            });
+        }
+    }
+
+    /// The pending chord has not been completed, replay its keys
+    fn abort_chord(&mut self, now: Instant) {
+        if let Some(chord) = self.pending_chord.take() {
+            for (key_index, code) in chord.pressed {
+                let held = self.press(Action::InjectKey(EvdevEvent { event: code }), now);
+                self.held.insert(key_index, held);
+            }
+        }
+    }
+
+    /// Execute the action of the pending tap dance, that has been selected by the number of taps.
+    /// If the key is still held down, the action will be released together with the key
+    fn finish_tap_dance(&mut self, now: Instant) {
+        if let Some(tap_dance) = self.pending_tap_dance.take() {
+            let action = tap_dance
+                .actions
+                .get(
+                    tap_dance
+                        .taps
+                        .min(tap_dance.actions.len())
+                        .saturating_sub(1),
+                )
+                .cloned()
+                .unwrap_or(Action::Null);
+
+            let held = self.press(Self::simple_action(action), now);
+
+            if tap_dance.pressed {
+                self.held.insert(tap_dance.key_index, held);
+            } else {
+                self.release_held(held, now);
+            }
+        }
+    }
+
+    /// Nested tap-hold keys, chords and tap dances are not supported
+    fn simple_action(action: Action) -> Action {
+        match action {
+            Action::TapHold(_) | Action::Chord(_) | Action::TapDance(_) => Action::Null,
+            action => action,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, Engine, Output};
+    use crate::keymap::table::{Action, EvdevEvent, Event, Key, KeyMappingTable, Rule, Source};
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+
+    const KEY_A: u32 = 30;
+    const KEY_B: u32 = 48;
+    const KEY_LEFTSHIFT: u32 = 42;
+    const KEY_LEFTCTRL: u32 = 29;
+    const KEY_ESC: u32 = 1;
+
+    // the USB ID of the device of the test table
+    const USB_ID: (u16, u16) = (0x1e7d, 0x3098);
+
+    // key indices of the test table
+    const CAPS_LOCK: usize = 10;
+    const FN: usize = 20;
+    const TOGGLE: usize = 21;
+    const SWITCH: usize = 22;
+    const SHIFT: usize = 23;
+    const LETTER_A: usize = 30;
+    const LETTER_B: usize = 31;
+    const LETTER_C: usize = 32;
+    const CHORD_J: usize = 40;
+    const CHORD_K: usize = 41;
+    const TAP_DANCE: usize = 50;
+    const PLAY_MACRO: usize = 60;
+
+    const KEY_J: u32 = 36;
+    const KEY_K: u32 = 37;
+
+    fn test_engine() -> Engine {
+        let table =
+            KeyMappingTable::from_str(include_str!("../../../support/tests/assets/layers.keymap"))
+                .unwrap();
+
+        Engine::new(table)
+    }
+
+    fn key(engine: &mut Engine, key_index: usize, code: u32, value: i32, now: Instant) -> bool {
+        engine.process_key_event(key_index, code, value, USB_ID, now)
+    }
+
+    fn down(code: u32) -> Output {
+        Output { code, down: true }
+    }
+
+    fn up(code: u32) -> Output {
+        Output { code, down: false }
+    }
+
+    #[test]
+    fn momentary_and_toggled_layers() {
+        let mut engine = test_engine();
+        let now = Instant::now();
+
+        // keys with disabled rules or rules of other devices are mirrored
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 1, now));
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 0, now));
+        assert!(!key(&mut engine, LETTER_C, 46, 1, now));
+        assert!(!key(&mut engine, LETTER_C, 46, 0, now));
+
+        // FN + A is mapped to B on layer 2, the release of A must be mapped as well,
+        // even if FN has been released before
+        assert!(key(&mut engine, FN, 0, 1, now));
+        assert_eq!(engine.active_layers(), vec![2, 1]);
+        assert!(key(&mut engine, LETTER_A, KEY_A, 1, now));
+        assert!(key(&mut engine, FN, 0, 0, now));
+        assert_eq!(engine.active_layers(), vec![1]);
+        assert!(key(&mut engine, LETTER_A, KEY_A, 0, now));
+        assert_eq!(engine.take_output(), vec![down(KEY_B), up(KEY_B)]);
+
+        // A is not mapped on layer 1
+        assert!(!key(&mut engine, LETTER_A, KEY_A, 1, now));
+        assert!(!key(&mut engine, LETTER_A, KEY_A, 0, now));
+
+        // toggle layer 2 on and off again
+        key(&mut engine, TOGGLE, 0, 1, now);
+        key(&mut engine, TOGGLE, 0, 0, now);
+        assert_eq!(engine.active_layers(), vec![2, 1]);
+
+        key(&mut engine, TOGGLE, 0, 1, now);
+        key(&mut engine, TOGGLE, 0, 0, now);
+        assert_eq!(engine.active_layers(), vec![1]);
+
+        // switch the default layer
+        key(&mut engine, SWITCH, 0, 1, now);
+        key(&mut engine, SWITCH, 0, 0, now);
+        assert_eq!(engine.active_layers(), vec![3]);
+        assert!(engine.take_output().is_empty());
+    }
+
+    #[test]
+    fn tap_hold() {
+        let mut engine = test_engine();
+        let now = Instant::now();
+
+        // tap: caps lock acts as escape
+        assert!(key(&mut engine, CAPS_LOCK, 58, 1, now));
+        engine.poll(now + Duration::from_millis(100));
+        assert!(engine.take_output().is_empty());
+        assert!(key(
+            &mut engine,
+            CAPS_LOCK,
+            58,
+            0,
+            now + Duration::from_millis(150)
+        ));
+        assert_eq!(engine.take_output(), vec![down(KEY_ESC), up(KEY_ESC)]);
+
+        // hold: caps lock acts as control, after the timeout expired
+        key(&mut engine, CAPS_LOCK, 58, 1, now);
+        engine.poll(now + Duration::from_millis(250));
+        assert_eq!(engine.take_output(), vec![down(KEY_LEFTCTRL)]);
+        key(
+            &mut engine,
+            CAPS_LOCK,
+            58,
+            0,
+            now + Duration::from_millis(300),
+        );
+        assert_eq!(engine.take_output(), vec![up(KEY_LEFTCTRL)]);
+
+        // hold: pressing another key before the timeout expired
+        key(&mut engine, CAPS_LOCK, 58, 1, now);
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 1, now));
+        assert_eq!(engine.take_output(), vec![down(KEY_LEFTCTRL)]);
+    }
+
+    #[test]
+    fn one_shot_modifiers() {
+        let mut engine = test_engine();
+        let now = Instant::now();
+
+        // tapping shift applies it to the next key only
+        assert!(key(&mut engine, SHIFT, KEY_LEFTSHIFT, 1, now));
+        assert!(key(&mut engine, SHIFT, KEY_LEFTSHIFT, 0, now));
+        assert!(engine.take_output().is_empty());
+
+        assert!(key(&mut engine, LETTER_B, KEY_B, 1, now));
+        assert!(key(&mut engine, LETTER_B, KEY_B, 2, now));
+        assert!(key(&mut engine, LETTER_B, KEY_B, 0, now));
+        assert_eq!(
+            engine.take_output(),
+            vec![
+                down(KEY_LEFTSHIFT),
+                down(KEY_B),
+                up(KEY_B),
+                up(KEY_LEFTSHIFT)
+            ]
+        );
+
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 1, now));
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 0, now));
+
+        // holding shift while pressing another key acts like a regular modifier
+        key(&mut engine, SHIFT, KEY_LEFTSHIFT, 1, now);
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 1, now));
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 0, now));
+        key(&mut engine, SHIFT, KEY_LEFTSHIFT, 0, now);
+        assert_eq!(
+            engine.take_output(),
+            vec![down(KEY_LEFTSHIFT), up(KEY_LEFTSHIFT)]
+        );
+
+        key(&mut engine, LETTER_B, KEY_B, 1, now);
+        assert!(engine.take_output().is_empty());
+    }
+
+    #[test]
+    fn chords() {
+        let mut engine = test_engine();
+        let now = Instant::now();
+
+        // the keys of a chord may be pressed in any order, the action is released
+        // together with the first released key
+        assert!(key(&mut engine, CHORD_K, KEY_K, 1, now));
+        assert!(engine.take_output().is_empty());
+        assert!(key(&mut engine, CHORD_J, KEY_J, 1, now));
+        assert_eq!(engine.take_output(), vec![down(KEY_ESC)]);
+        assert!(key(&mut engine, CHORD_J, KEY_J, 2, now));
+        assert!(key(&mut engine, CHORD_J, KEY_J, 0, now));
+        assert_eq!(engine.take_output(), vec![up(KEY_ESC)]);
+        assert!(key(&mut engine, CHORD_K, KEY_K, 0, now));
+        assert!(engine.take_output().is_empty());
+
+        // timeout: the pressed keys are replayed
+        key(&mut engine, CHORD_J, KEY_J, 1, now);
+        engine.poll(now + Duration::from_millis(100));
+        assert_eq!(engine.take_output(), vec![down(KEY_J)]);
+        assert!(key(&mut engine, CHORD_J, KEY_J, 0, now));
+        assert_eq!(engine.take_output(), vec![up(KEY_J)]);
+
+        // pressing another key aborts the chord
+        key(&mut engine, CHORD_J, KEY_J, 1, now);
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 1, now));
+        assert_eq!(engine.take_output(), vec![down(KEY_J)]);
+        key(&mut engine, CHORD_J, KEY_J, 0, now);
+        key(&mut engine, LETTER_B, KEY_B, 0, now);
+        assert_eq!(engine.take_output(), vec![up(KEY_J)]);
+
+        // releasing a key before the chord has been completed aborts the chord
+        key(&mut engine, CHORD_K, KEY_K, 1, now);
+        key(&mut engine, CHORD_K, KEY_K, 0, now);
+        assert_eq!(engine.take_output(), vec![down(KEY_K), up(KEY_K)]);
+    }
+
+    #[test]
+    fn tap_dance() {
+        let mut engine = test_engine();
+        let now = Instant::now();
+
+        // single tap
+        assert!(key(&mut engine, TAP_DANCE, 0, 1, now));
+        assert!(key(&mut engine, TAP_DANCE, 0, 0, now));
+        engine.poll(now + Duration::from_millis(100));
+        assert!(engine.take_output().is_empty());
+        engine.poll(now + Duration::from_millis(250));
+        assert_eq!(engine.take_output(), vec![down(KEY_A), up(KEY_A)]);
+
+        // double tap, the key is held down after the second tap
+        key(&mut engine, TAP_DANCE, 0, 1, now);
+        key(&mut engine, TAP_DANCE, 0, 0, now);
+        key(
+            &mut engine,
+            TAP_DANCE,
+            0,
+            1,
+            now + Duration::from_millis(100),
+        );
+        engine.poll(now + Duration::from_millis(350));
+        assert_eq!(engine.take_output(), vec![down(KEY_B)]);
+        key(
+            &mut engine,
+            TAP_DANCE,
+            0,
+            0,
+            now + Duration::from_millis(400),
+        );
+        assert_eq!(engine.take_output(), vec![up(KEY_B)]);
+
+        // pressing another key finishes the tap dance
+        key(&mut engine, TAP_DANCE, 0, 1, now);
+        key(&mut engine, TAP_DANCE, 0, 0, now);
+        assert!(!key(&mut engine, LETTER_B, KEY_B, 1, now));
+        assert_eq!(engine.take_output(), vec![down(KEY_A), up(KEY_A)]);
+    }
+
+    #[test]
+    fn play_macro() {
+        let mut engine = test_engine();
+        let now = Instant::now();
+
+        assert!(key(&mut engine, PLAY_MACRO, 0, 1, now));
+        assert!(key(&mut engine, PLAY_MACRO, 0, 0, now));
+
+        assert_eq!(engine.take_macros(), vec!["example".to_string()]);
+        assert!(engine.take_output().is_empty());
+    }
+
+    #[test]
+    fn unsupported_sources() {
+        assert!(validate(&test_engine().table).is_ok());
+
+        let key = Key::new(LETTER_A, USB_ID);
+
+        for event in [
+            Event::SimpleKeyUp(key.clone()),
+            Event::HidKeyDown(key.clone()),
+            Event::EasyShiftKeyDown(key.clone()),
+            Event::SimpleMouseDown(key),
+        ] {
+            let mut table = KeyMappingTable::new();
+            let action = Action::InjectKey(EvdevEvent { event: KEY_B });
+
+            table.insert(
+                Source::new(event.clone()),
+                Rule::new(action, "A to B", true),
+            );
+
+            assert!(validate(&table).is_err(), "{:?} must be rejected", event);
+        }
+    }
+}
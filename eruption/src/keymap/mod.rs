@@ -0,0 +1,158 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use config::{Config, ConfigError};
+use lazy_static::lazy_static;
+use log::*;
+use parking_lot::RwLock;
+
+use crate::{constants, macro_player, plugins::macros};
+
+pub mod engine;
+pub mod table;
+
+pub use engine::Engine;
+pub use table::KeyMappingTable;
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+lazy_static! {
+    /// The keymap table that is executed natively by the daemon, if any
+    pub static ref KEYMAP_ENGINE: Arc<RwLock<Option<Engine>>> = Arc::new(RwLock::new(None));
+
+    /// The path of the currently loaded keymap table
+    pub static ref KEYMAP_FILE: Arc<RwLock<Option<PathBuf>>> = Arc::new(RwLock::new(None));
+}
+
+/// Load the keymap table specified by `global.keymap`. Relative paths are
+/// resolved against the keymaps directory
+pub fn load_keymap(config: &Config) {
+    match config.get::<String>("global.keymap") {
+        Ok(file_name) => {
+            let path = Path::new(constants::DEFAULT_KEYMAP_DIR).join(file_name);
+
+            match load_table(&path) {
+                Ok(table) => {
+                    info!(
+                        "Loaded keymap table {} with {} rule(s)",
+                        path.display(),
+                        table.mappings.len()
+                    );
+
+                    *KEYMAP_ENGINE.write() = Some(Engine::new(table));
+                    *KEYMAP_FILE.write() = Some(path);
+                }
+
+                Err(e) => error!("Could not load keymap table {}: {}", path.display(), e),
+            }
+        }
+
+        Err(ConfigError::NotFound(_)) => {
+            debug!("No keymap table specified, native key remapping is disabled");
+        }
+
+        Err(e) => error!("Could not parse keymap configuration: {}", e),
+    }
+}
+
+/// Load the keymap table `path`, and make sure that it can be executed by the engine
+fn load_table(path: &Path) -> Result<KeyMappingTable> {
+    let table = KeyMappingTable::from_file(path)?;
+
+    engine::validate(&table)?;
+
+    Ok(table)
+}
+
+/// Reload the keymap table, if `path` refers to the currently loaded table.
+/// All layer state is reset
+pub fn reload_keymap(path: &Path) -> Result<()> {
+    let keymap_file = KEYMAP_FILE.read().clone();
+
+    if let Some(keymap_file) = keymap_file {
+        if same_file::is_same_file(path, &keymap_file).unwrap_or(false) {
+            let table = load_table(&keymap_file)?;
+
+            info!(
+                "Reloaded keymap table {} with {} rule(s)",
+                keymap_file.display(),
+                table.mappings.len()
+            );
+
+            *KEYMAP_ENGINE.write() = Some(Engine::new(table));
+        }
+    }
+
+    Ok(())
+}
+
+/// Process a key event of the key `key_index` with the evdev key code `code`, of
+/// the device with the USB ID `usb_id`.
+///
+/// Returns `true` if the event has been consumed by the keymap engine, so that it
+/// must not be mirrored to the virtual keyboard
+pub fn process_key_event(key_index: usize, code: u32, value: i32, usb_id: (u16, u16)) -> bool {
+    let mut engine = KEYMAP_ENGINE.write();
+
+    if let Some(engine) = engine.as_mut() {
+        let result = engine.process_key_event(key_index, code, value, usb_id, Instant::now());
+
+        inject_output(engine);
+
+        result
+    } else {
+        false
+    }
+}
+
+/// Resolve pending tap-hold keys, this has to be called periodically
+pub fn poll() {
+    let mut engine = KEYMAP_ENGINE.write();
+
+    if let Some(engine) = engine.as_mut() {
+        engine.poll(Instant::now());
+
+        inject_output(engine);
+    }
+}
+
+fn inject_output(engine: &mut Engine) {
+    for output in engine.take_output() {
+        macros::UINPUT_TX
+            .read()
+            .as_ref()
+            .unwrap()
+            .send(macros::Message::InjectKey {
+                key: output.code,
+                down: output.down,
+            })
+            .unwrap_or_else(|e| error!("Could not inject a remapped key: {}", e));
+    }
+
+    for name in engine.take_macros() {
+        macro_player::play_macro(&name)
+            .unwrap_or_else(|e| error!("Could not play back the macro {}: {}", name, e));
+    }
+}
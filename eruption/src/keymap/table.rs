@@ -0,0 +1,351 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! The file format of keymap tables. This file is shared between the daemon, that
+//! executes keymap tables natively, and `eruption-keymap`, that is used to edit them
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_any_key::any_key_map;
+
+type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub struct KeyMappingTable {
+    pub metadata: TableMetadata,
+
+    #[serde(with = "any_key_map")]
+    pub mappings: BTreeMap<Source, Rule>,
+}
+
+#[allow(unused)]
+impl KeyMappingTable {
+    pub fn new() -> Self {
+        Self {
+            metadata: TableMetadata::default(),
+            mappings: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read_to_string(path.as_ref())?;
+
+        Self::from_str(&data)
+    }
+
+    pub fn file_name(&self) -> &Path {
+        &self.metadata.file_name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.metadata.description
+    }
+
+    pub fn set_file_name<P: AsRef<Path>>(&mut self, file_name: P) {
+        self.metadata.file_name = file_name.as_ref().to_path_buf();
+    }
+
+    pub fn set_description(&mut self, description: &str) {
+        self.metadata.description = description.to_string();
+    }
+
+    pub fn insert(&mut self, source: Source, rule: Rule) -> Option<Rule> {
+        self.mappings.insert(source, rule)
+    }
+
+    pub fn remove(&mut self, source: &Source) -> Option<Rule> {
+        self.mappings.remove(source)
+    }
+
+    pub fn metadata(&self) -> &TableMetadata {
+        &self.metadata
+    }
+
+    pub fn mappings(&self) -> &BTreeMap<Source, Rule> {
+        &self.mappings
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut TableMetadata {
+        &mut self.metadata
+    }
+
+    pub fn mappings_mut(&mut self) -> &mut BTreeMap<Source, Rule> {
+        &mut self.mappings
+    }
+}
+
+impl FromStr for KeyMappingTable {
+    type Err = eyre::Error;
+
+    fn from_str(data: &str) -> Result<Self> {
+        Ok(serde_json::from_str(data)?)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub struct TableMetadata {
+    pub file_name: PathBuf,
+    pub description: String,
+    pub creation_date: DateTime<Utc>,
+}
+
+#[allow(unused)]
+impl TableMetadata {
+    pub fn new(file_name: PathBuf, description: String, creation_date: DateTime<Utc>) -> Self {
+        TableMetadata {
+            file_name,
+            description,
+            creation_date,
+        }
+    }
+}
+
+impl Default for TableMetadata {
+    fn default() -> TableMetadata {
+        Self {
+            file_name: PathBuf::from("default.keymap"),
+            description: "<no description specified>".to_string(),
+            creation_date: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub struct Source {
+    pub event: Event,
+    pub layers: LayerSet<usize>,
+}
+
+#[allow(unused)]
+impl Source {
+    pub fn new(event: Event) -> Self {
+        let mut layers = BTreeSet::new();
+
+        layers.insert(1);
+
+        Self {
+            event,
+            layers: LayerSet(layers),
+        }
+    }
+
+    #[allow(unused)]
+    pub fn new_with_layers(event: Event, active_layers: &[usize]) -> Self {
+        let mut layers = BTreeSet::new();
+
+        layers.extend(active_layers.iter());
+
+        Self {
+            event,
+            layers: LayerSet(layers),
+        }
+    }
+
+    pub fn get_layers_mut(&mut self) -> &mut BTreeSet<usize> {
+        &mut self.layers.0
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct LayerSet<T>(pub BTreeSet<T>)
+where
+    T: Ord + PartialOrd;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub struct Rule {
+    pub description: String,
+    pub enabled: bool,
+    pub action: Action,
+}
+
+#[allow(unused)]
+impl Rule {
+    pub fn new(action: Action, description: &str, enabled: bool) -> Self {
+        Self {
+            description: description.to_owned(),
+            enabled,
+            action,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Null,
+    InjectKey(EvdevEvent),
+    Call(Macro),
+
+    /// Make `layer` the default layer, and turn off all toggled layers
+    SwitchLayer(usize),
+
+    /// Turn `layer` on or off
+    ToggleLayer(usize),
+
+    /// Turn `layer` on, while the key is held down
+    MomentaryLayer(usize),
+
+    /// Execute one action if the key is tapped and another one if it is held down
+    TapHold(TapHold),
+
+    /// A modifier that applies to the next key press only
+    OneShot(EvdevEvent),
+
+    /// Execute an action if all keys of the chord are pressed within a time window
+    Chord(Chord),
+
+    /// Execute a different action depending on the number of taps
+    TapDance(TapDance),
+
+    /// Play back a macro file, that has been recorded with `eruption-macro`
+    PlayMacro(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub struct TapHold {
+    pub tap: Box<Action>,
+    pub hold: Box<Action>,
+
+    /// The time after which a key that is held down is considered as being held
+    pub timeout_millis: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub struct Chord {
+    /// The other keys of the chord, in addition to the key of the source of the rule
+    pub keys: Vec<Key>,
+    pub action: Box<Action>,
+
+    /// The time window in which all keys of the chord have to be pressed
+    pub timeout_millis: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub struct TapDance {
+    /// The actions for a single tap, a double tap, and so on
+    pub actions: Vec<Action>,
+
+    /// The time after the last tap, after which the tap dance is finished
+    pub timeout_millis: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub struct Key {
+    pub key_index: usize,
+
+    /// The USB vendor ID of the device
+    pub usb_vid: u16,
+
+    /// The USB product ID of the device
+    pub usb_pid: u16,
+}
+
+#[allow(unused)]
+impl Key {
+    pub fn new(key_index: usize, (usb_vid, usb_pid): (u16, u16)) -> Self {
+        Self {
+            key_index,
+            usb_vid,
+            usb_pid,
+        }
+    }
+
+    /// Returns `true` if the key refers to the key `key_index` of the device `usb_vid:usb_pid`.
+    /// A key with an USB ID of `0000:0000` matches the keys of all devices
+    pub fn matches(&self, key_index: usize, (usb_vid, usb_pid): (u16, u16)) -> bool {
+        self.key_index == key_index
+            && ((self.usb_vid == usb_vid && self.usb_pid == usb_pid)
+                || (self.usb_vid == 0 && self.usb_pid == 0))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub struct EvdevEvent {
+    pub event: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub struct Macro {
+    pub function_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    Null,
+
+    HidKeyDown(Key),
+    HidKeyUp(Key),
+    HidMouseDown(Key),
+    HidMouseUp(Key),
+
+    EasyShiftKeyDown(Key),
+    EasyShiftKeyUp(Key),
+    EasyShiftMouseDown(Key),
+    EasyShiftMouseUp(Key),
+    EasyShiftMouseWheel(Direction),
+    EasyShiftMouseDpi(Direction),
+
+    SimpleKeyDown(Key),
+    SimpleKeyUp(Key),
+    SimpleMouseDown(Key),
+    SimpleMouseUp(Key),
+    SimpleMouseWheel(Direction),
+    SimpleMouseDpi(Direction),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[allow(unused)]
+impl Direction {
+    pub fn as_int(&self) -> i32 {
+        match self {
+            Direction::Up => 1,
+            Direction::Down => 2,
+            Direction::Left => 3,
+            Direction::Right => 4,
+        }
+    }
+}
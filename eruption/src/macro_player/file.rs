@@ -0,0 +1,177 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! The `.macro` file format: A declarative description of a macro, that may be shared
+//! between machines. Macro files are recorded by `eruption-macro` and played back by the
+//! daemon, using the virtual keyboard and mouse devices
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// The file name extension of macro files
+pub const MACRO_FILE_EXTENSION: &str = "macro";
+
+#[derive(Debug, thiserror::Error)]
+pub enum MacroFileError {
+    #[error("A macro that repeats until it is cancelled must contain at least one delay")]
+    InfiniteRepeatWithoutDelay {},
+
+    #[error("Invalid key or button code: {code}")]
+    InvalidCode { code: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub struct MacroFile {
+    pub metadata: MacroMetadata,
+
+    /// The number of times the steps are played back, 0 repeats them until the macro is cancelled
+    #[serde(default = "default_repeat")]
+    pub repeat: usize,
+
+    /// Cancel the playback, as soon as a key is pressed
+    #[serde(default = "default_cancel_on_keypress")]
+    pub cancel_on_keypress: bool,
+
+    pub steps: Vec<Step>,
+}
+
+fn default_repeat() -> usize {
+    1
+}
+
+fn default_cancel_on_keypress() -> bool {
+    true
+}
+
+#[allow(unused)]
+impl MacroFile {
+    pub fn new(name: &str, description: &str, steps: Vec<Step>) -> Self {
+        Self {
+            metadata: MacroMetadata {
+                name: name.to_string(),
+                description: description.to_string(),
+            },
+
+            repeat: default_repeat(),
+            cancel_on_keypress: default_cancel_on_keypress(),
+
+            steps,
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read_to_string(path.as_ref())?;
+        let result: Self = serde_json::from_str(&data)?;
+
+        result.validate()?;
+
+        Ok(result)
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.validate()?;
+
+        let data = serde_json::to_string_pretty(&self)?;
+        fs::write(path.as_ref(), data)?;
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for step in &self.steps {
+            match step {
+                Step::KeyDown(code)
+                | Step::KeyUp(code)
+                | Step::ButtonDown(code)
+                | Step::ButtonUp(code) => {
+                    if evdev_rs::enums::int_to_ev_key(*code).is_none() {
+                        return Err(MacroFileError::InvalidCode { code: *code }.into());
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        if self.repeat == 0
+            && !self
+                .steps
+                .iter()
+                .any(|step| matches!(step, Step::Delay(millis) if *millis > 0))
+        {
+            Err(MacroFileError::InfiniteRepeatWithoutDelay {}.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The duration of a single iteration of the macro
+    pub fn duration(&self) -> Duration {
+        let millis = self
+            .steps
+            .iter()
+            .map(|step| match step {
+                Step::Delay(millis) => *millis,
+                _ => 0,
+            })
+            .fold(0_u64, u64::saturating_add);
+
+        Duration::from_millis(millis)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub struct MacroMetadata {
+    pub name: String,
+
+    #[serde(default)]
+    pub description: String,
+}
+
+/// A single step of a macro. Keys and mouse buttons are specified as evdev event codes
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    KeyDown(u32),
+    KeyUp(u32),
+
+    ButtonDown(u32),
+    ButtonUp(u32),
+
+    /// Relative mouse movement
+    MouseMove {
+        x: i32,
+        y: i32,
+    },
+
+    /// Mouse wheel movement, positive values scroll up
+    MouseWheel(i32),
+
+    /// Wait for the specified amount of milliseconds
+    Delay(u64),
+}
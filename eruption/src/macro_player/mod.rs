@@ -0,0 +1,190 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use flume::{unbounded, Receiver, RecvTimeoutError, Sender};
+use lazy_static::lazy_static;
+use log::*;
+use parking_lot::RwLock;
+
+use crate::constants;
+use crate::plugins::macros::{Message, UINPUT_TX};
+
+pub mod file;
+pub mod player;
+
+pub use file::{MacroFile, Step};
+pub use player::Player;
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MacroPlaybackError {
+    #[error("The virtual input devices are not initialized")]
+    UinputNotInitialized {},
+}
+
+/// A macro that is currently being played back
+#[derive(Debug)]
+struct Playback {
+    id: usize,
+    name: String,
+    cancel_on_keypress: bool,
+    cancel_tx: Sender<()>,
+}
+
+lazy_static! {
+    /// All macros that are currently being played back
+    static ref PLAYBACKS: Arc<RwLock<Vec<Playback>>> = Arc::new(RwLock::new(Vec::new()));
+
+    static ref NEXT_PLAYBACK_ID: AtomicUsize = AtomicUsize::new(0);
+}
+
+/// Returns the path of the macro file `name`. Plain names are resolved against the macros directory
+pub fn macro_path(name: &str) -> PathBuf {
+    let path = Path::new(name);
+
+    if path.components().count() > 1 {
+        path.to_path_buf()
+    } else {
+        Path::new(constants::DEFAULT_MACRO_DIR)
+            .join(path)
+            .with_extension(file::MACRO_FILE_EXTENSION)
+    }
+}
+
+/// Start the playback of the macro file `name` on a separate thread
+pub fn play_macro(name: &str) -> Result<()> {
+    let path = macro_path(name);
+    let macro_file = MacroFile::from_file(&path)?;
+
+    if UINPUT_TX.read().is_none() {
+        return Err(MacroPlaybackError::UinputNotInitialized {}.into());
+    }
+
+    let id = NEXT_PLAYBACK_ID.fetch_add(1, Ordering::SeqCst);
+    let (cancel_tx, cancel_rx) = unbounded();
+
+    PLAYBACKS.write().push(Playback {
+        id,
+        name: macro_file.metadata.name.clone(),
+        cancel_on_keypress: macro_file.cancel_on_keypress,
+        cancel_tx,
+    });
+
+    debug!("Playing back macro: {}", path.display());
+
+    let result = thread::Builder::new()
+        .name(format!("macro/{}", id))
+        .spawn(move || {
+            run_playback(Player::new(&macro_file), &cancel_rx);
+
+            PLAYBACKS.write().retain(|playback| playback.id != id);
+        });
+
+    if let Err(e) = result {
+        PLAYBACKS.write().retain(|playback| playback.id != id);
+
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Cancel all macros that are currently being played back
+pub fn stop_all_macros() {
+    for playback in PLAYBACKS.read().iter() {
+        debug!("Cancelling macro: {}", playback.name);
+
+        playback
+            .cancel_tx
+            .send(())
+            .unwrap_or_else(|e| error!("Could not cancel the macro {}: {}", playback.name, e));
+    }
+}
+
+/// Cancel all macros that shall be cancelled when a key is pressed, this is called
+/// for each key press on a hardware keyboard
+pub fn cancel_on_keypress() {
+    for playback in PLAYBACKS
+        .read()
+        .iter()
+        .filter(|playback| playback.cancel_on_keypress)
+    {
+        debug!("Cancelling macro on key press: {}", playback.name);
+
+        playback
+            .cancel_tx
+            .send(())
+            .unwrap_or_else(|e| error!("Could not cancel the macro {}: {}", playback.name, e));
+    }
+}
+
+fn run_playback(mut player: Player, cancel_rx: &Receiver<()>) {
+    for step in player.by_ref() {
+        let cancelled = match step {
+            Step::Delay(millis) => !matches!(
+                cancel_rx.recv_timeout(Duration::from_millis(millis)),
+                Err(RecvTimeoutError::Timeout)
+            ),
+
+            step => {
+                inject_step(&step);
+
+                !cancel_rx.is_empty()
+            }
+        };
+
+        if cancelled {
+            break;
+        }
+    }
+
+    // do not leave any keys or buttons pressed
+    for step in player.release_all() {
+        inject_step(&step);
+    }
+}
+
+fn inject_step(step: &Step) {
+    let message = match *step {
+        Step::KeyDown(key) => Message::InjectKey { key, down: true },
+        Step::KeyUp(key) => Message::InjectKey { key, down: false },
+
+        Step::ButtonDown(code) => Message::InjectMouseButton { code, down: true },
+        Step::ButtonUp(code) => Message::InjectMouseButton { code, down: false },
+
+        Step::MouseMove { x, y } => Message::InjectMouseMove { x, y },
+        Step::MouseWheel(delta) => Message::InjectMouseWheel { delta },
+
+        Step::Delay(_) => return,
+    };
+
+    if let Some(tx) = UINPUT_TX.read().as_ref() {
+        tx.send(message)
+            .unwrap_or_else(|e| error!("Could not inject a macro event: {}", e));
+    }
+}
@@ -0,0 +1,225 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::collections::BTreeSet;
+
+use super::file::{MacroFile, Step};
+
+/// Yields the steps of a macro in playback order, honoring the repeat count, and keeps
+/// track of the keys and buttons that are currently pressed, so that they can be released
+/// when the playback is cancelled
+#[derive(Debug, Clone)]
+pub struct Player {
+    steps: Vec<Step>,
+    repeat: usize,
+
+    position: usize,
+    iteration: usize,
+
+    pressed_keys: BTreeSet<u32>,
+    pressed_buttons: BTreeSet<u32>,
+}
+
+impl Player {
+    pub fn new(macro_file: &MacroFile) -> Self {
+        Self {
+            steps: macro_file.steps.clone(),
+            repeat: macro_file.repeat,
+
+            position: 0,
+            iteration: 0,
+
+            pressed_keys: BTreeSet::new(),
+            pressed_buttons: BTreeSet::new(),
+        }
+    }
+
+    /// Returns the steps required to release all keys and buttons that are still pressed
+    pub fn release_all(&mut self) -> Vec<Step> {
+        let keys = std::mem::take(&mut self.pressed_keys)
+            .into_iter()
+            .map(Step::KeyUp);
+
+        let buttons = std::mem::take(&mut self.pressed_buttons)
+            .into_iter()
+            .map(Step::ButtonUp);
+
+        keys.chain(buttons).collect()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.steps.is_empty() || (self.repeat != 0 && self.iteration >= self.repeat)
+    }
+}
+
+impl Iterator for Player {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished() {
+            return None;
+        }
+
+        if self.position >= self.steps.len() {
+            self.position = 0;
+            self.iteration += 1;
+
+            if self.is_finished() {
+                return None;
+            }
+        }
+
+        let step = self.steps[self.position].clone();
+        self.position += 1;
+
+        match step {
+            Step::KeyDown(code) => {
+                self.pressed_keys.insert(code);
+            }
+
+            Step::KeyUp(code) => {
+                self.pressed_keys.remove(&code);
+            }
+
+            Step::ButtonDown(code) => {
+                self.pressed_buttons.insert(code);
+            }
+
+            Step::ButtonUp(code) => {
+                self.pressed_buttons.remove(&code);
+            }
+
+            _ => { /* do nothing */ }
+        }
+
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Player;
+    use crate::macro_player::file::{MacroFile, Step};
+    use std::time::Duration;
+
+    const KEY_A: u32 = 30;
+    const KEY_LEFTSHIFT: u32 = 42;
+    const BTN_LEFT: u32 = 272;
+
+    fn test_macro() -> MacroFile {
+        MacroFile::new(
+            "test",
+            "",
+            vec![
+                Step::KeyDown(KEY_LEFTSHIFT),
+                Step::KeyDown(KEY_A),
+                Step::Delay(20),
+                Step::KeyUp(KEY_A),
+                Step::KeyUp(KEY_LEFTSHIFT),
+            ],
+        )
+    }
+
+    #[test]
+    fn example_macro_file() {
+        let data = include_str!("../scripts/lib/macros/example.macro");
+        let macro_file: MacroFile = serde_json::from_str(data).unwrap();
+
+        macro_file.validate().unwrap();
+
+        assert_eq!(macro_file.metadata.name, "example");
+        assert!(macro_file.cancel_on_keypress);
+        assert_eq!(macro_file.duration(), Duration::from_millis(570));
+        assert_eq!(macro_file.steps[0], Step::KeyDown(35));
+        assert_eq!(macro_file.steps[21], Step::ButtonUp(BTN_LEFT));
+
+        // the repeat count and cancel-on-keypress are optional
+        let macro_file: MacroFile =
+            serde_json::from_str(r#"{ "metadata": { "name": "test" }, "steps": [] }"#).unwrap();
+
+        assert_eq!(macro_file.repeat, 1);
+        assert!(macro_file.cancel_on_keypress);
+    }
+
+    #[test]
+    fn repeat() {
+        let mut macro_file = test_macro();
+
+        assert_eq!(Player::new(&macro_file).count(), 5);
+
+        macro_file.repeat = 3;
+        assert_eq!(Player::new(&macro_file).count(), 15);
+
+        // repeat until cancelled
+        macro_file.repeat = 0;
+        assert_eq!(Player::new(&macro_file).take(100).count(), 100);
+
+        macro_file
+            .steps
+            .retain(|step| !matches!(step, Step::Delay(_)));
+        assert!(macro_file.validate().is_err());
+    }
+
+    #[test]
+    fn long_delays() {
+        let mut macro_file = test_macro();
+
+        macro_file.steps.push(Step::Delay(u64::MAX));
+
+        assert_eq!(macro_file.duration(), Duration::from_millis(u64::MAX));
+    }
+
+    #[test]
+    fn invalid_codes() {
+        let mut macro_file = test_macro();
+
+        macro_file.steps.push(Step::ButtonDown(BTN_LEFT));
+        assert!(macro_file.validate().is_ok());
+
+        macro_file.steps.push(Step::ButtonUp(0xffff));
+        assert!(macro_file.validate().is_err());
+
+        macro_file.steps.pop();
+        macro_file.steps.push(Step::KeyDown(u32::MAX));
+        assert!(macro_file.validate().is_err());
+    }
+
+    #[test]
+    fn release_on_cancel() {
+        let macro_file = test_macro();
+        let mut player = Player::new(&macro_file);
+
+        assert_eq!(player.next(), Some(Step::KeyDown(KEY_LEFTSHIFT)));
+        assert_eq!(player.next(), Some(Step::KeyDown(KEY_A)));
+        assert_eq!(player.next(), Some(Step::Delay(20)));
+
+        assert_eq!(
+            player.release_all(),
+            vec![Step::KeyUp(KEY_A), Step::KeyUp(KEY_LEFTSHIFT)]
+        );
+
+        // keys that have been released by the macro are not released again
+        let mut player = Player::new(&macro_file);
+
+        player.by_ref().for_each(drop);
+        assert!(player.release_all().is_empty());
+    }
+}
@@ -53,6 +53,7 @@ use threads::*;
 
 mod util;
 
+mod canvas;
 mod hwdevices;
 use hwdevices::{KeyboardDevice, KeyboardHidEvent, MiscDevice, MouseDevice, MouseHidEvent};
 
@@ -60,6 +61,8 @@ mod color_scheme;
 mod constants;
 mod dbus_interface;
 mod events;
+mod keymap;
+mod macro_player;
 mod plugin_manager;
 mod plugins;
 mod profiles;
@@ -70,9 +73,9 @@ use crate::{
     color_scheme::ColorScheme,
     hwdevices::{DeviceStatus, MaturityLevel, RGBA},
     plugins::macros,
-    plugins::{sdk_support, uleds},
+    plugins::{lighting, openrgb, remote_api, sdk_support, uleds},
     profiles::Profile,
-    scripting::script,
+    scripting::{compositing, profiler, script},
 };
 
 use crate::threads::DbusApiEvent;
@@ -203,6 +206,12 @@ lazy_static! {
     /// Global "enable Linux Userspace LEDs support" flag
     pub static ref ULEDS_SUPPORT_ACTIVE: AtomicBool = AtomicBool::new(false);
 
+    /// Global "network lighting protocols are active" flag
+    pub static ref LIGHTING_SUPPORT_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+    /// Global "OpenRGB clients are active" flag
+    pub static ref OPENRGB_SUPPORT_ACTIVE: AtomicBool = AtomicBool::new(false);
+
 
     // Other state
 
@@ -354,7 +363,8 @@ pub enum EventAction {
 #[derive(Debug, Clone)]
 pub enum FileSystemEvent {
     ProfileChanged { action: EventAction, path: PathBuf },
-    ScriptChanged,
+    ScriptChanged { path: PathBuf },
+    KeymapChanged { path: PathBuf },
 }
 
 #[derive(Debug, Clone)]
@@ -418,6 +428,30 @@ pub enum SwitchProfileResult {
     FallbackToFailsafe,
 }
 
+/// Load the profiles of the slots that are assigned to devices by `profile`. Returns one entry
+/// for each `[[devices]]` section, or `None` if the section specifies its own scripts
+fn load_device_slot_profiles(profile: &Profile) -> Result<Vec<Option<Profile>>> {
+    let slot_profiles = SLOT_PROFILES.lock().clone().unwrap_or_default();
+
+    profile
+        .devices
+        .iter()
+        .map(|device| match device.slot {
+            Some(slot) => match slot_profiles.get(slot) {
+                // device sections of the profile of the slot are not followed
+                Some(profile_file) => Ok(Some(Profile::load_fully(profile_file)?)),
+
+                None => {
+                    error!("Invalid slot assigned to a device: {}", slot);
+                    Err(MainError::SwitchProfileError {}.into())
+                }
+            },
+
+            None => Ok(None),
+        })
+        .collect()
+}
+
 /// Switches the currently active profile to the profile file `profile_file`
 /// Returns Ok(Switched) if the new profile has been activated, Ok(InvalidProfile)
 /// if the old profile was kept, or else Ok(FallbackToFailsafe) when we entered
@@ -433,16 +467,28 @@ pub fn switch_profile(
 
         let profile = Profile::new_fail_safe();
 
+        // the failsafe profile does not use any device specific scripts
+        script::init_device_led_maps(&[]);
+        compositing::init_layers(vec![]);
+
         // spawn a new set of Lua VMs, with scripts from the failsafe profile
         for (thread_idx, manifest) in profile.manifests.values().enumerate() {
             let (lua_tx, lua_rx) = unbounded();
             let parameters = &manifest.get_merged_parameters(&profile);
-            threads::spawn_lua_thread(thread_idx, lua_rx, &manifest.script_file, parameters)
-                .unwrap_or_else(|e| {
-                    errors_present = true;
+            threads::spawn_lua_thread(
+                thread_idx,
+                lua_rx,
+                &manifest.script_file,
+                parameters,
+                None,
+                &profile.profile_file,
+                &manifest.permissions,
+            )
+            .unwrap_or_else(|e| {
+                errors_present = true;
 
-                    error!("Could not spawn a thread: {}", e);
-                });
+                error!("Could not spawn a thread: {}", e);
+            });
 
             let mut tx = LuaTx::new(manifest.script_file.to_owned(), lua_tx);
 
@@ -492,6 +538,10 @@ pub fn switch_profile(
 
         // be safe and clear any leftover channels
         LUA_TXS.write().clear();
+        profiler::reset();
+
+        // macros of the previous profile must not keep on injecting input
+        macro_player::stop_all_macros();
 
         switch_to_failsafe_profile(dbus_api_tx, notify)?;
         REQUEST_FAILSAFE_MODE.store(false, Ordering::SeqCst);
@@ -510,10 +560,14 @@ pub fn switch_profile(
 
         info!("Switching to profile: {}", &profile_file.display());
 
-        let profile = profiles::Profile::load_fully(profile_file);
+        let profile = profiles::Profile::load_fully(profile_file).and_then(|profile| {
+            let slot_profiles = load_device_slot_profiles(&profile)?;
+
+            Ok((profile, slot_profiles))
+        });
 
         match profile {
-            Ok(profile) => {
+            Ok((profile, slot_profiles)) => {
                 let mut errors_present = false;
 
                 // request termination of all Lua VMs
@@ -529,12 +583,18 @@ pub fn switch_profile(
 
                 // be safe and clear any leftover channels
                 LUA_TXS.write().clear();
+                profiler::reset();
+
+                // macros of the previous profile must not keep on injecting input
+                macro_player::stop_all_macros();
 
                 // we passed the point of no return, from here on we can't just go back
                 // but need to switch to failsafe mode when we encounter any critical errors
 
                 let mut num_vms = 0; // only valid if no errors occurred
 
+                script::init_device_led_maps(&profile.devices);
+
                 // now spawn a new set of Lua VMs, with scripts from the new profile
                 for (thread_idx, manifest) in profile.manifests.values().enumerate() {
                     let (lua_tx, lua_rx) = unbounded();
@@ -543,6 +603,9 @@ pub fn switch_profile(
                         lua_rx,
                         &manifest.script_file,
                         &manifest.get_merged_parameters(&profile),
+                        None,
+                        &profile.profile_file,
+                        &manifest.permissions,
                     ) {
                         errors_present = true;
 
@@ -560,6 +623,64 @@ pub fn switch_profile(
                     LUA_TXS.write().push(tx);
                 }
 
+                // spawn the Lua VMs of the scripts that render to a single device only
+                for (render_target, manifests) in profile.device_manifests.iter().enumerate() {
+                    // devices that follow a slot run the scripts of the profile of that slot
+                    let (source_profile, manifests) = match &slot_profiles[render_target] {
+                        Some(slot_profile) => (
+                            slot_profile,
+                            slot_profile.manifests.values().collect::<Vec<_>>(),
+                        ),
+
+                        None => (&profile, manifests.iter().collect::<Vec<_>>()),
+                    };
+
+                    for manifest in manifests {
+                        let thread_idx = LUA_TXS.read().len();
+
+                        let (lua_tx, lua_rx) = unbounded();
+                        if let Err(e) = threads::spawn_lua_thread(
+                            thread_idx,
+                            lua_rx,
+                            &manifest.script_file,
+                            &manifest.get_merged_parameters(source_profile),
+                            Some(render_target),
+                            &source_profile.profile_file,
+                            &manifest.permissions,
+                        ) {
+                            errors_present = true;
+
+                            error!("Could not spawn a thread: {}", e);
+                        }
+
+                        let mut tx = LuaTx::new(manifest.script_file.to_owned(), lua_tx);
+
+                        if !errors_present {
+                            num_vms += 1;
+                        } else {
+                            tx.is_failed = true;
+                        }
+
+                        LUA_TXS.write().push(tx);
+                    }
+                }
+
+                // assign the compositing layers, in the same order as the Lua VMs
+                let canvas_size = canvas::get_canvas_size();
+                let layers = LUA_TXS
+                    .read()
+                    .iter()
+                    .map(|tx| {
+                        compositing::Layer::new(
+                            &tx.script_file,
+                            &profile.get_layer_config(&tx.script_file),
+                            canvas_size,
+                        )
+                    })
+                    .collect();
+
+                compositing::init_layers(layers);
+
                 // it seems that at least one Lua VM failed during loading of the new profile,
                 // so we have to switch to failsafe mode to be safe
                 if errors_present || num_vms == 0 {
@@ -935,6 +1056,9 @@ fn run_main_loop(
             // join_all(futures);
         }
 
+        // resolve pending tap-hold keys of the keymap engine
+        keymap::poll();
+
         if last_status_poll.elapsed()
             >= Duration::from_millis(constants::POLL_TIMER_INTERVAL_MILLIS)
         {
@@ -1263,7 +1387,11 @@ pub fn register_filesystem_watcher(
                                            event.extension().unwrap_or_default().to_string_lossy() == "manifest" {
                                             info!("Script file, manifest or keymap changed: {:?}", event);
 
-                                            fsevents_tx_c.send(FileSystemEvent::ScriptChanged).unwrap();
+                                            fsevents_tx_c.send(FileSystemEvent::ScriptChanged { path: event }).unwrap();
+                                        } else if event.extension().unwrap_or_default().to_string_lossy() == "keymap" {
+                                            info!("Keymap table changed: {:?}", event);
+
+                                            fsevents_tx_c.send(FileSystemEvent::KeymapChanged { path: event }).unwrap();
                                         }
                                     }
 
@@ -1420,6 +1548,32 @@ pub fn init_misc_device(misc_device: &MiscDevice) {
     );
 }
 
+/// Resize the global LED maps to the size of the canvas, they may have been
+/// allocated before the canvas layout has been loaded
+fn resize_led_maps() {
+    let canvas_size = canvas::get_canvas_size();
+
+    for led_map in [
+        &*script::LED_MAP,
+        &*script::LAST_RENDERED_LED_MAP,
+        &*dbus_interface::LED_MAP,
+        &*lighting::LED_MAP,
+        &*openrgb::LED_MAP,
+        &*sdk_support::LED_MAP,
+        &*uleds::LED_MAP,
+    ] {
+        led_map.write().resize(
+            canvas_size,
+            RGBA {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            },
+        );
+    }
+}
+
 pub async fn async_main() -> std::result::Result<(), eyre::Error> {
     #[cfg(feature = "profiling")]
     coz::thread_init();
@@ -1560,6 +1714,20 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
         }
     }
 
+    // canvas geometry and device regions
+    canvas::load_layout(&config);
+    resize_led_maps();
+
+    // keymap table, that will be executed natively
+    keymap::load_keymap(&config);
+
+    // permissions that the administrator approved for scripts
+    scripting::sandbox::load_permission_grants(&config);
+
+    // load declarative device descriptions, in addition to the built-in drivers
+    info!("Loading device descriptions...");
+    hwdevices::load_device_descriptions();
+
     // load and initialize global runtime state
     info!("Loading saved state...");
     state::init_global_runtime_state()
@@ -1605,13 +1773,25 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
                     info!("Spawning keyboard input thread...");
 
                     let (kbd_tx, kbd_rx) = unbounded();
-                    threads::spawn_keyboard_input_thread(
-                        kbd_tx.clone(),
-                        device.clone(),
-                        index,
-                        usb_vid,
-                        usb_pid,
-                    )
+
+                    let virtual_input_rx =
+                        hwdevices::get_virtual_input_rx(device.read().as_device());
+                    if let Some(input_rx) = virtual_input_rx {
+                        threads::spawn_virtual_keyboard_input_thread(
+                            kbd_tx.clone(),
+                            device.clone(),
+                            index,
+                            input_rx,
+                        )
+                    } else {
+                        threads::spawn_keyboard_input_thread(
+                            kbd_tx.clone(),
+                            device.clone(),
+                            index,
+                            usb_vid,
+                            usb_pid,
+                        )
+                    }
                     .unwrap_or_else(|e| {
                         error!("Could not spawn a thread: {}", e);
                         panic!()
@@ -1636,13 +1816,24 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
                         // spawn a thread to handle mouse input
                         info!("Spawning mouse input thread...");
 
-                        spawn_mouse_input_thread(
-                            mouse_tx.clone(),
-                            device.clone(),
-                            index,
-                            usb_vid,
-                            usb_pid,
-                        )
+                        let virtual_input_rx =
+                            hwdevices::get_virtual_input_rx(device.read().as_device());
+                        if let Some(input_rx) = virtual_input_rx {
+                            threads::spawn_virtual_mouse_input_thread(
+                                mouse_tx.clone(),
+                                device.clone(),
+                                index,
+                                input_rx,
+                            )
+                        } else {
+                            spawn_mouse_input_thread(
+                                mouse_tx.clone(),
+                                device.clone(),
+                                index,
+                                usb_vid,
+                                usb_pid,
+                            )
+                        }
                         .unwrap_or_else(|e| {
                             error!("Could not spawn a thread: {}", e);
                             panic!()
@@ -1788,6 +1979,9 @@ pub async fn async_main() -> std::result::Result<(), eyre::Error> {
 
                 events::notify_observers(events::Event::DaemonShutdown)?;
 
+                // cancel running macros, so that no keys or buttons are left pressed
+                macro_player::stop_all_macros();
+
                 // we left the main loop, so send a final message to the running Lua VMs
                 info!("Shutting down all Lua VMs now...");
 
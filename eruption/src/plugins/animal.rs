@@ -40,7 +40,7 @@ use std::{
 };
 
 use crate::plugins::Plugin;
-use crate::{constants, plugins};
+use crate::{canvas, plugins};
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
 
@@ -355,7 +355,7 @@ impl Animal {
             }
         }
 
-        result.resize_with(constants::CANVAS_SIZE, || 0x000000000);
+        result.resize_with(canvas::get_canvas_size(), || 0x000000000);
         result
     }
 }
@@ -33,6 +33,8 @@ use std::{
 use crate::events;
 use crate::plugins::{self, Plugin};
 
+pub mod analysis;
+
 pub mod protocol {
     include!(concat!(env!("OUT_DIR"), "/audio_proxy.rs"));
 }
@@ -70,6 +72,9 @@ lazy_static! {
     /// Spectrum analyzer state
     static ref AUDIO_SPECTRUM: Arc<RwLock<Vec<f32>>> = Arc::new(RwLock::new(vec![0.0; FFT_SIZE / 2]));
 
+    /// Beat detection and frequency band analysis state
+    static ref AUDIO_ANALYZER: Arc<RwLock<analysis::AudioAnalyzer>> = Arc::new(RwLock::new(analysis::AudioAnalyzer::default()));
+
     /// Global "sound effects enabled" flag
     pub static ref ENABLE_SFX: AtomicBool = AtomicBool::new(false);
 }
@@ -82,11 +87,18 @@ static AUDIO_GRABBER_RECORDING: AtomicBool = AtomicBool::new(false);
 static AUDIO_GRABBER_PERFORM_RMS_COMPUTATION: AtomicBool = AtomicBool::new(false);
 static AUDIO_GRABBER_PERFORM_FFT_COMPUTATION: AtomicBool = AtomicBool::new(false);
 
+// Enable beat detection and frequency band analysis?
+static AUDIO_GRABBER_PERFORM_ANALYSIS: AtomicBool = AtomicBool::new(false);
+
 pub fn reset_audio_backend() {
     AUDIO_GRABBER_RECORD_AUDIO.store(false, Ordering::SeqCst);
 
     AUDIO_GRABBER_PERFORM_RMS_COMPUTATION.store(false, Ordering::SeqCst);
     AUDIO_GRABBER_PERFORM_FFT_COMPUTATION.store(false, Ordering::SeqCst);
+    AUDIO_GRABBER_PERFORM_ANALYSIS.store(false, Ordering::SeqCst);
+
+    // the settings of the bands may have been changed by the scripts of the previous profile
+    *AUDIO_ANALYZER.write() = analysis::AudioAnalyzer::default();
 
     *RATE_LIMIT_TIME.write() = Instant::now()
         .checked_sub(Duration::from_millis(ERROR_RATE_LIMIT_MILLIS))
@@ -151,6 +163,43 @@ impl AudioPlugin {
         AUDIO_GRABBER_BUFFER.read().to_vec()
     }
 
+    fn enable_audio_analysis() {
+        AUDIO_GRABBER_RECORD_AUDIO.store(true, Ordering::SeqCst);
+        AUDIO_GRABBER_PERFORM_ANALYSIS.store(true, Ordering::Relaxed);
+    }
+
+    pub fn get_audio_beat() -> bool {
+        Self::enable_audio_analysis();
+
+        AUDIO_ANALYZER.read().is_beat()
+    }
+
+    pub fn get_audio_bpm() -> f32 {
+        Self::enable_audio_analysis();
+
+        AUDIO_ANALYZER.read().bpm()
+    }
+
+    pub fn get_audio_bands() -> Vec<f32> {
+        Self::enable_audio_analysis();
+
+        AUDIO_ANALYZER.read().band_envelopes()
+    }
+
+    pub fn get_audio_bass_mid_treble() -> (f32, f32, f32) {
+        Self::enable_audio_analysis();
+
+        AUDIO_ANALYZER.read().bass_mid_treble()
+    }
+
+    pub fn set_audio_bands(count: usize, min_freq: f32, max_freq: f32) {
+        AUDIO_ANALYZER.write().set_bands(count, min_freq, max_freq);
+    }
+
+    pub fn set_audio_smoothing(attack: f32, release: f32) {
+        AUDIO_ANALYZER.write().set_smoothing(attack, release);
+    }
+
     pub fn get_audio_volume() -> isize {
         if let Some(backend) = &*AUDIO_BACKEND.lock() {
             backend.get_master_volume().unwrap_or(0) * 100 / u16::MAX as isize
@@ -223,6 +272,36 @@ impl Plugin for AudioPlugin {
             lua_ctx.create_function(move |_, ()| Ok(AudioPlugin::get_audio_raw_data()))?;
         globals.set("get_audio_raw_data", get_audio_raw_data)?;
 
+        let get_audio_beat =
+            lua_ctx.create_function(move |_, ()| Ok(AudioPlugin::get_audio_beat()))?;
+        globals.set("get_audio_beat", get_audio_beat)?;
+
+        let get_audio_bpm =
+            lua_ctx.create_function(move |_, ()| Ok(AudioPlugin::get_audio_bpm()))?;
+        globals.set("get_audio_bpm", get_audio_bpm)?;
+
+        let get_audio_bands =
+            lua_ctx.create_function(move |_, ()| Ok(AudioPlugin::get_audio_bands()))?;
+        globals.set("get_audio_bands", get_audio_bands)?;
+
+        let get_audio_bass_mid_treble =
+            lua_ctx.create_function(move |_, ()| Ok(AudioPlugin::get_audio_bass_mid_treble()))?;
+        globals.set("get_audio_bass_mid_treble", get_audio_bass_mid_treble)?;
+
+        let set_audio_bands =
+            lua_ctx.create_function(move |_, (count, min_freq, max_freq): (usize, f32, f32)| {
+                AudioPlugin::set_audio_bands(count, min_freq, max_freq);
+                Ok(())
+            })?;
+        globals.set("set_audio_bands", set_audio_bands)?;
+
+        let set_audio_smoothing =
+            lua_ctx.create_function(move |_, (attack, release): (f32, f32)| {
+                AudioPlugin::set_audio_smoothing(attack, release);
+                Ok(())
+            })?;
+        globals.set("set_audio_smoothing", set_audio_smoothing)?;
+
         let is_audio_muted =
             lua_ctx.create_function(move |_, ()| Ok(AudioPlugin::is_audio_muted()))?;
         globals.set("is_audio_muted", is_audio_muted)?;
@@ -502,6 +581,13 @@ mod backends {
                                                                     }),
                                                                 );
 
+                                                                // perform beat detection and frequency band analysis, before the buffer is padded
+                                                                if super::AUDIO_GRABBER_PERFORM_ANALYSIS
+                                                                    .load(Ordering::Relaxed)
+                                                                {
+                                                                    super::AUDIO_ANALYZER.write().process(&buffer);
+                                                                }
+
                                                                 if buffer.len() < FFT_SIZE {
                                                                     buffer.resize(FFT_SIZE, 0x0000);
                                                                 }
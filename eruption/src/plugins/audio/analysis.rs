@@ -0,0 +1,644 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! Beat detection, tempo estimation and frequency band analysis of the recorded audio signal
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+/// Sample rate of the audio signal, as recorded by the eruption-audio-proxy
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// Number of interleaved channels, as recorded by the eruption-audio-proxy
+pub const DEFAULT_CHANNELS: usize = 2;
+
+/// Size of the analysis window in samples
+pub const WINDOW_SIZE: usize = 1024;
+
+/// Number of analysis frames per second, independent of the sample rate
+const FRAME_RATE: f32 = 86.0;
+
+/// Length of the history used to compute the adaptive onset threshold, in seconds
+const THRESHOLD_HISTORY_SECS: f32 = 1.0;
+
+/// Length of the history used to estimate the tempo, in seconds
+const TEMPO_HISTORY_SECS: f32 = 6.0;
+
+/// Onsets closer together than this are considered to be the same onset, in seconds
+const MIN_ONSET_INTERVAL_SECS: f32 = 0.2;
+
+/// A beat is reported for this amount of time after an onset has been detected, in seconds
+const BEAT_HOLD_SECS: f32 = 0.1;
+
+/// Range of tempi that will be reported
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+
+/// Tempo estimation prefers tempi close to this value, to resolve octave ambiguities
+const PREFERRED_BPM: f32 = 120.0;
+
+/// Default number and frequency range of the log-spaced bands
+pub const DEFAULT_NUM_BANDS: usize = 16;
+pub const DEFAULT_MIN_FREQ: f32 = 20.0;
+pub const DEFAULT_MAX_FREQ: f32 = 16000.0;
+
+/// Upper limits of the bass and mid range, in Hz
+pub const BASS_MAX_FREQ: f32 = 250.0;
+pub const MID_MAX_FREQ: f32 = 4000.0;
+
+/// Default attack and release coefficients of the envelope followers
+pub const DEFAULT_ATTACK: f32 = 0.6;
+pub const DEFAULT_RELEASE: f32 = 0.1;
+
+/// Decay of the running peak that is used to normalize the envelopes, per frame
+const PEAK_DECAY: f32 = 0.998;
+
+/// Envelopes below this level are considered to be silence
+const NOISE_FLOOR: f32 = 1e-3;
+
+/// A frequency band, from `low` up to `high` Hz
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrequencyBand {
+    pub low: f32,
+    pub high: f32,
+}
+
+impl FrequencyBand {
+    /// Returns the range of FFT bins that cover the band
+    fn bins(&self, sample_rate: u32) -> (usize, usize) {
+        let bin_width = sample_rate as f32 / WINDOW_SIZE as f32;
+        let max_bin = WINDOW_SIZE / 2;
+
+        let low = ((self.low / bin_width).floor() as usize).clamp(1, max_bin - 1);
+        let high = ((self.high / bin_width).ceil() as usize).clamp(low + 1, max_bin);
+
+        (low, high)
+    }
+}
+
+impl fmt::Display for FrequencyBand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0}-{:.0} Hz", self.low, self.high)
+    }
+}
+
+/// Split the range from `min_freq` to `max_freq` into `count` logarithmically spaced bands
+pub fn log_spaced_bands(count: usize, min_freq: f32, max_freq: f32) -> Vec<FrequencyBand> {
+    let count = count.max(1);
+    let ratio = (max_freq / min_freq).powf(1.0 / count as f32);
+
+    (0..count)
+        .map(|i| FrequencyBand {
+            low: min_freq * ratio.powi(i as i32),
+            high: min_freq * ratio.powi(i as i32 + 1),
+        })
+        .collect()
+}
+
+/// A smoothed envelope, normalized by a slowly decaying running peak
+#[derive(Debug, Clone)]
+struct Envelope {
+    value: f32,
+    peak: f32,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        // start with a non-zero peak, so that the normalized envelope is always well defined
+        Self {
+            value: 0.0,
+            peak: NOISE_FLOOR,
+        }
+    }
+}
+
+impl Envelope {
+    fn update(&mut self, level: f32, attack: f32, release: f32) {
+        let coefficient = if level > self.value { attack } else { release };
+
+        self.value += (level - self.value) * coefficient;
+        self.peak = (self.peak * PEAK_DECAY).max(self.value).max(NOISE_FLOOR);
+    }
+
+    /// The envelope in the range [0..1]
+    fn normalized(&self) -> f32 {
+        (self.value / self.peak).clamp(0.0, 1.0)
+    }
+}
+
+/// Performs beat detection, tempo estimation and frequency band analysis of an audio signal.
+///
+/// Onsets are detected using the spectral flux of the signal, with an adaptive threshold.
+/// The tempo is estimated from the autocorrelation of the spectral flux
+#[derive(Clone)]
+pub struct AudioAnalyzer {
+    sample_rate: u32,
+    channels: usize,
+    hop_size: usize,
+
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+
+    /// Mono samples that have not been analyzed yet
+    pending: VecDeque<f32>,
+    previous_spectrum: Vec<f32>,
+
+    bands: Vec<FrequencyBand>,
+    band_envelopes: Vec<Envelope>,
+    bass: Envelope,
+    mid: Envelope,
+    treble: Envelope,
+    attack: f32,
+    release: f32,
+
+    flux_history: VecDeque<f32>,
+
+    /// Number of analyzed frames, used as the clock of the analyzer
+    frames: u64,
+    last_onset_frame: Option<u64>,
+    onsets: u64,
+    bpm: f32,
+}
+
+impl fmt::Debug for AudioAnalyzer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AudioAnalyzer")
+            .field("sample_rate", &self.sample_rate)
+            .field("channels", &self.channels)
+            .field("bands", &self.bands)
+            .field("onsets", &self.onsets)
+            .field("bpm", &self.bpm)
+            .finish()
+    }
+}
+
+impl Default for AudioAnalyzer {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_RATE, DEFAULT_CHANNELS)
+    }
+}
+
+impl AudioAnalyzer {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(WINDOW_SIZE);
+
+        // Hann window
+        let window = (0..WINDOW_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / WINDOW_SIZE as f32).cos())
+            .collect();
+
+        let bands = log_spaced_bands(DEFAULT_NUM_BANDS, DEFAULT_MIN_FREQ, DEFAULT_MAX_FREQ);
+
+        Self {
+            sample_rate,
+            channels: channels.max(1),
+            hop_size: ((sample_rate as f32 / FRAME_RATE).round() as usize).clamp(1, WINDOW_SIZE),
+
+            fft,
+            window,
+
+            pending: VecDeque::with_capacity(WINDOW_SIZE * 2),
+            previous_spectrum: vec![0.0; WINDOW_SIZE / 2],
+
+            band_envelopes: vec![Envelope::default(); bands.len()],
+            bands,
+            bass: Envelope::default(),
+            mid: Envelope::default(),
+            treble: Envelope::default(),
+            attack: DEFAULT_ATTACK,
+            release: DEFAULT_RELEASE,
+
+            flux_history: VecDeque::new(),
+
+            frames: 0,
+            last_onset_frame: None,
+            onsets: 0,
+            bpm: 0.0,
+        }
+    }
+
+    /// Use `count` log-spaced bands, covering the range from `min_freq` to `max_freq` Hz.
+    /// There are never more bands than FFT bins
+    pub fn set_bands(&mut self, count: usize, min_freq: f32, max_freq: f32) {
+        let count = count.min(WINDOW_SIZE / 2);
+        let nyquist = self.sample_rate as f32 / 2.0;
+
+        let min_freq = min_freq.clamp(1.0, nyquist - 1.0);
+        let max_freq = max_freq.clamp(min_freq + 1.0, nyquist);
+
+        self.bands = log_spaced_bands(count, min_freq, max_freq);
+        self.band_envelopes = vec![Envelope::default(); self.bands.len()];
+    }
+
+    /// Set the attack and release coefficients of the envelope followers, in the range [0..1].
+    /// Higher values make the envelopes follow the signal more quickly
+    pub fn set_smoothing(&mut self, attack: f32, release: f32) {
+        self.attack = attack.clamp(0.0, 1.0);
+        self.release = release.clamp(0.0, 1.0);
+    }
+
+    pub fn bands(&self) -> &[FrequencyBand] {
+        &self.bands
+    }
+
+    /// The smoothed and normalized envelopes of the configured bands, in the range [0..1]
+    pub fn band_envelopes(&self) -> Vec<f32> {
+        self.band_envelopes.iter().map(|e| e.normalized()).collect()
+    }
+
+    /// The smoothed envelopes of the bass, mid and treble ranges, in the range [0..1]
+    pub fn bass_mid_treble(&self) -> (f32, f32, f32) {
+        // the ranges are normalized by a common peak, so that they may be compared to each other
+        let peak = self.bass.peak.max(self.mid.peak).max(self.treble.peak);
+
+        (
+            self.bass.value / peak,
+            self.mid.value / peak,
+            self.treble.value / peak,
+        )
+    }
+
+    /// Returns `true` for a short amount of time after an onset has been detected
+    pub fn is_beat(&self) -> bool {
+        let hold = (BEAT_HOLD_SECS * self.frame_rate()).ceil() as u64;
+
+        self.last_onset_frame
+            .map(|frame| self.frames - frame <= hold)
+            .unwrap_or(false)
+    }
+
+    /// The total number of onsets detected so far
+    pub fn onsets(&self) -> u64 {
+        self.onsets
+    }
+
+    /// The estimated tempo in beats per minute, or 0 if no tempo could be estimated
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    fn frame_rate(&self) -> f32 {
+        self.sample_rate as f32 / self.hop_size as f32
+    }
+
+    /// Analyze a buffer of interleaved 16-bit samples
+    pub fn process(&mut self, samples: &[i16]) {
+        for frame in samples.chunks_exact(self.channels) {
+            let mono = frame.iter().map(|s| *s as f32).sum::<f32>()
+                / (self.channels as f32 * i16::MAX as f32);
+
+            self.pending.push_back(mono);
+        }
+
+        while self.pending.len() >= WINDOW_SIZE {
+            self.analyze_frame();
+
+            self.pending.drain(..self.hop_size);
+        }
+    }
+
+    fn analyze_frame(&mut self) {
+        let mut buffer: Vec<Complex<f32>> = self
+            .pending
+            .iter()
+            .take(WINDOW_SIZE)
+            .zip(self.window.iter())
+            .map(|(s, w)| Complex::from(s * w))
+            .collect();
+
+        self.fft.process(&mut buffer);
+
+        let spectrum: Vec<f32> = buffer[..WINDOW_SIZE / 2]
+            .iter()
+            .map(|c| c.norm() / WINDOW_SIZE as f32)
+            .collect();
+
+        self.frames += 1;
+
+        self.update_envelopes(&spectrum);
+        self.detect_onset(&spectrum);
+
+        self.previous_spectrum = spectrum;
+
+        self.estimate_tempo();
+    }
+
+    fn band_level(&self, spectrum: &[f32], band: &FrequencyBand) -> f32 {
+        let (low, high) = band.bins(self.sample_rate);
+
+        // root mean square of the magnitudes of all bins of the band
+        let sum = spectrum[low..high].iter().map(|m| m * m).sum::<f32>();
+
+        (sum / (high - low) as f32).sqrt()
+    }
+
+    fn update_envelopes(&mut self, spectrum: &[f32]) {
+        let (attack, release) = (self.attack, self.release);
+
+        let levels: Vec<f32> = self
+            .bands
+            .iter()
+            .map(|band| self.band_level(spectrum, band))
+            .collect();
+
+        for (envelope, level) in self.band_envelopes.iter_mut().zip(levels) {
+            envelope.update(level, attack, release);
+        }
+
+        let nyquist = self.sample_rate as f32 / 2.0;
+
+        let bass = self.band_level(
+            spectrum,
+            &FrequencyBand {
+                low: DEFAULT_MIN_FREQ,
+                high: BASS_MAX_FREQ,
+            },
+        );
+        let mid = self.band_level(
+            spectrum,
+            &FrequencyBand {
+                low: BASS_MAX_FREQ,
+                high: MID_MAX_FREQ,
+            },
+        );
+        let treble = self.band_level(
+            spectrum,
+            &FrequencyBand {
+                low: MID_MAX_FREQ,
+                high: DEFAULT_MAX_FREQ.min(nyquist),
+            },
+        );
+
+        self.bass.update(bass, attack, release);
+        self.mid.update(mid, attack, release);
+        self.treble.update(treble, attack, release);
+    }
+
+    fn detect_onset(&mut self, spectrum: &[f32]) {
+        // half-wave rectified spectral flux of the log-compressed magnitudes
+        let flux = spectrum
+            .iter()
+            .zip(self.previous_spectrum.iter())
+            .map(|(current, previous)| {
+                ((1.0 + 100.0 * current).ln() - (1.0 + 100.0 * previous).ln()).max(0.0)
+            })
+            .sum::<f32>();
+
+        let threshold_len = (THRESHOLD_HISTORY_SECS * self.frame_rate()) as usize;
+        let recent = self
+            .flux_history
+            .iter()
+            .rev()
+            .take(threshold_len)
+            .copied()
+            .collect::<Vec<_>>();
+
+        if recent.len() >= threshold_len / 2 {
+            let mean = recent.iter().sum::<f32>() / recent.len() as f32;
+            let variance =
+                recent.iter().map(|f| (f - mean).powi(2)).sum::<f32>() / recent.len() as f32;
+
+            let threshold = mean + 2.0 * variance.sqrt() + 0.1;
+
+            let min_interval = (MIN_ONSET_INTERVAL_SECS * self.frame_rate()) as u64;
+            let is_spaced = self
+                .last_onset_frame
+                .map(|frame| self.frames - frame >= min_interval)
+                .unwrap_or(true);
+
+            if flux > threshold && is_spaced {
+                self.last_onset_frame = Some(self.frames);
+                self.onsets += 1;
+            }
+        }
+
+        let tempo_len = (TEMPO_HISTORY_SECS * self.frame_rate()) as usize;
+
+        self.flux_history.push_back(flux);
+
+        while self.flux_history.len() > tempo_len {
+            self.flux_history.pop_front();
+        }
+    }
+
+    fn estimate_tempo(&mut self) {
+        let frame_rate = self.frame_rate();
+
+        let min_lag = (60.0 * frame_rate / MAX_BPM).floor() as usize;
+        let max_lag = (60.0 * frame_rate / MIN_BPM).ceil() as usize;
+
+        // require at least two periods of the slowest tempo
+        if self.flux_history.len() < max_lag * 2 {
+            return;
+        }
+
+        let mean = self.flux_history.iter().sum::<f32>() / self.flux_history.len() as f32;
+        let flux: Vec<f32> = self.flux_history.iter().map(|f| f - mean).collect();
+
+        let autocorrelation = |lag: usize| -> f32 {
+            flux.iter()
+                .zip(flux[lag..].iter())
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (flux.len() - lag) as f32
+        };
+
+        let values: Vec<f32> = (min_lag - 1..=max_lag + 1).map(autocorrelation).collect();
+
+        // weight the autocorrelation with a log-gaussian prior, centered around the preferred tempo
+        let weight = |lag: f32| {
+            let bpm = 60.0 * frame_rate / lag;
+            (-0.5 * (bpm / PREFERRED_BPM).log2().powi(2)).exp()
+        };
+
+        let best = (1..values.len() - 1)
+            .max_by(|a, b| {
+                let a = values[*a] * weight((min_lag - 1 + a) as f32);
+                let b = values[*b] * weight((min_lag - 1 + b) as f32);
+
+                a.total_cmp(&b)
+            })
+            .unwrap();
+
+        if values[best] <= 0.0 {
+            self.bpm = 0.0;
+            return;
+        }
+
+        // refine the lag using parabolic interpolation
+        let (y0, y1, y2) = (values[best - 1], values[best], values[best + 1]);
+        let denominator = y0 - 2.0 * y1 + y2;
+
+        let offset = if denominator.abs() > f32::EPSILON {
+            (0.5 * (y0 - y2) / denominator).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+
+        let lag = (min_lag - 1 + best) as f32 + offset;
+
+        self.bpm = (60.0 * frame_rate / lag).clamp(MIN_BPM, MAX_BPM);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{log_spaced_bands, AudioAnalyzer, WINDOW_SIZE};
+    use std::f32::consts::PI;
+    use std::path::PathBuf;
+
+    fn load_wav(file_name: &str) -> (hound::WavSpec, Vec<i16>) {
+        let path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("../support/tests/assets/audio")
+            .join(file_name);
+        let mut reader = hound::WavReader::open(path).unwrap();
+
+        let spec = reader.spec();
+        let samples = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+
+        (spec, samples)
+    }
+
+    #[test]
+    fn bands_are_log_spaced() {
+        let bands = log_spaced_bands(3, 20.0, 20000.0);
+
+        assert_eq!(bands.len(), 3);
+
+        assert!((bands[0].low - 20.0).abs() < 0.01);
+        assert!((bands[0].high - 200.0).abs() < 0.01);
+        assert!((bands[1].high - 2000.0).abs() < 0.1);
+        assert!((bands[2].high - 20000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn envelopes_before_the_first_frame() {
+        let mut analyzer = AudioAnalyzer::new(44100, 2);
+
+        assert_eq!(analyzer.bass_mid_treble(), (0.0, 0.0, 0.0));
+        assert!(analyzer.band_envelopes().iter().all(|e| *e == 0.0));
+
+        analyzer.set_bands(4, 20.0, 11000.0);
+
+        assert!(analyzer.band_envelopes().iter().all(|e| *e == 0.0));
+    }
+
+    #[test]
+    fn number_of_bands_is_limited() {
+        let mut analyzer = AudioAnalyzer::new(44100, 2);
+
+        analyzer.set_bands(usize::MAX, 20.0, 11000.0);
+
+        assert_eq!(analyzer.bands().len(), WINDOW_SIZE / 2);
+        assert_eq!(analyzer.band_envelopes().len(), WINDOW_SIZE / 2);
+
+        analyzer.set_bands(0, 20.0, 11000.0);
+
+        assert_eq!(analyzer.bands().len(), 1);
+    }
+
+    #[test]
+    fn detect_beats_and_tempo() {
+        // a kick drum pattern at 120 BPM, the first beat starts after 250 milliseconds
+        let (spec, samples) = load_wav("beat_120bpm.wav");
+
+        let mut analyzer = AudioAnalyzer::new(spec.sample_rate, spec.channels as usize);
+
+        // feed the analyzer in chunks, like the audio grabber would
+        for chunk in samples.chunks(2040) {
+            analyzer.process(chunk);
+        }
+
+        let duration = samples.len() as f32 / (spec.sample_rate as f32 * spec.channels as f32);
+        let expected_beats = ((duration - 0.25) * 2.0).ceil() as u64;
+
+        assert!(
+            analyzer.onsets() >= expected_beats - 1 && analyzer.onsets() <= expected_beats,
+            "onsets: {}, expected: {}",
+            analyzer.onsets(),
+            expected_beats
+        );
+
+        assert!(
+            (analyzer.bpm() - 120.0).abs() < 3.0,
+            "bpm: {}",
+            analyzer.bpm()
+        );
+    }
+
+    #[test]
+    fn follow_frequency_bands() {
+        // one second each of 100 Hz, 1 kHz and 8 kHz sine waves
+        let (spec, samples) = load_wav("tones.wav");
+
+        let mut analyzer = AudioAnalyzer::new(spec.sample_rate, spec.channels as usize);
+        analyzer.set_bands(3, 20.0, 11000.0);
+
+        let second = (spec.sample_rate * spec.channels as u32) as usize;
+
+        for (i, tone) in samples.chunks(second).enumerate() {
+            analyzer.process(tone);
+
+            let (bass, mid, treble) = analyzer.bass_mid_treble();
+            let envelopes = analyzer.band_envelopes();
+
+            let loudest_band = (0..envelopes.len())
+                .max_by(|a, b| {
+                    let a = analyzer.band_envelopes[*a].value;
+                    let b = analyzer.band_envelopes[*b].value;
+
+                    a.total_cmp(&b)
+                })
+                .unwrap();
+
+            match i {
+                0 => assert!(bass > mid && bass > treble),
+                1 => assert!(mid > bass && mid > treble),
+                _ => assert!(treble > bass && treble > mid),
+            }
+
+            assert_eq!(loudest_band, i);
+        }
+
+        // silence does not trigger any beats
+        let mut analyzer = AudioAnalyzer::new(spec.sample_rate, 1);
+        analyzer.process(&vec![0; spec.sample_rate as usize * 2]);
+
+        assert_eq!(analyzer.onsets(), 0);
+        assert!(!analyzer.is_beat());
+        assert_eq!(analyzer.bpm(), 0.0);
+
+        // a steady tone does not trigger any beats either, after it has started
+        let tone: Vec<i16> = (0..spec.sample_rate * 2)
+            .map(|i| {
+                ((2.0 * PI * 440.0 * i as f32 / spec.sample_rate as f32).sin() * 8000.0) as i16
+            })
+            .collect();
+
+        analyzer.process(&tone);
+
+        assert!(analyzer.onsets() <= 1);
+    }
+}
@@ -0,0 +1,202 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! Parser for Distributed Display Protocol (DDP) packets, as used by e.g. xLights and WLED
+
+/// The well-known UDP port of DDP
+pub const DEFAULT_PORT: u16 = 4048;
+
+/// Upper bound of the size of the frame buffer, that we are willing to maintain
+pub const MAX_BUFFER_SIZE: usize = 64 * 1024;
+
+const HEADER_SIZE: usize = 10;
+const TIMECODE_SIZE: usize = 4;
+
+const VERSION_MASK: u8 = 0b1100_0000;
+const VERSION_1: u8 = 0b0100_0000;
+const FLAG_TIMECODE: u8 = 0b0001_0000;
+const FLAG_STORAGE: u8 = 0b0000_1000;
+const FLAG_REPLY: u8 = 0b0000_0100;
+const FLAG_QUERY: u8 = 0b0000_0010;
+const FLAG_PUSH: u8 = 0b0000_0001;
+
+/// Destination id of the default output device
+const ID_DISPLAY: u8 = 1;
+
+/// Destination id that addresses all devices
+const ID_ALL: u8 = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DdpError {
+    #[error("Packet too short: {len} bytes")]
+    Truncated { len: usize },
+
+    #[error("Unsupported protocol version: {version}")]
+    UnsupportedVersion { version: u8 },
+}
+
+/// The result of parsing a single DDP packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet<'a> {
+    /// Pixel data for the display
+    Data(DataPacket<'a>),
+
+    /// Queries, replies, and packets addressed to other destinations; not used by Eruption
+    Ignored,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataPacket<'a> {
+    pub sequence: u8,
+    pub push: bool,
+
+    /// Byte offset of `data` into the frame buffer of the display
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+/// Parse a received UDP datagram
+pub fn parse_packet(buffer: &[u8]) -> Result<Packet<'_>, DdpError> {
+    if buffer.len() < HEADER_SIZE {
+        return Err(DdpError::Truncated { len: buffer.len() });
+    }
+
+    let flags = buffer[0];
+    if flags & VERSION_MASK != VERSION_1 {
+        return Err(DdpError::UnsupportedVersion {
+            version: (flags & VERSION_MASK) >> 6,
+        });
+    }
+
+    let id = buffer[3];
+    if flags & (FLAG_STORAGE | FLAG_REPLY | FLAG_QUERY) != 0 || (id != ID_DISPLAY && id != ID_ALL) {
+        return Ok(Packet::Ignored);
+    }
+
+    let offset = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+    let len = u16::from_be_bytes([buffer[8], buffer[9]]) as usize;
+
+    let header_size = if flags & FLAG_TIMECODE != 0 {
+        HEADER_SIZE + TIMECODE_SIZE
+    } else {
+        HEADER_SIZE
+    };
+
+    if buffer.len() < header_size + len {
+        return Err(DdpError::Truncated { len: buffer.len() });
+    }
+
+    Ok(Packet::Data(DataPacket {
+        sequence: buffer[1] & 0x0f,
+        push: flags & FLAG_PUSH != 0,
+        offset,
+        data: &buffer[header_size..header_size + len],
+    }))
+}
+
+/// Copy the payload of `packet` into the frame buffer, growing it as needed. Data
+/// beyond `MAX_BUFFER_SIZE` is discarded
+pub fn update_buffer(buffer: &mut Vec<u8>, packet: &DataPacket) {
+    if packet.offset >= MAX_BUFFER_SIZE {
+        return;
+    }
+
+    let end = (packet.offset + packet.data.len()).min(MAX_BUFFER_SIZE);
+    if buffer.len() < end {
+        buffer.resize(end, 0);
+    }
+
+    buffer[packet.offset..end].copy_from_slice(&packet.data[..end - packet.offset]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_packet(flags: u8, offset: u32, data: &[u8]) -> Vec<u8> {
+        let mut result = vec![VERSION_1 | flags, 0x03, 0x0b, ID_DISPLAY];
+
+        result.extend_from_slice(&offset.to_be_bytes());
+        result.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        result.extend_from_slice(data);
+
+        result
+    }
+
+    #[test]
+    fn test_parse_data_packet() {
+        let buffer = data_packet(FLAG_PUSH, 6, &[1, 2, 3, 4, 5, 6]);
+
+        match parse_packet(&buffer).unwrap() {
+            Packet::Data(packet) => {
+                assert_eq!(packet.sequence, 3);
+                assert!(packet.push);
+                assert_eq!(packet.offset, 6);
+                assert_eq!(packet.data, &[1, 2, 3, 4, 5, 6]);
+
+                let mut frame = vec![];
+                update_buffer(&mut frame, &packet);
+                assert_eq!(frame, vec![0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6]);
+            }
+
+            _ => panic!("Expected a data packet"),
+        }
+
+        // a timecode is skipped
+        let mut buffer = data_packet(FLAG_TIMECODE, 0, &[]);
+        buffer[8..10].copy_from_slice(&3u16.to_be_bytes());
+        buffer.extend_from_slice(&[0xff; TIMECODE_SIZE]);
+        buffer.extend_from_slice(&[7, 8, 9]);
+
+        match parse_packet(&buffer).unwrap() {
+            Packet::Data(packet) => {
+                assert!(!packet.push);
+                assert_eq!(packet.data, &[7, 8, 9]);
+            }
+
+            _ => panic!("Expected a data packet"),
+        }
+    }
+
+    #[test]
+    fn test_reject_invalid_packets() {
+        assert_eq!(
+            parse_packet(&[0x41, 0, 0]),
+            Err(DdpError::Truncated { len: 3 })
+        );
+
+        let mut buffer = data_packet(0, 0, &[1, 2, 3]);
+        buffer[0] = 0b1000_0000;
+        assert_eq!(
+            parse_packet(&buffer),
+            Err(DdpError::UnsupportedVersion { version: 2 })
+        );
+
+        let buffer = data_packet(0, 0, &[1, 2, 3]);
+        assert_eq!(
+            parse_packet(&buffer[..11]),
+            Err(DdpError::Truncated { len: 11 })
+        );
+
+        let buffer = data_packet(FLAG_QUERY, 0, &[]);
+        assert_eq!(parse_packet(&buffer), Ok(Packet::Ignored));
+    }
+}
@@ -0,0 +1,309 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! Parser for E1.31 (Streaming ACN, "sACN") data packets, as specified in ANSI E1.31-2018
+
+use std::net::Ipv4Addr;
+
+/// The well-known UDP port of E1.31
+pub const DEFAULT_PORT: u16 = 5568;
+
+/// Highest universe number that may be used for DMX data
+pub const MAX_UNIVERSE: u16 = 63999;
+
+/// Maximum number of DMX slots (channels) per universe
+pub const MAX_CHANNELS: usize = 512;
+
+const ACN_PACKET_IDENTIFIER: &[u8; 12] = b"ASC-E1.17\0\0\0";
+
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_ROOT_E131_EXTENDED: u32 = 0x0000_0008;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const DMP_ADDRESS_AND_DATA_TYPE: u8 = 0xa1;
+
+/// Offset of the first DMX slot (the start code) in a data packet
+const DMX_START_CODE_OFFSET: usize = 125;
+
+const OPTION_PREVIEW_DATA: u8 = 0b1000_0000;
+const OPTION_STREAM_TERMINATED: u8 = 0b0100_0000;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum E131Error {
+    #[error("Packet too short: {len} bytes")]
+    Truncated { len: usize },
+
+    #[error("Not an ACN packet")]
+    InvalidIdentifier,
+
+    #[error("Unsupported vector: {vector:#x}")]
+    UnsupportedVector { vector: u32 },
+
+    #[error("Invalid DMP layer")]
+    InvalidDmpLayer,
+
+    #[error("Universe out of range: {universe}")]
+    InvalidUniverse { universe: u16 },
+}
+
+/// The result of parsing a single E1.31 packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet<'a> {
+    /// DMX512-A data for a single universe
+    Data(DataPacket<'a>),
+
+    /// Synchronization and universe discovery packets; not used by Eruption
+    Extended,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataPacket<'a> {
+    pub source_name: String,
+    pub priority: u8,
+    pub sequence: u8,
+    pub preview: bool,
+    pub stream_terminated: bool,
+    pub universe: u16,
+    pub start_code: u8,
+
+    /// The DMX slots following the start code, `channels[0]` is DMX channel 1
+    pub channels: &'a [u8],
+}
+
+impl DataPacket<'_> {
+    /// Returns `true` if the packet carries regular dimmer data that should be displayed
+    pub fn is_displayable(&self) -> bool {
+        self.start_code == 0x00 && !self.preview && !self.stream_terminated
+    }
+}
+
+fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        buffer[offset],
+        buffer[offset + 1],
+        buffer[offset + 2],
+        buffer[offset + 3],
+    ])
+}
+
+/// Parse a received UDP datagram
+pub fn parse_packet(buffer: &[u8]) -> Result<Packet<'_>, E131Error> {
+    // root layer
+    if buffer.len() < 22 {
+        return Err(E131Error::Truncated { len: buffer.len() });
+    }
+
+    if read_u16(buffer, 0) != 0x0010 || &buffer[4..16] != ACN_PACKET_IDENTIFIER {
+        return Err(E131Error::InvalidIdentifier);
+    }
+
+    match read_u32(buffer, 18) {
+        VECTOR_ROOT_E131_DATA => {}
+        VECTOR_ROOT_E131_EXTENDED => return Ok(Packet::Extended),
+        vector => return Err(E131Error::UnsupportedVector { vector }),
+    }
+
+    if buffer.len() < DMX_START_CODE_OFFSET + 1 {
+        return Err(E131Error::Truncated { len: buffer.len() });
+    }
+
+    // framing layer
+    let vector = read_u32(buffer, 40);
+    if vector != VECTOR_E131_DATA_PACKET {
+        return Err(E131Error::UnsupportedVector { vector });
+    }
+
+    let source_name = &buffer[44..108];
+    let source_name = &source_name[..source_name
+        .iter()
+        .position(|c| *c == 0)
+        .unwrap_or(source_name.len())];
+    let source_name = String::from_utf8_lossy(source_name).to_string();
+
+    let priority = buffer[108];
+    let sequence = buffer[111];
+    let options = buffer[112];
+
+    let universe = read_u16(buffer, 113);
+    if universe == 0 || universe > MAX_UNIVERSE {
+        return Err(E131Error::InvalidUniverse { universe });
+    }
+
+    // DMP layer
+    if buffer[117] != VECTOR_DMP_SET_PROPERTY
+        || buffer[118] != DMP_ADDRESS_AND_DATA_TYPE
+        || read_u16(buffer, 121) != 0x0001
+    {
+        return Err(E131Error::InvalidDmpLayer);
+    }
+
+    // the property value count includes the start code
+    let count = read_u16(buffer, 123) as usize;
+    if count == 0 || count > MAX_CHANNELS + 1 {
+        return Err(E131Error::InvalidDmpLayer);
+    }
+
+    if buffer.len() < DMX_START_CODE_OFFSET + count {
+        return Err(E131Error::Truncated { len: buffer.len() });
+    }
+
+    Ok(Packet::Data(DataPacket {
+        source_name,
+        priority,
+        sequence,
+        preview: options & OPTION_PREVIEW_DATA != 0,
+        stream_terminated: options & OPTION_STREAM_TERMINATED != 0,
+        universe,
+        start_code: buffer[DMX_START_CODE_OFFSET],
+        channels: &buffer[DMX_START_CODE_OFFSET + 1..DMX_START_CODE_OFFSET + count],
+    }))
+}
+
+/// Returns `true` if a packet with sequence number `sequence` arrived out of order,
+/// relative to the last packet of the same universe (section 6.7.2 of the standard)
+pub fn is_out_of_order(last_sequence: u8, sequence: u8) -> bool {
+    let diff = sequence.wrapping_sub(last_sequence) as i8;
+
+    diff <= 0 && diff > -20
+}
+
+/// The multicast group that carries the data of `universe`
+pub fn multicast_address(universe: u16) -> Ipv4Addr {
+    let [hi, lo] = universe.to_be_bytes();
+
+    Ipv4Addr::new(239, 255, hi, lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_packet(universe: u16, sequence: u8, options: u8, channels: &[u8]) -> Vec<u8> {
+        let count = channels.len() + 1;
+        let len = DMX_START_CODE_OFFSET + count;
+        let mut result = vec![0u8; len];
+
+        // root layer
+        result[0..2].copy_from_slice(&0x0010u16.to_be_bytes());
+        result[4..16].copy_from_slice(ACN_PACKET_IDENTIFIER);
+        result[16..18].copy_from_slice(&(0x7000 | (len - 16) as u16).to_be_bytes());
+        result[18..22].copy_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+        result[22..38].copy_from_slice(&[0x42; 16]);
+
+        // framing layer
+        result[38..40].copy_from_slice(&(0x7000 | (len - 38) as u16).to_be_bytes());
+        result[40..44].copy_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+        result[44..52].copy_from_slice(b"test src");
+        result[108] = 100;
+        result[111] = sequence;
+        result[112] = options;
+        result[113..115].copy_from_slice(&universe.to_be_bytes());
+
+        // DMP layer
+        result[115..117].copy_from_slice(&(0x7000 | (len - 115) as u16).to_be_bytes());
+        result[117] = VECTOR_DMP_SET_PROPERTY;
+        result[118] = DMP_ADDRESS_AND_DATA_TYPE;
+        result[121..123].copy_from_slice(&0x0001u16.to_be_bytes());
+        result[123..125].copy_from_slice(&(count as u16).to_be_bytes());
+        result[DMX_START_CODE_OFFSET + 1..].copy_from_slice(channels);
+
+        result
+    }
+
+    #[test]
+    fn test_parse_data_packet() {
+        let buffer = data_packet(7, 42, 0, &[255, 128, 0, 1, 2, 3]);
+
+        match parse_packet(&buffer).unwrap() {
+            Packet::Data(packet) => {
+                assert_eq!(packet.source_name, "test src");
+                assert_eq!(packet.priority, 100);
+                assert_eq!(packet.sequence, 42);
+                assert_eq!(packet.universe, 7);
+                assert_eq!(packet.channels, &[255, 128, 0, 1, 2, 3]);
+                assert!(packet.is_displayable());
+            }
+
+            _ => panic!("Expected a data packet"),
+        }
+
+        let buffer = data_packet(7, 43, OPTION_STREAM_TERMINATED, &[0; 3]);
+
+        match parse_packet(&buffer).unwrap() {
+            Packet::Data(packet) => {
+                assert!(packet.stream_terminated);
+                assert!(!packet.is_displayable());
+            }
+
+            _ => panic!("Expected a data packet"),
+        }
+    }
+
+    #[test]
+    fn test_reject_invalid_packets() {
+        let buffer = data_packet(1, 0, 0, &[1, 2, 3]);
+
+        assert_eq!(
+            parse_packet(&buffer[..100]),
+            Err(E131Error::Truncated { len: 100 })
+        );
+
+        let mut invalid = buffer.clone();
+        invalid[4] = b'X';
+        assert_eq!(parse_packet(&invalid), Err(E131Error::InvalidIdentifier));
+
+        let invalid = data_packet(0, 0, 0, &[1, 2, 3]);
+        assert_eq!(
+            parse_packet(&invalid),
+            Err(E131Error::InvalidUniverse { universe: 0 })
+        );
+
+        // property value count exceeds the size of the packet
+        let mut invalid = buffer;
+        invalid[123..125].copy_from_slice(&16u16.to_be_bytes());
+        assert!(matches!(
+            parse_packet(&invalid),
+            Err(E131Error::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sequence_numbers() {
+        assert!(!is_out_of_order(10, 11));
+        assert!(is_out_of_order(10, 10));
+        assert!(is_out_of_order(10, 5));
+        assert!(!is_out_of_order(255, 0));
+        assert!(is_out_of_order(2, 250));
+
+        // a large jump backwards is treated as a restarted source
+        assert!(!is_out_of_order(100, 50));
+
+        assert_eq!(multicast_address(1), Ipv4Addr::new(239, 255, 0, 1));
+        assert_eq!(
+            multicast_address(0x1234),
+            Ipv4Addr::new(239, 255, 0x12, 0x34)
+        );
+    }
+}
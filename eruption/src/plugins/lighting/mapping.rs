@@ -0,0 +1,316 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! The mapping file, that assigns E1.31 and DDP channels to zones on the canvas
+
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::hwdevices::RGBA;
+
+use super::e131;
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MappingError {
+    #[error("Invalid E1.31 universe: {universe}")]
+    InvalidUniverse { universe: u16 },
+
+    #[error("Mapping of universe {universe} exceeds the available DMX channels")]
+    ChannelOutOfRange { universe: u16 },
+}
+
+/// Order of the color components in the received channel data
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelOrder {
+    #[default]
+    Rgb,
+    Rbg,
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+impl ChannelOrder {
+    fn to_rgb(self, c: &[u8]) -> (u8, u8, u8) {
+        match self {
+            ChannelOrder::Rgb => (c[0], c[1], c[2]),
+            ChannelOrder::Rbg => (c[0], c[2], c[1]),
+            ChannelOrder::Grb => (c[1], c[0], c[2]),
+            ChannelOrder::Gbr => (c[2], c[0], c[1]),
+            ChannelOrder::Brg => (c[1], c[2], c[0]),
+            ChannelOrder::Bgr => (c[2], c[1], c[0]),
+        }
+    }
+}
+
+/// Maps a range of DMX channels of an E1.31 universe onto the canvas
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct E131Mapping {
+    pub universe: u16,
+
+    /// The first DMX channel, starting at 1
+    #[serde(default = "default_channel")]
+    pub channel: usize,
+
+    /// Index of the first LED on the canvas, starting at 0
+    pub canvas_index: usize,
+
+    /// Number of LEDs, each LED occupies three channels
+    pub count: usize,
+
+    #[serde(default)]
+    pub order: ChannelOrder,
+}
+
+/// Maps a range of the DDP frame buffer onto the canvas
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DdpMapping {
+    /// Byte offset into the frame buffer, starting at 0
+    #[serde(default)]
+    pub offset: usize,
+
+    /// Index of the first LED on the canvas, starting at 0
+    pub canvas_index: usize,
+
+    /// Number of LEDs, each LED occupies three bytes
+    pub count: usize,
+
+    #[serde(default)]
+    pub order: ChannelOrder,
+}
+
+fn default_channel() -> usize {
+    1
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct Mapping {
+    #[serde(default)]
+    pub e131: Vec<E131Mapping>,
+
+    #[serde(default)]
+    pub ddp: Vec<DdpMapping>,
+}
+
+impl Mapping {
+    /// Load and validate a mapping file
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &str) -> Result<Self> {
+        let result = toml::from_str::<Mapping>(data)?;
+
+        for mapping in result.e131.iter() {
+            if mapping.universe == 0 || mapping.universe > e131::MAX_UNIVERSE {
+                return Err(MappingError::InvalidUniverse {
+                    universe: mapping.universe,
+                }
+                .into());
+            }
+
+            let last_channel = mapping
+                .count
+                .checked_mul(3)
+                .and_then(|n| n.checked_add(mapping.channel.checked_sub(1)?));
+
+            if !matches!(last_channel, Some(n) if n <= e131::MAX_CHANNELS) {
+                return Err(MappingError::ChannelOutOfRange {
+                    universe: mapping.universe,
+                }
+                .into());
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// All universes that are referenced by the mapping
+    pub fn universes(&self) -> BTreeSet<u16> {
+        self.e131.iter().map(|m| m.universe).collect()
+    }
+
+    /// Apply the DMX data of `universe` to the LED map
+    pub fn apply_e131(&self, universe: u16, channels: &[u8], led_map: &mut [RGBA]) {
+        for mapping in self.e131.iter().filter(|m| m.universe == universe) {
+            apply(
+                &channels[(mapping.channel - 1).min(channels.len())..],
+                mapping.canvas_index,
+                mapping.count,
+                mapping.order,
+                led_map,
+            );
+        }
+    }
+
+    /// Make the zones of `universe` transparent again, e.g. after the source terminated its stream
+    pub fn clear_e131(&self, universe: u16, led_map: &mut [RGBA]) {
+        for mapping in self.e131.iter().filter(|m| m.universe == universe) {
+            clear(mapping.canvas_index, mapping.count, led_map);
+        }
+    }
+
+    /// Apply the DDP frame buffer to the LED map
+    pub fn apply_ddp(&self, buffer: &[u8], led_map: &mut [RGBA]) {
+        for mapping in self.ddp.iter() {
+            apply(
+                &buffer[mapping.offset.min(buffer.len())..],
+                mapping.canvas_index,
+                mapping.count,
+                mapping.order,
+                led_map,
+            );
+        }
+    }
+}
+
+/// Convert up to `count` triplets of `data` to opaque colors; LEDs that lie beyond
+/// the end of the canvas, or that are not covered by `data`, are left untouched
+fn apply(
+    data: &[u8],
+    canvas_index: usize,
+    count: usize,
+    order: ChannelOrder,
+    led_map: &mut [RGBA],
+) {
+    let leds = led_map.iter_mut().skip(canvas_index).take(count);
+
+    for (color, c) in leds.zip(data.chunks_exact(3)) {
+        let (r, g, b) = order.to_rgb(c);
+
+        *color = RGBA { r, g, b, a: 255 };
+    }
+}
+
+fn clear(canvas_index: usize, count: usize, led_map: &mut [RGBA]) {
+    for color in led_map.iter_mut().skip(canvas_index).take(count) {
+        *color = RGBA {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn transparent(count: usize) -> Vec<RGBA> {
+        vec![
+            RGBA {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            };
+            count
+        ]
+    }
+
+    #[test]
+    fn test_load_mapping() -> Result<()> {
+        let path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let mapping = Mapping::load(&path.join("../support/tests/assets/lighting.toml"))?;
+
+        assert_eq!(mapping.e131.len(), 2);
+        assert_eq!(mapping.ddp.len(), 1);
+        assert_eq!(
+            mapping.universes().into_iter().collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(mapping.e131[1].order, ChannelOrder::Grb);
+        assert_eq!(mapping.ddp[0].count, 36);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_mapping() {
+        assert!(Mapping::parse("[[e131]]\nuniverse = 0\ncanvas_index = 0\ncount = 1\n").is_err());
+        assert!(Mapping::parse(
+            "[[e131]]\nuniverse = 1\nchannel = 500\ncanvas_index = 0\ncount = 5\n"
+        )
+        .is_err());
+        assert!(Mapping::parse(
+            "[[e131]]\nuniverse = 1\ncanvas_index = 0\ncount = 6148914691236517206\n"
+        )
+        .is_err());
+        assert!(Mapping::parse("[[ddp]]\ncanvas_index = 0\n").is_err());
+    }
+
+    #[test]
+    fn test_apply_mapping() -> Result<()> {
+        let mapping = Mapping::parse(
+            r#"
+            [[e131]]
+            universe = 1
+            channel = 4
+            canvas_index = 1
+            count = 2
+            order = "bgr"
+
+            [[ddp]]
+            offset = 3
+            canvas_index = 3
+            count = 4
+            "#,
+        )?;
+
+        let mut led_map = transparent(5);
+        mapping.apply_e131(1, &[9, 9, 9, 1, 2, 3, 4, 5, 6], &mut led_map);
+        mapping.apply_e131(2, &[9, 9, 9, 9, 9, 9], &mut led_map);
+
+        assert_eq!(led_map[0].a, 0);
+        assert_eq!(
+            (led_map[1].r, led_map[1].g, led_map[1].b, led_map[1].a),
+            (3, 2, 1, 255)
+        );
+        assert_eq!(
+            (led_map[2].r, led_map[2].g, led_map[2].b, led_map[2].a),
+            (6, 5, 4, 255)
+        );
+        assert_eq!(led_map[3].a, 0);
+
+        // clipped to the end of the canvas, and to the available data
+        mapping.apply_ddp(&[0, 0, 0, 10, 20, 30, 40, 50, 60, 70], &mut led_map);
+        assert_eq!((led_map[3].r, led_map[3].g, led_map[3].b), (10, 20, 30));
+        assert_eq!((led_map[4].r, led_map[4].g, led_map[4].b), (40, 50, 60));
+
+        mapping.clear_e131(1, &mut led_map);
+        assert_eq!(led_map[1].a, 0);
+        assert_eq!(led_map[2].a, 0);
+        assert_eq!(led_map[3].a, 255);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! Receivers for the network lighting protocols E1.31 (sACN) and DDP, that allow
+//! lighting consoles and sequencers (e.g. QLC+ or xLights) to drive the canvas.
+//!
+//! Received channels are assigned to zones on the canvas by a mapping file, and the
+//! resulting LED map is blended over the output of the Lua scripts
+
+use lazy_static::lazy_static;
+use log::{debug, error, info, trace, warn};
+use mlua::prelude::*;
+use parking_lot::{Mutex, RwLock};
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::hwdevices::RGBA;
+use crate::plugins::{self, Plugin};
+use crate::scripting::script::FRAME_GENERATION_COUNTER;
+use crate::{canvas, constants, LIGHTING_SUPPORT_ACTIVE};
+
+pub mod ddp;
+pub mod e131;
+pub mod mapping;
+
+use mapping::Mapping;
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+lazy_static! {
+    /// LED map that holds the colors received via the network
+    pub static ref LED_MAP: Arc<RwLock<Vec<RGBA>>> = Arc::new(RwLock::new(vec![RGBA {
+        r: 0x00,
+        g: 0x00,
+        b: 0x00,
+        a: 0x00,
+    }; canvas::get_canvas_size()]));
+
+    /// Point in time when the last packet has been applied to the LED map
+    static ref LAST_PACKET: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
+/// Receives E1.31 (sACN) and DDP packets and maps them onto the canvas
+pub struct LightingPlugin {}
+
+impl LightingPlugin {
+    pub fn new() -> Self {
+        LightingPlugin {}
+    }
+
+    fn bind(bind_address: IpAddr, port: u16) -> Result<UdpSocket> {
+        let socket = UdpSocket::bind((bind_address, port))?;
+        socket.set_read_timeout(Some(Duration::from_millis(
+            constants::LIGHTING_RECV_TIMEOUT_MILLIS,
+        )))?;
+
+        Ok(socket)
+    }
+
+    fn spawn_e131_thread(
+        bind_address: IpAddr,
+        port: u16,
+        mapping: Arc<Mapping>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let socket = Self::bind(bind_address, port)?;
+
+        // sACN sources usually send to a per-universe multicast group
+        if let IpAddr::V4(address) = bind_address {
+            for universe in mapping.universes() {
+                let group = e131::multicast_address(universe);
+
+                if let Err(e) = socket.join_multicast_v4(&group, &address) {
+                    warn!("E1.31: Could not join multicast group {}: {}", group, e);
+                }
+            }
+        }
+
+        info!("E1.31: Listening on {}:{}", bind_address, port);
+
+        thread::Builder::new().name("e131".into()).spawn(move || {
+            let mut buffer = [0u8; 1500];
+            let mut sequences = HashMap::new();
+
+            while !crate::QUIT.load(Ordering::SeqCst) {
+                let len = match socket.recv(&mut buffer) {
+                    Ok(len) => len,
+
+                    Err(e)
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut =>
+                    {
+                        check_timeout(timeout);
+
+                        continue;
+                    }
+
+                    Err(e) => {
+                        error!("E1.31: Could not receive a packet: {}", e);
+
+                        break;
+                    }
+                };
+
+                let packet = match e131::parse_packet(&buffer[..len]) {
+                    Ok(e131::Packet::Data(packet)) => packet,
+                    Ok(e131::Packet::Extended) => continue,
+
+                    Err(e) => {
+                        trace!("E1.31: Dropping invalid packet: {}", e);
+
+                        continue;
+                    }
+                };
+
+                if let Some(last) = sequences.insert(packet.universe, packet.sequence) {
+                    if e131::is_out_of_order(last, packet.sequence) {
+                        sequences.insert(packet.universe, last);
+
+                        continue;
+                    }
+                }
+
+                if packet.stream_terminated {
+                    debug!(
+                        "E1.31: Source '{}' terminated universe {}",
+                        packet.source_name, packet.universe
+                    );
+
+                    sequences.remove(&packet.universe);
+                    mapping.clear_e131(packet.universe, &mut LED_MAP.write());
+
+                    FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
+                } else if packet.is_displayable() {
+                    mapping.apply_e131(packet.universe, packet.channels, &mut LED_MAP.write());
+
+                    mark_active();
+                }
+
+                check_timeout(timeout);
+            }
+        })?;
+
+        Ok(())
+    }
+
+    fn spawn_ddp_thread(
+        bind_address: IpAddr,
+        port: u16,
+        mapping: Arc<Mapping>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let socket = Self::bind(bind_address, port)?;
+
+        info!("DDP: Listening on {}:{}", bind_address, port);
+
+        thread::Builder::new().name("ddp".into()).spawn(move || {
+            let mut buffer = [0u8; 1500];
+            let mut frame = Vec::new();
+
+            while !crate::QUIT.load(Ordering::SeqCst) {
+                let len = match socket.recv(&mut buffer) {
+                    Ok(len) => len,
+
+                    Err(e)
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut =>
+                    {
+                        check_timeout(timeout);
+
+                        continue;
+                    }
+
+                    Err(e) => {
+                        error!("DDP: Could not receive a packet: {}", e);
+
+                        break;
+                    }
+                };
+
+                let packet = match ddp::parse_packet(&buffer[..len]) {
+                    Ok(ddp::Packet::Data(packet)) => packet,
+                    Ok(ddp::Packet::Ignored) => continue,
+
+                    Err(e) => {
+                        trace!("DDP: Dropping invalid packet: {}", e);
+
+                        continue;
+                    }
+                };
+
+                ddp::update_buffer(&mut frame, &packet);
+
+                // senders set the push flag on the last packet of a frame
+                if packet.push {
+                    mapping.apply_ddp(&frame, &mut LED_MAP.write());
+
+                    mark_active();
+                }
+
+                check_timeout(timeout);
+            }
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Blend the received LED map from now on, and schedule a new frame
+fn mark_active() {
+    *LAST_PACKET.lock() = Some(Instant::now());
+
+    LIGHTING_SUPPORT_ACTIVE.store(true, Ordering::SeqCst);
+    FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Give control back to the Lua scripts, if no packet has been received for `timeout`
+fn check_timeout(timeout: Duration) {
+    let mut last_packet = LAST_PACKET.lock();
+
+    if matches!(*last_packet, Some(instant) if instant.elapsed() >= timeout) {
+        debug!("Lighting: No more data received, deactivating");
+
+        *last_packet = None;
+
+        for color in LED_MAP.write().iter_mut() {
+            color.a = 0;
+        }
+
+        LIGHTING_SUPPORT_ACTIVE.store(false, Ordering::SeqCst);
+        FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Default for LightingPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for LightingPlugin {
+    fn get_name(&self) -> String {
+        "Lighting".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "Receive E1.31 (sACN) and DDP packets from lighting software".to_string()
+    }
+
+    fn initialize(&mut self) -> plugins::Result<()> {
+        let (enabled, bind_address, e131_port, ddp_port, mapping_file, timeout) = {
+            let config = crate::CONFIG.lock();
+            let config = config.as_ref().unwrap();
+
+            (
+                config.get::<bool>("lighting.enabled").unwrap_or(false),
+                config
+                    .get::<String>("lighting.bind_address")
+                    .unwrap_or_else(|_| constants::LIGHTING_DEFAULT_BIND_ADDRESS.to_string()),
+                config
+                    .get::<u16>("lighting.e131_port")
+                    .unwrap_or(e131::DEFAULT_PORT),
+                config
+                    .get::<u16>("lighting.ddp_port")
+                    .unwrap_or(ddp::DEFAULT_PORT),
+                config
+                    .get::<String>("lighting.mapping_file")
+                    .unwrap_or_else(|_| constants::DEFAULT_LIGHTING_MAPPING_FILE.to_string()),
+                config
+                    .get::<u64>("lighting.timeout_millis")
+                    .unwrap_or(constants::LIGHTING_TIMEOUT_MILLIS),
+            )
+        };
+
+        if !enabled {
+            debug!("The lighting protocol receivers are disabled");
+
+            return Ok(());
+        }
+
+        // don't fall back to listening on all interfaces, if the address is misspelled
+        let bind_address = match bind_address.parse::<IpAddr>() {
+            Ok(bind_address) => bind_address,
+
+            Err(e) => {
+                error!(
+                    "Invalid lighting.bind_address {}, the receivers will not be started: {}",
+                    bind_address, e
+                );
+
+                return Ok(());
+            }
+        };
+
+        let mapping = Mapping::load(&PathBuf::from(&mapping_file)).map_err(|e| {
+            error!(
+                "Could not load the lighting mapping file {}: {}",
+                mapping_file, e
+            );
+
+            e
+        })?;
+
+        let mapping = Arc::new(mapping);
+        let timeout = Duration::from_millis(timeout);
+
+        // a port of 0 disables the respective protocol
+        if e131_port != 0 {
+            Self::spawn_e131_thread(bind_address, e131_port, mapping.clone(), timeout)
+                .map_err(|e| error!("E1.31: Could not start the receiver: {}", e))
+                .ok();
+        }
+
+        if ddp_port != 0 {
+            Self::spawn_ddp_thread(bind_address, ddp_port, mapping, timeout)
+                .map_err(|e| error!("DDP: Could not start the receiver: {}", e))
+                .ok();
+        }
+
+        Ok(())
+    }
+
+    fn register_lua_funcs(&self, _lua_ctx: &Lua) -> mlua::Result<()> {
+        Ok(())
+    }
+
+    async fn main_loop_hook(&self, _ticks: u64) {}
+
+    fn sync_main_loop_hook(&self, _ticks: u64) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -32,6 +32,8 @@ use std::sync::Arc;
 use std::{any::Any, thread};
 
 use crate::plugins::{self, Plugin};
+use crate::scripting::manifest::Permission;
+use crate::scripting::sandbox;
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
 
@@ -45,6 +47,11 @@ pub enum Message {
     MirrorMouseEventImmediate(evdev_rs::InputEvent),
     InjectButtonEvent { button: u32, down: bool },
     InjectMouseWheelEvent { direction: u32 },
+
+    // macro playback related, keys and buttons are specified as evdev event codes
+    InjectMouseButton { code: u32, down: bool },
+    InjectMouseMove { x: i32, y: i32 },
+    InjectMouseWheel { delta: i32 },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -487,6 +494,38 @@ impl MacrosPlugin {
         Ok(())
     }
 
+    /// Inject relative mouse movement events into the output of the virtual mouse,
+    /// followed by a single SYN_REPORT
+    fn inject_relative_mouse_events(events: &[(EV_REL, i32)], time: &TimeVal) -> Result<()> {
+        MOUSE_DEVICE.with(|dev| {
+            let device = dev.borrow();
+
+            if let Some(device) = device.as_ref() {
+                for (axis, value) in events.iter().filter(|(_, value)| *value != 0) {
+                    let event = InputEvent {
+                        time: *time,
+                        event_code: EventCode::EV_REL(*axis),
+                        value: *value,
+                    };
+
+                    device.write_event(&event).unwrap();
+                }
+
+                let event = InputEvent {
+                    time: *time,
+                    event_code: EventCode::EV_SYN(EV_SYN::SYN_REPORT),
+                    value: 0,
+                };
+
+                device.write_event(&event).unwrap();
+            } else {
+                error!("Device is not initialized");
+            }
+        });
+
+        Ok(())
+    }
+
     /// Inject a pre-existing InputEvent into to output of the virtual keyboard device
     fn inject_key_event(event: evdev_rs::InputEvent) -> Result<()> {
         // let mut do_initialize = false;
@@ -599,8 +638,16 @@ impl MacrosPlugin {
                         }
 
                         Message::InjectKey { key: ev_key, down } => {
-                            let key = evdev_rs::enums::int_to_ev_key(ev_key)
-                                .ok_or(MacrosPluginError::MappingError {})?;
+                            // an invalid code must not end this thread, since that would
+                            // stop all input of the grabbed devices
+                            let key = match evdev_rs::enums::int_to_ev_key(ev_key) {
+                                Some(key) => key,
+
+                                None => {
+                                    error!("Invalid key code: {}", ev_key);
+                                    continue;
+                                }
+                            };
 
                             let value = if down { 1 } else { 0 };
 
@@ -628,6 +675,54 @@ impl MacrosPlugin {
                         Message::InjectMouseWheelEvent { direction: _ } => {
                             // REL_RESERVED
                         }
+
+                        Message::InjectMouseButton { code, down } => {
+                            let button = match evdev_rs::enums::int_to_ev_key(code) {
+                                Some(button) => button,
+
+                                None => {
+                                    error!("Invalid mouse button code: {}", code);
+                                    continue;
+                                }
+                            };
+
+                            let value = if down { 1 } else { 0 };
+
+                            let time = evdev_rs::TimeVal {
+                                tv_sec: 0,
+                                tv_usec: 0,
+                            };
+
+                            Self::inject_single_mouse_event(button, value, &time)?;
+                        }
+
+                        Message::InjectMouseMove { x, y } => {
+                            let time = evdev_rs::TimeVal {
+                                tv_sec: 0,
+                                tv_usec: 0,
+                            };
+
+                            Self::inject_relative_mouse_events(
+                                &[(EV_REL::REL_X, x), (EV_REL::REL_Y, y)],
+                                &time,
+                            )?;
+                        }
+
+                        Message::InjectMouseWheel { delta } => {
+                            let time = evdev_rs::TimeVal {
+                                tv_sec: 0,
+                                tv_usec: 0,
+                            };
+
+                            // a wheel "click" equals 120 units of high-resolution scrolling
+                            Self::inject_relative_mouse_events(
+                                &[
+                                    (EV_REL::REL_WHEEL, delta),
+                                    (EV_REL::REL_WHEEL_HI_RES, delta.saturating_mul(120)),
+                                ],
+                                &time,
+                            )?;
+                        }
                     }
                 }
             })?;
@@ -654,7 +749,29 @@ impl Plugin for MacrosPlugin {
         Ok(())
     }
 
-    fn register_lua_funcs(&self, _lua_ctx: &Lua) -> mlua::Result<()> {
+    fn register_lua_funcs(&self, lua_ctx: &Lua) -> mlua::Result<()> {
+        let globals = lua_ctx.globals();
+
+        if sandbox::is_granted(Permission::InputInjection) {
+            let play_macro = lua_ctx.create_function(|_, name: String| {
+                if let Err(e) = crate::macro_player::play_macro(&name) {
+                    error!("Could not play back the macro {}: {}", name, e);
+
+                    Ok(false)
+                } else {
+                    Ok(true)
+                }
+            })?;
+            globals.set("play_macro", play_macro)?;
+
+            let stop_macros = lua_ctx.create_function(|_, ()| {
+                crate::macro_player::stop_all_macros();
+
+                Ok(())
+            })?;
+            globals.set("stop_macros", stop_macros)?;
+        }
+
         Ok(())
     }
 
@@ -670,3 +787,188 @@ impl Plugin for MacrosPlugin {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mlua::prelude::*;
+
+    /// Set up a VM with the complex keys library, that records the injected keys
+    fn complex_keys(tables: &str) -> Lua {
+        let lua = Lua::new();
+
+        lua.load(
+            r#"
+            target_fps = 20 -- one tick per 50 milliseconds
+            ticks = 0
+            events = {}
+
+            function inject_key(code, down)
+                events[#events + 1] = (down and "+" or "-") .. code
+            end
+
+            function consume_key() end
+            min = math.min
+
+            TAP_HOLD_TABLE = {}
+            TAP_DANCE_TABLE = {}
+            CHORD_TABLE = {}
+            "#,
+        )
+        .exec()
+        .unwrap();
+
+        lua.load(include_str!("../scripts/lib/complex_keys.lua"))
+            .exec()
+            .unwrap();
+
+        lua.load(tables).exec().unwrap();
+
+        lua
+    }
+
+    fn key_down(lua: &Lua, key_index: usize) -> bool {
+        let f: LuaFunction = lua.globals().get("complex_key_down").unwrap();
+        f.call(key_index).unwrap()
+    }
+
+    fn key_up(lua: &Lua, key_index: usize) -> bool {
+        let f: LuaFunction = lua.globals().get("complex_key_up").unwrap();
+        f.call(key_index).unwrap()
+    }
+
+    fn tick(lua: &Lua, count: usize) {
+        for _ in 0..count {
+            lua.load("ticks = ticks + 1; update_complex_keys()")
+                .exec()
+                .unwrap();
+        }
+    }
+
+    /// Returns the injected keys since the last call
+    fn events(lua: &Lua) -> String {
+        lua.load("local result = table.concat(events, ' '); events = {}; return result")
+            .eval()
+            .unwrap()
+    }
+
+    const TAP_HOLD: &str = "TAP_HOLD_TABLE[9] = { tap = 1, hold = 29, timeout = 200 }";
+
+    const TAP_DANCE: &str = r#"
+        TAP_DANCE_TABLE[9] = {
+            actions = { 30, function(down) inject_key(100, down) end },
+            timeout = 200
+        }"#;
+
+    const CHORD: &str = r#"
+        CHORD_TABLE[#CHORD_TABLE + 1] = {
+            keys = { [9] = 30, [15] = 31 },
+            action = 1,
+            timeout = 50
+        }"#;
+
+    #[test]
+    fn tap_hold() {
+        let lua = complex_keys(TAP_HOLD);
+
+        // tap
+        assert!(key_down(&lua, 9));
+        tick(&lua, 2);
+        assert_eq!(events(&lua), "");
+        assert!(key_up(&lua, 9));
+        assert_eq!(events(&lua), "+1 -1");
+
+        // hold, after the timeout
+        assert!(key_down(&lua, 9));
+        tick(&lua, 4);
+        assert_eq!(events(&lua), "+29");
+        tick(&lua, 4);
+        assert!(key_up(&lua, 9));
+        assert_eq!(events(&lua), "-29");
+
+        // hold, decided by pressing another key before the timeout
+        assert!(key_down(&lua, 9));
+        assert!(!key_down(&lua, 20));
+        assert!(key_up(&lua, 9));
+        assert_eq!(events(&lua), "+29 -29");
+    }
+
+    #[test]
+    fn tap_dance() {
+        let lua = complex_keys(TAP_DANCE);
+
+        // a single tap
+        assert!(key_down(&lua, 9));
+        assert!(key_up(&lua, 9));
+        tick(&lua, 3);
+        assert_eq!(events(&lua), "");
+        tick(&lua, 1);
+        assert_eq!(events(&lua), "+30 -30");
+
+        // three taps select the last action
+        for _ in 0..3 {
+            assert!(key_down(&lua, 9));
+            assert!(key_up(&lua, 9));
+            tick(&lua, 1);
+        }
+        tick(&lua, 4);
+        assert_eq!(events(&lua), "+100 -100");
+
+        // the action is held down while the key is held down
+        assert!(key_down(&lua, 9));
+        tick(&lua, 4);
+        assert_eq!(events(&lua), "+30");
+        assert!(key_up(&lua, 9));
+        assert_eq!(events(&lua), "-30");
+
+        // another key finishes the tap dance
+        assert!(key_down(&lua, 9));
+        assert!(key_up(&lua, 9));
+        assert!(!key_down(&lua, 20));
+        assert_eq!(events(&lua), "+30 -30");
+    }
+
+    #[test]
+    fn chord() {
+        let lua = complex_keys(CHORD);
+
+        assert!(key_down(&lua, 15));
+        assert!(key_down(&lua, 9));
+        assert_eq!(events(&lua), "+1");
+
+        // the action is released together with the first key,
+        // the release of the other key is consumed
+        assert!(key_up(&lua, 9));
+        assert_eq!(events(&lua), "-1");
+        assert!(key_up(&lua, 15));
+        assert_eq!(events(&lua), "");
+    }
+
+    #[test]
+    fn incomplete_chords() {
+        let lua = complex_keys(CHORD);
+
+        // the key is replayed after the timeout
+        assert!(key_down(&lua, 9));
+        tick(&lua, 1);
+        assert_eq!(events(&lua), "+30");
+        assert!(key_up(&lua, 9));
+        assert_eq!(events(&lua), "-30");
+
+        // the key is replayed if another key is pressed
+        assert!(key_down(&lua, 15));
+        assert!(!key_down(&lua, 20));
+        assert_eq!(events(&lua), "+31");
+        assert!(key_up(&lua, 15));
+        assert_eq!(events(&lua), "-31");
+
+        // the key is replayed if it is released
+        assert!(key_down(&lua, 9));
+        assert!(key_up(&lua, 9));
+        assert_eq!(events(&lua), "+30 -30");
+
+        // keys without a complex action are not consumed
+        assert!(!key_down(&lua, 20));
+        assert!(!key_up(&lua, 20));
+        assert_eq!(events(&lua), "");
+    }
+}
@@ -23,11 +23,15 @@ pub mod animal;
 pub mod audio;
 pub mod introspection;
 pub mod keyboard;
+pub mod lighting;
 pub mod macros;
 pub mod mouse;
+pub mod netfx;
+pub mod openrgb;
 pub mod persistence;
 pub mod plugin;
 pub mod profiles;
+pub mod remote_api;
 pub mod sdk_support;
 pub mod sensors;
 pub mod system;
@@ -37,11 +41,15 @@ pub use animal::AnimalPlugin;
 pub use audio::AudioPlugin;
 pub use introspection::IntrospectionPlugin;
 pub use keyboard::KeyboardPlugin;
+pub use lighting::LightingPlugin;
 pub use macros::MacrosPlugin;
 pub use mouse::MousePlugin;
+pub use netfx::NetFxPlugin;
+pub use openrgb::OpenRgbPlugin;
 pub use persistence::PersistencePlugin;
 pub use plugin::Plugin;
 pub use profiles::ProfilesPlugin;
+pub use remote_api::RemoteApiPlugin;
 pub use sdk_support::SdkSupportPlugin;
 pub use sensors::SensorsPlugin;
 pub use system::SystemPlugin;
@@ -109,6 +117,22 @@ pub fn register_plugins() -> Result<()> {
         .register_plugin(Box::new(SdkSupportPlugin::new()))
         .map_err(|_e| error!("An error occurred during initialization of the plugin"));
 
+    let _ = plugin_manager
+        .register_plugin(Box::new(RemoteApiPlugin::new()))
+        .map_err(|_e| error!("An error occurred during initialization of the plugin"));
+
+    let _ = plugin_manager
+        .register_plugin(Box::new(NetFxPlugin::new()))
+        .map_err(|_e| error!("An error occurred during initialization of the plugin"));
+
+    let _ = plugin_manager
+        .register_plugin(Box::new(LightingPlugin::new()))
+        .map_err(|_e| error!("An error occurred during initialization of the plugin"));
+
+    let _ = plugin_manager
+        .register_plugin(Box::new(OpenRgbPlugin::new()))
+        .map_err(|_e| error!("An error occurred during initialization of the plugin"));
+
     let _ = plugin_manager
         .register_plugin(Box::new(AudioPlugin::new()))
         .map_err(|_e| error!("An error occurred during initialization of the plugin"));
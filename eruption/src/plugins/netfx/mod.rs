@@ -0,0 +1,441 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! A native implementation of the Network FX server. The server is started by the
+//! `netfx.lua` script, which composites the received colors with the other scripts
+//! of the active profile
+
+use lazy_static::lazy_static;
+use log::{debug, error, info, trace, warn};
+use mlua::prelude::*;
+use parking_lot::{Mutex, RwLock};
+use std::any::Any;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::Duration;
+
+use crate::{
+    canvas, constants,
+    plugins::{self, Plugin},
+    scripting::callbacks,
+    scripting::manifest::Permission,
+    scripting::sandbox,
+};
+
+pub mod protocol;
+
+use protocol::{Command, ErrorCode, Message, Reply};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+lazy_static! {
+    /// The currently running Network FX server
+    static ref SERVER: Arc<Mutex<Option<Server>>> = Arc::new(Mutex::new(None));
+
+    /// The colors received by the Network FX server
+    static ref COLOR_MAP: Arc<RwLock<Vec<u32>>> =
+        Arc::new(RwLock::new(vec![0; canvas::get_canvas_size()]));
+}
+
+/// Set when `COLOR_MAP` has been modified since it has last been polled
+static COLOR_MAP_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Unique id of a client connection
+static NEXT_CLIENT_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct Server {
+    address: String,
+
+    /// The Lua VM thread that started, or most recently took over the server
+    owner: ThreadId,
+
+    quit: Arc<AtomicBool>,
+    clients: Arc<Mutex<Vec<(usize, TcpStream)>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    fn shutdown(mut self) {
+        self.quit.store(true, Ordering::SeqCst);
+
+        for (_, stream) in self.clients.lock().iter() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+
+        // wait for the listening socket to be closed, so that it may be bound again
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A plugin that implements the Network FX protocol natively
+pub struct NetFxPlugin {}
+
+impl NetFxPlugin {
+    pub fn new() -> Self {
+        NetFxPlugin {}
+    }
+
+    /// Start the Network FX server, or take over a server that is already listening on
+    /// the same address, e.g. when the profile is reloaded
+    pub fn listen(bind_address: &str, port: u16) -> Result<()> {
+        let address = format!("{}:{}", bind_address, port);
+        let owner = thread::current().id();
+
+        let mut server = SERVER.lock();
+
+        if let Some(server) = server.as_mut() {
+            if server.address == address {
+                server.owner = owner;
+
+                return Ok(());
+            }
+        }
+
+        if let Some(server) = server.take() {
+            server.shutdown();
+        }
+
+        let listener = TcpListener::bind(&address)?;
+        listener.set_nonblocking(true)?;
+
+        info!("Network FX: Server now listening on {}", address);
+
+        // don't show stale colors of a previous session
+        COLOR_MAP.write().iter_mut().for_each(|color| *color = 0);
+
+        let quit = Arc::new(AtomicBool::new(false));
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let thread = {
+            let quit = quit.clone();
+            let clients = clients.clone();
+
+            thread::Builder::new()
+                .name("netfx".into())
+                .spawn(move || run_listener(listener, quit, clients))?
+        };
+
+        server.replace(Server {
+            address,
+            owner,
+            quit,
+            clients,
+            thread: Some(thread),
+        });
+
+        Ok(())
+    }
+
+    /// Stop the Network FX server, if it is owned by the calling Lua VM
+    pub fn shutdown() {
+        let mut server = SERVER.lock();
+
+        if matches!(server.as_ref(), Some(s) if s.owner == thread::current().id()) {
+            info!("Network FX: Server shutting down");
+
+            server.take().unwrap().shutdown();
+        }
+    }
+
+    /// Returns the received colors, if they have been modified since the last call
+    pub fn poll() -> Option<Vec<u32>> {
+        if COLOR_MAP_CHANGED.swap(false, Ordering::SeqCst) {
+            Some(COLOR_MAP.read().clone())
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for NetFxPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Update the colors of the LEDs with the zero-based indices `indices`
+fn set_colors(colors: impl Iterator<Item = (usize, [u8; 4])>) {
+    let mut color_map = COLOR_MAP.write();
+
+    let canvas_size = canvas::get_canvas_size();
+    if color_map.len() != canvas_size {
+        color_map.resize(canvas_size, 0);
+    }
+
+    for (index, [r, g, b, a]) in colors {
+        if let Some(color) = color_map.get_mut(index) {
+            *color = callbacks::rgba_to_color(r, g, b, a);
+        }
+    }
+
+    COLOR_MAP_CHANGED.store(true, Ordering::SeqCst);
+}
+
+fn run_listener(
+    listener: TcpListener,
+    quit: Arc<AtomicBool>,
+    clients: Arc<Mutex<Vec<(usize, TcpStream)>>>,
+) {
+    while !quit.load(Ordering::SeqCst) && !crate::QUIT.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if clients.lock().len() >= constants::NETFX_MAX_CLIENTS {
+                    warn!("Network FX: Too many clients, rejecting {}", peer);
+
+                    continue;
+                }
+
+                info!("Network FX: Client connected from {}", peer);
+
+                let client_id = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
+
+                let result = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| {
+                        stream.set_read_timeout(Some(Duration::from_millis(
+                            constants::NETFX_CLIENT_TIMEOUT_MILLIS,
+                        )))
+                    })
+                    .and_then(|_| stream.try_clone())
+                    .map(|s| clients.lock().push((client_id, s)));
+
+                if let Err(e) = result {
+                    error!("Network FX: Could not set up the connection: {}", e);
+
+                    continue;
+                }
+
+                let result = {
+                    let clients = clients.clone();
+
+                    thread::Builder::new()
+                        .name("netfx/client".into())
+                        .spawn(move || {
+                            if let Err(e) = run_client(stream) {
+                                debug!("Network FX: Client {}: {}", peer, e);
+                            }
+
+                            info!("Network FX: Client disconnected");
+
+                            clients.lock().retain(|(id, _)| *id != client_id);
+                        })
+                };
+
+                if let Err(e) = result {
+                    error!("Network FX: Could not spawn a thread: {}", e);
+
+                    clients.lock().retain(|(id, _)| *id != client_id);
+                }
+            }
+
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(
+                    constants::NETFX_ACCEPT_TIMEOUT_MILLIS,
+                ));
+            }
+
+            Err(e) => {
+                error!("Network FX: Could not accept a connection: {}", e);
+
+                thread::sleep(Duration::from_millis(
+                    constants::NETFX_ACCEPT_TIMEOUT_MILLIS,
+                ));
+            }
+        }
+    }
+}
+
+/// Serve a client, using the text based protocol version 1, until the client
+/// negotiates a newer protocol version
+fn run_client(stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+
+        let n = (&mut reader)
+            .take(constants::NETFX_MAX_LINE_LENGTH as u64)
+            .read_line(&mut line)?;
+
+        if n == 0 {
+            break;
+        }
+
+        trace!("Network FX: Request: {}", line.trim_end());
+
+        let canvas_size = canvas::get_canvas_size();
+
+        match protocol::parse_command(&line, canvas_size) {
+            Ok(Command::Quit) => {
+                writer.write_all(b"BYE\n")?;
+
+                break;
+            }
+
+            Ok(Command::Status) => {
+                writer.write_all(b"Eruption Network FX / Protocol version: 1.0\n")?;
+            }
+
+            Ok(Command::Hello { version }) => {
+                let version = version.clamp(1, protocol::MAX_PROTOCOL_VERSION);
+
+                writer.write_all(protocol::format_hello_reply(version, canvas_size).as_bytes())?;
+
+                if version >= 2 {
+                    return run_binary_client(reader, writer);
+                }
+            }
+
+            Ok(Command::Set { indices, color }) => {
+                set_colors(indices.into_iter().map(|index| (index, color)));
+
+                writer.write_all(b"OK\n")?;
+            }
+
+            Err(code) => {
+                error!("Network FX: {}", code);
+
+                writer.write_all(format!("ERROR: {}\n", code.code()).as_bytes())?;
+
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Serve a client, using the binary protocol version 2
+fn run_binary_client(mut reader: BufReader<TcpStream>, mut writer: TcpStream) -> Result<()> {
+    loop {
+        match protocol::read_message(&mut reader, canvas::get_canvas_size()) {
+            Ok(Message::Frame {
+                seq,
+                ack_requested,
+                spans,
+            }) => {
+                set_colors(spans.iter().flat_map(|span| {
+                    let start = span.start;
+
+                    span.colors
+                        .iter()
+                        .enumerate()
+                        .map(move |(i, color)| (start + i, *color))
+                }));
+
+                if ack_requested {
+                    writer.write_all(&protocol::encode_reply(&Reply::Ack { seq }))?;
+                }
+            }
+
+            Ok(Message::Bye) => break,
+
+            Err(e) => {
+                if let Some(code) = e.downcast_ref::<ErrorCode>() {
+                    error!("Network FX: {}", code);
+
+                    let reply = Reply::Error {
+                        seq: 0,
+                        code: *code,
+                    };
+
+                    writer.write_all(&protocol::encode_reply(&reply))?;
+
+                    break;
+                }
+
+                match e.downcast_ref::<std::io::Error>() {
+                    Some(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                    _ => return Err(e),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl Plugin for NetFxPlugin {
+    fn get_name(&self) -> String {
+        "Network FX".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "Native implementation of the Network FX protocol".to_string()
+    }
+
+    fn initialize(&mut self) -> plugins::Result<()> {
+        Ok(())
+    }
+
+    fn register_lua_funcs(&self, lua_ctx: &Lua) -> mlua::Result<()> {
+        let globals = lua_ctx.globals();
+
+        // the server accepts connections from the network
+        if sandbox::is_granted(Permission::Network) {
+            let netfx_listen =
+                lua_ctx.create_function(|_, (bind_address, port): (String, u16)| {
+                    match NetFxPlugin::listen(&bind_address, port) {
+                        Ok(()) => Ok(true),
+
+                        Err(e) => {
+                            error!("Network FX: Could not start the server: {}", e);
+
+                            Ok(false)
+                        }
+                    }
+                })?;
+            globals.set("netfx_listen", netfx_listen)?;
+
+            let netfx_shutdown = lua_ctx.create_function(|_, ()| {
+                NetFxPlugin::shutdown();
+                Ok(())
+            })?;
+            globals.set("netfx_shutdown", netfx_shutdown)?;
+
+            let netfx_poll = lua_ctx.create_function(|_, ()| Ok(NetFxPlugin::poll()))?;
+            globals.set("netfx_poll", netfx_poll)?;
+        }
+
+        Ok(())
+    }
+
+    async fn main_loop_hook(&self, _ticks: u64) {}
+
+    fn sync_main_loop_hook(&self, _ticks: u64) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
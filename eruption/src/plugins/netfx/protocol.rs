@@ -0,0 +1,537 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! The Network FX protocol. Version 1 is a line based text protocol, version 2 adds a
+//! binary framing that carries a full or partial frame of RGBA values in a single message.
+//! A client switches to version 2 by sending a `HELLO` command, see `docs/NETFX.md`
+//!
+//! This file is shared with the `eruption-netfx` client, items that are only used by
+//! one side are marked with `#[allow(dead_code)]`
+
+use std::io::Read;
+
+#[allow(dead_code)]
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// The highest protocol version supported by this implementation
+pub const MAX_PROTOCOL_VERSION: u8 = 2;
+
+/// Size of the header of a binary (version 2) message, in bytes
+pub const HEADER_SIZE: usize = 8;
+
+/// Message types, sent by the client
+pub const MSG_FRAME: u8 = 0x01;
+pub const MSG_BYE: u8 = 0x02;
+
+/// Message types, sent by the server
+pub const MSG_ACK: u8 = 0x81;
+pub const MSG_ERROR: u8 = 0x82;
+
+/// The client requests an acknowledgement of the frame
+pub const FLAG_ACK_REQUESTED: u8 = 0x01;
+
+/// Errors reported by the server. The numeric codes are shared by both protocol versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ErrorCode {
+    #[error("Ill-formed request")]
+    IllFormed,
+
+    #[error("Color component value out of range")]
+    OutOfRange,
+
+    #[error("Invalid index into the canvas")]
+    InvalidIndex,
+}
+
+impl ErrorCode {
+    #[allow(dead_code)]
+    pub fn code(self) -> u8 {
+        match self {
+            ErrorCode::IllFormed => 100,
+            ErrorCode::OutOfRange => 110,
+            ErrorCode::InvalidIndex => 120,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            100 => Some(ErrorCode::IllFormed),
+            110 => Some(ErrorCode::OutOfRange),
+            120 => Some(ErrorCode::InvalidIndex),
+            _ => None,
+        }
+    }
+}
+
+/// The color of a single LED, `index` is the zero-based index into the canvas
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedColor {
+    pub index: usize,
+    pub color: [u8; 4],
+}
+
+// Protocol version 1
+
+/// A text command of protocol version 1
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Status,
+    Quit,
+
+    /// Negotiate the protocol version, the client sends the highest version it supports
+    Hello {
+        version: u8,
+    },
+
+    /// Set the LEDs with the zero-based indices `indices` to `color`
+    Set {
+        indices: Vec<usize>,
+        color: [u8; 4],
+    },
+}
+
+/// Parse a text command, e.g. `ALL:255:0:0:255` or `1,5-7:0:0:255:255`. Indices of
+/// version 1 are one-based
+#[allow(dead_code)]
+pub fn parse_command(line: &str, canvas_size: usize) -> std::result::Result<Command, ErrorCode> {
+    let line = line.trim();
+
+    match line {
+        "STATUS" => return Ok(Command::Status),
+        "QUIT" => return Ok(Command::Quit),
+        _ => {}
+    }
+
+    if let Some(version) = line.strip_prefix("HELLO ") {
+        let version = version.trim().parse().map_err(|_| ErrorCode::IllFormed)?;
+
+        return Ok(Command::Hello { version });
+    }
+
+    let fields: Vec<&str> = line.split(':').collect();
+
+    if fields.len() != 5 {
+        return Err(ErrorCode::IllFormed);
+    }
+
+    let mut color = [0u8; 4];
+
+    for (component, field) in color.iter_mut().zip(&fields[1..]) {
+        let value: i64 = field.trim().parse().map_err(|_| ErrorCode::IllFormed)?;
+
+        *component = u8::try_from(value).map_err(|_| ErrorCode::OutOfRange)?;
+    }
+
+    let parse_index = |s: &str| -> std::result::Result<usize, ErrorCode> {
+        let index: usize = s.trim().parse().map_err(|_| ErrorCode::InvalidIndex)?;
+
+        if (1..=canvas_size).contains(&index) {
+            Ok(index - 1)
+        } else {
+            Err(ErrorCode::InvalidIndex)
+        }
+    };
+
+    let mut indices = Vec::new();
+
+    if fields[0].trim() == "ALL" {
+        indices.extend(0..canvas_size);
+    } else {
+        for zone in fields[0].split(',') {
+            match zone.split_once('-') {
+                Some((low, high)) => indices.extend(parse_index(low)?..=parse_index(high)?),
+                None => indices.push(parse_index(zone)?),
+            }
+        }
+    }
+
+    Ok(Command::Set { indices, color })
+}
+
+/// Format the colors of LEDs as text commands of protocol version 1
+#[allow(dead_code)]
+pub fn format_commands(leds: &[LedColor]) -> String {
+    leds.iter()
+        .map(|led| {
+            let [r, g, b, a] = led.color;
+
+            format!("{}:{}:{}:{}:{}\n", led.index + 1, r, g, b, a)
+        })
+        .collect()
+}
+
+/// The reply of the server to a `HELLO` command: `HELLO <version> <canvas size>`
+#[allow(dead_code)]
+pub fn format_hello_reply(version: u8, canvas_size: usize) -> String {
+    format!("HELLO {} {}\n", version, canvas_size)
+}
+
+/// Parse the reply of the server to a `HELLO` command, returns the negotiated
+/// protocol version and the size of the canvas
+#[allow(dead_code)]
+pub fn parse_hello_reply(line: &str) -> Option<(u8, usize)> {
+    let mut parts = line.trim().strip_prefix("HELLO ")?.split_whitespace();
+
+    let version = parts.next()?.parse().ok()?;
+    let canvas_size = parts.next()?.parse().ok()?;
+
+    Some((version, canvas_size))
+}
+
+// Protocol version 2
+
+/// A run of consecutive LEDs, starting at the zero-based index `start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub colors: Vec<[u8; 4]>,
+}
+
+/// The fixed size header of a binary message
+///
+/// | Offset | Size | Field                                          |
+/// | ------ | ---- | ---------------------------------------------- |
+/// | 0      | 1    | Protocol version (2)                           |
+/// | 1      | 1    | Message type                                   |
+/// | 2      | 1    | Flags                                          |
+/// | 3      | 1    | Error code, only used by `MSG_ERROR`           |
+/// | 4      | 2    | Sequence number (big endian)                   |
+/// | 6      | 2    | Number of spans that follow (big endian)       |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub msg_type: u8,
+    pub flags: u8,
+    pub code: u8,
+    pub seq: u16,
+    pub count: u16,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let seq = self.seq.to_be_bytes();
+        let count = self.count.to_be_bytes();
+
+        [
+            2,
+            self.msg_type,
+            self.flags,
+            self.code,
+            seq[0],
+            seq[1],
+            count[0],
+            count[1],
+        ]
+    }
+
+    pub fn decode(buf: &[u8; HEADER_SIZE]) -> std::result::Result<Self, ErrorCode> {
+        if buf[0] != 2 {
+            return Err(ErrorCode::IllFormed);
+        }
+
+        Ok(Self {
+            msg_type: buf[1],
+            flags: buf[2],
+            code: buf[3],
+            seq: u16::from_be_bytes([buf[4], buf[5]]),
+            count: u16::from_be_bytes([buf[6], buf[7]]),
+        })
+    }
+}
+
+/// A binary message, sent by the client
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Frame {
+        seq: u16,
+        ack_requested: bool,
+        spans: Vec<Span>,
+    },
+
+    Bye,
+}
+
+/// A binary message, sent by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    Ack { seq: u16 },
+    Error { seq: u16, code: ErrorCode },
+}
+
+/// Coalesce the colors of LEDs into as few spans as possible
+#[allow(dead_code)]
+pub fn spans_from_leds(leds: &[LedColor]) -> Vec<Span> {
+    let mut leds = leds.to_vec();
+    leds.sort_by_key(|led| led.index);
+    leds.dedup_by_key(|led| led.index);
+
+    let mut result: Vec<Span> = Vec::new();
+
+    for led in leds {
+        match result.last_mut() {
+            Some(span) if span.start + span.colors.len() == led.index => {
+                span.colors.push(led.color)
+            }
+
+            _ => result.push(Span {
+                start: led.index,
+                colors: vec![led.color],
+            }),
+        }
+    }
+
+    result
+}
+
+/// Encode a frame, consisting of one or more spans
+#[allow(dead_code)]
+pub fn encode_frame(seq: u16, ack_requested: bool, spans: &[Span]) -> Vec<u8> {
+    let header = Header {
+        msg_type: MSG_FRAME,
+        flags: if ack_requested { FLAG_ACK_REQUESTED } else { 0 },
+        code: 0,
+        seq,
+        count: spans.len() as u16,
+    };
+
+    let mut result = header.encode().to_vec();
+
+    for span in spans {
+        result.extend_from_slice(&(span.start as u16).to_be_bytes());
+        result.extend_from_slice(&(span.colors.len() as u16).to_be_bytes());
+
+        for color in span.colors.iter() {
+            result.extend_from_slice(color);
+        }
+    }
+
+    result
+}
+
+#[allow(dead_code)]
+pub fn encode_bye() -> [u8; HEADER_SIZE] {
+    Header {
+        msg_type: MSG_BYE,
+        flags: 0,
+        code: 0,
+        seq: 0,
+        count: 0,
+    }
+    .encode()
+}
+
+#[allow(dead_code)]
+pub fn encode_reply(reply: &Reply) -> [u8; HEADER_SIZE] {
+    let (msg_type, code, seq) = match *reply {
+        Reply::Ack { seq } => (MSG_ACK, 0, seq),
+        Reply::Error { seq, code } => (MSG_ERROR, code.code(), seq),
+    };
+
+    Header {
+        msg_type,
+        flags: 0,
+        code,
+        seq,
+        count: 0,
+    }
+    .encode()
+}
+
+#[allow(dead_code)]
+pub fn decode_reply(buf: &[u8; HEADER_SIZE]) -> std::result::Result<Reply, ErrorCode> {
+    let header = Header::decode(buf)?;
+
+    match header.msg_type {
+        MSG_ACK => Ok(Reply::Ack { seq: header.seq }),
+
+        MSG_ERROR => Ok(Reply::Error {
+            seq: header.seq,
+            code: ErrorCode::from_code(header.code).ok_or(ErrorCode::IllFormed)?,
+        }),
+
+        _ => Err(ErrorCode::IllFormed),
+    }
+}
+
+/// Read a binary message from the client. Spans are validated against `canvas_size`
+/// before their payload is read, so the size of a message is bounded by the size of the canvas
+#[allow(dead_code)]
+pub fn read_message<R: Read>(reader: &mut R, canvas_size: usize) -> Result<Message> {
+    let mut buf = [0u8; HEADER_SIZE];
+    reader.read_exact(&mut buf)?;
+
+    let header = Header::decode(&buf)?;
+
+    match header.msg_type {
+        MSG_BYE => Ok(Message::Bye),
+
+        MSG_FRAME => {
+            if header.count as usize > canvas_size {
+                return Err(ErrorCode::IllFormed.into());
+            }
+
+            let mut spans = Vec::with_capacity(header.count as usize);
+
+            for _ in 0..header.count {
+                let mut buf = [0u8; 4];
+                reader.read_exact(&mut buf)?;
+
+                let start = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+                let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+
+                if start + len > canvas_size {
+                    return Err(ErrorCode::InvalidIndex.into());
+                }
+
+                let mut data = vec![0u8; len * 4];
+                reader.read_exact(&mut data)?;
+
+                let colors = data
+                    .chunks_exact(4)
+                    .map(|c| [c[0], c[1], c[2], c[3]])
+                    .collect();
+
+                spans.push(Span { start, colors });
+            }
+
+            Ok(Message::Frame {
+                seq: header.seq,
+                ack_requested: header.flags & FLAG_ACK_REQUESTED != 0,
+                spans,
+            })
+        }
+
+        _ => Err(ErrorCode::IllFormed.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn text_commands() {
+        assert_eq!(parse_command("STATUS", 10), Ok(Command::Status));
+        assert_eq!(
+            parse_command("HELLO 2\n", 10),
+            Ok(Command::Hello { version: 2 })
+        );
+
+        assert_eq!(
+            parse_command("1,3-5:255:0:0:255", 10),
+            Ok(Command::Set {
+                indices: vec![0, 2, 3, 4],
+                color: [255, 0, 0, 255]
+            })
+        );
+
+        assert_eq!(
+            parse_command("ALL:0:0:0:0", 3),
+            Ok(Command::Set {
+                indices: vec![0, 1, 2],
+                color: [0, 0, 0, 0]
+            })
+        );
+
+        assert_eq!(parse_command("1:2:3", 10), Err(ErrorCode::IllFormed));
+        assert_eq!(parse_command("1:256:0:0:0", 10), Err(ErrorCode::OutOfRange));
+        assert_eq!(parse_command("0:0:0:0:0", 10), Err(ErrorCode::InvalidIndex));
+        assert_eq!(
+            parse_command("11:0:0:0:0", 10),
+            Err(ErrorCode::InvalidIndex)
+        );
+
+        assert_eq!(parse_hello_reply("HELLO 2 144\n"), Some((2, 144)));
+        assert_eq!(parse_hello_reply("ERROR: 100\n"), None);
+    }
+
+    #[test]
+    fn coalesce_spans() {
+        let led = |index, v| LedColor {
+            index,
+            color: [v, v, v, 255],
+        };
+
+        let spans = spans_from_leds(&[led(5, 5), led(0, 0), led(1, 1), led(6, 6), led(2, 2)]);
+
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    start: 0,
+                    colors: vec![[0, 0, 0, 255], [1, 1, 1, 255], [2, 2, 2, 255]]
+                },
+                Span {
+                    start: 5,
+                    colors: vec![[5, 5, 5, 255], [6, 6, 6, 255]]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_messages() {
+        let spans = vec![
+            Span {
+                start: 0,
+                colors: vec![[1, 2, 3, 4]; 3],
+            },
+            Span {
+                start: 8,
+                colors: vec![[5, 6, 7, 8]],
+            },
+        ];
+
+        let data = encode_frame(42, true, &spans);
+        assert_eq!(data.len(), HEADER_SIZE + 2 * 4 + 4 * 4);
+
+        let mut data = Cursor::new(data);
+        assert_eq!(
+            read_message(&mut data, 10).unwrap(),
+            Message::Frame {
+                seq: 42,
+                ack_requested: true,
+                spans: spans.clone()
+            }
+        );
+
+        // spans that exceed the canvas are rejected
+        let mut data = Cursor::new(encode_frame(1, false, &spans));
+        let err = read_message(&mut data, 8).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ErrorCode>(),
+            Some(&ErrorCode::InvalidIndex)
+        );
+
+        let mut data = Cursor::new(encode_bye());
+        assert_eq!(read_message(&mut data, 10).unwrap(), Message::Bye);
+
+        let reply = Reply::Error {
+            seq: 7,
+            code: ErrorCode::InvalidIndex,
+        };
+        assert_eq!(decode_reply(&encode_reply(&reply)), Ok(reply));
+    }
+}
@@ -0,0 +1,539 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! An opt-in server for the OpenRGB network SDK protocol. It allows existing OpenRGB
+//! clients, like game integrations, Artemis or home automation systems, to control the
+//! devices that are managed by Eruption, without fighting over the HID devices.
+//!
+//! Colors that are set by clients are painted into an overlay LED map, that is blended
+//! over the output of the Lua scripts
+
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use mlua::prelude::*;
+use parking_lot::RwLock;
+use std::any::Any;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crate::hwdevices::{self, DeviceTrait, RGBA};
+use crate::plugins::{self, Plugin};
+use crate::scripting::script::FRAME_GENERATION_COUNTER;
+use crate::{canvas, constants, OPENRGB_SUPPORT_ACTIVE};
+
+pub mod protocol;
+
+use protocol::{Controller, Header, Led, Matrix, OpenRgbError, Request, Zone, ZoneType};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+lazy_static! {
+    /// LED map that holds the colors set by OpenRGB clients
+    pub static ref LED_MAP: Arc<RwLock<Vec<RGBA>>> = Arc::new(RwLock::new(vec![RGBA {
+        r: 0x00,
+        g: 0x00,
+        b: 0x00,
+        a: 0x00,
+    }; canvas::get_canvas_size()]));
+}
+
+/// The number of currently connected clients
+static CLIENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// A device, as advertised to OpenRGB clients
+struct ManagedDevice {
+    /// The index of the device, as reported by the `GetManagedDevices` D-Bus method
+    device_index: usize,
+
+    controller: Controller,
+
+    /// Indices into the native LED layout of the device, for each LED of the controller
+    native_leds: Vec<Vec<usize>>,
+}
+
+impl ManagedDevice {
+    /// Index of the first LED of zone `zone`
+    fn zone_offset(&self, zone: usize) -> Option<usize> {
+        if zone < self.controller.zones.len() {
+            Some(
+                self.controller.zones[..zone]
+                    .iter()
+                    .map(|z| z.leds_count as usize)
+                    .sum(),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Paint `colors` into the overlay LED map, starting at the LED with index `first_led`
+    fn paint(&self, first_led: usize, colors: &[[u8; 3]]) {
+        let targets = {
+            let layout = canvas::CANVAS_LAYOUT.read();
+            let mut result = vec![];
+
+            for (native_leds, color) in self.native_leds.iter().skip(first_led).zip(colors) {
+                for index in native_leds {
+                    if let Some(idx) = layout.device_to_canvas_index(self.device_index, *index) {
+                        result.push((idx, *color));
+                    }
+                }
+            }
+
+            result
+        };
+
+        {
+            let mut led_map = LED_MAP.write();
+
+            for (idx, [r, g, b]) in targets {
+                if let Some(color) = led_map.get_mut(idx) {
+                    *color = RGBA { r, g, b, a: 255 };
+                }
+            }
+        }
+
+        OPENRGB_SUPPORT_ACTIVE.store(true, Ordering::SeqCst);
+        FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Fill in the fields of a controller description, that are common to all device classes
+fn describe_device(
+    device: &dyn DeviceTrait,
+    device_type: i32,
+    zones: Vec<Zone>,
+    leds: Vec<Led>,
+) -> Controller {
+    let usb_vid = device.get_usb_vid();
+    let usb_pid = device.get_usb_pid();
+
+    let (make, model) =
+        hwdevices::get_device_make_and_model(usb_vid, usb_pid).unwrap_or_else(|| {
+            (
+                "Unknown".to_string(),
+                format!("{:04x}:{:04x}", usb_vid, usb_pid),
+            )
+        });
+
+    Controller {
+        device_type,
+        name: format!("{} {}", make, model),
+        vendor: make,
+        description: "Managed by Eruption".to_string(),
+        version: device.get_firmware_revision(),
+        serial: device.get_serial().unwrap_or_default().to_string(),
+        location: device.get_usb_path(),
+        zones,
+        leds,
+    }
+}
+
+/// Describe a device without a known LED topology, as a single LED. If the device has been
+/// assigned a region of the canvas, the LED covers that region, otherwise it covers the
+/// native LEDs of non-keyboard devices
+fn describe_single_led_device(
+    device_index: usize,
+    device: &dyn DeviceTrait,
+    device_type: i32,
+) -> ManagedDevice {
+    let zones = vec![Zone {
+        name: "Device".to_string(),
+        zone_type: ZoneType::Single,
+        leds_count: 1,
+        matrix: None,
+    }];
+
+    let leds = vec![Led {
+        name: "Device".to_string(),
+        value: 0,
+    }];
+
+    let has_region = canvas::CANVAS_LAYOUT
+        .read()
+        .regions
+        .iter()
+        .any(|r| r.device == device_index);
+
+    // the native LEDs are mapped onto the canvas by `CanvasLayout::device_to_canvas_index`
+    let native_leds = if has_region {
+        (0..constants::CANVAS_SIZE).collect()
+    } else {
+        (constants::MAX_KEYS..constants::CANVAS_SIZE).collect()
+    };
+
+    ManagedDevice {
+        device_index,
+        controller: describe_device(device, device_type, zones, leds),
+        native_leds: vec![native_leds],
+    }
+}
+
+/// Enumerate all devices, in the same order as the `GetManagedDevices` D-Bus method
+fn enumerate_devices() -> Vec<ManagedDevice> {
+    let mut result = vec![];
+
+    for keyboard in crate::KEYBOARD_DEVICES.read().iter() {
+        let device = keyboard.read();
+
+        let num_keys = device.get_num_keys();
+        let num_rows = device.get_num_rows();
+        let num_cols = device.get_num_cols();
+
+        // derive the LEDs and the matrix map from the topology of the keyboard
+        let mut native_leds: Vec<Vec<usize>> = vec![];
+        let mut map = vec![];
+
+        for row in 0..num_rows {
+            let topology = device.get_row_topology(row);
+
+            for col in 0..num_cols {
+                match topology.get(col).map(|idx| *idx as usize) {
+                    Some(idx) if idx < num_keys => {
+                        let led = match native_leds.iter().position(|leds| leds[0] == idx) {
+                            Some(led) => led,
+
+                            None => {
+                                native_leds.push(vec![idx]);
+                                native_leds.len() - 1
+                            }
+                        };
+
+                        map.push(Some(led as u32));
+                    }
+
+                    _ => map.push(None),
+                }
+            }
+        }
+
+        let leds = native_leds
+            .iter()
+            .map(|idx| Led {
+                name: format!("Key: {}", idx[0]),
+                value: idx[0] as u32,
+            })
+            .collect::<Vec<_>>();
+
+        let zones = vec![Zone {
+            name: "Keyboard".to_string(),
+            zone_type: ZoneType::Matrix,
+            leds_count: leds.len() as u32,
+            matrix: Some(Matrix {
+                height: num_rows as u32,
+                width: num_cols as u32,
+                map,
+            }),
+        }];
+
+        result.push(ManagedDevice {
+            device_index: result.len(),
+            controller: describe_device(
+                device.as_device(),
+                protocol::DEVICE_TYPE_KEYBOARD,
+                zones,
+                leds,
+            ),
+            native_leds,
+        });
+    }
+
+    for mouse in crate::MOUSE_DEVICES.read().iter() {
+        let device = mouse.read();
+
+        result.push(describe_single_led_device(
+            result.len(),
+            device.as_device(),
+            protocol::DEVICE_TYPE_MOUSE,
+        ));
+    }
+
+    for misc in crate::MISC_DEVICES.read().iter() {
+        let device = misc.read();
+
+        result.push(describe_single_led_device(
+            result.len(),
+            device.as_device(),
+            protocol::DEVICE_TYPE_UNKNOWN,
+        ));
+    }
+
+    result
+}
+
+/// Make the overlay transparent again, after the last client has disconnected
+fn clear_led_map() {
+    for color in LED_MAP.write().iter_mut() {
+        color.a = 0;
+    }
+
+    OPENRGB_SUPPORT_ACTIVE.store(false, Ordering::SeqCst);
+    FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
+}
+
+fn run_client(mut stream: TcpStream, peer: SocketAddr) -> Result<()> {
+    stream.set_nodelay(true)?;
+
+    let mut devices = enumerate_devices();
+    let mut protocol_version = 0;
+
+    loop {
+        let mut header = [0u8; protocol::HEADER_SIZE];
+        stream.read_exact(&mut header)?;
+
+        let header = Header::decode(&header)?;
+
+        if header.size as usize > constants::OPENRGB_MAX_PACKET_SIZE {
+            return Err(OpenRgbError::PacketTooLarge {
+                size: header.size as usize,
+            }
+            .into());
+        }
+
+        let mut payload = vec![0u8; header.size as usize];
+        stream.read_exact(&mut payload)?;
+
+        if crate::QUIT.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let request = match protocol::parse_request(header.packet_id, &payload) {
+            Ok(request) => request,
+
+            Err(e) => {
+                debug!("OpenRGB: Client {}: Invalid request: {}", peer, e);
+
+                continue;
+            }
+        };
+
+        let device = devices.get(header.device_index as usize);
+
+        match request {
+            Request::ProtocolVersion { version } => {
+                protocol_version = version.min(protocol::MAX_PROTOCOL_VERSION);
+
+                let reply = protocol::MAX_PROTOCOL_VERSION.to_le_bytes();
+                stream.write_all(&protocol::encode_packet(0, header.packet_id, &reply))?;
+            }
+
+            Request::SetClientName { name } => {
+                info!("OpenRGB: Client {} identifies as '{}'", peer, name);
+            }
+
+            Request::ControllerCount => {
+                // clients query the number of controllers before enumerating them
+                devices = enumerate_devices();
+
+                let reply = (devices.len() as u32).to_le_bytes();
+                stream.write_all(&protocol::encode_packet(0, header.packet_id, &reply))?;
+            }
+
+            Request::ControllerData {
+                protocol_version: requested,
+            } => match device {
+                Some(device) => {
+                    let reply = device
+                        .controller
+                        .encode(requested.min(protocol::MAX_PROTOCOL_VERSION));
+
+                    stream.write_all(&protocol::encode_packet(
+                        header.device_index,
+                        header.packet_id,
+                        &reply,
+                    ))?;
+                }
+
+                None => warn!(
+                    "OpenRGB: Client {} requested an invalid device: {}",
+                    peer, header.device_index
+                ),
+            },
+
+            Request::ProfileList => {
+                if protocol_version >= 2 {
+                    let reply = protocol::encode_empty_profile_list();
+                    stream.write_all(&protocol::encode_packet(0, header.packet_id, &reply))?;
+                }
+            }
+
+            Request::UpdateLeds { colors } => {
+                if let Some(device) = device {
+                    device.paint(0, &colors);
+                }
+            }
+
+            Request::UpdateZoneLeds { zone, colors } => {
+                if let Some(device) = device {
+                    if let Some(offset) = device.zone_offset(zone as usize) {
+                        let count = device.controller.zones[zone as usize].leds_count as usize;
+
+                        device.paint(offset, &colors[..colors.len().min(count)]);
+                    }
+                }
+            }
+
+            Request::UpdateSingleLed { led, color } => {
+                if let Some(device) = device {
+                    device.paint(led as usize, &[color]);
+                }
+            }
+
+            Request::Unsupported { packet_id } => {
+                debug!(
+                    "OpenRGB: Client {}: Ignoring unsupported request: {}",
+                    peer, packet_id
+                );
+            }
+        }
+    }
+}
+
+/// Acts as a server for the OpenRGB network SDK protocol
+pub struct OpenRgbPlugin {}
+
+impl OpenRgbPlugin {
+    pub fn new() -> Self {
+        OpenRgbPlugin {}
+    }
+
+    fn start_listener_thread(listen_address: &str) -> Result<()> {
+        let listener = TcpListener::bind(listen_address)?;
+
+        info!("OpenRGB: Listening on {}", listen_address);
+
+        let builder = thread::Builder::new().name("openrgb".into());
+        builder.spawn(move || {
+            for stream in listener.incoming() {
+                if crate::QUIT.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let stream = match stream {
+                    Ok(stream) => stream,
+
+                    Err(e) => {
+                        error!("OpenRGB: Could not accept connection: {}", e);
+
+                        continue;
+                    }
+                };
+
+                let peer = match stream.peer_addr() {
+                    Ok(peer) => peer,
+                    Err(_) => continue,
+                };
+
+                if CLIENTS.load(Ordering::SeqCst) >= constants::OPENRGB_MAX_CLIENTS {
+                    warn!("OpenRGB: Too many clients, rejecting {}", peer);
+
+                    continue;
+                }
+
+                CLIENTS.fetch_add(1, Ordering::SeqCst);
+
+                info!("OpenRGB: Client connected: {}", peer);
+
+                let result = thread::Builder::new()
+                    .name(format!("openrgb/{}", peer))
+                    .spawn(move || {
+                        if let Err(e) = run_client(stream, peer) {
+                            debug!("OpenRGB: Client {}: {}", peer, e);
+                        }
+
+                        info!("OpenRGB: Client disconnected: {}", peer);
+
+                        if CLIENTS.fetch_sub(1, Ordering::SeqCst) == 1 {
+                            clear_led_map();
+                        }
+                    });
+
+                if let Err(e) = result {
+                    error!("OpenRGB: Could not spawn a thread: {}", e);
+
+                    CLIENTS.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        })?;
+
+        Ok(())
+    }
+}
+
+impl Default for OpenRgbPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for OpenRgbPlugin {
+    fn get_name(&self) -> String {
+        "OpenRGB".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "Server for the OpenRGB network SDK protocol".to_string()
+    }
+
+    fn initialize(&mut self) -> plugins::Result<()> {
+        let (enabled, listen_address) = {
+            let config = crate::CONFIG.lock();
+            let config = config.as_ref().unwrap();
+
+            (
+                config.get::<bool>("openrgb.enabled").unwrap_or(false),
+                config
+                    .get::<String>("openrgb.listen_address")
+                    .unwrap_or_else(|_| constants::OPENRGB_DEFAULT_ADDRESS.to_string()),
+            )
+        };
+
+        if !enabled {
+            debug!("The OpenRGB SDK server is disabled");
+
+            return Ok(());
+        }
+
+        Self::start_listener_thread(&listen_address)?;
+
+        Ok(())
+    }
+
+    fn register_lua_funcs(&self, _lua_ctx: &Lua) -> mlua::Result<()> {
+        Ok(())
+    }
+
+    async fn main_loop_hook(&self, _ticks: u64) {}
+
+    fn sync_main_loop_hook(&self, _ticks: u64) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
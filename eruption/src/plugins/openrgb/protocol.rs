@@ -0,0 +1,542 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! Wire format of the OpenRGB network SDK protocol, server side.
+//!
+//! All integers are little-endian. Strings are prefixed by a 16 bit length that
+//! includes the terminating NUL byte
+
+/// Highest protocol version supported by the server
+pub const MAX_PROTOCOL_VERSION: u32 = 3;
+
+pub const HEADER_SIZE: usize = 16;
+pub const MAGIC: &[u8; 4] = b"ORGB";
+
+// packet ids
+pub const REQUEST_CONTROLLER_COUNT: u32 = 0;
+pub const REQUEST_CONTROLLER_DATA: u32 = 1;
+pub const REQUEST_PROTOCOL_VERSION: u32 = 40;
+pub const SET_CLIENT_NAME: u32 = 50;
+pub const DEVICE_LIST_UPDATED: u32 = 100;
+pub const REQUEST_PROFILE_LIST: u32 = 150;
+pub const RGBCONTROLLER_UPDATELEDS: u32 = 1050;
+pub const RGBCONTROLLER_UPDATEZONELEDS: u32 = 1051;
+pub const RGBCONTROLLER_UPDATESINGLELED: u32 = 1052;
+
+// device types
+pub const DEVICE_TYPE_KEYBOARD: i32 = 5;
+pub const DEVICE_TYPE_MOUSE: i32 = 6;
+pub const DEVICE_TYPE_UNKNOWN: i32 = 19;
+
+const MODE_FLAG_HAS_PER_LED_COLOR: u32 = 1 << 5;
+const MODE_COLORS_PER_LED: u32 = 1;
+
+const NO_LED: u32 = 0xffff_ffff;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum OpenRgbError {
+    #[error("Invalid magic bytes")]
+    InvalidMagic,
+
+    #[error("Packet too short")]
+    Truncated,
+
+    #[error("Packet too large: {size} bytes")]
+    PacketTooLarge { size: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub device_index: u32,
+    pub packet_id: u32,
+    pub size: u32,
+}
+
+impl Header {
+    pub fn decode(buffer: &[u8; HEADER_SIZE]) -> Result<Self, OpenRgbError> {
+        if &buffer[0..4] != MAGIC {
+            return Err(OpenRgbError::InvalidMagic);
+        }
+
+        Ok(Header {
+            device_index: u32::from_le_bytes(buffer[4..8].try_into().unwrap()),
+            packet_id: u32::from_le_bytes(buffer[8..12].try_into().unwrap()),
+            size: u32::from_le_bytes(buffer[12..16].try_into().unwrap()),
+        })
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut result = [0u8; HEADER_SIZE];
+
+        result[0..4].copy_from_slice(MAGIC);
+        result[4..8].copy_from_slice(&self.device_index.to_le_bytes());
+        result[8..12].copy_from_slice(&self.packet_id.to_le_bytes());
+        result[12..16].copy_from_slice(&self.size.to_le_bytes());
+
+        result
+    }
+}
+
+/// A request sent by a client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    ControllerCount,
+    ControllerData {
+        protocol_version: u32,
+    },
+    ProtocolVersion {
+        version: u32,
+    },
+    SetClientName {
+        name: String,
+    },
+    ProfileList,
+    UpdateLeds {
+        colors: Vec<[u8; 3]>,
+    },
+    UpdateZoneLeds {
+        zone: u32,
+        colors: Vec<[u8; 3]>,
+    },
+    UpdateSingleLed {
+        led: u32,
+        color: [u8; 3],
+    },
+
+    /// Mode changes, zone resizing and profile management are not supported
+    Unsupported {
+        packet_id: u32,
+    },
+}
+
+struct Reader<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buffer: &'a [u8]) -> Self {
+        Self { buffer, offset: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], OpenRgbError> {
+        let result = self
+            .buffer
+            .get(self.offset..self.offset + len)
+            .ok_or(OpenRgbError::Truncated)?;
+
+        self.offset += len;
+
+        Ok(result)
+    }
+
+    fn u16(&mut self) -> Result<u16, OpenRgbError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, OpenRgbError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn color(&mut self) -> Result<[u8; 3], OpenRgbError> {
+        let color = self.bytes(4)?;
+
+        Ok([color[0], color[1], color[2]])
+    }
+
+    fn colors(&mut self) -> Result<Vec<[u8; 3]>, OpenRgbError> {
+        let count = self.u16()?;
+
+        (0..count).map(|_| self.color()).collect()
+    }
+}
+
+/// Parse the payload of a request with the packet id `packet_id`
+pub fn parse_request(packet_id: u32, payload: &[u8]) -> Result<Request, OpenRgbError> {
+    let mut reader = Reader::new(payload);
+
+    let result = match packet_id {
+        REQUEST_CONTROLLER_COUNT => Request::ControllerCount,
+
+        // clients that speak protocol version 0 do not send their version
+        REQUEST_CONTROLLER_DATA => Request::ControllerData {
+            protocol_version: reader.u32().unwrap_or(0),
+        },
+
+        REQUEST_PROTOCOL_VERSION => Request::ProtocolVersion {
+            version: reader.u32().unwrap_or(0),
+        },
+
+        SET_CLIENT_NAME => {
+            let name = payload.split(|c| *c == 0).next().unwrap_or_default();
+
+            Request::SetClientName {
+                name: String::from_utf8_lossy(name).to_string(),
+            }
+        }
+
+        REQUEST_PROFILE_LIST => Request::ProfileList,
+
+        RGBCONTROLLER_UPDATELEDS => {
+            let _data_size = reader.u32()?;
+
+            Request::UpdateLeds {
+                colors: reader.colors()?,
+            }
+        }
+
+        RGBCONTROLLER_UPDATEZONELEDS => {
+            let _data_size = reader.u32()?;
+            let zone = reader.u32()?;
+
+            Request::UpdateZoneLeds {
+                zone,
+                colors: reader.colors()?,
+            }
+        }
+
+        RGBCONTROLLER_UPDATESINGLELED => Request::UpdateSingleLed {
+            led: reader.u32()?,
+            color: reader.color()?,
+        },
+
+        packet_id => Request::Unsupported { packet_id },
+    };
+
+    Ok(result)
+}
+
+/// Encode a complete packet, including the header
+pub fn encode_packet(device_index: u32, packet_id: u32, payload: &[u8]) -> Vec<u8> {
+    let header = Header {
+        device_index,
+        packet_id,
+        size: payload.len() as u32,
+    };
+
+    let mut result = header.encode().to_vec();
+    result.extend_from_slice(payload);
+
+    result
+}
+
+/// The reply to `REQUEST_PROFILE_LIST`; profiles are managed by Eruption itself
+pub fn encode_empty_profile_list() -> Vec<u8> {
+    let mut result = 6u32.to_le_bytes().to_vec();
+    result.extend_from_slice(&0u16.to_le_bytes());
+
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneType {
+    Single = 0,
+    Linear = 1,
+    Matrix = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Matrix {
+    pub height: u32,
+    pub width: u32,
+
+    /// Row-major map of LED indices, relative to the start of the zone. Cells
+    /// without a LED hold `None`
+    pub map: Vec<Option<u32>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    pub name: String,
+    pub zone_type: ZoneType,
+    pub leds_count: u32,
+    pub matrix: Option<Matrix>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Led {
+    pub name: String,
+    pub value: u32,
+}
+
+/// Description of a single RGB controller, i.e. a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Controller {
+    pub device_type: i32,
+    pub name: String,
+    pub vendor: String,
+    pub description: String,
+    pub version: String,
+    pub serial: String,
+    pub location: String,
+    pub zones: Vec<Zone>,
+
+    /// The LEDs of all zones, in the order of the zones
+    pub leds: Vec<Led>,
+}
+
+#[derive(Default)]
+struct Writer {
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    fn u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u16(value.len() as u16 + 1);
+        self.buffer.extend_from_slice(value.as_bytes());
+        self.buffer.push(0);
+    }
+}
+
+impl Controller {
+    /// Encode the reply to `REQUEST_CONTROLLER_DATA`, as understood by clients
+    /// that speak protocol version `protocol_version`
+    pub fn encode(&self, protocol_version: u32) -> Vec<u8> {
+        let mut writer = Writer::default();
+
+        // placeholder for the size of the data
+        writer.u32(0);
+        writer.i32(self.device_type);
+        writer.string(&self.name);
+
+        if protocol_version >= 1 {
+            writer.string(&self.vendor);
+        }
+
+        writer.string(&self.description);
+        writer.string(&self.version);
+        writer.string(&self.serial);
+        writer.string(&self.location);
+
+        // a single "Direct" mode, the LEDs are always under direct control
+        writer.u16(1);
+        writer.i32(0);
+
+        writer.string("Direct");
+        writer.i32(0);
+        writer.u32(MODE_FLAG_HAS_PER_LED_COLOR);
+
+        // speed_min, speed_max
+        writer.u32(0);
+        writer.u32(0);
+
+        if protocol_version >= 3 {
+            // brightness_min, brightness_max
+            writer.u32(0);
+            writer.u32(0);
+        }
+
+        // colors_min, colors_max, speed
+        writer.u32(0);
+        writer.u32(0);
+        writer.u32(0);
+
+        if protocol_version >= 3 {
+            // brightness
+            writer.u32(0);
+        }
+
+        // direction, color_mode, no mode specific colors
+        writer.u32(0);
+        writer.u32(MODE_COLORS_PER_LED);
+        writer.u16(0);
+
+        writer.u16(self.zones.len() as u16);
+
+        for zone in self.zones.iter() {
+            writer.string(&zone.name);
+            writer.i32(zone.zone_type as i32);
+
+            // leds_min, leds_max, leds_count
+            writer.u32(zone.leds_count);
+            writer.u32(zone.leds_count);
+            writer.u32(zone.leds_count);
+
+            match &zone.matrix {
+                Some(matrix) => {
+                    writer.u16((8 + matrix.map.len() * 4) as u16);
+                    writer.u32(matrix.height);
+                    writer.u32(matrix.width);
+
+                    for led in matrix.map.iter() {
+                        writer.u32(led.unwrap_or(NO_LED));
+                    }
+                }
+
+                None => writer.u16(0),
+            }
+        }
+
+        writer.u16(self.leds.len() as u16);
+
+        for led in self.leds.iter() {
+            writer.string(&led.name);
+            writer.u32(led.value);
+        }
+
+        // current colors, the LEDs are not read back
+        writer.u16(self.leds.len() as u16);
+
+        for _ in self.leds.iter() {
+            writer.u32(0);
+        }
+
+        let size = writer.buffer.len() as u32;
+        writer.buffer[0..4].copy_from_slice(&size.to_le_bytes());
+
+        writer.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyboard() -> Controller {
+        Controller {
+            device_type: DEVICE_TYPE_KEYBOARD,
+            name: "ROCCAT Vulcan".to_string(),
+            vendor: "ROCCAT".to_string(),
+            description: "Test".to_string(),
+            version: "1".to_string(),
+            serial: "".to_string(),
+            location: "usb".to_string(),
+            zones: vec![Zone {
+                name: "Keyboard".to_string(),
+                zone_type: ZoneType::Matrix,
+                leds_count: 3,
+                matrix: Some(Matrix {
+                    height: 2,
+                    width: 2,
+                    map: vec![Some(0), Some(1), None, Some(2)],
+                }),
+            }],
+            leds: (0..3)
+                .map(|i| Led {
+                    name: format!("Key: {}", i),
+                    value: i,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let header = Header {
+            device_index: 2,
+            packet_id: RGBCONTROLLER_UPDATELEDS,
+            size: 42,
+        };
+
+        assert_eq!(Header::decode(&header.encode()), Ok(header));
+
+        let mut invalid = header.encode();
+        invalid[0] = b'X';
+        assert_eq!(Header::decode(&invalid), Err(OpenRgbError::InvalidMagic));
+    }
+
+    #[test]
+    fn test_parse_requests() {
+        let mut payload = vec![];
+        payload.extend_from_slice(&14u32.to_le_bytes());
+        payload.extend_from_slice(&2u16.to_le_bytes());
+        payload.extend_from_slice(&[255, 0, 0, 0, 0, 128, 255, 0]);
+
+        assert_eq!(
+            parse_request(RGBCONTROLLER_UPDATELEDS, &payload),
+            Ok(Request::UpdateLeds {
+                colors: vec![[255, 0, 0], [0, 128, 255]]
+            })
+        );
+
+        // announces more colors than there are
+        payload[4] = 3;
+        assert_eq!(
+            parse_request(RGBCONTROLLER_UPDATELEDS, &payload),
+            Err(OpenRgbError::Truncated)
+        );
+
+        assert_eq!(
+            parse_request(REQUEST_CONTROLLER_DATA, &[]),
+            Ok(Request::ControllerData {
+                protocol_version: 0
+            })
+        );
+
+        assert_eq!(
+            parse_request(SET_CLIENT_NAME, b"Artemis\0"),
+            Ok(Request::SetClientName {
+                name: "Artemis".to_string()
+            })
+        );
+
+        assert_eq!(
+            parse_request(1100, &[]),
+            Ok(Request::Unsupported { packet_id: 1100 })
+        );
+    }
+
+    #[test]
+    fn test_encode_controller() {
+        let controller = keyboard();
+
+        let v0 = controller.encode(0);
+        let v1 = controller.encode(1);
+        let v3 = controller.encode(3);
+
+        assert_eq!(
+            u32::from_le_bytes(v3[0..4].try_into().unwrap()) as usize,
+            v3.len()
+        );
+        assert_eq!(
+            i32::from_le_bytes(v3[4..8].try_into().unwrap()),
+            DEVICE_TYPE_KEYBOARD
+        );
+
+        // name, followed by the vendor in protocol version 1 and later
+        assert_eq!(&v3[8..10], &14u16.to_le_bytes());
+        assert_eq!(&v3[10..24], b"ROCCAT Vulcan\0");
+        assert_eq!(&v3[24..26], &7u16.to_le_bytes());
+        assert_eq!(&v3[26..33], b"ROCCAT\0");
+
+        // the vendor string, and the brightness fields of the mode
+        assert_eq!(v1.len(), v0.len() + 2 + 7);
+        assert_eq!(v3.len(), v1.len() + 3 * 4);
+
+        // the matrix map, followed by the LEDs and the colors
+        let tail = 2 + 3 * (2 + 7 + 4) + 2 + 3 * 4;
+        let map = &v3[v3.len() - tail - 16..v3.len() - tail];
+        assert_eq!(&map[8..12], &NO_LED.to_le_bytes());
+        assert_eq!(&map[12..16], &2u32.to_le_bytes());
+    }
+}
@@ -25,22 +25,44 @@ use mlua::prelude::*;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use std::any::Any;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use crate::constants;
 use crate::plugins::{self, Plugin};
+use crate::scripting::manifest::Permission;
+use crate::scripting::sandbox;
+
+/// The current version of the schema of the persistent store
+pub const STORE_VERSION: u32 = 2;
+
+/// Values stored with keys that start with this prefix are shared by all scripts and profiles
+pub const GLOBAL_KEY_PREFIX: &str = "global.";
+
+/// The namespace of values that are shared by all scripts and profiles
+pub const GLOBAL_NAMESPACE: &str = "global";
+
+/// The namespace of values that have been migrated from a store without namespaces
+pub const LEGACY_NAMESPACE: &str = "legacy";
 
 lazy_static! {
     /// A persistent key/value store that may be used by Lua scripts to store data across script reloads
     /// Will be stored to disk, and will survive a restart of the daemon
-    pub static ref GLOBAL_STORE: Arc<RwLock<HashMap<String, StoreValue>>> = Arc::new(RwLock::new(HashMap::new()));
+    pub static ref GLOBAL_STORE: Arc<RwLock<Store>> = Arc::new(RwLock::new(Store::new()));
 
     /// An ephemeral key/value store that may be used by Lua scripts to store data across script reloads
     /// This is suitable only for transient data, since it will not survive a restart of the daemon
-    pub static ref GLOBAL_EPHEMERAL_STORE: Arc<RwLock<HashMap<String, StoreValue>>> = Arc::new(RwLock::new(HashMap::new()));
+    pub static ref GLOBAL_EPHEMERAL_STORE: Arc<RwLock<Store>> = Arc::new(RwLock::new(Store::new()));
+}
+
+thread_local! {
+    /// The namespace of the script that is executed by the current thread
+    pub static STORE_NAMESPACE: RefCell<Option<String>> = RefCell::new(None);
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -54,6 +76,170 @@ pub enum StoreValue {
     Hash(HashMap<String, String>),
 }
 
+impl fmt::Display for StoreValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreValue::Int(val) => write!(f, "{}", val),
+            StoreValue::Float(val) => write!(f, "{}", val),
+            StoreValue::Bool(val) => write!(f, "{}", val),
+            StoreValue::String(val) => write!(f, "'{}'", val),
+            StoreValue::Color(val) => write!(f, "0x{:08x}", val),
+            StoreValue::Array(val) => {
+                let mut items = val.iter().collect::<Vec<_>>();
+                items.sort();
+
+                write!(f, "{:?}", items.iter().map(|(_, v)| v).collect::<Vec<_>>())
+            }
+            StoreValue::Hash(val) => write!(f, "{:?}", val.iter().collect::<BTreeMap<_, _>>()),
+        }
+    }
+}
+
+/// A key/value store, that keeps the values of each script and profile in a separate namespace
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Store {
+    pub version: u32,
+    pub namespaces: BTreeMap<String, BTreeMap<String, StoreValue>>,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self {
+            version: STORE_VERSION,
+            namespaces: BTreeMap::new(),
+        }
+    }
+
+    /// Parse a serialized store, migrating it from older versions of the schema if necessary
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+
+        // the first version of the store was a flat map, without a version field
+        let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(1);
+
+        match version {
+            1 => {
+                let values: HashMap<String, StoreValue> = serde_json::from_value(value)?;
+
+                info!(
+                    "Migrating {} values of the persistent store to the '{}' namespace",
+                    values.len(),
+                    LEGACY_NAMESPACE
+                );
+
+                let mut result = Self::new();
+
+                if !values.is_empty() {
+                    result
+                        .namespaces
+                        .insert(LEGACY_NAMESPACE.to_string(), values.into_iter().collect());
+                }
+
+                Ok(result)
+            }
+
+            v if v == STORE_VERSION as u64 => Ok(serde_json::from_value(value)?),
+
+            v => Err(PersistencePluginError::VersionError { version: v }.into()),
+        }
+    }
+
+    pub fn get(&self, namespace: &str, key: &str) -> Option<&StoreValue> {
+        self.namespaces
+            .get(namespace)
+            .and_then(|values| values.get(key))
+            // values that have been migrated from an older version of the store
+            // are visible to all scripts, until they are overwritten
+            .or_else(|| {
+                self.namespaces
+                    .get(LEGACY_NAMESPACE)
+                    .and_then(|values| values.get(key))
+            })
+    }
+
+    pub fn insert(&mut self, namespace: &str, key: String, value: StoreValue) {
+        self.namespaces
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(key, value);
+    }
+
+    /// Returns `true` if `namespace` is selected by `filter`. An empty filter selects
+    /// all namespaces, the name of a profile selects the namespaces of all of its scripts
+    pub fn matches(namespace: &str, filter: &str) -> bool {
+        filter.is_empty()
+            || namespace == filter
+            || namespace
+                .strip_prefix(filter)
+                .map(|rest| rest.starts_with('/'))
+                .unwrap_or(false)
+    }
+
+    /// Returns a copy of the namespaces that are selected by `filter`
+    pub fn filtered(&self, filter: &str) -> Self {
+        Self {
+            version: STORE_VERSION,
+            namespaces: self
+                .namespaces
+                .iter()
+                .filter(|(namespace, _)| Self::matches(namespace, filter))
+                .map(|(namespace, values)| (namespace.clone(), values.clone()))
+                .collect(),
+        }
+    }
+
+    /// Merge the values of `other` into this store, values of `other` take precedence
+    pub fn merge(&mut self, other: Store) {
+        for (namespace, values) in other.namespaces {
+            self.namespaces.entry(namespace).or_default().extend(values);
+        }
+    }
+
+    /// Remove all namespaces that are selected by `filter`, returns the number of removed values
+    pub fn clear(&mut self, filter: &str) -> usize {
+        let mut count = 0;
+
+        self.namespaces.retain(|namespace, values| {
+            if Self::matches(namespace, filter) {
+                count += values.len();
+                false
+            } else {
+                true
+            }
+        });
+
+        count
+    }
+}
+
+/// Returns the namespace of the script `script_file` of the profile `profile_file`
+pub fn script_namespace(profile_file: &Path, script_file: &Path) -> String {
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+
+    format!("{}/{}", file_name(profile_file), file_name(script_file))
+}
+
+/// Returns the namespace that `key` is stored in, when used by the script of the current thread
+fn namespace_of(key: &str) -> String {
+    if key.starts_with(GLOBAL_KEY_PREFIX) {
+        GLOBAL_NAMESPACE.to_string()
+    } else {
+        STORE_NAMESPACE
+            .with(|n| n.borrow().clone())
+            .unwrap_or_else(|| GLOBAL_NAMESPACE.to_string())
+    }
+}
+
 pub type Result<T> = std::result::Result<T, eyre::Error>;
 
 #[derive(Debug, thiserror::Error)]
@@ -63,6 +249,9 @@ pub enum PersistencePluginError {
 
     #[error("Non existent key: {description}")]
     KeyError { description: String },
+
+    #[error("Unsupported version of the persistent store: {version}")]
+    VersionError { version: u64 },
 }
 
 pub struct PersistencePlugin {}
@@ -73,7 +262,7 @@ macro_rules! store_operation {
             pub(crate) fn [<store_ $t>](key: String, value: $tval) -> Result<()> {
                 GLOBAL_STORE
                     .write()
-                    .insert(key, $sval(value));
+                    .insert(&namespace_of(&key), key, $sval(value));
                 Ok(())
             }
         }
@@ -84,7 +273,7 @@ macro_rules! load_operation {
     ($t:ident, $tval:ty, $sval:ty) => {
         paste::item! {
             pub(crate) fn [<load_ $t>](key: &str) -> Result<$tval> {
-                match GLOBAL_STORE.read().get(key) {
+                match GLOBAL_STORE.read().get(&namespace_of(key), key) {
                     Some(value) => {
                         if let $sval(val) = value {
                             Ok(val.clone())
@@ -110,7 +299,7 @@ macro_rules! store_transient_operation {
             pub(crate) fn [<store_ $t _transient>](key: String, value: $tval) -> Result<()> {
                 GLOBAL_EPHEMERAL_STORE
                     .write()
-                    .insert(key, $sval(value));
+                    .insert(&namespace_of(&key), key, $sval(value));
                 Ok(())
             }
         }
@@ -121,7 +310,7 @@ macro_rules! load_transient_operation {
     ($t:ident, $tval:ty, $sval:ty) => {
         paste::item! {
             pub(crate) fn [<load_ $t _transient>](key: &str) -> Result<$tval> {
-                match GLOBAL_EPHEMERAL_STORE.read().get(key) {
+                match GLOBAL_EPHEMERAL_STORE.read().get(&namespace_of(key), key) {
                     Some(value) => {
                         if let $sval(val) = value {
                             Ok(val.clone())
@@ -146,15 +335,33 @@ impl PersistencePlugin {
         PersistencePlugin {}
     }
 
+    fn store_file() -> PathBuf {
+        PathBuf::from(constants::STATE_DIR).join(PathBuf::from("persistent.store"))
+    }
+
     /// Stores the state of the persistence layer to disk
     pub fn store_persistent_data() -> Result<()> {
         info!("Storing persistent state data to disk...");
 
         let json_string = serde_json::to_string_pretty(&*GLOBAL_STORE.read())?;
 
-        let path = PathBuf::from(constants::STATE_DIR).join(PathBuf::from("persistent.store"));
+        let path = Self::store_file();
+        let tmp_path = path.with_extension("store.tmp");
+
+        // write to a temporary file first and then atomically replace the store,
+        // so that a crash while writing can't corrupt the previously stored data
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+
+            file.write_all(json_string.as_bytes())?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&tmp_path, &path)?;
 
-        fs::write(path, json_string)?;
+        if let Some(dir) = path.parent() {
+            fs::File::open(dir)?.sync_all()?;
+        }
 
         Ok(())
     }
@@ -163,19 +370,68 @@ impl PersistencePlugin {
     pub fn load_persistent_data() -> Result<()> {
         info!("Loading persistent state data from disk...");
 
-        let path = PathBuf::from(constants::STATE_DIR).join(PathBuf::from("persistent.store"));
+        let path = Self::store_file();
 
-        let json_string = fs::read_to_string(path)?;
+        let json_string = fs::read_to_string(&path)?;
 
-        let map: HashMap<String, StoreValue> = serde_json::from_str(&json_string)?;
+        let store = Store::from_json(&json_string).map_err(|e| {
+            // keep the unreadable file around, it would be overwritten on the next shutdown otherwise
+            let backup = path.with_extension("store.corrupt");
+
+            fs::rename(&path, &backup).unwrap_or_else(|e| {
+                error!("Could not rename the unreadable persistent store: {}", e)
+            });
+
+            e
+        })?;
 
         {
-            *GLOBAL_STORE.write() = map;
+            *GLOBAL_STORE.write() = store;
         }
 
         Ok(())
     }
 
+    /// Returns all persistent values as tuples of `(namespace, key, value)`
+    pub fn list_persistent_data(filter: &str) -> Vec<(String, String, String)> {
+        GLOBAL_STORE
+            .read()
+            .filtered(filter)
+            .namespaces
+            .iter()
+            .flat_map(|(namespace, values)| {
+                values
+                    .iter()
+                    .map(|(key, value)| (namespace.clone(), key.clone(), value.to_string()))
+            })
+            .collect()
+    }
+
+    /// Serializes the persistent values of the namespaces selected by `filter`
+    pub fn export_persistent_data(filter: &str) -> Result<String> {
+        let store = GLOBAL_STORE.read().filtered(filter);
+
+        Ok(serde_json::to_string_pretty(&store)?)
+    }
+
+    /// Merges previously exported persistent values into the store
+    pub fn import_persistent_data(json: &str) -> Result<()> {
+        let store = Store::from_json(json)?;
+
+        GLOBAL_STORE.write().merge(store);
+
+        Self::store_persistent_data()
+    }
+
+    /// Removes the persistent values of the namespaces selected by `filter`
+    pub fn clear_persistent_data(filter: &str) -> Result<usize> {
+        let count = GLOBAL_STORE.write().clear(filter);
+
+        Self::store_persistent_data()?;
+
+        Ok(count)
+    }
+
     // persistent data
     store_operation!(int, i64, StoreValue::Int);
     load_operation!(int, i64, StoreValue::Int);
@@ -240,108 +496,111 @@ impl Plugin for PersistencePlugin {
     fn register_lua_funcs(&self, lua_ctx: &Lua) -> mlua::Result<()> {
         let globals = lua_ctx.globals();
 
-        // persistent data
-        let store_int = lua_ctx.create_function(|_, (key, value): (String, i64)| {
-            PersistencePlugin::store_int(key, value).unwrap();
-            Ok(())
-        })?;
-        globals.set("store_int", store_int)?;
-
-        let load_int = lua_ctx.create_function(|_, (key, default): (String, i64)| {
-            match PersistencePlugin::load_int(&key) {
-                Ok(result) => Ok(result),
-                Err(_e) => Ok(default),
-            }
-        })?;
-        globals.set("load_int", load_int)?;
-
-        let store_float = lua_ctx.create_function(|_, (key, value): (String, f64)| {
-            PersistencePlugin::store_float(key, value).unwrap();
-            Ok(())
-        })?;
-        globals.set("store_float", store_float)?;
-
-        let load_float = lua_ctx.create_function(|_, (key, default): (String, f64)| {
-            match PersistencePlugin::load_float(&key) {
-                Ok(result) => Ok(result),
-                Err(_e) => Ok(default),
-            }
-        })?;
-        globals.set("load_float", load_float)?;
-
-        let store_bool = lua_ctx.create_function(|_, (key, value): (String, bool)| {
-            PersistencePlugin::store_bool(key, value).unwrap();
-            Ok(())
-        })?;
-        globals.set("store_bool", store_bool)?;
+        // persistent data, only available if the script has been granted the permission
+        if sandbox::is_granted(Permission::Persistence) {
+            let store_int = lua_ctx.create_function(|_, (key, value): (String, i64)| {
+                PersistencePlugin::store_int(key, value).unwrap();
+                Ok(())
+            })?;
+            globals.set("store_int", store_int)?;
 
-        let load_bool = lua_ctx.create_function(|_, (key, default): (String, bool)| {
-            match PersistencePlugin::load_bool(&key) {
-                Ok(result) => Ok(result),
-                Err(_e) => Ok(default),
-            }
-        })?;
-        globals.set("load_bool", load_bool)?;
+            let load_int = lua_ctx.create_function(|_, (key, default): (String, i64)| {
+                match PersistencePlugin::load_int(&key) {
+                    Ok(result) => Ok(result),
+                    Err(_e) => Ok(default),
+                }
+            })?;
+            globals.set("load_int", load_int)?;
 
-        let store_string = lua_ctx.create_function(|_, (key, value): (String, String)| {
-            PersistencePlugin::store_string(key, value).unwrap();
-            Ok(())
-        })?;
-        globals.set("store_string", store_string)?;
+            let store_float = lua_ctx.create_function(|_, (key, value): (String, f64)| {
+                PersistencePlugin::store_float(key, value).unwrap();
+                Ok(())
+            })?;
+            globals.set("store_float", store_float)?;
 
-        let load_string = lua_ctx.create_function(|_, (key, default): (String, String)| {
-            match PersistencePlugin::load_string(&key) {
-                Ok(result) => Ok(result),
-                Err(_e) => Ok(default),
-            }
-        })?;
-        globals.set("load_string", load_string)?;
+            let load_float = lua_ctx.create_function(|_, (key, default): (String, f64)| {
+                match PersistencePlugin::load_float(&key) {
+                    Ok(result) => Ok(result),
+                    Err(_e) => Ok(default),
+                }
+            })?;
+            globals.set("load_float", load_float)?;
 
-        let store_color = lua_ctx.create_function(|_, (key, value): (String, u32)| {
-            PersistencePlugin::store_color(key, value).unwrap();
-            Ok(())
-        })?;
-        globals.set("store_color", store_color)?;
+            let store_bool = lua_ctx.create_function(|_, (key, value): (String, bool)| {
+                PersistencePlugin::store_bool(key, value).unwrap();
+                Ok(())
+            })?;
+            globals.set("store_bool", store_bool)?;
 
-        let load_color = lua_ctx.create_function(|_, (key, default): (String, u32)| {
-            match PersistencePlugin::load_color(&key) {
-                Ok(result) => Ok(result),
-                Err(_e) => Ok(default),
-            }
-        })?;
-        globals.set("load_color", load_color)?;
+            let load_bool = lua_ctx.create_function(|_, (key, default): (String, bool)| {
+                match PersistencePlugin::load_bool(&key) {
+                    Ok(result) => Ok(result),
+                    Err(_e) => Ok(default),
+                }
+            })?;
+            globals.set("load_bool", load_bool)?;
 
-        let store_string_array =
-            lua_ctx.create_function(|_, (key, value): (String, HashMap<i32, String>)| {
-                PersistencePlugin::store_string_array(key, value).unwrap();
+            let store_string = lua_ctx.create_function(|_, (key, value): (String, String)| {
+                PersistencePlugin::store_string(key, value).unwrap();
                 Ok(())
             })?;
-        globals.set("store_string_array", store_string_array)?;
+            globals.set("store_string", store_string)?;
 
-        let load_string_array =
-            lua_ctx.create_function(|_, (key, default): (String, HashMap<i32, String>)| {
-                match PersistencePlugin::load_string_array(&key) {
+            let load_string = lua_ctx.create_function(|_, (key, default): (String, String)| {
+                match PersistencePlugin::load_string(&key) {
                     Ok(result) => Ok(result),
                     Err(_e) => Ok(default),
                 }
             })?;
-        globals.set("load_string_array", load_string_array)?;
+            globals.set("load_string", load_string)?;
 
-        let store_string_hash =
-            lua_ctx.create_function(|_, (key, value): (String, HashMap<String, String>)| {
-                PersistencePlugin::store_string_hash(key, value).unwrap();
+            let store_color = lua_ctx.create_function(|_, (key, value): (String, u32)| {
+                PersistencePlugin::store_color(key, value).unwrap();
                 Ok(())
             })?;
-        globals.set("store_string_hash", store_string_hash)?;
+            globals.set("store_color", store_color)?;
 
-        let load_string_hash =
-            lua_ctx.create_function(|_, (key, default): (String, HashMap<String, String>)| {
-                match PersistencePlugin::load_string_hash(&key) {
+            let load_color = lua_ctx.create_function(|_, (key, default): (String, u32)| {
+                match PersistencePlugin::load_color(&key) {
                     Ok(result) => Ok(result),
                     Err(_e) => Ok(default),
                 }
             })?;
-        globals.set("load_string_hash", load_string_hash)?;
+            globals.set("load_color", load_color)?;
+
+            let store_string_array =
+                lua_ctx.create_function(|_, (key, value): (String, HashMap<i32, String>)| {
+                    PersistencePlugin::store_string_array(key, value).unwrap();
+                    Ok(())
+                })?;
+            globals.set("store_string_array", store_string_array)?;
+
+            let load_string_array =
+                lua_ctx.create_function(|_, (key, default): (String, HashMap<i32, String>)| {
+                    match PersistencePlugin::load_string_array(&key) {
+                        Ok(result) => Ok(result),
+                        Err(_e) => Ok(default),
+                    }
+                })?;
+            globals.set("load_string_array", load_string_array)?;
+
+            let store_string_hash =
+                lua_ctx.create_function(|_, (key, value): (String, HashMap<String, String>)| {
+                    PersistencePlugin::store_string_hash(key, value).unwrap();
+                    Ok(())
+                })?;
+            globals.set("store_string_hash", store_string_hash)?;
+
+            let load_string_hash = lua_ctx.create_function(
+                |_, (key, default): (String, HashMap<String, String>)| {
+                    match PersistencePlugin::load_string_hash(&key) {
+                        Ok(result) => Ok(result),
+                        Err(_e) => Ok(default),
+                    }
+                },
+            )?;
+            globals.set("load_string_hash", load_string_hash)?;
+        }
 
         // transient data
         let store_int_transient = lua_ctx.create_function(|_, (key, value): (String, i64)| {
@@ -482,3 +741,80 @@ impl Plugin for PersistencePlugin {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Store, StoreValue, LEGACY_NAMESPACE, STORE_VERSION};
+
+    #[test]
+    fn migrate_flat_store() {
+        let json = r#"{ "statistics.count": { "Int": 42 }, "color": { "Color": 4278190335 } }"#;
+
+        let store = Store::from_json(json).unwrap();
+
+        assert_eq!(store.version, STORE_VERSION);
+        assert_eq!(store.namespaces.len(), 1);
+        assert_eq!(store.namespaces[LEGACY_NAMESPACE].len(), 2);
+
+        // migrated values are visible from any namespace, until they are overwritten
+        assert_eq!(
+            store.get("test.profile/stats.lua", "statistics.count"),
+            Some(&StoreValue::Int(42))
+        );
+
+        let mut store = store;
+        store.insert(
+            "test.profile/stats.lua",
+            "statistics.count".to_string(),
+            StoreValue::Int(43),
+        );
+
+        assert_eq!(
+            store.get("test.profile/stats.lua", "statistics.count"),
+            Some(&StoreValue::Int(43))
+        );
+        assert_eq!(
+            store.get("other.profile/stats.lua", "statistics.count"),
+            Some(&StoreValue::Int(42))
+        );
+
+        // round trip
+        let json = serde_json::to_string_pretty(&store).unwrap();
+        assert_eq!(Store::from_json(&json).unwrap(), store);
+
+        assert!(Store::from_json(r#"{ "version": 99, "namespaces": {} }"#).is_err());
+    }
+
+    #[test]
+    fn filter_and_clear_namespaces() {
+        let mut store = Store::new();
+
+        store.insert("a.profile/x.lua", "key".to_string(), StoreValue::Bool(true));
+        store.insert("a.profile/y.lua", "key".to_string(), StoreValue::Bool(true));
+        store.insert(
+            "ab.profile/x.lua",
+            "key".to_string(),
+            StoreValue::Bool(true),
+        );
+        store.insert("global", "global.key".to_string(), StoreValue::Bool(true));
+
+        assert_eq!(store.filtered("").namespaces.len(), 4);
+        assert_eq!(store.filtered("a.profile").namespaces.len(), 2);
+        assert_eq!(store.filtered("a.profile/x.lua").namespaces.len(), 1);
+        assert_eq!(store.filtered("global").namespaces.len(), 1);
+
+        assert_eq!(store.clear("a.profile"), 2);
+        assert_eq!(store.namespaces.len(), 2);
+        assert!(store.get("a.profile/x.lua", "key").is_none());
+
+        let mut imported = Store::new();
+        imported.insert("global", "global.key".to_string(), StoreValue::Bool(false));
+
+        store.merge(imported);
+
+        assert_eq!(
+            store.get("global", "global.key"),
+            Some(&StoreValue::Bool(false))
+        );
+    }
+}
@@ -0,0 +1,739 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! An opt-in remote control API, that allows other hosts on the network (e.g. a
+//! phone, a stream deck or a home automation system) to control the daemon.
+//!
+//! Clients speak JSON-RPC 2.0, either as newline-delimited messages over a plain
+//! TCP connection, or as text messages over a WebSocket connection on the same port
+
+use flume::{unbounded, Sender};
+use lazy_static::lazy_static;
+use log::{debug, error, info, trace, warn};
+use mlua::prelude::*;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::any::Any;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    constants, events,
+    plugins::{self, sdk_support, Plugin, ProfilesPlugin},
+    script,
+    scripting::parameters,
+    scripting::parameters_util,
+    DbusApiEvent, SwitchProfileResult,
+};
+
+pub mod rpc;
+pub mod websocket;
+
+use rpc::{Method, RpcError};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteApiError {
+    #[error("Remote API plugin error: {description}")]
+    PluginError { description: String },
+
+    #[error("No access token has been configured")]
+    NoTokenError {},
+}
+
+lazy_static! {
+    /// Clients that have subscribed to events
+    static ref SUBSCRIBERS: Arc<Mutex<Vec<Subscriber>>> = Arc::new(Mutex::new(Vec::new()));
+}
+
+/// The number of currently connected clients
+static CLIENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Unique id of a client connection
+static NEXT_CLIENT_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct Subscriber {
+    client_id: usize,
+    filter: rpc::EventFilter,
+    tx: Sender<Outgoing>,
+}
+
+/// Messages that are sent to a client by its writer thread
+#[derive(Debug, Clone)]
+enum Outgoing {
+    Text(String),
+
+    /// Frames that are sent by the WebSocket protocol itself, e.g. pongs
+    Raw(Vec<u8>),
+
+    Close,
+}
+
+/// Passes the frames that are sent by the WebSocket protocol on to the writer thread
+struct RawWriter(Sender<Outgoing>);
+
+impl Write for RawWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(Outgoing::Raw(buf.to_vec()))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    /// Newline-delimited JSON-RPC messages
+    Plain,
+    WebSocket,
+}
+
+fn subscribe(client_id: usize, filter: rpc::EventFilter, tx: Sender<Outgoing>) {
+    let mut subscribers = SUBSCRIBERS.lock();
+
+    subscribers.retain(|s| s.client_id != client_id);
+    subscribers.push(Subscriber {
+        client_id,
+        filter,
+        tx,
+    });
+}
+
+fn unsubscribe(client_id: usize) {
+    SUBSCRIBERS.lock().retain(|s| s.client_id != client_id);
+}
+
+/// Push an event to all clients that have subscribed to events of that kind
+pub fn publish_event(event: rpc::Event) {
+    let mut subscribers = SUBSCRIBERS.lock();
+
+    if subscribers.is_empty() {
+        return;
+    }
+
+    let notification = event.to_notification();
+
+    // drop subscriptions of clients that are gone
+    subscribers.retain(|s| {
+        !s.filter.matches(&event) || s.tx.send(Outgoing::Text(notification.clone())).is_ok()
+    });
+}
+
+/// Notify subscribed clients about a change of the state of the daemon
+pub fn notify_state_changed(event: &DbusApiEvent) {
+    let event = match event {
+        DbusApiEvent::ActiveProfileChanged => rpc::Event::ProfileChanged {
+            profile_file: get_active_profile(),
+        },
+
+        DbusApiEvent::ActiveSlotChanged => rpc::Event::SlotChanged {
+            slot: crate::ACTIVE_SLOT.load(Ordering::SeqCst) as u64,
+        },
+
+        DbusApiEvent::BrightnessChanged => rpc::Event::BrightnessChanged {
+            brightness: crate::BRIGHTNESS.load(Ordering::SeqCst) as i64,
+        },
+
+        _ => return,
+    };
+
+    publish_event(event);
+}
+
+fn get_active_profile() -> String {
+    crate::ACTIVE_PROFILE
+        .lock()
+        .as_ref()
+        .map(|profile| profile.profile_file.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Execute a request of an authenticated client
+fn dispatch(
+    client_id: usize,
+    method: Method,
+    tx: &Sender<Outgoing>,
+) -> std::result::Result<Value, RpcError> {
+    let failed = |e: eyre::Error| RpcError::new(rpc::OPERATION_FAILED, &e.to_string());
+
+    match method {
+        // re-authentication of an already authenticated client is a no-op
+        Method::Authenticate { .. } => Ok(json!(true)),
+
+        Method::Status => Ok(json!({
+            "description": "Eruption",
+            "version": env!("CARGO_PKG_VERSION"),
+            "active_profile": get_active_profile(),
+            "active_slot": crate::ACTIVE_SLOT.load(Ordering::SeqCst),
+            "brightness": crate::BRIGHTNESS.load(Ordering::SeqCst),
+        })),
+
+        Method::GetActiveProfile => Ok(json!(get_active_profile())),
+
+        Method::SwitchProfile { profile_file } => {
+            info!("Remote API: Switching to profile: {}", profile_file);
+
+            let switched =
+                crate::switch_profile_please(Some(&PathBuf::from(profile_file))).map_err(failed)?;
+
+            Ok(json!(switched == SwitchProfileResult::Switched))
+        }
+
+        Method::GetActiveSlot => Ok(json!(crate::ACTIVE_SLOT.load(Ordering::SeqCst))),
+
+        Method::SwitchSlot { slot } => {
+            if slot >= constants::NUM_SLOTS {
+                return Err(RpcError::new(rpc::INVALID_PARAMS, "Invalid slot"));
+            }
+
+            info!("Remote API: Switching to slot #{}", slot + 1);
+
+            ProfilesPlugin::switch_to_slot(slot);
+
+            Ok(json!(true))
+        }
+
+        Method::GetBrightness => Ok(json!(crate::BRIGHTNESS.load(Ordering::SeqCst))),
+
+        Method::SetBrightness { brightness } => {
+            if !(0..=100).contains(&brightness) {
+                return Err(RpcError::new(rpc::INVALID_PARAMS, "Invalid brightness"));
+            }
+
+            crate::BRIGHTNESS.store(brightness as isize, Ordering::SeqCst);
+            script::FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
+
+            Ok(json!(true))
+        }
+
+        Method::SetParameters(params) => {
+            let parameter_values: Vec<parameters::UntypedParameter> = params
+                .parameters
+                .into_iter()
+                .map(|(name, value)| parameters::UntypedParameter {
+                    name,
+                    value: match value {
+                        Value::String(s) => s,
+                        value => value.to_string(),
+                    },
+                })
+                .collect();
+
+            parameters_util::apply_parameters(
+                &params.profile_file,
+                &params.script_file,
+                &parameter_values,
+            )
+            .map_err(failed)?;
+
+            Ok(json!(true))
+        }
+
+        Method::SetCanvas { canvas } => {
+            sdk_support::set_canvas(&canvas);
+
+            Ok(json!(true))
+        }
+
+        Method::Subscribe(filter) => {
+            subscribe(client_id, filter, tx.clone());
+
+            Ok(json!(true))
+        }
+
+        Method::Unsubscribe => {
+            unsubscribe(client_id);
+
+            Ok(json!(true))
+        }
+    }
+}
+
+/// The state of a single client connection
+struct Session {
+    client_id: usize,
+    peer: SocketAddr,
+    stream: TcpStream,
+    token: String,
+    authenticated: Arc<AtomicBool>,
+    tx: Sender<Outgoing>,
+}
+
+impl Session {
+    fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::SeqCst)
+    }
+
+    fn authenticate(&mut self) {
+        info!("Remote API: Client {} authenticated", self.peer);
+
+        self.authenticated.store(true, Ordering::SeqCst);
+
+        // authenticated clients may stay idle, e.g. while waiting for events
+        if let Err(e) = self.stream.set_read_timeout(None) {
+            error!("Remote API: Could not set the read timeout: {}", e);
+        }
+    }
+
+    /// Handle a single JSON-RPC message. Returns `false` if the connection shall be closed
+    fn handle_message(&mut self, data: &str) -> bool {
+        let data = data.trim();
+
+        if data.is_empty() {
+            return true;
+        }
+
+        trace!("Remote API: {}: {}", self.peer, data);
+
+        let (id, result, keep_alive) = match rpc::parse_request(data) {
+            Ok(request) => match request.method {
+                Method::Authenticate { token } if !self.is_authenticated() => {
+                    if rpc::tokens_equal(&token, &self.token) {
+                        self.authenticate();
+
+                        (request.id, Ok(json!(true)), true)
+                    } else {
+                        warn!("Remote API: Authentication of client {} failed", self.peer);
+
+                        let error = RpcError::new(rpc::NOT_AUTHENTICATED, "Invalid token");
+
+                        (request.id, Err(error), false)
+                    }
+                }
+
+                _ if !self.is_authenticated() => {
+                    let error = RpcError::new(rpc::NOT_AUTHENTICATED, "Not authenticated");
+
+                    (request.id, Err(error), true)
+                }
+
+                method => {
+                    let result = dispatch(self.client_id, method, &self.tx);
+
+                    (request.id, result, true)
+                }
+            },
+
+            Err((id, error)) => (id, Err(error), true),
+        };
+
+        let reply = match (&id, result) {
+            // notifications are not answered
+            (None, Ok(_)) => None,
+
+            (Some(id), Ok(result)) => Some(rpc::response(id, result)),
+            (id, Err(error)) => Some(rpc::error_response(id.as_ref(), &error)),
+        };
+
+        if let Some(reply) = reply {
+            let _ = self.tx.send(Outgoing::Text(reply));
+        }
+
+        keep_alive
+    }
+}
+
+/// Detect the transport by peeking at the first bytes sent by the client
+fn detect_transport(reader: &mut BufReader<TcpStream>) -> Result<Transport> {
+    let buf = reader.fill_buf()?;
+
+    if buf.starts_with(b"GET ") {
+        Ok(Transport::WebSocket)
+    } else {
+        Ok(Transport::Plain)
+    }
+}
+
+/// Send the outgoing messages of a client. Clients that do not authenticate in time
+/// are disconnected, by shutting down the connection
+fn run_writer(
+    stream: TcpStream,
+    peer: SocketAddr,
+    transport: Transport,
+    rx: flume::Receiver<Outgoing>,
+    authenticated: Arc<AtomicBool>,
+) {
+    let deadline =
+        Instant::now() + Duration::from_millis(constants::REMOTE_API_AUTH_TIMEOUT_MILLIS);
+
+    let mut writer = BufWriter::new(stream);
+
+    loop {
+        let message = if authenticated.load(Ordering::SeqCst) {
+            rx.recv().map_err(|_| flume::RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_deadline(deadline)
+        };
+
+        let message = match message {
+            Ok(message) => message,
+
+            Err(flume::RecvTimeoutError::Timeout) => {
+                if authenticated.load(Ordering::SeqCst) {
+                    continue;
+                }
+
+                warn!("Remote API: Client {} did not authenticate in time", peer);
+
+                break;
+            }
+
+            Err(flume::RecvTimeoutError::Disconnected) => break,
+        };
+
+        let result = match (transport, message) {
+            (Transport::Plain, Outgoing::Text(text)) => writer
+                .write_all(text.as_bytes())
+                .and_then(|_| writer.write_all(b"\n"))
+                .and_then(|_| writer.flush())
+                .map_err(|e| e.into()),
+
+            (Transport::WebSocket, Outgoing::Text(text)) => {
+                websocket::write_text(&mut writer, &text)
+            }
+
+            (_, Outgoing::Raw(data)) => writer
+                .write_all(&data)
+                .and_then(|_| writer.flush())
+                .map_err(|e| e.into()),
+
+            (_, Outgoing::Close) => break,
+        };
+
+        if let Err(e) = result {
+            debug!("Remote API: Could not send message: {}", e);
+
+            break;
+        }
+    }
+
+    let _ = writer.flush();
+    let _ = writer.get_ref().shutdown(std::net::Shutdown::Both);
+}
+
+fn run_client(stream: TcpStream, peer: SocketAddr, token: String) -> Result<()> {
+    let client_id = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
+
+    // unauthenticated clients must not be able to occupy a connection
+    stream.set_read_timeout(Some(Duration::from_millis(
+        constants::REMOTE_API_AUTH_TIMEOUT_MILLIS,
+    )))?;
+
+    let (tx, rx) = unbounded();
+    let authenticated = Arc::new(AtomicBool::new(false));
+
+    let mut session = Session {
+        client_id,
+        peer,
+        stream: stream.try_clone()?,
+        token,
+        authenticated: authenticated.clone(),
+        tx,
+    };
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let transport = detect_transport(&mut reader)?;
+
+    let writer_stream = stream.try_clone()?;
+    let writer = thread::Builder::new()
+        .name(format!("remote-api/{}", client_id))
+        .spawn(move || run_writer(writer_stream, peer, transport, rx, authenticated))?;
+
+    let result = (|| -> Result<()> {
+        match transport {
+            Transport::Plain => {
+                let mut line = String::new();
+
+                loop {
+                    line.clear();
+
+                    let n = (&mut reader)
+                        .take(constants::REMOTE_API_MAX_MESSAGE_SIZE as u64)
+                        .read_line(&mut line)?;
+
+                    if n == 0 || crate::QUIT.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    if !line.ends_with('\n') && n >= constants::REMOTE_API_MAX_MESSAGE_SIZE {
+                        return Err(websocket::WebSocketError::MessageTooLarge {}.into());
+                    }
+
+                    if !session.handle_message(&line) {
+                        break;
+                    }
+                }
+            }
+
+            Transport::WebSocket => {
+                let handshake = websocket::read_handshake(&mut reader)?;
+
+                let key = match handshake.key() {
+                    Some(key) => key,
+
+                    None => {
+                        (&stream)
+                            .write_all(websocket::error_response("400 Bad Request").as_bytes())?;
+
+                        return Err(RemoteApiError::PluginError {
+                            description: "Invalid WebSocket upgrade request".to_owned(),
+                        }
+                        .into());
+                    }
+                };
+
+                // WebSocket clients may authenticate during the opening handshake
+                let token = handshake
+                    .header("authorization")
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .or_else(|| handshake.query_parameter("token"));
+
+                if let Some(token) = token {
+                    if !rpc::tokens_equal(token.trim(), &session.token) {
+                        warn!("Remote API: Authentication of client {} failed", peer);
+
+                        (&stream)
+                            .write_all(websocket::error_response("401 Unauthorized").as_bytes())?;
+
+                        return Ok(());
+                    }
+
+                    session.authenticate();
+                }
+
+                (&stream).write_all(websocket::handshake_response(key).as_bytes())?;
+
+                let mut connection = websocket::Connection::new(
+                    &mut reader,
+                    RawWriter(session.tx.clone()),
+                    constants::REMOTE_API_MAX_MESSAGE_SIZE,
+                );
+
+                while let Some(message) = connection.read_message()? {
+                    if crate::QUIT.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    if let websocket::Message::Text(text) = message {
+                        if !session.handle_message(&text) {
+                            break;
+                        }
+                    }
+                }
+
+                // the close frame is sent after all pending replies
+                connection.close()?;
+            }
+        }
+
+        Ok(())
+    })();
+
+    unsubscribe(client_id);
+
+    let _ = session.tx.send(Outgoing::Close);
+    drop(session);
+
+    let _ = writer.join();
+
+    result
+}
+
+/// A plugin that provides a remote control API via TCP and WebSockets
+pub struct RemoteApiPlugin {}
+
+impl RemoteApiPlugin {
+    pub fn new() -> Self {
+        RemoteApiPlugin {}
+    }
+
+    fn start_listener_thread(listen_address: &str, token: String) -> Result<()> {
+        let listener = TcpListener::bind(listen_address)?;
+
+        info!("Remote API: Listening on {}", listen_address);
+
+        let builder = thread::Builder::new().name("remote-api".into());
+        builder.spawn(move || {
+            for stream in listener.incoming() {
+                if crate::QUIT.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let stream = match stream {
+                    Ok(stream) => stream,
+
+                    Err(e) => {
+                        error!("Remote API: Could not accept connection: {}", e);
+
+                        continue;
+                    }
+                };
+
+                let peer = match stream.peer_addr() {
+                    Ok(peer) => peer,
+                    Err(_) => continue,
+                };
+
+                if CLIENTS.load(Ordering::SeqCst) >= constants::REMOTE_API_MAX_CLIENTS {
+                    warn!("Remote API: Too many clients, rejecting {}", peer);
+
+                    continue;
+                }
+
+                info!("Remote API: Client connected: {}", peer);
+
+                let token = token.clone();
+
+                CLIENTS.fetch_add(1, Ordering::SeqCst);
+
+                let result = thread::Builder::new()
+                    .name("remote-api/client".into())
+                    .spawn(move || {
+                        if let Err(e) = run_client(stream, peer, token) {
+                            debug!("Remote API: Client {}: {}", peer, e);
+                        }
+
+                        info!("Remote API: Client disconnected: {}", peer);
+
+                        CLIENTS.fetch_sub(1, Ordering::SeqCst);
+                    });
+
+                if let Err(e) = result {
+                    error!("Remote API: Could not spawn a thread: {}", e);
+
+                    CLIENTS.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        })?;
+
+        Ok(())
+    }
+}
+
+impl Default for RemoteApiPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for RemoteApiPlugin {
+    fn get_name(&self) -> String {
+        "Remote API".to_string()
+    }
+
+    fn get_description(&self) -> String {
+        "Remote control API via TCP and WebSockets".to_string()
+    }
+
+    fn initialize(&mut self) -> plugins::Result<()> {
+        let (enabled, listen_address, token) = {
+            let config = crate::CONFIG.lock();
+            let config = config.as_ref().unwrap();
+
+            (
+                config.get::<bool>("remote.enabled").unwrap_or(false),
+                config
+                    .get::<String>("remote.listen_address")
+                    .unwrap_or_else(|_| constants::REMOTE_API_DEFAULT_ADDRESS.to_string()),
+                config.get::<String>("remote.token").unwrap_or_default(),
+            )
+        };
+
+        if !enabled {
+            debug!("The remote API is disabled");
+
+            return Ok(());
+        }
+
+        // never expose the API without authentication
+        if token.trim().is_empty() {
+            error!("The remote API is enabled, but no access token has been configured");
+
+            return Err(RemoteApiError::NoTokenError {}.into());
+        }
+
+        Self::start_listener_thread(&listen_address, token.trim().to_string())?;
+
+        events::register_observer(|event: &events::Event| {
+            let event = match event {
+                events::Event::KeyDown(index) => rpc::Event::Key {
+                    key_index: *index as u32,
+                    pressed: true,
+                },
+
+                events::Event::KeyUp(index) => rpc::Event::Key {
+                    key_index: *index as u32,
+                    pressed: false,
+                },
+
+                events::Event::MouseButtonDown(index) => rpc::Event::MouseButton {
+                    button_index: *index as u32,
+                    pressed: true,
+                },
+
+                events::Event::MouseButtonUp(index) => rpc::Event::MouseButton {
+                    button_index: *index as u32,
+                    pressed: false,
+                },
+
+                events::Event::MouseWheelEvent(direction) => rpc::Event::MouseWheel {
+                    direction: *direction as u32,
+                },
+
+                _ => return Ok(true),
+            };
+
+            publish_event(event);
+
+            Ok(true) // event has been processed
+        });
+
+        Ok(())
+    }
+
+    fn register_lua_funcs(&self, _lua_ctx: &Lua) -> mlua::Result<()> {
+        Ok(())
+    }
+
+    async fn main_loop_hook(&self, _ticks: u64) {}
+
+    fn sync_main_loop_hook(&self, _ticks: u64) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,428 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! JSON-RPC 2.0 messages of the remote control API
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Invalid JSON was received
+pub const PARSE_ERROR: i64 = -32700;
+
+/// The JSON sent is not a valid request object
+pub const INVALID_REQUEST: i64 = -32600;
+
+/// The method does not exist
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Invalid method parameters
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// The requested operation failed
+pub const OPERATION_FAILED: i64 = -32000;
+
+/// The client has not been authenticated yet, or the token is invalid
+pub const NOT_AUTHENTICATED: i64 = -32001;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// A request, as it has been received from the client
+#[derive(Debug, Clone, Deserialize)]
+struct RawRequest {
+    jsonrpc: String,
+
+    /// Requests without an id are notifications, they will not be answered
+    #[serde(default)]
+    id: Option<Value>,
+
+    method: String,
+
+    #[serde(default)]
+    params: Value,
+}
+
+/// The methods of the remote control API
+#[derive(Debug, Clone, PartialEq)]
+pub enum Method {
+    Authenticate {
+        token: String,
+    },
+
+    Status,
+
+    GetActiveProfile,
+    SwitchProfile {
+        profile_file: String,
+    },
+
+    GetActiveSlot,
+    SwitchSlot {
+        slot: usize,
+    },
+
+    GetBrightness,
+    SetBrightness {
+        brightness: i64,
+    },
+
+    SetParameters(SetParametersParams),
+
+    /// Set the canvas to a flat list of RGBA values
+    SetCanvas {
+        canvas: Vec<u8>,
+    },
+
+    Subscribe(EventFilter),
+    Unsubscribe,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SetParametersParams {
+    pub profile_file: String,
+    pub script_file: String,
+    pub parameters: BTreeMap<String, Value>,
+}
+
+/// The kinds of events a client subscribes to
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct EventFilter {
+    #[serde(default)]
+    pub keyboard_events: bool,
+
+    #[serde(default)]
+    pub mouse_events: bool,
+
+    #[serde(default)]
+    pub state_events: bool,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        match event {
+            Event::Key { .. } => self.keyboard_events,
+
+            Event::MouseButton { .. } | Event::MouseWheel { .. } => self.mouse_events,
+
+            Event::ProfileChanged { .. }
+            | Event::SlotChanged { .. }
+            | Event::BrightnessChanged { .. } => self.state_events,
+        }
+    }
+}
+
+/// An event that is pushed to subscribed clients as a JSON-RPC notification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Key { key_index: u32, pressed: bool },
+    MouseButton { button_index: u32, pressed: bool },
+    MouseWheel { direction: u32 },
+
+    ProfileChanged { profile_file: String },
+    SlotChanged { slot: u64 },
+    BrightnessChanged { brightness: i64 },
+}
+
+impl Event {
+    /// Serialize the event as a JSON-RPC notification
+    pub fn to_notification(&self) -> String {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "event",
+            "params": self,
+        })
+        .to_string()
+    }
+}
+
+/// A parsed request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    /// The id of the request, `None` for notifications
+    pub id: Option<Value>,
+
+    pub method: Method,
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, &e.to_string()))
+}
+
+/// Parse a request. On error, the id of the request is returned along with the error
+pub fn parse_request(data: &str) -> Result<Request, (Option<Value>, RpcError)> {
+    let value: Value = serde_json::from_str(data)
+        .map_err(|e| (None, RpcError::new(PARSE_ERROR, &e.to_string())))?;
+
+    let id = value.get("id").cloned();
+
+    let request: RawRequest = serde_json::from_value(value)
+        .map_err(|e| (id.clone(), RpcError::new(INVALID_REQUEST, &e.to_string())))?;
+
+    if request.jsonrpc != "2.0" {
+        return Err((
+            request.id,
+            RpcError::new(INVALID_REQUEST, "Unsupported JSON-RPC version"),
+        ));
+    }
+
+    #[derive(Deserialize)]
+    struct AuthenticateParams {
+        token: String,
+    }
+
+    #[derive(Deserialize)]
+    struct SwitchProfileParams {
+        profile_file: String,
+    }
+
+    #[derive(Deserialize)]
+    struct SwitchSlotParams {
+        slot: usize,
+    }
+
+    #[derive(Deserialize)]
+    struct SetBrightnessParams {
+        brightness: i64,
+    }
+
+    #[derive(Deserialize)]
+    struct SetCanvasParams {
+        canvas: Vec<u8>,
+    }
+
+    let method = (|| match request.method.as_str() {
+        "authenticate" => {
+            let p: AuthenticateParams = params(request.params)?;
+
+            Ok(Method::Authenticate { token: p.token })
+        }
+
+        "status" => Ok(Method::Status),
+
+        "get_active_profile" => Ok(Method::GetActiveProfile),
+
+        "switch_profile" => {
+            let p: SwitchProfileParams = params(request.params)?;
+
+            Ok(Method::SwitchProfile {
+                profile_file: p.profile_file,
+            })
+        }
+
+        "get_active_slot" => Ok(Method::GetActiveSlot),
+
+        "switch_slot" => {
+            let p: SwitchSlotParams = params(request.params)?;
+
+            Ok(Method::SwitchSlot { slot: p.slot })
+        }
+
+        "get_brightness" => Ok(Method::GetBrightness),
+
+        "set_brightness" => {
+            let p: SetBrightnessParams = params(request.params)?;
+
+            Ok(Method::SetBrightness {
+                brightness: p.brightness,
+            })
+        }
+
+        "set_parameters" => Ok(Method::SetParameters(params(request.params)?)),
+
+        "set_canvas" => {
+            let p: SetCanvasParams = params(request.params)?;
+
+            Ok(Method::SetCanvas { canvas: p.canvas })
+        }
+
+        "subscribe" => Ok(Method::Subscribe(params(request.params)?)),
+
+        "unsubscribe" => Ok(Method::Unsubscribe),
+
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+    })()
+    .map_err(|e| (request.id.clone(), e))?;
+
+    Ok(Request {
+        id: request.id,
+        method,
+    })
+}
+
+/// Serialize a successful response
+pub fn response(id: &Value, result: Value) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": result,
+    })
+    .to_string()
+}
+
+/// Serialize an error response. Errors of requests without an id are reported with an id of `null`
+pub fn error_response(id: Option<&Value>, error: &RpcError) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id.unwrap_or(&Value::Null),
+        "error": {
+            "code": error.code,
+            "message": error.message,
+        },
+    })
+    .to_string()
+}
+
+/// Compare two tokens in constant time
+pub fn tokens_equal(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_requests() {
+        let request = parse_request(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "switch_slot", "params": {"slot": 2}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(request.id, Some(json!(1)));
+        assert_eq!(request.method, Method::SwitchSlot { slot: 2 });
+
+        let request =
+            parse_request(r#"{"jsonrpc": "2.0", "id": "a", "method": "status"}"#).unwrap();
+        assert_eq!(request.method, Method::Status);
+
+        let request = parse_request(
+            r#"{"jsonrpc": "2.0", "method": "subscribe", "params": {"state_events": true}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(request.id, None);
+        assert_eq!(
+            request.method,
+            Method::Subscribe(EventFilter {
+                keyboard_events: false,
+                mouse_events: false,
+                state_events: true,
+            })
+        );
+
+        let request = parse_request(
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "set_parameters", "params": {
+                "profile_file": "a.profile", "script_file": "b.lua",
+                "parameters": {"speed": 2.5}}}"#,
+        )
+        .unwrap();
+
+        match request.method {
+            Method::SetParameters(p) => assert_eq!(p.parameters["speed"], json!(2.5)),
+            _ => panic!("Unexpected method"),
+        }
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let error = |data| parse_request(data).unwrap_err();
+
+        assert_eq!(error("{").1.code, PARSE_ERROR);
+        assert_eq!(
+            error(r#"{"id": 1, "method": "status"}"#).1.code,
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            error(r#"{"jsonrpc": "1.0", "id": 1, "method": "status"}"#)
+                .1
+                .code,
+            INVALID_REQUEST
+        );
+
+        let (id, e) = error(r#"{"jsonrpc": "2.0", "id": 7, "method": "reboot"}"#);
+        assert_eq!((id, e.code), (Some(json!(7)), METHOD_NOT_FOUND));
+
+        assert_eq!(
+            error(
+                r#"{"jsonrpc": "2.0", "id": 1, "method": "set_canvas",
+                    "params": {"canvas": [256]}}"#
+            )
+            .1
+            .code,
+            INVALID_PARAMS
+        );
+    }
+
+    #[test]
+    fn responses() {
+        let parse = |data: String| serde_json::from_str::<Value>(&data).unwrap();
+
+        assert_eq!(
+            parse(response(&json!(1), json!(true))),
+            json!({"jsonrpc": "2.0", "id": 1, "result": true})
+        );
+
+        assert_eq!(
+            parse(error_response(
+                None,
+                &RpcError::new(PARSE_ERROR, "Parse error")
+            )),
+            json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {"code": -32700, "message": "Parse error"}
+            })
+        );
+
+        assert_eq!(
+            parse(Event::SlotChanged { slot: 1 }.to_notification()),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "event",
+                "params": {"type": "slot_changed", "slot": 1}
+            })
+        );
+    }
+
+    #[test]
+    fn compare_tokens() {
+        assert!(tokens_equal("secret", "secret"));
+        assert!(!tokens_equal("secret", "secreT"));
+        assert!(!tokens_equal("secret", "secret2"));
+        assert!(!tokens_equal("", "secret"));
+    }
+}
@@ -0,0 +1,376 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+//! The server side of the WebSocket protocol (RFC 6455). The opening handshake is
+//! handled here, since clients may authenticate during the handshake, the framing
+//! is left to `tungstenite`
+
+use sha1::{Digest, Sha1};
+use std::io::{self, BufRead, Read, Write};
+use tungstenite::protocol::frame::coding::{Data, OpCode};
+use tungstenite::protocol::frame::Frame;
+use tungstenite::protocol::{Role, WebSocketConfig, WebSocketContext};
+
+pub use tungstenite::Message;
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// The GUID that is appended to the key of the client, to compute the accept key
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The max size of the HTTP request of the opening handshake
+const MAX_HANDSHAKE_SIZE: usize = 8192;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebSocketError {
+    #[error("Invalid opening handshake")]
+    InvalidHandshake {},
+
+    #[error("Message too large")]
+    MessageTooLarge {},
+}
+
+/// The HTTP request of the opening handshake
+#[derive(Debug, Clone, Default)]
+pub struct Handshake {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Handshake {
+    /// Get the value of the header `name`, the lookup is case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get the value of the parameter `name` of the query string
+    pub fn query_parameter(&self, name: &str) -> Option<&str> {
+        let (_, query) = self.path.split_once('?')?;
+
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The key of the client, if this is a valid WebSocket upgrade request
+    pub fn key(&self) -> Option<&str> {
+        let upgrade = self.header("upgrade")?;
+        let version = self.header("sec-websocket-version")?;
+
+        if upgrade.eq_ignore_ascii_case("websocket") && version.trim() == "13" {
+            self.header("sec-websocket-key").map(|key| key.trim())
+        } else {
+            None
+        }
+    }
+}
+
+/// Read the HTTP request of the opening handshake
+pub fn read_handshake<R: BufRead>(reader: &mut R) -> Result<Handshake> {
+    let mut result = Handshake::default();
+
+    // a line without a newline must not exceed the max size either
+    let mut reader = reader.take(MAX_HANDSHAKE_SIZE as u64);
+
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+
+        let n = reader.read_line(&mut line)?;
+
+        if n == 0 || !line.ends_with('\n') {
+            return Err(WebSocketError::InvalidHandshake {}.into());
+        }
+
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+
+        if result.path.is_empty() {
+            // request line, e.g. "GET /?token=abc HTTP/1.1"
+            let mut parts = line.split_whitespace();
+
+            match (parts.next(), parts.next()) {
+                (Some("GET"), Some(path)) => result.path = path.to_string(),
+                _ => return Err(WebSocketError::InvalidHandshake {}.into()),
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            result
+                .headers
+                .push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Compute the value of the `Sec-WebSocket-Accept` header
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+
+    sha1.update(key.as_bytes());
+    sha1.update(WEBSOCKET_GUID.as_bytes());
+
+    base64::encode(sha1.finalize())
+}
+
+/// The response that completes the opening handshake
+pub fn handshake_response(key: &str) -> String {
+    format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )
+}
+
+/// A response that rejects the opening handshake, e.g. `401 Unauthorized`
+pub fn error_response(status: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    )
+}
+
+/// The stream of a connection: Messages are read from `reader`, while the frames
+/// that are sent by the protocol itself, like pongs, are written to `writer`
+struct Stream<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W> Read for Stream<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R, W: Write> Write for Stream<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// The receiving side of a connection, after the opening handshake has been completed.
+/// Fragmented messages are reassembled, pings are answered and the closing
+/// handshake is performed by `tungstenite`
+pub struct Connection<R, W> {
+    context: WebSocketContext,
+    stream: Stream<R, W>,
+}
+
+impl<R: Read, W: Write> Connection<R, W> {
+    pub fn new(reader: R, writer: W, max_size: usize) -> Self {
+        let config = WebSocketConfig {
+            max_message_size: Some(max_size),
+            max_frame_size: Some(max_size),
+            ..WebSocketConfig::default()
+        };
+
+        Self {
+            context: WebSocketContext::new(Role::Server, Some(config)),
+            stream: Stream { reader, writer },
+        }
+    }
+
+    /// Read the next message, returns `None` as soon as the connection has been closed
+    pub fn read_message(&mut self) -> Result<Option<Message>> {
+        match self.context.read_message(&mut self.stream) {
+            Ok(Message::Close(_)) => Ok(None),
+            Ok(message) => Ok(Some(message)),
+
+            Err(tungstenite::Error::ConnectionClosed) | Err(tungstenite::Error::AlreadyClosed) => {
+                Ok(None)
+            }
+
+            Err(tungstenite::Error::Capacity(_)) => Err(WebSocketError::MessageTooLarge {}.into()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Start the closing handshake, or complete it, if it has been started by the client
+    pub fn close(&mut self) -> Result<()> {
+        match self.context.close(&mut self.stream, None) {
+            Ok(()) => Ok(()),
+
+            Err(tungstenite::Error::ConnectionClosed) | Err(tungstenite::Error::AlreadyClosed) => {
+                Ok(())
+            }
+
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Write a text message, as sent by the server
+pub fn write_text<W: Write>(writer: &mut W, text: &str) -> Result<()> {
+    Frame::message(text.into(), OpCode::Data(Data::Text), true).format(writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const OPCODE_CONTINUATION: u8 = 0x0;
+    const OPCODE_TEXT: u8 = 0x1;
+    const OPCODE_CLOSE: u8 = 0x8;
+    const OPCODE_PING: u8 = 0x9;
+
+    /// Encode a masked frame, as sent by a client
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+
+        let mut result = vec![if fin { 0x80 } else { 0x00 } | opcode];
+
+        if payload.len() < 126 {
+            result.push(0x80 | payload.len() as u8);
+        } else {
+            result.push(0x80 | 126);
+            result.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+
+        result.extend_from_slice(&mask);
+        result.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+
+        result
+    }
+
+    #[test]
+    fn handshake() {
+        let request = "GET /chat?token=secret HTTP/1.1\r\n\
+                       Host: server.example.com\r\n\
+                       Upgrade: websocket\r\n\
+                       Connection: Upgrade\r\n\
+                       Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                       Sec-WebSocket-Version: 13\r\n\r\n";
+
+        let handshake = read_handshake(&mut Cursor::new(request)).unwrap();
+
+        assert_eq!(handshake.path, "/chat?token=secret");
+        assert_eq!(handshake.query_parameter("token"), Some("secret"));
+        assert_eq!(handshake.header("HOST"), Some("server.example.com"));
+        assert_eq!(handshake.key(), Some("dGhlIHNhbXBsZSBub25jZQ=="));
+
+        // the example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        assert!(read_handshake(&mut Cursor::new("POST / HTTP/1.1\r\n\r\n")).is_err());
+        assert!(read_handshake(&mut Cursor::new("GET / HTTP/1.1\r\n")).is_err());
+    }
+
+    #[test]
+    fn oversized_handshake() {
+        // a single line without a newline
+        let request = format!("GET /{}", "x".repeat(1024 * 1024));
+        assert!(read_handshake(&mut Cursor::new(request)).is_err());
+
+        // too many headers
+        let request = format!("GET / HTTP/1.1\r\n{}\r\n", "Host: a\r\n".repeat(1024));
+        assert!(read_handshake(&mut Cursor::new(request)).is_err());
+    }
+
+    #[test]
+    fn messages() {
+        let mut data = client_frame(true, OPCODE_TEXT, b"hello");
+
+        // a fragmented message, interleaved with a ping
+        data.extend(client_frame(false, OPCODE_TEXT, b"frag"));
+        data.extend(client_frame(true, OPCODE_PING, b"p"));
+        data.extend(client_frame(true, OPCODE_CONTINUATION, &[b'x'; 200]));
+        data.extend(client_frame(true, OPCODE_CLOSE, &[]));
+
+        let mut replies = Vec::new();
+        let mut connection = Connection::new(Cursor::new(data), &mut replies, 1024);
+
+        let mut read = || connection.read_message().unwrap();
+
+        assert_eq!(read(), Some(Message::Text("hello".to_string())));
+        assert_eq!(read(), Some(Message::Ping(b"p".to_vec())));
+        assert_eq!(
+            read(),
+            Some(Message::Text(format!("frag{}", "x".repeat(200))))
+        );
+        assert_eq!(read(), None);
+
+        connection.close().unwrap();
+        drop(connection);
+
+        // the ping has been answered, as well as the close frame
+        assert_eq!(replies, b"\x8a\x01p\x88\x00");
+    }
+
+    #[test]
+    fn invalid_messages() {
+        // messages that exceed the max size are rejected
+        let data = client_frame(true, OPCODE_TEXT, &[b'x'; 200]);
+        let mut connection = Connection::new(Cursor::new(data), io::sink(), 100);
+        assert!(connection.read_message().is_err());
+
+        let mut data = client_frame(false, OPCODE_TEXT, &[b'x'; 80]);
+        data.extend(client_frame(true, OPCODE_CONTINUATION, &[b'x'; 80]));
+        let mut connection = Connection::new(Cursor::new(data), io::sink(), 100);
+        assert!(connection.read_message().is_err());
+
+        // frames sent by a client have to be masked
+        let data = b"\x81\x05hello".to_vec();
+        let mut connection = Connection::new(Cursor::new(data), io::sink(), 100);
+        assert!(connection.read_message().is_err());
+    }
+
+    #[test]
+    fn write_messages() {
+        let mut buf = Vec::new();
+        write_text(&mut buf, "hello").unwrap();
+        assert_eq!(buf, b"\x81\x05hello");
+
+        let mut buf = Vec::new();
+        write_text(&mut buf, &"x".repeat(300)).unwrap();
+        assert_eq!(&buf[..4], &[0x81, 126, 0x01, 0x2c]);
+        assert_eq!(buf.len(), 304);
+
+        // a close frame, if the server closes the connection
+        let mut buf = Vec::new();
+        let mut connection = Connection::new(io::empty(), &mut buf, 100);
+        connection.close().unwrap();
+        drop(connection);
+        assert_eq!(buf, b"\x88\x00");
+    }
+}
@@ -20,15 +20,17 @@
 */
 
 use crate::{
-    constants, hwdevices, init_keyboard_device, init_misc_device, init_mouse_device, script,
-    spawn_keyboard_input_thread, spawn_misc_input_thread, spawn_mouse_input_thread, DbusApiEvent,
-    SwitchProfileResult, SDK_SUPPORT_ACTIVE,
+    canvas, constants, dbus_interface, events, hwdevices, init_keyboard_device,
+    init_misc_device, init_mouse_device, script, spawn_keyboard_input_thread,
+    spawn_misc_input_thread, spawn_mouse_input_thread, DbusApiEvent, SwitchProfileResult,
+    SDK_SUPPORT_ACTIVE,
 };
-use flume::unbounded;
+use flume::{unbounded, Receiver, Sender};
 use lazy_static::lazy_static;
 use log::{debug, error, info, trace};
 use mlua::prelude::*;
 use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
 use nix::unistd::unlink;
 use parking_lot::{Mutex, RwLock};
 use prost::Message;
@@ -70,11 +72,151 @@ lazy_static! {
         g: 0x00,
         b: 0x00,
         a: 0x00,
-    }; constants::CANVAS_SIZE]));
+    }; canvas::get_canvas_size()]));
 }
 
 lazy_static! {
     pub static ref LISTENER: Arc<Mutex<Option<Socket>>> = Arc::new(Mutex::new(None));
+
+    /// The event subscription of the currently connected SDK client
+    static ref SUBSCRIPTION: Arc<Mutex<Option<Subscription>>> = Arc::new(Mutex::new(None));
+}
+
+/// The kinds of events a SDK client has subscribed to
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EventFilter {
+    pub keyboard_events: bool,
+    pub mouse_events: bool,
+    pub state_events: bool,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &protocol::Event) -> bool {
+        use protocol::event::EventMessage;
+
+        match event.event_message {
+            Some(EventMessage::Key(_)) => self.keyboard_events,
+
+            Some(EventMessage::MouseButton(_)) | Some(EventMessage::MouseWheel(_)) => {
+                self.mouse_events
+            }
+
+            Some(EventMessage::ProfileChanged(_))
+            | Some(EventMessage::SlotChanged(_))
+            | Some(EventMessage::BrightnessChanged(_)) => self.state_events,
+
+            None => false,
+        }
+    }
+}
+
+struct Subscription {
+    filter: EventFilter,
+    tx: Sender<protocol::Event>,
+}
+
+/// Subscribe the currently connected SDK client to events matching `filter`.
+/// Returns the receiving end of the event queue
+fn subscribe(filter: EventFilter) -> Receiver<protocol::Event> {
+    let (tx, rx) = unbounded();
+
+    SUBSCRIPTION.lock().replace(Subscription { filter, tx });
+
+    rx
+}
+
+fn unsubscribe() {
+    SUBSCRIPTION.lock().take();
+}
+
+/// Keyboard and mouse events are only delivered to clients that hold the
+/// `org.eruption.monitor` permission, just like on the D-Bus interface
+fn has_monitor_permission(socket: &Socket) -> Result<bool> {
+    let credentials = getsockopt(socket.as_raw_fd(), PeerCredentials)?;
+
+    if credentials.uid() == 0 {
+        return Ok(true);
+    }
+
+    let (result, _dismissed) = dbus_interface::perms::has_monitor_permission_of_process(
+        credentials.pid() as u32,
+        credentials.uid(),
+    )?;
+
+    Ok(result)
+}
+
+/// Push an event to the connected SDK client, if it has subscribed to events of that kind
+pub fn publish_event(event: protocol::Event) {
+    let mut subscription = SUBSCRIPTION.lock();
+
+    if let Some(sub) = subscription.as_ref() {
+        if sub.filter.matches(&event) && sub.tx.send(event).is_err() {
+            // the client is gone, drop the stale subscription
+            subscription.take();
+        }
+    }
+}
+
+/// Notify a subscribed SDK client about a change of the state of the daemon
+pub fn notify_state_changed(event: &DbusApiEvent) {
+    use protocol::event::EventMessage;
+
+    let event_message = match event {
+        DbusApiEvent::ActiveProfileChanged => {
+            let profile_file = crate::ACTIVE_PROFILE
+                .lock()
+                .as_ref()
+                .map(|profile| profile.profile_file.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            EventMessage::ProfileChanged(protocol::ProfileChangedEvent { profile_file })
+        }
+
+        DbusApiEvent::ActiveSlotChanged => EventMessage::SlotChanged(protocol::SlotChangedEvent {
+            slot: crate::ACTIVE_SLOT.load(Ordering::SeqCst) as u64,
+        }),
+
+        DbusApiEvent::BrightnessChanged => {
+            EventMessage::BrightnessChanged(protocol::BrightnessChangedEvent {
+                brightness: crate::BRIGHTNESS.load(Ordering::SeqCst) as i64,
+            })
+        }
+
+        _ => return,
+    };
+
+    publish_event(protocol::Event {
+        event_message: Some(event_message),
+    });
+}
+
+/// Set the canvas to a flat list of RGBA values, and activate it
+pub fn set_canvas(payload_map: &[u8]) {
+    let mut led_map = vec![
+        RGBA {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        };
+        canvas::get_canvas_size()
+    ];
+
+    for (led, rgba) in led_map.iter_mut().zip(payload_map.chunks_exact(4)) {
+        *led = RGBA {
+            r: rgba[0],
+            g: rgba[1],
+            b: rgba[2],
+            a: rgba[3],
+        };
+    }
+
+    LED_MAP.write().copy_from_slice(&led_map);
+
+    SDK_SUPPORT_ACTIVE.store(true, Ordering::SeqCst);
+
+    script::FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
 }
 
 use bincode::{Decode, Encode};
@@ -362,6 +504,10 @@ impl SdkSupportPlugin {
                     Ok((socket, _sockaddr)) => {
                         debug!("Eruption SDK client connected");
 
+                        // events are only delivered to the client that subscribed to them
+                        unsubscribe();
+                        let mut events_rx: Option<Receiver<protocol::Event>> = None;
+
                         // socket.set_nodelay(true)?; // not supported on AF_UNIX on Linux
                         socket.set_send_buffer_size(constants::NET_BUFFER_CAPACITY * 2)?;
                         socket.set_recv_buffer_size(constants::NET_BUFFER_CAPACITY * 2)?;
@@ -609,43 +755,7 @@ impl SdkSupportPlugin {
                                             )) => {
                                                 trace!("Set canvas");
 
-                                                let payload_map = message.canvas;
-
-                                                let mut led_map = [RGBA {
-                                                    r: 0,
-                                                    g: 0,
-                                                    b: 0,
-                                                    a: 0,
-                                                };
-                                                    constants::CANVAS_SIZE];
-
-                                                let mut i = 0;
-                                                let mut cntr = 0;
-
-                                                loop {
-                                                    led_map[cntr] = RGBA {
-                                                        r: payload_map[i],
-                                                        g: payload_map[i + 1],
-                                                        b: payload_map[i + 2],
-                                                        a: payload_map[i + 3],
-                                                    };
-
-                                                    i += 4;
-                                                    cntr += 1;
-
-                                                    if cntr >= led_map.len()
-                                                        || i >= payload_map.len()
-                                                    {
-                                                        break;
-                                                    }
-                                                }
-
-                                                LED_MAP.write().copy_from_slice(&led_map);
-
-                                                SDK_SUPPORT_ACTIVE.store(true, Ordering::SeqCst);
-
-                                                script::FRAME_GENERATION_COUNTER
-                                                    .fetch_add(1, Ordering::SeqCst);
+                                                set_canvas(&message.canvas);
 
                                                 let response = protocol::Response {
                                                     response_message: Some(
@@ -721,6 +831,95 @@ impl SdkSupportPlugin {
                                                 }
                                             }
 
+                                            Some(protocol::request::RequestMessage::Subscribe(
+                                                message,
+                                            )) => {
+                                                trace!("Subscribe to events");
+
+                                                let filter = EventFilter {
+                                                    keyboard_events: message.keyboard_events,
+                                                    mouse_events: message.mouse_events,
+                                                    state_events: message.state_events,
+                                                };
+
+                                                let permitted = if filter.keyboard_events
+                                                    || filter.mouse_events
+                                                {
+                                                    has_monitor_permission(&socket)
+                                                        .unwrap_or_else(|e| {
+                                                            error!("Could not check the permissions of the client: {}", e);
+                                                            false
+                                                        })
+                                                } else {
+                                                    true
+                                                };
+
+                                                if permitted {
+                                                    events_rx = Some(subscribe(filter));
+                                                } else {
+                                                    info!("Eruption SDK client is not permitted to receive input events");
+                                                }
+
+                                                let response = protocol::Response {
+                                                    response_message: Some(
+                                                        protocol::response::ResponseMessage::Subscribe(
+                                                            protocol::SubscribeResponse {
+                                                                subscribed: permitted,
+                                                            },
+                                                        ),
+                                                    ),
+                                                };
+
+                                                let mut buf = Vec::new();
+                                                response.encode_length_delimited(&mut buf)?;
+
+                                                // send data
+                                                match socket.send(&buf) {
+                                                    Ok(_n) => {}
+
+                                                    Err(_e) => {
+                                                        return Err(SdkPluginError::PluginError {
+                                                            description: "Lost connection to Eruption SDK client".to_owned(),
+                                                        }
+                                                            .into());
+                                                    }
+                                                }
+                                            }
+
+                                            Some(
+                                                protocol::request::RequestMessage::Unsubscribe(
+                                                    _message,
+                                                ),
+                                            ) => {
+                                                trace!("Unsubscribe from events");
+
+                                                unsubscribe();
+                                                events_rx = None;
+
+                                                let response = protocol::Response {
+                                                    response_message: Some(
+                                                        protocol::response::ResponseMessage::Unsubscribe(
+                                                            protocol::UnsubscribeResponse {},
+                                                        ),
+                                                    ),
+                                                };
+
+                                                let mut buf = Vec::new();
+                                                response.encode_length_delimited(&mut buf)?;
+
+                                                // send data
+                                                match socket.send(&buf) {
+                                                    Ok(_n) => {}
+
+                                                    Err(_e) => {
+                                                        return Err(SdkPluginError::PluginError {
+                                                            description: "Lost connection to Eruption SDK client".to_owned(),
+                                                        }
+                                                            .into());
+                                                    }
+                                                }
+                                            }
+
                                             None => {
                                                 // not sure how this can happen
                                                 error!(
@@ -737,12 +936,38 @@ impl SdkSupportPlugin {
                                 }
                             }
 
-                            if SDK_SUPPORT_ACTIVE.load(Ordering::SeqCst) {
+                            // push pending events to the subscribed client
+                            if let Some(events_rx) = events_rx.as_ref() {
+                                for event in events_rx.try_iter() {
+                                    let response = protocol::Response {
+                                        response_message: Some(
+                                            protocol::response::ResponseMessage::Event(event),
+                                        ),
+                                    };
+
+                                    let mut buf = Vec::new();
+                                    response.encode_length_delimited(&mut buf)?;
+
+                                    if socket.send(&buf).is_err() {
+                                        unsubscribe();
+
+                                        return Err(SdkPluginError::PluginError {
+                                            description: "Lost connection to Eruption SDK client"
+                                                .to_owned(),
+                                        }
+                                        .into());
+                                    }
+                                }
+                            }
+
+                            if SDK_SUPPORT_ACTIVE.load(Ordering::SeqCst) || events_rx.is_some() {
                                 thread::sleep(Duration::from_millis(1));
                             } else {
                                 thread::sleep(Duration::from_millis(15));
                             }
                         }
+
+                        unsubscribe();
                     }
 
                     Err(_e) => {
@@ -773,17 +998,49 @@ impl Plugin for SdkSupportPlugin {
         Self::initialize_socket()?;
         Self::start_control_thread()?;
 
-        // events::register_observer(|event: &events::Event| {
-        //     match event {
-        //         events::Event::KeyDown(_index) => {}
+        events::register_observer(|event: &events::Event| {
+            use protocol::event::EventMessage;
+
+            let event_message = match event {
+                events::Event::KeyDown(index) => EventMessage::Key(protocol::KeyEvent {
+                    key_index: *index as u32,
+                    pressed: true,
+                }),
+
+                events::Event::KeyUp(index) => EventMessage::Key(protocol::KeyEvent {
+                    key_index: *index as u32,
+                    pressed: false,
+                }),
+
+                events::Event::MouseButtonDown(index) => {
+                    EventMessage::MouseButton(protocol::MouseButtonEvent {
+                        button_index: *index as u32,
+                        pressed: true,
+                    })
+                }
 
-        //         events::Event::KeyUp(_index) => {}
+                events::Event::MouseButtonUp(index) => {
+                    EventMessage::MouseButton(protocol::MouseButtonEvent {
+                        button_index: *index as u32,
+                        pressed: false,
+                    })
+                }
+
+                events::Event::MouseWheelEvent(direction) => {
+                    EventMessage::MouseWheel(protocol::MouseWheelEvent {
+                        direction: *direction as u32,
+                    })
+                }
 
-        //         _ => (),
-        //     };
+                _ => return Ok(true),
+            };
 
-        //     Ok(true) // event has been processed
-        // });
+            publish_event(protocol::Event {
+                event_message: Some(event_message),
+            });
+
+            Ok(true) // event has been processed
+        });
 
         Ok(())
     }
@@ -810,3 +1067,64 @@ impl Plugin for SdkSupportPlugin {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{protocol, EventFilter};
+
+    fn event(event_message: protocol::event::EventMessage) -> protocol::Event {
+        protocol::Event {
+            event_message: Some(event_message),
+        }
+    }
+
+    #[test]
+    fn filter_events() {
+        use protocol::event::EventMessage;
+
+        let key = event(EventMessage::Key(protocol::KeyEvent {
+            key_index: 1,
+            pressed: true,
+        }));
+        let button = event(EventMessage::MouseButton(protocol::MouseButtonEvent {
+            button_index: 1,
+            pressed: false,
+        }));
+        let wheel = event(EventMessage::MouseWheel(protocol::MouseWheelEvent {
+            direction: 1,
+        }));
+        let slot = event(EventMessage::SlotChanged(protocol::SlotChangedEvent { slot: 2 }));
+
+        let filter = EventFilter {
+            keyboard_events: true,
+            ..Default::default()
+        };
+
+        assert!(filter.matches(&key));
+        assert!(!filter.matches(&button));
+        assert!(!filter.matches(&wheel));
+        assert!(!filter.matches(&slot));
+
+        let filter = EventFilter {
+            mouse_events: true,
+            state_events: true,
+            ..Default::default()
+        };
+
+        assert!(!filter.matches(&key));
+        assert!(filter.matches(&button));
+        assert!(filter.matches(&wheel));
+        assert!(filter.matches(&slot));
+
+        // events without a payload are never delivered
+        let filter = EventFilter {
+            keyboard_events: true,
+            mouse_events: true,
+            state_events: true,
+        };
+
+        assert!(!filter.matches(&protocol::Event {
+            event_message: None
+        }));
+    }
+}
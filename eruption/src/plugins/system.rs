@@ -28,6 +28,8 @@ use std::sync::atomic::Ordering;
 
 use crate::plugins;
 use crate::plugins::Plugin;
+use crate::scripting::manifest::Permission;
+use crate::scripting::sandbox;
 
 // pub type Result<T> = std::result::Result<T, eyre::Error>;
 
@@ -149,16 +151,18 @@ impl Plugin for SystemPlugin {
             lua_ctx.create_function(|_, ()| Ok(SystemPlugin::get_total_tasks()))?;
         globals.set("get_total_tasks", get_total_tasks)?;
 
-        let system = lua_ctx.create_function(|_, (command, args): (String, Vec<String>)| {
-            Ok(SystemPlugin::system(&command, &args))
-        })?;
-        globals.set("system", system)?;
-
-        let exit = lua_ctx.create_function(|_, (): ()| {
-            SystemPlugin::exit();
-            Ok(())
-        })?;
-        globals.set("exit", exit)?;
+        if sandbox::is_granted(Permission::ProcessExecution) {
+            let system = lua_ctx.create_function(|_, (command, args): (String, Vec<String>)| {
+                Ok(SystemPlugin::system(&command, &args))
+            })?;
+            globals.set("system", system)?;
+
+            let exit = lua_ctx.create_function(|_, (): ()| {
+                SystemPlugin::exit();
+                Ok(())
+            })?;
+            globals.set("exit", exit)?;
+        }
 
         Ok(())
     }
@@ -38,7 +38,7 @@ use std::thread;
 use crate::hwdevices::RGBA;
 use crate::plugins::Plugin;
 use crate::scripting::script::FRAME_GENERATION_COUNTER;
-use crate::{constants, plugins, util, ULEDS_SUPPORT_ACTIVE};
+use crate::{canvas, plugins, util, ULEDS_SUPPORT_ACTIVE};
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
 
@@ -55,7 +55,7 @@ lazy_static! {
         g: 0x00,
         b: 0x00,
         a: 0x00,
-    }; constants::CANVAS_SIZE]));
+    }; canvas::get_canvas_size()]));
 }
 
 lazy_static! {
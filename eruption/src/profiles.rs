@@ -33,6 +33,7 @@ use std::{collections::BTreeMap, ffi::OsStr};
 use std::{fs, io};
 use uuid::Uuid;
 
+use crate::scripting::layers::LayerConfig;
 use crate::scripting::manifest::Manifest;
 use crate::scripting::parameters::{
     ProfileConfiguration, ProfileParameter, ProfileScriptParameters, TypedValue,
@@ -62,6 +63,15 @@ pub enum ProfileError {
 
     #[error("Could not parse a param value")]
     ParseParamError {},
+
+    #[error("Profile inheritance cycle detected at: {path}")]
+    InheritanceCycle { path: String },
+
+    #[error("Could not find parent profile: {parent}")]
+    ParentNotFound { parent: String },
+
+    #[error("Could not load included profile fragment: {path}")]
+    IncludeError { path: String },
 }
 
 fn default_id() -> Uuid {
@@ -88,13 +98,113 @@ pub struct Profile {
     pub name: String,
     pub description: String,
 
+    /// The parent profile, specified either by its UUID or by its `.profile` file
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+
+    /// Profile fragments that will be merged into this profile
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<PathBuf>,
+
     #[serde(default = "default_script_file")]
     pub active_scripts: Vec<PathBuf>,
     #[serde(default)]
     pub config: ProfileConfiguration,
 
+    /// Parameter values inherited from the parent profile and the included fragments.
+    /// Values in `config` take precedence over the inherited ones
+    #[serde(skip)]
+    pub inherited_config: ProfileConfiguration,
+
+    /// The chain of ancestor profiles and included fragments, the most distant ancestor first
+    #[serde(skip)]
+    pub resolved_from: Vec<PathBuf>,
+
+    /// Scripts that render to a single device only, instead of the shared canvas
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceProfile>,
+
+    /// Compositing settings of the layers of the scripts in `active_scripts`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<LayerConfig>,
+
     #[serde(skip)]
     pub manifests: IndexMap<String, Manifest>,
+
+    /// Manifests of the scripts in `devices`, in the same order
+    #[serde(skip)]
+    pub device_manifests: Vec<Vec<Manifest>>,
+}
+
+/// A profile fragment that may be included by other profiles. Full profiles may be
+/// included as well, their `id`, `name` and `description` will be ignored
+#[derive(Deserialize, Debug, Clone, Default)]
+struct ProfileInclude {
+    #[serde(default)]
+    active_scripts: Vec<PathBuf>,
+    #[serde(default)]
+    config: ProfileConfiguration,
+    #[serde(default)]
+    devices: Vec<DeviceProfile>,
+    #[serde(default)]
+    layers: Vec<LayerConfig>,
+}
+
+impl ProfileInclude {
+    fn load(path: &Path) -> Result<Self> {
+        let toml = fs::read_to_string(path).map_err(|e| {
+            error!("Error opening profile fragment. {}", e);
+
+            ProfileError::IncludeError {
+                path: path.display().to_string(),
+            }
+        })?;
+
+        let result = toml::de::from_str::<Self>(&toml).map_err(|e| {
+            error!("Error parsing profile fragment. {}", e);
+
+            ProfileError::IncludeError {
+                path: path.display().to_string(),
+            }
+        })?;
+
+        Ok(result)
+    }
+}
+
+/// Selects a device and assigns a set of scripts, or the profile of a slot to it. All criteria
+/// that are specified have to match, e.g. `usb_vid` and `usb_pid` or the `device` index
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceProfile {
+    /// The index of the device, as reported by the `GetManagedDevices` D-Bus method
+    pub device: Option<usize>,
+
+    pub usb_vid: Option<u16>,
+    pub usb_pid: Option<u16>,
+    pub serial: Option<String>,
+
+    #[serde(default)]
+    pub active_scripts: Vec<PathBuf>,
+
+    /// Run the scripts of the profile that is assigned to this slot, instead of `active_scripts`
+    pub slot: Option<usize>,
+}
+
+impl DeviceProfile {
+    /// Returns `true` if the device with the specified properties is selected
+    pub fn matches(&self, device: usize, usb_vid: u16, usb_pid: u16, serial: Option<&str>) -> bool {
+        self.device.map_or(true, |d| d == device)
+            && self.usb_vid.map_or(true, |v| v == usb_vid)
+            && self.usb_pid.map_or(true, |p| p == usb_pid)
+            && self
+                .serial
+                .as_ref()
+                .map_or(true, |s| Some(s.as_str()) == serial)
+    }
 }
 
 macro_rules! get_default_value {
@@ -114,7 +224,7 @@ macro_rules! get_config_value {
     ($t:ident, $pval:ty, $tval:ty) => {
         paste::item! {
             pub fn [<get_ $t _value>](&self, script_name: &str, parameter_name: &str) -> Option<&$tval> {
-                match self.config.get_parameter(script_name, parameter_name) {
+                match self.get_parameter(script_name, parameter_name) {
                     Some(ProfileParameter { value: $pval(value), .. } ) => Some(value),
                     None => {
                         debug!("Using default value for config param");
@@ -162,13 +272,20 @@ impl Profile {
             id: Uuid::new_v4(),
             name: "Failsafe mode".to_string(),
             description: "Failsafe mode virtual profile".to_string(),
+            extends: None,
+            includes: vec![],
             profile_file: PathBuf::from("failsafe.profile"),
             // force hardcoded directory for failsafe scripts
             active_scripts: vec![PathBuf::from(
                 "/usr/share/eruption/scripts/lib/failsafe.lua",
             )],
             config: ProfileConfiguration::new(),
+            inherited_config: ProfileConfiguration::new(),
+            resolved_from: vec![],
+            devices: vec![],
+            layers: vec![],
             manifests: IndexMap::new(),
+            device_manifests: vec![],
         };
 
         if let Err(err) = profile.load_manifests() {
@@ -206,23 +323,168 @@ impl Profile {
     }
 
     // Just load the profile file itself, no state, no manifests.
+    // The parent profile and included fragments will be resolved though
     pub fn load_file_only(profile_file: &Path) -> Result<Self> {
-        match fs::read_to_string(profile_file) {
-            Ok(toml) => match toml::de::from_str::<Self>(&toml) {
-                Ok(mut result) => {
-                    result.profile_file = profile_file.to_path_buf();
-                    Ok(result)
+        Self::load_file_resolved(profile_file, &mut vec![])
+    }
+
+    fn load_file_resolved(profile_file: &Path, visited: &mut Vec<PathBuf>) -> Result<Self> {
+        let canonical_path = profile_file
+            .canonicalize()
+            .unwrap_or_else(|_| profile_file.to_path_buf());
+
+        if visited.contains(&canonical_path) {
+            error!(
+                "Profile inheritance cycle detected: {}",
+                profile_file.display()
+            );
+
+            return Err(ProfileError::InheritanceCycle {
+                path: profile_file.display().to_string(),
+            }
+            .into());
+        }
+
+        let (mut profile, declares_scripts) = match fs::read_to_string(profile_file) {
+            Ok(toml) => match toml::de::from_str::<toml::Value>(&toml) {
+                Ok(value) => {
+                    // we need to know whether `active_scripts` has been specified explicitly,
+                    // otherwise the scripts of the parent profile will be inherited
+                    let declares_scripts = value.get("active_scripts").is_some();
+
+                    match value.try_into::<Self>() {
+                        Ok(mut result) => {
+                            result.profile_file = profile_file.to_path_buf();
+                            (result, declares_scripts)
+                        }
+                        Err(e) => {
+                            error!("Error parsing profile file. {}", e);
+                            return Err(ProfileError::ParseError {}.into());
+                        }
+                    }
                 }
                 Err(e) => {
                     error!("Error parsing profile file. {}", e);
-                    Err(ProfileError::ParseError {}.into())
+                    return Err(ProfileError::ParseError {}.into());
                 }
             },
             Err(e) => {
                 error!("Error opening profile file. {}", e);
-                Err(ProfileError::OpenError {}.into())
+                return Err(ProfileError::OpenError {}.into());
             }
+        };
+
+        if profile.extends.is_some() || !profile.includes.is_empty() {
+            visited.push(canonical_path);
+            let result = profile.resolve(declares_scripts, visited);
+            visited.pop();
+
+            result?;
         }
+
+        Ok(profile)
+    }
+
+    /// Merge the parent profile and all included fragments into this profile.
+    /// Scripts of the parent come first, values specified in this profile win
+    fn resolve(&mut self, declares_scripts: bool, visited: &mut Vec<PathBuf>) -> Result<()> {
+        let mut active_scripts = vec![];
+        let mut inherited_config = ProfileConfiguration::new();
+        let mut devices = vec![];
+        let mut layers = vec![];
+
+        if let Some(parent) = &self.extends {
+            let parent_file = self.find_parent_file(parent)?;
+            let parent = Self::load_file_resolved(&parent_file, visited)?;
+
+            active_scripts = parent.active_scripts;
+
+            inherited_config = parent.inherited_config;
+            inherited_config.merge(&parent.config);
+
+            devices = parent.devices;
+            layers = parent.layers;
+
+            self.resolved_from = parent.resolved_from;
+            self.resolved_from.push(parent_file);
+        }
+
+        for include in self.includes.iter() {
+            let include_file = resolve_relative_path(&self.profile_file, include);
+            let fragment = ProfileInclude::load(&include_file)?;
+
+            for script in fragment.active_scripts {
+                if !active_scripts.contains(&script) {
+                    active_scripts.push(script);
+                }
+            }
+
+            inherited_config.merge(&fragment.config);
+
+            devices.extend(fragment.devices);
+
+            // layers that are defined later take precedence
+            layers.splice(0..0, fragment.layers);
+
+            self.resolved_from.push(include_file);
+        }
+
+        if declares_scripts {
+            for script in self.active_scripts.drain(..) {
+                if !active_scripts.contains(&script) {
+                    active_scripts.push(script);
+                }
+            }
+        }
+
+        if !active_scripts.is_empty() {
+            self.active_scripts = active_scripts;
+        }
+
+        self.inherited_config = inherited_config;
+
+        devices.append(&mut self.devices);
+        self.devices = devices;
+
+        self.layers.append(&mut layers);
+
+        Ok(())
+    }
+
+    /// Find the `.profile` file of the parent profile `parent`, that may either be
+    /// a UUID or a file name relative to the directory of this profile
+    fn find_parent_file(&self, parent: &str) -> Result<PathBuf> {
+        let result = match Uuid::parse_str(parent) {
+            Ok(uuid) => find_path_by_uuid_only(uuid, &self.profile_file),
+
+            Err(_) => {
+                let path = resolve_relative_path(&self.profile_file, Path::new(parent));
+
+                if path.exists() {
+                    Some(path)
+                } else if crate::CONFIG.lock().is_some() {
+                    get_profile_dirs()
+                        .iter()
+                        .map(|dir| dir.join(parent))
+                        .find(|path| path.exists())
+                } else {
+                    None
+                }
+            }
+        };
+
+        result.ok_or_else(|| {
+            error!(
+                "Could not find parent profile {} of profile {}",
+                parent,
+                self.profile_file.display()
+            );
+
+            ProfileError::ParentNotFound {
+                parent: parent.to_owned(),
+            }
+            .into()
+        })
     }
 
     fn load_manifests(&mut self) -> Result<()> {
@@ -243,17 +505,56 @@ impl Profile {
                 }
             }
         }
+
+        self.device_manifests.clear();
+
+        for device in self.devices.iter() {
+            let mut manifests = vec![];
+
+            // the scripts of a slot are resolved by the daemon, since they may change at runtime
+            if device.slot.is_some() && !device.active_scripts.is_empty() {
+                error!(
+                    "A device section of profile {} specifies both a slot and scripts",
+                    self.profile_file.display()
+                );
+                return Err(ProfileError::OpenError {}.into());
+            }
+
+            for script_file in device.active_scripts.iter() {
+                match Manifest::load(script_file) {
+                    Ok(manifest) => manifests.push(manifest),
+                    Err(err) => {
+                        error!(
+                            "Could not load device script {} for profile {}: {}",
+                            script_file.display(),
+                            self.profile_file.display(),
+                            err
+                        );
+                        return Err(ProfileError::OpenError {}.into());
+                    }
+                }
+            }
+
+            self.device_manifests.push(manifests);
+        }
+
         Ok(())
     }
 
     fn merge_parameters(&mut self) {
-        for manifest in self.manifests.values() {
-            let profile_script_parameters = self.config.get_parameters_mut(&manifest.name);
-            for manifest_parameter in manifest.config.iter() {
-                let profile_parameter =
-                    profile_script_parameters.get_parameter_mut(&manifest_parameter.name);
-                if let Some(profile_parameter) = profile_parameter {
-                    profile_parameter.manifest = Some(manifest_parameter.manifest.to_owned())
+        for manifest in self
+            .manifests
+            .values()
+            .chain(self.device_manifests.iter().flatten())
+        {
+            for config in [&mut self.config, &mut self.inherited_config] {
+                let profile_script_parameters = config.get_parameters_mut(&manifest.name);
+                for manifest_parameter in manifest.config.iter() {
+                    let profile_parameter =
+                        profile_script_parameters.get_parameter_mut(&manifest_parameter.name);
+                    if let Some(profile_parameter) = profile_parameter {
+                        profile_parameter.manifest = Some(manifest_parameter.manifest.to_owned())
+                    }
                 }
             }
         }
@@ -346,9 +647,32 @@ impl Profile {
         Ok(())
     }
 
-    fn get_parameter_default(&self, script_name: &str, parameter_name: &str) -> Option<TypedValue> {
+    /// Returns the compositing settings of the layer of the script `script_file`
+    pub fn get_layer_config(&self, script_file: &Path) -> LayerConfig {
+        self.layers
+            .iter()
+            .find(|layer| layer.matches(script_file))
+            .cloned()
+            .unwrap_or_else(|| LayerConfig::new(script_file))
+    }
+
+    /// Returns the parameter `parameter_name` of the script `script_name`, falling back
+    /// to the value inherited from the parent profile or the included fragments
+    pub fn get_parameter(
+        &self,
+        script_name: &str,
+        parameter_name: &str,
+    ) -> Option<&ProfileParameter> {
         self.config
-            .get_parameter(script_name, parameter_name)?
+            .get_parameter(script_name, parameter_name)
+            .or_else(|| {
+                self.inherited_config
+                    .get_parameter(script_name, parameter_name)
+            })
+    }
+
+    fn get_parameter_default(&self, script_name: &str, parameter_name: &str) -> Option<TypedValue> {
+        self.get_parameter(script_name, parameter_name)?
             .get_default()
     }
 
@@ -383,9 +707,16 @@ impl Default for Profile {
             profile_file,
             name: "Default".into(),
             description: "Auto-generated profile".into(),
+            extends: None,
+            includes: vec![],
             active_scripts: vec![PathBuf::from(constants::DEFAULT_EFFECT_SCRIPT)],
             config: ProfileConfiguration::new(),
+            inherited_config: ProfileConfiguration::new(),
+            resolved_from: vec![],
+            devices: vec![],
+            layers: vec![],
             manifests: IndexMap::new(),
+            device_manifests: vec![],
         }
     }
 }
@@ -520,18 +851,62 @@ pub fn find_path_by_uuid_from(uuid: Uuid, profile_dirs: &Vec<PathBuf>) -> Option
     result
 }
 
+/// Resolve `path` relative to the directory containing `profile_file`
+fn resolve_relative_path(profile_file: &Path, path: &Path) -> PathBuf {
+    match profile_file.parent() {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Find the profile file with the UUID `uuid`, searching the directory of `profile_file`
+/// first. Only the `id` field is parsed, so that we do not need to resolve the parent
+/// profiles of all other profiles
+fn find_path_by_uuid_only(uuid: Uuid, profile_file: &Path) -> Option<PathBuf> {
+    #[derive(Deserialize)]
+    struct ProfileId {
+        id: Option<Uuid>,
+    }
+
+    let mut profile_dirs = vec![];
+
+    if let Some(dir) = profile_file.parent() {
+        profile_dirs.push(dir.to_path_buf());
+    }
+
+    if crate::CONFIG.lock().is_some() {
+        profile_dirs.extend(get_profile_dirs());
+    }
+
+    let profile_files = get_profile_files_from(&profile_dirs).unwrap_or_else(|_| vec![]);
+
+    profile_files.into_iter().find(|path| {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|toml| toml::de::from_str::<ProfileId>(&toml).ok())
+            .and_then(|profile| profile.id)
+            == Some(uuid)
+    })
+}
+
 pub fn get_fail_safe_profile() -> Profile {
     Profile::new_fail_safe()
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{path::PathBuf, str::FromStr};
+    use std::{
+        path::{Path, PathBuf},
+        str::FromStr,
+    };
 
     use indexmap::IndexMap;
     use uuid::Uuid;
 
-    use crate::scripting::parameters::{ManifestValue, ProfileParameter, TypedValue};
+    use crate::scripting::layers::BlendMode;
+    use crate::scripting::parameters::{
+        ManifestValue, ProfileConfiguration, ProfileParameter, TypedValue,
+    };
 
     use super::Profile;
 
@@ -775,6 +1150,143 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn load_profile_with_device_scripts() -> super::Result<()> {
+        let assets_path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("../support/tests/assets");
+
+        let profile_path = assets_path.join("device_scripts.profile").canonicalize()?;
+
+        let config = config::Config::builder()
+            .set_override(
+                "global.script_dirs",
+                vec![assets_path.to_string_lossy().to_string()],
+            )?
+            .build()
+            .unwrap();
+
+        *crate::CONFIG.lock() = Some(config);
+
+        let profile = super::Profile::load_fully(&profile_path)?;
+
+        assert_eq!(profile.devices.len(), 3);
+        assert_eq!(profile.device_manifests.len(), 3);
+        assert_eq!(profile.device_manifests[0].len(), 1);
+
+        // the scripts of a slot are resolved by the daemon
+        assert_eq!(profile.devices[2].slot, Some(1));
+        assert!(profile.device_manifests[2].is_empty());
+
+        assert!(profile.devices[0].matches(3, 0x1e7d, 0x3098, None));
+        assert!(!profile.devices[0].matches(0, 0x1e7d, 0x3099, None));
+
+        assert!(profile.devices[1].matches(1, 0x1e7d, 0x3098, Some("ABC123")));
+        assert!(!profile.devices[1].matches(1, 0x1e7d, 0x3098, None));
+        assert!(!profile.devices[1].matches(0, 0x1e7d, 0x3098, Some("ABC123")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_profile_with_layers() -> super::Result<()> {
+        let assets_path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("../support/tests/assets");
+
+        let profile_path = assets_path.join("layers.profile").canonicalize()?;
+        let profile = super::Profile::load_file_and_state_only(&profile_path)?;
+
+        let script_file = assets_path.join("manifest_test.lua");
+        let layer = profile.get_layer_config(&script_file);
+
+        assert_eq!(layer.blend_mode, BlendMode::Screen);
+        assert_eq!(layer.opacity, 0.75);
+        assert_eq!(layer.key_mask, vec![1, 2, 3]);
+
+        // scripts without a layer use the defaults
+        let layer = profile.get_layer_config(Path::new("organic.lua"));
+
+        assert_eq!(layer.blend_mode, BlendMode::Normal);
+        assert_eq!(layer.opacity, 1.0);
+        assert!(layer.key_mask.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_profile_with_parent_and_includes() -> super::Result<()> {
+        let assets_path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("../support/tests/assets");
+
+        let profile_path = assets_path.join("inherit_child.profile");
+        let profile = super::Profile::load_file_only(&profile_path)?;
+
+        assert_eq!(profile.name, "Inherit Child");
+        assert_eq!(
+            profile.active_scripts,
+            vec![
+                PathBuf::from("organic.lua"),
+                PathBuf::from("shockwave.lua"),
+                PathBuf::from("macros.lua"),
+                PathBuf::from("water.lua"),
+                PathBuf::from("impact.lua"),
+            ]
+        );
+
+        assert_eq!(
+            profile.resolved_from,
+            vec![
+                assets_path.join("test3.profile"),
+                assets_path.join("inherit.include"),
+            ]
+        );
+
+        // values of the profile itself win over inherited values
+        assert_eq!(
+            profile.get_bool_value("Shockwave", "mouse_events"),
+            Some(&false)
+        );
+
+        // included fragments win over the parent profile
+        assert_eq!(profile.get_float_value("Raindrops", "opacity"), Some(&0.5));
+
+        assert_eq!(
+            profile.get_color_value("Shockwave", "color_step_shockwave"),
+            Some(&0x05010000)
+        );
+
+        assert_eq!(
+            profile.get_layer_config(Path::new("water.lua")).blend_mode,
+            BlendMode::Add
+        );
+
+        // profiles that do not declare any scripts inherit the scripts of their parent
+        let profile_path = assets_path.join("inherit_grandchild.profile");
+        let grandchild = super::Profile::load_file_only(&profile_path)?;
+
+        assert_eq!(grandchild.active_scripts, profile.active_scripts);
+        assert_eq!(grandchild.resolved_from.len(), 3);
+        assert_eq!(
+            grandchild.get_bool_value("Shockwave", "mouse_events"),
+            Some(&false)
+        );
+        assert!(grandchild.config.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_profile_inheritance_cycle() {
+        let assets_path = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("../support/tests/assets");
+
+        let result = super::Profile::load_file_only(&assets_path.join("cycle_a.profile"));
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<super::ProfileError>(),
+            Some(super::ProfileError::InheritanceCycle { .. })
+        ));
+    }
+
     #[test]
     pub fn verify_deserialization_and_serialization() -> super::Result<()> {
         let lit_profile = Profile {
@@ -782,6 +1294,8 @@ mod tests {
             profile_file: PathBuf::from("test.profile"),
             name: "Test profile".to_string(),
             description: "Testing serialization".to_string(),
+            extends: None,
+            includes: vec![],
             active_scripts: vec![
                 PathBuf::from("xyz"),
                 PathBuf::from("def"),
@@ -824,7 +1338,12 @@ mod tests {
                 ),
             ]
             .into(),
+            inherited_config: ProfileConfiguration::new(),
+            resolved_from: vec![],
+            devices: vec![],
+            layers: vec![],
             manifests: IndexMap::new(),
+            device_manifests: vec![],
         };
 
         let lit_toml = r#"
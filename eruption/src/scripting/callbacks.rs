@@ -36,7 +36,7 @@ use std::vec::Vec;
 use std::{cell::RefCell, thread};
 
 use crate::{
-    constants,
+    canvas, constants,
     hwdevices::RGBA,
     plugin_manager,
     plugins::macros,
@@ -46,6 +46,8 @@ use crate::{
         LOCAL_LED_MAP_MODIFIED,
     },
     scripting::callbacks,
+    scripting::manifest::Permission,
+    scripting::sandbox,
 };
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
@@ -203,17 +205,17 @@ pub(crate) fn get_support_script_files() -> Vec<String> {
 
 /// Returns the number of "pixels" on the canvas
 pub(crate) fn get_canvas_size() -> usize {
-    constants::CANVAS_SIZE
+    canvas::get_canvas_size()
 }
 
 /// Returns the height of the canvas
 pub(crate) fn get_canvas_height() -> usize {
-    constants::CANVAS_HEIGHT
+    canvas::get_canvas_height()
 }
 
 /// Returns the width of the canvas
 pub(crate) fn get_canvas_width() -> usize {
-    constants::CANVAS_WIDTH
+    canvas::get_canvas_width()
 }
 
 /// Inject a key on the eruption virtual keyboard.
@@ -691,7 +693,7 @@ pub(crate) fn get_color_map() -> Vec<u32> {
         })
         .collect::<Vec<u32>>();
 
-    assert!(result.len() == constants::CANVAS_SIZE);
+    assert!(result.len() == canvas::get_canvas_size());
 
     result
 }
@@ -710,12 +712,15 @@ pub(crate) fn submit_color_map(map: &[u32]) -> Result<()> {
     //     )
     // );
 
-    let mut led_map = [RGBA {
-        r: 0,
-        g: 0,
-        b: 0,
-        a: 0,
-    }; constants::CANVAS_SIZE];
+    let mut led_map = vec![
+        RGBA {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        };
+        canvas::get_canvas_size()
+    ];
 
     let mut i = 0;
     loop {
@@ -881,32 +886,35 @@ pub fn register_support_funcs(lua_ctx: &Lua) -> mlua::Result<()> {
     })?;
     globals.set("range", range)?;
 
-    // keyboard state and macros
-    let inject_key = lua_ctx.create_function(|_, (ev_key, down): (u32, bool)| {
-        callbacks::inject_key(ev_key, down);
-        Ok(())
-    })?;
-    globals.set("inject_key", inject_key)?;
-
-    let inject_key_with_delay =
-        lua_ctx.create_function(|_, (ev_key, down, millis): (u32, bool, u64)| {
-            callbacks::inject_key_with_delay(ev_key, down, millis);
+    if sandbox::is_granted(Permission::InputInjection) {
+        // keyboard state and macros
+        let inject_key = lua_ctx.create_function(|_, (ev_key, down): (u32, bool)| {
+            callbacks::inject_key(ev_key, down);
             Ok(())
         })?;
-    globals.set("inject_key_with_delay", inject_key_with_delay)?;
-
-    // mouse state and macros
-    let inject_mouse_button = lua_ctx.create_function(|_, (button_index, down): (u32, bool)| {
-        callbacks::inject_mouse_button(button_index, down);
-        Ok(())
-    })?;
-    globals.set("inject_mouse_button", inject_mouse_button)?;
-
-    let inject_mouse_wheel = lua_ctx.create_function(|_, direction: u32| {
-        callbacks::inject_mouse_wheel(direction);
-        Ok(())
-    })?;
-    globals.set("inject_mouse_wheel", inject_mouse_wheel)?;
+        globals.set("inject_key", inject_key)?;
+
+        let inject_key_with_delay =
+            lua_ctx.create_function(|_, (ev_key, down, millis): (u32, bool, u64)| {
+                callbacks::inject_key_with_delay(ev_key, down, millis);
+                Ok(())
+            })?;
+        globals.set("inject_key_with_delay", inject_key_with_delay)?;
+
+        // mouse state and macros
+        let inject_mouse_button =
+            lua_ctx.create_function(|_, (button_index, down): (u32, bool)| {
+                callbacks::inject_mouse_button(button_index, down);
+                Ok(())
+            })?;
+        globals.set("inject_mouse_button", inject_mouse_button)?;
+
+        let inject_mouse_wheel = lua_ctx.create_function(|_, direction: u32| {
+            callbacks::inject_mouse_wheel(direction);
+            Ok(())
+        })?;
+        globals.set("inject_mouse_wheel", inject_mouse_wheel)?;
+    }
 
     // color handling
     let color_to_rgb = lua_ctx.create_function(|_, c: u32| Ok(callbacks::color_to_rgb(c)))?;
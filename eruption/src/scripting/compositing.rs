@@ -0,0 +1,375 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use crate::hwdevices::RGBA;
+
+pub use super::layers::{BlendMode, LayerConfig};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+/// Prefix of the parameters that control the layer of a script, e.g. `layer.opacity`
+pub const LAYER_PARAMETER_PREFIX: &str = "layer.";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CompositingError {
+    #[error("Unknown layer parameter: {name}")]
+    UnknownLayerParameter { name: String },
+
+    #[error("Invalid value for layer parameter {name}: {value}")]
+    InvalidLayerParameter { name: String, value: String },
+}
+
+lazy_static! {
+    /// The layers of the currently running Lua VMs, indexed by the index of the VM
+    pub static ref LAYERS: Arc<RwLock<Vec<Layer>>> = Arc::new(RwLock::new(vec![]));
+}
+
+thread_local! {
+    /// Index into LAYERS, of the layer that this thread renders to
+    pub static LAYER_INDEX: RefCell<Option<usize>> = RefCell::new(None);
+}
+
+impl BlendMode {
+    /// Combine the background color component `a` with the foreground color component `b`,
+    /// both in the range 0.0..=255.0
+    pub fn mix(&self, a: f32, b: f32) -> f32 {
+        if *self == BlendMode::Normal {
+            return b;
+        }
+
+        let (a, b) = (a / 255.0, b / 255.0);
+
+        let result = match self {
+            BlendMode::Normal => b,
+            BlendMode::Add => (a + b).min(1.0),
+            BlendMode::Multiply => a * b,
+            BlendMode::Screen => 1.0 - (1.0 - a) * (1.0 - b),
+            BlendMode::Overlay => {
+                if a < 0.5 {
+                    2.0 * a * b
+                } else {
+                    1.0 - 2.0 * (1.0 - a) * (1.0 - b)
+                }
+            }
+            BlendMode::Difference => (a - b).abs(),
+        };
+
+        result * 255.0
+    }
+}
+
+impl LayerConfig {
+    /// Apply a `layer.*` parameter, e.g. as received via D-Bus
+    pub fn set_parameter(&mut self, name: &str, value: &str) -> Result<()> {
+        let invalid = || CompositingError::InvalidLayerParameter {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+
+        match name.strip_prefix(LAYER_PARAMETER_PREFIX) {
+            Some("blend_mode") => self.blend_mode = BlendMode::from_str(value)?,
+
+            Some("opacity") => {
+                let opacity = f64::from_str(value).map_err(|_| invalid())?;
+
+                if !(0.0..=1.0).contains(&opacity) {
+                    return Err(invalid().into());
+                }
+
+                self.opacity = opacity;
+            }
+
+            Some("key_mask") => {
+                self.key_mask = value
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|s| !s.is_empty())
+                    .map(usize::from_str)
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|_| invalid())?;
+            }
+
+            _ => {
+                return Err(CompositingError::UnknownLayerParameter {
+                    name: name.to_string(),
+                }
+                .into())
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The runtime representation of a layer
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub script_file: PathBuf,
+    pub blend_mode: BlendMode,
+    pub opacity: f32,
+
+    /// `None` if the layer is shown on all LEDs
+    pub key_mask: Option<Vec<bool>>,
+}
+
+impl Layer {
+    pub fn new(script_file: &Path, config: &LayerConfig, canvas_size: usize) -> Self {
+        let key_mask = if config.key_mask.is_empty() {
+            None
+        } else {
+            let mut mask = vec![false; canvas_size];
+
+            for index in config.key_mask.iter() {
+                if let Some(m) = mask.get_mut(*index) {
+                    *m = true;
+                }
+            }
+
+            Some(mask)
+        };
+
+        Self {
+            script_file: script_file.to_path_buf(),
+            blend_mode: config.blend_mode,
+            opacity: config.opacity.clamp(0.0, 1.0) as f32,
+            key_mask,
+        }
+    }
+
+    /// Returns `true` if the layer is shown on the LED with index `index`
+    pub fn is_visible(&self, index: usize) -> bool {
+        self.key_mask
+            .as_ref()
+            .map_or(true, |mask| mask.get(index).copied().unwrap_or(false))
+    }
+
+    /// Blend the `foreground` layer onto `background`, scaled by `brightness` in percent
+    pub fn blend(&self, background: &mut [RGBA], foreground: &[RGBA], brightness: f32) {
+        for (idx, (bg, fg)) in background.iter_mut().zip(foreground.iter()).enumerate() {
+            if !self.is_visible(idx) {
+                continue;
+            }
+
+            let alpha = fg.a as f32 * self.opacity;
+
+            let component = |bg: u8, fg: u8| -> u8 {
+                let mixed = self.blend_mode.mix(bg as f32, fg as f32);
+
+                (((alpha * mixed + (255.0 - alpha) * bg as f32).floor() * brightness / 100.0)
+                    as u32
+                    >> 8) as u8
+            };
+
+            *bg = RGBA {
+                r: component(bg.r, fg.r),
+                g: component(bg.g, fg.g),
+                b: component(bg.b, fg.b),
+                a: alpha as u8,
+            };
+        }
+    }
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Self {
+            script_file: PathBuf::new(),
+            blend_mode: BlendMode::Normal,
+            opacity: 1.0,
+            key_mask: None,
+        }
+    }
+}
+
+/// Returns the index of the layer of the current thread, if any
+pub fn get_layer_index() -> Option<usize> {
+    LAYER_INDEX.with(|index| *index.borrow())
+}
+
+/// Replace the layers of the running Lua VMs
+pub fn init_layers(layers: Vec<Layer>) {
+    *LAYERS.write() = layers;
+}
+
+/// Update a `layer.*` parameter of all layers that belong to the script `script_file`
+pub fn set_layer_parameter(
+    script_file: &Path,
+    name: &str,
+    value: &str,
+    canvas_size: usize,
+    configs: &mut Vec<LayerConfig>,
+) -> Result<()> {
+    // update the layer configuration of the profile first
+    let config = match configs.iter_mut().find(|c| c.matches(script_file)) {
+        Some(config) => config,
+
+        None => {
+            configs.push(LayerConfig::new(
+                script_file
+                    .file_name()
+                    .map(Path::new)
+                    .unwrap_or(script_file),
+            ));
+            configs.last_mut().unwrap()
+        }
+    };
+
+    config.set_parameter(name, value)?;
+
+    // then update the running layers
+    for layer in LAYERS
+        .write()
+        .iter_mut()
+        .filter(|l| config.matches(&l.script_file))
+    {
+        *layer = Layer::new(&layer.script_file.clone(), config, canvas_size);
+    }
+
+    Ok(())
+}
+
+/// Alpha blend an overlay, e.g. the LED map of a plugin, onto `led_map`
+pub fn blend_overlay(led_map: &mut [RGBA], overlay: &[RGBA]) {
+    for (bg, fg) in led_map.iter_mut().zip(overlay.iter()) {
+        let component = |bg: u8, fg_c: u8| -> u8 {
+            ((fg.a as u32 * fg_c as u32 + (255 - fg.a as u32) * bg as u32) / 255) as u8
+        };
+
+        *bg = RGBA {
+            r: component(bg.r, fg.r),
+            g: component(bg.g, fg.g),
+            b: component(bg.b, fg.b),
+            a: bg.a.max(fg.a),
+        };
+    }
+}
+
+/// Scale all colors of `led_map` by `brightness` in percent. Applied exactly once per frame,
+/// after all layers and overlays have been blended
+pub fn apply_brightness(led_map: &mut [RGBA], brightness: f32) {
+    let scale = |c: u8| -> u8 { (c as f32 * brightness / 100.0).clamp(0.0, 255.0) as u8 };
+
+    for color in led_map.iter_mut() {
+        color.r = scale(color.r);
+        color.g = scale(color.g);
+        color.b = scale(color.b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_brightness, blend_overlay, BlendMode, Layer, LayerConfig};
+    use crate::hwdevices::RGBA;
+    use std::path::Path;
+
+    fn color(r: u8, g: u8, b: u8, a: u8) -> RGBA {
+        RGBA { r, g, b, a }
+    }
+
+    fn components(c: &RGBA) -> (u8, u8, u8, u8) {
+        (c.r, c.g, c.b, c.a)
+    }
+
+    #[test]
+    fn blend_modes() {
+        assert_eq!(BlendMode::Normal.mix(100.0, 200.0), 200.0);
+        assert_eq!(BlendMode::Add.mix(200.0, 200.0), 255.0);
+        assert_eq!(BlendMode::Multiply.mix(255.0, 51.0).round(), 51.0);
+        assert_eq!(BlendMode::Screen.mix(0.0, 51.0).round(), 51.0);
+        assert_eq!(BlendMode::Difference.mix(200.0, 50.0).round(), 150.0);
+        assert_eq!(BlendMode::Overlay.mix(0.0, 255.0), 0.0);
+    }
+
+    #[test]
+    fn blend_layers() {
+        let mut config = LayerConfig::new(Path::new("afterglow.lua"));
+        config.set_parameter("layer.blend_mode", "add").unwrap();
+        config.set_parameter("layer.opacity", "0.5").unwrap();
+        config.set_parameter("layer.key_mask", "0, 2").unwrap();
+
+        assert_eq!(config.key_mask, vec![0, 2]);
+        assert!(config.set_parameter("layer.opacity", "1.5").is_err());
+        assert!(config.set_parameter("layer.blend_mode", "dodge").is_err());
+
+        let layer = Layer::new(
+            Path::new("/usr/share/eruption/scripts/afterglow.lua"),
+            &config,
+            3,
+        );
+
+        assert!(config.matches(&layer.script_file));
+
+        let mut background = vec![color(100, 0, 0, 255); 3];
+        let foreground = vec![color(100, 255, 0, 255); 3];
+
+        layer.blend(&mut background, &foreground, 100.0);
+
+        // masked out LEDs are left untouched
+        assert_eq!(components(&background[1]), (100, 0, 0, 255));
+
+        assert_eq!(components(&background[0]), (149, 127, 0, 127));
+        assert_eq!(components(&background[2]), (149, 127, 0, 127));
+    }
+
+    #[test]
+    fn normal_blend_matches_alpha_blend() {
+        let layer = Layer::new(
+            Path::new("organic.lua"),
+            &LayerConfig::new(Path::new("organic.lua")),
+            1,
+        );
+
+        let mut background = vec![color(10, 20, 30, 255)];
+        let foreground = vec![color(200, 100, 50, 128)];
+
+        layer.blend(&mut background, &foreground, 100.0);
+
+        // (128 * fg + 127 * bg) >> 8
+        assert_eq!(components(&background[0]), (104, 59, 39, 128));
+    }
+
+    #[test]
+    fn overlays_do_not_compound_brightness() {
+        let mut led_map = vec![color(200, 100, 0, 255); 2];
+
+        // the first LED is covered by two opaque overlays, the second LED is not covered
+        let overlay = vec![color(0, 0, 255, 255), color(0, 0, 0, 0)];
+
+        blend_overlay(&mut led_map, &overlay);
+        blend_overlay(&mut led_map, &overlay);
+
+        // fully transparent and fully opaque overlays are exact
+        assert_eq!(components(&led_map[0]), (0, 0, 255, 255));
+        assert_eq!(components(&led_map[1]), (200, 100, 0, 255));
+
+        apply_brightness(&mut led_map, 50.0);
+
+        assert_eq!(components(&led_map[0]), (0, 0, 127, 255));
+        assert_eq!(components(&led_map[1]), (100, 50, 0, 255));
+    }
+}
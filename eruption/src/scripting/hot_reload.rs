@@ -0,0 +1,264 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::collections::HashSet;
+use std::ffi::c_void;
+
+use mlua::prelude::*;
+
+/// Tables nested deeper than this are not carried over to the reloaded script
+const MAX_DEPTH: usize = 16;
+
+/// A snapshot of a Lua value, that outlives the Lua VM it has been taken from.
+///
+/// Functions, threads and userdata can not be transferred to another VM and are skipped.
+/// Every table is captured only once, further references to it (including cycles) are skipped
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateValue {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(Vec<u8>),
+    Table(Vec<(StateValue, StateValue)>),
+}
+
+impl StateValue {
+    /// Take a snapshot of the Lua value `value`, returns `None` if it can not be transferred.
+    /// `visited` holds the tables that have already been captured
+    pub fn from_lua_value(
+        value: &LuaValue,
+        depth: usize,
+        visited: &mut HashSet<*const c_void>,
+    ) -> Option<Self> {
+        match value {
+            LuaValue::Nil => Some(Self::Nil),
+            LuaValue::Boolean(b) => Some(Self::Boolean(*b)),
+            LuaValue::Integer(i) => Some(Self::Integer(*i)),
+            LuaValue::Number(n) => Some(Self::Number(*n)),
+            LuaValue::String(s) => Some(Self::String(s.as_bytes().to_vec())),
+
+            LuaValue::Table(table) if depth < MAX_DEPTH => {
+                if !visited.insert(value.to_pointer()) {
+                    return None;
+                }
+
+                let mut result = Vec::new();
+
+                for pair in table.clone().pairs::<LuaValue, LuaValue>() {
+                    let (key, value) = pair.ok()?;
+
+                    if let (Some(key), Some(value)) = (
+                        Self::from_lua_value(&key, depth + 1, visited),
+                        Self::from_lua_value(&value, depth + 1, visited),
+                    ) {
+                        result.push((key, value));
+                    }
+                }
+
+                Some(Self::Table(result))
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Re-create the value in the Lua VM `lua_ctx`
+    pub fn to_lua_value<'lua>(&self, lua_ctx: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match self {
+            Self::Nil => Ok(LuaValue::Nil),
+            Self::Boolean(b) => Ok(LuaValue::Boolean(*b)),
+            Self::Integer(i) => Ok(LuaValue::Integer(*i)),
+            Self::Number(n) => Ok(LuaValue::Number(*n)),
+            Self::String(s) => Ok(LuaValue::String(lua_ctx.create_string(s)?)),
+
+            Self::Table(pairs) => {
+                let table = lua_ctx.create_table()?;
+
+                for (key, value) in pairs {
+                    let key = key.to_lua_value(lua_ctx)?;
+
+                    // `nil` and `NaN` are not valid table keys
+                    if matches!(key, LuaValue::Nil)
+                        || matches!(key, LuaValue::Number(n) if n.is_nan())
+                    {
+                        continue;
+                    }
+
+                    table.raw_set(key, value.to_lua_value(lua_ctx)?)?;
+                }
+
+                Ok(LuaValue::Table(table))
+            }
+        }
+    }
+}
+
+/// The global variables of a script, carried over to the next instance of the script on a hot reload
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScriptState {
+    pub globals: Vec<(String, StateValue)>,
+}
+
+impl ScriptState {
+    /// Take a snapshot of all global variables of `lua_ctx`, except for the ones in `builtins`.
+    /// Globals that can not be transferred (e.g. functions) are skipped
+    pub fn capture(lua_ctx: &Lua, builtins: &HashSet<String>) -> LuaResult<Self> {
+        let mut globals = Vec::new();
+        let mut visited = HashSet::new();
+
+        for pair in lua_ctx.globals().pairs::<LuaValue, LuaValue>() {
+            let (key, value) = pair?;
+
+            if let LuaValue::String(key) = key {
+                let key = key.to_str()?;
+
+                if builtins.contains(key) {
+                    continue;
+                }
+
+                if let Some(value) = StateValue::from_lua_value(&value, 0, &mut visited) {
+                    globals.push((key.to_string(), value));
+                }
+            }
+        }
+
+        Ok(Self { globals })
+    }
+
+    /// Re-create the snapshot as a table in the Lua VM `lua_ctx`
+    pub fn to_lua_table<'lua>(&self, lua_ctx: &'lua Lua) -> LuaResult<LuaTable<'lua>> {
+        let table = lua_ctx.create_table()?;
+
+        for (key, value) in self.globals.iter() {
+            table.raw_set(key.as_str(), value.to_lua_value(lua_ctx)?)?;
+        }
+
+        Ok(table)
+    }
+}
+
+/// Returns the names of all global variables that are currently defined in `lua_ctx`
+pub fn global_names(lua_ctx: &Lua) -> LuaResult<HashSet<String>> {
+    let mut result = HashSet::new();
+
+    for pair in lua_ctx.globals().pairs::<LuaValue, LuaValue>() {
+        let (key, _value) = pair?;
+
+        if let LuaValue::String(key) = key {
+            result.insert(key.to_str()?.to_string());
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{global_names, ScriptState, StateValue};
+    use mlua::prelude::*;
+
+    #[test]
+    fn carry_over_globals() {
+        let old = Lua::new();
+        let builtins = global_names(&old).unwrap();
+
+        old.load(
+            r#"
+            counter = 42
+            ratio = 0.5
+            name = "creature"
+            creature = { x = 1, y = 2, trail = { 3, 4, 5 } }
+            function on_tick(delta) end
+            "#,
+        )
+        .exec()
+        .unwrap();
+
+        let state = ScriptState::capture(&old, &builtins).unwrap();
+
+        // functions and the builtins of the VM are skipped
+        assert_eq!(state.globals.len(), 4);
+        assert!(state
+            .globals
+            .iter()
+            .any(|(k, v)| k == "counter" && *v == StateValue::Integer(42)));
+
+        drop(old);
+
+        let new = Lua::new();
+        new.globals()
+            .set("old_state", state.to_lua_table(&new).unwrap())
+            .unwrap();
+
+        let result: (i64, f64, String, i64, i64) = new
+            .load(
+                r#"
+                return old_state.counter, old_state.ratio, old_state.name,
+                       old_state.creature.y, old_state.creature.trail[3]
+                "#,
+            )
+            .eval()
+            .unwrap();
+
+        assert_eq!(result, (42, 0.5, "creature".to_string(), 2, 5));
+        assert!(new
+            .load("return old_state.on_tick == nil")
+            .eval::<bool>()
+            .unwrap());
+    }
+
+    #[test]
+    fn cyclic_tables() {
+        let lua = Lua::new();
+        let builtins = global_names(&lua).unwrap();
+
+        lua.load("cycle = {} cycle.self = cycle").exec().unwrap();
+
+        // must not recurse endlessly
+        let state = ScriptState::capture(&lua, &builtins).unwrap();
+
+        assert_eq!(state.globals.len(), 1);
+    }
+
+    #[test]
+    fn many_references_to_the_same_table() {
+        let lua = Lua::new();
+        let builtins = global_names(&lua).unwrap();
+
+        // without tracking of the visited tables, this would take 8^16 steps
+        lua.load(
+            r#"
+            shared = {}
+            for i = 1, 8 do shared[i] = shared end
+            alias = shared
+            "#,
+        )
+        .exec()
+        .unwrap();
+
+        let state = ScriptState::capture(&lua, &builtins).unwrap();
+
+        // the table is captured once, all further references to it are skipped
+        assert_eq!(state.globals.len(), 1);
+        assert_eq!(state.globals[0].1, StateValue::Table(vec![]));
+    }
+}
@@ -0,0 +1,107 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+pub type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayerError {
+    #[error("Unknown blend mode: {mode}")]
+    UnknownBlendMode { mode: String },
+}
+
+/// The blend mode of a layer, specifies how the colors of a layer are
+/// combined with the colors of the layers below it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Add,
+    Multiply,
+    Screen,
+    Overlay,
+    Difference,
+}
+
+impl FromStr for BlendMode {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "normal" => Ok(BlendMode::Normal),
+            "add" => Ok(BlendMode::Add),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "overlay" => Ok(BlendMode::Overlay),
+            "difference" => Ok(BlendMode::Difference),
+
+            _ => Err(LayerError::UnknownBlendMode {
+                mode: s.to_string(),
+            }
+            .into()),
+        }
+    }
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+/// Compositing settings of the layer of a script, as specified in a `.profile` file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerConfig {
+    /// The script that renders to this layer
+    pub script: PathBuf,
+
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+
+    /// Opacity of the layer, in the range 0.0..=1.0
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+
+    /// Zero based indices of the LEDs of the canvas that the layer is shown on.
+    /// An empty mask shows the layer on all LEDs
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub key_mask: Vec<usize>,
+}
+
+impl LayerConfig {
+    pub fn new(script: &Path) -> Self {
+        Self {
+            script: script.to_path_buf(),
+            blend_mode: BlendMode::default(),
+            opacity: default_opacity(),
+            key_mask: vec![],
+        }
+    }
+
+    /// Returns `true` if the layer belongs to the script `script_file`
+    pub fn matches(&self, script_file: &Path) -> bool {
+        script_file.ends_with(&self.script)
+    }
+}
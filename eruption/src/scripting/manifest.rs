@@ -65,6 +65,8 @@ pub struct Manifest {
     pub min_supported_version: String,
     pub tags: Option<Vec<ScriptTag>>,
     #[serde(default)]
+    pub permissions: Vec<Permission>,
+    #[serde(default)]
     pub config: ManifestConfiguration,
 }
 
@@ -102,12 +104,23 @@ impl Manifest {
     }
 
     pub fn get_merged_parameters(&self, profile: &Profile) -> Vec<PlainParameter> {
+        // values of the profile itself take precedence over values inherited from
+        // the parent profile or from included fragments
         let profile_script_parameters = profile.config.get_parameters(&self.name);
-        if let Some(profile_script_parameters) = profile_script_parameters {
+        let inherited_script_parameters = profile.inherited_config.get_parameters(&self.name);
+
+        if profile_script_parameters.is_some() || inherited_script_parameters.is_some() {
             self.config
                 .iter()
                 .map(|manifest_parameter| {
-                    match profile_script_parameters.get_parameter(&manifest_parameter.name) {
+                    let profile_parameter = profile_script_parameters
+                        .and_then(|p| p.get_parameter(&manifest_parameter.name))
+                        .or_else(|| {
+                            inherited_script_parameters
+                                .and_then(|p| p.get_parameter(&manifest_parameter.name))
+                        });
+
+                    match profile_parameter {
                         Some(profile_parameter) => profile_parameter.to_plain_parameter(),
                         None => {
                             debug!(
@@ -364,3 +377,33 @@ impl ScriptTag {
         }
     }
 }
+
+/// Capabilities that a script has to request in its manifest. The Lua functions
+/// and libraries that require a permission are only available to a script if the
+/// respective permission has been granted
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Permission {
+    /// Script may load native Lua modules, like e.g. `socket`. Native modules are not
+    /// sandboxed, so this permission allows a script to run arbitrary code in the daemon
+    Network,
+
+    /// Script may execute other processes or terminate the daemon (`system`, `exit`)
+    ProcessExecution,
+
+    /// Script may inject keystrokes and mouse events (`inject_key`, `inject_mouse_button`, ...)
+    InputInjection,
+
+    /// Script may store data that persists across restarts of the daemon (`store_int`, `load_int`, ...)
+    Persistence,
+}
+
+impl Permission {
+    pub fn get_description(&self) -> String {
+        match *self {
+            Permission::Network => "Access the network and load native Lua modules".into(),
+            Permission::ProcessExecution => "Execute programs and terminate the daemon".into(),
+            Permission::InputInjection => "Inject keystrokes and mouse events".into(),
+            Permission::Persistence => "Store data that persists across restarts".into(),
+        }
+    }
+}
@@ -20,8 +20,13 @@
 */
 
 pub mod callbacks;
+pub mod compositing;
 pub mod constants;
+pub mod hot_reload;
+pub mod layers;
 pub mod manifest;
 pub mod parameters;
 pub mod parameters_util;
+pub mod profiler;
+pub mod sandbox;
 pub mod script;
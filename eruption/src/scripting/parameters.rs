@@ -233,6 +233,15 @@ impl ProfileConfiguration {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Merge all parameters of `other` into `self`, values of `other` take precedence
+    pub fn merge(&mut self, other: &ProfileConfiguration) {
+        for (script_name, parameters) in other.0.iter() {
+            for parameter in parameters.iter() {
+                self.set_parameter(script_name, parameter.to_owned());
+            }
+        }
+    }
 }
 
 #[allow(dead_code)]
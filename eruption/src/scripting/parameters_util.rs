@@ -26,8 +26,10 @@ use std::str::FromStr;
 use std::sync::atomic::Ordering;
 
 use crate::{
+    canvas,
     profiles::Profile,
     script,
+    scripting::compositing,
     scripting::manifest::Manifest,
     scripting::parameters::{
         ManifestParameter, ManifestValue, PlainParameter, ProfileParameter, ToPlainParameter,
@@ -45,6 +47,9 @@ pub enum ParametersUtilError {
 
     #[error("Script manifest does not reference the parameter")]
     NoSuchParameter {},
+
+    #[error("Layer parameters may only be set on the active profile")]
+    ProfileNotActive {},
 }
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
@@ -61,6 +66,22 @@ pub fn apply_parameters(
     let profile_path = PathBuf::from(&profile_file);
     let script_path = PathBuf::from(&script_file);
 
+    // parameters that control the compositing layer of the script are not passed to the script
+    let (layer_parameters, parameter_values): (Vec<_>, Vec<_>) = parameter_values
+        .iter()
+        .cloned()
+        .partition(|p| p.name.starts_with(compositing::LAYER_PARAMETER_PREFIX));
+
+    if !layer_parameters.is_empty() {
+        apply_layer_parameters(&profile_path, &script_path, &layer_parameters)?;
+
+        if parameter_values.is_empty() {
+            return Ok(());
+        }
+    }
+
+    let parameter_values = &parameter_values[..];
+
     // If the specified profile_file is for the active profile, update that directly.
     {
         let active_profile = &mut *crate::ACTIVE_PROFILE.lock();
@@ -100,6 +121,39 @@ pub fn apply_parameters(
     Ok(())
 }
 
+/// Apply the `layer.*` parameters to the layer of the script `script_path`. Since
+/// layers are defined in the `.profile` file, changes will not be persisted
+fn apply_layer_parameters(
+    profile_path: &Path,
+    script_path: &Path,
+    layer_parameters: &[UntypedParameter],
+) -> Result<()> {
+    let active_profile = &mut *crate::ACTIVE_PROFILE.lock();
+
+    match active_profile.as_mut() {
+        Some(active_profile) if is_same_file(&active_profile.profile_file, profile_path) => {
+            for parameter in layer_parameters {
+                compositing::set_layer_parameter(
+                    script_path,
+                    &parameter.name,
+                    &parameter.value,
+                    canvas::get_canvas_size(),
+                    &mut active_profile.layers,
+                )?;
+            }
+
+            script::FRAME_GENERATION_COUNTER.fetch_add(1, Ordering::SeqCst);
+
+            Ok(())
+        }
+
+        _ => {
+            error!("Layer parameters may only be set on the active profile");
+            Err(ParametersUtilError::ProfileNotActive {}.into())
+        }
+    }
+}
+
 fn update_profile_and_state_file(
     profile: &mut Profile,
     script_path: &Path,
@@ -0,0 +1,534 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use log::*;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::constants;
+
+/// Upper bounds of the buckets of the frame-time histograms, in microseconds.
+/// The last bucket of a histogram holds all samples that exceed the last bound
+pub const BUCKET_BOUNDS_MICROS: [u64; 10] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000,
+];
+
+/// Number of samples that have to be recorded before the budget is enforced
+const MIN_SAMPLES: u64 = constants::TARGET_FPS;
+
+/// Smoothing factor of the moving average of the frame-time
+const AVERAGE_ALPHA: f64 = 0.1;
+
+/// Throttled scripts run at least once per second
+const MAX_THROTTLE_INTERVAL: u32 = constants::TARGET_FPS as u32;
+
+lazy_static! {
+    /// Frame-time statistics of the currently running Lua VMs, indexed by the index of the VM
+    pub static ref SCRIPT_PROFILES: Arc<RwLock<Vec<Option<ScriptProfile>>>> =
+        Arc::new(RwLock::new(vec![]));
+
+    /// Used to detect stale updates of VMs that have already been replaced
+    static ref NEXT_PROFILE_ID: AtomicU64 = AtomicU64::new(1);
+}
+
+thread_local! {
+    /// Profiler and budget state of the script running in this thread
+    static THREAD_STATE: RefCell<Option<ThreadState>> = RefCell::new(None);
+}
+
+/// What to do with a script that exceeds its frame-time budget
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetAction {
+    /// Only report the script
+    None,
+
+    /// Skip ticks, so that the average frame-time of the script stays within the budget
+    Throttle,
+
+    /// Stop calling the `on_tick` handler of the script
+    Suspend,
+}
+
+impl std::str::FromStr for BudgetAction {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "throttle" => Ok(Self::Throttle),
+            "suspend" => Ok(Self::Suspend),
+
+            _ => Err(eyre::eyre!("Invalid budget action: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Budget {
+    pub micros: f64,
+    pub action: BudgetAction,
+}
+
+impl Budget {
+    /// Get the per-script frame-time budget from the configuration, if any
+    pub fn from_config() -> Option<Self> {
+        let config = crate::CONFIG.lock();
+        let config = config.as_ref()?;
+
+        let millis = config.get_float("global.script_budget_millis").ok()?;
+
+        if millis <= 0.0 {
+            return None;
+        }
+
+        let action = config
+            .get_string("global.script_budget_action")
+            .ok()
+            .and_then(|action| {
+                action
+                    .parse::<BudgetAction>()
+                    .map_err(|e| warn!("{}", e))
+                    .ok()
+            })
+            .unwrap_or(BudgetAction::None);
+
+        Some(Self {
+            micros: millis * 1000.0,
+            action,
+        })
+    }
+}
+
+/// State of a script with regard to its frame-time budget
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetState {
+    /// Within budget, or no budget has been configured
+    Ok,
+
+    /// Exceeds its budget, but no action is taken
+    OverBudget,
+
+    /// Only every n-th tick is forwarded to the script
+    Throttled { interval: u32 },
+
+    /// The script does not receive any ticks
+    Suspended,
+}
+
+/// A histogram of frame-times
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub total_micros: u64,
+    pub max_micros: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKET_BOUNDS_MICROS.len() + 1],
+            count: 0,
+            total_micros: 0,
+            max_micros: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, micros: u64) {
+        let index = BUCKET_BOUNDS_MICROS
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MICROS.len());
+
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.total_micros += micros;
+        self.max_micros = self.max_micros.max(micros);
+    }
+
+    pub fn mean_micros(&self) -> u64 {
+        self.total_micros.checked_div(self.count).unwrap_or(0)
+    }
+
+    /// Returns the upper bound of the bucket that holds the percentile `p` (0.0 - 1.0),
+    /// or the maximum frame-time for samples of the last bucket
+    pub fn percentile_micros(&self, p: f64) -> u64 {
+        let rank = (self.count as f64 * p.clamp(0.0, 1.0)).ceil() as u64;
+
+        let mut sum = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            sum += count;
+
+            if sum >= rank && *count > 0 {
+                return BUCKET_BOUNDS_MICROS
+                    .get(index)
+                    .map(|bound| (*bound).min(self.max_micros))
+                    .unwrap_or(self.max_micros);
+            }
+        }
+
+        0
+    }
+}
+
+/// Frame-time statistics of a single Lua VM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptProfile {
+    #[serde(skip)]
+    id: u64,
+
+    pub script_file: PathBuf,
+
+    /// Time spent in the `on_tick` handler
+    pub tick: Histogram,
+
+    /// Time spent blending the color map of the script onto the canvas
+    pub render: Histogram,
+
+    /// Moving average of the time spent in the `on_tick` handler
+    pub average_micros: f64,
+
+    pub budget_micros: Option<f64>,
+    pub state: BudgetState,
+
+    /// Number of ticks that were not forwarded to the script
+    pub skipped_ticks: u64,
+}
+
+/// Enforces the frame-time budget of a single script
+#[derive(Debug, Clone)]
+pub struct BudgetController {
+    budget: Option<Budget>,
+
+    average_micros: f64,
+    samples: u64,
+
+    interval: u32,
+    suspended: bool,
+
+    pending_ticks: u32,
+    accumulated_delta: u32,
+}
+
+impl BudgetController {
+    pub fn new(budget: Option<Budget>) -> Self {
+        Self {
+            budget,
+            average_micros: 0.0,
+            samples: 0,
+            interval: 1,
+            suspended: false,
+            pending_ticks: 0,
+            accumulated_delta: 0,
+        }
+    }
+
+    /// Returns the delta that shall be passed to `on_tick`, including the deltas of
+    /// skipped ticks, or `None` if the tick shall be skipped
+    pub fn begin_tick(&mut self, delta: u32) -> Option<u32> {
+        if self.suspended {
+            return None;
+        }
+
+        self.pending_ticks += 1;
+        self.accumulated_delta = self.accumulated_delta.saturating_add(delta);
+
+        if self.pending_ticks >= self.interval {
+            self.pending_ticks = 0;
+
+            Some(std::mem::take(&mut self.accumulated_delta))
+        } else {
+            None
+        }
+    }
+
+    /// Record the time spent in `on_tick` and adjust the throttling interval
+    pub fn end_tick(&mut self, micros: u64) {
+        self.average_micros = if self.samples == 0 {
+            micros as f64
+        } else {
+            AVERAGE_ALPHA * micros as f64 + (1.0 - AVERAGE_ALPHA) * self.average_micros
+        };
+
+        self.samples += 1;
+
+        if let Some(budget) = self.budget {
+            if self.samples >= MIN_SAMPLES {
+                match budget.action {
+                    BudgetAction::None => (),
+
+                    BudgetAction::Throttle => {
+                        let interval = (self.average_micros / budget.micros).ceil() as u32;
+                        self.interval = interval.clamp(1, MAX_THROTTLE_INTERVAL);
+                    }
+
+                    BudgetAction::Suspend => {
+                        if self.average_micros > budget.micros {
+                            self.suspended = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn average_micros(&self) -> f64 {
+        self.average_micros
+    }
+
+    pub fn state(&self) -> BudgetState {
+        if self.suspended {
+            BudgetState::Suspended
+        } else if self.interval > 1 {
+            BudgetState::Throttled {
+                interval: self.interval,
+            }
+        } else if matches!(self.budget, Some(budget) if self.samples >= MIN_SAMPLES && self.average_micros > budget.micros)
+        {
+            BudgetState::OverBudget
+        } else {
+            BudgetState::Ok
+        }
+    }
+}
+
+struct ThreadState {
+    index: usize,
+    id: u64,
+    controller: BudgetController,
+}
+
+/// Clear the statistics of all Lua VMs, e.g. when switching profiles
+pub fn reset() {
+    SCRIPT_PROFILES.write().clear();
+}
+
+/// Start profiling the script `script_file`, that runs in the current thread in the Lua VM
+/// with the index `index`. Any previous statistics of the VM are discarded
+pub fn init_thread(index: usize, script_file: &Path) {
+    let budget = Budget::from_config();
+    let id = NEXT_PROFILE_ID.fetch_add(1, Ordering::SeqCst);
+
+    {
+        let mut profiles = SCRIPT_PROFILES.write();
+
+        if profiles.len() <= index {
+            profiles.resize(index + 1, None);
+        }
+
+        profiles[index] = Some(ScriptProfile {
+            id,
+            script_file: script_file.to_path_buf(),
+            tick: Histogram::default(),
+            render: Histogram::default(),
+            average_micros: 0.0,
+            budget_micros: budget.map(|b| b.micros),
+            state: BudgetState::Ok,
+            skipped_ticks: 0,
+        });
+    }
+
+    THREAD_STATE.with(|s| {
+        *s.borrow_mut() = Some(ThreadState {
+            index,
+            id,
+            controller: BudgetController::new(budget),
+        })
+    });
+}
+
+/// Update the statistics of the VM of the current thread, if it is still current
+fn update_profile<F: FnOnce(&mut ScriptProfile)>(index: usize, id: u64, f: F) {
+    if let Some(Some(profile)) = SCRIPT_PROFILES.write().get_mut(index) {
+        if profile.id == id {
+            f(profile);
+        }
+    }
+}
+
+/// Returns the delta that shall be passed to `on_tick`, or `None` if the tick shall be skipped
+pub fn begin_tick(delta: u32) -> Option<u32> {
+    THREAD_STATE.with(|s| match s.borrow_mut().as_mut() {
+        Some(state) => {
+            let result = state.controller.begin_tick(delta);
+
+            if result.is_none() {
+                update_profile(state.index, state.id, |p| p.skipped_ticks += 1);
+            }
+
+            result
+        }
+
+        None => Some(delta),
+    })
+}
+
+/// Record the time spent in the `on_tick` handler of the script of the current thread
+pub fn end_tick(elapsed: Duration) {
+    THREAD_STATE.with(|s| {
+        if let Some(state) = s.borrow_mut().as_mut() {
+            let micros = elapsed.as_micros() as u64;
+
+            let previous_state = state.controller.state();
+            state.controller.end_tick(micros);
+            let new_state = state.controller.state();
+
+            update_profile(state.index, state.id, |p| {
+                if previous_state != new_state {
+                    match new_state {
+                        BudgetState::Suspended => warn!(
+                            "Script {} exceeds its frame-time budget and has been suspended",
+                            p.script_file.display()
+                        ),
+
+                        BudgetState::Throttled { interval } => info!(
+                            "Script {} exceeds its frame-time budget, running every {} ticks",
+                            p.script_file.display(),
+                            interval
+                        ),
+
+                        _ => (),
+                    }
+                }
+
+                p.tick.record(micros);
+                p.average_micros = state.controller.average_micros();
+                p.state = new_state;
+            });
+        }
+    });
+}
+
+/// Record the time spent blending the color map of the script of the current thread
+pub fn record_render(elapsed: Duration) {
+    THREAD_STATE.with(|s| {
+        if let Some(state) = s.borrow().as_ref() {
+            update_profile(state.index, state.id, |p| {
+                p.render.record(elapsed.as_micros() as u64)
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Budget, BudgetAction, BudgetController, BudgetState, Histogram, MIN_SAMPLES};
+
+    #[test]
+    fn histogram() {
+        let mut histogram = Histogram::default();
+
+        for micros in [50, 80, 200, 900, 900, 900, 3_000, 4_000, 20_000, 250_000] {
+            histogram.record(micros);
+        }
+
+        assert_eq!(histogram.count, 10);
+        assert_eq!(histogram.buckets, vec![2, 1, 0, 3, 0, 2, 0, 1, 0, 0, 1]);
+        assert_eq!(histogram.max_micros, 250_000);
+        assert_eq!(histogram.mean_micros(), 28_003);
+
+        assert_eq!(histogram.percentile_micros(0.5), 1_000);
+        assert_eq!(histogram.percentile_micros(0.9), 25_000);
+        assert_eq!(histogram.percentile_micros(1.0), 250_000);
+
+        assert_eq!(Histogram::default().percentile_micros(0.5), 0);
+    }
+
+    #[test]
+    fn throttle() {
+        let budget = Budget {
+            micros: 1_000.0,
+            action: BudgetAction::Throttle,
+        };
+
+        let mut controller = BudgetController::new(Some(budget));
+
+        // a script that takes 2.5 times its budget
+        for _ in 0..MIN_SAMPLES {
+            assert_eq!(controller.begin_tick(1), Some(1));
+            controller.end_tick(2_500);
+        }
+
+        assert_eq!(controller.state(), BudgetState::Throttled { interval: 3 });
+
+        // skipped deltas are passed on to the next tick
+        assert_eq!(controller.begin_tick(1), None);
+        assert_eq!(controller.begin_tick(2), None);
+        assert_eq!(controller.begin_tick(1), Some(4));
+
+        // recovers after the script became faster
+        for _ in 0..100 {
+            controller.end_tick(100);
+        }
+
+        assert_eq!(controller.state(), BudgetState::Ok);
+        assert_eq!(controller.begin_tick(1), Some(1));
+    }
+
+    #[test]
+    fn suspend() {
+        let budget = Budget {
+            micros: 1_000.0,
+            action: BudgetAction::Suspend,
+        };
+
+        let mut controller = BudgetController::new(Some(budget));
+
+        for _ in 0..MIN_SAMPLES {
+            controller.end_tick(100);
+        }
+
+        // a single slow frame does not suspend a script
+        controller.end_tick(5_000);
+
+        assert_eq!(controller.state(), BudgetState::Ok);
+
+        for _ in 0..MIN_SAMPLES {
+            controller.end_tick(5_000);
+        }
+
+        assert_eq!(controller.state(), BudgetState::Suspended);
+        assert_eq!(controller.begin_tick(1), None);
+    }
+
+    #[test]
+    fn no_budget() {
+        let mut controller = BudgetController::new(None);
+
+        for _ in 0..MIN_SAMPLES {
+            assert_eq!(controller.begin_tick(1), Some(1));
+            controller.end_tick(1_000_000);
+        }
+
+        assert_eq!(controller.state(), BudgetState::Ok);
+    }
+}
@@ -0,0 +1,330 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use config::{Config, ConfigError};
+use lazy_static::lazy_static;
+use log::*;
+use mlua::prelude::*;
+use mlua::StdLib;
+use parking_lot::RwLock;
+use serde::Deserialize;
+
+use crate::scripting::manifest::Permission;
+use crate::util;
+
+lazy_static! {
+    /// Permissions that have been approved by the administrator, see `load_permission_grants`
+    pub static ref PERMISSION_GRANTS: Arc<RwLock<Vec<PermissionGrant>>> =
+        Arc::new(RwLock::new(vec![]));
+}
+
+thread_local! {
+    /// Permissions that have been granted to the script running in this thread
+    pub static GRANTED_PERMISSIONS: RefCell<Option<HashSet<Permission>>> = RefCell::new(None);
+}
+
+/// A `[[permissions]]` section of the configuration file, approves permissions for a script
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PermissionGrant {
+    /// The script file, relative paths are resolved against the script directories
+    pub script: PathBuf,
+
+    pub permissions: Vec<Permission>,
+}
+
+/// Load the permissions that have been approved by the administrator from the
+/// `[[permissions]]` sections of the configuration file
+pub fn load_permission_grants(config: &Config) {
+    match config.get::<Vec<PermissionGrant>>("permissions") {
+        Ok(grants) => {
+            info!("Loaded {} script permission grant(s)", grants.len());
+
+            *PERMISSION_GRANTS.write() = grants;
+        }
+
+        Err(ConfigError::NotFound(_)) => {
+            debug!("No script permissions have been granted");
+        }
+
+        Err(e) => error!("Could not parse script permission grants: {}", e),
+    }
+}
+
+/// Returns the permissions of the script `script_file`, that have been requested by its
+/// manifest and that have also been approved in the configuration file
+pub fn granted_permissions(script_file: &Path, requested: &[Permission]) -> HashSet<Permission> {
+    let script_file = fs::canonicalize(script_file).unwrap_or_else(|_| script_file.to_path_buf());
+
+    let approved = PERMISSION_GRANTS
+        .read()
+        .iter()
+        .filter(|grant| {
+            let path = if grant.script.is_absolute() {
+                Ok(grant.script.clone())
+            } else {
+                util::match_script_path(&grant.script)
+            };
+
+            path.ok().and_then(|p| fs::canonicalize(p).ok()).as_ref() == Some(&script_file)
+        })
+        .flat_map(|grant| grant.permissions.clone())
+        .collect::<HashSet<_>>();
+
+    let mut result = HashSet::new();
+
+    for permission in requested {
+        if approved.contains(permission) {
+            result.insert(*permission);
+        } else {
+            warn!(
+                "Permission {:?} has not been granted to the script {}",
+                permission,
+                script_file.display()
+            );
+        }
+    }
+
+    result
+}
+
+/// Returns `true` if the script running in the current thread has been granted `permission`
+pub fn is_granted(permission: Permission) -> bool {
+    GRANTED_PERMISSIONS.with(|p| {
+        p.borrow()
+            .as_ref()
+            .map(|p| p.contains(&permission))
+            .unwrap_or(false)
+    })
+}
+
+/// The parts of the Lua standard library that are loaded into the VM of every script.
+/// `io`, `os` and `debug` are left out, see `restrict_standard_library`
+pub fn standard_library() -> StdLib {
+    StdLib::COROUTINE
+        | StdLib::TABLE
+        | StdLib::STRING
+        | StdLib::UTF8
+        | StdLib::MATH
+        | StdLib::PACKAGE
+}
+
+/// Expose the parts of the `io` and `os` libraries that are safe to use or that are
+/// required by a permission that has been granted to the script running in the current
+/// thread. Forces `load` and `loadfile` to only accept source code, since malicious
+/// binary chunks may be used to corrupt the memory of the daemon
+pub fn restrict_standard_library(lua_ctx: &Lua) -> mlua::Result<()> {
+    lua_ctx.load_from_std_lib(StdLib::IO | StdLib::OS)?;
+
+    lua_ctx
+        .load(
+            r#"
+            local os_lib, io_lib, lua_load, lua_loadfile = os, io, load, loadfile
+
+            os = {
+                clock = os_lib.clock,
+                date = os_lib.date,
+                difftime = os_lib.difftime,
+                time = os_lib.time,
+            }
+            io = nil
+
+            load = function(chunk, chunkname, _mode, ...)
+                return lua_load(chunk, chunkname, "t", ...)
+            end
+
+            loadfile = function(filename, _mode, ...)
+                return lua_loadfile(filename, "t", ...)
+            end
+
+            dofile = nil
+
+            package.loaded.os = os
+            package.loaded.io = nil
+            package.loaded.debug = nil
+
+            return os_lib, io_lib
+            "#,
+        )
+        .eval::<(LuaTable, LuaTable)>()
+        .and_then(|(os_lib, io_lib)| {
+            if is_granted(Permission::ProcessExecution) {
+                let os: LuaTable = lua_ctx.globals().get("os")?;
+
+                os.set("execute", os_lib.get::<_, LuaFunction>("execute")?)?;
+                os.set("exit", os_lib.get::<_, LuaFunction>("exit")?)?;
+
+                let io = lua_ctx.create_table()?;
+                io.set("popen", io_lib.get::<_, LuaFunction>("popen")?)?;
+
+                lua_ctx.globals().set("io", io.clone())?;
+                lua_ctx
+                    .globals()
+                    .get::<_, LuaTable>("package")?
+                    .get::<_, LuaTable>("loaded")?
+                    .set("io", io)?;
+            }
+
+            Ok(())
+        })?;
+
+    if !is_granted(Permission::Network) {
+        // only keep the preload and the Lua searchers, so that native modules can't be loaded
+        lua_ctx
+            .load(
+                "package.loadlib = nil; package.cpath = ''; \
+                 package.searchers = { package.searchers[1], package.searchers[2] }",
+            )
+            .exec()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        granted_permissions, restrict_standard_library, standard_library, PermissionGrant,
+        GRANTED_PERMISSIONS, PERMISSION_GRANTS,
+    };
+    use crate::scripting::manifest::Permission;
+    use mlua::prelude::*;
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    fn sandboxed_vm(permissions: &[Permission]) -> Lua {
+        GRANTED_PERMISSIONS.with(|p| *p.borrow_mut() = Some(permissions.iter().copied().collect()));
+
+        let lua_ctx = unsafe { Lua::unsafe_new_with(standard_library(), LuaOptions::default()) };
+        restrict_standard_library(&lua_ctx).unwrap();
+
+        lua_ctx
+    }
+
+    fn eval_bool(lua_ctx: &Lua, chunk: &str) -> bool {
+        lua_ctx.load(chunk).eval::<bool>().unwrap()
+    }
+
+    #[test]
+    fn ungranted_script_can_not_access_the_system() {
+        let lua_ctx = sandboxed_vm(&[]);
+
+        assert!(eval_bool(&lua_ctx, "return io == nil and debug == nil"));
+        assert!(eval_bool(
+            &lua_ctx,
+            "return os.remove == nil and os.rename == nil"
+        ));
+        assert!(eval_bool(
+            &lua_ctx,
+            "return os.execute == nil and os.exit == nil"
+        ));
+        assert!(eval_bool(
+            &lua_ctx,
+            "return dofile == nil and package.loadlib == nil"
+        ));
+
+        // the libraries must not be reachable through `require` either
+        assert!(eval_bool(&lua_ctx, "return not pcall(require, 'io')"));
+        assert!(eval_bool(&lua_ctx, "return not pcall(require, 'debug')"));
+        assert!(eval_bool(&lua_ctx, "return require('os').remove == nil"));
+
+        // the safe parts of `os` are still available
+        assert!(eval_bool(
+            &lua_ctx,
+            "return os.time() > 0 and os.clock() >= 0"
+        ));
+    }
+
+    #[test]
+    fn bytecode_can_not_be_loaded() {
+        let lua_ctx = sandboxed_vm(&[]);
+
+        assert!(eval_bool(
+            &lua_ctx,
+            r#"
+            local bytecode = string.dump(function() return 42 end)
+
+            return load(bytecode) == nil and load(bytecode, "chunk", "b") == nil
+                and load(bytecode, "chunk", "bt", {}) == nil
+            "#
+        ));
+
+        // source code is still accepted, with and without a custom environment
+        assert!(eval_bool(
+            &lua_ctx,
+            "return load('return math.pi')() == math.pi"
+        ));
+        assert!(eval_bool(
+            &lua_ctx,
+            "return load('return x', 'c', 'b', { x = 1 })() == 1"
+        ));
+    }
+
+    #[test]
+    fn granted_permission_exposes_only_the_required_functions() {
+        let lua_ctx = sandboxed_vm(&[Permission::ProcessExecution]);
+
+        assert!(eval_bool(
+            &lua_ctx,
+            "return os.execute ~= nil and os.exit ~= nil"
+        ));
+        assert!(eval_bool(
+            &lua_ctx,
+            "return io.popen ~= nil and require('io').popen ~= nil"
+        ));
+
+        assert!(eval_bool(
+            &lua_ctx,
+            "return io.open == nil and os.remove == nil"
+        ));
+        assert!(eval_bool(&lua_ctx, "return debug == nil"));
+    }
+
+    #[test]
+    fn only_approved_permissions_are_granted() {
+        let scripts = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/scripts");
+
+        *PERMISSION_GRANTS.write() = vec![PermissionGrant {
+            script: scripts.join("lib/../macros.lua"),
+            permissions: vec![Permission::InputInjection],
+        }];
+
+        let requested = [Permission::InputInjection, Permission::ProcessExecution];
+
+        assert_eq!(
+            granted_permissions(&scripts.join("macros.lua"), &requested),
+            HashSet::from([Permission::InputInjection])
+        );
+
+        // permissions are granted per script
+        assert!(granted_permissions(&scripts.join("netfx.lua"), &requested).is_empty());
+
+        // approved permissions that have not been requested are not granted either
+        assert!(granted_permissions(&scripts.join("macros.lua"), &[]).is_empty());
+
+        PERMISSION_GRANTS.write().clear();
+    }
+}
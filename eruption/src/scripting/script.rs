@@ -33,13 +33,18 @@ use std::fs;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use std::vec::Vec;
 
 use crate::{
-    constants, hwdevices::KeyboardHidEvent, hwdevices::MouseHidEvent, hwdevices::RGBA,
-    scripting::callbacks, scripting::constants::*,
+    canvas, constants, hwdevices::KeyboardHidEvent, hwdevices::MouseHidEvent, hwdevices::RGBA,
+    profiles::DeviceProfile, scripting::callbacks, scripting::compositing, scripting::constants::*,
 };
 
+use crate::scripting::hot_reload::{self, ScriptState};
+use crate::scripting::profiler;
+use crate::scripting::sandbox;
+
 use super::parameters::PlainParameter;
 use super::parameters::TypedValue;
 
@@ -69,6 +74,9 @@ pub enum Message {
     // Abort,
     Unload,
 
+    /// The script file has been modified, restart the interpreter and carry over its state
+    HotReload,
+
     /// blend LOCAL_LED_MAP with LED_MAP ("realize" the color map)
     RealizeColorMap,
 
@@ -84,7 +92,7 @@ lazy_static! {
         g: 0x00,
         b: 0x00,
         a: 0x00,
-    }; constants::CANVAS_SIZE]));
+    }; canvas::get_canvas_size()]));
 
     /// The last successfully rendered canvas
     pub static ref LAST_RENDERED_LED_MAP: Arc<RwLock<Vec<RGBA>>> = Arc::new(RwLock::new(vec![RGBA {
@@ -92,10 +100,13 @@ lazy_static! {
         g: 0x00,
         b: 0x00,
         a: 0x00,
-    }; constants::CANVAS_SIZE]));
+    }; canvas::get_canvas_size()]));
 
     /// Frame generation counter, used to detect if we need to submit the LED_MAP to the hardware
     pub static ref FRAME_GENERATION_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    /// LED maps of the scripts that render to a single device only
+    pub static ref DEVICE_LED_MAPS: Arc<RwLock<Vec<DeviceLedMap>>> = Arc::new(RwLock::new(vec![]));
 }
 
 thread_local! {
@@ -105,15 +116,70 @@ thread_local! {
         g: 0x00,
         b: 0x00,
         a: 0x00,
-    }; constants::CANVAS_SIZE]);
+    }; canvas::get_canvas_size()]);
 
     /// True, if LED color map was modified at least once in this thread
     pub static LOCAL_LED_MAP_MODIFIED: RefCell<bool> = RefCell::new(false);
 
+    /// Index into DEVICE_LED_MAPS, or None if this thread renders to the shared canvas
+    pub static RENDER_TARGET: RefCell<Option<usize>> = RefCell::new(None);
+
     /// Vec of allocated gradient objects
     pub static ALLOCATED_GRADIENTS: RefCell<HashMap<usize, colorgrad::Gradient>> = RefCell::new(HashMap::new());
 }
 
+/// The LED map of a set of scripts that render to a single device only
+#[derive(Debug, Clone)]
+pub struct DeviceLedMap {
+    pub device: DeviceProfile,
+    pub led_map: Vec<RGBA>,
+}
+
+/// Allocate a LED map for each set of device specific scripts of a profile
+pub fn init_device_led_maps(devices: &[DeviceProfile]) {
+    *DEVICE_LED_MAPS.write() = devices
+        .iter()
+        .map(|device| DeviceLedMap {
+            device: device.clone(),
+            led_map: vec![
+                RGBA {
+                    r: 0x00,
+                    g: 0x00,
+                    b: 0x00,
+                    a: 0x00,
+                };
+                canvas::get_canvas_size()
+            ],
+        })
+        .collect();
+}
+
+/// Clear the LED maps of all sets of device specific scripts
+pub fn clear_device_led_maps() {
+    for device_led_map in DEVICE_LED_MAPS.write().iter_mut() {
+        device_led_map.led_map.fill(RGBA {
+            r: 0x00,
+            g: 0x00,
+            b: 0x00,
+            a: 0x00,
+        });
+    }
+}
+
+/// Returns the LED map of the first set of device specific scripts that selects the device
+pub fn get_device_led_map(
+    device: usize,
+    usb_vid: u16,
+    usb_pid: u16,
+    serial: Option<&str>,
+) -> Option<Vec<RGBA>> {
+    DEVICE_LED_MAPS
+        .read()
+        .iter()
+        .find(|m| m.device.matches(device, usb_vid, usb_pid, serial))
+        .map(|m| m.led_map.clone())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ScriptingError {
     #[error("Could not read script file")]
@@ -147,12 +213,17 @@ pub enum RunScriptResult {
 
     // Currently running interpreter will be shut down and restarted
     RestartScript,
+
+    /// Currently running interpreter will be shut down and restarted with the modified script,
+    /// the state of the old interpreter is passed to the `on_hot_reload` handler of the new one
+    HotReload(ScriptState),
 }
 
 /// Used to control the message processing loop of `run_script`
 pub enum RunningScriptResult {
     Continue,
     RestartScript,
+    HotReload,
     TerminateGracefully,
     TerminateWithErrors,
 }
@@ -231,21 +302,32 @@ impl<'lua> RunningScriptCallHelper<'lua> {
 }
 
 /// Loads and runs a lua script.
-/// Initializes a lua environment, loads the script and executes it
+/// Initializes a lua environment, loads the script and executes it.
+/// If `old_state` is set, the script has been hot reloaded and `on_hot_reload` is invoked after `on_startup`
 pub fn run_script(
     script_file: &Path,
     parameter_values: &mut BTreeMap<String, PlainParameter>,
     rx: &Receiver<Message>,
+    old_state: Option<ScriptState>,
 ) -> Result<RunScriptResult> {
     match fs::read_to_string(script_file) {
         Ok(script) => {
-            let lua_ctx =
-                unsafe { Lua::unsafe_new_with(mlua::StdLib::ALL, mlua::LuaOptions::default()) };
+            let lua_ctx = unsafe {
+                Lua::unsafe_new_with(sandbox::standard_library(), mlua::LuaOptions::default())
+            };
+
+            // Globals defined by Eruption, these are not part of the state of the script
+            let mut builtins = Default::default();
 
             // Prepare the Lua environment and eval the script
             let prepared = register_support_globals(&lua_ctx)
                 .and_then(|()| register_support_funcs(&lua_ctx))
+                .and_then(|()| sandbox::restrict_standard_library(&lua_ctx))
                 .and_then(|()| set_parameter_values(&lua_ctx, parameter_values.values()))
+                .and_then(|()| {
+                    builtins = hot_reload::global_names(&lua_ctx)?;
+                    Ok(())
+                })
                 .and_then(|()| lua_ctx.load(&script).eval::<()>());
 
             if let Err(e) = prepared {
@@ -264,6 +346,24 @@ pub fn run_script(
                 return Ok(RunScriptResult::TerminatedWithErrors);
             }
 
+            if let Some(old_state) = old_state {
+                match old_state.to_lua_table(&lua_ctx) {
+                    Ok(old_state) => {
+                        if call_helper.call(FUNCTION_ON_HOT_RELOAD, old_state).is_err() {
+                            return Ok(RunScriptResult::TerminatedWithErrors);
+                        }
+                    }
+
+                    Err(e) => {
+                        error!(
+                            "Could not restore the state of {}: {}",
+                            call_helper.file_name, e
+                        );
+                        return Ok(RunScriptResult::TerminatedWithErrors);
+                    }
+                }
+            }
+
             loop {
                 if let Ok(msg) = rx.recv() {
                     if let Message::SetParameters {
@@ -287,6 +387,19 @@ pub fn run_script(
                         Ok(RunningScriptResult::RestartScript) => {
                             return Ok(RunScriptResult::RestartScript)
                         }
+                        Ok(RunningScriptResult::HotReload) => {
+                            let state =
+                                ScriptState::capture(&lua_ctx, &builtins).unwrap_or_else(|e| {
+                                    warn!(
+                                        "Could not save the state of {}: {}",
+                                        call_helper.file_name, e
+                                    );
+
+                                    ScriptState::default()
+                                });
+
+                            return Ok(RunScriptResult::HotReload(state));
+                        }
                         Err(e) => {
                             let error = e.source().unwrap_or(&UnknownError {});
                             error!(
@@ -381,6 +494,7 @@ fn process_message(
         Message::MouseMove(rel_x, rel_y, rel_z) => on_mouse_move(call_helper, rel_x, rel_y, rel_z),
         Message::MouseWheelEvent(param) => on_mouse_wheel_event(call_helper, param),
         Message::Unload => on_unload(call_helper),
+        Message::HotReload => Ok(RunningScriptResult::HotReload),
         Message::SetParameters { parameter_values } => {
             on_apply_parameters(call_helper, parameter_values)
         }
@@ -411,45 +525,72 @@ fn on_tick(call_helper: &mut RunningScriptCallHelper, param: u32) -> Result<Runn
     let called = if call_helper.skip_on_tick {
         Ok(RunningScriptCallHelperResult::NoHandler)
     } else {
-        call_helper.call(FUNCTION_ON_TICK, param)
+        // scripts that exceed their frame-time budget may skip ticks
+        match profiler::begin_tick(param) {
+            Some(delta) => {
+                let start = Instant::now();
+                let called = call_helper.call(FUNCTION_ON_TICK, delta);
+
+                profiler::end_tick(start.elapsed());
+
+                called
+            }
+
+            None => Ok(RunningScriptCallHelperResult::Successful),
+        }
     };
 
     continue_if_ok(called)
 }
 
-fn realize_color_map() -> Result<RunningScriptResult> {
-    if LOCAL_LED_MAP_MODIFIED.with(|f| *f.borrow()) {
-        LOCAL_LED_MAP.with(|foreground| {
-            let brightness = crate::BRIGHTNESS.load(Ordering::SeqCst);
+/// Returns the global brightness in percent, taking the brightness fader into account
+pub fn get_effective_brightness() -> f32 {
+    let brightness = crate::BRIGHTNESS.load(Ordering::SeqCst);
 
-            let fader = crate::BRIGHTNESS_FADER.load(Ordering::SeqCst);
-            let fader_base = crate::BRIGHTNESS_FADER_BASE.load(Ordering::SeqCst);
+    let fader = crate::BRIGHTNESS_FADER.load(Ordering::SeqCst);
+    let fader_base = crate::BRIGHTNESS_FADER_BASE.load(Ordering::SeqCst);
 
-            let brightness = if fader_base > 0 && fader > 0 {
-                (1.0 - (fader as f32 / fader_base as f32)) * brightness as f32
-            } else {
-                brightness as f32
-            };
+    if fader_base > 0 && fader > 0 {
+        (1.0 - (fader as f32 / fader_base as f32)) * brightness as f32
+    } else {
+        brightness as f32
+    }
+}
 
-            for chunks in LED_MAP.write().chunks_exact_mut(constants::CANVAS_SIZE) {
-                for (idx, background) in chunks.iter_mut().enumerate() {
-                    let bg = &background;
-                    let fg = foreground.borrow()[idx];
+fn realize_color_map() -> Result<RunningScriptResult> {
+    let start = Instant::now();
 
-                    #[rustfmt::skip]
-                    let color = RGBA {
-                        r: ((((fg.a as f32) * fg.r as f32 + (255 - fg.a) as f32 * bg.r as f32).floor() * brightness / 100.0) as u32 >> 8) as u8,
-                        g: ((((fg.a as f32) * fg.g as f32 + (255 - fg.a) as f32 * bg.g as f32).floor() * brightness / 100.0) as u32 >> 8) as u8,
-                        b: ((((fg.a as f32) * fg.b as f32 + (255 - fg.a) as f32 * bg.b as f32).floor() * brightness / 100.0) as u32 >> 8) as u8,
-                        a: fg.a,
-                    };
+    if LOCAL_LED_MAP_MODIFIED.with(|f| *f.borrow()) {
+        LOCAL_LED_MAP.with(|foreground| {
+            // blend according to the blend mode, opacity and key mask of the layer of this script
+            let layers = compositing::LAYERS.read();
+            let default_layer = compositing::Layer::default();
+            let layer = compositing::get_layer_index()
+                .and_then(|index| layers.get(index))
+                .unwrap_or(&default_layer);
+
+            let blend = |led_map: &mut [RGBA]| {
+                for chunks in led_map.chunks_exact_mut(canvas::get_canvas_size()) {
+                    // brightness is applied to the final LED map by the device I/O thread
+                    layer.blend(chunks, &foreground.borrow(), 100.0);
+                }
+            };
 
-                    *background = color;
+            // scripts that render to a single device only, use a separate LED map
+            match RENDER_TARGET.with(|t| *t.borrow()) {
+                Some(target) => {
+                    if let Some(device_led_map) = DEVICE_LED_MAPS.write().get_mut(target) {
+                        blend(&mut device_led_map.led_map);
+                    }
                 }
+
+                None => blend(&mut LED_MAP.write()),
             }
         });
     }
 
+    profiler::record_render(start.elapsed());
+
     // signal readiness / notify the main thread that we are done
     let val = { *crate::COLOR_MAPS_READY_CONDITION.0.lock() };
 
@@ -39,6 +39,15 @@ function on_startup(config)
     for i = 1, canvas_size do color_map[i] = color_background end
 end
 
+function on_hot_reload(old_state)
+    -- keep the creature of the previous instance of this script, and destroy the one that
+    -- has just been created by the new instance
+    if old_state.handle ~= nil and old_state.handle ~= handle then
+        animal_destroy(handle)
+        handle = old_state.handle
+    end
+end
+
 function on_tick(delta)
     ticks = ticks + delta
 
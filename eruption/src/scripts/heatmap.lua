@@ -28,7 +28,7 @@ local function load_key_histogram(name)
     local result = {}
 
     for i = 1, num_keys do
-        local key = "statistics.histograms." .. name .. "[" .. i .. "]"
+        local key = "global.statistics.histograms." .. name .. "[" .. i .. "]"
         result[i] = load_int(key, 1)
     end
 
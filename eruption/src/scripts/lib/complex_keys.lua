@@ -0,0 +1,265 @@
+-- SPDX-License-Identifier: GPL-3.0-or-later
+--
+-- This file is part of Eruption.
+--
+-- Eruption is free software: you can redistribute it and/or modify
+-- it under the terms of the GNU General Public License as published by
+-- the Free Software Foundation, either version 3 of the License, or
+-- (at your option) any later version.
+--
+-- Eruption is distributed in the hope that it will be useful,
+-- but WITHOUT ANY WARRANTY without even the implied warranty of
+-- MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+-- GNU General Public License for more details.
+--
+-- You should have received a copy of the GNU General Public License
+-- along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+--
+-- Copyright (c) 2019-2022, The Eruption Development Team
+--
+
+-- Tap-hold keys, tap dances and chords, as declared in the `TAP_HOLD_TABLE`,
+-- the `TAP_DANCE_TABLE` and the `CHORD_TABLE` of the `macros.lua` script.
+-- Requires the globals `ticks` and `target_fps`, as well as the functions
+-- `inject_key(..)`, `consume_key()` and `min(..)`
+
+-- state of tap-hold keys, tap dances and chords
+pending_tap_holds = {} -- tap-hold keys that are held down: { since = ticks, held = bool }
+active_tap_dance = nil -- { key_index, count, pressed, deadline }
+held_tap_dance_actions = {} -- actions of finished tap dances, whose key is still held down
+pending_chord = nil -- a chord that has not been completed yet: { chord, pressed, deadline }
+active_chord_action = nil -- the action of a completed chord, whose keys are still held down
+chord_keys_down = {} -- keys of a completed chord, whose release will be consumed
+replayed_keys = {} -- keys of an incomplete chord, that have been replayed
+
+-- convert a duration in milliseconds to ticks
+function millis_to_ticks(millis) return millis * target_fps / 1000 end
+
+-- execute the action of a tap-hold key, a tap dance or a chord: either
+-- an evdev key code, or a macro function
+function execute_action(action, down)
+    if type(action) == "number" then
+        inject_key(action, down)
+    elseif type(action) == "function" then
+        action(down)
+    end
+end
+
+-- handle key presses of tap-hold keys, tap dances and chords,
+-- returns true if the key has been consumed
+function complex_key_down(key_index)
+    -- a pending chord collects its keys
+    if pending_chord ~= nil then
+        if pending_chord.chord.keys[key_index] ~= nil then
+            consume_key()
+
+            pending_chord.pressed[#pending_chord.pressed + 1] = key_index
+
+            local num_keys = 0
+            for _, _ in pairs(pending_chord.chord.keys) do
+                num_keys = num_keys + 1
+            end
+
+            if #pending_chord.pressed == num_keys then
+                for _, index in ipairs(pending_chord.pressed) do
+                    chord_keys_down[index] = true
+                end
+
+                active_chord_action = pending_chord.chord.action
+                execute_action(active_chord_action, true)
+
+                pending_chord = nil
+            end
+
+            return true
+        else
+            abort_chord()
+        end
+    end
+
+    -- repeated taps of a tap dance
+    if active_tap_dance ~= nil then
+        if active_tap_dance.key_index == key_index then
+            consume_key()
+
+            active_tap_dance.count = active_tap_dance.count + 1
+            active_tap_dance.pressed = true
+            active_tap_dance.deadline = ticks +
+                                            millis_to_ticks(
+                                                TAP_DANCE_TABLE[key_index].timeout)
+
+            return true
+        else
+            finish_tap_dance()
+        end
+    end
+
+    -- pressing another key decides pending tap-hold keys in favor of "hold"
+    for index, state in pairs(pending_tap_holds) do
+        if not state.held then
+            state.held = true
+            execute_action(TAP_HOLD_TABLE[index].hold, true)
+        end
+    end
+
+    for _, chord in ipairs(CHORD_TABLE) do
+        if chord.keys[key_index] ~= nil then
+            consume_key()
+
+            pending_chord = {
+                chord = chord,
+                pressed = {key_index},
+                deadline = ticks + millis_to_ticks(chord.timeout)
+            }
+
+            return true
+        end
+    end
+
+    if TAP_DANCE_TABLE[key_index] ~= nil then
+        consume_key()
+
+        active_tap_dance = {
+            key_index = key_index,
+            count = 1,
+            pressed = true,
+            deadline = ticks + millis_to_ticks(TAP_DANCE_TABLE[key_index].timeout)
+        }
+
+        return true
+    end
+
+    if TAP_HOLD_TABLE[key_index] ~= nil then
+        consume_key()
+
+        pending_tap_holds[key_index] = {since = ticks, held = false}
+
+        return true
+    end
+
+    return false
+end
+
+-- handle key releases of tap-hold keys, tap dances and chords,
+-- returns true if the key has been consumed
+function complex_key_up(key_index)
+    -- releasing a key of an incomplete chord aborts the chord
+    if pending_chord ~= nil then
+        for _, index in ipairs(pending_chord.pressed) do
+            if index == key_index then
+                abort_chord()
+                break
+            end
+        end
+    end
+
+    if replayed_keys[key_index] ~= nil then
+        consume_key()
+
+        inject_key(replayed_keys[key_index], false)
+        replayed_keys[key_index] = nil
+
+        return true
+    end
+
+    if chord_keys_down[key_index] then
+        consume_key()
+
+        chord_keys_down[key_index] = nil
+
+        -- the action is released together with the first key of the chord
+        if active_chord_action ~= nil then
+            execute_action(active_chord_action, false)
+            active_chord_action = nil
+        end
+
+        return true
+    end
+
+    if held_tap_dance_actions[key_index] ~= nil then
+        consume_key()
+
+        execute_action(held_tap_dance_actions[key_index], false)
+        held_tap_dance_actions[key_index] = nil
+
+        return true
+    end
+
+    if active_tap_dance ~= nil and active_tap_dance.key_index == key_index then
+        consume_key()
+
+        active_tap_dance.pressed = false
+        active_tap_dance.deadline = ticks +
+                                        millis_to_ticks(
+                                            TAP_DANCE_TABLE[key_index].timeout)
+
+        return true
+    end
+
+    local state = pending_tap_holds[key_index]
+    if state ~= nil then
+        consume_key()
+
+        if state.held then
+            execute_action(TAP_HOLD_TABLE[key_index].hold, false)
+        else
+            execute_action(TAP_HOLD_TABLE[key_index].tap, true)
+            execute_action(TAP_HOLD_TABLE[key_index].tap, false)
+        end
+
+        pending_tap_holds[key_index] = nil
+
+        return true
+    end
+
+    return false
+end
+
+-- replay the keys of a chord that has not been completed in time
+function abort_chord()
+    local chord = pending_chord
+    pending_chord = nil
+
+    for _, index in ipairs(chord.pressed) do
+        inject_key(chord.chord.keys[index], true)
+        replayed_keys[index] = chord.chord.keys[index]
+    end
+end
+
+-- execute the action that has been selected by the number of taps
+function finish_tap_dance()
+    local dance = active_tap_dance
+    active_tap_dance = nil
+
+    local actions = TAP_DANCE_TABLE[dance.key_index].actions
+    local action = actions[min(dance.count, #actions)]
+
+    if action == nil then return end
+
+    execute_action(action, true)
+
+    if dance.pressed then
+        held_tap_dance_actions[dance.key_index] = action
+    else
+        execute_action(action, false)
+    end
+end
+
+-- resolve timeouts of tap-hold keys, tap dances and chords
+function update_complex_keys()
+    if pending_chord ~= nil and ticks >= pending_chord.deadline then
+        abort_chord()
+    end
+
+    if active_tap_dance ~= nil and ticks >= active_tap_dance.deadline then
+        finish_tap_dance()
+    end
+
+    for index, state in pairs(pending_tap_holds) do
+        if not state.held and ticks - state.since >=
+            millis_to_ticks(TAP_HOLD_TABLE[index].timeout) then
+            state.held = true
+            execute_action(TAP_HOLD_TABLE[index].hold, true)
+        end
+    end
+end
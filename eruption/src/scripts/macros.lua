@@ -74,6 +74,10 @@ EASY_SHIFT_MOUSE_DPI_MACRO_TABLE =
         {}, {}, {}, {}, {}, {}
     }
 
+TAP_HOLD_TABLE = {} -- tap-hold keys: { tap = action, hold = action, timeout = millis }
+TAP_DANCE_TABLE = {} -- tap dances: { actions = { action, ... }, timeout = millis }
+CHORD_TABLE = {} -- chords: { keys = { [key_index] = ev_key, ... }, action = action, timeout = millis }
+
 -- import default color scheme
 require "themes/default"
 
@@ -116,6 +120,9 @@ game_mode_enabled = load_bool_transient("global.game_mode_enabled", false) -- ke
 saved_audio_muted = is_audio_muted()
 force_update = false -- force a call to submit_color_map(...) during the current frame
 
+-- tap-hold keys, tap dances and chords
+require "complex_keys"
+
 -- utility functions --
 function consume_key() inject_key(0, false) end
 
@@ -504,6 +511,9 @@ function on_key_down(key_index)
         end
     end
 
+    -- tap-hold keys, tap dances and chords
+    if complex_key_down(key_index) then return end
+
     simple_remapping(key_index, true)
 
     -- complex remapping of keys
@@ -560,6 +570,9 @@ function on_key_up(key_index)
         end
     end
 
+    -- tap-hold keys, tap dances and chords
+    if complex_key_up(key_index) then return end
+
     simple_remapping(key_index, false)
 
     -- complex remapping of keys
@@ -695,6 +708,8 @@ end
 function on_tick(delta)
     ticks = ticks + delta
 
+    update_complex_keys()
+
     -- audio muted state changed?
     local audio_muted = is_audio_muted()
     store_bool_transient("global.audio_muted", audio_muted)
@@ -21,212 +21,42 @@ require "declarations"
 require "utilities"
 require "debug"
 
+-- The Network FX server is implemented natively by the daemon, this script
+-- starts the server and composites the received colors with the other scripts
+
 -- global state variables --
 color_map = {}
-server = nil
-conn = nil
 initialized = false
 
--- utility functions --
-local function split(str, pat)
-    local t = {}
-    local fpat = "(.-)" .. pat
-    local last_end = 1
-    local s, e, cap = str:find(fpat, 1)
-
-    while s do
-        if s ~= 1 or cap ~= "" then table.insert(t, cap) end
-        last_end = e + 1
-        s, e, cap = str:find(fpat, last_end)
-    end
-
-    if last_end <= #str then
-        cap = str:sub(last_end)
-        table.insert(t, cap)
-    end
-
-    return t
-end
-
 -- event handler functions --
 function on_startup(config)
     for i = 1, canvas_size do color_map[i] = 0x00000000 end
 
-    -- bind server socket
-    local status, socket = pcall(require, "socket")
-    if not status then
-        error(
-            "Your system is missing a required Lua library. You may want to install a package named like 'lua-socket' or 'lua-socket-compat'")
-    else
-        server = socket.tcp()
-
-        -- configure socket
-        server:setoption("reuseaddr", true)
-        -- server:setoption("reuseport", true)
-
-        local status, msg = server:bind(bind_address, port, 1)
-        if status == nil then
-            error(
-                "Network FX: Could not bind socket to the specified address: " ..
-                    msg)
-            return
-        end
-
-        local status, msg = server:listen(0)
-        if status == nil then
-            error(
-                "Network FX: Could not transition socket to listening state: " ..
-                    msg)
-            return
-        end
-
-        -- set non-blocking mode
-        server:settimeout(0)
-
-        local ip, port = server:getsockname()
-        info("Network FX: Server now listening on " .. ip .. ":" .. port)
-
-        initialized = true
+    if not netfx_listen(bind_address, port) then
+        error("Network FX: Could not bind socket to the specified address")
+        return
     end
+
+    initialized = true
 end
 
 function on_quit()
-    if server ~= nil then
-        info("Network FX: Server shutting down")
-        server:close()
+    if initialized then
+        netfx_shutdown()
     end
 end
 
 function on_tick(delta)
     if initialized then
-        if conn == nil then
-            -- we currently have no client connected, so poll for pending connection requests
-            conn = server:accept()
-            if conn ~= nil then
-                -- a new client connected
-
-                -- set non-blocking mode
-                conn:settimeout(0)
+        -- returns nil if no colors have been received since the last call
+        local colors = netfx_poll()
 
-                local ip, port = conn:getpeername()
-                info("Network FX: Client connected from " .. ip .. ":" .. port)
+        if colors ~= nil then
+            for i = 1, canvas_size do
+                color_map[i] = colors[i] or 0x00000000
             end
-        else
-            -- receive and process data
-            local data, status
-
-            repeat
-                data, status = conn:receive("*l")
-
-                if data ~= nil then
-                    trace("Network FX: Request: " .. data)
-
-                    -- check for, and process protocol commands
-                    if data == "QUIT" then
-                        conn:send("BYE\n")
-                        conn:close()
-                        conn = nil
-                        return
-                    elseif data == "STATUS" then
-                        conn:send(
-                            "Eruption Network FX / Protocol version: 1.0\n")
-                        return
-                    end
-
-                    -- data is apparently not a command
-                    local result = split(data, ':')
-
-                    -- validate request parameters
-                    if #result ~= 5 then
-                        error("Network FX: Request is ill-formed")
-
-                        conn:send("ERROR: 100\n")
-                        conn:close()
-                        conn = nil
-                        return
-                    end
-
-                    local r, g, b, a = tonumber(result[2]), tonumber(result[3]),
-                                       tonumber(result[4]), tonumber(result[5])
-
-                    -- validate colors
-                    if r < 0 or r > 255 or g < 0 or g > 255 or b < 0 or b > 255 or
-                        a < 0 or a > 255 then
-                        error("Network FX: Color component value out of range")
-
-                        conn:send("ERROR: 110\n")
-                        conn:close()
-                        conn = nil
-                        return
-                    end
-
-                    local color = rgba_to_color(r, g, b, a)
-
-                    local components = split(result[1], ',')
-
-                    for idx = 1, #components do
-                        local spec = split(components[idx], '-')
-                        if #spec < 2 then
-                            if result[1] == "ALL" then
-                                -- predefined zone: full canvas
-                                for i = 1, canvas_size do
-                                    color_map[i] = color
-                                end
-                            else
-                                -- set a single pixel on the canvas to a specific color
-                                local num = tonumber(components[idx])
-                                if n(num) > 0 and n(num) <= canvas_size then
-                                    local index = tonumber(components[idx])
-                                    color_map[index] = color
-                                else
-                                    error("Network FX: Invalid index")
-
-                                    conn:send("ERROR: 120\n")
-                                    conn:close()
-                                    conn = nil
-                                    return
-                                end
-                            end
-                        else
-                            -- set a range of pixels to a specific color
-                            if spec[1] ~= nil and spec[2] ~= nil and spec[1] ~=
-                                '' and spec[2] ~= '' and tonumber(spec[1]) > 0 and
-                                tonumber(spec[1]) <= canvas_size and
-                                tonumber(spec[2]) > 0 and tonumber(spec[2]) <=
-                                canvas_size then
-                                local low = tonumber(spec[1])
-                                local high = tonumber(spec[2])
-
-                                for index = low, high do
-                                    color_map[index] = color
-                                end
-                            else
-                                error("Network FX: Invalid index")
-
-                                conn:send("ERROR: 120\n")
-                                conn:close()
-                                conn = nil
-                                return
-                            end
-                        end
-
-                        submit_color_map(color_map)
-                    end
-
-                    conn:send("OK\n")
-                else
-                    -- socket:read returned 'nil'
-
-                    if status == "closed" then
-                        -- lost connection to client
-                        info("Network FX: Client disconnected")
 
-                        conn = nil
-                    elseif status == "timeout" then
-                        -- don't do anything on timeouts
-                    end
-                end
-            until data == nil
+            submit_color_map(color_map)
         end
     end
 end
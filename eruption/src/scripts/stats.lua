@@ -35,8 +35,13 @@ local function load_key_histogram(name)
     local result = {}
 
     for i = 1, num_keys do
-        local key = "statistics.histograms." .. name .. "[" .. i .. "]"
-        result[i] = load_int(key, 0)
+        -- histograms are shared with other scripts, e.g. the heatmap
+        local key = "global.statistics.histograms." .. name .. "[" .. i .. "]"
+
+        -- fall back to values stored by previous versions of eruption
+        local legacy_key = "statistics.histograms." .. name .. "[" .. i .. "]"
+
+        result[i] = load_int(key, load_int(legacy_key, 0))
     end
 
     return result
@@ -46,7 +51,7 @@ local function store_key_histogram(key_histogram, name)
     trace("Statistics: Saving histogram '" .. name .. "' to persistent storage")
 
     for i = 1, num_keys do
-        local key = "statistics.histograms." .. name .. "[" .. i .. "]"
+        local key = "global.statistics.histograms." .. name .. "[" .. i .. "]"
         store_int(key, key_histogram[i])
     end
 end
@@ -55,7 +60,7 @@ local function dump_key_histogram(name)
     info("Statistics: Dumping: '" .. name .. "'")
 
     for i = 1, num_keys do
-        local key = "statistics.histograms." .. name .. "[" .. i .. "]"
+        local key = "global.statistics.histograms." .. name .. "[" .. i .. "]"
         local result = load_int(key, 0)
 
         info(i .. ": " .. result)
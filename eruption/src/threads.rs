@@ -31,10 +31,13 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 use crate::{
-    constants, dbus_interface, hwdevices, macros, plugins, script,
-    scripting::parameters::PlainParameter, sdk_support, uleds, DeviceAction, EvdevError,
-    KeyboardDevice, MainError, MouseDevice, COLOR_MAPS_READY_CONDITION, FAILED_TXS, KEY_STATES,
-    LUA_TXS, QUIT, REQUEST_FAILSAFE_MODE, RGBA, SDK_SUPPORT_ACTIVE, ULEDS_SUPPORT_ACTIVE,
+    canvas, constants, dbus_interface, hwdevices, lighting, macros, openrgb, plugins, remote_api,
+    script, scripting::compositing, scripting::manifest::Permission,
+    scripting::parameters::PlainParameter, scripting::profiler, scripting::sandbox, sdk_support,
+    uleds, DeviceAction, EvdevError, KeyboardDevice, MainError, MouseDevice,
+    COLOR_MAPS_READY_CONDITION, FAILED_TXS, KEY_STATES, LIGHTING_SUPPORT_ACTIVE, LUA_TXS,
+    OPENRGB_SUPPORT_ACTIVE, QUIT, REQUEST_FAILSAFE_MODE, RGBA, SDK_SUPPORT_ACTIVE,
+    ULEDS_SUPPORT_ACTIVE,
 };
 
 pub type Result<T> = std::result::Result<T, eyre::Error>;
@@ -71,23 +74,31 @@ pub fn spawn_dbus_api_thread(
 
                 // process events, destined for the dbus api
                 match dbus_api_rx.recv_timeout(Duration::from_millis(timeout)) {
-                    Ok(result) => match result {
-                        DbusApiEvent::ProfilesChanged => dbus.notify_profiles_changed()?,
+                    Ok(result) => {
+                        // forward state changes to subscribed SDK and remote API clients
+                        sdk_support::notify_state_changed(&result);
+                        remote_api::notify_state_changed(&result);
 
-                        DbusApiEvent::ActiveProfileChanged => {
-                            dbus.notify_active_profile_changed()?
-                        }
+                        match result {
+                            DbusApiEvent::ProfilesChanged => dbus.notify_profiles_changed()?,
 
-                        DbusApiEvent::ActiveSlotChanged => dbus.notify_active_slot_changed()?,
+                            DbusApiEvent::ActiveProfileChanged => {
+                                dbus.notify_active_profile_changed()?
+                            }
 
-                        DbusApiEvent::BrightnessChanged => dbus.notify_brightness_changed()?,
+                            DbusApiEvent::ActiveSlotChanged => dbus.notify_active_slot_changed()?,
 
-                        DbusApiEvent::DeviceStatusChanged => dbus.notify_device_status_changed()?,
+                            DbusApiEvent::BrightnessChanged => dbus.notify_brightness_changed()?,
 
-                        DbusApiEvent::DeviceHotplug(device_info, remove) => {
-                            dbus.notify_device_hotplug(device_info, remove)?
+                            DbusApiEvent::DeviceStatusChanged => {
+                                dbus.notify_device_status_changed()?
+                            }
+
+                            DbusApiEvent::DeviceHotplug(device_info, remove) => {
+                                dbus.notify_device_hotplug(device_info, remove)?
+                            }
                         }
-                    },
+                    }
 
                     Err(_e) => {
                         event_received = dbus.get_next_event_timeout(0).unwrap_or_else(|e| {
@@ -417,6 +428,126 @@ pub fn spawn_mouse_input_thread(
     Ok(())
 }
 
+/// Spawns the keyboard events thread of a virtual keyboard and executes it's main loop
+pub fn spawn_virtual_keyboard_input_thread(
+    kbd_tx: Sender<Option<evdev_rs::InputEvent>>,
+    keyboard_device: KeyboardDevice,
+    device_index: usize,
+    input_rx: Receiver<evdev_rs::InputEvent>,
+) -> plugins::Result<()> {
+    thread::Builder::new()
+        .name(format!("events/kbd:{}", device_index))
+        .spawn(move || -> Result<()> {
+            info!("Now listening on virtual keyboard: {}", device_index);
+
+            loop {
+                // check if we shall terminate the input thread, before we poll the keyboard
+                if QUIT.load(Ordering::SeqCst) {
+                    break Ok(());
+                }
+
+                match input_rx.recv_timeout(Duration::from_millis(constants::SLEEP_TIME_TIMEOUT)) {
+                    Ok(event) => {
+                        trace!("Key event: {:?}", event);
+
+                        // reset "to be dropped" flag
+                        macros::DROP_CURRENT_KEY.store(false, Ordering::SeqCst);
+
+                        // update our internal representation of the keyboard state
+                        if let evdev_rs::enums::EventCode::EV_KEY(ref code) = event.event_code {
+                            let is_pressed = event.value > 0;
+                            let index = keyboard_device.read().ev_key_to_key_index(*code) as usize;
+
+                            if let Some(v) = KEY_STATES.write().get_mut(index) {
+                                *v = is_pressed;
+                            }
+                        }
+
+                        kbd_tx.send(Some(event)).unwrap_or_else(|e| {
+                            error!("Could not send a keyboard event to the main thread: {}", e);
+                        });
+
+                        // update AFK timer
+                        *crate::LAST_INPUT_TIME.lock() = Instant::now();
+                    }
+
+                    Err(flume::RecvTimeoutError::Timeout) => { /* do nothing */ }
+
+                    Err(flume::RecvTimeoutError::Disconnected) => {
+                        return Err(EvdevError::EvdevEventError {}.into());
+                    }
+                }
+            }
+        })
+        .unwrap_or_else(|e| {
+            error!("Could not spawn a thread: {}", e);
+            panic!()
+        });
+
+    Ok(())
+}
+
+/// Spawns the mouse events thread of a virtual mouse and executes it's main loop
+pub fn spawn_virtual_mouse_input_thread(
+    mouse_tx: Sender<Option<evdev_rs::InputEvent>>,
+    mouse_device: MouseDevice,
+    device_index: usize,
+    input_rx: Receiver<evdev_rs::InputEvent>,
+) -> plugins::Result<()> {
+    thread::Builder::new()
+        .name(format!("events/mouse:{}", device_index))
+        .spawn(move || -> Result<()> {
+            info!("Now listening on virtual mouse: {}", device_index);
+
+            loop {
+                // check if we shall terminate the input thread, before we poll the mouse device
+                if QUIT.load(Ordering::SeqCst) {
+                    break Ok(());
+                }
+
+                match input_rx.recv_timeout(Duration::from_millis(constants::SLEEP_TIME_TIMEOUT)) {
+                    Ok(event) => {
+                        // reset "to be dropped" flag
+                        macros::DROP_CURRENT_MOUSE_INPUT.store(false, Ordering::SeqCst);
+
+                        // update our internal representation of the device state
+                        if let evdev_rs::enums::EventCode::EV_KEY(code) = event.event_code {
+                            let is_pressed = event.value > 0;
+                            match mouse_device.read().ev_key_to_button_index(code) {
+                                Ok(index) => {
+                                    crate::BUTTON_STATES.write()[index as usize] = is_pressed
+                                }
+
+                                Err(e) => {
+                                    log::warn!("Mouse event for '{code:?}' not processed: {e}")
+                                }
+                            }
+                        }
+
+                        mouse_tx.send(Some(event)).unwrap_or_else(|e| {
+                            error!("Could not send a mouse event to the main thread: {}", e);
+                        });
+
+                        // update AFK timer
+                        *crate::LAST_INPUT_TIME.lock() = Instant::now();
+                    }
+
+                    Err(flume::RecvTimeoutError::Timeout) => { /* do nothing */ }
+
+                    Err(flume::RecvTimeoutError::Disconnected) => {
+                        return Err(EvdevError::EvdevEventError {}.into());
+                    }
+                }
+            }
+        })
+        .unwrap_or_else(|e| {
+            error!("Could not spawn a thread: {}", e);
+            panic!()
+        });
+
+    Ok(())
+}
+
 /// Spawns the mouse events thread for an additional sub-device on the mouse and executes the thread's main loop
 /* pub fn spawn_mouse_input_thread_secondary(
     mouse_tx: Sender<Option<evdev_rs::InputEvent>>,
@@ -699,6 +830,9 @@ pub fn spawn_lua_thread(
     lua_rx: Receiver<script::Message>,
     script_file: &Path,
     parameters: &[PlainParameter],
+    render_target: Option<usize>,
+    profile_file: &Path,
+    permissions: &[Permission],
 ) -> Result<()> {
     info!("Loading Lua script: {}", script_file.display());
 
@@ -714,18 +848,44 @@ pub fn spawn_lua_thread(
         .map(|pv| (pv.name.clone(), pv.clone()))
         .collect();
 
+    let store_namespace = plugins::persistence::script_namespace(profile_file, &script_file);
+    let permissions = sandbox::granted_permissions(&script_file, permissions);
+
     builder.spawn(move || -> Result<()> {
         #[cfg(feature = "profiling")]
         coz::thread_init();
 
+        script::RENDER_TARGET.with(|t| *t.borrow_mut() = render_target);
+        plugins::persistence::STORE_NAMESPACE.with(|n| *n.borrow_mut() = Some(store_namespace));
+        compositing::LAYER_INDEX.with(|i| *i.borrow_mut() = Some(thread_idx));
+        sandbox::GRANTED_PERMISSIONS.with(|p| *p.borrow_mut() = Some(permissions));
+        profiler::init_thread(thread_idx, &script_file);
+
+        // state of the script, carried over to the next instance on a hot reload
+        let mut old_state = None;
+
         loop {
-            let result = script::run_script(&script_file, &mut parameter_values, &lua_rx);
+            let result = script::run_script(
+                &script_file,
+                &mut parameter_values,
+                &lua_rx,
+                old_state.take(),
+            );
 
             match result {
                 Ok(script::RunScriptResult::RestartScript) => {
                     debug!("Restarting script {}", script_file.to_string_lossy());
                 }
 
+                Ok(script::RunScriptResult::HotReload(state)) => {
+                    info!("Hot reloading script {}", script_file.to_string_lossy());
+
+                    // the modified script starts with a fresh frame-time budget
+                    profiler::init_thread(thread_idx, &script_file);
+
+                    old_state = Some(state);
+                }
+
                 Ok(script::RunScriptResult::TerminatedGracefully) => return Ok(()),
 
                 Ok(script::RunScriptResult::TerminatedWithErrors) => {
@@ -785,14 +945,14 @@ pub fn spawn_device_io_thread(dev_io_rx: Receiver<DeviceAction>) -> Result<()> {
                             let mut drop_frame = false;
 
                             // first, start with a clear canvas
-                            script::LED_MAP.write().copy_from_slice(
-                                &[RGBA {
-                                    r: 0,
-                                    g: 0,
-                                    b: 0,
-                                    a: 0,
-                                }; constants::CANVAS_SIZE],
-                            );
+                            script::LED_MAP.write().fill(RGBA {
+                                r: 0,
+                                g: 0,
+                                b: 0,
+                                a: 0,
+                            });
+
+                            script::clear_device_led_maps();
 
                             // instruct Lua VMs to realize their color maps,
                             // e.g. to blend their local color maps with the canvas
@@ -828,50 +988,50 @@ pub fn spawn_device_io_thread(dev_io_rx: Receiver<DeviceAction>) -> Result<()> {
                                 }
                             }
 
-                            if ULEDS_SUPPORT_ACTIVE.load(Ordering::SeqCst) {
-                                // blend the LED map of the Userspace LEDs support plugin
-                                let uleds_led_map = uleds::LED_MAP.read();
-                                let brightness = crate::BRIGHTNESS.load(Ordering::SeqCst);
-
-                                for chunks in script::LED_MAP.write().chunks_exact_mut(constants::CANVAS_SIZE) {
-                                    for (idx, background) in chunks.iter_mut().enumerate() {
-                                        let bg = &background;
-                                        let fg = uleds_led_map[idx];
-
-                                        #[rustfmt::skip]
-                                        let color = RGBA {
-                                            r: ((((fg.a as f32) * fg.r as f32 + (255 - fg.a) as f32 * bg.r as f32).floor() * brightness as f32 / 100.0) as u32 >> 8) as u8,
-                                            g: ((((fg.a as f32) * fg.g as f32 + (255 - fg.a) as f32 * bg.g as f32).floor() * brightness as f32 / 100.0) as u32 >> 8) as u8,
-                                            b: ((((fg.a as f32) * fg.b as f32 + (255 - fg.a) as f32 * bg.b as f32).floor() * brightness as f32 / 100.0) as u32 >> 8) as u8,
-                                            a: fg.a,
-                                        };
-
-                                        *background = color;
-                                    }
+                            // blend the overlays of the plugins and of the D-Bus API onto the canvas, as
+                            // well as onto the LED maps of the device specific scripts, then apply the
+                            // global brightness exactly once, after all layers and overlays
+                            let uleds_active = ULEDS_SUPPORT_ACTIVE.load(Ordering::SeqCst);
+                            let lighting_active = LIGHTING_SUPPORT_ACTIVE.load(Ordering::SeqCst);
+                            let openrgb_active = OPENRGB_SUPPORT_ACTIVE.load(Ordering::SeqCst);
+                            let sdk_active = SDK_SUPPORT_ACTIVE.load(Ordering::SeqCst);
+                            let dbus_active = dbus_interface::is_led_map_active();
+
+                            let brightness = script::get_effective_brightness();
+
+                            let finalize = |led_map: &mut [RGBA]| {
+                                if uleds_active {
+                                    // blend the LED map of the Userspace LEDs support plugin
+                                    compositing::blend_overlay(led_map, &uleds::LED_MAP.read());
                                 }
-                            }
 
-                            if SDK_SUPPORT_ACTIVE.load(Ordering::SeqCst) {
-                                // finally, blend the LED map of the SDK support plugin
-                                let sdk_led_map = sdk_support::LED_MAP.read();
-                                let brightness = crate::BRIGHTNESS.load(Ordering::SeqCst);
-
-                                for chunks in script::LED_MAP.write().chunks_exact_mut(constants::CANVAS_SIZE) {
-                                    for (idx, background) in chunks.iter_mut().enumerate() {
-                                        let bg = &background;
-                                        let fg = sdk_led_map[idx];
-
-                                        #[rustfmt::skip]
-                                        let color = RGBA {
-                                            r: ((((fg.a as f32) * fg.r as f32 + (255 - fg.a) as f32 * bg.r as f32).floor() * brightness as f32 / 100.0) as u32 >> 8) as u8,
-                                            g: ((((fg.a as f32) * fg.g as f32 + (255 - fg.a) as f32 * bg.g as f32).floor() * brightness as f32 / 100.0) as u32 >> 8) as u8,
-                                            b: ((((fg.a as f32) * fg.b as f32 + (255 - fg.a) as f32 * bg.b as f32).floor() * brightness as f32 / 100.0) as u32 >> 8) as u8,
-                                            a: fg.a,
-                                        };
-
-                                        *background = color;
-                                    }
+                                if lighting_active {
+                                    // blend the LED map of the E1.31 and DDP receivers
+                                    compositing::blend_overlay(led_map, &lighting::LED_MAP.read());
+                                }
+
+                                if openrgb_active {
+                                    // blend the LED map of the OpenRGB SDK server
+                                    compositing::blend_overlay(led_map, &openrgb::LED_MAP.read());
                                 }
+
+                                if sdk_active {
+                                    // blend the LED map of the SDK support plugin
+                                    compositing::blend_overlay(led_map, &sdk_support::LED_MAP.read());
+                                }
+
+                                if dbus_active {
+                                    // blend the LED map that has been set via the D-Bus API
+                                    compositing::blend_overlay(led_map, &dbus_interface::LED_MAP.read());
+                                }
+
+                                compositing::apply_brightness(led_map, brightness);
+                            };
+
+                            finalize(&mut script::LED_MAP.write());
+
+                            for device_led_map in script::DEVICE_LED_MAPS.write().iter_mut() {
+                                finalize(&mut device_led_map.led_map);
                             }
 
                             // number of pending blend ops should have reached zero by now
@@ -886,11 +1046,23 @@ pub fn spawn_device_io_thread(dev_io_rx: Receiver<DeviceAction>) -> Result<()> {
 
                             // send the final (combined) color map to all of the devices
                             if !drop_frame {
-                                for keyboard_device in crate::KEYBOARD_DEVICES.read().iter() {
+                                // each device shows its own region of the canvas
+                                let canvas_layout = canvas::CANVAS_LAYOUT.read();
+
+                                let num_keyboards = crate::KEYBOARD_DEVICES.read().len();
+                                let num_mice = crate::MOUSE_DEVICES.read().len();
+
+                                for (device_index, keyboard_device) in crate::KEYBOARD_DEVICES.read().iter().enumerate() {
                                     if let Some(mut device) = keyboard_device.try_write() {
                                         if let Ok(is_initialized) = device.is_initialized() {
                                             if is_initialized {
-                                                if let Err(e) = device.send_led_map(&script::LED_MAP.read()) {
+                                                // use the LED map of the device specific scripts, if available
+                                                let device_led_map = script::get_device_led_map(device_index, device.get_usb_vid(), device.get_usb_pid(), device.get_serial());
+
+                                                let led_map = script::LED_MAP.read();
+                                                let led_map = device_led_map.as_deref().unwrap_or(&led_map);
+
+                                                if let Err(e) = device.send_led_map(&canvas_layout.map_to_device(led_map, device_index)) {
                                                     error!("Error sending LED map to a device: {}", e);
 
                                                     if device.has_failed().unwrap_or(true) {
@@ -924,11 +1096,19 @@ pub fn spawn_device_io_thread(dev_io_rx: Receiver<DeviceAction>) -> Result<()> {
                                     }
                                 }
 
-                                for mouse_device in crate::MOUSE_DEVICES.read().iter() {
+                                for (index, mouse_device) in crate::MOUSE_DEVICES.read().iter().enumerate() {
+                                    let device_index = num_keyboards + index;
+
                                     if let Some(mut device) = mouse_device.try_write() {
                                         if let Ok(is_initialized) = device.is_initialized() {
                                             if is_initialized {
-                                                if let Err(e) = device.send_led_map(&script::LED_MAP.read()) {
+                                                // use the LED map of the device specific scripts, if available
+                                                let device_led_map = script::get_device_led_map(device_index, device.get_usb_vid(), device.get_usb_pid(), device.get_serial());
+
+                                                let led_map = script::LED_MAP.read();
+                                                let led_map = device_led_map.as_deref().unwrap_or(&led_map);
+
+                                                if let Err(e) = device.send_led_map(&canvas_layout.map_to_device(led_map, device_index)) {
                                                     error!("Error sending LED map to a device: {}", e);
 
                                                     if device.has_failed().unwrap_or(true) {
@@ -962,11 +1142,19 @@ pub fn spawn_device_io_thread(dev_io_rx: Receiver<DeviceAction>) -> Result<()> {
                                     }
                                 }
 
-                                for misc_device in crate::MISC_DEVICES.read().iter() {
+                                for (index, misc_device) in crate::MISC_DEVICES.read().iter().enumerate() {
+                                    let device_index = num_keyboards + num_mice + index;
+
                                     if let Some(mut device) = misc_device.try_write() {
                                         if let Ok(is_initialized) = device.is_initialized() {
                                             if is_initialized {
-                                                if let Err(e) = device.send_led_map(&script::LED_MAP.read()) {
+                                                // use the LED map of the device specific scripts, if available
+                                                let device_led_map = script::get_device_led_map(device_index, device.get_usb_vid(), device.get_usb_pid(), device.get_serial());
+
+                                                let led_map = script::LED_MAP.read();
+                                                let led_map = device_led_map.as_deref().unwrap_or(&led_map);
+
+                                                if let Err(e) = device.send_led_map(&canvas_layout.map_to_device(led_map, device_index)) {
                                                     error!("Error sending LED map to a device: {}", e);
 
                                                     if device.has_failed().unwrap_or(true) {
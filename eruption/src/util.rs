@@ -173,6 +173,36 @@ pub fn get_script_dirs() -> Vec<PathBuf> {
     result
 }
 
+/// Returns the directories that hold declarative device descriptions
+pub fn get_device_description_dirs() -> Vec<PathBuf> {
+    let mut result = vec![];
+
+    let config = crate::CONFIG.lock();
+
+    let device_description_dirs = config
+        .as_ref()
+        .map(|c| {
+            c.get::<Vec<String>>("global.device_description_dirs")
+                .unwrap_or_else(|_| vec![])
+        })
+        .unwrap_or_else(std::vec::Vec::new);
+
+    let mut device_description_dirs = device_description_dirs
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<PathBuf>>();
+
+    result.append(&mut device_description_dirs);
+
+    // if no directories have been specified, use the hard coded default instead
+    if result.is_empty() {
+        let path = PathBuf::from(constants::DEFAULT_DEVICE_DESCRIPTION_DIR);
+        result.push(path);
+    }
+
+    result
+}
+
 pub fn match_script_path<P: AsRef<Path>>(script_file: &P) -> Result<PathBuf> {
     let script_file = script_file.as_ref();
 
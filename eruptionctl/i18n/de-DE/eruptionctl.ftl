@@ -26,7 +26,7 @@ completions-about = Shell Definitionen für Autovervollständigung erzeugen
 config-about = Laufzeit Konfiguration des Eruption Daemons bearbeiten
 color-scheme-about = Ein Farbschema definieren, importieren oder löschen
 devices-about = Gerätespezifische Konfiguration bearbeiten
-status-about = Zeigt das aktuell aktive Profil oder den Slot
+status-about = Zeigt das aktuell aktive Profil oder den Slot, oder Statistiken der laufenden Skripte
 switch-about = Zu einem anderen Profil im aktiven Slot, oder einem anderen Slot wechseln
 profiles-about = Profil bezogene Unterbefehle
 names-about = Slot-Namen anzeigen oder ändern
@@ -34,6 +34,7 @@ scripts-about = Script bezogene Unterbefehle
 effects-about = Spezialeffekte wie z.B. Ambient, Bildoverlays oder Animationen
 param-about = Lesen oder schreiben von Parameterwerten von aktuell laufenden Scripten
 rules-about = Automatisierungsregeln anzeigen und verwalten
+store-about = Von Lua-Skripten gespeicherte Werte verwalten
 
 rules-list = Alle Automatisierungsregeln anzeigen
 rules-add = Eine neue Automatisierungsregel hinzufügen
@@ -26,7 +26,7 @@ completions-about = Generate shell completions
 config-about = Configuration related sub-commands
 color-scheme-about = Define, import or delete a named color scheme
 devices-about = Get or set some device specific configuration parameters
-status-about = Shows the currently active profile or slot
+status-about = Shows the currently active profile or slot, or statistics of the running scripts
 switch-about = Switch to a different profile or slot
 profiles-about = Profile related sub-commands
 names-about = Naming related commands such as renaming of profile slots
@@ -34,6 +34,7 @@ scripts-about = Script related sub-commands
 effects-about = Special effects like Ambient, image overlays or animations
 param-about = Get or set script parameters on the currently active profile
 rules-about = Rules related sub-commands
+store-about = Manage values that have been stored by Lua scripts
 
 rules-list = List all available rules
 rules-add = Create a new rule
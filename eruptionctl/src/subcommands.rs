@@ -30,6 +30,7 @@ mod profiles;
 mod rules;
 mod scripts;
 mod status;
+mod store;
 mod switch;
 
 use crate::translations::tr;
@@ -106,7 +107,13 @@ pub enum Subcommands {
         command: rules::RulesSubcommands,
     },
 
-    #[clap(display_order = 11, hide = true, about(tr!("completions-about")))]
+    #[clap(display_order = 11, about(tr!("store-about")))]
+    Store {
+        #[clap(subcommand)]
+        command: store::StoreSubcommands,
+    },
+
+    #[clap(display_order = 12, hide = true, about(tr!("completions-about")))]
     Completions { shell: clap_complete::Shell },
 }
 
@@ -127,6 +134,7 @@ pub async fn handle_command(subcommand: Subcommands) -> Result<()> {
         Subcommands::Names { command } => names::handle_command(command).await,
         Subcommands::Effects { command } => effects::handle_command(command).await,
         Subcommands::Rules { command } => rules::handle_command(command).await,
+        Subcommands::Store { command } => store::handle_command(command).await,
         Subcommands::Completions { shell } => completions::handle_command(shell).await,
     }
 }
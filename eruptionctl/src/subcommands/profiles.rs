@@ -22,8 +22,10 @@
 use color_eyre::Help;
 use colored::*;
 use eyre::Context;
+use std::path::PathBuf;
 
 use crate::dbus_client::dbus_system_bus;
+use crate::scripting::parameters::ProfileConfiguration;
 use crate::util;
 
 type Result<T> = std::result::Result<T, eyre::Error>;
@@ -76,13 +78,51 @@ async fn list_command() -> Result<()> {
 async fn info_command(profile_name: String) -> Result<()> {
     match util::match_profile_by_name(&profile_name) {
         Ok(profile) => {
+            // prefer the profile as resolved by the daemon, fall back to resolving it locally
+            let profile = match get_profile_info(&profile.profile_file.to_string_lossy()).await {
+                Ok(resolved) => resolved,
+
+                Err(_) => {
+                    let mut config = profile.inherited_config;
+                    config.merge(&profile.config);
+
+                    ProfileInfo {
+                        id: profile.id.to_string(),
+                        name: profile.name,
+                        description: profile.description,
+                        extends: profile.extends,
+                        includes: profile.includes,
+                        resolved_from: profile.resolved_from,
+                        active_scripts: profile.active_scripts,
+                        config,
+                    }
+                }
+            };
+
+            println!(
+                "Profile:\t{} ({})\nDescription:\t{}",
+                profile.name, profile.id, profile.description,
+            );
+
+            if let Some(extends) = &profile.extends {
+                println!("Extends:\t{}", extends);
+            }
+
+            if !profile.includes.is_empty() {
+                println!("Includes:\t{:?}", profile.includes);
+            }
+
+            if !profile.resolved_from.is_empty() {
+                println!("Resolved from:");
+
+                for file in profile.resolved_from.iter() {
+                    println!("\t\t{}", file.display());
+                }
+            }
+
             println!(
-                "Profile:\t{} ({})\nDescription:\t{}\nScripts:\t{:?}\n\n{:#?}",
-                profile.name,
-                profile.id,
-                profile.description,
-                profile.active_scripts,
-                profile.config,
+                "Scripts:\t{:?}\n\n{:#?}",
+                profile.active_scripts, profile.config,
             );
         }
         Err(err) => eprintln!("{}", err),
@@ -91,6 +131,29 @@ async fn info_command(profile_name: String) -> Result<()> {
     Ok(())
 }
 
+/// A profile with its parent profile and included fragments merged in
+#[derive(Debug, serde::Deserialize)]
+struct ProfileInfo {
+    id: String,
+    name: String,
+    description: String,
+    extends: Option<String>,
+    includes: Vec<PathBuf>,
+    resolved_from: Vec<PathBuf>,
+    active_scripts: Vec<PathBuf>,
+    config: ProfileConfiguration,
+}
+
+/// Query the daemon for the resolved profile `profile_file`
+async fn get_profile_info(profile_file: &str) -> Result<ProfileInfo> {
+    let (result,): (String,) = dbus_system_bus("/org/eruption/profile")
+        .await?
+        .method_call("org.eruption.Profile", "GetProfileInfo", (profile_file,))
+        .await?;
+
+    Ok(serde_json::from_str(&result)?)
+}
+
 /// Enumerate all available profiles
 async fn get_profiles() -> Result<Vec<(String, String)>> {
     let (result,): (Vec<(String, String)>,) = dbus_system_bus("/org/eruption/profile")
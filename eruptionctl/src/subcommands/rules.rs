@@ -94,6 +94,12 @@ pub enum Selector {
         mode: WindowFocusedSelectorMode,
         regex: String,
     },
+    Schedule {
+        schedule: String,
+    },
+    Daylight {
+        mode: String,
+    },
 }
 
 impl fmt::Display for Selector {
@@ -115,6 +121,14 @@ impl fmt::Display for Selector {
                     regex.to_string().bold()
                 )?;
             }
+
+            Selector::Schedule { schedule } => {
+                write!(f, "On schedule: '{}'", schedule.to_string().bold())?;
+            }
+
+            Selector::Daylight { mode } => {
+                write!(f, "On daylight: {}", mode.to_string().bold())?;
+            }
         };
 
         Ok(())
@@ -193,6 +207,18 @@ rules add window-name '.*YouTube.*Mozilla Firefox' /var/lib/eruption/profiles/pr
 rules add window-instance gnome-calculator 2
 
 You may want to use the command line tool `xprop` to find the relevant information
+
+
+Time:
+rules add time '<minute> <hour> <day of month> <month> <day of week>' [<profile-name.profile>|<slot number>]
+rules add daylight [day|night] [<profile-name.profile>|<slot number>]
+
+rules add time '* 9-16 * * 1-5' /var/lib/eruption/profiles/profile1.profile
+rules add daylight night 2
+
+Time based rules are active as long as they match, afterwards the previous profile or slot is restored.
+Rules based on daylight require the `latitude` and `longitude` to be set in the `[global]` section
+of the configuration file of eruption-process-monitor
 "#
     );
 }
@@ -377,6 +403,10 @@ async fn set_rules(rules: &IndexMap<Selector, (RuleMetadata, Action)>) -> Result
                     ("window-name".to_string(), regex.to_owned())
                 }
             },
+
+            Selector::Schedule { schedule } => ("time".to_string(), schedule.to_owned()),
+
+            Selector::Daylight { mode } => ("daylight".to_string(), mode.to_owned()),
         };
 
         let action = match action {
@@ -447,6 +477,30 @@ fn parse_rule(rule: &(String, String, String, String)) -> Result<(Selector, Rule
             mode: WindowFocusedSelectorMode::WindowName,
             regex: selector.to_owned(),
         });
+    } else if sensor == "time" {
+        if selector.split_whitespace().count() != 5 {
+            return Err(RuleError::Parse {
+                description:
+                    "A schedule requires 5 fields: minute hour day-of-month month day-of-week"
+                        .to_owned(),
+            }
+            .into());
+        }
+
+        parsed_selector = Some(Selector::Schedule {
+            schedule: selector.to_owned(),
+        });
+    } else if sensor == "daylight" {
+        let mode = selector.to_lowercase();
+
+        if mode != "day" && mode != "night" {
+            return Err(RuleError::Parse {
+                description: "Daylight rules require either 'day' or 'night'".to_owned(),
+            }
+            .into());
+        }
+
+        parsed_selector = Some(Selector::Daylight { mode });
     }
 
     // parse action
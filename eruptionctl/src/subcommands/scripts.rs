@@ -83,6 +83,18 @@ async fn info_command(script_name: String) -> Result<()> {
                 script.description,
                 script.tags.as_ref().unwrap_or(&empty),
             );
+
+            if script.permissions.is_empty() {
+                println!("Permissions:\tNone");
+            } else {
+                let permissions = script
+                    .permissions
+                    .iter()
+                    .map(|p| format!("{:?} ({})", p, p.get_description()))
+                    .collect::<Vec<_>>();
+
+                println!("Permissions:\t{}", permissions.join("\n\t\t").yellow());
+            }
         }
         None => eprintln!("Script not found."),
     }
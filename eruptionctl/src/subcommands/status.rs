@@ -19,6 +19,8 @@
     Copyright (c) 2019-2022, The Eruption Development Team
 */
 
+use std::path::PathBuf;
+
 use color_eyre::Help;
 use colored::*;
 use dbus::nonblock::stdintf::org_freedesktop_dbus::Properties;
@@ -38,12 +40,21 @@ pub enum StatusSubcommands {
     /// Shows the currently active slot
     #[clap(display_order = 1)]
     Slot,
+
+    /// Shows frame-time statistics of the running Lua scripts
+    #[clap(display_order = 2)]
+    Scripts {
+        /// Show the frame-time histogram of each script
+        #[clap(short = 'H', long)]
+        histogram: bool,
+    },
 }
 
 pub async fn handle_command(command: StatusSubcommands) -> Result<()> {
     match command {
         StatusSubcommands::Profile => profile_command().await,
         StatusSubcommands::Slot => slot_command().await,
+        StatusSubcommands::Scripts { histogram } => scripts_command(histogram).await,
     }
 }
 
@@ -68,6 +79,135 @@ async fn slot_command() -> Result<()> {
     Ok(())
 }
 
+async fn scripts_command(histogram: bool) -> Result<()> {
+    let stats = get_script_stats()
+        .await
+        .wrap_err("Could not connect to the Eruption daemon")
+        .suggestion("Please verify that the Eruption daemon is running")?;
+
+    let millis = |micros: u64| format!("{:.2}ms", micros as f64 / 1000.0);
+
+    println!(
+        "{:<32} {:>9} {:>9} {:>9} {:>9} {:>9}  {}",
+        "Script".bold(),
+        "Mean".bold(),
+        "P95".bold(),
+        "Max".bold(),
+        "Render".bold(),
+        "Budget".bold(),
+        "State".bold()
+    );
+
+    for script in stats.scripts.iter() {
+        let name = script
+            .script_file
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| script.script_file.display().to_string());
+
+        let budget = script
+            .budget_micros
+            .map(|b| millis(b as u64))
+            .unwrap_or_else(|| "-".to_string());
+
+        let state = match script.state {
+            BudgetState::Ok => "ok".green(),
+            BudgetState::OverBudget => "over budget".yellow(),
+            BudgetState::Throttled { interval } => format!(
+                "throttled (every {} ticks, {} skipped)",
+                interval, script.skipped_ticks
+            )
+            .yellow(),
+            BudgetState::Suspended => "suspended".red(),
+        };
+
+        println!(
+            "{:<32} {:>9} {:>9} {:>9} {:>9} {:>9}  {}",
+            name,
+            millis(script.tick_mean_micros),
+            millis(script.tick_p95_micros),
+            millis(script.tick.max_micros),
+            millis(script.render_mean_micros),
+            budget,
+            state
+        );
+
+        if histogram {
+            print_histogram(&script.tick, &stats.bucket_bounds_micros);
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+fn print_histogram(histogram: &Histogram, bounds: &[u64]) {
+    const WIDTH: u64 = 40;
+
+    let max = histogram.buckets.iter().copied().max().unwrap_or(0).max(1);
+
+    for (index, count) in histogram.buckets.iter().enumerate() {
+        let label = match bounds.get(index) {
+            Some(bound) => format!("<= {:.2}ms", *bound as f64 / 1000.0),
+            None => format!(
+                " > {:.2}ms",
+                bounds.last().copied().unwrap_or(0) as f64 / 1000.0
+            ),
+        };
+
+        println!(
+            "  {:>12} {:>8} {}",
+            label,
+            count,
+            "#".repeat((count * WIDTH / max) as usize)
+        );
+    }
+}
+
+/// Frame-time statistics of the running Lua scripts, as reported by the daemon
+#[derive(Debug, serde::Deserialize)]
+struct ScriptStats {
+    bucket_bounds_micros: Vec<u64>,
+    scripts: Vec<ScriptProfile>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ScriptProfile {
+    script_file: PathBuf,
+    tick: Histogram,
+    tick_mean_micros: u64,
+    tick_p95_micros: u64,
+    render_mean_micros: u64,
+    budget_micros: Option<f64>,
+    state: BudgetState,
+    skipped_ticks: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Histogram {
+    buckets: Vec<u64>,
+    max_micros: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum BudgetState {
+    Ok,
+    OverBudget,
+    Throttled { interval: u32 },
+    Suspended,
+}
+
+/// Get frame-time statistics of the running Lua scripts
+async fn get_script_stats() -> Result<ScriptStats> {
+    let (result,): (String,) = dbus_system_bus("/org/eruption/status")
+        .await?
+        .method_call("org.eruption.Status", "GetScriptStats", ())
+        .await?;
+
+    Ok(serde_json::from_str(&result)?)
+}
+
 /// Get the name of the currently active profile
 async fn get_active_profile() -> Result<String> {
     let result: String = dbus_system_bus("/org/eruption/profile")
@@ -0,0 +1,194 @@
+/*  SPDX-License-Identifier: GPL-3.0-or-later  */
+
+/*
+    This file is part of Eruption.
+
+    Eruption is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Eruption is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::fs;
+use std::path::PathBuf;
+
+use color_eyre::Help;
+use colored::*;
+use eyre::Context;
+
+use crate::dbus_client::dbus_system_bus;
+
+type Result<T> = std::result::Result<T, eyre::Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("No namespace specified")]
+    NoNamespace {},
+}
+
+/// Subcommands of the "store" command
+#[derive(Debug, clap::Parser)]
+pub enum StoreSubcommands {
+    /// List the values stored by Lua scripts, optionally only of a single profile or script
+    #[clap(display_order = 0)]
+    List { namespace: Option<String> },
+
+    /// Export the stored values to a file, or to stdout
+    #[clap(display_order = 1)]
+    Export {
+        #[clap(short, long)]
+        namespace: Option<String>,
+
+        file: Option<PathBuf>,
+    },
+
+    /// Import previously exported values, existing values with the same keys are replaced
+    #[clap(display_order = 2)]
+    Import { file: PathBuf },
+
+    /// Remove the stored values of a single profile or script, or of all scripts
+    #[clap(display_order = 3)]
+    Clear {
+        namespace: Option<String>,
+
+        /// Remove the values of all scripts
+        #[clap(short, long)]
+        all: bool,
+    },
+}
+
+pub async fn handle_command(command: StoreSubcommands) -> Result<()> {
+    match command {
+        StoreSubcommands::List { namespace } => list_command(namespace).await,
+        StoreSubcommands::Export { namespace, file } => export_command(namespace, file).await,
+        StoreSubcommands::Import { file } => import_command(file).await,
+        StoreSubcommands::Clear { namespace, all } => clear_command(namespace, all).await,
+    }
+}
+
+async fn list_command(namespace: Option<String>) -> Result<()> {
+    let values = list_values(&namespace.unwrap_or_default())
+        .await
+        .wrap_err("Could not connect to the Eruption daemon")
+        .suggestion("Please verify that the Eruption daemon is running")?;
+
+    let mut current_namespace = None;
+
+    for (namespace, key, value) in values {
+        if current_namespace.as_ref() != Some(&namespace) {
+            println!("{}", namespace.bold());
+            current_namespace = Some(namespace);
+        }
+
+        println!("  {} = {}", key, value);
+    }
+
+    Ok(())
+}
+
+async fn export_command(namespace: Option<String>, file: Option<PathBuf>) -> Result<()> {
+    let data = export_values(&namespace.unwrap_or_default())
+        .await
+        .wrap_err("Could not connect to the Eruption daemon")
+        .suggestion("Please verify that the Eruption daemon is running")?;
+
+    match file {
+        Some(file) => fs::write(&file, data)
+            .wrap_err_with(|| format!("Could not write file: {}", file.display()))?,
+
+        None => println!("{}", data),
+    }
+
+    Ok(())
+}
+
+async fn import_command(file: PathBuf) -> Result<()> {
+    let data = fs::read_to_string(&file)
+        .wrap_err_with(|| format!("Could not read file: {}", file.display()))?;
+
+    import_values(&data)
+        .await
+        .wrap_err("Could not import the stored values")
+        .suggestion("Please verify that the Eruption daemon is running")?;
+
+    Ok(())
+}
+
+async fn clear_command(namespace: Option<String>, all: bool) -> Result<()> {
+    match namespace {
+        Some(namespace) if !namespace.is_empty() => {
+            let count = clear_values(&namespace)
+                .await
+                .wrap_err("Could not connect to the Eruption daemon")
+                .suggestion("Please verify that the Eruption daemon is running")?;
+
+            println!("Removed {} values", count);
+        }
+
+        _ if all => {
+            let count = clear_values("")
+                .await
+                .wrap_err("Could not connect to the Eruption daemon")
+                .suggestion("Please verify that the Eruption daemon is running")?;
+
+            println!("Removed {} values", count);
+        }
+
+        _ => {
+            return Err(StoreError::NoNamespace {})
+                .suggestion("Please specify a namespace, or use --all to remove all stored values")
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the stored values as tuples of (namespace, key, value)
+async fn list_values(namespace: &str) -> Result<Vec<(String, String, String)>> {
+    let (result,): (Vec<(String, String, String)>,) = dbus_system_bus("/org/eruption/persistence")
+        .await?
+        .method_call("org.eruption.Persistence", "ListValues", (namespace,))
+        .await?;
+
+    Ok(result)
+}
+
+/// Export the stored values as JSON
+async fn export_values(namespace: &str) -> Result<String> {
+    let (result,): (String,) = dbus_system_bus("/org/eruption/persistence")
+        .await?
+        .method_call("org.eruption.Persistence", "Export", (namespace,))
+        .await?;
+
+    Ok(result)
+}
+
+/// Import previously exported values
+async fn import_values(data: &str) -> Result<bool> {
+    let (result,): (bool,) = dbus_system_bus("/org/eruption/persistence")
+        .await?
+        .method_call("org.eruption.Persistence", "Import", (data,))
+        .await?;
+
+    Ok(result)
+}
+
+/// Remove the stored values of all namespaces selected by `namespace`
+async fn clear_values(namespace: &str) -> Result<u64> {
+    let (result,): (u64,) = dbus_system_bus("/org/eruption/persistence")
+        .await?
+        .method_call("org.eruption.Persistence", "Clear", (namespace,))
+        .await?;
+
+    Ok(result)
+}
@@ -20,11 +20,13 @@
 */
 
 use crate::canvas::Canvas;
+use crate::events::{Event, EventSubscription};
 use crate::hardware::HotplugInfo;
 use crate::transport::{LocalTransport, ServerStatus, Transport};
 use crate::Result;
 use parking_lot::Mutex;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct Connection {
@@ -57,6 +59,22 @@ impl Connection {
     pub fn notify_device_hotplug(&self, hotplug_info: &HotplugInfo) -> Result<()> {
         self.con.lock().notify_device_hotplug(hotplug_info)
     }
+
+    /// Subscribe to events, use `next_event()` to receive them. Returns `false` if the
+    /// daemon refused the subscription, keyboard and mouse events require the
+    /// `org.eruption.monitor` permission
+    pub fn subscribe(&self, subscription: &EventSubscription) -> Result<bool> {
+        self.con.lock().subscribe(subscription)
+    }
+
+    pub fn unsubscribe(&self) -> Result<()> {
+        self.con.lock().unsubscribe()
+    }
+
+    /// Wait up to `timeout` for the next event, returns `None` if no event arrived in time
+    pub fn next_event(&self, timeout: Duration) -> Result<Option<Event>> {
+        self.con.lock().next_event(timeout)
+    }
 }
 
 impl Drop for Connection {
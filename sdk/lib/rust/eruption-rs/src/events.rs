@@ -0,0 +1,74 @@
+/*  SPDX-License-Identifier: LGPL-3.0-or-later  */
+
+/*
+    This file is part of the Eruption SDK.
+
+    The Eruption SDK is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Lesser General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    The Eruption SDK is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Lesser General Public License for more details.
+
+    You should have received a copy of the GNU Lesser General Public License
+    along with the Eruption SDK.  If not, see <http://www.gnu.org/licenses/>.
+
+    Copyright (c) 2019-2022, The Eruption Development Team
+*/
+
+use std::path::PathBuf;
+
+/// An event, pushed by the Eruption daemon to subscribed clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A key has been pressed or released
+    Key { key_index: u32, pressed: bool },
+
+    /// A mouse button has been pressed or released
+    MouseButton { button_index: u32, pressed: bool },
+
+    /// The mouse wheel has been moved
+    MouseWheel { direction: u32 },
+
+    /// The active profile has been switched
+    ProfileChanged { profile_file: PathBuf },
+
+    /// The active slot has been switched
+    SlotChanged { slot: u64 },
+
+    /// The global brightness has been changed
+    BrightnessChanged { brightness: i64 },
+}
+
+/// Selects the kinds of events that a client wants to receive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventSubscription {
+    /// Key presses and releases
+    pub keyboard_events: bool,
+
+    /// Mouse button presses, releases and mouse wheel events
+    pub mouse_events: bool,
+
+    /// Profile, slot and brightness changes
+    pub state_events: bool,
+}
+
+impl EventSubscription {
+    /// Subscribe to all kinds of events
+    pub fn all() -> Self {
+        Self {
+            keyboard_events: true,
+            mouse_events: true,
+            state_events: true,
+        }
+    }
+}
+
+impl Default for EventSubscription {
+    fn default() -> Self {
+        Self::all()
+    }
+}
@@ -20,6 +20,7 @@
 */
 
 use crate::canvas::Canvas;
+use crate::events::{Event, EventSubscription};
 use crate::hardware::HotplugInfo;
 use crate::transport::{ServerStatus, Transport};
 use crate::{util, Result};
@@ -27,11 +28,12 @@ use eyre::eyre;
 use parking_lot::Mutex;
 use prost::Message;
 use socket2::{Domain, SockAddr, Socket, Type};
-use std::collections::HashMap;
-use std::io::{Cursor, Write};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Cursor, Write};
 use std::mem::MaybeUninit;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 pub mod protocol {
     include!(concat!(env!("OUT_DIR"), "/sdk_support.rs"));
@@ -43,6 +45,9 @@ const MAX_BUF: usize = 4096;
 #[derive(Debug, Clone)]
 pub struct LocalTransport {
     pub(crate) socket: Arc<Mutex<Socket>>,
+
+    /// Events that have been received while waiting for a response
+    pending_events: Arc<Mutex<VecDeque<Event>>>,
 }
 
 impl LocalTransport {
@@ -53,8 +58,66 @@ impl LocalTransport {
                 Type::SEQPACKET,
                 None,
             )?)),
+            pending_events: Arc::new(Mutex::new(VecDeque::new())),
         })
     }
+
+    /// Receive the next response from the socket. Events that arrive in the meantime
+    /// are queued, so that they can be picked up later on by `next_event()`
+    fn recv_response(&self, socket: &Socket, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        loop {
+            let n = socket.recv(buf)?;
+
+            if n == 0 {
+                return Ok(0);
+            }
+
+            let tmp = unsafe { util::assume_init(&buf[..n]) };
+
+            match protocol::Response::decode_length_delimited(&mut Cursor::new(&tmp)) {
+                Ok(protocol::Response {
+                    response_message: Some(protocol::response::ResponseMessage::Event(event)),
+                }) => {
+                    if let Some(event) = convert_event(event) {
+                        self.pending_events.lock().push_back(event);
+                    }
+                }
+
+                _ => return Ok(n),
+            }
+        }
+    }
+}
+
+/// Convert an event from its wire representation
+fn convert_event(event: protocol::Event) -> Option<Event> {
+    use protocol::event::EventMessage;
+
+    match event.event_message? {
+        EventMessage::Key(key) => Some(Event::Key {
+            key_index: key.key_index,
+            pressed: key.pressed,
+        }),
+
+        EventMessage::MouseButton(button) => Some(Event::MouseButton {
+            button_index: button.button_index,
+            pressed: button.pressed,
+        }),
+
+        EventMessage::MouseWheel(wheel) => Some(Event::MouseWheel {
+            direction: wheel.direction,
+        }),
+
+        EventMessage::ProfileChanged(profile) => Some(Event::ProfileChanged {
+            profile_file: PathBuf::from(profile.profile_file),
+        }),
+
+        EventMessage::SlotChanged(slot) => Some(Event::SlotChanged { slot: slot.slot }),
+
+        EventMessage::BrightnessChanged(brightness) => Some(Event::BrightnessChanged {
+            brightness: brightness.brightness,
+        }),
+    }
 }
 
 impl Transport for LocalTransport {
@@ -89,7 +152,7 @@ impl Transport for LocalTransport {
                 // read response
                 let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
 
-                match socket.recv(&mut tmp) {
+                match self.recv_response(&socket, &mut tmp) {
                     Ok(0) => Err(eyre!("Lost connection to Eruption")),
 
                     Ok(_n) => {
@@ -132,7 +195,7 @@ impl Transport for LocalTransport {
                 // read response
                 let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
 
-                match socket.recv(&mut tmp) {
+                match self.recv_response(&socket, &mut tmp) {
                     Ok(0) => Err(eyre!("Lost connection to Eruption")),
 
                     Ok(_n) => {
@@ -176,7 +239,7 @@ impl Transport for LocalTransport {
                 // read response
                 let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
 
-                match socket.recv(&mut tmp) {
+                match self.recv_response(&socket, &mut tmp) {
                     Ok(0) => Err(eyre!("Lost connection to Eruption")),
 
                     Ok(_n) => {
@@ -227,7 +290,7 @@ impl Transport for LocalTransport {
                 // read response
                 let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
 
-                match socket.recv(&mut tmp) {
+                match self.recv_response(&socket, &mut tmp) {
                     Ok(0) => Err(eyre!("Lost connection to Eruption")),
 
                     Ok(_n) => {
@@ -275,7 +338,7 @@ impl Transport for LocalTransport {
                 // read response
                 let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
 
-                match socket.recv(&mut tmp) {
+                match self.recv_response(&socket, &mut tmp) {
                     Ok(0) => Err(eyre!("Lost connection to Eruption")),
 
                     Ok(_n) => {
@@ -320,7 +383,7 @@ impl Transport for LocalTransport {
                 // read response
                 let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
 
-                match socket.recv(&mut tmp) {
+                match self.recv_response(&socket, &mut tmp) {
                     Ok(0) => Err(eyre!("Lost connection to Eruption")),
 
                     Ok(_n) => {
@@ -338,6 +401,122 @@ impl Transport for LocalTransport {
             Err(_e) => Err(eyre!("Lost connection to Eruption")),
         }
     }
+
+    fn subscribe(&self, subscription: &EventSubscription) -> Result<bool> {
+        let request = protocol::Request {
+            request_message: Some(protocol::request::RequestMessage::Subscribe(
+                protocol::SubscribeRequest {
+                    keyboard_events: subscription.keyboard_events,
+                    mouse_events: subscription.mouse_events,
+                    state_events: subscription.state_events,
+                },
+            )),
+        };
+
+        let socket = self.socket.lock();
+        self.send_request(&socket, request)?;
+
+        // read response
+        let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
+
+        match self.recv_response(&socket, &mut tmp) {
+            Ok(0) => Err(eyre!("Lost connection to Eruption")),
+
+            Ok(n) => {
+                let tmp = unsafe { util::assume_init(&tmp[..n]) };
+                let result = protocol::Response::decode_length_delimited(&mut Cursor::new(&tmp))?;
+                if let Some(protocol::response::ResponseMessage::Subscribe(subscribe_response)) =
+                    result.response_message
+                {
+                    Ok(subscribe_response.subscribed)
+                } else {
+                    Err(eyre!("Unexpected response"))
+                }
+            }
+
+            Err(_e) => Err(eyre!("Lost connection to Eruption")),
+        }
+    }
+
+    fn unsubscribe(&self) -> Result<()> {
+        let request = protocol::Request {
+            request_message: Some(protocol::request::RequestMessage::Unsubscribe(
+                protocol::UnsubscribeRequest {},
+            )),
+        };
+
+        let socket = self.socket.lock();
+        self.send_request(&socket, request)?;
+
+        // read response
+        let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
+
+        let result = match self.recv_response(&socket, &mut tmp) {
+            Ok(0) => Err(eyre!("Lost connection to Eruption")),
+
+            Ok(_n) => Ok(()),
+
+            Err(_e) => Err(eyre!("Lost connection to Eruption")),
+        };
+
+        // events that are still in flight are of no interest anymore
+        self.pending_events.lock().clear();
+
+        result
+    }
+
+    fn next_event(&self, timeout: Duration) -> Result<Option<Event>> {
+        if let Some(event) = self.pending_events.lock().pop_front() {
+            return Ok(Some(event));
+        }
+
+        let socket = self.socket.lock();
+
+        // a zero timeout would mean "block forever"
+        socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+
+        let mut tmp = [MaybeUninit::zeroed(); MAX_BUF];
+        let result = socket.recv(&mut tmp);
+
+        socket.set_read_timeout(None)?;
+
+        match result {
+            Ok(0) => Err(eyre!("Lost connection to Eruption")),
+
+            Ok(n) => {
+                let tmp = unsafe { util::assume_init(&tmp[..n]) };
+                let result = protocol::Response::decode_length_delimited(&mut Cursor::new(&tmp))?;
+                if let Some(protocol::response::ResponseMessage::Event(event)) =
+                    result.response_message
+                {
+                    Ok(convert_event(event))
+                } else {
+                    Ok(None)
+                }
+            }
+
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                Ok(None)
+            }
+
+            Err(_e) => Err(eyre!("Lost connection to Eruption")),
+        }
+    }
+}
+
+impl LocalTransport {
+    fn send_request(&self, socket: &Socket, request: protocol::Request) -> Result<()> {
+        let mut buf = Vec::new();
+        request.encode_length_delimited(&mut buf)?;
+
+        socket
+            .send(&buf)
+            .map_err(|_e| eyre!("Lost connection to Eruption"))?;
+
+        Ok(())
+    }
 }
 
 impl Drop for LocalTransport {
@@ -345,3 +524,86 @@ impl Drop for LocalTransport {
         let _ = self.disconnect();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{convert_event, protocol};
+    use crate::events::Event;
+
+    fn event(event_message: protocol::event::EventMessage) -> protocol::Event {
+        protocol::Event {
+            event_message: Some(event_message),
+        }
+    }
+
+    #[test]
+    fn convert_events() {
+        use protocol::event::EventMessage;
+
+        assert_eq!(
+            convert_event(event(EventMessage::Key(protocol::KeyEvent {
+                key_index: 42,
+                pressed: true,
+            }))),
+            Some(Event::Key {
+                key_index: 42,
+                pressed: true
+            })
+        );
+
+        assert_eq!(
+            convert_event(event(EventMessage::MouseButton(
+                protocol::MouseButtonEvent {
+                    button_index: 2,
+                    pressed: false,
+                }
+            ))),
+            Some(Event::MouseButton {
+                button_index: 2,
+                pressed: false
+            })
+        );
+
+        assert_eq!(
+            convert_event(event(EventMessage::MouseWheel(protocol::MouseWheelEvent {
+                direction: 1,
+            }))),
+            Some(Event::MouseWheel { direction: 1 })
+        );
+
+        assert_eq!(
+            convert_event(event(EventMessage::ProfileChanged(
+                protocol::ProfileChangedEvent {
+                    profile_file: "/var/lib/eruption/profiles/default.profile".to_string(),
+                }
+            ))),
+            Some(Event::ProfileChanged {
+                profile_file: PathBuf::from("/var/lib/eruption/profiles/default.profile")
+            })
+        );
+
+        assert_eq!(
+            convert_event(event(EventMessage::SlotChanged(
+                protocol::SlotChangedEvent { slot: 3 }
+            ))),
+            Some(Event::SlotChanged { slot: 3 })
+        );
+
+        assert_eq!(
+            convert_event(event(EventMessage::BrightnessChanged(
+                protocol::BrightnessChangedEvent { brightness: 85 }
+            ))),
+            Some(Event::BrightnessChanged { brightness: 85 })
+        );
+
+        // events without a payload are dropped
+        assert_eq!(
+            convert_event(protocol::Event {
+                event_message: None
+            }),
+            None
+        );
+    }
+}
@@ -21,8 +21,10 @@
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::canvas::Canvas;
+use crate::events::{Event, EventSubscription};
 use crate::hardware::HotplugInfo;
 use crate::Result;
 
@@ -45,6 +47,12 @@ pub trait Transport {
     fn submit_canvas(&self, canvas: &Canvas) -> Result<()>;
 
     fn notify_device_hotplug(&self, hotplug_info: &HotplugInfo) -> Result<()>;
+
+    fn subscribe(&self, subscription: &EventSubscription) -> Result<bool>;
+    fn unsubscribe(&self) -> Result<()>;
+
+    /// Wait up to `timeout` for the next event, returns `None` if no event arrived in time
+    fn next_event(&self, timeout: Duration) -> Result<Option<Event>>;
 }
 
 #[derive(Debug, Default, Clone)]
@@ -32,12 +32,97 @@
 #define ERUPTION_SDK_VERSION 0.0.5
 
 
+/**
+ * The type of an event
+ */
+typedef enum EruptionEventType {
+  None = 0,
+  Key = 1,
+  MouseButton = 2,
+  MouseWheel = 3,
+  ProfileChanged = 4,
+  SlotChanged = 5,
+  BrightnessChanged = 6,
+} EruptionEventType;
+
+/**
+ * An opaque handle to a connection to the Eruption daemon
+ */
+typedef struct EruptionConnection EruptionConnection;
+
+/**
+ * An event, pushed by the Eruption daemon to subscribed clients
+ */
+typedef struct EruptionEvent {
+  enum EruptionEventType event_type;
+  /**
+   * The key index, the mouse button index or the direction of the mouse wheel
+   */
+  uint32_t index;
+  /**
+   * Whether a key or mouse button has been pressed or released
+   */
+  bool pressed;
+  /**
+   * The new slot or the new brightness value
+   */
+  int64_t value;
+} EruptionEvent;
+
 #ifdef __cplusplus
 extern "C" {
 #endif // __cplusplus
 
 void hello(void);
 
+/**
+ * Connect to the Eruption daemon. Returns NULL on error
+ */
+struct EruptionConnection *eruption_connect(void);
+
+/**
+ * Disconnect from the Eruption daemon and free the connection handle
+ *
+ * # Safety
+ *
+ * `con` must be a handle returned by `eruption_connect()` or NULL, and must not be used afterwards
+ */
+void eruption_disconnect(struct EruptionConnection *con);
+
+/**
+ * Subscribe to events, use `eruption_next_event()` to receive them
+ *
+ * # Safety
+ *
+ * `con` must be a valid handle returned by `eruption_connect()`
+ */
+bool eruption_subscribe(struct EruptionConnection *con,
+                        bool keyboard_events,
+                        bool mouse_events,
+                        bool state_events);
+
+/**
+ * Unsubscribe from all events
+ *
+ * # Safety
+ *
+ * `con` must be a valid handle returned by `eruption_connect()`
+ */
+bool eruption_unsubscribe(struct EruptionConnection *con);
+
+/**
+ * Wait up to `timeout_millis` milliseconds for the next event. Returns true and fills
+ * in `event` if an event has been received, false on timeout or error
+ *
+ * # Safety
+ *
+ * `con` must be a valid handle returned by `eruption_connect()` and `event` must point
+ * to a writable `EruptionEvent`
+ */
+bool eruption_next_event(struct EruptionConnection *con,
+                         uint32_t timeout_millis,
+                         struct EruptionEvent *event);
+
 #ifdef __cplusplus
 } // extern "C"
 #endif // __cplusplus
@@ -19,7 +19,199 @@
     Copyright (c) 2019-2022, The Eruption Development Team
 */
 
+use std::ptr;
+use std::time::Duration;
+
+use eruption_sdk::connection::{Connection, ConnectionType};
+use eruption_sdk::events::{Event, EventSubscription};
+
+/// An opaque handle to a connection to the Eruption daemon
+pub struct EruptionConnection {
+    connection: Connection,
+}
+
+/// The type of an event
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EruptionEventType {
+    None = 0,
+    Key = 1,
+    MouseButton = 2,
+    MouseWheel = 3,
+    ProfileChanged = 4,
+    SlotChanged = 5,
+    BrightnessChanged = 6,
+}
+
+/// An event, pushed by the Eruption daemon to subscribed clients
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EruptionEvent {
+    pub event_type: EruptionEventType,
+
+    /// The key index, the mouse button index or the direction of the mouse wheel
+    pub index: u32,
+
+    /// Whether a key or mouse button has been pressed or released
+    pub pressed: bool,
+
+    /// The new slot or the new brightness value
+    pub value: i64,
+}
+
+impl From<Event> for EruptionEvent {
+    fn from(event: Event) -> Self {
+        let mut result = EruptionEvent {
+            event_type: EruptionEventType::None,
+            index: 0,
+            pressed: false,
+            value: 0,
+        };
+
+        match event {
+            Event::Key { key_index, pressed } => {
+                result.event_type = EruptionEventType::Key;
+                result.index = key_index;
+                result.pressed = pressed;
+            }
+
+            Event::MouseButton {
+                button_index,
+                pressed,
+            } => {
+                result.event_type = EruptionEventType::MouseButton;
+                result.index = button_index;
+                result.pressed = pressed;
+            }
+
+            Event::MouseWheel { direction } => {
+                result.event_type = EruptionEventType::MouseWheel;
+                result.index = direction;
+            }
+
+            Event::ProfileChanged { profile_file: _ } => {
+                result.event_type = EruptionEventType::ProfileChanged;
+            }
+
+            Event::SlotChanged { slot } => {
+                result.event_type = EruptionEventType::SlotChanged;
+                result.value = slot as i64;
+            }
+
+            Event::BrightnessChanged { brightness } => {
+                result.event_type = EruptionEventType::BrightnessChanged;
+                result.value = brightness;
+            }
+        }
+
+        result
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn hello() {
     println!("Hello from liberuption!");
 }
+
+/// Connect to the Eruption daemon. Returns NULL on error
+#[no_mangle]
+pub extern "C" fn eruption_connect() -> *mut EruptionConnection {
+    let connection = match Connection::new(ConnectionType::Local) {
+        Ok(connection) => connection,
+        Err(_e) => return ptr::null_mut(),
+    };
+
+    if connection.connect().is_err() {
+        return ptr::null_mut();
+    }
+
+    Box::into_raw(Box::new(EruptionConnection { connection }))
+}
+
+/// Disconnect from the Eruption daemon and free the connection handle
+///
+/// # Safety
+///
+/// `con` must be a handle returned by `eruption_connect()` or NULL, and must not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn eruption_disconnect(con: *mut EruptionConnection) {
+    if !con.is_null() {
+        let con = Box::from_raw(con);
+        let _ = con.connection.disconnect();
+    }
+}
+
+/// Subscribe to events, use `eruption_next_event()` to receive them
+///
+/// # Safety
+///
+/// `con` must be a valid handle returned by `eruption_connect()`
+#[no_mangle]
+pub unsafe extern "C" fn eruption_subscribe(
+    con: *mut EruptionConnection,
+    keyboard_events: bool,
+    mouse_events: bool,
+    state_events: bool,
+) -> bool {
+    match con.as_ref() {
+        Some(con) => con
+            .connection
+            .subscribe(&EventSubscription {
+                keyboard_events,
+                mouse_events,
+                state_events,
+            })
+            .unwrap_or(false),
+
+        None => false,
+    }
+}
+
+/// Unsubscribe from all events
+///
+/// # Safety
+///
+/// `con` must be a valid handle returned by `eruption_connect()`
+#[no_mangle]
+pub unsafe extern "C" fn eruption_unsubscribe(con: *mut EruptionConnection) -> bool {
+    match con.as_ref() {
+        Some(con) => con.connection.unsubscribe().is_ok(),
+        None => false,
+    }
+}
+
+/// Wait up to `timeout_millis` milliseconds for the next event. Returns true and fills
+/// in `event` if an event has been received, false on timeout or error
+///
+/// # Safety
+///
+/// `con` must be a valid handle returned by `eruption_connect()` and `event` must point
+/// to a writable `EruptionEvent`
+#[no_mangle]
+pub unsafe extern "C" fn eruption_next_event(
+    con: *mut EruptionConnection,
+    timeout_millis: u32,
+    event: *mut EruptionEvent,
+) -> bool {
+    let con = match con.as_ref() {
+        Some(con) => con,
+        None => return false,
+    };
+
+    if event.is_null() {
+        return false;
+    }
+
+    match con
+        .connection
+        .next_event(Duration::from_millis(timeout_millis as u64))
+    {
+        Ok(Some(e)) => {
+            *event = e.into();
+
+            true
+        }
+
+        _ => false,
+    }
+}
@@ -423,6 +423,7 @@ install -Dp -m 0755 %{_builddir}/%{OrigName}-%{commit}/target/release/eruption-g
 %{_datarootdir}/%{ShortName}/scripts/lib/easing.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/queue.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/utilities.lua
+%{_datarootdir}/%{ShortName}/scripts/lib/complex_keys.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/declarations.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/failsafe.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/failsafe.lua.manifest
@@ -452,6 +453,7 @@ install -Dp -m 0755 %{_builddir}/%{OrigName}-%{commit}/target/release/eruption-g
 %{_datarootdir}/%{ShortName}/scripts/lib/hwdevices/mice/roccat_nyth.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/hwdevices/misc/roccat_elo_71_air.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/hwdevices/misc/roccat_aimo_pad.lua
+%{_datarootdir}/%{ShortName}/scripts/lib/hwdevices/misc/generic_lamp_array.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/hwdevices/misc/custom_serial_leds.lua
 %config %{_datarootdir}/%{ShortName}/scripts/lib/themes/default.lua
 %config %{_datarootdir}/%{ShortName}/scripts/lib/themes/gaming.lua
@@ -460,6 +462,7 @@ install -Dp -m 0755 %{_builddir}/%{OrigName}-%{commit}/target/release/eruption-g
 %config %{_datarootdir}/%{ShortName}/scripts/lib/macros/failsafe-macros.lua
 %config %{_datarootdir}/%{ShortName}/scripts/lib/macros/starcraft2.lua
 %{_datarootdir}/%{ShortName}/scripts/lib/macros/examples.lua
+%{_datarootdir}/%{ShortName}/scripts/lib/macros/example.macro
 %{_datarootdir}/%{ShortName}/scripts/macros.lua
 %{_datarootdir}/%{ShortName}/scripts/macros.lua.manifest
 %{_datarootdir}/%{ShortName}/scripts/stats.lua
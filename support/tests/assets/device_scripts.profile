@@ -0,0 +1,38 @@
+#    SPDX-License-Identifier: GPL-3.0-or-later
+#
+#    This file is part of Eruption.
+#
+#    Eruption is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    Eruption is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+#
+#    Copyright (c) 2019-2022, The Eruption Development Team
+
+
+id = '3c0f4a0e-5b7e-11ed-9b6a-0242ac120002'
+name = 'Device Scripts Test'
+description = 'Assign scripts to individual devices'
+active_scripts = [ 'manifest_test.lua' ]
+
+[[devices]]
+usb_vid = 0x1e7d
+usb_pid = 0x3098
+active_scripts = [ 'manifest_test.lua' ]
+
+[[devices]]
+device = 1
+serial = 'ABC123'
+active_scripts = [ 'manifest_test.lua' ]
+
+[[devices]]
+device = 2
+slot = 1
@@ -0,0 +1,32 @@
+#    SPDX-License-Identifier: GPL-3.0-or-later
+#
+#    This file is part of Eruption.
+#
+#    Eruption is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    Eruption is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+#
+#    Copyright (c) 2019-2022, The Eruption Development Team
+
+
+
+id = '2b0d6f3e-7c4a-11ed-9d3f-0242ac120002'
+name = 'Inherit Child'
+description = 'Extends Test 3 by UUID and includes a fragment'
+extends = '5dc62fa6-e965-45cb-a1dc-e87d29713097'
+includes = [ 'inherit.include' ]
+active_scripts = [ 'impact.lua' ]
+
+[[config.Shockwave]]
+type = 'bool'
+name = 'mouse_events'
+value = false
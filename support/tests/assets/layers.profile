@@ -0,0 +1,31 @@
+#    SPDX-License-Identifier: GPL-3.0-or-later
+#
+#    This file is part of Eruption.
+#
+#    Eruption is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    Eruption is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with Eruption.  If not, see <http://www.gnu.org/licenses/>.
+#
+#    Copyright (c) 2019-2022, The Eruption Development Team
+
+
+
+id = '8f1e2c44-6a3d-11ed-a1eb-0242ac120002'
+name = 'Layers Test'
+description = 'Blend scripts using compositing layers'
+active_scripts = [ 'manifest_test.lua' ]
+
+[[layers]]
+script = 'manifest_test.lua'
+blend_mode = 'screen'
+opacity = 0.75
+key_mask = [ 1, 2, 3 ]